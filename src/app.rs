@@ -0,0 +1,1934 @@
+//! The command line interface, shared by the `trusted-signing-cli` and `cargo-trusted-sign` binaries
+
+use crate::{
+    attestation::{Attestation, AttestedFile},
+    audit::{AuditEntry, AuditLog},
+    azure::{self, AzArg},
+    bench, build, cabinet,
+    cargo::{self, Artifact, Selection},
+    catalog, certificate, checksums,
+    ci::{self, CiFormat},
+    cli::{
+        self, Args, BenchArgs, CargoSelection, Cli, Command, ConfigCommand, InspectArgs, RegionsArgs, SetSecretArgs,
+        TimestampArgs, UnsignArgs, VerifyArgs,
+    },
+    color::{self, ColorChoice},
+    config, credman, daemon, dotenv, driver, electron, endpoint,
+    error::Error,
+    eventlog::{self, EventLog},
+    events::{Event, Events, RunConfig},
+    hooks::{self, PreSignFailure},
+    init, input, inspect, interrupt, job,
+    journal::Journal,
+    logging::{self, LogFile, LogLevel},
+    manifest,
+    marker::{self, Marker},
+    pending,
+    pin::Pin,
+    pipe, profiles,
+    progress::{self, Progress},
+    psmodule, purge, quota, redact,
+    report::{FileReport, OutputFormat, Report, Status},
+    schema, scratch, serve, session, settings,
+    signer::{Backend, SignOptions, Signer, DLIB_VERSION},
+    smoke::{self, SmokeTest},
+    signtool::{self, Digest, TIMESTAMP_URL},
+    squirrel, status, tauri, testcert, timestamp, unsign,
+    verify::{self, Verification},
+    whoami, wrapper,
+};
+#[cfg(feature = "download")]
+use crate::update;
+#[cfg(feature = "otlp")]
+use crate::otlp;
+#[cfg(feature = "webhook")]
+use crate::webhook::{self, WebhookOn};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use tracing::{debug, debug_span, info, warn, Instrument};
+
+/// Run the command line `argv`, returning the exit code of the process
+pub async fn main(argv: impl IntoIterator<Item = OsString>) -> ExitCode {
+    match dispatch(argv).await {
+        Ok(code) => code,
+        Err(err) => fail(&err, err.exit_code()),
+    }
+}
+
+/// Run the command line `argv`. Errors are printed by [`main`], those of the signing run itself by [`sign_files`]
+async fn dispatch(argv: impl IntoIterator<Item = OsString>) -> Result<ExitCode, Error> {
+    let mut argv = cli::with_default_command(argv);
+    if electron::windows_sign_requested(&argv) {
+        argv = electron::windows_sign_arguments(argv)?;
+    }
+    // before anything parses the arguments, so clap sees the variables
+    let env_file = dotenv::load(&argv).map_err(Error::Usage)?;
+    // after the env file, which may set it too
+    let env_args = match std::env::var(cli::ARGS_VAR) {
+        Ok(value) => cli::with_env_args(&mut argv, &value).map_err(Error::Usage)?,
+        Err(std::env::VarError::NotPresent) => Vec::new(),
+        Err(std::env::VarError::NotUnicode(_)) => Err(Error::Usage(format!("{} is not valid unicode", cli::ARGS_VAR)))?,
+    };
+    let resolved = config::resolve(argv).map_err(Error::Usage)?;
+    // config show takes the sign arguments without requiring any, so it can't go through the real parse.
+    // The lenient parse ignores --help, that is left to the real one
+    let help = resolved.argv.iter().any(|arg| arg == "-h" || arg == "--help");
+    let lenient = Cli::command().ignore_errors(true).try_get_matches_from(&resolved.argv);
+    // --ci of the sign arguments, CI for the commands without them
+    let sign = lenient.as_ref().ok().and_then(cli::sign_matches);
+    let strict = sign.and_then(|sign| sign.try_get_one::<bool>("ci").ok().flatten().copied());
+    ci::set_strict(strict.unwrap_or_else(ci::from_env));
+    let mut az_args = sign.and_then(|sign| sign.try_get_many::<AzArg>("az_arg").ok().flatten()).into_iter().flatten();
+    if let (true, Some(interactive)) = (ci::strict(), az_args.find(|arg| azure::is_interactive(arg))) {
+        Err(Error::Usage(format!(
+            "--ci never waits for input, --az-arg {} would make az login prompt",
+            interactive.arg
+        )))?;
+    }
+    if let (Ok(matches), false) = (lenient, help) {
+        // every command that runs az takes the sign arguments
+        if let Some(Ok(Some(args))) = cli::sign_matches(&matches).map(|sign| sign.try_get_many::<AzArg>("az_arg")) {
+            azure::set_extra_args(args.cloned().collect());
+        }
+        if let Some(code) = lenient_command(&matches, &resolved, &env_file).await? {
+            return Ok(code);
+        }
+    }
+    let cli = match Cli::try_parse_from(&resolved.argv) {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            if err.use_stderr() && !env_args.is_empty() {
+                eprintln!("\n{} added: {}", cli::ARGS_VAR, cli::describe_env_args(&env_args));
+            }
+            return Ok(ExitCode::from(err.exit_code() as u8));
+        }
+    };
+    let started = Started { resolved, env_file, env_args, without_daemon: false };
+    match cli.command {
+        Command::Sign(args) => sign_files(*args, None, started).await,
+        Command::Cargo(args) => sign_files(args.sign, Some(args.selection), started).await,
+        // signed like sign when no daemon is running
+        Command::SignViaDaemon(args) => match daemon::sign(&args).await? {
+            Some(()) => Ok(ExitCode::SUCCESS),
+            None => sign_files(*args, None, Started { without_daemon: true, ..started }).await,
+        },
+        command => run_command(command).await,
+    }
+}
+
+/// What the command line came with besides the arguments, for the signing run to log once logging is set up
+struct Started {
+    resolved: config::Resolved,
+    env_file: dotenv::EnvFile,
+    /// The arguments CLI_ARGS added
+    env_args: Vec<String>,
+    /// sign-via-daemon found no daemon
+    without_daemon: bool,
+}
+
+/// Set up logging to stderr and, with `log_file`, to that file. Returns the file written
+fn init_logging(
+    level: Option<LogLevel>,
+    color: ColorChoice,
+    log_file: Option<LogFile>,
+) -> Result<Option<PathBuf>, Error> {
+    let log_file = logging::init_with_file(level, color::init(color), log_file).map_err(Error::Usage)?;
+    if let Some(path) = &log_file {
+        info!("writing diagnostics to {}", path.display());
+    }
+    Ok(log_file)
+}
+
+/// Print `value` as JSON to stdout with `--output json`, as its table to stderr otherwise
+fn print_output<T: serde::Serialize>(
+    value: &T,
+    output: Option<&OutputFormat>,
+    table: impl FnOnce(&T) -> String,
+) -> Result<(), Error> {
+    match output {
+        Some(OutputFormat::Json) => {
+            let json = serde_json::to_string_pretty(value).map_err(|err| err.to_string())?;
+            println!("{}", logging::mask(&json));
+        }
+        _ => eprint!("{}", logging::mask(&table(value))),
+    }
+    Ok(())
+}
+
+/// Run the commands that take the sign arguments without requiring them from the lenient parse `matches`, `None`
+/// when it is another one, left to the real parse
+async fn lenient_command(
+    matches: &ArgMatches,
+    resolved: &config::Resolved,
+    env_file: &dotenv::EnvFile,
+) -> Result<Option<ExitCode>, Error> {
+    let Some((name, matches)) = matches.subcommand() else {
+        return Ok(None);
+    };
+    let handled = match name {
+        "config" => matches.subcommand_name() == Some("show"),
+        // a dry run only lists the artifacts
+        "cargo" => CargoSelection::from_arg_matches(matches).is_ok_and(|selection| selection.dry_run),
+        // quota without a subcommand needs the credentials and the account, like list-profiles
+        "quota" => matches.subcommand_name().is_none(),
+        // whoami only needs the credentials, status is read-only, print-signtool-args needs no credentials
+        "whoami" | "list-profiles" | "list-accounts" | "status" | "print-signtool-args" => true,
+        _ => false,
+    };
+    if !handled {
+        return Ok(None);
+    }
+    if !matches!(name, "config" | "cargo") {
+        init_logging(None, ColorChoice::Auto, None)?;
+    }
+    let output = matches.try_get_one::<OutputFormat>("output").ok().flatten();
+    match name {
+        "config" => {
+            let show = matches.subcommand_matches("show").expect("config show");
+            let effective = settings::effective(show, resolved, env_file, DLIB_VERSION);
+            let output = show.try_get_one::<OutputFormat>("output").ok().flatten();
+            print_output(&effective, output, |effective| effective.table())?;
+        }
+        "cargo" => return Ok(Some(dry_run(&CargoSelection::from_arg_matches(matches).map_err(|err| err.to_string())?))),
+        "whoami" => {
+            let identity = whoami::run(matches, resolved, env_file)?;
+            print_output(&identity, output, |identity| identity.table())?;
+        }
+        "list-profiles" => {
+            let account = matches.try_get_one::<String>("account").ok().flatten().cloned().unwrap_or_default();
+            print_output(&profiles::run(matches)?, output, |found| profiles::table(&account, found))?;
+        }
+        "list-accounts" => {
+            print_output(&profiles::accounts(matches)?, output, |found| profiles::accounts_table(found))?
+        }
+        "quota" => {
+            let usage = quota::service(matches)?;
+            print_output(&usage, output, |usage| usage.table())?;
+            if let Some(&warn_at) = matches.try_get_one::<u64>("warn_at").ok().flatten() {
+                if usage.signatures >= warn_at {
+                    let err = Error::QuotaReached { account: usage.account, used: usage.signatures, warn_at };
+                    warn!("{}", err);
+                    return Ok(Some(ExitCode::from(err.exit_code())));
+                }
+            }
+        }
+        "status" => print_output(&status::run(matches, DLIB_VERSION).await, output, |status| status.table())?,
+        "print-signtool-args" => print_signtool_args(matches).await?,
+        _ => unreachable!("only the commands handled are run"),
+    }
+    Ok(Some(ExitCode::SUCCESS))
+}
+
+/// Run a command that doesn't sign files, after setting up its logging
+async fn run_command(command: Command) -> Result<ExitCode, Error> {
+    let (level, color, log_file) = match &command {
+        Command::Serve(args) => (args.sign.log_level, args.sign.color, args.sign.log_file()),
+        Command::Daemon(args) => (args.sign.log_level, args.sign.color, args.sign.log_file()),
+        Command::SmokeTest(args) => (args.log_level, args.color, None),
+        Command::Bench(args) => (args.sign.log_level, args.sign.color, None),
+        _ => (None, ColorChoice::Auto, None),
+    };
+    // these only print
+    if !matches!(command, Command::Schema { .. } | Command::Completions { .. } | Command::Config(_)) {
+        init_logging(level, color, log_file)?;
+    }
+    match command {
+        Command::Schema { format } => {
+            println!("{}", serde_json::to_string_pretty(&schema::schema(format)).map_err(|err| err.to_string())?)
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), env!("CARGO_PKG_NAME"), &mut std::io::stdout())
+        }
+        Command::Config(ConfigCommand::SetSecret(args)) => set_secret(&args)?,
+        Command::Serve(args) => serve::run(*args).await?,
+        Command::Daemon(args) => daemon::run(*args).await?,
+        Command::Init(args) => init::run(*args)?,
+        // the smoke test reports its errors itself
+        Command::SmokeTest(args) => {
+            if let Err(err) = smoke_test(*args).await {
+                return Ok(ExitCode::from(err.exit_code()));
+            }
+        }
+        Command::Bench(args) => bench(*args).await?,
+        Command::Regions(args) => regions(args).await?,
+        Command::Purge(args) => purge::run(args)?,
+        Command::Quota(args) => quota::run(args.command.expect("quota without a subcommand is run earlier"))?,
+        Command::Verify(args) => verify(*args)?,
+        Command::Timestamp(args) => timestamp(*args)?,
+        Command::Unsign(args) => unsign(*args)?,
+        Command::GenerateTestCert(args) => testcert::run(*args)?,
+        Command::Inspect(args) => inspect(*args)?,
+        #[cfg(feature = "download")]
+        Command::SelfUpdate(args) => update::run(*args).await?,
+        Command::Sign(_) | Command::Cargo(_) | Command::SignViaDaemon(_) => unreachable!("sign_files signs"),
+        Command::Config(ConfigCommand::Show(_))
+        | Command::PrintSigntoolArgs(_)
+        | Command::Whoami(_)
+        | Command::Status(_)
+        | Command::ListProfiles(_)
+        | Command::ListAccounts(_) => unreachable!("the command is run by lenient_command"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Sign the files of `args`, and those of the Cargo build of `cargo`. Prints the error of the run itself, in the
+/// form --quiet and --output ask for
+async fn sign_files(mut args: Args, cargo: Option<CargoSelection>, started: Started) -> Result<ExitCode, Error> {
+    let Started { resolved, env_file, env_args, without_daemon } = started;
+    // before anything that may fail with them in its message
+    if let Some(secret) = &args.azure_client_secret {
+        logging::register_secret(secret);
+    }
+    #[cfg(feature = "webhook")]
+    for secret in args.webhook.iter().chain(args.webhook_header.iter().map(|(_, value)| value)) {
+        logging::register_secret(secret);
+    }
+    args.read_service_principal()?;
+    // logged once logging is set up
+    let mut expanded_paths = Vec::new();
+    if args.expand_env_in_paths {
+        for file in args.file.iter_mut() {
+            let expanded = input::expand_env(file).map_err(Error::Usage)?;
+            if expanded != *file {
+                expanded_paths.push((std::mem::replace(file, expanded), file.clone()));
+            }
+        }
+    }
+    for name in &args.file_from_env {
+        args.file.push(input::from_env(name).map_err(Error::Usage)?);
+    }
+    if let Some(source) = args.job.clone() {
+        job::read(&source).and_then(|job| job::apply(job, &mut args))?;
+    }
+    if args.electron_builder_hook && !electron_builder_hook(&mut args)? {
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.windows_sign_hook {
+        if args.fd == Digest::Sha1 && args.require_sha2 {
+            Err(Error::Usage(format!(
+                "refusing the SHA-1 signature of {} with --require-sha2, set hashes to [\"sha256\"] to avoid this call",
+                args.file.join(", ")
+            )))?;
+        }
+        if args.fd == Digest::Sha1 {
+            // @electron/windows-sign goes on with the SHA-256 call when this one succeeds
+            tell(&format!(
+                "skipping the SHA-1 signature of {}, Trusted Signing only signs with SHA-256 or stronger. \
+                 Set hashes to [\"sha256\"] to avoid this call",
+                args.file.join(", ")
+            ));
+            return Ok(ExitCode::SUCCESS);
+        }
+        args.single = true;
+    }
+    if args.single {
+        single(&mut args)?;
+    }
+
+    if init_logging(log_level(&args), args.color, args.log_file())?.is_some() {
+        let matches = Cli::command().ignore_errors(true).try_get_matches_from(&resolved.argv);
+        if let Some(sign) = matches.as_ref().ok().and_then(cli::sign_matches) {
+            let effective = settings::effective(sign, &resolved, &env_file, DLIB_VERSION);
+            let version = env!("CARGO_PKG_VERSION");
+            let os = std::env::consts::OS;
+            debug!(target: logging::DIAGNOSTICS, "trusted-signing-cli {} on {}\n{}", version, os, effective.table());
+        }
+    }
+    if let Some(path) = &env_file.path {
+        info!("using env file {}", path.display());
+    }
+    if !env_args.is_empty() {
+        info!("added {} from {}", cli::describe_env_args(&env_args), cli::ARGS_VAR);
+    }
+    for warning in &env_file.warnings {
+        warn!("{}", warning);
+    }
+    if without_daemon {
+        info!("no daemon is running, signing without one");
+    }
+    for (original, expanded) in &expanded_paths {
+        info!("expanded the file {} → {}", original, expanded);
+    }
+    for loaded in &resolved.loaded {
+        info!("using {}", loaded);
+    }
+    for warning in &resolved.warnings {
+        warn!("{}", warning);
+    }
+    let (build, cabinets) = gather_files(&mut args, cargo.as_ref())?;
+    discover_endpoint(&mut args)?;
+
+    if let Some(dir) = &args.temp_dir {
+        // the work on a file takes about as much room as the file
+        let largest = args.file.iter().filter_map(|file| fs::metadata(file).ok()).map(|metadata| metadata.len()).max();
+        scratch::init(dir, largest.unwrap_or(0))?;
+    }
+
+    // removed again when dropped at the end, whether signing worked or not
+    let mut piped = None;
+    if let (true, Some(kind)) = (args.pipe, &args.kind) {
+        let limit = args.max_input_size * 1024 * 1024;
+        let extension = pipe::check(kind, &args.extensions())?;
+        let (dir, path) = pipe::spool(std::io::stdin().lock(), &extension, limit)?;
+        args.file.push(path.display().to_string());
+        piped = Some((dir, path));
+    }
+
+    let mut report = Report::new(&args.file);
+    report.build = build;
+    for file in &mut report.files {
+        file.cabinet_of = cabinets.get(&file.path).cloned();
+    }
+    report.correlation_id = match (args.correlation_per_file, args.single) {
+        (true, _) => None,
+        // the metadata of --single calls is kept, a new id every call would mean a new file every call
+        (false, true) => args.correlation_id.clone(),
+        (false, false) => Some(args.correlation_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string())),
+    };
+    let eventlog = args.eventlog.then(|| EventLog::open(&args.eventlog_source)).flatten();
+    #[cfg(feature = "otlp")]
+    let trace = match otlp::Config::from_env(args.otlp_endpoint.as_deref(), |name| std::env::var(name).ok()) {
+        Ok(config) => config.map(|config| {
+            for (_, value) in &config.headers {
+                logging::register_secret(value);
+            }
+            otlp::Trace::new(config)
+        }),
+        Err(err) => {
+            warn!("{}, no trace is exported", err);
+            None
+        }
+    };
+    let run = run(&args, &mut report, eventlog.as_ref(), #[cfg(feature = "otlp")] trace.as_ref());
+    let result = match (run.await, &piped) {
+        (Ok(()), Some((_, path))) => pipe::emit(path),
+        (result, _) => result,
+    };
+    let error = result.as_ref().err().map(|err| err.to_string());
+    if let Some(eventlog) = &eventlog {
+        eventlog.write(&eventlog::run_event(&args.target(), &report, error.as_deref()));
+    }
+    if let Some(path) = step_summary(&args) {
+        if let Err(err) = report.append_step_summary(&path, &args.target(), error.as_deref()) {
+            warn!("{}", err);
+        }
+    }
+    #[cfg(feature = "webhook")]
+    if let Some(url) = args.webhook.as_deref().filter(|_| error.is_some() || args.webhook_on == WebhookOn::Always) {
+        let payload = webhook::Payload::new(&args.target(), &report, error.as_deref(), ci::metadata());
+        if let Err(err) = webhook::send(url, &args.webhook_header, &payload, Duration::from_secs(1)).await {
+            warn!("{}, the run is not affected", err);
+        }
+    }
+    // also after Ctrl+C, the spans of the files signed until then are in it
+    #[cfg(feature = "otlp")]
+    if let Some(trace) = &trace {
+        if let Err(err) = trace.export(&args.target(), &report, error.as_deref()).await {
+            warn!("{}, the run is not affected", err);
+        }
+    }
+    Ok(conclude(&args, &mut report, result, &mut io::stdout(), &mut io::stderr()))
+}
+
+/// The level of the informational lines, only errors are logged for --quiet
+fn log_level(args: &Args) -> Option<LogLevel> {
+    match args.quiet {
+        true => Some(LogLevel::Error),
+        false => args.log_level,
+    }
+}
+
+/// End a sign run with its error on `stderr`, as the short block of --quiet or in full, and the report on `stdout`
+/// for --output json. Returns the exit code of the run
+fn conclude(
+    args: &Args,
+    report: &mut Report,
+    result: Result<(), Error>,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> ExitCode {
+    let exit_code = match &result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) if args.quiet => {
+            let _ = write!(stderr, "{}", logging::mask(&report.error_block(err)));
+            ExitCode::from(err.exit_code())
+        }
+        Err(err) => {
+            let message = logging::mask(&err.to_string());
+            let _ = writeln!(stderr, "The application signing was not successful.\n\r{}", message);
+            ExitCode::from(err.exit_code())
+        }
+    };
+
+    if args.output == OutputFormat::Json {
+        report.error = result.err();
+        match report.to_json() {
+            Ok(json) => {
+                let _ = writeln!(stdout, "{}", logging::mask(&json));
+            }
+            Err(err) => tell(&err),
+        }
+    }
+    exit_code
+}
+
+/// The summary table of a sign run on `stderr`, unless --quiet
+fn print_summary(args: &Args, report: &Report, stderr: &mut impl Write) {
+    if !args.quiet {
+        let _ = write!(stderr, "{}", logging::mask(&report.summary_table()));
+    }
+}
+
+/// Add the files the build, Cargo, Tauri, the manifests, packages and modules of `args` make or list to its files,
+/// with the catalogs last. Returns the build of --run and the cabinet each expanded file came from
+fn gather_files(
+    args: &mut Args,
+    cargo: Option<&CargoSelection>,
+) -> Result<(Option<build::Build>, HashMap<String, String>), Error> {
+    // before the outputs of cargo, tauri and the manifests are looked up, the build is what makes them
+    let mut build = None;
+    if let Some(command) = &args.run {
+        let done = build::run(command)?;
+        args.file.extend(build::outputs(&args.then_sign)?);
+        build = Some(done);
+    }
+    if let Some(selection) = cargo {
+        for artifact in cargo_artifacts(selection)? {
+            match artifact.built() {
+                true => args.file.push(artifact.path.display().to_string()),
+                false => warn!("{} was not built, skipping it", artifact.path.display()),
+            }
+        }
+        if args.file.is_empty() {
+            Err(Error::Usage(format!(
+                "nothing to sign, build the {} profile first, e.g. `cargo build{}`",
+                selection.profile(),
+                if selection.release { " --release" } else { "" }
+            )))?;
+        }
+    }
+    if let (Some(path), true) = (&args.tauri, args.tauri_updater) {
+        tauri::check_updater(path, args.tauri_stage, args.tauri_cli_path.as_deref())?;
+    }
+    if let Some(path) = &args.tauri {
+        // the app exe and installers go before any files given along
+        args.file.splice(0..0, tauri::outputs(path, args.tauri_stage)?);
+    }
+
+    if let Some(path) = &args.manifest {
+        args.file.extend(manifest::files(path, args.manifest_format, &args.extensions())?);
+    }
+
+    if let Some(path) = &args.driver_package {
+        args.file.extend(driver::files(path, args.inf2cat_os.is_some())?);
+    }
+    if let Some(path) = &args.squirrel_releases {
+        args.file.extend(squirrel::files(path, &args.extensions())?);
+    }
+    if let Some(dir) = &args.ps_module {
+        args.file.extend(psmodule::files(dir)?);
+        args.catalog = Some(psmodule::catalog(dir));
+        args.catalog_covers = vec![dir.clone()];
+    }
+    if let Some(catalog) = &args.catalog {
+        if !driver::is_catalog(&catalog.display().to_string()) {
+            Err(Error::Usage(format!("--catalog '{}' has to end in .cat", catalog.display())))?;
+        }
+        args.file.push(catalog.display().to_string());
+    }
+    let mut cabinets = HashMap::new();
+    if args.msi_with_cabs {
+        (args.file, cabinets) = cabinet::expand(&args.file)?;
+    }
+    if driver::order(&mut args.file) {
+        info!("signing the catalogs last, they have to cover the signed binaries");
+    }
+    Ok((build, cabinets))
+}
+
+/// Print `message` for the user to stderr, masked like everything else the tool prints
+fn tell(message: &impl std::fmt::Display) {
+    eprintln!("{}", logging::mask(&message.to_string()));
+}
+
+/// Print `err` for the user with [`tell`] and exit with `code`
+fn fail(err: &impl std::fmt::Display, code: impl Into<ExitCode>) -> ExitCode {
+    tell(err);
+    code.into()
+}
+
+/// Print the signtool arguments, or write them to a wrapper, from the leniently parsed sign arguments
+async fn print_signtool_args(matches: &ArgMatches) -> Result<(), Error> {
+    let get = |id: &str| matches.try_get_one::<String>(id).ok().flatten().cloned();
+    let required = [("endpoint", "--endpoint"), ("account", "--account"), ("certificate", "--certificate")];
+    let missing: Vec<&str> = required.iter().filter(|(id, _)| get(id).is_none()).map(|(_, flag)| *flag).collect();
+    if !missing.is_empty() {
+        Err(Error::Usage(format!("print-signtool-args needs {}", missing.join(", "))))?;
+    }
+    let digest = |id: &str| matches.try_get_one::<Digest>(id).ok().flatten().copied().unwrap_or(Digest::Sha256);
+    let sign_tool_path = get("sing_tool_path").unwrap_or_else(|| signtool::DEFAULT_PATH.to_string());
+    let options = SignOptions::new(
+        &get("endpoint").unwrap_or_default(),
+        &get("account").unwrap_or_default(),
+        &get("certificate").unwrap_or_default(),
+    )?
+    .sign_tool_path(&sign_tool_path)
+    .file_digest(digest("fd"))
+    .timestamp(&get("tr").unwrap_or_else(|| TIMESTAMP_URL.to_string()), digest("td"))
+    .description(get("description"))
+    .append_signature(matches!(matches.try_get_one::<bool>("append_signature"), Ok(Some(true))))
+    .correlation_id(get("correlation_id"))
+    .signtool_template(
+        get("signtool_template").as_deref().map(signtool::Template::parse).transpose().map_err(Error::Usage)?,
+        matches.try_get_many::<String>("signtool_extra_arg").ok().flatten().into_iter().flatten().cloned().collect(),
+    )
+    .dlib_package(matches.try_get_one::<PathBuf>("dlib_package").ok().flatten().cloned());
+    #[cfg(feature = "download")]
+    let options = options.dlib_feed(get("dlib_feed"), get("feed_token"));
+
+    let mut signer = Signer::new(options, Events::default());
+    let args = signer.signtool_arguments().await?;
+    match matches.try_get_one::<PathBuf>("emit_wrapper").ok().flatten() {
+        Some(path) => match wrapper::write(path, &sign_tool_path, &args)? {
+            true => info!("wrote {}", path.display()),
+            false => info!("{} is up to date", path.display()),
+        },
+        None => println!("{}", wrapper::command_line(&args)),
+    }
+    Ok(())
+}
+
+/// List the regions -e knows, with the time each endpoint took to answer for --probe
+async fn regions(args: RegionsArgs) -> Result<(), Error> {
+    let mut regions = endpoint::regions();
+    if args.probe {
+        // all at once, an unreachable endpoint takes the whole timeout
+        let probed = futures_util::future::join_all(regions.iter().map(|region| endpoint::probe(&region.url))).await;
+        for (region, probed) in regions.iter_mut().zip(probed) {
+            match probed {
+                Ok(latency) => region.latency_ms = Some(latency.as_millis() as u64),
+                Err(err) => region.error = Some(err),
+            }
+        }
+    }
+    let closest = regions.iter().filter_map(|region| region.latency_ms.map(|latency| (latency, region.code))).min();
+    let width = regions.iter().map(|region| region.name.len()).max().unwrap_or(0);
+    let url_width = regions.iter().map(|region| region.url.len()).max().unwrap_or(0);
+    for region in &regions {
+        let probed = match (region.latency_ms, &region.error) {
+            (Some(latency), _) if closest.is_some_and(|(_, code)| code == region.code) => {
+                format!("  {} ms, closest", latency)
+            }
+            (Some(latency), _) => format!("  {} ms", latency),
+            (None, Some(error)) => format!("  {}", error),
+            (None, None) => String::new(),
+        };
+        let shorthand = match region.shorthand {
+            true => format!("-e {}", region.code),
+            false => "full URL only".to_string(),
+        };
+        eprintln!(
+            "{:<6} {:<width$}  {:<url_width$}  ({}){}",
+            region.code, region.name, region.url, shorthand, probed
+        );
+    }
+    if args.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&regions).map_err(|err| err.to_string())?);
+    }
+    if args.probe && closest.is_none() {
+        Err(Error::Other("none of the endpoints could be reached".to_string()))?;
+    }
+    Ok(())
+}
+
+/// Record a stage of the smoke test, passing its result on
+fn stage<T>(smoke: &mut SmokeTest, started: Instant, result: Result<T, Error>) -> Result<T, Error> {
+    smoke.record(started.elapsed(), result.as_ref().map(|_| ()).map_err(Error::to_string));
+    result
+}
+
+/// The stages of the smoke test on `file`, stopping at the first that fails
+async fn smoke_stages(args: &Args, signer: &mut Signer, file: &str, smoke: &mut SmokeTest) -> Result<(), Error> {
+    let started = Instant::now();
+    // the other backends have no signing client, connecting finds their tools
+    let dependencies = match args.backend {
+        Backend::TrustedSigning => signer.download_client().await.map(|_| ()),
+        Backend::Rest | Backend::KeyVault | Backend::Pfx => Ok(()),
+    };
+    stage(smoke, started, dependencies)?;
+    let started = Instant::now();
+    let connected = signer.connect().await;
+    stage(smoke, started, connected)?;
+    warn!("signing a throwaway executable, this uses one signature of the account's quota");
+    let started = Instant::now();
+    let signed = signer.sign_file(file).await;
+    smoke.signatures_used = signed.is_ok() as usize;
+    stage(smoke, started, signed)?;
+    let started = Instant::now();
+    let verified = verify::verifier(args.verify_with_signtool, Some(&args.sing_tool_path)).and_then(|verifier| {
+        match verify::verify(&verifier, file) {
+            verification if verification.valid => Ok(()),
+            verification => Err(Error::Signing {
+                path: file.to_string(),
+                message: format!(
+                    "the signed file doesn't verify: {}",
+                    verification.error.unwrap_or_else(|| "signtool verify failed".to_string())
+                ),
+                output: String::new(),
+            }),
+        }
+    });
+    stage(smoke, started, verified)
+}
+
+/// Sign and verify a throwaway executable with the sign arguments, reporting each stage. The executable is
+/// deleted again, whatever happened
+async fn smoke_test(args: Args) -> Result<(), Error> {
+    interrupt::install();
+    let target = args.target();
+    let mut smoke = SmokeTest::new(&target.endpoint, &target.account, &target.certificate);
+    let failed = |err: std::io::Error| Error::Other(format!("the smoke test executable could not be written: {}", err));
+    let dir = scratch::dir("trusted-signing-cli-smoke-test", None).map_err(failed)?;
+    let file = dir.path().join("smoke-test.exe");
+    fs::write(&file, smoke::stub_exe()).map_err(failed)?;
+    let file = file.display().to_string();
+    let options = sign_options(&args, None).inspect_err(tell)?;
+    let mut signer = Signer::new(options, Events::new(args.events));
+    let result = smoke_stages(&args, &mut signer, &file, &mut smoke).await;
+    match args.output {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&smoke).map_err(|err| err.to_string())?;
+            println!("{}", logging::mask(&json));
+        }
+        OutputFormat::Text => eprint!("{}", logging::mask(&smoke.table())),
+    }
+    result
+}
+
+/// Sign throwaway files to measure how fast the service signs with these arguments, after saying what it costs
+async fn bench(mut args: BenchArgs) -> Result<(), Error> {
+    interrupt::install();
+    args.sign.read_service_principal()?;
+    let target = args.sign.target();
+    let (count, jobs) = (args.count as usize, args.jobs as usize);
+    eprintln!("{}", bench::cost(count, &quota::usage(&target.endpoint, &target.account)));
+    if !args.yes {
+        Err(Error::Usage("pass --yes to sign them".to_string()))?;
+    }
+    if let Some(dir) = &args.sign.temp_dir {
+        scratch::init(dir, args.size * count as u64)?;
+    }
+    let options = sign_options(&args.sign, None)?;
+    let failed = |err: std::io::Error| Error::Other(format!("the files to sign could not be written: {}", err));
+    let dir = scratch::dir("trusted-signing-cli-bench", None).map_err(failed)?;
+    let files = bench::write_files(dir.path(), count, args.size).map_err(failed)?;
+    let size = args.size;
+    let bench = tokio::task::spawn_blocking(move || bench::run(&options, &target, &files, size, jobs))
+        .await
+        .map_err(|err| Error::Other(err.to_string()))??;
+    match args.sign.output {
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&bench).map_err(|err| err.to_string())?;
+            println!("{}", logging::mask(&json));
+        }
+        OutputFormat::Text => eprint!("{}", logging::mask(&bench.table())),
+    }
+    match (bench.signed, bench.failed) {
+        (_, 0) => Ok(()),
+        (0, _) => Err(Error::AllFailed { total: bench.files }),
+        (_, failed) => Err(Error::PartialFailure { failed, total: bench.files }),
+    }
+}
+
+/// The files of the arguments of verify and timestamp, globs expanded
+fn expand_globs(patterns: &[String]) -> Result<Vec<String>, Error> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let matched: Vec<String> = match pattern.contains(['*', '?', '[']) {
+            true => glob::glob(pattern)
+                .map_err(|err| Error::Usage(format!("'{}' is not a valid glob: {}", pattern, err)))?
+                .flatten()
+                .map(|path| path.display().to_string())
+                .collect(),
+            false => vec![pattern.clone()],
+        };
+        // a glob without matches is reported like a missing file
+        files.extend(match matched.is_empty() {
+            true => vec![pattern.clone()],
+            false => matched,
+        });
+    }
+    Ok(files)
+}
+
+/// Verify the files and globs given, failing when any of them isn't validly signed
+fn verify(args: VerifyArgs) -> Result<(), Error> {
+    let verifier = verify::verifier(args.verify_with_signtool, args.sing_tool_path.as_deref())?;
+    let paths = expand_globs(&args.file)?;
+    let (files, skipped) = verify::select(&paths, args.recursive, &args.extensions(), &args.exclude)?;
+    let expected = certificate::Expected { subject: args.expect_subject, thumbprints: args.expect_thumbprint };
+    let mut verifications: Vec<Verification> =
+        files.iter().map(|file| verify::verify(&verifier, file)).collect();
+    for verification in verifications.iter_mut().filter(|verification| verification.valid && !expected.is_empty()) {
+        let checked = match certificate::of_file(&verification.path) {
+            Some(certificate) => expected.check(&verification.path, &certificate),
+            None => Err(format!("the signing certificate of '{}' could not be read to check it", verification.path)),
+        };
+        if let Err(err) = checked {
+            verification.valid = false;
+            verification.error = Some(err);
+        }
+    }
+    // files of a tree without a signature may be meant to stay so, unless --require-all
+    let tolerated = |verification: &Verification| verification.unsigned && args.recursive && !args.require_all;
+    let failed = verifications.iter().filter(|verification| !verification.valid && !tolerated(verification)).count();
+    for verification in &verifications {
+        let unknown = || "unknown".to_string();
+        match (verification.valid, &verification.error) {
+            _ if verification.unsigned => eprintln!("unsigned {}", verification.path),
+            (false, error) if verification.status == verify::Status::UntrustedRoot => {
+                eprintln!("untrusted {}: {}", verification.path, error.clone().unwrap_or_else(unknown))
+            }
+            (true, _) => eprintln!(
+                "valid    {}: {} issued by {}, {}, timestamped {} by {}",
+                verification.path,
+                verification.subject.clone().unwrap_or_else(unknown),
+                verification.issuer.clone().unwrap_or_else(unknown),
+                verification.digest.clone().unwrap_or_else(unknown),
+                verification.timestamp.clone().unwrap_or_else(unknown),
+                verification.timestamp_authority.clone().unwrap_or_else(unknown),
+            ),
+            (false, error) => eprintln!("invalid  {}: {}", verification.path, error.clone().unwrap_or_else(unknown)),
+        }
+    }
+    for skipped in &skipped {
+        match skipped.skip {
+            verify::Skip::Excluded => eprintln!("excluded {}: {}", skipped.path, skipped.reason),
+            verify::Skip::NotSignable => debug!("not signable {}: {}", skipped.path, skipped.reason),
+        }
+    }
+    let summary = verify::Summary::new(&verifications, &skipped);
+    if args.recursive {
+        eprintln!(
+            "{} verified, {} unsigned, {} invalid, {} excluded, {} not signable",
+            summary.verified, summary.unsigned, summary.invalid, summary.excluded, summary.not_signable
+        );
+    }
+    if args.output == OutputFormat::Json {
+        let json = serde_json::json!({
+            "files": verifications,
+            "total": files.len(),
+            "failed": failed,
+            "summary": summary,
+            "skipped": skipped,
+        });
+        println!("{}", serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?);
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::VerificationFailed { failed, total: files.len() }),
+    }
+}
+
+/// Timestamp the files and globs given, failing when any of them didn't get a timestamp
+fn timestamp(args: TimestampArgs) -> Result<(), Error> {
+    let sign_tool_path = verify::find_signtool(args.sing_tool_path.as_deref())?;
+    let verifier = verify::Verifier::new(args.verify_with_signtool, &sign_tool_path);
+    let listed = match args.pending {
+        true => pending::all(),
+        false => Vec::new(),
+    };
+    let files = match args.pending {
+        true => listed.iter().map(|entry| entry.path.clone()).collect(),
+        false => expand_globs(&args.file)?,
+    };
+    if args.pending && files.is_empty() {
+        info!("no files are awaiting a timestamp");
+    }
+    let mut results = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        // a file changed since it was signed would get a timestamp over a signature that isn't the one listed
+        let timestamped = match listed.get(index).and_then(pending::changed) {
+            Some(changed) => timestamp::Timestamped { path: file.clone(), error: Some(changed), ..Default::default() },
+            None => timestamp::timestamp(&sign_tool_path, &verifier, &args.tr, args.td, args.retries, file),
+        };
+        match (&timestamped.error, &timestamped.timestamp_url) {
+            (None, Some(url)) => info!("timestamped {} by {}", file, url),
+            (error, _) => warn!("{} could not be timestamped: {}", file, error.as_deref().unwrap_or("unknown")),
+        }
+        results.push(timestamped);
+    }
+    let failed = results.iter().filter(|timestamped| timestamped.error.is_some()).count();
+    if args.pending {
+        let done: Vec<String> =
+            results.iter().filter(|timestamped| timestamped.error.is_none()).map(|done| done.path.clone()).collect();
+        pending::remove(&done)?;
+        if failed > 0 {
+            warn!("{} file(s) are still awaiting a timestamp", failed);
+        }
+    }
+    if args.output == OutputFormat::Json {
+        let json = serde_json::json!({ "files": results, "total": files.len(), "failed": failed });
+        println!("{}", serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?);
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::Signing {
+            path: String::new(),
+            message: format!("{} of {} files could not be timestamped", failed, files.len()),
+            output: String::new(),
+        }),
+    }
+}
+
+/// Remove the signatures of the files and globs given, failing when any of them couldn't be unsigned
+fn unsign(args: UnsignArgs) -> Result<(), Error> {
+    let files = expand_globs(&args.file)?;
+    let mut results = Vec::new();
+    for file in &files {
+        let unsigned = unsign::unsign(file, args.backup);
+        match (&unsigned.error, unsigned.removed) {
+            (None, 0) => info!("{} has no signature", file),
+            (None, removed) => info!("removed {} signature(s) from {}", removed, file),
+            (Some(error), _) => warn!("{} could not be unsigned: {}", file, error),
+        }
+        results.push(unsigned);
+    }
+    let failed = results.iter().filter(|unsigned| unsigned.error.is_some()).count();
+    if args.output == OutputFormat::Json {
+        let json = serde_json::json!({ "files": results, "total": files.len(), "failed": failed });
+        println!("{}", serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?);
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::Signing {
+            path: String::new(),
+            message: format!("{} of {} files could not be unsigned", failed, files.len()),
+            output: String::new(),
+        }),
+    }
+}
+
+/// Print the signatures of the files and globs given as JSON, failing when any of them couldn't be read, or has
+/// none with --fail-if-unsigned
+fn inspect(args: InspectArgs) -> Result<(), Error> {
+    let files = expand_globs(&args.file)?;
+    let inspections: Vec<_> = files.iter().map(|file| inspect::inspect(file)).collect();
+    let unsigned =
+        inspections.iter().filter(|inspection| inspection.error.is_none() && inspection.signatures.is_empty()).count();
+    for inspection in &inspections {
+        match &inspection.error {
+            Some(error) => warn!("{} could not be inspected: {}", inspection.path, error),
+            None if inspection.signatures.is_empty() => info!("{} has no signature", inspection.path),
+            None => info!("{} has {} signature(s)", inspection.path, inspection.signatures.len()),
+        }
+    }
+    let errors = inspections.iter().filter(|inspection| inspection.error.is_some()).count();
+    let json =
+        serde_json::json!({ "files": inspections, "total": files.len(), "unsigned": unsigned, "failed": errors });
+    println!("{}", serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?);
+    let failed = match args.fail_if_unsigned {
+        true => errors + unsigned,
+        false => errors,
+    };
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::VerificationFailed { failed, total: files.len() }),
+    }
+}
+
+/// Store a client secret in Credential Manager, prompted for twice or read from stdin when it's piped
+fn set_secret(args: &SetSecretArgs) -> Result<(), Error> {
+    let secret = match std::io::stdin().is_terminal() {
+        true if ci::strict() => Err(ci::prompt_refused("pipe the client secret to stdin"))?,
+        true => dialoguer::Password::new()
+            .with_prompt(format!("Client secret for '{}'", args.target))
+            .with_confirmation("Repeat the client secret", "the secrets don't match")
+            .interact()
+            .map_err(|err| Error::Other(format!("prompt failed: {}", err)))?,
+        false => {
+            let mut secret = String::new();
+            std::io::stdin().read_to_string(&mut secret).map_err(|err| format!("stdin could not be read: {}", err))?;
+            secret.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if secret.is_empty() {
+        Err(Error::Usage("the client secret is empty".to_string()))?;
+    }
+    credman::write(&args.target, &args.user_name, &secret)?;
+    eprintln!("stored the client secret as '{}', sign with --azure-client-secret-credman {}", args.target, args.target);
+    Ok(())
+}
+
+/// Sign the installers of the Tauri app at `path` signed in this run for its updater, then update latest.json
+fn tauri_updater(args: &Args, path: &Path, report: &Report) -> Result<(), Error> {
+    let cli = tauri::check_updater(path, args.tauri_stage, args.tauri_cli_path.as_deref())?;
+    let installers = tauri::outputs(path, tauri::Stage::PostBundle)?;
+    let mut signed = Vec::new();
+    for file in report.files.iter().filter(|file| file.status == Status::Signed && installers.contains(&file.path)) {
+        let signature = tauri::sign_updater(&cli, &file.path)?;
+        let size = fs::metadata(&file.path).map(|metadata| metadata.len()).unwrap_or_default();
+        info!("signed {} for the updater", file.path);
+        signed.push((file.path.clone(), signature, size));
+    }
+    let Some(latest) = &args.tauri_latest_json else {
+        return Ok(());
+    };
+    let updated = tauri::update_latest_json(latest, &signed)?;
+    match updated.is_empty() {
+        true => warn!("{} downloads none of the installers signed, it is left as it is", latest.display()),
+        false => info!("updated the signatures of {} in {}", updated.join(", "), latest.display()),
+    }
+    Ok(())
+}
+
+/// Set up the arguments for a call of electron-builder's sign hook, returning whether to sign at all
+fn electron_builder_hook(args: &mut Args) -> Result<bool, Error> {
+    let hook = electron::from_env()?;
+    if args.file.len() != 1 {
+        Err(Error::Usage(format!(
+            "electron-builder's sign hook passes a single file, got {}",
+            args.file.len()
+        )))?;
+    }
+    let Some(digest) = hook.digest else {
+        tell(&format!(
+            "skipping the SHA-1 signature of {}, Trusted Signing only signs with SHA-256 or stronger. \
+             Set signingHashAlgorithms to [\"sha256\"] to avoid this call",
+            args.file[0]
+        ));
+        return Ok(false);
+    };
+    args.fd = digest;
+    args.append_signature = hook.nested;
+    args.single = true;
+    Ok(true)
+}
+
+/// Set up the arguments for a tool calling once per file, like Inno Setup
+fn single(args: &mut Args) -> Result<(), Error> {
+    if args.file.len() != 1 {
+        Err(Error::Usage(format!("--single signs a single file, got {}", args.file.len())))?;
+    }
+    // the tool calls for every file, one login has to do for all of them
+    args.reuse_session = true;
+    args.no_progress = true;
+    args.quiet = args.log_level.is_none() && !args.verbose;
+    Ok(())
+}
+
+/// Look up the endpoint when -e isn't given: log in like the sign does, find the account of -a (or
+/// --account-resource-id) among those the principal can read and take the endpoint of its region. The sign reuses
+/// this login and the secret read for it
+pub(crate) fn discover_endpoint(args: &mut Args) -> Result<(), Error> {
+    if matches!(args.backend, Backend::KeyVault | Backend::Pfx) {
+        return Ok(());
+    }
+    if let Some(id) = &args.account_resource_id {
+        let name = azure::account_name_of(id).to_string();
+        match &args.account {
+            Some(account) if !account.eq_ignore_ascii_case(&name) => Err(Error::Usage(format!(
+                "--account-resource-id names the account '{}', not the '{}' of -a",
+                name, account
+            )))?,
+            Some(_) => (),
+            None => args.account = Some(name),
+        }
+    }
+    let (None, Some(account)) = (&args.endpoint, args.account.clone()) else {
+        return Ok(());
+    };
+    let secret = args.client_secret()?;
+    logging::register_secret(&secret);
+    match args.reuse_session
+        && session::reusable(&args.azure_cli_path, &args.azure_tenant_id, &args.azure_client_id, &secret)
+    {
+        true => info!("reusing the azure cli session of {}", args.azure_client_id),
+        false => {
+            info!("logging in to azure cli as {} to look up the endpoint of {}", args.azure_client_id, account);
+            azure::login(&args.azure_cli_path, &args.azure_tenant_id, &args.azure_client_id, &secret, false)
+                .map_err(Error::Auth)?;
+            if let Err(err) = session::save(&args.azure_tenant_id, &args.azure_client_id, &secret) {
+                debug!("{}", err);
+            }
+        }
+    }
+    let (resource_id, subscriptions) = (args.account_resource_id.as_deref(), args.subscription_id.as_slice());
+    let found = azure::find_account(&args.azure_cli_path, &account, resource_id, subscriptions)?;
+    let endpoint = found.endpoint.unwrap_or_default();
+    info!(
+        "resolved the endpoint of {} to {} ({}, resource group {} in subscription {})",
+        found.name, endpoint, found.location, found.resource_group, found.subscription_id
+    );
+    args.endpoint = Some(endpoint);
+    args.azure_client_secret = Some(secret);
+    (args.azure_client_secret_credman, args.azure_client_secret_keyvault) = (None, None);
+    args.reuse_session = true;
+    Ok(())
+}
+
+/// The artifacts of the Cargo workspace in the current directory, or of --manifest-path
+fn cargo_artifacts(selection: &CargoSelection) -> Result<Vec<Artifact>, Error> {
+    let metadata = cargo::metadata(selection.manifest_path.as_deref())?;
+    cargo::artifacts(
+        &metadata,
+        &Selection {
+            profile: Some(selection.profile()),
+            target: selection.target.as_deref(),
+            packages: &selection.package,
+            bins: &selection.bin,
+        },
+    )
+}
+
+/// Print the artifacts a `cargo --dry-run` would sign, one per line on stdout
+fn dry_run(selection: &CargoSelection) -> ExitCode {
+    match cargo_artifacts(selection) {
+        Ok(artifacts) => {
+            for artifact in &artifacts {
+                match artifact.built() {
+                    true => println!("{}", artifact.path.display()),
+                    false => eprintln!("not built: {}", artifact.path.display()),
+                }
+            }
+            let built = artifacts.iter().filter(|artifact| artifact.built()).count();
+            eprintln!("{} of {} artifacts would be signed", built, artifacts.len());
+            ExitCode::SUCCESS
+        }
+        Err(err) => fail(&err, ExitCode::from(err.exit_code())),
+    }
+}
+
+/// GitHub's job summary to append the run to, with --github-summary or by default in Actions. Other steps append
+/// to it too, outside Actions there is none
+fn step_summary(args: &Args) -> Option<PathBuf> {
+    let github = args.github_summary || CiFormat::detect(args.ci_format) == CiFormat::Github;
+    std::env::var_os("GITHUB_STEP_SUMMARY").filter(|path| github && !path.is_empty()).map(PathBuf::from)
+}
+
+/// Whether anything prints or records the SHA-256 of the files, they are only hashed then. Large MSIs take a while
+fn hashes_needed(args: &Args) -> bool {
+    args.audit_log.is_some()
+        || args.eventlog
+        || step_summary(args).is_some()
+        || args.attestation_file.is_some()
+        || args.print_hashes
+        || args.marker_files
+        || args.checksums_file.is_some()
+        || args.journal_path().is_some()
+        || args.output == OutputFormat::Json
+        || args.report.is_some()
+}
+
+/// The effective configuration a journal is valid for, files signed with another profile or options are signed again
+fn journal_config(args: &Args) -> String {
+    let target = args.target();
+    let options = [
+        ("backend", format!("{:?}", args.backend)),
+        ("endpoint", target.endpoint),
+        ("account", target.account),
+        ("certificate_profile", target.certificate),
+        ("route", format!("{:?}", args.route)),
+        ("fallback_endpoint", format!("{:?}", args.fallback_endpoint)),
+        ("also_profile", format!("{:?}", args.also_profile)),
+        ("fd", format!("{:?}", args.fd)),
+        ("tr", args.tr.clone()),
+        ("td", format!("{:?}", args.td)),
+        ("description", format!("{:?}", args.description)),
+        ("append_signature", args.append_signature.to_string()),
+        ("detached_p7", format!("{:?}", args.detached_p7)),
+        ("require_sha2", args.require_sha2.to_string()),
+        ("nuget", args.nuget.to_string()),
+        ("clickonce", args.clickonce.to_string()),
+        ("deep", args.deep.to_string()),
+        ("burn_bundle", args.burn_bundle.to_string()),
+        ("driver_package", format!("{:?}", args.driver_package)),
+        ("catalog", format!("{:?}", args.catalog)),
+        ("expect_subject", format!("{:?}", args.expect_subject)),
+        ("expect_thumbprint", format!("{:?}", args.expect_thumbprint)),
+        ("pin_subject", format!("{:?}", args.pin_subject)),
+    ];
+    options.map(|(name, value)| format!("{}={}", name, value)).join("\n")
+}
+
+/// Append the outcome of a file to the journal, a journal that can't be written doesn't stop the run
+fn journal_file(journal: &mut Option<Journal>, file: &FileReport) {
+    if let Some(Err(err)) = journal.as_mut().map(|journal| journal.record(file)) {
+        warn!("{}, the run is not affected", err);
+    }
+}
+
+/// The library options of the sign arguments
+pub(crate) fn sign_options(args: &Args, correlation_id: Option<String>) -> Result<SignOptions, Error> {
+    let required = [(&args.endpoint, "--endpoint"), (&args.account, "--account"), (&args.certificate, "--certificate")];
+    let missing: Vec<&str> = required
+        .into_iter()
+        .filter(|(value, _)| matches!(args.backend, Backend::TrustedSigning | Backend::Rest) && value.is_none())
+        .map(|(_, flag)| flag)
+        .collect();
+    if !missing.is_empty() {
+        Err(Error::Usage(format!("signing with Trusted Signing needs {}", missing.join(", "))))?;
+    }
+    if args.require_sha2 {
+        let weak = [
+            (args.fd == Digest::Sha1, "--fd SHA1"),
+            (args.td == Digest::Sha1, "--td SHA1"),
+            (args.catalog.is_some() && args.catalog_hash == catalog::CatalogHash::Sha1, "--catalog-hash SHA1"),
+        ];
+        let weak: Vec<&str> = weak.into_iter().filter(|(weak, _)| *weak).map(|(_, flag)| flag).collect();
+        if !weak.is_empty() {
+            Err(Error::Usage(format!("--require-sha2 allows SHA-2 only, drop {}", weak.join(" and "))))?;
+        }
+    }
+    if args.verify_revocation && !cfg!(windows) {
+        let message = "--verify-revocation builds the chain with Windows' CertGetCertificateChain, it needs Windows";
+        Err(Error::Usage(message.to_string()))?;
+    }
+    // test signatures and release ones never come out of the same run
+    let other_backends = match args.backend {
+        Backend::Pfx => vec![
+            (args.endpoint.is_some(), "--endpoint"),
+            (args.account.is_some(), "--account"),
+            (args.account_resource_id.is_some(), "--account-resource-id"),
+            (args.certificate.is_some(), "--certificate"),
+            (args.kv_uri.is_some(), "--kv-uri"),
+            (args.kv_certificate.is_some(), "--kv-certificate"),
+            (!args.route.is_empty(), "--route"),
+            (!args.fallback_endpoint.is_empty(), "--fallback-endpoint"),
+        ],
+        _ => vec![(args.pfx.is_some(), "--pfx")],
+    };
+    let other_backends: Vec<&str> = other_backends.into_iter().filter(|(set, _)| *set).map(|(_, flag)| flag).collect();
+    match (args.backend, other_backends.is_empty()) {
+        (_, true) => (),
+        (Backend::Pfx, false) => Err(Error::Usage(format!(
+            "--backend pfx signs with the PFX only, one run doesn't mix backends: drop {}",
+            other_backends.join(", ")
+        )))?,
+        (_, false) => Err(Error::Usage(
+            "--pfx signs under --backend pfx only, one run doesn't mix backends".to_string(),
+        ))?,
+    }
+    if args.also_profile.is_some() && args.backend != Backend::TrustedSigning {
+        Err(Error::Usage("--also-profile appends with signtool, it doesn't work with another --backend".to_string()))?;
+    }
+    if args.detached_p7.is_some() && args.backend != Backend::TrustedSigning {
+        Err(Error::Usage("--detached-p7 signs with signtool, it doesn't work with another --backend".to_string()))?;
+    }
+    if args.signtool_template.is_some() && args.backend != Backend::TrustedSigning {
+        Err(Error::Usage(
+            "--signtool-template arranges the /dlib arguments of signtool, it doesn't work with another --backend"
+                .to_string(),
+        ))?;
+    }
+    if !args.signtool_extra_arg.is_empty() && matches!(args.backend, Backend::KeyVault | Backend::Rest) {
+        Err(Error::Usage("--signtool-extra-arg is passed to signtool, which this --backend doesn't run".to_string()))?;
+    }
+    let target = args.target();
+    let secret = match args.backend {
+        Backend::Pfx => String::new(),
+        _ => args.client_secret()?,
+    };
+    let options = match args.backend {
+        Backend::TrustedSigning | Backend::Rest => {
+            let options = SignOptions::new(&target.endpoint, &target.account, &target.certificate)?;
+            options.rest(args.backend == Backend::Rest)
+        }
+        Backend::KeyVault => SignOptions::key_vault(&target.endpoint, &target.certificate)?,
+        Backend::Pfx => SignOptions::pfx(&args.pfx.clone().unwrap_or_default(), &args.pfx_password()?)?,
+    };
+    let options = options
+        .azure_sign_tool_path(&args.azure_sign_tool_path)
+        .osslsigncode_path(&args.osslsigncode_path)
+        .credentials(&args.azure_tenant_id, &args.azure_client_id, &secret)
+        .azure_cli_path(&args.azure_cli_path)
+        .sign_tool_path(&args.sing_tool_path)
+        .verify_with_signtool(args.verify_with_signtool)
+        .file_digest(args.fd)
+        .timestamp(&args.tr, args.td)
+        .description(args.description.clone())
+        .file_descriptions(args.file_descriptions.clone())
+        .append_signature(args.append_signature)
+        .detached_p7(args.detached_p7.clone(), &args.p7_content_type, args.p7_content)
+        .correlation_id(correlation_id)
+        .correlation_per_file(args.correlation_per_file)
+        .ignore_unsupported(args.ignore_unsupported)
+        .skip_invalid(args.skip_invalid)
+        .extensions(args.extensions())
+        .validate_profile(args.validate_profile)
+        .show_account(args.verbose)
+        .reuse_session(args.reuse_session)
+        .keep_metadata(args.single)
+        .strict_permissions(args.strict_permissions)
+        .check_clock(args.check_clock)
+        .strict_clock(args.strict_clock)
+        .preflight_network(args.preflight_network)
+        .allow_custom_endpoint(args.allow_custom_endpoint)
+        .fallback_endpoints(args.fallback_endpoint.clone())
+        .also_profile(args.also_profile.clone())
+        .fail_on_warnings(args.fail_on_warnings)
+        .require_sha2(args.require_sha2)
+        .validate_pe(args.validate_pe)
+        .expect(args.expect_arch, args.expect_subsystem)
+        .expect_certificate(certificate::Expected {
+            subject: args.expect_subject.clone(),
+            thumbprints: args.expect_thumbprint.clone(),
+        })
+        .exclude_signed_by(args.exclude_signed_by.clone())
+        .pin(
+            Pin::new(args.pin_subject.clone(), args.pin_file.clone(), args.pin_mode, args.update_pin)
+                .map_err(Error::Usage)?,
+        )
+        .check_scripts(args.ps_module.is_some())
+        .verify_revocation(args.verify_revocation.then_some(args.revocation_offline))
+        .wait_for_file(args.wait_for_file)
+        .preserve_timestamps(args.preserve_timestamps)
+        .defer_timestamp_on_failure(args.defer_timestamp_on_failure)
+        .warn_expiry((args.warn_expiry > 0).then_some(args.warn_expiry))
+        .quota(args.quota_warn, args.quota_hard_limit)
+        .dlib_package(args.dlib_package.clone())
+        .nuget(args.nuget.then(|| args.nuget_certificate_fingerprint.clone()).flatten(), args.dotnet_path.clone())
+        .vsix_tool(args.vsix_tool.clone())
+        .clickonce(args.clickonce.then(|| args.clickonce_certificate_hash.clone()).flatten(), args.mage_path.clone())
+        .deep_bundles(args.deep, args.makeappx_path.clone(), args.publisher.clone())
+        .skip_publisher_check(args.skip_publisher_check)
+        .burn_bundles(args.burn_bundle, args.wix_path.clone())
+        .squirrel(args.squirrel_releases.is_some())
+        .page_hashes(args.driver_package.is_some())
+        .signtool_template(
+            args.signtool_template.as_deref().map(signtool::Template::parse).transpose().map_err(Error::Usage)?,
+            args.signtool_extra_arg.clone(),
+        )
+        .hash(hashes_needed(args));
+    #[cfg(feature = "download")]
+    let options = options.dlib_feed(args.dlib_feed.clone(), args.feed_token.clone());
+    Ok(args.route.iter().cloned().fold(options, SignOptions::route))
+}
+
+/// Get a catalog ready to be signed after the binaries before it: the --catalog generated with makecat, the others
+/// generated with inf2cat for --inf2cat-os, once, and checked to list every binary signed so far from their
+/// directory. A failed binary fails the catalog too
+fn prepare_catalog(args: &Args, report: &Report, catalog: &str, generated: &mut bool) -> Result<(), Error> {
+    let failed: Vec<&str> = report
+        .files
+        .iter()
+        .filter(|file| file.status == Status::Failed)
+        .filter(|file| driver::is_binary(&file.path) || (args.ps_module.is_some() && psmodule::is_script(&file.path)))
+        .map(|file| file.path.as_str())
+        .collect();
+    if !failed.is_empty() {
+        let what = match args.ps_module.is_some() {
+            true => "scripts",
+            false => "binaries",
+        };
+        Err(Error::Signing {
+            path: catalog.to_string(),
+            message: format!("catalog '{}' is not signed, the {} {} failed", catalog, what, failed.join(", ")),
+            output: String::new(),
+        })?;
+    }
+    if let Some(generating) = args.catalog.as_deref().filter(|generating| *generating == Path::new(catalog)) {
+        info!("generating the catalog {} with makecat", catalog);
+        let makecat = catalog::find_makecat(args.makecat_path.as_deref(), &args.sing_tool_path)?;
+        let (hash, attributes) = (args.catalog_hash, &args.catalog_attribute);
+        let file_paths = args.ps_module.is_some();
+        return catalog::generate(&makecat, generating, hash, attributes, &args.catalog_covers, file_paths);
+    }
+    if let (Some(os), Some(dir), false) = (&args.inf2cat_os, &args.driver_package, *generated) {
+        info!("generating the catalogs of {} for {}", dir.display(), os);
+        driver::generate(&driver::find_inf2cat(args.inf2cat_path.as_deref())?, dir, os)?;
+        *generated = true;
+    }
+    let dir = Path::new(catalog).parent().unwrap_or(Path::new(""));
+    let signed: Vec<String> = report
+        .files
+        .iter()
+        .filter(|file| file.status == Status::Signed && driver::is_binary(&file.path))
+        .filter(|file| Path::new(&file.path).starts_with(dir))
+        .map(|file| file.path.clone())
+        .collect();
+    let uncovered = driver::uncovered(Path::new(catalog), &signed)?;
+    if !uncovered.is_empty() {
+        Err(Error::Signing {
+            path: catalog.to_string(),
+            message: format!(
+                "catalog '{}' doesn't match {}, the package would fail to install: generate the catalog again \
+                 after building the binaries, or pass --inf2cat-os",
+                catalog,
+                uncovered.join(", ")
+            ),
+            output: String::new(),
+        })?;
+    }
+    debug!("catalog {} covers {}", catalog, signed.join(", "));
+    Ok(())
+}
+
+/// Run --post-sign-cmd or --on-failure-cmd once the result of a file is known, a failing post-sign command fails
+/// the file
+fn after_sign(args: &Args, file: &str, result: Result<(), Error>) -> Result<(), Error> {
+    let (name, command) = match &result {
+        Ok(()) => ("post-sign", &args.post_sign_cmd),
+        Err(_) => ("on-failure", &args.on_failure_cmd),
+    };
+    let Some(command) = command else {
+        return result;
+    };
+    let error = result.as_ref().err().map(|err| logging::mask(&err.to_string())).unwrap_or_default();
+    let status = match result.is_ok() {
+        true => "signed",
+        false => "failed",
+    };
+    match (result, hooks::run(name, command, &[("file", file), ("status", status), ("error", &error)])) {
+        (result, Ok(())) => result,
+        (Ok(()), Err(err)) => Err(Error::Other(err)),
+        (Err(err), Err(hook)) => {
+            warn!("{}", hook);
+            Err(err)
+        }
+    }
+}
+
+/// Write the event of a file the signer didn't get to sign, skipped or failed before
+fn log_unsigned(eventlog: Option<&EventLog>, args: &Args, file: &FileReport, signtool_version: Option<&str>) {
+    let Some(eventlog) = eventlog else {
+        return;
+    };
+    let target = args.target();
+    let entry = AuditEntry {
+        timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        path: std::path::absolute(&file.path).unwrap_or_else(|_| file.path.clone().into()).display().to_string(),
+        sha256_before: None,
+        sha256_after: None,
+        endpoint: &target.endpoint,
+        account: &target.account,
+        certificate_profile: file.certificate_profile.as_deref().unwrap_or(&target.certificate),
+        certificate: None,
+        azure_client_id: &args.azure_client_id,
+        signtool_version,
+        dlib_version: DLIB_VERSION,
+        status: file.status,
+        error: file.error.as_deref(),
+        client: None,
+    };
+    eventlog.write(&eventlog::file_event(&entry, file.skip_reason.as_deref()));
+}
+
+async fn run(
+    args: &Args,
+    report: &mut Report,
+    eventlog: Option<&EventLog>,
+    #[cfg(feature = "otlp")] trace: Option<&otlp::Trace>,
+) -> Result<(), Error> {
+    let started = Instant::now();
+    interrupt::install();
+    if let Some(max_runtime) = args.max_runtime {
+        interrupt::set_deadline(max_runtime);
+    }
+    redact::set_enabled(!args.no_redact);
+
+    let mut events = Events::new(args.events);
+    match CiFormat::detect(args.ci_format) {
+        // TeamCity reads its service messages from stdout, which is the signed file with --pipe
+        CiFormat::Teamcity if args.pipe => (),
+        format => {
+            if let Some(sink) = format.sink() {
+                events.add(sink);
+            }
+        }
+    }
+    #[cfg(feature = "otlp")]
+    if let Some(trace) = trace {
+        events.add(trace.sink());
+    }
+    let mut signer = Signer::new(sign_options(args, report.correlation_id.clone())?, events);
+    let target = args.target();
+    signer.events().emit(Event::RunStarted {
+        config: RunConfig {
+            endpoint: &target.endpoint,
+            account: &target.account,
+            certificate_profile: &target.certificate,
+            azure_client_id: &args.azure_client_id,
+            azure_cli_path: &args.azure_cli_path,
+            sign_tool_path: &args.sing_tool_path,
+            files: &args.file,
+        },
+    });
+
+    if let Some(dir) = args.marker_dir.as_deref() {
+        let mut markers = std::collections::HashSet::new();
+        if let Some(file) = args.file.iter().find(|file| !markers.insert(marker::path(file, Some(dir)))) {
+            Err(Error::Usage(format!("another file is named like {}, their markers would be the same file", file)))?;
+        }
+    }
+    if let Some(command) = &args.pre_run_cmd {
+        hooks::run("pre-run", command, &[])?;
+    }
+    let journal = args.journal_path().map(|path| Journal::open(&path, &args.file, &journal_config(args), args.resume));
+    let mut journal = match journal {
+        // only the journal of a large run was asked for by nobody
+        Some(Err(err)) if args.journal.is_none() && !args.resume => {
+            warn!("{}, the run is not affected", err);
+            None
+        }
+        journal => journal.transpose().map_err(Error::Other)?,
+    };
+    let resumed: std::collections::HashSet<&str> = match &journal {
+        Some(journal) => args.file.iter().filter(|file| journal.is_done(file)).map(String::as_str).collect(),
+        None => Default::default(),
+    };
+    if let (Some(journal), false) = (&journal, resumed.is_empty()) {
+        let path = journal.path().display();
+        info!("resuming {}: {} of {} files were signed by an earlier run", path, resumed.len(), args.file.len());
+    }
+    // a run skipping every file doesn't need the signing client or a login, unless a hook could change the files
+    let idle = !args.strict_auth
+        && args.pre_sign_cmd.is_none()
+        && args.file.iter().all(|file| {
+            let marker = args.marker_files.then(|| marker::path(file, args.marker_dir.as_deref()));
+            let current = marker.is_some_and(|marker| marker::is_current(&marker, file));
+            let resumed = resumed.contains(file.as_str());
+            !driver::is_catalog(file) && (current || resumed || signer.skip_reason(file).is_some())
+        });
+    let connected = match idle {
+        true => {
+            info!("every file is skipped, not logging in");
+            Ok(())
+        }
+        false => tokio::select! {
+            biased;
+            () = interrupt::requested() => Err(interrupt::error()),
+            connected = signer.connect() => connected,
+        },
+    };
+    report.metrics = signer.metrics().clone();
+    report.azure_cli_version = signer.azure_cli_version().map(|version| version.to_string());
+    report.profile_warnings = signer.profile_warnings().to_vec();
+    report.clock = signer.clock().cloned();
+    connected?;
+
+    let mut audit_log = args.audit_log.as_deref().map(AuditLog::open).transpose()?;
+    let signtool_version = signer.signtool_version().map(str::to_string);
+    let mut attestation = Attestation::default();
+
+    // iterate over files, stopping at the first failure
+    let mut failure = None;
+    let mut generated = false;
+    let mut progress = Progress::new(args.file.len(), args.no_progress || args.quiet);
+    let mut slowest = Duration::ZERO;
+    for (index, file) in args.file.iter().enumerate() {
+        // the files left stay not attempted
+        if interrupt::interrupted() {
+            break;
+        }
+        if !interrupt::fits(slowest) {
+            let slowest = slowest.as_secs_f64();
+            info!("not starting {}, less of --max-runtime is left than the slowest file's {:.1}s", file, slowest);
+            interrupt::stop_for_deadline();
+            break;
+        }
+        let span = debug_span!("sign", file = %file);
+        span.in_scope(|| info!("signing {}", file));
+        progress.start(file);
+        signer.events().emit(Event::FileStarted {
+            path: file,
+            index,
+            total: args.file.len(),
+        });
+        let marker = args.marker_files.then(|| marker::path(file, args.marker_dir.as_deref()));
+        if marker.as_deref().is_some_and(|marker| marker::is_current(marker, file)) {
+            span.in_scope(|| info!("skipped {}: its marker is current, it was signed by an earlier run", file));
+            progress.complete(true);
+            report.skip(index, "its marker is current");
+            signer.events().emit(Event::FileFinished {
+                file: &report.files[index],
+            });
+            log_unsigned(eventlog, args, &report.files[index], signtool_version.as_deref());
+            continue;
+        }
+        if resumed.contains(file.as_str()) {
+            span.in_scope(|| info!("skipped {}: the journal records it as signed by an earlier run", file));
+            progress.complete(true);
+            report.skip(index, "the journal records it as signed");
+            signer.events().emit(Event::FileFinished {
+                file: &report.files[index],
+            });
+            log_unsigned(eventlog, args, &report.files[index], signtool_version.as_deref());
+            continue;
+        }
+        let pre_sign = args.pre_sign_cmd.as_ref().map(|command| hooks::run("pre-sign", command, &[("file", file)]));
+        let prepared = match pre_sign {
+            Some(Err(err)) if args.pre_sign_failure == PreSignFailure::Skip => {
+                span.in_scope(|| warn!("{}, skipping {}", err, file));
+                progress.complete(true);
+                report.skip(index, "the pre-sign command failed");
+                journal_file(&mut journal, &report.files[index]);
+                signer.events().emit(Event::FileFinished {
+                    file: &report.files[index],
+                });
+                log_unsigned(eventlog, args, &report.files[index], signtool_version.as_deref());
+                continue;
+            }
+            Some(Err(err)) => Err(Error::Other(err)),
+            _ if driver::is_catalog(file) => span.in_scope(|| prepare_catalog(args, report, file, &mut generated)),
+            _ => Ok(()),
+        };
+        if let Err(err) = prepared {
+            let _span = span.entered();
+            let result = after_sign(args, file, Err(err));
+            progress.complete(false);
+            report.record(index, &result, None, Duration::ZERO);
+            journal_file(&mut journal, &report.files[index]);
+            signer.events().emit(Event::FileFinished {
+                file: &report.files[index],
+            });
+            log_unsigned(eventlog, args, &report.files[index], signtool_version.as_deref());
+            info!("{} {}", report.files[index].status.styled(0), file);
+            match args.continue_on_error {
+                true => continue,
+                false => {
+                    failure = result.err();
+                    break;
+                }
+            }
+        }
+        let (outcome, mut result) = signer.attempt(file).instrument(span.clone()).await;
+        slowest = slowest.max(outcome.duration);
+        let _span = span.entered();
+        if let Some(reason) = outcome.skipped {
+            progress.complete(true);
+            report.skip(index, reason);
+            report.files[index].protected_by = outcome.protected_by.clone();
+            journal_file(&mut journal, &report.files[index]);
+            signer.events().emit(Event::FileFinished {
+                file: &report.files[index],
+            });
+            log_unsigned(eventlog, args, &report.files[index], signtool_version.as_deref());
+            continue;
+        }
+        report.files[index].certificate_profile = Some(outcome.target.certificate.clone());
+        report.files[index].endpoint = Some(outcome.target.endpoint.clone());
+        report.files[index].mechanism = Some(outcome.mechanism);
+        report.files[index].signatures = outcome.signatures.clone();
+        report.files[index].also_certificate_profile =
+            outcome.also_signed.as_ref().map(|also| also.certificate.clone());
+        report.files[index].sha256_before = outcome.sha256_before.clone();
+        report.files[index].sha256_after = outcome.sha256_after.clone();
+        report.files[index].warnings = outcome.warnings.clone();
+        report.files[index].certificate = outcome.certificate.clone();
+        report.files[index].signature_file = outcome.signature_file.clone();
+        report.files[index].digests = outcome.digests.clone();
+        report.files[index].revocation = outcome.revocation.clone();
+        report.files[index].retries = outcome.retries;
+        report.files[index].awaiting_timestamp = outcome.awaiting_timestamp && result.is_ok();
+        if args.correlation_per_file {
+            report.files[index].correlation_id = outcome.correlation_id.clone();
+        }
+        if let (Ok(()), Some(generated)) = (&result, &args.catalog) {
+            if generated == Path::new(file) {
+                result = catalog::check(&args.sing_tool_path, generated, &args.catalog_covers);
+            }
+        }
+        result = after_sign(args, file, result);
+        progress.complete(result.is_ok());
+        report.record(index, &result, outcome.size, outcome.duration);
+        report.files[index].test_signed = args.backend == Backend::Pfx && result.is_ok();
+        journal_file(&mut journal, &report.files[index]);
+        signer.events().emit(Event::FileFinished {
+            file: &report.files[index],
+        });
+        let thumbprint = match (&result, &outcome.certificate) {
+            (Ok(()), Some(certificate)) => format!(" ({})", certificate.thumbprint),
+            _ => String::new(),
+        };
+        info!("{} {}{}", report.files[index].status.styled(0), file, thumbprint);
+        if let (true, Ok(()), Some(sha256)) = (args.print_hashes, &result, &outcome.sha256_after) {
+            println!("{}  {}", sha256, file);
+        }
+        if let (Some(marker), Ok(()), Some(sha256)) = (&marker, &result, &outcome.sha256_after) {
+            let recorded = Marker {
+                sha256: sha256.clone(),
+                signed_at: outcome.signed_at.clone(),
+            };
+            if let Err(err) = marker::write(marker, &recorded) {
+                warn!("{}", err);
+            }
+        }
+        if audit_log.is_some() || eventlog.is_some() {
+            let entry = AuditEntry {
+                timestamp: outcome.signed_at.clone(),
+                path: std::path::absolute(file).unwrap_or_else(|_| file.into()).display().to_string(),
+                sha256_before: outcome.sha256_before.clone(),
+                sha256_after: outcome.sha256_after.clone(),
+                endpoint: &outcome.target.endpoint,
+                account: &outcome.target.account,
+                certificate_profile: &outcome.target.certificate,
+                certificate: outcome.certificate.as_ref(),
+                azure_client_id: &args.azure_client_id,
+                signtool_version: signtool_version.as_deref(),
+                dlib_version: DLIB_VERSION,
+                status: report.files[index].status,
+                error: report.files[index].error.as_deref(),
+                client: None,
+            };
+            if let Some(eventlog) = eventlog {
+                eventlog.write(&eventlog::file_event(&entry, None));
+            }
+            if let Some(Err(err)) = audit_log.as_mut().map(|audit_log| audit_log.append(&entry)) {
+                result = result.and(Err(Error::Other(err)));
+            }
+        }
+        if let (Some(sha256_before), Some(sha256_after)) = (outcome.sha256_before, outcome.sha256_after) {
+            attestation.add(AttestedFile {
+                name: file.clone(),
+                sha256_before,
+                sha256_after,
+                size: fs::metadata(file).map(|metadata| metadata.len()).unwrap_or(0),
+                certificate_profile: outcome.target.certificate,
+                endpoint: outcome.target.endpoint,
+                timestamp_authority: args.tr.clone(),
+                signed_at: outcome.signed_at,
+            });
+        }
+        if let Err(err) = result {
+            if !args.continue_on_error {
+                failure = Some(err);
+                break;
+            }
+        }
+    }
+    drop(progress);
+    if interrupt::interrupted() {
+        failure = Some(interrupt::error());
+    }
+
+    report.metrics = signer.metrics().clone();
+    report.quota = signer.quota().to_vec();
+    report.finish(started.elapsed());
+    if failure.is_none() && report.summary.failed > 0 {
+        failure = Some(match report.summary.signed {
+            0 => Error::AllFailed {
+                total: report.files.len(),
+            },
+            _ => Error::PartialFailure {
+                failed: report.summary.failed,
+                total: report.files.len(),
+            },
+        });
+    }
+    if let (Some(path), true) = (&args.tauri, args.tauri_updater) {
+        // the installers that did get signed need a new updater signature, even when others failed
+        match (tauri_updater(args, path, report), &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (Err(err), None) => failure = Some(err),
+            (Ok(()), _) => (),
+        }
+    }
+    if let Some(path) = &args.update_latest_yml {
+        // the files that did get signed changed, even when others failed
+        match (electron::update_latest_yml(path), &failure) {
+            (Ok(updated), _) => {
+                for latest in &updated {
+                    for file in &latest.missing {
+                        let path = &latest.path;
+                        warn!("'{}' references {}, which isn't next to it, its entry is left as it is", path, file);
+                    }
+                    if !latest.rewritten.is_empty() {
+                        info!("updated the entries of {} in {}", latest.rewritten.join(", "), latest.path);
+                    }
+                }
+                report.latest_yml = updated;
+            }
+            (Err(err), Some(_)) => warn!("{}", err),
+            (Err(err), None) => failure = Some(err),
+        }
+    }
+    if let Some(path) = &args.checksums_file {
+        // the files that did get signed are listed, even when others failed
+        let base = args.checksums_base.as_deref();
+        match (checksums::write(path, base, args.checksums_format, &report.files), &failure) {
+            (Ok(written), _) => {
+                info!("wrote the checksums of {} file(s) to {}", written.files, written.path);
+                report.checksums = Some(written);
+            }
+            (Err(err), Some(_)) => warn!("{}", err),
+            (Err(err), None) => failure = Some(err),
+        }
+    }
+    if let Some(command) = &args.post_run_cmd {
+        let error = failure.as_ref().map(|err| logging::mask(&err.to_string())).unwrap_or_default();
+        let status = match failure.is_none() {
+            true => "succeeded",
+            false => "failed",
+        };
+        let [signed, failed, total] =
+            [report.summary.signed, report.summary.failed, report.files.len()].map(|count| count.to_string());
+        let counts = [("signed", signed.as_str()), ("failed", &failed), ("total", &total)];
+        let placeholders = [[("status", status), ("error", &error)].as_slice(), &counts].concat();
+        match (hooks::run("post-run", command, &placeholders), &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (Err(err), None) => failure = Some(Error::Other(err)),
+            (Ok(()), _) => (),
+        }
+    }
+    signer.events().emit(Event::RunFinished { report });
+    progress::suspend(|| print_summary(args, report, &mut io::stderr()));
+    if let Some(path) = &args.metrics_file {
+        match (report.metrics.write_raw(path), &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (result, _) => result?,
+        }
+    }
+    if let Some(path) = &args.attestation_file {
+        let complete = failure.is_none() && report.summary.signed == report.files.len();
+        match (attestation.write(path, complete, DLIB_VERSION, ci::run_url()), &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (result, _) => result?,
+        }
+    }
+    if let Some(path) = &args.manifest {
+        // the files that did get signed changed, even when others failed
+        let signed: Vec<String> = report
+            .files
+            .iter()
+            .filter(|file| file.status == Status::Signed)
+            .map(|file| file.path.clone())
+            .collect();
+        match (manifest::update(path, args.manifest_format, &signed, &args.extensions()), &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (result, _) => result?,
+        }
+    }
+    if let Some(dir) = &args.squirrel_releases {
+        // the packages that did get signed changed, even when others failed
+        let releases = squirrel::update_releases(dir).and_then(|changed| {
+            if changed {
+                info!("updated the packages in {}", dir.join(squirrel::RELEASES).display());
+            }
+            squirrel::verify_releases(dir)
+        });
+        match (releases, &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (result, _) => result?,
+        }
+    }
+    if let (Some(format), Some(path)) = (args.report, &args.report_file) {
+        // a signing failure is the more important error to surface
+        match (report.write(format, path), &failure) {
+            (Err(err), Some(_)) => warn!("{}", err),
+            (result, _) => result?,
+        }
+    }
+
+    if let Some(journal) = journal {
+        let path = journal.path().display().to_string();
+        match (&failure, journal) {
+            (None, journal) => match journal.remove() {
+                Ok(()) => debug!("deleted the journal {}", path),
+                Err(err) => warn!("{}", err),
+            },
+            (Some(_), _) => info!("the journal {} records the files done, sign the rest with --resume", path),
+        }
+    }
+    if let Some(err) = failure {
+        Err(err)?;
+    }
+
+    info!("signing complete");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::Command, error::exit_code};
+
+    fn sign_args(extra: &[&str]) -> Args {
+        let argv = ["tsc", "sign", "-e", "eus", "-a", "acc", "-c", "prof", "--azure-client-id", "c"];
+        let argv = argv.iter().chain(&["--azure-tenant-id", "t", "--azure-client-secret", "s"]).chain(extra);
+        let Command::Sign(args) = Cli::try_parse_from(argv.chain(&["a.exe", "b.exe"])).unwrap().command else {
+            panic!("not a sign");
+        };
+        *args
+    }
+
+    fn finished(failed: bool) -> Report {
+        let mut report = Report::new(&["a.exe".to_string(), "b.exe".to_string()]);
+        report.record(0, &Ok(()), Some(2048), Duration::from_millis(10));
+        let result = match failed {
+            true => Err(Error::Signing {
+                path: "b.exe".to_string(),
+                message: "signtool failed".to_string(),
+                output: "details".to_string(),
+            }),
+            false => Ok(()),
+        };
+        report.record(1, &result, Some(10), Duration::from_millis(5));
+        report.finish(Duration::from_secs(2));
+        report
+    }
+
+    #[test]
+    fn quiet_json_keeps_the_report() {
+        let args = sign_args(&["-q", "--output", "json"]);
+        assert_eq!(log_level(&args), Some(LogLevel::Error));
+        assert_eq!(log_level(&sign_args(&["--output", "json"])), None);
+
+        let mut report = finished(false);
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        print_summary(&args, &report, &mut stderr);
+        let code = conclude(&args, &mut report, Ok(()), &mut stdout, &mut stderr);
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(String::from_utf8(stderr).unwrap(), "");
+        let json: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+        assert_eq!(json["summary"]["signed"], 2);
+        assert_eq!(json["files"][1]["path"], "b.exe");
+
+        // without --quiet the summary is printed
+        let mut stderr = Vec::new();
+        print_summary(&sign_args(&["--output", "json"]), &report, &mut stderr);
+        assert!(!stderr.is_empty());
+    }
+
+    #[test]
+    fn quiet_failure_block_alone() {
+        let args = sign_args(&["-q"]);
+        let mut report = finished(true);
+        let error = Error::Other("1 file(s) could not be signed".to_string());
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        print_summary(&args, &report, &mut stderr);
+        let code = conclude(&args, &mut report, Err(error), &mut stdout, &mut stderr);
+        assert_eq!(code, ExitCode::from(exit_code::OTHER));
+        assert!(stdout.is_empty());
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            "error: 1 file(s) could not be signed\n  b.exe: signtool failed\n"
+        );
+
+        // the login fails before any file, what az said is the reason
+        logging::register_secret("Zq9-login-secret-Zq9");
+        let mut report = Report::new(&["a.exe".to_string()]);
+        let error = Error::Auth(
+            "login via azure cli 'az' failed: exit status: 1\n\rERROR: AADSTS7000215: Invalid client secret \
+             Zq9-login-secret-Zq9 provided.\r\nTrace ID: 1f2e\r\nTimestamp: 2026-10-14 10:00:00Z"
+                .to_string(),
+        );
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let code = conclude(&args, &mut report, Err(error), &mut stdout, &mut stderr);
+        assert_eq!(code, ExitCode::from(exit_code::AUTH));
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            "error: login via azure cli 'az' failed: exit status: 1\n  \
+             ERROR: AADSTS7000215: Invalid client secret *** provided.\n"
+        );
+    }
+}
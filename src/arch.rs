@@ -0,0 +1,226 @@
+use clap::ValueEnum;
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// The machine a PE image must be built for, with --expect-arch
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    X64,
+    Arm64,
+    X86,
+}
+
+impl Arch {
+    /// IMAGE_FILE_MACHINE_* of the COFF header
+    fn machine(self) -> u16 {
+        match self {
+            Arch::X64 => 0x8664,
+            Arch::Arm64 => 0xaa64,
+            Arch::X86 => 0x14c,
+        }
+    }
+}
+
+/// The subsystem a PE image must run in, with --expect-subsystem
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    /// IMAGE_SUBSYSTEM_WINDOWS_GUI
+    Gui,
+    /// IMAGE_SUBSYSTEM_WINDOWS_CUI
+    Console,
+}
+
+impl Subsystem {
+    fn value(self) -> u16 {
+        match self {
+            Subsystem::Gui => 2,
+            Subsystem::Console => 3,
+        }
+    }
+}
+
+/// The fields of the headers of a PE image that tell what it was built for
+#[derive(Debug, PartialEq, Eq)]
+pub struct Headers {
+    pub machine: u16,
+    /// 0x10b for PE32, 0x20b for PE32+
+    pub magic: u16,
+    pub subsystem: u16,
+}
+
+fn u16_at(image: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(image.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+/// The headers of a PE image by its first bytes, None for anything that isn't one
+pub fn headers(image: &[u8]) -> Option<Headers> {
+    let header = u32::from_le_bytes(image.get(0x3c..0x40)?.try_into().ok()?) as usize;
+    if image.get(..2)? != b"MZ" || image.get(header..header + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let optional = header + 24;
+    Some(Headers {
+        machine: u16_at(image, header + 4)?,
+        magic: u16_at(image, optional)?,
+        subsystem: u16_at(image, optional + 68)?,
+    })
+}
+
+/// Name of an IMAGE_FILE_MACHINE_* value, the hex value for the uncommon ones
+fn machine_name(machine: u16) -> String {
+    match machine {
+        0x8664 => "x64".to_string(),
+        0xaa64 => "arm64".to_string(),
+        0x14c => "x86".to_string(),
+        0x1c4 => "arm".to_string(),
+        machine => format!("an unknown machine ({:#06x})", machine),
+    }
+}
+
+/// Name of an IMAGE_SUBSYSTEM_* value
+fn subsystem_name(subsystem: u16) -> String {
+    match subsystem {
+        1 => "native".to_string(),
+        2 => "gui".to_string(),
+        3 => "console".to_string(),
+        10..=13 => "efi".to_string(),
+        subsystem => format!("unknown ({})", subsystem),
+    }
+}
+
+/// Why `headers` don't match the expected architecture and subsystem, when they don't. The optional header must
+/// be PE32+ for 64 bit machines and PE32 for x86
+fn mismatch(headers: &Headers, arch: Option<Arch>, subsystem: Option<Subsystem>) -> Option<String> {
+    let mut found = Vec::new();
+    if let Some(arch) = arch {
+        let magic = match arch {
+            Arch::X86 => 0x10b,
+            Arch::X64 | Arch::Arm64 => 0x20b,
+        };
+        if headers.machine != arch.machine() {
+            let (built, expected) = (machine_name(headers.machine), machine_name(arch.machine()));
+            found.push(format!("it is built for {}, not {}", built, expected));
+        } else if headers.magic != magic {
+            let format = match headers.magic {
+                0x20b => "PE32+",
+                _ => "PE32",
+            };
+            let expected = machine_name(arch.machine());
+            found.push(format!("its optional header is {}, which doesn't fit {}", format, expected));
+        }
+    }
+    if let Some(subsystem) = subsystem.filter(|subsystem| headers.subsystem != subsystem.value()) {
+        found.push(format!(
+            "its subsystem is {}, not {}",
+            subsystem_name(headers.subsystem),
+            subsystem_name(subsystem.value())
+        ));
+    }
+    (!found.is_empty()).then(|| found.join(" and "))
+}
+
+/// The machine the PE image at `path` is built for, None when it can't be read or isn't one
+pub fn machine_of(path: &Path) -> Option<u16> {
+    let mut image = Vec::new();
+    fs::File::open(path).ok()?.take(4096).read_to_end(&mut image).ok()?;
+    headers(&image).map(|headers| headers.machine)
+}
+
+/// The dlib signtool can load, which has to be built for its machine: `lib_path` when it is, otherwise the one for
+/// signtool's machine the package unpacked next to it (bin\x86, bin\arm64). Passes `lib_path` when either can't be
+/// read, signtool reports what it can't load itself
+pub fn matching_dlib(sign_tool_path: &Path, lib_path: &Path) -> Result<PathBuf, String> {
+    let (Some(signtool), Some(dlib)) = (machine_of(sign_tool_path), machine_of(lib_path)) else {
+        return Ok(lib_path.to_path_buf());
+    };
+    if signtool == dlib {
+        return Ok(lib_path.to_path_buf());
+    }
+    let known = [Arch::X64, Arch::Arm64, Arch::X86].iter().any(|arch| arch.machine() == signtool);
+    let sibling = match (known, lib_path.parent().and_then(Path::parent), lib_path.file_name()) {
+        (true, Some(bin), Some(name)) => Some(bin.join(machine_name(signtool)).join(name)),
+        _ => None,
+    };
+    match sibling.filter(|sibling| machine_of(sibling) == Some(signtool)) {
+        Some(sibling) => Ok(sibling),
+        None => Err(format!(
+            "signtool {} is built for {} and can't load the signing client {}, which is built for {}. The package \
+             has no {} signing client, pass --sing-tool-path the {} signtool of the Windows SDK \
+             (bin\\<version>\\{}\\signtool.exe)",
+            sign_tool_path.display(),
+            machine_name(signtool),
+            lib_path.display(),
+            machine_name(dlib),
+            machine_name(signtool),
+            machine_name(dlib),
+            machine_name(dlib),
+        )),
+    }
+}
+
+/// Check a signed file is a PE image for `arch` and `subsystem`. Files that aren't PE images pass
+pub fn check(path: &str, arch: Option<Arch>, subsystem: Option<Subsystem>) -> Result<(), String> {
+    let mut image = Vec::new();
+    let file = fs::File::open(path).map_err(|err| format!("'{}' could not be read: {}", path, err))?;
+    file.take(4096).read_to_end(&mut image).map_err(|err| format!("'{}' could not be read: {}", path, err))?;
+    match headers(&image).and_then(|headers| mismatch(&headers, arch, subsystem)) {
+        Some(mismatch) => Err(format!("'{}' doesn't match --expect-arch/--expect-subsystem: {}", path, mismatch)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X64_CONSOLE: &[u8] = include_bytes!("../tests/fixtures/pe/x64-console.bin");
+    const ARM64_GUI: &[u8] = include_bytes!("../tests/fixtures/pe/arm64-gui.bin");
+    const X86_GUI: &[u8] = include_bytes!("../tests/fixtures/pe/x86-gui.bin");
+
+    #[test]
+    fn machines_and_subsystems() {
+        assert_eq!(headers(X64_CONSOLE), Some(Headers { machine: 0x8664, magic: 0x20b, subsystem: 3 }));
+        assert_eq!(headers(b"#!/bin/sh\n"), None);
+        assert_eq!(headers(&X64_CONSOLE[..0x90]), None);
+
+        let arm64 = headers(ARM64_GUI).unwrap();
+        assert_eq!(mismatch(&arm64, Some(Arch::Arm64), Some(Subsystem::Gui)), None);
+        assert_eq!(mismatch(&arm64, Some(Arch::X64), None).unwrap(), "it is built for arm64, not x64");
+        let x86 = headers(X86_GUI).unwrap();
+        assert_eq!(mismatch(&x86, Some(Arch::X86), None), None);
+        assert_eq!(
+            mismatch(&x86, Some(Arch::X64), Some(Subsystem::Console)).unwrap(),
+            "it is built for x86, not x64 and its subsystem is gui, not console"
+        );
+        // a PE32 header claiming a 64 bit machine
+        let odd = Headers { machine: 0x8664, magic: 0x10b, subsystem: 0 };
+        let expected = "its optional header is PE32, which doesn't fit x64";
+        assert_eq!(mismatch(&odd, Some(Arch::X64), None).unwrap(), expected);
+        let zeroed = Headers { machine: 0, ..odd };
+        let expected = "it is built for an unknown machine (0x0000), not x64 and its subsystem is unknown (0), not gui";
+        assert_eq!(mismatch(&zeroed, Some(Arch::X64), Some(Subsystem::Gui)).unwrap(), expected);
+
+        // the package unpacked bin\x64 and bin\arm64, not bin\x86
+        let dir = tempfile::tempdir().unwrap();
+        let dlib = |arch: &str| dir.path().join("bin").join(arch).join("Azure.CodeSigning.Dlib.dll");
+        let [x64, arm64] = ["x64", "arm64"].map(dlib);
+        for (dlib, image) in [(&x64, X64_CONSOLE), (&arm64, ARM64_GUI)] {
+            fs::create_dir_all(dlib.parent().unwrap()).unwrap();
+            fs::write(dlib, image).unwrap();
+        }
+        let signtool = dir.path().join("signtool.exe");
+        fs::write(&signtool, X64_CONSOLE).unwrap();
+        assert_eq!(machine_of(&signtool), Some(0x8664));
+        assert_eq!(matching_dlib(&signtool, &x64).unwrap(), x64);
+        fs::write(&signtool, ARM64_GUI).unwrap();
+        assert_eq!(matching_dlib(&signtool, &x64).unwrap(), arm64);
+        fs::write(&signtool, X86_GUI).unwrap();
+        let err = matching_dlib(&signtool, &x64).unwrap_err();
+        assert!(err.contains("is built for x86 and can't load the signing client") && err.contains("no x86 signing"));
+        // nothing to compare with
+        assert_eq!(matching_dlib(&dir.path().join("missing.exe"), &x64).unwrap(), x64);
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+const PREDICATE_TYPE: &str = "https://github.com/levminer/trusted-signing-cli/attestation/v1";
+
+/// A file that was signed, with the bytes before and after
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestedFile {
+    pub name: String,
+    pub sha256_before: String,
+    pub sha256_after: String,
+    pub size: u64,
+    pub certificate_profile: String,
+    pub endpoint: String,
+    pub timestamp_authority: String,
+    /// UTC, RFC 3339
+    pub signed_at: String,
+}
+
+/// Statement subject, referencing a signed file by its final digest
+#[derive(Serialize)]
+struct Subject<'a> {
+    name: &'a str,
+    digest: BTreeMap<&'static str, &'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Predicate<'a> {
+    /// False when the run stopped early or some files failed
+    complete: bool,
+    tool: &'static str,
+    tool_version: &'static str,
+    dlib_version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ci_run_url: Option<String>,
+    files: &'a [AttestedFile],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Statement<'a> {
+    #[serde(rename = "_type")]
+    statement_type: &'static str,
+    subject: Vec<Subject<'a>>,
+    predicate_type: &'static str,
+    predicate: Predicate<'a>,
+}
+
+/// In-toto like statement linking the signed bytes to the run
+#[derive(Default)]
+pub struct Attestation {
+    files: Vec<AttestedFile>,
+}
+
+impl Attestation {
+    pub fn add(&mut self, file: AttestedFile) {
+        self.files.push(file);
+    }
+
+    fn render(&self, complete: bool, dlib_version: &str, ci_run_url: Option<String>) -> Result<String, String> {
+        let statement = Statement {
+            statement_type: STATEMENT_TYPE,
+            subject: self
+                .files
+                .iter()
+                .map(|file| Subject {
+                    name: &file.name,
+                    digest: BTreeMap::from([("sha256", file.sha256_after.as_str())]),
+                })
+                .collect(),
+            predicate_type: PREDICATE_TYPE,
+            predicate: Predicate {
+                complete,
+                tool: env!("CARGO_PKG_NAME"),
+                tool_version: env!("CARGO_PKG_VERSION"),
+                dlib_version,
+                ci_run_url,
+                files: &self.files,
+            },
+        };
+        serde_json::to_string_pretty(&statement).map_err(|err| format!("attestation could not be serialized: {:?}", err))
+    }
+
+    /// Write the statement to `path`, replacing it in one step so readers never see half a document
+    pub fn write(&self, path: &Path, complete: bool, dlib_version: &str, ci_run_url: Option<String>) -> Result<(), String> {
+        let content = self.render(complete, dlib_version, ci_run_url)?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, content)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|err| format!("attestation '{}' could not be written: {:?}", path.display(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statement() {
+        let mut attestation = Attestation::default();
+        attestation.add(AttestedFile {
+            name: "app.exe".to_string(),
+            sha256_before: "aa".to_string(),
+            sha256_after: "bb".to_string(),
+            size: 10,
+            certificate_profile: "prof".to_string(),
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            timestamp_authority: "http://timestamp.acs.microsoft.com".to_string(),
+            signed_at: "2024-01-01T00:00:00.000Z".to_string(),
+        });
+
+        let statement: serde_json::Value =
+            serde_json::from_str(&attestation.render(false, "1.0.60", None).unwrap()).unwrap();
+        assert_eq!(statement["_type"], STATEMENT_TYPE);
+        assert_eq!(statement["subject"][0], serde_json::json!({ "name": "app.exe", "digest": { "sha256": "bb" } }));
+        assert_eq!(statement["predicate"]["complete"], false);
+        assert_eq!(statement["predicate"]["files"][0]["sha256Before"], "aa");
+        assert!(statement["predicate"].get("ciRunUrl").is_none());
+    }
+}
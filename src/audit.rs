@@ -0,0 +1,60 @@
+use crate::{certificate::SigningCertificate, report::Status};
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// One signing operation, never containing secrets
+#[derive(Serialize, Debug)]
+pub struct AuditEntry<'a> {
+    /// UTC, RFC 3339
+    pub timestamp: String,
+    pub path: String,
+    pub sha256_before: Option<String>,
+    pub sha256_after: Option<String>,
+    pub endpoint: &'a str,
+    pub account: &'a str,
+    pub certificate_profile: &'a str,
+    /// The certificate the file was signed with, when its signature could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<&'a SigningCertificate>,
+    pub azure_client_id: &'a str,
+    pub signtool_version: Option<&'a str>,
+    pub dlib_version: &'a str,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'a str>,
+    /// Address of the machine that sent the file to `serve`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+/// Append-only JSON lines log of every signing operation
+pub struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("audit log '{}' could not be opened: {:?}", path.display(), err))?;
+        Ok(AuditLog { file })
+    }
+
+    /// Append an entry and flush it to disk before returning.
+    /// Each entry is a single write to a file opened for appending, so concurrent
+    /// invocations sharing the log never interleave within a line.
+    pub fn append(&mut self, entry: &AuditEntry) -> Result<(), String> {
+        let mut line = serde_json::to_string(entry).map_err(|err| format!("audit entry could not be serialized: {:?}", err))?;
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|err| format!("audit log could not be written: {:?}", err))
+    }
+}
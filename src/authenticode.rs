@@ -0,0 +1,233 @@
+use crate::{
+    der::{self, Element},
+    signtool::Digest,
+};
+use sha2::{Digest as _, Sha256, Sha384, Sha512};
+
+const SIGNED_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 2];
+const SPC_INDIRECT_DATA: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 4];
+const CONTENT_TYPE: &[u64] = &[1, 2, 840, 113549, 1, 9, 3];
+const MESSAGE_DIGEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 4];
+const SPC_SP_OPUS_INFO: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 12];
+const SPC_STATEMENT_TYPE: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 11];
+const INDIVIDUAL_CODE_SIGNING: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 21];
+const RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+pub(crate) const NESTED_SIGNATURE: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 4, 1];
+
+/// The OID of a digest algorithm and its hash of `data`, SHA1 isn't offered
+fn hash(digest: Digest, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let (arc, hash) = match digest {
+        Digest::Sha1 => Err("SHA1 signatures can't be made with the REST backend, pass --fd SHA256 or stronger")?,
+        Digest::Sha256 => (1, Sha256::digest(data).to_vec()),
+        Digest::Sha384 => (2, Sha384::digest(data).to_vec()),
+        Digest::Sha512 => (3, Sha512::digest(data).to_vec()),
+    };
+    Ok((der::oid(&[2, 16, 840, 1, 101, 3, 4, 2, arc]), hash))
+}
+
+/// The elements of the constructed value at the start of `input`
+fn open(input: &[u8]) -> Option<Vec<Element<'_>>> {
+    der::children(der::read(input)?.0.content)
+}
+
+/// The SignedData inside a ContentInfo
+pub(crate) fn signed_data(pkcs7: &[u8]) -> Option<Vec<Element<'_>>> {
+    let content_info = open(pkcs7)?;
+    if content_info.first()?.encoded != der::oid(SIGNED_DATA) {
+        return None;
+    }
+    let explicit = content_info.get(1).filter(|element| element.tag == der::context(0))?;
+    der::children(der::read(explicit.content)?.0.content)
+}
+
+/// The SpcIndirectDataContent, with the Authenticode hash of the file, of the unsigned PKCS#7 that
+/// `osslsigncode extract-data` writes
+pub fn indirect_data(pkcs7: &[u8]) -> Result<Vec<u8>, String> {
+    let invalid = || "osslsigncode extract-data wrote no PKCS#7 with the data to sign".to_string();
+    let signed_data = signed_data(pkcs7).ok_or_else(invalid)?;
+    let encapsulated = der::children(signed_data.get(2).ok_or_else(invalid)?.content).ok_or_else(invalid)?;
+    match encapsulated.as_slice() {
+        [content_type, explicit] if content_type.encoded == der::oid(SPC_INDIRECT_DATA) => {
+            Ok(der::read(explicit.content).ok_or_else(invalid)?.0.encoded.to_vec())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// The certificates the signing service returns, a PKCS#7 certificate bundle or a single certificate
+pub fn certificates(bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let invalid = || "the signing certificate returned by Trusted Signing is not a certificate bundle".to_string();
+    let Some(signed_data) = signed_data(bytes) else {
+        return match open(bytes) {
+            Some(_) => Ok(vec![bytes.to_vec()]),
+            None => Err(invalid()),
+        };
+    };
+    let certificates = signed_data.iter().find(|element| element.tag == der::context(0)).ok_or_else(invalid)?;
+    let certificates = der::children(certificates.content).ok_or_else(invalid)?;
+    Ok(certificates.iter().map(|certificate| certificate.encoded.to_vec()).collect())
+}
+
+/// The issuer, serial number and subject of a certificate
+fn identity(certificate: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let tbs = open(certificate)?.first()?.encoded;
+    let fields = open(tbs)?;
+    // the version is the optional [0] before the serial number
+    let fields = match fields.first()?.tag == der::context(0) {
+        true => &fields[1..],
+        false => &fields[..],
+    };
+    match fields {
+        [serial, _, issuer, _, subject, ..] => Some((issuer.encoded, serial.encoded, subject.encoded)),
+        _ => None,
+    }
+}
+
+/// The certificate that signed, the one of the chain that issued none of the others
+fn leaf(certificates: &[Vec<u8>]) -> Option<(&[u8], &[u8])> {
+    let identities: Vec<_> = certificates.iter().filter_map(|certificate| identity(certificate)).collect();
+    let issued = |subject: &[u8]| identities.iter().any(|(issuer, _, other)| *issuer == subject && *other != subject);
+    let (issuer, serial, _) = identities.iter().find(|(_, _, subject)| !issued(subject)).or(identities.first())?;
+    Some((issuer, serial))
+}
+
+/// The certificate of the first signer of a PKCS#7 SignedData, found by the issuer and serial number its
+/// SignerInfo names
+pub fn signing_certificate(pkcs7: &[u8]) -> Option<&[u8]> {
+    let signed_data = signed_data(pkcs7)?;
+    let certificates = signed_data.iter().find(|element| element.tag == der::context(0))?;
+    let signer_info = der::children(signed_data.last().filter(|element| element.tag == der::SET)?.content)?;
+    let signer = der::children(signer_info.first()?.content)?;
+    issued_to(certificates, signer.get(1)?)
+}
+
+/// The certificate among the `[0]` certificates of a SignedData that the IssuerAndSerialNumber `id` names
+pub(crate) fn issued_to<'a>(certificates: &Element<'a>, id: &Element) -> Option<&'a [u8]> {
+    let id = der::children(Some(id).filter(|element| element.tag == der::SEQUENCE)?.content)?;
+    let [issuer, serial] = id.as_slice() else {
+        return None;
+    };
+    der::children(certificates.content)?.into_iter().map(|certificate| certificate.encoded).find(|certificate| {
+        identity(certificate).is_some_and(|(other, number, _)| other == issuer.encoded && number == serial.encoded)
+    })
+}
+
+/// The PKCS#7 signatures nested in the unsigned attributes of the first signer, where signtool /as appends them
+pub fn nested(pkcs7: &[u8]) -> Vec<&[u8]> {
+    let nested = || {
+        let signed_data = signed_data(pkcs7)?;
+        let signer_info = der::children(signed_data.last().filter(|element| element.tag == der::SET)?.content)?;
+        let signer = der::children(signer_info.first()?.content)?;
+        let unsigned = signer.last().filter(|element| element.tag == der::context(1))?;
+        let mut nested = Vec::new();
+        for attribute in der::children(unsigned.content)? {
+            if let [oid, values] = der::children(attribute.content)?[..] {
+                if oid.encoded == der::oid(NESTED_SIGNATURE) {
+                    nested.extend(der::children(values.content)?.iter().map(|value| value.encoded));
+                }
+            }
+        }
+        Some(nested)
+    };
+    nested().unwrap_or_default()
+}
+
+/// How many signatures are nested in the first signer
+pub fn nested_signatures(pkcs7: &[u8]) -> usize {
+    nested(pkcs7).len()
+}
+
+/// An Authenticode signature waiting for its signature value
+#[derive(Debug)]
+pub struct Signable {
+    content: Vec<u8>,
+    attributes: Vec<u8>,
+    digest: Digest,
+}
+
+impl Signable {
+    /// Prepare signing the SpcIndirectDataContent `content`, `description` is shown in the UAC prompt
+    pub fn new(content: Vec<u8>, digest: Digest, description: Option<&str>) -> Result<Self, String> {
+        // like PKCS#7 wants, the tag and length of the content aren't hashed
+        let value = der::read(&content).ok_or("the data to sign is not DER")?.0.content;
+        let (_, message_digest) = hash(digest, value)?;
+        let attribute = |oid: &[u64], value: Vec<u8>| der::sequence(&[&der::oid(oid), &der::set_of(vec![value])]);
+        let program_name = description
+            // programName [0] EXPLICIT SpcString, its unicode choice is [0] IMPLICIT BMPString
+            .map(|description| der::tlv(der::context(0), &der::tlv(0x80, &der::utf16(description))))
+            .unwrap_or_default();
+        let attributes = der::set_of(vec![
+            attribute(CONTENT_TYPE, der::oid(SPC_INDIRECT_DATA)),
+            attribute(SPC_SP_OPUS_INFO, der::sequence(&[&program_name])),
+            attribute(SPC_STATEMENT_TYPE, der::sequence(&[&der::oid(INDIVIDUAL_CODE_SIGNING)])),
+            attribute(MESSAGE_DIGEST, der::tlv(der::OCTET_STRING, &message_digest)),
+        ]);
+        Ok(Self { content, attributes, digest })
+    }
+
+    /// The hash the signing service signs, of the signed attributes
+    pub fn hash(&self) -> Vec<u8> {
+        hash(self.digest, &self.attributes).expect("the digest was checked").1
+    }
+
+    /// The PKCS#7 SignedData with the `signature` of [`Signable::hash`] by the first certificate of the chain
+    pub fn signed_data(&self, certificates: &[Vec<u8>], signature: &[u8]) -> Result<Vec<u8>, String> {
+        let (issuer, serial) = leaf(certificates).ok_or("the signing certificate could not be read")?;
+        let (algorithm, _) = hash(self.digest, &[])?;
+        let algorithm = der::sequence(&[&algorithm, &der::null()]);
+        let signer_info = der::sequence(&[
+            &der::small_integer(1),
+            &der::sequence(&[issuer, serial]),
+            &algorithm,
+            // the signed attributes as [0] IMPLICIT instead of a SET
+            &der::tlv(der::context(0), der::read(&self.attributes).expect("encoded above").0.content),
+            &der::sequence(&[&der::oid(RSA_ENCRYPTION), &der::null()]),
+            &der::tlv(der::OCTET_STRING, signature),
+        ]);
+        let signed_data = der::sequence(&[
+            &der::small_integer(1),
+            &der::set_of(vec![algorithm.clone()]),
+            &der::sequence(&[&der::oid(SPC_INDIRECT_DATA), &der::tlv(der::context(0), &self.content)]),
+            &der::tlv(der::context(0), &certificates.concat()),
+            &der::set_of(vec![signer_info]),
+        ]);
+        Ok(der::sequence(&[&der::oid(SIGNED_DATA), &der::tlv(der::context(0), &signed_data)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A certificate reduced to what is read of it
+    fn certificate(issuer: &str, serial: u8, subject: &str) -> Vec<u8> {
+        let name = |name: &str| der::sequence(&[&der::bmp_string(name)]);
+        let tbs = der::sequence(&[
+            &der::tlv(der::context(0), &der::small_integer(2)),
+            &der::small_integer(serial),
+            &der::sequence(&[]),
+            &name(issuer),
+            &der::sequence(&[]),
+            &name(subject),
+        ]);
+        der::sequence(&[&tbs])
+    }
+
+    #[test]
+    fn signed_data_round_trip() {
+        let content = der::sequence(&[&der::sequence(&[&der::oid(&[1, 3, 6, 1, 4, 1, 311, 2, 1, 15])])]);
+        let signable = Signable::new(content.clone(), Digest::Sha256, Some("My App")).unwrap();
+        assert_eq!(signable.hash().len(), 32);
+        assert!(Signable::new(content.clone(), Digest::Sha1, None).is_err());
+
+        let chain = vec![certificate("Root", 1, "Intermediate"), certificate("Intermediate", 7, "Leaf")];
+        assert_eq!(leaf(&chain).unwrap().1, der::small_integer(7));
+        let pkcs7 = signable.signed_data(&chain, &[0x5a; 256]).unwrap();
+        assert_eq!(indirect_data(&pkcs7).unwrap(), content);
+        assert_eq!(certificates(&pkcs7).unwrap(), chain);
+        assert_eq!(signing_certificate(&pkcs7), Some(chain[1].as_slice()));
+        assert_eq!(nested_signatures(&pkcs7), 0);
+        assert_eq!(certificates(&chain[0]).unwrap(), chain[..1]);
+        assert!(indirect_data(&content).is_err());
+    }
+}
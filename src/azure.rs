@@ -0,0 +1,1209 @@
+use crate::{
+    config, encoding, endpoint,
+    error::Error,
+    interrupt,
+    logging::{self, failure_output, run_captured},
+    permissions,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use duct::cmd;
+use serde::Serialize;
+use std::{
+    io::{Seek, SeekFrom, Write},
+    sync::Mutex,
+};
+use tempfile::NamedTempFile;
+use tracing::{debug, warn};
+
+/// Where the Azure CLI installer puts az, used unless --azure-cli-path says otherwise
+pub const DEFAULT_CLI_PATH: &str = r"C:\Program Files\Microsoft SDKs\Azure\CLI2\wbin\az.cmd";
+
+/// The az commands this tool runs, which `--az-arg <command>:<arg>` can add an argument to
+const COMMANDS: [&str; 8] = ["login", "account", "rest", "resource", "keyvault", "ad", "logout", "version"];
+
+/// Arguments this tool sets for az itself, or whose output it parses, which `--az-arg` can't override
+const RESERVED: [&str; 11] = [
+    "-u", "-p", "-t", "-o", "--username", "--password", "--tenant", "--service-principal", "--identity", "--output",
+    "--query",
+];
+
+/// az arguments that make az login wait for someone to log in, refused with --ci
+const INTERACTIVE: [&str; 1] = ["--use-device-code"];
+
+/// An argument `--az-arg` adds to the az calls of `command`, every call when it is None
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzArg {
+    pub command: Option<String>,
+    pub arg: String,
+}
+
+/// The `--az-arg` arguments of this run
+static EXTRA_ARGS: Mutex<Vec<AzArg>> = Mutex::new(Vec::new());
+
+/// Parse `--az-arg`: `<arg>` for az login, `<command>:<arg>` for the calls of one az command, like
+/// `rest:--debug`, or `all:<arg>` for every call. Arguments this tool sets itself are refused
+pub fn parse_az_arg(value: &str) -> Result<AzArg, String> {
+    let (command, arg) = match value.split_once(':') {
+        Some(("all", arg)) => (None, arg),
+        Some((command, arg)) if COMMANDS.contains(&command) => (Some(command.to_string()), arg),
+        _ => (Some("login".to_string()), value),
+    };
+    if arg.is_empty() {
+        Err(format!("--az-arg '{}' has no argument", value))?;
+    }
+    let name = arg.split('=').next().unwrap_or(arg);
+    // az takes `-pvalue` for short options, and unambiguous abbreviations of long ones
+    let overrides = |reserved: &&str| match reserved.starts_with("--") {
+        true => name.len() > 2 && name.starts_with("--") && reserved.starts_with(name),
+        false => arg.starts_with(reserved) && !arg.starts_with("--"),
+    };
+    if let Some(reserved) = RESERVED.iter().copied().find(overrides) {
+        Err(format!(
+            "--az-arg '{}' would override {}, which trusted-signing-cli sets for az itself or reads the output of",
+            value, reserved
+        ))?;
+    }
+    Ok(AzArg { command, arg: arg.to_string() })
+}
+
+/// Whether an `--az-arg` makes az login prompt, like `--use-device-code`
+pub fn is_interactive(arg: &AzArg) -> bool {
+    let name = arg.arg.split('=').next().unwrap_or_default();
+    arg.command.as_deref().is_none_or(|command| command == "login") && INTERACTIVE.contains(&name)
+}
+
+/// Add `args` to the az calls from now on, see [`parse_az_arg`]
+pub fn set_extra_args(args: Vec<AzArg>) {
+    *EXTRA_ARGS.lock().unwrap() = args;
+}
+
+/// `args` of an az call followed by the `--az-arg` arguments for its command
+fn with_extra_args(args: &[&str]) -> Vec<String> {
+    append_extra_args(args, &EXTRA_ARGS.lock().unwrap())
+}
+
+fn append_extra_args(args: &[&str], extra: &[AzArg]) -> Vec<String> {
+    let command = args.first().copied().unwrap_or_default();
+    let extra = extra.iter().filter(|extra| extra.command.as_deref().is_none_or(|name| name == command));
+    args.iter().copied().chain(extra.map(|extra| extra.arg.as_str())).map(str::to_string).collect()
+}
+
+/// The client secret in a file for az to read with `-p @file`, so it isn't on the command line of az where other
+/// users of the machine can see it. Only the current user may read the file, in the config directory, and it is
+/// overwritten before it is removed, also when the login fails
+struct SecretFile(NamedTempFile);
+
+impl SecretFile {
+    fn new(secret: &str) -> Result<Self, String> {
+        let dir = config::dir();
+        if !dir.exists() {
+            permissions::create_private_dir(&dir)?;
+        }
+        let mut file = tempfile::Builder::new()
+            .prefix("login-")
+            .tempfile_in(&dir)
+            .map_err(|err| format!("the client secret file could not be created in '{}': {}", dir.display(), err))?;
+        file.write_all(secret.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|err| format!("the client secret file could not be written: {}", err))?;
+        Ok(Self(file))
+    }
+
+    /// What `-p` takes to read the file
+    fn argument(&self) -> String {
+        format!("@{}", self.0.path().display())
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        // the NamedTempFile removes it after this
+        let file = self.0.as_file_mut();
+        let length = file.metadata().map(|metadata| metadata.len()).unwrap_or(0) as usize;
+        let wiped = file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| file.write_all(&vec![0; length]))
+            .and_then(|_| file.sync_all());
+        if let Err(err) = wiped {
+            debug!("the client secret file could not be overwritten: {}", err);
+        }
+    }
+}
+
+/// Login to azure cli with a service principal, returning what az printed on stdout.
+/// Unless `show_account` is set, az is told to print nothing so the subscription list
+/// (tenant ids, subscription names) never ends up in CI logs.
+pub fn login(
+    cli_path: &str,
+    tenant_id: &str,
+    client_id: &str,
+    client_secret: &str,
+    show_account: bool,
+) -> Result<String, String> {
+    let mut options = vec!["--only-show-errors"];
+    if !show_account {
+        options.extend(["--output", "none"]);
+    }
+
+    let secret_file = SecretFile::new(client_secret)?;
+    let password = secret_file.argument();
+    let args: Vec<&str> = ["login", "--service-principal", "-t", tenant_id, "-u", client_id, "-p", &password]
+        .into_iter()
+        .chain(options)
+        .collect();
+    // the secret stays in its file, -p only names the file
+    let args = with_extra_args(&args);
+    let output = run_captured(cmd(cli_path, args))
+        .map_err(|err| format!("login via azure cli '{}' failed: {:?}", cli_path, err))?;
+    if !output.status.success() {
+        Err(format!(
+            "login via azure cli '{}' failed: {}\n\r{}",
+            cli_path,
+            output.status,
+            failure_output(&output)
+        ))?;
+    }
+
+    Ok(encoding::decode(&output.stdout))
+}
+
+/// Resource Trusted Signing access tokens are issued for
+const CODE_SIGNING_RESOURCE: &str = "https://codesigning.azure.net";
+
+/// Acquire an access token for Trusted Signing with the logged in principal, returning when
+/// it expires. The token itself is never returned so it can't end up in a log.
+pub fn access_token_expiry(cli_path: &str) -> Result<String, String> {
+    let expires_on = az(
+        cli_path,
+        &[
+            "account",
+            "get-access-token",
+            "--resource",
+            CODE_SIGNING_RESOURCE,
+            "--query",
+            "expiresOn",
+            "--output",
+            "tsv",
+        ],
+    )?;
+    Ok(expires_on.trim().to_string())
+}
+
+/// Acquire an access token for the Trusted Signing REST API with the logged in principal. It is registered as a
+/// secret first, so the log masks it like the client secret
+pub fn access_token(cli_path: &str) -> Result<String, String> {
+    let token = az(
+        cli_path,
+        &[
+            "account",
+            "get-access-token",
+            "--resource",
+            CODE_SIGNING_RESOURCE,
+            "--query",
+            "accessToken",
+            "--output",
+            "tsv",
+        ],
+    )?;
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        Err("azure cli returned no access token for Trusted Signing".to_string())?;
+    }
+    crate::logging::register_secret(&token);
+    Ok(token)
+}
+
+/// A version of the Azure CLI, like 2.64.0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Releases before 2.30.0 log in through ADAL and treat service principal logins differently, they get a warning
+pub const BASELINE_VERSION: Version = Version(2, 30, 0);
+
+/// The version az prints, the JSON of `az version` or the `azure-cli  2.28.0 *` line of `az --version`
+fn parse_version(output: &str) -> Option<Version> {
+    let json: Option<serde_json::Value> = serde_json::from_str(output).ok();
+    let version = match json {
+        Some(json) => json["azure-cli"].as_str()?.to_string(),
+        None => {
+            let line = output.lines().find(|line| line.trim_start().starts_with("azure-cli"))?;
+            line.split_whitespace().nth(1)?.trim_end_matches('*').to_string()
+        }
+    };
+    let mut parts = version.split('.').map(|part| part.parse::<u32>());
+    match (parts.next(), parts.next(), parts.next().unwrap_or(Ok(0))) {
+        (Some(Ok(major)), Some(Ok(minor)), Ok(patch)) => Some(Version(major, minor, patch)),
+        _ => None,
+    }
+}
+
+/// Version of the Azure CLI at `cli_path`, az is only asked once per run. `None` when it couldn't be told
+pub fn cli_version(cli_path: &str) -> Option<Version> {
+    static VERSION: std::sync::OnceLock<Option<Version>> = std::sync::OnceLock::new();
+    *VERSION.get_or_init(|| {
+        // `az version` only exists since 2.0.79, older releases have nothing but `az --version`
+        let output = az(cli_path, &["version", "--output", "json"]).or_else(|_| az(cli_path, &["--version"]));
+        let version = output.as_deref().ok().and_then(parse_version);
+        match version {
+            Some(version) => debug!("azure cli version {}", version),
+            None => debug!("the azure cli version could not be told: {:?}", output),
+        }
+        version
+    })
+}
+
+/// A warning when `version` is older than [`BASELINE_VERSION`]
+pub fn baseline_warning(version: Version) -> Option<String> {
+    (version < BASELINE_VERSION).then(|| {
+        format!(
+            "azure cli {} is older than {}, logins may behave differently, upgrade with \
+             `winget upgrade Microsoft.AzureCLI` or the MSI from https://aka.ms/installazurecliwindows",
+            version, BASELINE_VERSION
+        )
+    })
+}
+
+/// Fail when `feature` needs a newer Azure CLI than the one at `cli_path`, an unknown version is let through
+pub fn require(cli_path: &str, feature: &str, minimum: Version) -> Result<(), Error> {
+    match cli_version(cli_path) {
+        Some(version) if version < minimum => Err(Error::MissingDependency {
+            component: "azure cli",
+            message: format!(
+                "{} requires az >= {}, found {}, upgrade via `winget upgrade Microsoft.AzureCLI` or the MSI from \
+                 https://aka.ms/installazurecliwindows",
+                feature, minimum, version
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check a Key Vault secret identifier like `https://my-vault.vault.azure.net/secrets/signing-sp`, a version may
+/// follow the name
+pub fn parse_secret_id(id: &str) -> Result<String, String> {
+    let id = id.trim().trim_end_matches('/');
+    let host_and_path = id.strip_prefix("https://").unwrap_or_default();
+    let parts: Vec<&str> = host_and_path.split('/').collect();
+    let valid = matches!(parts.as_slice(), [host, "secrets", name] | [host, "secrets", name, _]
+        if host.contains('.') && !name.is_empty());
+    match valid && parts.iter().all(|part| !part.is_empty()) {
+        true => Ok(id.to_string()),
+        false => Err(format!(
+            "'{}' is not a Key Vault secret identifier like https://my-vault.vault.azure.net/secrets/signing-sp",
+            id
+        )),
+    }
+}
+
+/// Why az could not read a Key Vault secret, told apart by what it printed
+fn secret_error(id: &str, message: &str) -> Error {
+    let has = |markers: &[&str]| markers.iter().any(|marker| message.contains(marker));
+    if has(&["Forbidden", "does not have secrets get permission", "not authorized", "AccessDenied"]) {
+        Error::Auth(format!(
+            "the bootstrap identity may not read the secret '{}', it needs the Key Vault Secrets User role or a \
+             get permission for secrets in the vault's access policy",
+            id
+        ))
+    } else if has(&["az login", "Please run 'az login'", "ManagedIdentityCredential"]) {
+        Error::Auth(format!(
+            "azure cli is not logged in to read the secret '{}', log in with `az login` first or pass \
+             --bootstrap-managed-identity",
+            id
+        ))
+    } else if has(&["SecretDisabled", "is currently disabled"]) {
+        Error::Usage(format!("the Key Vault secret '{}' is disabled, enable it or use another version", id))
+    } else if has(&["SecretNotFound", "was not found", "VaultNotFound"]) {
+        Error::Usage(format!("the Key Vault secret '{}' does not exist", id))
+    } else if has(&["Failed to establish", "Max retries", "getaddrinfo", "Name or service not known", "timed out"]) {
+        Error::Other(format!(
+            "Key Vault could not be reached to read the secret '{}', check the network and proxy: {}",
+            id,
+            message.trim()
+        ))
+    } else {
+        Error::Other(format!("the Key Vault secret '{}' could not be read: {}", id, message.trim()))
+    }
+}
+
+/// The value of a secret in the JSON `az keyvault secret show` prints, unless it is disabled or expired at `now`
+fn secret_value(id: &str, json: &str, now: DateTime<Utc>) -> Result<String, Error> {
+    // the JSON holds the secret, so a parse error must not quote it
+    let secret: serde_json::Value = serde_json::from_str(json)
+        .map_err(|_| Error::Other(format!("azure cli printed no secret for '{}'", id)))?;
+    if secret["attributes"]["enabled"].as_bool() == Some(false) {
+        Err(Error::Usage(format!("the Key Vault secret '{}' is disabled, enable it or use another version", id)))?;
+    }
+    let expires = secret["attributes"]["expires"].as_str().and_then(|date| DateTime::parse_from_rfc3339(date).ok());
+    if let Some(expires) = expires.filter(|expires| *expires <= now) {
+        Err(Error::Usage(format!(
+            "the Key Vault secret '{}' expired on {}, set a new version or extend its expiry",
+            id,
+            expires.to_rfc3339()
+        )))?;
+    }
+    match secret["value"].as_str().filter(|value| !value.is_empty()) {
+        Some(value) => Ok(value.to_string()),
+        None => Err(Error::Usage(format!("the Key Vault secret '{}' is empty", id))),
+    }
+}
+
+/// Read the client secret from Key Vault with the identity az is logged in as, or with the managed identity of
+/// the machine when `managed_identity` is set. The value is registered as a secret before anything else happens
+/// with it and az's output isn't logged, so it only lives in memory
+pub fn key_vault_secret(cli_path: &str, id: &str, managed_identity: bool) -> Result<String, Error> {
+    if managed_identity {
+        // managed identity logins before the move to MSAL don't get tokens for Key Vault reliably
+        require(cli_path, "--bootstrap-managed-identity", BASELINE_VERSION)?;
+        debug!("logging in to azure cli with the managed identity to read {}", id);
+        let args = ["login", "--identity", "--allow-no-subscriptions", "--output", "none", "--only-show-errors"];
+        let args = with_extra_args(&args);
+        let output = run_captured(cmd(cli_path, args))
+            .map_err(|err| format!("azure cli '{}' could not be run: {:?}", cli_path, err))?;
+        if !output.status.success() {
+            Err(Error::Auth(format!(
+                "login with the managed identity failed, does this machine have one? {}",
+                failure_output(&output)
+            )))?;
+        }
+    }
+
+    let args = with_extra_args(&["keyvault", "secret", "show", "--id", id, "--output", "json", "--only-show-errors"]);
+    debug!("running {} {}", cli_path, args.join(" "));
+    let output = interrupt::output(cmd(cli_path, args).stdout_capture().stderr_capture().unchecked())
+        .map_err(|err| format!("azure cli '{}' could not be run: {:?}", cli_path, err))?;
+    if !output.status.success() {
+        Err(secret_error(id, &encoding::decode(&output.stderr)))?;
+    }
+    let value = secret_value(id, &encoding::decode(&output.stdout), Utc::now())?;
+    logging::register_secret(&value);
+    Ok(value)
+}
+
+/// Who az is logged in as
+#[derive(Debug, PartialEq, Eq)]
+pub struct Account {
+    pub tenant_id: String,
+    /// The client id of a service principal
+    pub user_name: String,
+    /// Like `servicePrincipal` or `user`, empty when az doesn't say
+    pub user_type: String,
+}
+
+/// The tenant and principal az is logged in as
+pub fn account(cli_path: &str) -> Result<Account, String> {
+    let account = az(
+        cli_path,
+        &["account", "show", "--query", "[tenantId, user.name, user.type]", "--output", "tsv"],
+    )?;
+    let mut lines = account.lines().map(str::trim);
+    match (lines.next(), lines.next(), lines.next()) {
+        (Some(tenant), Some(user), user_type) => Ok(Account {
+            tenant_id: tenant.to_string(),
+            user_name: user.to_string(),
+            user_type: user_type.unwrap_or_default().to_string(),
+        }),
+        _ => Err(format!("azure cli printed no account: {}", account.trim())),
+    }
+}
+
+/// Display name of the service principal with `client_id` in Microsoft Entra ID, which needs the principal to be
+/// allowed to read it
+pub fn display_name(cli_path: &str, client_id: &str) -> Result<String, String> {
+    let name = az(cli_path, &["ad", "sp", "show", "--id", client_id, "--query", "displayName", "--output", "tsv"])?;
+    match name.trim() {
+        "" => Err(format!("azure cli printed no display name for {}", client_id)),
+        name => Ok(name.to_string()),
+    }
+}
+
+/// Log az out of the account of `user_name`, other accounts az is logged in as stay
+pub fn logout(cli_path: &str, user_name: &str) -> Result<(), String> {
+    az(cli_path, &["logout", "--username", user_name, "--only-show-errors"]).map(|_| ())
+}
+
+/// ARM API version used to list certificate profiles
+const CODE_SIGNING_API_VERSION: &str = "2024-02-05-preview";
+
+/// ARM API version used to list subscriptions
+const SUBSCRIPTIONS_API_VERSION: &str = "2022-12-01";
+
+/// Result of looking up the account and certificate profile in Azure
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProfileCheck {
+    Exists,
+    /// Something doesn't exist, the message lists what does
+    Missing(String),
+    /// The principal may not read the account, nothing can be said
+    Forbidden(String),
+}
+
+/// Run az and return what it printed on stdout
+fn az(cli_path: &str, args: &[&str]) -> Result<String, String> {
+    let args = with_extra_args(args);
+    let output = run_captured(cmd(cli_path, &args))
+        .map_err(|err| format!("azure cli '{}' could not be run: {:?}", cli_path, err))?;
+    if !output.status.success() {
+        Err(format!(
+            "azure cli '{} {}' failed: {}\n\r{}",
+            cli_path,
+            args.first().map(String::as_str).unwrap_or_default(),
+            output.status,
+            failure_output(&output)
+        ))?;
+    }
+    Ok(encoding::decode(&output.stdout))
+}
+
+/// Whether an az error is about missing permissions rather than missing resources
+fn is_forbidden(message: &str) -> bool {
+    ["AuthorizationFailed", "Forbidden", "does not have authorization"]
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+/// Names in an ARM list response (`{"value": [{"name": ..}]}`) or a plain list of names
+fn names(json: &str) -> Vec<String> {
+    let value: serde_json::Value = serde_json::from_str(json).unwrap_or_default();
+    let items = value.get("value").unwrap_or(&value).as_array().cloned().unwrap_or_default();
+    items
+        .iter()
+        .filter_map(|item| item.get("name").or(Some(item)).and_then(|name| name.as_str()).map(str::to_string))
+        .collect()
+}
+
+/// The names and ids of the code signing accounts visible to the principal, as JSON
+fn accounts(cli_path: &str) -> Result<String, String> {
+    az(
+        cli_path,
+        &[
+            "resource",
+            "list",
+            "--resource-type",
+            "Microsoft.CodeSigning/codeSigningAccounts",
+            "--query",
+            "[].{name: name, id: id}",
+            "--output",
+            "json",
+        ],
+    )
+}
+
+/// The resource id of `account` in the output of [`accounts`]
+fn account_id(ids: &str, account: &str) -> Option<String> {
+    let accounts: Vec<serde_json::Value> = serde_json::from_str(ids).unwrap_or_default();
+    accounts
+        .iter()
+        .find(|item| item["name"].as_str().is_some_and(|name| name.eq_ignore_ascii_case(account)))
+        .and_then(|item| item["id"].as_str())
+        .map(str::to_string)
+}
+
+/// The ARM list of the certificate profiles of `account`, or why it can't be had
+fn profiles_json(cli_path: &str, account: &str) -> Result<Result<String, ProfileCheck>, String> {
+    let forbidden = |err: String| match is_forbidden(&err) {
+        true => Ok(Err(ProfileCheck::Forbidden(err))),
+        false => Err(err),
+    };
+
+    let ids = match accounts(cli_path) {
+        Ok(ids) => ids,
+        Err(err) => return forbidden(err),
+    };
+    let Some(id) = account_id(&ids, account) else {
+        let visible = names(&ids);
+        return Ok(Err(ProfileCheck::Missing(match visible.is_empty() {
+            true => format!(
+                "code signing account '{}' was not found, no account is visible to this principal, it needs the \
+                 Reader role on the account",
+                account
+            ),
+            false => format!(
+                "code signing account '{}' was not found, accounts visible to this principal: {}",
+                account,
+                visible.join(", ")
+            ),
+        })));
+    };
+
+    let url = format!(
+        "https://management.azure.com{}/certificateProfiles?api-version={}",
+        id, CODE_SIGNING_API_VERSION
+    );
+    match az(cli_path, &["rest", "--method", "get", "--url", &url, "--output", "json"]) {
+        Ok(profiles) => Ok(Ok(profiles)),
+        Err(err) => forbidden(err),
+    }
+}
+
+/// Check that the code signing account and its certificate profile exist, needs ARM read permission
+pub fn check_profile(cli_path: &str, account: &str, certificate: &str) -> Result<ProfileCheck, String> {
+    let profiles = match profiles_json(cli_path, account)? {
+        Ok(profiles) => names(&profiles),
+        Err(check) => return Ok(check),
+    };
+    match profiles.iter().any(|name| name.eq_ignore_ascii_case(certificate)) {
+        true => Ok(ProfileCheck::Exists),
+        false => Ok(ProfileCheck::Missing(format!(
+            "certificate profile '{}' does not exist in account '{}', existing profiles: {}",
+            certificate,
+            account,
+            profiles.join(", ")
+        ))),
+    }
+}
+
+/// A certificate profile of a code signing account
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct CertificateProfile {
+    pub name: String,
+    /// Like `PublicTrust`, `PrivateTrust` or `PrivateTrustCIPolicy`
+    pub profile_type: String,
+    /// Like `Active`, `Disabled` or `Suspended`
+    pub status: String,
+    /// Subject of the newest active certificate
+    pub subject: Option<String>,
+    /// When the newest active certificate was issued and when it expires, certificates are renewed daily
+    pub valid_from: Option<String>,
+    pub valid_to: Option<String>,
+    /// The earliest expiry the profile reports besides its certificates, like that of its identity validation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+}
+
+/// The day of a date like `2026-10-16` or an RFC 3339 time
+fn date_of(value: &str) -> Option<NaiveDate> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => Some(time.date_naive()),
+        Err(_) => NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok(),
+    }
+}
+
+/// The earliest expiry in the properties of a profile outside its certificates, which are renewed daily. The API
+/// documents no field for the expiry of the identity validation, so any property named like an expiry counts
+fn profile_expiry(properties: &serde_json::Value) -> Option<String> {
+    let mut found: Vec<(NaiveDate, String)> = Vec::new();
+    for (key, value) in properties.as_object().into_iter().flatten() {
+        match value {
+            serde_json::Value::String(text) if key.to_ascii_lowercase().contains("expir") => {
+                found.extend(date_of(text).map(|date| (date, text.clone())));
+            }
+            serde_json::Value::Object(_) => {
+                found.extend(profile_expiry(value).and_then(|text| Some((date_of(&text)?, text))))
+            }
+            _ => (),
+        }
+    }
+    found.into_iter().min().map(|(_, text)| text)
+}
+
+/// Warnings for a profile that can't sign or soon won't: not active, or expiring within `days` of `today`
+pub fn expiry_warnings(account: &str, profile: &CertificateProfile, days: i64, today: NaiveDate) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if !profile.status.is_empty() && !profile.status.eq_ignore_ascii_case("active") {
+        warnings.push(format!(
+            "certificate profile '{}' of '{}' is {}, signing with it fails until it is active again",
+            profile.name, account, profile.status
+        ));
+    }
+    let expires = profile.expires.as_deref().and_then(|text| Some((text, date_of(text)?)));
+    if let Some((text, date)) = expires {
+        let left = (date - today).num_days();
+        match left {
+            left if left < 0 => warnings.push(format!(
+                "certificate profile '{}' of '{}' expired on {}, renew its identity validation in the portal",
+                profile.name, account, text
+            )),
+            left if left <= days => warnings.push(format!(
+                "certificate profile '{}' of '{}' expires in {} day(s) on {}, renew its identity validation in the \
+                 portal before signing starts failing",
+                profile.name, account, left, text
+            )),
+            _ => (),
+        }
+    }
+    warnings
+}
+
+/// The certificate profiles in an ARM list response
+fn certificate_profiles_of(json: &str) -> Vec<CertificateProfile> {
+    let value: serde_json::Value = serde_json::from_str(json).unwrap_or_default();
+    let items = value["value"].as_array().cloned().unwrap_or_default();
+    let text = |value: &serde_json::Value| value.as_str().map(str::to_string);
+    items
+        .iter()
+        .map(|item| {
+            let properties = &item["properties"];
+            let certificates = properties["certificates"].as_array().cloned().unwrap_or_default();
+            let newest = certificates
+                .iter()
+                .rev()
+                .find(|certificate| certificate["status"].as_str().is_none_or(|status| status == "Active"));
+            CertificateProfile {
+                name: text(&item["name"]).unwrap_or_default(),
+                profile_type: text(&properties["profileType"]).unwrap_or_default(),
+                status: text(&properties["status"]).unwrap_or_default(),
+                subject: newest.and_then(|certificate| text(&certificate["subjectName"])),
+                valid_from: newest.and_then(|certificate| text(&certificate["createdDate"])),
+                valid_to: newest.and_then(|certificate| text(&certificate["expiryDate"])),
+                expires: profile_expiry(properties),
+            }
+        })
+        .collect()
+}
+
+/// The certificate profiles of the code signing account, needs ARM read permission like [`check_profile`]
+pub fn certificate_profiles(cli_path: &str, account: &str) -> Result<Vec<CertificateProfile>, Error> {
+    match profiles_json(cli_path, account)? {
+        Ok(profiles) => Ok(certificate_profiles_of(&profiles)),
+        Err(ProfileCheck::Missing(message)) => Err(Error::Usage(message)),
+        Err(ProfileCheck::Forbidden(err)) => {
+            debug!("{}", err);
+            Err(Error::Auth(format!(
+                "the principal may not read the certificate profiles of '{}', it needs the Reader role (or another \
+                 role with Microsoft.CodeSigning/codeSigningAccounts/certificateProfiles/read) on the account or \
+                 its resource group",
+                account
+            )))
+        }
+        Err(ProfileCheck::Exists) => unreachable!("listing profiles doesn't check one"),
+    }
+}
+
+/// Every item of an ARM list at `url`, following its `nextLink` to the pages after the first
+fn arm_list(cli_path: &str, url: &str) -> Result<Vec<serde_json::Value>, String> {
+    let mut items = Vec::new();
+    let mut next = Some(url.to_string());
+    while let Some(url) = next {
+        let page = az(cli_path, &["rest", "--method", "get", "--url", &url, "--output", "json"])?;
+        let page: serde_json::Value =
+            serde_json::from_str(&page).map_err(|err| format!("azure cli printed an invalid ARM list: {}", err))?;
+        items.extend(page["value"].as_array().cloned().unwrap_or_default());
+        next = page["nextLink"].as_str().map(str::to_string);
+    }
+    Ok(items)
+}
+
+/// A code signing account, as `list-accounts` lists it
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct CodeSigningAccount {
+    /// What `-a` takes
+    pub name: String,
+    /// The Azure location, like `westeurope`
+    pub location: String,
+    /// What `-e` takes, `None` for a location without a known Trusted Signing endpoint
+    pub endpoint: Option<String>,
+    pub resource_group: String,
+    pub subscription_id: String,
+}
+
+/// The segment after `name` in an ARM resource id, like the resource group after `resourceGroups`
+fn id_segment<'a>(id: &'a str, name: &str) -> Option<&'a str> {
+    let mut segments = id.split('/');
+    segments.find(|segment| segment.eq_ignore_ascii_case(name))?;
+    segments.next()
+}
+
+fn code_signing_account(item: &serde_json::Value) -> Option<CodeSigningAccount> {
+    let id = item["id"].as_str()?;
+    let location = item["location"].as_str().unwrap_or_default();
+    Some(CodeSigningAccount {
+        name: item["name"].as_str()?.to_string(),
+        location: location.to_string(),
+        endpoint: endpoint::region_of(location).map(endpoint::url),
+        resource_group: id_segment(id, "resourceGroups").unwrap_or_default().to_string(),
+        subscription_id: id_segment(id, "subscriptions").unwrap_or_default().to_string(),
+    })
+}
+
+/// The code signing accounts in `subscriptions`, or in every subscription the principal can read when none are
+/// given. A subscription it may list but not read the accounts of is skipped with a warning
+pub fn code_signing_accounts(cli_path: &str, subscriptions: &[String]) -> Result<Vec<CodeSigningAccount>, Error> {
+    let subscriptions: Vec<String> = match subscriptions {
+        [] => {
+            let url = format!("https://management.azure.com/subscriptions?api-version={}", SUBSCRIPTIONS_API_VERSION);
+            let listed = arm_list(cli_path, &url).map_err(Error::Auth)?;
+            listed.iter().filter_map(|item| item["subscriptionId"].as_str().map(str::to_string)).collect()
+        }
+        given => given.to_vec(),
+    };
+    if subscriptions.is_empty() {
+        Err(Error::Auth(
+            "the principal can't read any subscription, it needs the Reader role on the code signing accounts, their \
+             resource group or subscription"
+                .to_string(),
+        ))?;
+    }
+
+    let mut accounts = Vec::new();
+    for subscription in &subscriptions {
+        let url = format!(
+            "https://management.azure.com/subscriptions/{}/providers/Microsoft.CodeSigning/codeSigningAccounts\
+             ?api-version={}",
+            subscription, CODE_SIGNING_API_VERSION
+        );
+        match arm_list(cli_path, &url) {
+            Ok(items) => accounts.extend(items.iter().filter_map(code_signing_account)),
+            Err(err) if is_forbidden(&err) => {
+                warn!("the principal may not read the code signing accounts of subscription {}", subscription);
+                debug!("{}", err);
+            }
+            Err(err) => Err(Error::Auth(err))?,
+        }
+    }
+    accounts.sort_by(|a, b| (&a.subscription_id, &a.name).cmp(&(&b.subscription_id, &b.name)));
+    Ok(accounts)
+}
+
+/// Check `--account-resource-id`: the ARM id of a code signing account, like
+/// `/subscriptions/<id>/resourceGroups/<group>/providers/Microsoft.CodeSigning/codeSigningAccounts/<name>`
+pub fn parse_account_resource_id(value: &str) -> Result<String, String> {
+    let id = value.trim().trim_end_matches('/');
+    let segments: Vec<&str> = id.split('/').collect();
+    let expected = ["", "subscriptions", "", "resourcegroups", "", "providers", "microsoft.codesigning", ""];
+    let matches = segments.len() == 9
+        && segments.iter().zip(expected).all(|(segment, expected)| match expected.is_empty() {
+            true => true,
+            false => segment.eq_ignore_ascii_case(expected),
+        })
+        && segments[7].eq_ignore_ascii_case("codeSigningAccounts")
+        && segments[2..].iter().step_by(2).all(|segment| !segment.is_empty());
+    match matches {
+        true => Ok(id.to_string()),
+        false => Err(format!(
+            "'{}' isn't the resource id of a code signing account, like \
+             /subscriptions/<id>/resourceGroups/<group>/providers/Microsoft.CodeSigning/codeSigningAccounts/<name>",
+            value
+        )),
+    }
+}
+
+/// The account name at the end of a resource id [`parse_account_resource_id`] took
+pub fn account_name_of(resource_id: &str) -> &str {
+    resource_id.rsplit('/').next().unwrap_or_default()
+}
+
+/// The only account named `name` of those visible, names compare without case like ARM's. None or several are
+/// errors listing what is visible
+fn only_account(accounts: Vec<CodeSigningAccount>, name: &str) -> Result<CodeSigningAccount, String> {
+    let visible: Vec<String> = accounts.iter().map(|account| account.name.clone()).collect();
+    let mut named: Vec<CodeSigningAccount> =
+        accounts.into_iter().filter(|account| account.name.eq_ignore_ascii_case(name)).collect();
+    match named.len() {
+        0 if visible.is_empty() => Err(format!(
+            "code signing account '{}' was not found, no account is visible to this principal, it needs the Reader \
+             role on the account to look up its endpoint, or pass -e",
+            name
+        )),
+        0 => Err(format!(
+            "code signing account '{}' was not found to look up its endpoint, visible are: {}",
+            name,
+            visible.join(", ")
+        )),
+        1 => Ok(named.remove(0)),
+        _ => {
+            let candidates: Vec<String> = named
+                .iter()
+                .map(|account| {
+                    format!(
+                        "  {} in {} (resource group {} in subscription {})",
+                        account.name, account.location, account.resource_group, account.subscription_id
+                    )
+                })
+                .collect();
+            Err(format!(
+                "{} code signing accounts are named '{}', pass -e or --account-resource-id to pick one:\n{}",
+                named.len(),
+                name,
+                candidates.join("\n")
+            ))
+        }
+    }
+}
+
+/// Find the code signing account to take the endpoint from when `-e` isn't given: the one `resource_id` names, or
+/// the only one named `name` in `subscriptions`, or in those the principal can read when none are given. Needs the
+/// Reader role on the account
+pub fn find_account(
+    cli_path: &str,
+    name: &str,
+    resource_id: Option<&str>,
+    subscriptions: &[String],
+) -> Result<CodeSigningAccount, Error> {
+    let account = match resource_id {
+        Some(id) => {
+            let url = format!("https://management.azure.com{}?api-version={}", id, CODE_SIGNING_API_VERSION);
+            let json = az(cli_path, &["rest", "--method", "get", "--url", &url, "--output", "json"])
+                .map_err(|err| Error::Auth(format!("the code signing account {} could not be read: {}", id, err)))?;
+            let item: serde_json::Value = serde_json::from_str(&json).unwrap_or_default();
+            code_signing_account(&item)
+                .ok_or_else(|| Error::Auth(format!("azure cli printed no code signing account for {}", id)))?
+        }
+        None => only_account(code_signing_accounts(cli_path, subscriptions)?, name).map_err(Error::Usage)?,
+    };
+    match account.endpoint {
+        Some(_) => Ok(account),
+        None => Err(Error::Usage(format!(
+            "code signing account '{}' is in {}, which has no Trusted Signing endpoint this release knows, pass -e",
+            account.name, account.location
+        ))),
+    }
+}
+
+/// Version of the Azure Monitor metrics API
+const METRICS_API_VERSION: &str = "2023-10-01";
+
+/// What the principal needs to read the account and its metrics
+const READER_NEEDED: &str = "it needs the Reader role on the codeSigningAccount (or Monitoring Reader, with \
+                             Microsoft.Insights/metrics/read)";
+
+/// A code signing account with its plan, as ARM has it
+#[derive(Debug, PartialEq, Eq)]
+pub struct AccountPlan {
+    /// The ARM resource id
+    pub id: String,
+    /// Like `Basic` or `Premium`
+    pub sku: Option<String>,
+}
+
+/// The ARM resource of code signing account `account`, or the one `resource_id` names, with its plan
+pub fn account_plan(cli_path: &str, account: &str, resource_id: Option<&str>) -> Result<AccountPlan, Error> {
+    let forbidden = |err: String| match is_forbidden(&err) {
+        true => {
+            debug!("{}", err);
+            Error::Auth(format!("the principal may not read code signing account '{}', {}", account, READER_NEEDED))
+        }
+        false => Error::Auth(err),
+    };
+    let id = match resource_id {
+        Some(id) => id.to_string(),
+        None => {
+            let ids = accounts(cli_path).map_err(forbidden)?;
+            match account_id(&ids, account) {
+                Some(id) => id,
+                None if names(&ids).is_empty() => Err(Error::Auth(format!(
+                    "code signing account '{}' was not found, no account is visible to this principal, {}",
+                    account, READER_NEEDED
+                )))?,
+                None => Err(Error::Usage(format!(
+                    "code signing account '{}' was not found, accounts visible to this principal: {}",
+                    account,
+                    names(&ids).join(", ")
+                )))?,
+            }
+        }
+    };
+    let url = format!("https://management.azure.com{}?api-version={}", id, CODE_SIGNING_API_VERSION);
+    let json = az(cli_path, &["rest", "--method", "get", "--url", &url, "--output", "json"]).map_err(forbidden)?;
+    let item: serde_json::Value = serde_json::from_str(&json).unwrap_or_default();
+    let sku = item["properties"]["sku"]["name"].as_str().or(item["sku"]["name"].as_str()).map(str::to_string);
+    Ok(AccountPlan { id, sku })
+}
+
+/// The sum of the data points of the first metric in an Azure Monitor metrics response
+fn metric_count(json: &str) -> Result<u64, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|err| format!("azure cli printed invalid metrics: {}", err))?;
+    let series = value["value"][0]["timeseries"].as_array().cloned().unwrap_or_default();
+    let count: f64 = series
+        .iter()
+        .flat_map(|series| series["data"].as_array().cloned().unwrap_or_default())
+        .filter_map(|point| point["count"].as_f64().or(point["total"].as_f64()))
+        .sum();
+    Ok(count.round() as u64)
+}
+
+/// The signatures Azure Monitor counted (`SignCompleted`) for the account `id` between `from` and `to`, RFC 3339
+/// times. Needs the Reader role on the account like [`account_plan`]
+pub fn signatures_completed(cli_path: &str, id: &str, from: &str, to: &str) -> Result<u64, Error> {
+    let url = format!(
+        "https://management.azure.com{}/providers/Microsoft.Insights/metrics?api-version={}\
+         &metricnames=SignCompleted&aggregation=Count&interval=P1D&timespan={}/{}",
+        id, METRICS_API_VERSION, from, to
+    );
+    let json = az(cli_path, &["rest", "--method", "get", "--url", &url, "--output", "json"]).map_err(|err| {
+        match is_forbidden(&err) {
+            true => {
+                debug!("{}", err);
+                Error::Auth(format!(
+                    "the principal may not read the metrics of code signing account {}, {}",
+                    account_name_of(id),
+                    READER_NEEDED
+                ))
+            }
+            false => Error::Auth(err),
+        }
+    })?;
+    metric_count(&json).map_err(Error::Other)
+}
+
+/// The subject of the newest active certificate of a certificate profile, `None` when the principal may not read
+/// the profile, needs ARM read permission like [`check_profile`]
+pub fn certificate_subject(cli_path: &str, account: &str, certificate: &str) -> Result<Option<String>, String> {
+    let forbidden = |err: String| match is_forbidden(&err) {
+        true => Ok(None),
+        false => Err(err),
+    };
+    let ids = match accounts(cli_path) {
+        Ok(ids) => ids,
+        Err(err) => return forbidden(err),
+    };
+    let id = account_id(&ids, account).ok_or_else(|| {
+        format!("code signing account '{}' was not found to read the certificate subject from", account)
+    })?;
+    let url = format!(
+        "https://management.azure.com{}/certificateProfiles/{}?api-version={}",
+        id, certificate, CODE_SIGNING_API_VERSION
+    );
+    let profile = match az(cli_path, &["rest", "--method", "get", "--url", &url, "--output", "json"]) {
+        Ok(profile) => profile,
+        Err(err) => return forbidden(err),
+    };
+    Ok(newest_subject(&profile))
+}
+
+/// The subject of the last active certificate in a certificate profile, certificates are listed oldest first
+fn newest_subject(profile: &str) -> Option<String> {
+    let profile: serde_json::Value = serde_json::from_str(profile).ok()?;
+    let certificates = profile["properties"]["certificates"].as_array()?;
+    certificates
+        .iter()
+        .rev()
+        .filter(|certificate| certificate["status"].as_str().is_none_or(|status| status == "Active"))
+        .find_map(|certificate| certificate["subjectName"].as_str())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    /// Fake az that prints a subscription list unless asked for `--output none`
+    fn stub_az(dir: &tempfile::TempDir) -> PathBuf {
+        if cfg!(windows) {
+            let path = dir.path().join("az.cmd");
+            fs::write(
+                &path,
+                "@echo off\r\necho %* | findstr /C:\"--output none\" >nul || echo [{\"tenantId\": \"00000000-1111-2222-3333-444444444444\"}]\r\n",
+            )
+            .unwrap();
+            path
+        } else {
+            let path = dir.path().join("az");
+            fs::write(
+                &path,
+                "#!/bin/sh\ncase \"$*\" in *\"--output none\"*) ;; *) echo '[{\"tenantId\": \"00000000-1111-2222-3333-444444444444\"}]' ;; esac\n",
+            )
+            .unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            path
+        }
+    }
+
+    #[test]
+    fn login_hides_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let az = stub_az(&dir);
+
+        let output = login(az.to_str().unwrap(), "tenant", "client", "secret", false).unwrap();
+        assert!(!output.contains("tenantId"));
+
+        let output = login(az.to_str().unwrap(), "tenant", "client", "secret", true).unwrap();
+        assert!(output.contains("tenantId"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn login_keeps_the_secret_off_the_command_line() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let az = dir.path().join("az");
+        // prints its arguments, then the file -p points at
+        fs::write(&az, "#!/bin/sh\necho \"$*\"\ncat \"${8#@}\"\n").unwrap();
+        fs::set_permissions(&az, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = login(az.to_str().unwrap(), "tenant", "client", "hunter2", true).unwrap();
+        let (args, secret) = output.split_once('\n').unwrap();
+        assert!(!args.contains("hunter2") && args.contains("-p @"));
+        assert_eq!(secret, "hunter2");
+        let file = args.split(' ').find_map(|arg| arg.strip_prefix('@')).unwrap();
+        assert!(!PathBuf::from(file).exists());
+    }
+
+    #[test]
+    fn extra_az_arguments() {
+        let login = parse_az_arg("--allow-no-subscriptions").unwrap();
+        assert_eq!(login.command.as_deref(), Some("login"));
+        let debug = parse_az_arg("all:--debug").unwrap();
+        assert_eq!(debug, AzArg { command: None, arg: "--debug".to_string() });
+        let rest = parse_az_arg("rest:--verbose").unwrap();
+        // a colon that doesn't follow a command is part of the argument
+        assert_eq!(parse_az_arg("--scope=https://x").unwrap().arg, "--scope=https://x");
+
+        let extra = [login, debug, rest];
+        let args = append_extra_args(&["rest", "--url", "u"], &extra);
+        assert_eq!(args, ["rest", "--url", "u", "--debug", "--verbose"]);
+        assert_eq!(append_extra_args(&["login", "-t", "t"], &extra).len(), 5);
+
+        for reserved in ["-pX", "-o", "--output=json", "--outp", "rest:--query", "all:--username", "--identity"] {
+            let error = parse_az_arg(reserved).unwrap_err();
+            assert!(error.contains("trusted-signing-cli sets"), "{}: {}", reserved, error);
+        }
+        assert!(parse_az_arg("--only-show-errors").is_ok());
+        assert!(parse_az_arg("all:").is_err());
+        assert!(is_interactive(&parse_az_arg("--use-device-code").unwrap()));
+        assert!(!is_interactive(&parse_az_arg("rest:--use-device-code").unwrap()));
+    }
+
+    #[test]
+    fn cli_versions() {
+        let json = r#"{"azure-cli": "2.64.0", "azure-cli-core": "2.64.0", "extensions": {}}"#;
+        assert_eq!(parse_version(json), Some(Version(2, 64, 0)));
+        let text = "azure-cli                         2.28.0 *\n\ncore                              2.28.0 *\n";
+        assert_eq!(parse_version(text), Some(Version(2, 28, 0)));
+        assert_eq!(parse_version("az: command not found"), None);
+        assert!(baseline_warning(Version(2, 28, 0)).unwrap().contains("older than 2.30.0"));
+        assert_eq!(baseline_warning(Version(2, 30, 0)), None);
+    }
+
+    #[test]
+    fn key_vault_secrets() {
+        let id = "https://vault.vault.azure.net/secrets/signing-sp";
+        assert_eq!(parse_secret_id(&format!("{}/", id)).unwrap(), id);
+        assert!(parse_secret_id("https://vault.vault.azure.net/secrets/signing-sp/0a1b2c").is_ok());
+        assert!(parse_secret_id("https://vault.vault.azure.net/keys/signing-sp").is_err());
+        assert!(parse_secret_id("vault.vault.azure.net/secrets/signing-sp").is_err());
+
+        let now = DateTime::parse_from_rfc3339("2026-10-14T10:00:00Z").unwrap().to_utc();
+        let secret = |attributes: &str| format!(r#"{{"value": "s3cret", "attributes": {}}}"#, attributes);
+        assert_eq!(secret_value(id, &secret(r#"{"enabled": true, "expires": null}"#), now).unwrap(), "s3cret");
+        let expired = secret_value(id, &secret(r#"{"enabled": true, "expires": "2026-01-01T00:00:00+00:00"}"#), now);
+        assert!(matches!(expired, Err(Error::Usage(message)) if message.contains("expired")));
+        let disabled = secret_value(id, &secret(r#"{"enabled": false}"#), now);
+        assert!(matches!(disabled, Err(Error::Usage(message)) if message.contains("disabled")));
+
+        let forbidden = "ERROR: (Forbidden) The user, group or application 'appid=x' does not have secrets get \
+                         permission on key vault 'vault'";
+        assert!(matches!(secret_error(id, forbidden), Error::Auth(_)));
+        let offline = "ERROR: HTTPSConnectionPool: Max retries exceeded (Failed to establish a new connection)";
+        assert!(matches!(secret_error(id, offline), Error::Other(message) if message.contains("could not be reached")));
+        assert!(matches!(secret_error(id, "ERROR: (SecretNotFound) was not found"), Error::Usage(_)));
+    }
+
+    #[test]
+    fn profile_names() {
+        assert_eq!(names(r#"{"value": [{"name": "public"}, {"name": "private"}]}"#), ["public", "private"]);
+        assert_eq!(names(r#"[{"name": "acc", "id": "/subscriptions/x"}]"#), ["acc"]);
+        assert!(is_forbidden("ERROR: (AuthorizationFailed) The client 'x' does not have authorization"));
+        assert!(!is_forbidden("ERROR: (ResourceNotFound) not found"));
+        let profile = r#"{"properties": {"certificates": [{"subjectName": "CN=Old", "status": "Active"},
+            {"subjectName": "CN=New", "status": "Active"}, {"subjectName": "CN=Revoked", "status": "Revoked"}]}}"#;
+        assert_eq!(newest_subject(profile).as_deref(), Some("CN=New"));
+    }
+
+    #[test]
+    fn certificate_profile_list() {
+        let list = r#"{"value": [{"name": "public", "properties": {"profileType": "PublicTrust", "status": "Active",
+            "certificates": [{"subjectName": "CN=Contoso", "status": "Active", "createdDate": "2026-10-13",
+            "expiryDate": "2026-10-16"}]}}, {"name": "test", "properties": {"profileType": "PrivateTrust",
+            "status": "Disabled"}}]}"#;
+        let profiles = certificate_profiles_of(list);
+        assert_eq!(profiles[0].subject.as_deref(), Some("CN=Contoso"));
+        let validity = (profiles[0].valid_from.as_deref(), profiles[0].valid_to.as_deref());
+        assert_eq!(validity, (Some("2026-10-13"), Some("2026-10-16")));
+        assert_eq!((profiles[1].profile_type.as_str(), profiles[1].status.as_str()), ("PrivateTrust", "Disabled"));
+        assert_eq!(profiles[1].valid_to, None);
+        assert_eq!(profiles[0].expires, None);
+    }
+
+    #[test]
+    fn profile_expiry_warnings() {
+        let properties = serde_json::json!({"status": "Active", "identityValidation": {"expirationDate":
+            "2026-10-20T00:00:00Z"}, "certificates": [{"expiryDate": "2026-10-15"}], "validityExpiry": "2027-01-01"});
+        assert_eq!(profile_expiry(&properties).as_deref(), Some("2026-10-20T00:00:00Z"));
+        let today = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+        let mut profile = CertificateProfile {
+            name: "prof".to_string(),
+            profile_type: "PublicTrust".to_string(),
+            status: "Active".to_string(),
+            subject: None,
+            valid_from: None,
+            valid_to: Some("2026-10-15".to_string()),
+            expires: profile_expiry(&properties),
+        };
+        let warnings = expiry_warnings("acc", &profile, 14, today);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("expires in 6 day(s)"));
+        assert!(expiry_warnings("acc", &profile, 5, today).is_empty());
+        profile.status = "Suspended".to_string();
+        profile.expires = Some("2026-10-01".to_string());
+        let warnings = expiry_warnings("acc", &profile, 14, today);
+        assert!(warnings[0].contains("is Suspended") && warnings[1].contains("expired on 2026-10-01"));
+    }
+
+    #[test]
+    fn code_signing_account_from_arm() {
+        let item = serde_json::json!({
+            "id": "/subscriptions/sub-1/resourceGroups/Signing-RG/providers/Microsoft.CodeSigning/codeSigningAccounts/",
+            "name": "a",
+            "location": "westeurope"
+        });
+        let account = code_signing_account(&item).unwrap();
+        assert_eq!(account.endpoint.as_deref(), Some("https://weu.codesigning.azure.net"));
+        assert_eq!((account.resource_group.as_str(), account.subscription_id.as_str()), ("Signing-RG", "sub-1"));
+        let elsewhere = code_signing_account(&serde_json::json!({"id": "/x", "name": "a", "location": "mars"}));
+        assert_eq!(elsewhere.unwrap().endpoint, None);
+    }
+
+    #[test]
+    fn signatures_from_metrics() {
+        let json = r#"{"value": [{"name": {"value": "SignCompleted"}, "unit": "Count", "timeseries": [{"data": [
+            {"timeStamp": "2026-10-01T00:00:00Z", "count": 120.0},
+            {"timeStamp": "2026-10-02T00:00:00Z"},
+            {"timeStamp": "2026-10-03T00:00:00Z", "count": 7}
+        ]}]}]}"#;
+        assert_eq!(metric_count(json), Ok(127));
+        assert_eq!(metric_count(r#"{"value": [{"timeseries": []}]}"#), Ok(0));
+        assert!(metric_count("not json").is_err());
+    }
+
+    #[test]
+    fn endpoint_of_the_account() {
+        let id = "/subscriptions/sub-1/resourceGroups/rg/providers/Microsoft.CodeSigning/codeSigningAccounts/Acc/";
+        assert_eq!(parse_account_resource_id(id).unwrap(), id.trim_end_matches('/'));
+        assert_eq!(account_name_of(&parse_account_resource_id(id).unwrap()), "Acc");
+        assert!(parse_account_resource_id("/subscriptions/sub-1/resourceGroups/rg").is_err());
+        let other = "/subscriptions/s/resourceGroups/rg/providers/Microsoft.KeyVault/vaults/v";
+        assert!(parse_account_resource_id(other).is_err());
+
+        let account = |name: &str, location: &str, subscription: &str| CodeSigningAccount {
+            name: name.to_string(),
+            location: location.to_string(),
+            endpoint: endpoint::region_of(location).map(endpoint::url),
+            resource_group: "rg".to_string(),
+            subscription_id: subscription.to_string(),
+        };
+        let accounts = || vec![account("acc", "eastus", "sub-1"), account("other", "westeurope", "sub-1")];
+        let found = only_account(accounts(), "ACC").unwrap();
+        assert_eq!(found.endpoint.as_deref(), Some("https://eus.codesigning.azure.net"));
+        let error = only_account(accounts(), "missing").unwrap_err();
+        assert!(error.ends_with("visible are: acc, other"), "{}", error);
+        assert!(only_account(Vec::new(), "acc").unwrap_err().contains("no account is visible"));
+
+        let mut twice = accounts();
+        twice.push(account("acc", "westeurope", "sub-2"));
+        let error = only_account(twice, "acc").unwrap_err();
+        assert!(error.starts_with("2 code signing accounts are named 'acc'"), "{}", error);
+        assert!(error.contains("acc in westeurope (resource group rg in subscription sub-2)"), "{}", error);
+    }
+}
@@ -0,0 +1,301 @@
+//! `bench`: signing throwaway files with the normal pipeline to measure the latency and throughput of the service
+//! at a concurrency, before a release signs thousands of files
+
+use crate::{
+    error::Error,
+    events::Events,
+    hints, interrupt, quota,
+    route::Target,
+    signer::{SignOptions, SignOutcome, Signer},
+    smoke,
+};
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Barrier, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Distinct errors kept for the summary, a throttled run fails hundreds of files the same way
+const MAX_ERRORS: usize = 5;
+
+/// Check `--size`: bytes, or with a unit of 1024 like `64KB` or `1MB`
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| format!("'{}' is not a size like 64KB or 1MB", value))?;
+    let factor: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => Err(format!("'{}' is not a size like 64KB or 1MB", value))?,
+    };
+    number.checked_mul(factor).ok_or_else(|| format!("'{}' is too large", value))
+}
+
+/// A size as `1.0 MB`
+fn describe(size: u64) -> String {
+    match size {
+        size if size >= 1 << 20 => format!("{:.1} MB", size as f64 / (1 << 20) as f64),
+        size if size >= 1 << 10 => format!("{:.1} KB", size as f64 / (1 << 10) as f64),
+        size => format!("{} bytes", size),
+    }
+}
+
+/// What the run costs, printed before anything is signed
+pub fn cost(count: usize, usage: &quota::Usage) -> String {
+    format!(
+        "bench signs {} throwaway files, which uses {} signatures of the quota of {}. This machine counted {} in {}",
+        count, count, usage.account, usage.signatures, usage.month
+    )
+}
+
+/// Write `count` executables of `size` bytes into `dir`, the stub of the smoke test with an overlay so each is
+/// different
+pub fn write_files(dir: &Path, count: usize, size: u64) -> io::Result<Vec<String>> {
+    let stub = smoke::stub_exe();
+    let mut files = Vec::new();
+    for index in 0..count {
+        let mut image = stub.clone();
+        image.extend((index as u64).to_le_bytes());
+        image.resize((size as usize).max(image.len()), 0);
+        let file = dir.join(format!("bench-{:05}.exe", index));
+        fs::write(&file, image)?;
+        files.push(file.display().to_string());
+    }
+    Ok(files)
+}
+
+/// Latencies of the files signed, in milliseconds
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Latency {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    pub mean: u64,
+}
+
+/// The `percent` percentile of `sorted`, by nearest rank
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    match sorted.len() {
+        0 => 0,
+        len => sorted[(len * percent).div_ceil(100).clamp(1, len) - 1],
+    }
+}
+
+/// What `bench` found
+#[derive(Serialize, Debug)]
+pub struct Bench {
+    pub endpoint: String,
+    pub account: String,
+    pub certificate_profile: String,
+    pub files: usize,
+    /// Bytes of each file
+    pub size: u64,
+    pub jobs: usize,
+    pub signed: usize,
+    pub failed: usize,
+    /// Failures the service throttled or refused for the quota, 429 Too Many Requests
+    pub throttled: usize,
+    /// Endpoints files failed at before a fallback endpoint signed them
+    pub retries: u32,
+    /// From the first file to the last, the logins before aren't counted
+    pub duration_ms: u64,
+    pub files_per_minute: f64,
+    pub latency_ms: Latency,
+    /// How many files were signed at once on average, the latencies added up over the duration. Close to the jobs
+    /// as long as the service keeps up
+    pub concurrency: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl Bench {
+    fn new(target: &Target, files: usize, size: u64, jobs: usize) -> Self {
+        Bench {
+            endpoint: target.endpoint.clone(),
+            account: target.account.clone(),
+            certificate_profile: target.certificate.clone(),
+            files,
+            size,
+            jobs,
+            signed: 0,
+            failed: 0,
+            throttled: 0,
+            retries: 0,
+            duration_ms: 0,
+            files_per_minute: 0.0,
+            latency_ms: Latency::default(),
+            concurrency: 0.0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Count the outcomes of the files, signed `duration` from the first to the last
+    fn finish(&mut self, results: &[(SignOutcome, Result<(), Error>)], duration: Duration) {
+        let mut latencies = Vec::new();
+        for (outcome, result) in results {
+            self.retries += outcome.retries;
+            match result {
+                Ok(()) => {
+                    self.signed += 1;
+                    latencies.push(outcome.duration.as_millis() as u64);
+                }
+                Err(err) => {
+                    self.failed += 1;
+                    if is_throttled(err) {
+                        self.throttled += 1;
+                    }
+                    let message = err.to_string();
+                    if self.errors.len() < MAX_ERRORS && !self.errors.contains(&message) {
+                        self.errors.push(message);
+                    }
+                }
+            }
+        }
+        latencies.sort_unstable();
+        let total: u64 = latencies.iter().sum();
+        self.duration_ms = duration.as_millis() as u64;
+        self.latency_ms = Latency {
+            p50: percentile(&latencies, 50),
+            p90: percentile(&latencies, 90),
+            p99: percentile(&latencies, 99),
+            max: latencies.last().copied().unwrap_or_default(),
+            mean: total.checked_div(latencies.len() as u64).unwrap_or_default(),
+        };
+        if self.duration_ms > 0 {
+            self.files_per_minute = self.signed as f64 * 60_000.0 / self.duration_ms as f64;
+            self.concurrency = total as f64 / self.duration_ms as f64;
+        }
+    }
+
+    /// Human readable summary
+    pub fn table(&self) -> String {
+        let seconds = |ms: u64| match ms {
+            0..1000 => format!("{}ms", ms),
+            ms => format!("{:.1}s", ms as f64 / 1000.0),
+        };
+        let latency = &self.latency_ms;
+        let mut table = format!("Benchmark of {} / {} at {}\n", self.account, self.certificate_profile, self.endpoint);
+        table.push_str(&format!(
+            "  files         {} of {}, {} at a time\n",
+            self.files,
+            describe(self.size),
+            self.jobs
+        ));
+        table.push_str(&format!(
+            "  signed        {}, {} failed ({} throttled), {} retries\n",
+            self.signed, self.failed, self.throttled, self.retries
+        ));
+        table.push_str(&format!(
+            "  duration      {}, {:.1} files per minute\n",
+            seconds(self.duration_ms),
+            self.files_per_minute
+        ));
+        table.push_str(&format!(
+            "  latency       p50 {}, p90 {}, p99 {}, max {}\n",
+            seconds(latency.p50),
+            seconds(latency.p90),
+            seconds(latency.p99),
+            seconds(latency.max)
+        ));
+        table.push_str(&format!(
+            "  concurrency   {:.1} files at once on average, one at a time would take {}\n",
+            self.concurrency,
+            seconds(latency.mean * self.signed as u64)
+        ));
+        for error in &self.errors {
+            table.push_str(&format!("  error         {}\n", error.lines().next().unwrap_or_default()));
+        }
+        table.push_str(&format!("  quota used    {} signature(s)\n", self.signed));
+        table
+    }
+}
+
+/// Whether the service throttled the file or refused it for the quota
+fn is_throttled(err: &Error) -> bool {
+    let Error::Signing { message, output, .. } = err else {
+        return false;
+    };
+    [output, message].iter().any(|text| hints::hint(text).is_some_and(|hint| hint.cause == "quota exceeded"))
+}
+
+/// Sign `files` with `jobs` signers at once, each on a thread of its own since signtool blocks. The signers log in
+/// one after the other, the clock starts once all of them did
+pub fn run(options: &SignOptions, target: &Target, files: &[String], size: u64, jobs: usize) -> Result<Bench, Error> {
+    let queue = Mutex::new(files.iter());
+    let connecting = Mutex::new(());
+    let barrier = Barrier::new(jobs);
+    let started = Mutex::new(None);
+    let results = Mutex::new(Vec::new());
+    let failures = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build();
+                let runtime = runtime.expect("a runtime for the signer");
+                let mut signer = Signer::new(options.clone(), Events::default());
+                let connected = {
+                    let _turn = connecting.lock().unwrap();
+                    runtime.block_on(signer.connect())
+                };
+                barrier.wait();
+                started.lock().unwrap().get_or_insert_with(Instant::now);
+                if let Err(err) = connected {
+                    failures.lock().unwrap().push(err);
+                    return;
+                }
+                while let Some(file) = queue.lock().unwrap().next().filter(|_| !interrupt::interrupted()) {
+                    let result = runtime.block_on(signer.attempt(file));
+                    results.lock().unwrap().push(result);
+                }
+            });
+        }
+    });
+    if let Some(err) = failures.into_inner().unwrap().into_iter().next() {
+        Err(err)?;
+    }
+    let duration = started.into_inner().unwrap().map(|started| started.elapsed()).unwrap_or_default();
+    let mut bench = Bench::new(target, files.len(), size, jobs);
+    bench.finish(&results.into_inner().unwrap(), duration);
+    Ok(bench)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_and_percentiles() {
+        assert_eq!(parse_size("1MB"), Ok(1 << 20));
+        assert_eq!(parse_size("64 kb"), Ok(64 << 10));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("1TB").is_err() && parse_size("MB").is_err());
+        assert_eq!(describe(1 << 20), "1.0 MB");
+
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!([50, 90, 99].map(|percent| percentile(&sorted, percent)), [50, 90, 99]);
+        assert_eq!(percentile(&[7], 99), 7);
+        assert_eq!(percentile(&[], 50), 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let files = write_files(dir.path(), 2, 8192).unwrap();
+        let [first, second] = [&files[0], &files[1]].map(|file| fs::read(file).unwrap());
+        assert_eq!((first.len(), second.len()), (8192, 8192));
+        assert_ne!(first, second);
+        assert!(first.starts_with(b"MZ"));
+
+        let throttled = Error::Signing {
+            path: "a.exe".to_string(),
+            message: "signtool failed".to_string(),
+            output: "Service request failed.\r\nStatus: 429 (Too Many Requests)".to_string(),
+        };
+        assert!(is_throttled(&throttled));
+        assert!(!is_throttled(&Error::Other("429".to_string())));
+    }
+}
@@ -0,0 +1,13 @@
+use std::{ffi::OsString, process::ExitCode};
+
+/// `cargo trusted-sign`, cargo runs this as `cargo-trusted-sign trusted-sign <args>`
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    // also works when run directly as cargo-trusted-sign
+    if argv.get(1).is_some_and(|arg| arg == "trusted-sign") {
+        argv.remove(1);
+    }
+    argv.insert(1.min(argv.len()), OsString::from("cargo"));
+    trusted_signing_cli::app::main(argv).await
+}
@@ -0,0 +1,112 @@
+use crate::{error::Error, interrupt, logging};
+use duct::cmd;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Instant;
+use tracing::info;
+
+/// The build `--run` ran before signing
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct Build {
+    pub command: String,
+    pub duration_ms: u64,
+}
+
+/// Run the build command through the shell, `cmd /C` on Windows and `sh -c` elsewhere, its output streamed to
+/// stderr so stdout stays for the report. Fails with the build's own exit code when it doesn't exit with zero
+pub fn run(command: &str) -> Result<Build, Error> {
+    info!("running the build: {}", logging::mask(command));
+    let started = Instant::now();
+    let shell = match cfg!(windows) {
+        true => cmd!("cmd", "/C", command),
+        false => cmd!("sh", "-c", command),
+    };
+    let output = interrupt::without_input(shell).stdout_to_stderr().unchecked().run().map_err(|err| Error::Build {
+        command: command.to_string(),
+        code: None,
+        message: format!("the build '{}' could not be run: {}", command, err),
+    })?;
+    let build = Build { command: command.to_string(), duration_ms: started.elapsed().as_millis() as u64 };
+    match output.status.success() {
+        true => {
+            info!("the build finished in {:.1}s", build.duration_ms as f64 / 1000.0);
+            Ok(build)
+        }
+        false => Err(Error::Build {
+            command: command.to_string(),
+            code: output.status.code(),
+            message: format!("the build '{}' failed, {}, nothing was signed", command, output.status),
+        }),
+    }
+}
+
+/// `pattern` with its `{a,b}` alternatives expanded, for globs like `bundle/**/*.{exe,msi}` the glob crate
+/// doesn't take
+pub fn braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(close) = pattern[open..].find('}').map(|close| open + close) else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    pattern[open + 1..close]
+        .split(',')
+        .flat_map(|alternative| braces(&format!("{}{}{}", prefix, alternative, suffix)))
+        .collect()
+}
+
+/// The files the --then-sign globs match once the build is done, each once and in the order of the globs. A glob
+/// matching nothing fails, the build didn't produce what was to be signed
+pub fn outputs(patterns: &[String]) -> Result<Vec<String>, Error> {
+    let mut files: Vec<String> = Vec::new();
+    for pattern in patterns {
+        let mut matched = Vec::new();
+        for expanded in braces(pattern) {
+            let paths = glob::glob(&expanded)
+                .map_err(|err| Error::Usage(format!("--then-sign '{}' is not a valid glob: {}", pattern, err)))?;
+            matched.extend(paths.flatten().filter(|path| path.is_file()).map(|path| path.display().to_string()));
+        }
+        if matched.is_empty() {
+            Err(Error::Usage(format!("--then-sign '{}' matched no files after the build", pattern)))?;
+        }
+        matched.sort();
+        for file in matched {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn outputs_after_the_build() {
+        assert_eq!(braces("bundle/**/*.{exe,msi}"), ["bundle/**/*.exe", "bundle/**/*.msi"]);
+        assert_eq!(braces("{a,b}/x.{exe,dll}").len(), 4);
+        assert_eq!(braces("odd{.exe"), ["odd{.exe"]);
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().display().to_string();
+        fs::create_dir_all(temp.path().join("bundle/nsis")).unwrap();
+        for file in ["bundle/nsis/app.exe", "bundle/app.msi", "bundle/app.pdb"] {
+            fs::write(temp.path().join(file), "").unwrap();
+        }
+        let files = outputs(&[format!("{}/bundle/**/*.{{exe,msi}}", dir), format!("{}/bundle/*.msi", dir)]).unwrap();
+        let found: Vec<&Path> = files.iter().map(|file| Path::new(file).strip_prefix(temp.path()).unwrap()).collect();
+        assert_eq!(found, [Path::new("bundle/app.msi"), Path::new("bundle/nsis/app.exe")]);
+        assert!(outputs(&[format!("{}/bundle/*.zip", dir)]).is_err());
+
+        // `exit 3` means the same to cmd and sh
+        match run("exit 3") {
+            Err(err @ Error::Build { .. }) => assert_eq!(err.exit_code(), 3),
+            other => panic!("{:?}", other),
+        }
+        assert!(run("exit 0").is_ok());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    signtool,
+};
+use duct::cmd;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where the WiX Toolset v3 installs insignia, the newest toolset wins
+const INSIGNIA_PATTERN: &str = r"C:\Program Files (x86)\WiX Toolset v3*\bin\insignia.exe";
+
+/// The section Burn keeps the manifest and the attached containers of a bundle in
+const SECTION: &[u8; 8] = b".wixburn";
+
+/// The section table of a PE image is in its first few KB, bundles themselves can be gigabytes
+const HEADERS_SIZE: u64 = 64 * 1024;
+
+fn u16_at(image: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(image.get(offset..offset + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_at(image: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(image.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+/// The names of the sections of a PE image, from the headers at its start
+fn section_names(headers: &[u8]) -> Option<Vec<&[u8]>> {
+    let header = u32_at(headers, 0x3c)?;
+    if headers.get(header..header + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let (count, optional_size) = (u16_at(headers, header + 6)?, u16_at(headers, header + 20)?);
+    let table = header + 24 + optional_size;
+    (0..count).map(|index| headers.get(table + index * 40..table + index * 40 + 8)).collect()
+}
+
+/// Whether the file is a WiX Burn bundle, an executable with a `.wixburn` section
+pub fn is_bundle(path: &str) -> bool {
+    let mut headers = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(HEADERS_SIZE).read_to_end(&mut headers));
+    read.is_ok() && section_names(&headers).is_some_and(|names| names.contains(&SECTION.as_slice()))
+}
+
+/// The insignia to run: `wix_path` when given, which may also be the WiX Toolset directory or its bin directory,
+/// otherwise the one of the toolset in %WIX%, of the newest installed toolset or on the PATH
+pub fn find_insignia(wix_path: Option<&str>) -> Result<PathBuf, Error> {
+    let candidates = |dir: &Path| [dir.join("insignia.exe"), dir.join("bin").join("insignia.exe")];
+    if let Some(path) = wix_path {
+        let path = Path::new(path);
+        let mut found = [path.to_path_buf()].into_iter().chain(candidates(path));
+        return found.find(|candidate| candidate.is_file()).ok_or_else(|| Error::MissingDependency {
+            component: "insignia",
+            message: format!("insignia was not found at '{}'", path.display()),
+        });
+    }
+    let toolset = std::env::var_os("WIX").map(|dir| candidates(Path::new(&dir))).into_iter().flatten();
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join("insignia.exe")).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let Some(insignia) =
+        toolset.chain(signtool::newest(INSIGNIA_PATTERN)).chain(on_path).find(|candidate| candidate.is_file())
+    else {
+        Err(Error::MissingDependency {
+            component: "insignia",
+            message: "insignia.exe was not found, signing Burn bundles needs it from the WiX Toolset v3: install \
+                      the toolset or pass --wix-path"
+                .to_string(),
+        })?
+    };
+    debug!("detaching Burn engines with {}", insignia.display());
+    Ok(insignia)
+}
+
+/// Run insignia with `args`, failing with the bundle as the file
+fn insignia(insignia: &Path, args: &[&Path], bundle: &str, message: String) -> Result<(), Error> {
+    let output = run_captured(cmd(insignia, args)).map_err(|err| Error::MissingDependency {
+        component: "insignia",
+        message: format!("insignia '{}' could not be run: {}", insignia.display(), err),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: bundle.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+/// Write the Burn engine of `bundle` to `engine`, the bundle itself stays as it is
+pub fn detach(path: &Path, bundle: &Path, engine: &Path, file: &str) -> Result<(), Error> {
+    let args = [Path::new("-ib"), bundle, Path::new("-o"), engine];
+    insignia(path, &args, file, format!("insignia could not detach the engine of '{}'", file))
+}
+
+/// Write `bundle` with its engine replaced by the signed `engine` to `output`
+pub fn reattach(path: &Path, engine: &Path, bundle: &Path, output: &Path, file: &str) -> Result<(), Error> {
+    let args = [Path::new("-ab"), engine, bundle, Path::new("-o"), output];
+    insignia(path, &args, file, format!("insignia could not reattach the signed engine of '{}'", file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PE headers with the sections `names`
+    fn headers(names: &[&[u8; 8]]) -> Vec<u8> {
+        let mut headers = vec![0u8; 0x200];
+        headers[..2].copy_from_slice(b"MZ");
+        headers[0x3c] = 0x80;
+        headers[0x80..0x84].copy_from_slice(b"PE\0\0");
+        headers[0x86..0x88].copy_from_slice(&(names.len() as u16).to_le_bytes());
+        headers[0x94..0x96].copy_from_slice(&0xf0u16.to_le_bytes());
+        for (index, name) in names.iter().enumerate() {
+            let entry = 0x80 + 24 + 0xf0 + index * 40;
+            headers[entry..entry + 8].copy_from_slice(name.as_slice());
+        }
+        headers
+    }
+
+    #[test]
+    fn bundle_sections() {
+        let bundle = headers(&[b".text\0\0\0", b".rdata\0\0", SECTION]);
+        assert!(section_names(&bundle).unwrap().contains(&SECTION.as_slice()));
+        let plain = headers(&[b".text\0\0\0"]);
+        assert!(!section_names(&plain).unwrap().contains(&SECTION.as_slice()));
+        assert_eq!(section_names(b"MZ not a PE"), None);
+    }
+}
@@ -0,0 +1,120 @@
+//! External cabinets of Windows Installer packages, which are signed before the package referencing them
+
+use crate::error::Error;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use tracing::debug;
+
+fn is_msi(file: &str) -> bool {
+    Path::new(file).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("msi"))
+}
+
+/// The cabinets the Media table of `msi` names that are files, the embedded ones are streams named with a `#`
+pub fn external(msi: &Path) -> Result<Vec<String>, String> {
+    let mut package = msi::open(msi).map_err(|err| err.to_string())?;
+    if !package.has_table("Media") {
+        return Ok(Vec::new());
+    }
+    let rows = package.select_rows(msi::Select::table("Media")).map_err(|err| err.to_string())?;
+    let mut cabinets = Vec::new();
+    for row in rows {
+        match row["Cabinet"].as_str() {
+            Some(cabinet) if !cabinet.is_empty() && !cabinet.starts_with('#') => cabinets.push(cabinet.to_string()),
+            _ => (),
+        }
+    }
+    Ok(cabinets)
+}
+
+/// The files with the external cabinets of every MSI among them right before it, so they are signed first, and the
+/// MSI of each cabinet. A cabinet listed as well is signed once. An MSI that can't be read is left to fail signing
+pub fn expand(files: &[String]) -> Result<(Vec<String>, HashMap<String, String>), Error> {
+    let mut expanded = Vec::new();
+    let mut parents = HashMap::new();
+    let mut seen = HashSet::new();
+    for file in files {
+        if !is_msi(file) {
+            if seen.insert(file.clone()) {
+                expanded.push(file.clone());
+            }
+            continue;
+        }
+        let msi = Path::new(file);
+        let cabinets = match external(msi) {
+            Ok(cabinets) => cabinets,
+            Err(err) => {
+                debug!("the cabinets of {} could not be read: {}", file, err);
+                Vec::new()
+            }
+        };
+        let dir = msi.parent().unwrap_or(Path::new(""));
+        let paths: Vec<_> = cabinets.iter().map(|cabinet| dir.join(cabinet)).collect();
+        let missing: Vec<&str> = cabinets
+            .iter()
+            .zip(&paths)
+            .filter(|(_, path)| !path.is_file())
+            .map(|(cabinet, _)| cabinet.as_str())
+            .collect();
+        if !missing.is_empty() {
+            Err(Error::InvalidInput {
+                path: file.clone(),
+                message: format!(
+                    "'{}' references external cabinets that aren't next to it: {}",
+                    file,
+                    missing.join(", ")
+                ),
+            })?;
+        }
+        for path in paths {
+            let cabinet = path.display().to_string();
+            if seen.insert(cabinet.clone()) {
+                expanded.push(cabinet.clone());
+            }
+            parents.insert(cabinet, file.clone());
+        }
+        if seen.insert(file.clone()) {
+            expanded.push(file.clone());
+        }
+    }
+    Ok((expanded, parents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use msi::{Column, Insert, Package, PackageType, Value};
+    use std::fs;
+
+    #[test]
+    fn cabinets_before_their_msi() {
+        let dir = tempfile::tempdir().unwrap();
+        let setup = dir.path().join("setup.msi");
+        let mut package = Package::create(PackageType::Installer, fs::File::create_new(&setup).unwrap()).unwrap();
+        let columns = vec![
+            Column::build("DiskId").primary_key().int16(),
+            Column::build("LastSequence").int32(),
+            Column::build("Cabinet").nullable().string(255),
+        ];
+        package.create_table("Media", columns).unwrap();
+        let media = Insert::into("Media")
+            .row(vec![Value::Int(1), Value::Int(10), Value::Str("#embedded.cab".to_string())])
+            .row(vec![Value::Int(2), Value::Int(20), Value::Str("disk2.cab".to_string())])
+            .row(vec![Value::Int(3), Value::Int(30), Value::Null]);
+        package.insert_rows(media).unwrap();
+        package.flush().unwrap();
+        drop(package);
+        assert_eq!(external(&setup).unwrap(), ["disk2.cab"]);
+
+        let setup = setup.display().to_string();
+        let err = expand(std::slice::from_ref(&setup)).unwrap_err().to_string();
+        assert!(err.ends_with("references external cabinets that aren't next to it: disk2.cab"), "{}", err);
+
+        let cabinet = dir.path().join("disk2.cab").display().to_string();
+        fs::write(&cabinet, b"MSCF").unwrap();
+        let (files, parents) = expand(&[cabinet.clone(), "app.exe".to_string(), setup.clone()]).unwrap();
+        assert_eq!(files, [cabinet.clone(), "app.exe".to_string(), setup.clone()]);
+        assert_eq!(parents.get(&cabinet), Some(&setup));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::error::Error;
+use duct::cmd;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The parts of `cargo metadata` needed to find the built artifacts
+#[derive(Deserialize, Debug)]
+pub struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    /// Missing before cargo 1.71, then every member is a default member
+    #[serde(default)]
+    workspace_default_members: Option<Vec<String>>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize, Debug)]
+struct Package {
+    id: String,
+    name: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    manifest_path: PathBuf,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+impl Metadata {
+    /// Where cargo builds to, honoring CARGO_TARGET_DIR and the cargo config
+    pub fn target_directory(&self) -> &Path {
+        &self.target_directory
+    }
+
+    /// Version and bin target names of the package of `manifest_path`
+    pub fn package(&self, manifest_path: &Path) -> Option<(&str, Vec<&str>)> {
+        let manifest_path = manifest_path.canonicalize().ok()?;
+        let package = self
+            .packages
+            .iter()
+            .find(|package| package.manifest_path.canonicalize().is_ok_and(|path| path == manifest_path))?;
+        let bins = package
+            .targets
+            .iter()
+            .filter(|target| target.kind.iter().any(|kind| kind == "bin"))
+            .map(|target| target.name.as_str())
+            .collect();
+        Some((&package.version, bins))
+    }
+}
+
+/// Which of the built artifacts to sign
+#[derive(Debug, Default)]
+pub struct Selection<'a> {
+    /// Cargo profile, `dev` when not given
+    pub profile: Option<&'a str>,
+    pub target: Option<&'a str>,
+    /// Packages to take the artifacts of, the default members of the workspace when empty
+    pub packages: &'a [String],
+    /// Binaries to sign, every bin and cdylib when empty
+    pub bins: &'a [String],
+}
+
+/// A bin or cdylib of a workspace member
+#[derive(Debug, PartialEq, Eq)]
+pub struct Artifact {
+    pub package: String,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl Artifact {
+    /// Whether cargo built the artifact yet
+    pub fn built(&self) -> bool {
+        self.path.is_file()
+    }
+}
+
+/// Read the metadata of the workspace, through the cargo that runs this subcommand when it does
+pub fn metadata(manifest_path: Option<&Path>) -> Result<Metadata, Error> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut args = vec!["metadata".into(), "--format-version".into(), "1".into(), "--no-deps".into()];
+    if let Some(path) = manifest_path {
+        args.extend(["--manifest-path".into(), path.as_os_str().to_owned()]);
+    }
+    let output = cmd(&cargo, args)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(|err| Error::MissingDependency {
+            component: "cargo",
+            message: format!("cargo could not be run: {}", err),
+        })?;
+    if !output.status.success() {
+        Err(Error::Usage(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))?;
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|err| Error::Other(format!("cargo metadata could not be read: {}", err)))
+}
+
+/// Output directory of a profile, dev and test build to `debug`, release and bench to `release`
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    }
+}
+
+/// The bins and cdylibs of the selection as the .exe and .dll files they build to, whether they were built or not
+pub fn artifacts(metadata: &Metadata, selection: &Selection) -> Result<Vec<Artifact>, Error> {
+    if let Some(target) = selection.target.filter(|target| !target.contains("windows")) {
+        Err(Error::Usage(format!(
+            "target '{}' doesn't produce Windows files, only windows targets can be signed",
+            target
+        )))?;
+    }
+
+    let members: Vec<&Package> =
+        metadata.packages.iter().filter(|package| metadata.workspace_members.contains(&package.id)).collect();
+    for name in selection.packages {
+        if !members.iter().any(|package| &package.name == name) {
+            Err(Error::Usage(format!("package '{}' is not a member of the workspace", name)))?;
+        }
+    }
+    let packages: Vec<&Package> = match selection.packages.is_empty() {
+        true => members
+            .into_iter()
+            .filter(|package| {
+                metadata.workspace_default_members.as_ref().is_none_or(|default| default.contains(&package.id))
+            })
+            .collect(),
+        false => members.into_iter().filter(|package| selection.packages.contains(&package.name)).collect(),
+    };
+
+    let mut dir = metadata.target_directory.clone();
+    if let Some(target) = selection.target {
+        dir.push(target);
+    }
+    dir.push(profile_dir(selection.profile.unwrap_or("dev")));
+
+    let mut artifacts = Vec::new();
+    for package in &packages {
+        for target in &package.targets {
+            let file = match (target.kind.iter().any(|kind| kind == "bin"), selection.bins.is_empty()) {
+                (true, true) => format!("{}.exe", target.name),
+                (true, false) if selection.bins.contains(&target.name) => format!("{}.exe", target.name),
+                // --bin only selects binaries, like it does for cargo build
+                (false, true) if target.kind.iter().any(|kind| kind == "cdylib") => {
+                    format!("{}.dll", target.name.replace('-', "_"))
+                }
+                _ => continue,
+            };
+            artifacts.push(Artifact {
+                package: package.name.clone(),
+                name: target.name.clone(),
+                path: dir.join(file),
+            });
+        }
+    }
+    for name in selection.bins {
+        if !artifacts.iter().any(|artifact| &artifact.name == name) {
+            Err(Error::Usage(format!("no bin target named '{}' in the selected packages", name)))?;
+        }
+    }
+    Ok(artifacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_artifacts() {
+        let metadata: Metadata = serde_json::from_str(
+            r#"{
+                "packages": [
+                    {"id": "app 0.1.0", "name": "app", "targets": [
+                        {"name": "app", "kind": ["bin"]},
+                        {"name": "app-updater", "kind": ["bin"]},
+                        {"name": "build-script-build", "kind": ["custom-build"]}
+                    ]},
+                    {"id": "plugin 0.1.0", "name": "plugin", "targets": [{"name": "my-plugin", "kind": ["cdylib", "rlib"]}]},
+                    {"id": "xtask 0.1.0", "name": "xtask", "targets": [{"name": "xtask", "kind": ["bin"]}]}
+                ],
+                "workspace_members": ["app 0.1.0", "plugin 0.1.0", "xtask 0.1.0"],
+                "workspace_default_members": ["app 0.1.0", "plugin 0.1.0"],
+                "target_directory": "/work/target"
+            }"#,
+        )
+        .unwrap();
+        let paths = |selection: &Selection| -> Vec<PathBuf> {
+            artifacts(&metadata, selection).unwrap().into_iter().map(|artifact| artifact.path).collect()
+        };
+
+        let release = Selection {
+            profile: Some("release"),
+            ..Selection::default()
+        };
+        assert_eq!(
+            paths(&release),
+            [
+                Path::new("/work/target/release/app.exe"),
+                Path::new("/work/target/release/app-updater.exe"),
+                Path::new("/work/target/release/my_plugin.dll"),
+            ]
+        );
+
+        let packages = ["xtask".to_string()];
+        let target = Selection {
+            target: Some("x86_64-pc-windows-msvc"),
+            packages: &packages,
+            ..Selection::default()
+        };
+        assert_eq!(paths(&target), [Path::new("/work/target/x86_64-pc-windows-msvc/debug/xtask.exe")]);
+
+        let bins = ["app-updater".to_string()];
+        let bin = Selection {
+            bins: &bins,
+            ..Selection::default()
+        };
+        assert_eq!(paths(&bin), [Path::new("/work/target/debug/app-updater.exe")]);
+
+        let unknown = ["missing".to_string()];
+        assert!(artifacts(&metadata, &Selection { packages: &unknown, ..Selection::default() }).is_err());
+        assert!(artifacts(&metadata, &Selection { target: Some("aarch64-apple-darwin"), ..Selection::default() }).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{
+    error::Error,
+    input,
+    logging::{self, run_captured},
+    scratch, signtool,
+};
+use clap::ValueEnum;
+use duct::cmd;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where the Windows SDKs put makecat.exe
+const MAKECAT_PATTERN: &str = r"C:\Program Files (x86)\Windows Kits\10\bin\*\x64\makecat.exe";
+
+/// Hash a catalog lists its files by, catalogs of SHA1 also validate on Windows before 8
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+#[value(rename_all = "UPPER")]
+pub enum CatalogHash {
+    Sha1,
+    Sha256,
+}
+
+/// A `NAME=VALUE` of --catalog-attribute, the name can't hold the `:` the catalog definition separates with
+pub fn parse_attribute(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() && !name.contains(':') && !value.contains(['\r', '\n']) => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("catalog attribute '{}' must look like NAME=VALUE, without ':' in the name", value)),
+    }
+}
+
+/// The files `covers` names for the catalog, each with the tag it is listed by: directories give every file under
+/// them, tagged by their path inside, and files their name. Sorted by tag, the catalog itself is left out
+pub fn members(catalog: &Path, covers: &[PathBuf]) -> Result<Vec<(String, PathBuf)>, Error> {
+    let catalog = std::path::absolute(catalog).unwrap_or_else(|_| catalog.to_path_buf());
+    let mut members = Vec::new();
+    for cover in covers {
+        let cover = std::path::absolute(cover).unwrap_or_else(|_| cover.clone());
+        if cover.is_file() {
+            let tag = cover.file_name().unwrap_or_default().to_string_lossy().to_string();
+            members.push((tag, cover));
+            continue;
+        }
+        if !cover.is_dir() {
+            Err(Error::Usage(format!("'{}' for the catalog doesn't exist", cover.display())))?;
+        }
+        let pattern = Path::new(&glob::Pattern::escape(&cover.to_string_lossy())).join("**").join("*");
+        for path in glob::glob(&pattern.to_string_lossy()).map_err(|err| err.to_string())?.filter_map(Result::ok) {
+            if path.is_file() && path != catalog {
+                let inside = path.strip_prefix(&cover).unwrap_or(&path);
+                let tag = inside.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>();
+                members.push((tag.join("\\"), path));
+            }
+        }
+    }
+    members.sort();
+    members.dedup_by(|a, b| a.1 == b.1);
+    if let Some(pair) = members.windows(2).find(|pair| pair[0].0.eq_ignore_ascii_case(&pair[1].0)) {
+        Err(Error::Usage(format!(
+            "'{}' and '{}' would both be listed as {} in the catalog",
+            pair[0].1.display(),
+            pair[1].1.display(),
+            pair[0].0
+        )))?;
+    }
+    if members.is_empty() {
+        Err(Error::Usage(format!("the catalog '{}' would cover no files", catalog.display())))?;
+    }
+    Ok(members)
+}
+
+/// The catalog definition makecat reads. The same files and options always give the same definition. With
+/// `file_paths` the files are listed like New-FileCatalog does, by hash with their path as an attribute, which is
+/// how Test-FileCatalog finds them
+fn definition(
+    catalog: &Path,
+    hash: CatalogHash,
+    attributes: &[(String, String)],
+    members: &[(String, PathBuf)],
+    file_paths: bool,
+) -> String {
+    let name = catalog.file_name().unwrap_or_default().to_string_lossy();
+    let result_dir = catalog.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut lines = vec![
+        "[CatalogHeader]".to_string(),
+        format!("Name={}", name),
+        format!("ResultDir={}", result_dir.display()),
+        "PublicVersion=0x0000001".to_string(),
+        "EncodingType=0x00010001".to_string(),
+    ];
+    match hash {
+        CatalogHash::Sha1 => lines.push("CatalogVersion=1".to_string()),
+        CatalogHash::Sha256 => lines.extend(["CatalogVersion=2".to_string(), "HashAlgorithms=SHA256".to_string()]),
+    }
+    // authenticated, with an ASCII name and value
+    for (index, (name, value)) in attributes.iter().enumerate() {
+        lines.push(format!("CATATTR{}=0x10010001:{}:{}", index + 1, name, value));
+    }
+    lines.extend(["".to_string(), "[CatalogFiles]".to_string()]);
+    for (tag, path) in members {
+        match file_paths {
+            true => lines.extend([
+                format!("<HASH>{}={}", tag, path.display()),
+                format!("<HASH>{}ATTR1=0x10010001:FilePath:{}", tag, tag),
+            ]),
+            false => lines.push(format!("{}={}", tag, path.display())),
+        }
+    }
+    lines.join("\r\n") + "\r\n"
+}
+
+/// The makecat to run, `path` when given, then the one next to signtool, the newest one of the Windows SDKs and
+/// one on PATH
+pub fn find_makecat(path: Option<&str>, sign_tool_path: &str) -> Result<PathBuf, Error> {
+    if let Some(path) = path {
+        return match Path::new(path).is_file() {
+            true => Ok(PathBuf::from(path)),
+            false => Err(Error::MissingDependency {
+                component: "makecat",
+                message: format!("makecat '{}' was not found", path),
+            }),
+        };
+    }
+    let executable = if cfg!(windows) { "makecat.exe" } else { "makecat" };
+    let beside_signtool = Path::new(sign_tool_path).with_file_name(executable);
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join(executable)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let Some(makecat) = [beside_signtool]
+        .into_iter()
+        .chain(signtool::newest(MAKECAT_PATTERN))
+        .chain(on_path)
+        .find(|candidate| candidate.is_file())
+    else {
+        Err(Error::MissingDependency {
+            component: "makecat",
+            message: "makecat.exe was not found, generating catalogs needs it from the Windows SDK like signtool: \
+                      install the SDK or pass --makecat-path"
+                .to_string(),
+        })?
+    };
+    debug!("generating catalogs with {}", makecat.display());
+    Ok(makecat)
+}
+
+/// Generate `catalog` over the files as they are now, replacing an earlier one. Everything but the time makecat
+/// puts in comes out the same for the same files. `file_paths` makes a catalog Test-FileCatalog can check
+pub fn generate(
+    makecat: &Path,
+    catalog: &Path,
+    hash: CatalogHash,
+    attributes: &[(String, String)],
+    covers: &[PathBuf],
+    file_paths: bool,
+) -> Result<(), Error> {
+    let members = members(catalog, covers)?;
+    let dir = scratch::dir("trusted-signing-catalog", Some(catalog))
+        .map_err(|err| format!("a temporary directory could not be created: {}", err))?;
+    let cdf = dir.path().join("catalog.cdf");
+    fs::write(&cdf, definition(catalog, hash, attributes, &members, file_paths))
+        .map_err(|err| format!("'{}' could not be written: {}", cdf.display(), err))?;
+    if catalog.exists() {
+        fs::remove_file(catalog)
+            .map_err(|err| format!("the earlier catalog '{}' could not be removed: {}", catalog.display(), err))?;
+    }
+    let output = run_captured(cmd!(makecat, "-v", &cdf)).map_err(|err| Error::MissingDependency {
+        component: "makecat",
+        message: format!("makecat '{}' could not be run: {}", makecat.display(), err),
+    })?;
+    if !output.status.success() || !catalog.is_file() {
+        Err(Error::Signing {
+            path: catalog.display().to_string(),
+            message: format!(
+                "makecat could not generate the catalog '{}', error: {}",
+                catalog.display(),
+                output.status
+            ),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    debug!("catalog {} lists {} files", catalog.display(), members.len());
+    Ok(())
+}
+
+/// Check with signtool that the signed `catalog` validates every file it was generated for
+pub fn check(sign_tool_path: &str, catalog: &Path, covers: &[PathBuf]) -> Result<(), Error> {
+    let mut invalid = Vec::new();
+    for (_, path) in members(catalog, covers)? {
+        let path = path.display().to_string();
+        let verified = run_captured(cmd!(
+            sign_tool_path,
+            "verify",
+            "/pa",
+            "/c",
+            catalog,
+            input::tool_path(&path).as_ref()
+        ));
+        match verified {
+            Ok(output) if output.status.success() => (),
+            Ok(output) => {
+                debug!("{} doesn't validate against {}: {}", path, catalog.display(), logging::failure_output(&output));
+                invalid.push(path);
+            }
+            Err(err) => Err(format!("signtool could not be run: {}", err))?,
+        }
+    }
+    if !invalid.is_empty() {
+        Err(Error::Signing {
+            path: catalog.display().to_string(),
+            message: format!("catalog '{}' doesn't validate {}", catalog.display(), invalid.join(", ")),
+            output: String::new(),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_definition() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("scripts").join("lib")).unwrap();
+        for name in ["scripts/setup.ps1", "scripts/lib/util.psm1", "scripts/data.ini", "scripts/scripts.cat"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        fs::write(dir.path().join("readme.txt"), "readme").unwrap();
+        let catalog = dir.path().join("scripts").join("scripts.cat");
+        let covers = [dir.path().join("scripts"), dir.path().join("readme.txt")];
+        let members = members(&catalog, &covers).unwrap();
+        let tags: Vec<&str> = members.iter().map(|(tag, _)| tag.as_str()).collect();
+        assert_eq!(tags, ["data.ini", "lib\\util.psm1", "readme.txt", "setup.ps1"]);
+
+        let attributes = [parse_attribute("OSAttr=2:10.0").unwrap()];
+        let cdf = definition(&catalog, CatalogHash::Sha256, &attributes, &members, false);
+        assert!(cdf.starts_with("[CatalogHeader]\r\nName=scripts.cat\r\n"));
+        assert!(cdf.contains("\r\nCatalogVersion=2\r\nHashAlgorithms=SHA256\r\nCATATTR1=0x10010001:OSAttr:2:10.0\r\n"));
+        assert!(cdf.contains(&format!("\r\nlib\\util.psm1={}\r\n", members[1].1.display())));
+        assert_eq!(cdf, definition(&catalog, CatalogHash::Sha256, &attributes, &members, false));
+        assert!(!definition(&catalog, CatalogHash::Sha1, &[], &members, false).contains("HashAlgorithms"));
+        let module = definition(&catalog, CatalogHash::Sha256, &[], &members, true);
+        let path = members[1].1.display();
+        assert!(module.contains(&format!("\r\n<HASH>lib\\util.psm1={}\r\n", path)));
+        assert!(module.contains("\r\n<HASH>lib\\util.psm1ATTR1=0x10010001:FilePath:lib\\util.psm1\r\n"));
+        assert!(parse_attribute("Os:Attr=1").is_err() && parse_attribute("=1").is_err());
+
+        fs::write(dir.path().join("setup.ps1"), "other").unwrap();
+        assert!(super::members(&catalog, &[covers[0].clone(), dir.path().join("setup.ps1")]).is_err());
+    }
+}
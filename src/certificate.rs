@@ -0,0 +1,489 @@
+use crate::{
+    authenticode,
+    der::{self, Element},
+    driver, hash, rest,
+};
+use chrono::{NaiveDateTime, SecondsFormat};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+};
+use tracing::debug;
+
+const EXTENDED_KEY_USAGE: &[u64] = &[2, 5, 29, 37];
+
+/// The short names of the attributes of a distinguished name, as Windows prints them
+pub(crate) const ATTRIBUTES: [(&str, &str); 10] = [
+    ("2.5.4.3", "CN"),
+    ("2.5.4.5", "SERIALNUMBER"),
+    ("2.5.4.6", "C"),
+    ("2.5.4.7", "L"),
+    ("2.5.4.8", "S"),
+    ("2.5.4.9", "STREET"),
+    ("2.5.4.10", "O"),
+    ("2.5.4.11", "OU"),
+    ("2.5.4.17", "PostalCode"),
+    ("1.2.840.113549.1.9.1", "E"),
+];
+
+/// The certificate a file was signed with, read from the signature embedded in it. Trusted Signing issues a new
+/// one every few days
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct SigningCertificate {
+    pub subject: String,
+    pub issuer: String,
+    /// SHA1 of the certificate, uppercase like signtool prints it
+    pub thumbprint: String,
+    /// Serial number in hex
+    pub serial: String,
+    /// RFC 3339, UTC
+    pub not_before: String,
+    /// RFC 3339, UTC
+    pub not_after: String,
+    /// The extended key usages by OID, the one under 1.3.6.1.4.1.311.97 identifies the certificate profile
+    pub eku: Vec<String>,
+}
+
+/// The text of a string value of a name
+fn text(value: &Element) -> String {
+    match value.tag {
+        der::BMP_STRING => String::from_utf16_lossy(
+            &value.content.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect::<Vec<_>>(),
+        ),
+        _ => String::from_utf8_lossy(value.content).to_string(),
+    }
+}
+
+/// A distinguished name like `CN=Fabrikam, O=Fabrikam, C=US`, most specific attribute first
+fn name(name: &Element) -> Option<String> {
+    let mut parts = Vec::new();
+    for relative in der::children(name.content)? {
+        for attribute in der::children(relative.content)? {
+            let [oid, value] = der::children(attribute.content)?[..] else {
+                return None;
+            };
+            let oid = der::oid_text(oid.content);
+            let short = ATTRIBUTES.iter().find(|(known, _)| *known == oid).map_or(oid.as_str(), |(_, short)| short);
+            let value = text(&value);
+            match value.contains([',', '+', '=', '"']) {
+                true => parts.push(format!("{}=\"{}\"", short, value.replace('"', "\"\""))),
+                false => parts.push(format!("{}={}", short, value)),
+            }
+        }
+    }
+    parts.reverse();
+    Some(parts.join(", "))
+}
+
+/// A UTCTime or GeneralizedTime as RFC 3339, to the second
+pub(crate) fn time(time: &Element) -> Option<String> {
+    let text = std::str::from_utf8(time.content).ok()?;
+    let text = match time.tag {
+        // UTCTime has two digits for the year, 50 and up are 19xx
+        0x17 if text.get(..2)? >= "50" => format!("19{}", text),
+        0x17 => format!("20{}", text),
+        // timestamp authorities add fractions of a second
+        _ => match text.split_once('.') {
+            Some((seconds, _)) => format!("{}Z", seconds),
+            None => text.to_string(),
+        },
+    };
+    let time = NaiveDateTime::parse_from_str(&text, "%Y%m%d%H%M%SZ").ok()?;
+    Some(time.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// The OIDs of the extended key usage extension among `extensions`
+fn extended_key_usages(extensions: &Element) -> Option<Vec<String>> {
+    let extensions = der::children(der::read(extensions.content)?.0.content)?;
+    for extension in extensions {
+        let fields = der::children(extension.content)?;
+        if fields.first()?.encoded != der::oid(EXTENDED_KEY_USAGE) {
+            continue;
+        }
+        let usages = der::read(fields.last()?.content)?.0;
+        return Some(der::children(usages.content)?.iter().map(|oid| der::oid_text(oid.content)).collect());
+    }
+    Some(Vec::new())
+}
+
+/// The details of a DER certificate
+pub fn details(certificate: &[u8]) -> Option<SigningCertificate> {
+    let tbs = der::children(der::read(certificate)?.0.content)?;
+    let fields = der::children(tbs.first()?.content)?;
+    // the version is the optional [0] before the serial number
+    let fields = match fields.first()?.tag == der::context(0) {
+        true => &fields[1..],
+        false => &fields[..],
+    };
+    let [serial, _, issuer, validity, subject, _, rest @ ..] = fields else {
+        return None;
+    };
+    let validity = der::children(validity.content)?;
+    let eku = match rest.iter().find(|element| element.tag == der::context(3)) {
+        Some(extensions) => extended_key_usages(extensions)?,
+        None => Vec::new(),
+    };
+    Some(SigningCertificate {
+        subject: name(subject)?,
+        issuer: name(issuer)?,
+        thumbprint: hash::hex(&Sha1::digest(certificate)).to_ascii_uppercase(),
+        serial: hash::hex(serial.content).to_ascii_uppercase(),
+        not_before: time(validity.first()?)?,
+        not_after: time(validity.get(1)?)?,
+        eku,
+    })
+}
+
+/// The PKCS#7 of every signature entry in the certificate table of a PE image, the primary signature first
+pub(crate) fn pe_signatures(path: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut file = File::open(path).map_err(|err| err.to_string())?;
+    let mut headers = Vec::new();
+    file.by_ref().take(4096).read_to_end(&mut headers).map_err(|err| err.to_string())?;
+    let Some((offset, size)) = driver::certificate_table(&headers) else {
+        return Ok(Vec::new());
+    };
+    let mut table = vec![0; size];
+    file.seek(SeekFrom::Start(offset as u64)).and_then(|_| file.read_exact(&mut table)).map_err(|err| err.to_string())?;
+    // WIN_CERTIFICATEs: their length, revision and type, 2 for PKCS#7 SignedData, then the signature, each
+    // aligned to 8 bytes
+    let mut signatures = Vec::new();
+    let mut start = 0;
+    while let Some(length) = driver::u32_at(&table, start).filter(|length| *length >= 8) {
+        let signature = table.get(start + 8..start + length);
+        if let (Some([2, 0]), Some(signature)) = (table.get(start + 6..start + 8), signature) {
+            signatures.push(signature.to_vec());
+        }
+        start += length.next_multiple_of(8);
+    }
+    if signatures.is_empty() {
+        Err("the certificate table holds no PKCS#7 signature")?;
+    }
+    Ok(signatures)
+}
+
+/// Whether the certificate of `path` is read by [of_file], the signature of other kinds of files is kept elsewhere
+pub fn is_read(path: &str) -> bool {
+    is_pkcs7(path) || rest::is_pe(path)
+}
+
+/// Catalogs and detached signatures are a PKCS#7 as a whole
+fn is_pkcs7(path: &str) -> bool {
+    driver::is_catalog(path) || path.to_ascii_lowercase().ends_with(".p7")
+}
+
+/// The PKCS#7 of the primary signature of a signed PE image, catalog or detached PKCS#7
+fn primary_signature(path: &str) -> Option<Vec<u8>> {
+    let pkcs7 = match is_pkcs7(path) {
+        true => fs::read(path).map_err(|err| err.to_string()).map(Some),
+        false => pe_signatures(path).map(|signatures| signatures.into_iter().next()),
+    };
+    let pkcs7 = match pkcs7 {
+        Ok(pkcs7) => pkcs7?,
+        Err(err) => {
+            debug!("the signature of {} could not be read: {}", path, err);
+            return None;
+        }
+    };
+    Some(pkcs7)
+}
+
+/// The certificate of the primary signature of a signed PE image, catalog or detached PKCS#7, None for other
+/// files and files without a signature
+pub fn of_file(path: &str) -> Option<SigningCertificate> {
+    let pkcs7 = primary_signature(path)?;
+    let certificate = authenticode::signing_certificate(&pkcs7).and_then(details);
+    if certificate.is_none() {
+        debug!("the signing certificate of {} could not be read", path);
+    }
+    certificate
+}
+
+/// The signing certificate of the primary signature of `path` followed by the CAs that issued it, as far as the
+/// signature includes them. Read from the same files as [of_file]
+pub fn chain_of_file(path: &str) -> Option<Vec<SigningCertificate>> {
+    let pkcs7 = primary_signature(path)?;
+    let mut chain = vec![authenticode::signing_certificate(&pkcs7).and_then(details)?];
+    let included = authenticode::certificates(&pkcs7).unwrap_or_default();
+    let included: Vec<SigningCertificate> = included.iter().filter_map(|certificate| details(certificate)).collect();
+    while let Some(last) = chain.last().filter(|last| last.issuer != last.subject) {
+        match included.iter().find(|candidate| candidate.subject == last.issuer && !chain.contains(candidate)) {
+            Some(issuer) => chain.push(issuer.clone()),
+            None => break,
+        }
+    }
+    Some(chain)
+}
+
+/// The signing certificates of every signature of a signed PE image, catalog or detached PKCS#7, nested ones
+/// included, none for other files and files without a signature
+pub fn signers_of_file(path: &str) -> Vec<SigningCertificate> {
+    let signatures = match is_pkcs7(path) {
+        true => fs::read(path).map(|pkcs7| vec![pkcs7]).map_err(|err| err.to_string()),
+        false => pe_signatures(path),
+    };
+    let signatures = signatures.unwrap_or_else(|err| {
+        debug!("the signatures of {} could not be read: {}", path, err);
+        Vec::new()
+    });
+    signatures
+        .iter()
+        .flat_map(|pkcs7| std::iter::once(pkcs7.as_slice()).chain(authenticode::nested(pkcs7)))
+        .filter_map(|pkcs7| authenticode::signing_certificate(pkcs7).and_then(details))
+        .collect()
+}
+
+/// Whether two distinguished names have the same attributes and values, like `dn:` of --expect-subject compares
+pub fn same_name(one: &str, other: &str) -> bool {
+    attributes(one) == attributes(other)
+}
+
+/// How --expect-subject matches the subject of the signing certificate, picked by a prefix of its value
+#[derive(Debug, Clone)]
+pub enum SubjectMatch {
+    /// `dn:CN=Contoso, O=Contoso, C=US`, the same attributes and values in any order, ignoring case and the
+    /// spaces around separators
+    Exact(String),
+    /// `regex:^CN=Contoso\b`, a regular expression matching the subject as printed, anywhere unless anchored
+    Pattern(regex::Regex),
+    /// Anything else, a part of the subject as printed, ignoring case
+    Contains(String),
+}
+
+impl fmt::Display for SubjectMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectMatch::Exact(dn) => write!(f, "dn:{}", dn),
+            SubjectMatch::Pattern(pattern) => write!(f, "regex:{}", pattern),
+            SubjectMatch::Contains(part) => f.write_str(part),
+        }
+    }
+}
+
+impl SubjectMatch {
+    fn matches(&self, subject: &str) -> bool {
+        match self {
+            SubjectMatch::Exact(dn) => attributes(dn) == attributes(subject),
+            SubjectMatch::Pattern(pattern) => pattern.is_match(subject),
+            SubjectMatch::Contains(part) => subject.to_lowercase().contains(&part.to_lowercase()),
+        }
+    }
+}
+
+/// Check `--expect-subject`
+pub fn parse_subject(value: &str) -> Result<SubjectMatch, String> {
+    if let Some(dn) = value.strip_prefix("dn:") {
+        return match attributes(dn).iter().all(|(key, _)| !key.is_empty()) && !dn.trim().is_empty() {
+            true => Ok(SubjectMatch::Exact(dn.trim().to_string())),
+            false => Err(format!("'{}' is not a distinguished name like CN=Contoso, O=Contoso, C=US", dn)),
+        };
+    }
+    if let Some(pattern) = value.strip_prefix("regex:") {
+        return regex::Regex::new(pattern)
+            .map(SubjectMatch::Pattern)
+            .map_err(|err| format!("'{}' is not a regular expression: {}", pattern, err));
+    }
+    match value.trim().is_empty() {
+        true => Err("the expected subject is empty".to_string()),
+        false => Ok(SubjectMatch::Contains(value.to_string())),
+    }
+}
+
+/// Check `--expect-thumbprint`: the SHA-1 of a certificate in hex, spaces and colons like the certificate dialog
+/// and openssl print them are dropped
+pub fn parse_thumbprint(value: &str) -> Result<String, String> {
+    let hex: String = value.chars().filter(|c| !matches!(c, ' ' | ':')).collect();
+    match hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Ok(hex.to_ascii_uppercase()),
+        false => Err(format!("'{}' is not a SHA-1 thumbprint of 40 hex digits", value)),
+    }
+}
+
+/// The attributes of a distinguished name in the order written, their short names uppercase. Values may be quoted
+/// like Windows prints them or escaped with backslashes like RFC 4514 writes them
+pub(crate) fn parts(dn: &str) -> Vec<(String, String)> {
+    let mut parts = vec![String::new()];
+    let (mut quoted, mut escaped) = (false, false);
+    for c in dn.chars() {
+        let part = parts.last_mut().expect("one part at least");
+        match c {
+            _ if escaped => {
+                part.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ',' | '+' | ';' if !quoted => parts.push(String::new()),
+            _ => part.push(c),
+        }
+    }
+    parts
+        .iter()
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| match part.split_once('=') {
+            Some((key, value)) => (key.trim().to_ascii_uppercase(), value.trim().to_string()),
+            None => (String::new(), part.trim().to_string()),
+        })
+        .collect()
+}
+
+/// The attributes of a distinguished name with their values lowercase, sorted
+fn attributes(dn: &str) -> Vec<(String, String)> {
+    let mut attributes: Vec<(String, String)> =
+        parts(dn).into_iter().map(|(key, value)| (key, value.to_lowercase())).collect();
+    attributes.sort();
+    attributes
+}
+
+/// The certificate a signed file has to be signed with, by --expect-subject and --expect-thumbprint
+#[derive(Debug, Clone, Default)]
+pub struct Expected {
+    pub subject: Option<SubjectMatch>,
+    /// Uppercase, any of them
+    pub thumbprints: Vec<String>,
+}
+
+impl Expected {
+    pub fn is_empty(&self) -> bool {
+        self.subject.is_none() && self.thumbprints.is_empty()
+    }
+
+    /// Check the certificate `path` was signed with, the message of a mismatch names the actual subject and
+    /// thumbprint
+    pub fn check(&self, path: &str, certificate: &SigningCertificate) -> Result<(), String> {
+        let actual = || format!("{} (thumbprint {})", certificate.subject, certificate.thumbprint);
+        if let Some(subject) = self.subject.as_ref().filter(|subject| !subject.matches(&certificate.subject)) {
+            Err(format!("'{}' was signed by {}, not the --expect-subject '{}'", path, actual(), subject))?;
+        }
+        if !self.thumbprints.is_empty() && !self.thumbprints.contains(&certificate.thumbprint) {
+            Err(format!(
+                "'{}' was signed by {}, not the --expect-thumbprint {}",
+                path,
+                actual(),
+                self.thumbprints.join(", ")
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// A signer whose files --exclude-signed-by keeps from being signed again
+#[derive(Debug, Clone)]
+pub enum Protected {
+    /// The SHA-1 thumbprint of its certificate, uppercase
+    Thumbprint(String),
+    /// Its subject, matched like --expect-subject
+    Subject(SubjectMatch),
+}
+
+impl fmt::Display for Protected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protected::Thumbprint(thumbprint) => f.write_str(thumbprint),
+            Protected::Subject(subject) => subject.fmt(f),
+        }
+    }
+}
+
+impl Protected {
+    pub fn matches(&self, certificate: &SigningCertificate) -> bool {
+        match self {
+            Protected::Thumbprint(thumbprint) => *thumbprint == certificate.thumbprint,
+            Protected::Subject(subject) => subject.matches(&certificate.subject),
+        }
+    }
+}
+
+/// Check `--exclude-signed-by`: a thumbprint when it is 40 hex digits, otherwise a subject like --expect-subject
+pub fn parse_protected(value: &str) -> Result<Protected, String> {
+    match parse_thumbprint(value) {
+        Ok(thumbprint) => Ok(Protected::Thumbprint(thumbprint)),
+        Err(_) => parse_subject(value).map(Protected::Subject),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certificate_details() {
+        let attribute = |arcs: &[u64], value: Vec<u8>| der::set_of(vec![der::sequence(&[&der::oid(arcs), &value])]);
+        let printable = |text: &str| der::tlv(0x13, text.as_bytes());
+        let subject = der::sequence(&[
+            &attribute(&[2, 5, 4, 6], printable("US")),
+            &attribute(&[2, 5, 4, 10], der::tlv(0x0c, b"Fabrikam, Inc.")),
+            &attribute(&[2, 5, 4, 3], der::bmp_string("Fabrikam")),
+        ]);
+        let issuer = der::sequence(&[&attribute(&[2, 5, 4, 3], printable("Microsoft ID Verified CS EOC CA 01"))]);
+        let code_signing = der::oid(&[1, 3, 6, 1, 5, 5, 7, 3, 3]);
+        let usages = der::sequence(&[&code_signing, &der::oid(&[1, 3, 6, 1, 4, 1, 311, 97, 1])]);
+        let extension = der::sequence(&[&der::oid(EXTENDED_KEY_USAGE), &der::tlv(der::OCTET_STRING, &usages)]);
+        let tbs = der::sequence(&[
+            &der::tlv(der::context(0), &der::small_integer(2)),
+            &der::tlv(der::INTEGER, &[0x33, 0x00, 0x0a]),
+            &der::sequence(&[]),
+            &issuer,
+            &der::sequence(&[&der::tlv(0x17, b"251014100000Z"), &der::tlv(0x18, b"20251017100000Z")]),
+            &subject,
+            &der::sequence(&[]),
+            &der::tlv(der::context(3), &der::sequence(&[&extension])),
+        ]);
+        let certificate = der::sequence(&[&tbs, &der::sequence(&[]), &der::tlv(der::BIT_STRING, &[0])]);
+
+        let details = details(&certificate).unwrap();
+        assert_eq!(details.subject, "CN=Fabrikam, O=\"Fabrikam, Inc.\", C=US");
+        assert_eq!(details.issuer, "CN=Microsoft ID Verified CS EOC CA 01");
+        assert_eq!(details.thumbprint, hash::hex(&Sha1::digest(&certificate)).to_ascii_uppercase());
+        assert_eq!(details.serial, "33000A");
+        assert_eq!(details.not_before, "2025-10-14T10:00:00Z");
+        assert_eq!(details.not_after, "2025-10-17T10:00:00Z");
+        assert_eq!(details.eku, ["1.3.6.1.5.5.7.3.3", "1.3.6.1.4.1.311.97.1"]);
+        assert_eq!(of_file("Cargo.toml"), None);
+    }
+
+    #[test]
+    fn expected_certificates() {
+        let certificate = SigningCertificate {
+            subject: "CN=Fabrikam, O=\"Fabrikam, Inc.\", L=Redmond, C=US".to_string(),
+            issuer: "CN=Microsoft ID Verified CS EOC CA 01".to_string(),
+            thumbprint: "3F9001EA83C560D712C24CF213C3D312CB3BFF51".to_string(),
+            serial: "33000A".to_string(),
+            not_before: "2025-10-14T10:00:00Z".to_string(),
+            not_after: "2025-10-17T10:00:00Z".to_string(),
+            eku: Vec::new(),
+        };
+        let check = |subject: &str| {
+            let expected = Expected { subject: Some(parse_subject(subject).unwrap()), thumbprints: Vec::new() };
+            expected.check("a.exe", &certificate)
+        };
+        check("fabrikam, inc.").unwrap();
+        check("dn:C=US,L=Redmond, O=Fabrikam\\, Inc., cn=fabrikam").unwrap();
+        check("regex:^CN=Fabrikam,").unwrap();
+        assert!(check("dn:CN=Fabrikam, C=US").is_err());
+        assert!(check("regex:^CN=Fabrikam Test").is_err());
+        assert_eq!(
+            check("Contoso").unwrap_err(),
+            "'a.exe' was signed by CN=Fabrikam, O=\"Fabrikam, Inc.\", L=Redmond, C=US \
+             (thumbprint 3F9001EA83C560D712C24CF213C3D312CB3BFF51), not the --expect-subject 'Contoso'"
+        );
+        assert!(parse_subject("dn:Fabrikam").is_err());
+        assert!(parse_subject("regex:(").is_err());
+
+        let thumbprint = parse_thumbprint("3f 90 01 ea 83 c5 60 d7 12 c2 4c f2 13 c3 d3 12 cb 3b ff 51").unwrap();
+        assert_eq!(thumbprint, certificate.thumbprint);
+        assert!(parse_thumbprint("3F9001EA").is_err());
+        let expected = Expected { subject: None, thumbprints: vec!["0".repeat(40), thumbprint] };
+        expected.check("a.exe", &certificate).unwrap();
+        let expected = Expected { subject: None, thumbprints: vec!["0".repeat(40)] };
+        assert!(expected.check("a.exe", &certificate).unwrap_err().contains("thumbprint 3F9001EA"));
+
+        let protects = |value: &str| parse_protected(value).unwrap().matches(&certificate);
+        assert!(protects("FABRIKAM") && protects("3f:90:01:ea:83:c5:60:d7:12:c2:4c:f2:13:c3:d3:12:cb:3b:ff:51"));
+        assert!(!protects("Microsoft Corporation") && !protects(&"0".repeat(40)));
+        assert!(parse_protected(" ").is_err());
+    }
+}
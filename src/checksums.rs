@@ -0,0 +1,145 @@
+//! A SHA256SUMS file of the signed files, written once signing changed their bytes
+
+use crate::{
+    error::Error,
+    hash,
+    report::{FileReport, Status},
+};
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// How each line of a checksums file is written
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    /// `<hex>  <path>`, what `sha256sum -c` checks
+    #[default]
+    Gnu,
+    /// `SHA256 (<path>) = <hex>`, what `shasum -c` and BSD `sha256 -c` check
+    Bsd,
+}
+
+/// What --checksums-file wrote
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct Checksums {
+    pub path: String,
+    /// How many signed files it lists
+    pub files: usize,
+    /// The files that failed or were skipped, left out
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub omitted: Vec<String>,
+}
+
+/// `path` relative to `base`, both made absolute first. A path on another drive
+/// than `base` stays absolute
+fn relative(path: &Path, base: &Path) -> PathBuf {
+    let (Ok(path), Ok(base)) = (std::path::absolute(path), std::path::absolute(base)) else {
+        return path.to_path_buf();
+    };
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return path;
+    }
+    while let (Some(one), Some(other)) = (path_components.peek(), base_components.peek()) {
+        if one != other {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+    let up = base_components.filter(|component| matches!(component, Component::Normal(_))).map(|_| "..".into());
+    up.chain(path_components.map(|component| component.as_os_str().to_os_string())).collect()
+}
+
+/// The line of a file, escaped like sha256sum does a name with a backslash or a line break
+fn line(format: Format, name: &str, sha256: &str) -> String {
+    match format {
+        Format::Gnu if name.contains(['\\', '\n']) => {
+            format!("\\{}  {}\n", sha256, name.replace('\\', "\\\\").replace('\n', "\\n"))
+        }
+        Format::Gnu => format!("{}  {}\n", sha256, name),
+        Format::Bsd => format!("SHA256 ({}) = {}\n", name, sha256),
+    }
+}
+
+/// Write the checksums of the signed `files` to `path`, with their paths relative to `base` or to the directory
+/// of `path`. The file is written next to it and renamed, an upload step never sees half of it
+pub fn write(path: &Path, base: Option<&Path>, format: Format, files: &[FileReport]) -> Result<Checksums, Error> {
+    let failed = |err: io::Error| format!("the checksums file '{}' could not be written: {}", path.display(), err);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let base = base.unwrap_or(dir);
+    let mut content = String::new();
+    let mut omitted = Vec::new();
+    for file in files {
+        if file.status != Status::Signed {
+            omitted.push(file.path.clone());
+            continue;
+        }
+        let sha256 = match &file.sha256_after {
+            Some(sha256) => sha256.clone(),
+            None => {
+                hash::sha256_file(&file.path).map_err(|err| format!("'{}' could not be hashed: {}", file.path, err))?
+            }
+        };
+        let name = relative(Path::new(&file.path), base).display().to_string();
+        // a backslash is part of the name elsewhere
+        let name = match cfg!(windows) {
+            true => name.replace('\\', "/"),
+            false => name,
+        };
+        content.push_str(&line(format, &name, &sha256));
+    }
+    fs::create_dir_all(dir).map_err(failed)?;
+    let mut temporary = tempfile::NamedTempFile::new_in(dir).map_err(failed)?;
+    io::Write::write_all(&mut temporary, content.as_bytes()).map_err(failed)?;
+    temporary.persist(path).map_err(|err| failed(err.error))?;
+    Ok(Checksums {
+        path: path.display().to_string(),
+        files: files.len() - omitted.len(),
+        omitted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Report;
+
+    #[test]
+    fn signed_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("dist/x64")).unwrap();
+        let [app, setup, lib] = ["dist/app.exe", "dist/x64/setup.msi", "lib.dll"]
+            .map(|name| dir.path().join(name).display().to_string());
+        for file in [&app, &setup, &lib] {
+            fs::write(file, file.as_bytes()).unwrap();
+        }
+        let mut report = Report::new(&[app.clone(), setup.clone(), lib.clone()]);
+        report.files[0].status = Status::Signed;
+        report.files[0].sha256_after = Some("a".repeat(64));
+        report.files[1].status = Status::Signed;
+        report.files[2].status = Status::Failed;
+
+        let path = dir.path().join("dist/SHA256SUMS");
+        let written = write(&path, None, Format::Gnu, &report.files).unwrap();
+        assert_eq!(written.files, 2);
+        assert_eq!(written.omitted, std::slice::from_ref(&lib));
+        let setup_sha256 = hash::sha256_file(&setup).unwrap();
+        let expected = format!("{}  app.exe\n{}  x64/setup.msi\n", "a".repeat(64), setup_sha256);
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+        report.files[2].status = Status::Signed;
+        write(&path, Some(&dir.path().join("dist/x64")), Format::Bsd, &report.files).unwrap();
+        let lines: Vec<String> = fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+        assert_eq!(lines[0], format!("SHA256 (../app.exe) = {}", "a".repeat(64)));
+        assert_eq!(lines[1], format!("SHA256 (setup.msi) = {}", setup_sha256));
+        assert!(lines[2].starts_with("SHA256 (../../lib.dll) = "));
+
+        assert_eq!(line(Format::Gnu, "a\\b", "00"), "\\00  a\\\\b\n");
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    error::Error,
+    events::{Event, Sink},
+    logging, redact,
+    report::Status,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by --ci, or by CI for the commands without the sign arguments: nothing waits for input
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Parse --ci and CI: anything but false, no, off, 0 or nothing turns it on, some CI systems set CI to their name
+pub fn parse_enabled(value: &str) -> Result<bool, String> {
+    let value = value.trim().to_ascii_lowercase();
+    Ok(!["", "false", "no", "off", "0"].contains(&value.as_str()))
+}
+
+/// Whether CI turns the CI mode on
+pub fn from_env() -> bool {
+    std::env::var("CI").is_ok_and(|value| parse_enabled(&value) == Ok(true))
+}
+
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::SeqCst);
+}
+
+/// Whether the run is in CI mode, see [`set_strict`]
+pub fn strict() -> bool {
+    STRICT.load(Ordering::SeqCst)
+}
+
+/// The error of a prompt in CI mode, which would wait for an answer that never comes
+pub fn prompt_refused(instead: &str) -> Error {
+    Error::Usage(format!("--ci never waits for input, {}", instead))
+}
+
+/// CI system to format output for
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiFormat {
+    /// GitHub Actions workflow commands
+    Github,
+    /// Azure DevOps logging commands
+    Azdo,
+    /// TeamCity service messages
+    Teamcity,
+    /// Plain output, even when a CI system is detected
+    #[value(name = "none")]
+    Disabled,
+}
+
+impl CiFormat {
+    /// Pick the format from the environment when none was given
+    pub fn detect(format: Option<CiFormat>) -> CiFormat {
+        Self::detect_with(format, |name| std::env::var(name).ok())
+    }
+
+    /// Pick the format from the variables `var` reads. Azure DevOps sets TF_BUILD to True, any other value is not
+    /// Azure DevOps, whose logging commands would print the secrets they mask to anywhere else
+    fn detect_with(format: Option<CiFormat>, var: impl Fn(&str) -> Option<String>) -> CiFormat {
+        match format {
+            Some(format) => format,
+            None if var("GITHUB_ACTIONS").is_some_and(|value| value == "true") => CiFormat::Github,
+            None if var("TF_BUILD").is_some_and(|value| value.eq_ignore_ascii_case("true")) => CiFormat::Azdo,
+            None if var("TEAMCITY_VERSION").is_some() => CiFormat::Teamcity,
+            None => CiFormat::Disabled,
+        }
+    }
+
+    /// The sink writing this format, if any
+    pub fn sink(self) -> Option<Box<dyn Sink>> {
+        match self {
+            CiFormat::Github => Some(Box::new(GitHub)),
+            CiFormat::Azdo => Some(Box::new(AzureDevOps::default())),
+            CiFormat::Teamcity => Some(Box::new(TeamCity)),
+            CiFormat::Disabled => None,
+        }
+    }
+}
+
+/// Link to the CI run this invocation is part of, when the environment tells
+pub fn run_url() -> Option<String> {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if let (Some(server), Some(repository), Some(run_id)) =
+        (var("GITHUB_SERVER_URL"), var("GITHUB_REPOSITORY"), var("GITHUB_RUN_ID"))
+    {
+        return Some(format!("{}/{}/actions/runs/{}", server, repository, run_id));
+    }
+    if let (Some(collection), Some(project), Some(build_id)) =
+        (var("SYSTEM_COLLECTIONURI"), var("SYSTEM_TEAMPROJECT"), var("BUILD_BUILDID"))
+    {
+        return Some(format!("{}{}/_build/results?buildId={}", collection, project, build_id));
+    }
+    var("BUILD_URL")
+}
+
+/// What the environment tells about the CI run, for notifications outside of it
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub system: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// The branch or tag built
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Who started the run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+}
+
+/// The metadata of the CI run by the variables of GitHub Actions, Azure DevOps or TeamCity, None outside of them
+pub fn metadata() -> Option<Metadata> {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let run_url = run_url();
+    match CiFormat::detect(None) {
+        CiFormat::Github => Some(Metadata {
+            system: "github",
+            run_url,
+            repository: var("GITHUB_REPOSITORY"),
+            git_ref: var("GITHUB_REF"),
+            commit: var("GITHUB_SHA"),
+            pipeline: var("GITHUB_WORKFLOW"),
+            run_id: var("GITHUB_RUN_ID"),
+            actor: var("GITHUB_ACTOR"),
+        }),
+        CiFormat::Azdo => Some(Metadata {
+            system: "azure-devops",
+            run_url,
+            repository: var("BUILD_REPOSITORY_NAME"),
+            git_ref: var("BUILD_SOURCEBRANCH"),
+            commit: var("BUILD_SOURCEVERSION"),
+            pipeline: var("BUILD_DEFINITIONNAME"),
+            run_id: var("BUILD_BUILDID"),
+            actor: var("BUILD_REQUESTEDFOR"),
+        }),
+        CiFormat::Teamcity => Some(Metadata {
+            system: "teamcity",
+            run_url,
+            pipeline: var("TEAMCITY_BUILDCONF_NAME"),
+            run_id: var("BUILD_NUMBER"),
+            ..Metadata::default()
+        }),
+        CiFormat::Disabled => None,
+    }
+}
+
+/// Escape the message of a workflow command
+fn escape_data(value: &str) -> String {
+    value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escape a property of a workflow command
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// GitHub Actions workflow commands, written to stderr which the runner parses as well
+struct GitHub;
+
+/// The commands registering every secret with the runner of `format`, one per secret
+fn mask_commands(format: CiFormat) -> Vec<String> {
+    let command = |secret: &str| match format {
+        CiFormat::Github => Some(format!("::add-mask::{}", escape_data(secret))),
+        CiFormat::Azdo => Some(format!("##vso[task.setsecret]{}", escape_azdo_data(secret))),
+        CiFormat::Teamcity | CiFormat::Disabled => None,
+    };
+    logging::secrets().iter().filter_map(|secret| command(secret)).collect()
+}
+
+impl GitHub {
+    fn command(&self, command: &str) {
+        // not masked, secrets have to reach the runner to be registered
+        eprintln!("{}", redact::apply(command));
+    }
+}
+
+impl Sink for GitHub {
+    fn emit(&mut self, _seq: u64, event: &Event) {
+        match event {
+            Event::RunStarted { .. } => {
+                for command in mask_commands(CiFormat::Github) {
+                    self.command(&command);
+                }
+            }
+            Event::FileStarted { path, .. } => self.command(&format!("::group::Signing {}", escape_data(path))),
+            Event::FileFinished { file } => {
+                self.command("::endgroup::");
+                match file.status {
+                    Status::Failed => self.command(&format!(
+                        "::error file={},title=Signing failed::{}",
+                        escape_property(&file.path),
+                        escape_data(file.reason().unwrap_or("signing failed"))
+                    )),
+                    Status::Skipped => self.command(&format!(
+                        "::warning file={},title=Skipped::{}",
+                        escape_property(&file.path),
+                        escape_data(file.skip_reason.as_deref().unwrap_or("skipped"))
+                    )),
+                    Status::Signed | Status::NotAttempted => (),
+                }
+            }
+            Event::PhaseStarted { .. }
+            | Event::PhaseFinished { .. }
+            | Event::DownloadProgress { .. }
+            | Event::RunFinished { .. } => (),
+        }
+    }
+}
+
+/// Escape the message of a logging command
+fn escape_azdo_data(value: &str) -> String {
+    value.replace('%', "%AZP25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escape a property of a logging command
+fn escape_azdo_property(value: &str) -> String {
+    escape_azdo_data(value).replace(';', "%3B").replace(']', "%5D")
+}
+
+/// Azure DevOps logging commands, written to stderr which the agent parses like stdout
+/// so reports on stdout stay machine readable
+#[derive(Default)]
+struct AzureDevOps {
+    total: usize,
+    finished: usize,
+}
+
+impl AzureDevOps {
+    fn command(&self, command: &str) {
+        // not masked, secrets have to reach the runner to be registered
+        eprintln!("{}", redact::apply(command));
+    }
+}
+
+impl Sink for AzureDevOps {
+    fn emit(&mut self, _seq: u64, event: &Event) {
+        match event {
+            Event::RunStarted { config } => {
+                self.total = config.files.len();
+                for command in mask_commands(CiFormat::Azdo) {
+                    self.command(&command);
+                }
+            }
+            Event::FileFinished { file } => {
+                self.finished += 1;
+                match file.status {
+                    Status::Failed => self.command(&format!(
+                        "##vso[task.logissue type=error;sourcepath={}]{}",
+                        escape_azdo_property(&file.path),
+                        escape_azdo_data(file.reason().unwrap_or("signing failed"))
+                    )),
+                    Status::Skipped => self.command(&format!(
+                        "##vso[task.logissue type=warning;sourcepath={}]{}",
+                        escape_azdo_property(&file.path),
+                        escape_azdo_data(file.skip_reason.as_deref().unwrap_or("skipped"))
+                    )),
+                    Status::Signed | Status::NotAttempted => (),
+                }
+                if self.total > 1 {
+                    self.command(&format!(
+                        "##vso[task.setprogress value={};]Signing",
+                        self.finished * 100 / self.total
+                    ));
+                }
+            }
+            Event::RunFinished { report } => self.command(&format!(
+                "##vso[task.setvariable variable=signedFiles;isOutput=true]{}",
+                report.summary.signed
+            )),
+            Event::FileStarted { .. }
+            | Event::PhaseStarted { .. }
+            | Event::PhaseFinished { .. }
+            | Event::DownloadProgress { .. } => (),
+        }
+    }
+}
+
+/// Escape a value of a service message
+fn escape_teamcity(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            '\u{0085}' => escaped.push_str("|x"),
+            '\u{2028}' => escaped.push_str("|l"),
+            '\u{2029}' => escaped.push_str("|p"),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+/// TeamCity service messages, written to stdout since that's the only stream TeamCity parses
+struct TeamCity;
+
+impl TeamCity {
+    fn message(&self, name: &str, attributes: &[(&str, &str)]) {
+        let attributes: String = attributes
+            .iter()
+            .map(|(key, value)| format!(" {}='{}'", key, escape_teamcity(value)))
+            .collect();
+        println!("{}", redact::apply(&format!("##teamcity[{}{}]", name, attributes)));
+    }
+
+    fn statistic(&self, key: &str, value: impl ToString) {
+        self.message(
+            "buildStatisticValue",
+            &[("key", &format!("trustedSigning.{}", key)), ("value", &value.to_string())],
+        );
+    }
+}
+
+impl Sink for TeamCity {
+    fn emit(&mut self, _seq: u64, event: &Event) {
+        match event {
+            Event::PhaseStarted { phase } => self.message("blockOpened", &[("name", phase)]),
+            Event::PhaseFinished { phase, .. } => self.message("blockClosed", &[("name", phase)]),
+            Event::FileStarted { path, .. } => self.message("blockOpened", &[("name", &format!("sign {}", path))]),
+            Event::FileFinished { file } => {
+                self.message("blockClosed", &[("name", &format!("sign {}", file.path))]);
+                if file.status == Status::Failed {
+                    let description = format!("Signing failed: {}: {}", file.path, file.reason().unwrap_or(""));
+                    self.message("buildProblem", &[("description", &description)]);
+                }
+            }
+            Event::RunFinished { report } => {
+                self.statistic("signed", report.summary.signed);
+                self.statistic("failed", report.summary.failed);
+                self.statistic("skipped", report.summary.skipped);
+                self.statistic("notAttempted", report.summary.not_attempted);
+                self.statistic("durationMs", report.summary.duration_ms);
+                for stats in report.metrics.stats() {
+                    self.statistic(&format!("{}.totalMs", stats.phase), stats.total_ms);
+                }
+            }
+            Event::RunStarted { .. } | Event::DownloadProgress { .. } => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ci_variable() {
+        assert!(["true", "1", "woodpecker", " Yes "].iter().all(|value| parse_enabled(value) == Ok(true)));
+        assert!(["false", "0", "", "OFF"].iter().all(|value| parse_enabled(value) == Ok(false)));
+    }
+
+    #[test]
+    fn detected_formats() {
+        let detect = |name: &str, value: &str| {
+            let (name, value) = (name.to_string(), value.to_string());
+            CiFormat::detect_with(None, move |var| (var == name).then(|| value.clone()))
+        };
+        assert_eq!(detect("TF_BUILD", "True"), CiFormat::Azdo);
+        assert_eq!(detect("TF_BUILD", "true"), CiFormat::Azdo);
+        assert_eq!(detect("TF_BUILD", ""), CiFormat::Disabled);
+        assert_eq!(detect("TF_BUILD", "False"), CiFormat::Disabled);
+        assert_eq!(detect("GITHUB_ACTIONS", "true"), CiFormat::Github);
+        assert_eq!(detect("GITHUB_ACTIONS", ""), CiFormat::Disabled);
+        assert_eq!(detect("TEAMCITY_VERSION", "2024.07"), CiFormat::Teamcity);
+        assert_eq!(CiFormat::detect_with(Some(CiFormat::Azdo), |_| None), CiFormat::Azdo);
+    }
+
+    #[test]
+    fn secrets_masked_once() {
+        // registered early by the app and again by the signer
+        logging::register_secret("Zq9-twice-secret-Zq9");
+        logging::register_secret("Zq9-twice-secret-Zq9");
+        for (format, line) in [
+            (CiFormat::Github, "::add-mask::Zq9-twice-secret-Zq9"),
+            (CiFormat::Azdo, "##vso[task.setsecret]Zq9-twice-secret-Zq9"),
+        ] {
+            let commands = mask_commands(format);
+            assert_eq!(commands.iter().filter(|command| *command == line).count(), 1, "{:?}", commands);
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_data("50% done\r\nnext"), "50%25 done%0D%0Anext");
+        assert_eq!(escape_property(r"C:\out\app,1.exe"), r"C%3A\out\app%2C1.exe");
+        assert_eq!(escape_azdo_data("50% done\n"), "50%AZP25 done%0A");
+        assert_eq!(escape_azdo_property("a;b]c"), "a%3Bb%5Dc");
+        assert_eq!(escape_teamcity("it's [a|b]\r\n"), "it|'s |[a||b|]|r|n");
+    }
+}
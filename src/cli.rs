@@ -0,0 +1,2087 @@
+#[cfg(feature = "download")]
+use crate::feed;
+#[cfg(feature = "otlp")]
+use crate::otlp;
+use crate::signtool::{self, Digest, P7Content, TIMESTAMP_URL};
+#[cfg(feature = "webhook")]
+use crate::webhook::{self, WebhookOn};
+use crate::{
+    arch::{Arch, Subsystem},
+    catalog::{self, CatalogHash},
+    certificate::{self, SubjectMatch},
+    config, input,
+    pin::{self, PinMode},
+    route::{self, Profile, Route, Target},
+    schema, scratch,
+};
+use crate::{
+    azure, bench, checksums,
+    ci::{self, CiFormat},
+    color::ColorChoice,
+    endpoint, error,
+    events::EventFormat,
+    logging::{LogFile, LogLevel},
+    report::{OutputFormat, ReportFormat},
+};
+use crate::{
+    credman,
+    error::Error,
+    hooks::{self, PreSignFailure},
+    journal, keyvault, manifest, principal, rest,
+    revocation::Offline,
+    signer::Backend,
+    tauri, testcert, verify,
+};
+use clap::{
+    builder::{ArgPredicate::IsPresent, BoolishValueParser},
+    ArgAction, ArgGroup, ArgMatches, CommandFactory, Parser, Subcommand,
+};
+use clap_complete::Shell;
+use std::{ffi::OsString, net::SocketAddr, path::PathBuf, time::Duration};
+use tracing::debug;
+
+/// Simple CLI tool to sign files with Trusted Signing
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = "Without a subcommand the arguments are passed to `sign`, e.g. `trusted-signing-cli -e <url> -a <account> -c <profile> file.exe`",
+    after_long_help = format!(
+        "Without a subcommand the arguments are passed to `sign`, e.g. `trusted-signing-cli -e <url> -a <account> -c <profile> file.exe`\n\n{}",
+        error::exit_codes_help()
+    )
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    // only sign needs files, the other commands taking the sign arguments find them or don't sign any
+    #[command(mut_arg("file", |arg| {
+        let sources = [
+            "tauri", "manifest", "driver_package", "squirrel_releases", "catalog", "pipe", "file_from_env", "then_sign",
+            "ps_module", "job",
+        ];
+        arg.required_unless_present_any(sources)
+    }))]
+    Sign(Box<Args>),
+
+    /// Sign the bins and cdylibs Cargo built, `cargo trusted-sign` runs this
+    #[command(mut_arg("file", |arg| arg.help("More file(s) to sign along with the Cargo artifacts")))]
+    Cargo(Box<CargoArgs>),
+
+    /// Print the signtool arguments for signing with Trusted Signing, for tools that run signtool themselves.
+    /// Takes the same arguments as sign, only the endpoint, account and certificate profile are required
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    PrintSigntoolArgs(Box<SigntoolArgs>),
+
+    /// Sign files other machines send over HTTP, with one long-lived login.
+    /// Takes the same arguments as sign, without files
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    Serve(Box<ServeArgs>),
+
+    /// Log in once and sign the files sign-via-daemon sends over a named pipe (a socket in the config
+    /// directory off Windows) of the current user, for tools that sign one file per call. Requests are taken at
+    /// the same time but queued for its one signer, which signs their files one after another. Takes the same
+    /// arguments as sign, without files
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    Daemon(Box<DaemonArgs>),
+
+    /// Sign files with the running daemon, or like sign when none is running. The daemon signs with its own
+    /// arguments: -e, -a, -c and the options of how files are signed, like --fd, --tr or --description, are
+    /// checked against them and the files refused when one differs. Takes the same arguments as sign
+    #[command(mut_arg("file", |arg| arg.required(true)))]
+    SignViaDaemon(Box<Args>),
+
+    /// Create a config profile interactively, or from flags with --non-interactive
+    Init(Box<InitArgs>),
+
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Print the principal a sign with these arguments would use and which credential it came from, after logging
+    /// in or reusing the session. Takes the same arguments as sign, only the credentials are required
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    Whoami(Box<Args>),
+
+    /// List the certificate profiles of the account with their type, status and validity, after logging in or
+    /// reusing the session. Takes the same arguments as sign, only the credentials and the account are required
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    ListProfiles(Box<Args>),
+
+    /// List the code signing accounts the principal can read, with the -a and -e values of each, after logging in
+    /// or reusing the session. Takes the same arguments as sign, only the credentials are required
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    ListAccounts(Box<ListAccountsArgs>),
+
+    /// Print the cached signing client, the signtool a sign would run and the azure cli session with when its token
+    /// expires. Takes the same arguments as sign, none of them are required
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    Status(Box<Args>),
+
+    /// Sign and verify a throwaway executable with these arguments to check a runner end to end: the signing
+    /// client, the login, signing and verifying, each reported as passed or failed. Uses one signature of the
+    /// account's quota. Takes the same arguments as sign, without files
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    SmokeTest(Box<Args>),
+
+    /// Sign --count throwaway executables with these arguments, --jobs at once, and report the latency
+    /// percentiles, throughput, retries and throttling of the service. Uses --count signatures of the account's
+    /// quota, so it needs --yes. Takes the same arguments as sign, without files
+    #[command(mut_arg("file", |arg| arg.hide(true)))]
+    Bench(Box<BenchArgs>),
+
+    /// List the Trusted Signing regions with their endpoint URLs, -e takes the code or the URL
+    Regions(RegionsArgs),
+
+    /// Remove the signing client, the session marker, kept metadata and the config of this tool to start over.
+    /// The Azure CLI session is only logged out with --include-azure-session
+    Purge(PurgeArgs),
+
+    /// Print the signatures the account of -a used this month as Azure Monitor counted them, against what its
+    /// plan includes, after logging in or reusing the session. Takes the same arguments as sign, only the
+    /// credentials and the account are required. `quota show` and `quota reset` keep to the count of this machine
+    #[command(
+        mut_arg("file", |arg| arg.hide(true)),
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
+    Quota(Box<QuotaArgs>),
+
+    /// Check the Authenticode signatures of files locally, without credentials or the signing client
+    Verify(Box<VerifyArgs>),
+
+    /// Add RFC 3161 timestamps to files signed without one, without signing them again, credentials or the signing
+    /// client
+    Timestamp(Box<TimestampArgs>),
+
+    /// Remove the signatures of PE images and scripts, to sign them again from scratch, without credentials or the
+    /// signing client
+    Unsign(Box<UnsignArgs>),
+
+    /// Print the signatures embedded in files as JSON: digests, certificate chains, timestamps and nesting. Read
+    /// offline, without credentials, signtool or the signing client
+    Inspect(Box<InspectArgs>),
+
+    /// Create a self-signed code signing certificate in a password protected PFX, for --backend pfx to sign pull
+    /// request builds with. Its subject gets an OU marking it as for testing only, Windows trusts it nowhere it
+    /// isn't installed as a root
+    GenerateTestCert(Box<GenerateTestCertArgs>),
+
+    /// Print the JSON Schema of the JSON report, of a line of the NDJSON events or of a --job, to validate them or
+    /// generate types from. Its $id names the version of the format
+    Schema {
+        /// Format to print the schema of
+        #[arg(value_enum)]
+        format: schema::Format,
+    },
+
+    /// Replace this executable with the latest release, or another one with --version
+    #[cfg(feature = "download")]
+    SelfUpdate(Box<SelfUpdateArgs>),
+
+    /// Print a shell completion script
+    Completions {
+        /// Shell to generate the script for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the settings a sign with these arguments would use and where each value came from.
+    /// Takes the same arguments as sign, none of them are required
+    Show(Box<Args>),
+
+    /// Store a client secret in Windows Credential Manager for --azure-client-secret-credman, read from a
+    /// prompt or stdin
+    SetSecret(SetSecretArgs),
+}
+
+/// Arguments of `quota`
+#[derive(clap::Args, Debug)]
+pub struct QuotaArgs {
+    #[command(subcommand)]
+    pub command: Option<QuotaCommand>,
+
+    /// Not given with a subcommand
+    #[command(flatten)]
+    pub sign: Option<Args>,
+
+    /// Exit with code 8 when the account used N signatures or more this month, for pipelines to alert on
+    #[arg(long, value_name = "N")]
+    pub warn_at: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QuotaCommand {
+    /// Print this month's count of every account, made by this machine's runs only, an estimate of the quota
+    Show,
+
+    /// Start the counts over, of every account or only of --account
+    Reset {
+        /// Code signing account to reset the count of
+        #[arg(long, short)]
+        account: Option<String>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SetSecretArgs {
+    /// Target name of the generic credential
+    #[arg(value_name = "TARGET")]
+    pub target: String,
+
+    /// User name stored with the credential, like the client id, for telling entries apart
+    #[arg(long, default_value = "trusted-signing-cli")]
+    pub user_name: String,
+}
+
+/// The credential flags --sp-file and --sp-json-env stand in for
+const SP_CONFLICTS: [&str; 6] = [
+    "azure_client_secret",
+    "azure_client_secret_credman",
+    "azure_client_secret_keyvault",
+    "azure_client_id",
+    "azure_tenant_id",
+    "pfx",
+];
+
+/// Sign files, this is what runs when no subcommand is given
+#[derive(clap::Args, Debug)]
+#[command(
+    after_help = error::exit_codes_help(),
+    group(
+        ArgGroup::new("client_secret")
+            .args(["azure_client_secret", "azure_client_secret_credman", "azure_client_secret_keyvault"])
+    ),
+    // a PFX signs without Azure credentials
+    group(
+        ArgGroup::new("credentials")
+            .required(true)
+            .multiple(true)
+            .args([
+                "azure_client_secret",
+                "azure_client_secret_credman",
+                "azure_client_secret_keyvault",
+                "sp_file",
+                "sp_json_env",
+                "pfx",
+            ])
+    ),
+    // the catalog options apply to --ps-module's catalog too
+    group(ArgGroup::new("catalogs").args(["catalog", "ps_module"]))
+)]
+pub struct Args {
+    /// File(s) to sign, taken from the include globs of the project config when not given. Everything after `--`
+    /// is a file, for names beginning with a dash like `-- -launcher.exe`
+    #[arg(value_name = "FILE(S)", num_args = 1..=99)]
+    pub file: Vec<String>,
+
+    /// Expand the %VAR% and ${VAR} in the FILE(S) given, for paths whatever passed them didn't interpolate. A
+    /// referenced variable that isn't set fails, and every path expanded is logged. Off by default, percent signs
+    /// are legitimate in file names
+    /// The env var accepts true/false, yes/no, on/off and 1/0
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_EXPAND_ENV_IN_PATHS",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub expand_env_in_paths: bool,
+
+    /// Also sign the file whose path is in this environment variable, read as it is so the path never goes through
+    /// shell quoting. Can be given more than once
+    #[arg(long, value_name = "VAR_NAME")]
+    pub file_from_env: Vec<String>,
+
+    /// Run this build command through the shell first, its output streamed, and sign only when it exits with zero.
+    /// A failed build exits with the build's code and nothing is signed
+    #[arg(long, value_name = "COMMAND")]
+    pub run: Option<String>,
+
+    /// Sign the files this glob matches once the --run build is done, like `bundle/**/*.{exe,msi}`. Can be given
+    /// more than once, a glob matching nothing fails
+    #[arg(long, value_name = "GLOB", requires = "run")]
+    pub then_sign: Vec<String>,
+
+    /// Config file with named profiles [default: ~/.trusted-signing-cli/config.toml]
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Sign the exe and installers of the Tauri app in this directory or its src-tauri directory,
+    /// found through its tauri.conf.json and Cargo target directory
+    #[arg(long, value_name = "DIR", num_args = 0..=1, require_equals = true, default_missing_value = ".", verbatim_doc_comment)]
+    pub tauri: Option<PathBuf>,
+
+    /// Which outputs of the Tauri build to sign
+    #[arg(long, value_enum, default_value = "all", requires = "tauri")]
+    pub tauri_stage: tauri::Stage,
+
+    /// After signing the installers, sign them again for the Tauri updater with the Tauri CLI and the key in
+    /// TAURI_SIGNING_PRIVATE_KEY, which rewrites their .sig files. Both are checked before anything is signed
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_TAURI_UPDATER",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        requires = "tauri"
+    )]
+    pub tauri_updater: bool,
+
+    /// The Tauri CLI for --tauri-updater, the one installed in the project's node_modules or tauri or
+    /// cargo-tauri on the PATH by default
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_TAURI_CLI_PATH", requires = "tauri_updater")]
+    pub tauri_cli_path: Option<String>,
+
+    /// Update the signatures of the installers in this latest.json of the updater, and their sizes where it lists
+    /// them, once --tauri-updater signed them again
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_TAURI_LATEST_JSON", requires = "tauri_updater")]
+    pub tauri_latest_json: Option<PathBuf>,
+
+    /// Sign the Windows artifacts of a cargo-dist dist-manifest.json or of cargo-packager's output,
+    /// then update the checksums the manifest records
+    #[arg(long, value_name = "PATH", verbatim_doc_comment)]
+    pub manifest: Option<PathBuf>,
+
+    /// Which tool wrote the --manifest
+    #[arg(long, value_enum, default_value = "dist", requires = "manifest")]
+    pub manifest_format: manifest::Format,
+
+    /// Sign the files of a JSON job, - reads it from stdin: defaults for the endpoint, account, certificate
+    /// profile, digests and timestamp, the files with their own description, profile or output path, and whether
+    /// to go on after a failure. Prints the JSON report to stdout, `schema job` prints the schema of the job
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["file", "output"], verbatim_doc_comment)]
+    pub job: Option<String>,
+
+    /// Descriptions of single files, from --job
+    #[arg(skip)]
+    pub file_descriptions: Vec<(String, String)>,
+
+    /// Profile of the config file to take arguments from, flags and env vars still win
+    #[arg(long, env = "TRUSTED_SIGNING_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Don't look for a .trusted-signing.toml in the current directory and its parents
+    #[arg(long)]
+    pub no_project_config: bool,
+
+    /// What a ${VAR} or %VAR% in a config value gets when VAR is unset: an error naming it, or nothing
+    #[arg(long, value_enum, value_name = "MODE", env = "TRUSTED_SIGNING_CONFIG_UNSET_VARS", default_value = "error")]
+    pub config_unset_vars: config::UnsetVars,
+
+    /// Load environment variables from this file instead of a .env in the current directory,
+    /// variables already set in the environment win
+    #[arg(long, conflicts_with = "no_env_file")]
+    pub env_file: Option<PathBuf>,
+
+    /// Don't load environment variables from a .env file
+    #[arg(long)]
+    pub no_env_file: bool,
+
+    /// Azure client secret
+    #[arg(long, env = "AZURE_CLIENT_SECRET")]
+    pub azure_client_secret: Option<String>,
+
+    /// Read the Azure client secret from the password of this generic credential of Windows Credential Manager
+    #[arg(long, value_name = "TARGET", env = "AZURE_CLIENT_SECRET_CREDMAN")]
+    pub azure_client_secret_credman: Option<String>,
+
+    /// Read the Azure client secret from this Key Vault secret, like https://my-vault.vault.azure.net/secrets/sp,
+    /// with the identity the Azure CLI is already logged in as
+    #[arg(long, value_name = "SECRET_ID", env = "AZURE_CLIENT_SECRET_KEYVAULT", value_parser = azure::parse_secret_id)]
+    pub azure_client_secret_keyvault: Option<String>,
+
+    /// Read the --azure-client-secret-keyvault secret with the managed identity of this machine
+    /// The env var accepts true/false, yes/no, on/off and 1/0
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_BOOTSTRAP_MANAGED_IDENTITY",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        requires = "azure_client_secret_keyvault",
+        verbatim_doc_comment
+    )]
+    pub bootstrap_managed_identity: bool,
+
+    /// Azure client secret
+    #[arg(
+        long,
+        env = "AZURE_CLIENT_ID",
+        required = false,
+        required_unless_present_any = ["pfx", "sp_file", "sp_json_env"],
+        default_value_ifs([("pfx", IsPresent, ""), ("sp_file", IsPresent, ""), ("sp_json_env", IsPresent, "")])
+    )]
+    pub azure_client_id: String,
+
+    /// Azure tenant id
+    #[arg(
+        long,
+        env = "AZURE_TENANT_ID",
+        required = false,
+        required_unless_present_any = ["pfx", "sp_file", "sp_json_env"],
+        default_value_ifs([("pfx", IsPresent, ""), ("sp_file", IsPresent, ""), ("sp_json_env", IsPresent, "")])
+    )]
+    pub azure_tenant_id: String,
+
+    /// Read the client id, secret and tenant, and the subscription -a is looked up in, from the JSON of
+    /// `az ad sp create-for-rbac --sdk-auth`, instead of the --azure-* credential flags
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "TRUSTED_SIGNING_SP_FILE",
+        conflicts_with_all = SP_CONFLICTS
+    )]
+    pub sp_file: Option<PathBuf>,
+
+    /// Like --sp-file, with the JSON in this environment variable, like AZURE_CREDENTIALS of azure/login
+    #[arg(
+        long,
+        value_name = "VAR",
+        env = "TRUSTED_SIGNING_SP_JSON_ENV",
+        conflicts_with_all = SP_CONFLICTS,
+        conflicts_with = "sp_file"
+    )]
+    pub sp_json_env: Option<String>,
+
+    /// Subscription of the --sp-file principal, where -a is looked up
+    #[arg(skip)]
+    pub subscription_id: Option<String>,
+
+    /// Azure CLI path
+    #[arg(
+        long,
+        env = "AZURE_CLI_PATH",
+        default_value = azure::DEFAULT_CLI_PATH
+    )]
+    pub azure_cli_path: String,
+
+    /// Extra argument for az login, repeatable, like --az-arg=--allow-no-subscriptions.
+    /// <command>:<arg> adds it to the calls of one az command instead (login, account, rest,
+    /// resource, keyvault, ad, logout or version) and all:<arg> to every call, like --az-arg=all:--debug.
+    /// The credentials, --output and --query are set by trusted-signing-cli and can't be passed
+    #[arg(
+        long,
+        value_name = "ARG",
+        value_parser = azure::parse_az_arg,
+        allow_hyphen_values = true,
+        verbatim_doc_comment
+    )]
+    pub az_arg: Vec<azure::AzArg>,
+
+    /// Signtool path
+    #[arg(
+        long,
+        env = "SIGNTOOL_PATH",
+        default_value = signtool::DEFAULT_PATH
+    )]
+    pub sing_tool_path: String,
+
+    /// Verify signatures with `signtool verify` and its output instead of WinVerifyTrust, the only way off Windows
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_VERIFY_WITH_SIGNTOOL",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub verify_with_signtool: bool,
+
+    /// NuGet feed to download the Trusted Signing client from instead of nuget.org,
+    /// a v3 service index (.../index.json) or a flat container URL
+    #[cfg(feature = "download")]
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "TRUSTED_SIGNING_DLIB_FEED",
+        value_parser = feed::parse,
+        conflicts_with = "dlib_package",
+        verbatim_doc_comment
+    )]
+    pub dlib_feed: Option<String>,
+
+    /// Token for --dlib-feed, user:password is sent as basic auth (az:<PAT> for Azure Artifacts), anything else as bearer
+    #[cfg(feature = "download")]
+    #[arg(long, env = "TRUSTED_SIGNING_FEED_TOKEN", requires = "dlib_feed")]
+    pub feed_token: Option<String>,
+
+    /// Microsoft.Trusted.Signing.Client .nupkg to install the signing client from instead of downloading it, for
+    /// build agents that can't reach nuget.org. It has to be the version this build expects
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_DLIB_PACKAGE")]
+    pub dlib_package: Option<PathBuf>,
+
+    /// Where signatures come from, keyvault signs with a certificate in Azure Key Vault through AzureSignTool,
+    /// rest (experimental) through Trusted Signing's REST API and osslsigncode, off Windows too, and pfx with a
+    /// local PFX for test signatures
+    #[arg(long, value_enum, env = "TRUSTED_SIGNING_BACKEND", default_value = "trusted-signing")]
+    pub backend: Backend,
+
+    /// Signing Endpoint, a full URL or a region code like eus, wus2, weu or neu
+    /// Example: https://eus.codesigning.azure.net
+    #[arg(
+        long,
+        short = 'e',
+        env = "AZURE_TRUSTED_SIGNING_ENDPOINT",
+        value_parser = endpoint::normalize,
+        verbatim_doc_comment
+    )]
+    pub endpoint: Option<String>,
+
+    /// Code Signing Account name
+    #[arg(long, short = 'a', env = "AZURE_TRUSTED_SIGNING_ACCOUNT")]
+    pub account: Option<String>,
+
+    /// ARM resource id of the code signing account to look the endpoint up from when -e isn't given,
+    /// for an account name used in more than one subscription. Names the account when -a isn't given
+    /// Example: /subscriptions/<id>/resourceGroups/<group>/providers/Microsoft.CodeSigning/codeSigningAccounts/<name>
+    #[arg(
+        long,
+        value_name = "ID",
+        env = "TRUSTED_SIGNING_ACCOUNT_RESOURCE_ID",
+        value_parser = azure::parse_account_resource_id,
+        verbatim_doc_comment
+    )]
+    pub account_resource_id: Option<String>,
+
+    /// Certificate Profile name
+    #[arg(long, short = 'c', env = "AZURE_TRUSTED_SIGNING_CERTIFICATE_PROFILE")]
+    pub certificate: Option<String>,
+
+    /// Key Vault to sign with under --backend keyvault
+    /// Example: https://my-vault.vault.azure.net
+    #[arg(
+        long,
+        value_name = "URI",
+        env = "AZURE_KEY_VAULT_URI",
+        required_if_eq("backend", "keyvault"),
+        verbatim_doc_comment
+    )]
+    pub kv_uri: Option<String>,
+
+    /// Name of the certificate in the Key Vault to sign with
+    #[arg(long, value_name = "NAME", env = "AZURE_KEY_VAULT_CERTIFICATE", required_if_eq("backend", "keyvault"))]
+    pub kv_certificate: Option<String>,
+
+    /// PFX with the certificate and private key to sign with under --backend pfx, for builds that mustn't spend
+    /// quota or see credentials, like those of pull requests from forks. The signatures are test signatures
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_PFX", required_if_eq("backend", "pfx"))]
+    pub pfx: Option<PathBuf>,
+
+    /// Environment variable holding the password of --pfx, so the password isn't on the command line
+    #[arg(long, value_name = "VAR", env = "TRUSTED_SIGNING_PFX_PASSWORD_ENV", requires = "pfx")]
+    pub pfx_password_env: Option<String>,
+
+    /// AzureSignTool path or name on the PATH, used by --backend keyvault
+    #[arg(long, value_name = "PATH", env = "AZURE_SIGN_TOOL_PATH", default_value = keyvault::DEFAULT_TOOL)]
+    pub azure_sign_tool_path: String,
+
+    /// osslsigncode path or name on the PATH, used by --backend rest to embed signatures
+    #[arg(long, value_name = "PATH", env = "OSSLSIGNCODE_PATH", default_value = rest::DEFAULT_OSSLSIGNCODE)]
+    pub osslsigncode_path: String,
+
+    /// Sign files matching a glob with another certificate profile, and optionally account and endpoint
+    /// Repeatable, the first matching route wins and other files use -c
+    #[arg(long, value_name = "GLOB=PROFILE[,ACCOUNT[,ENDPOINT]]", value_parser = route::parse, verbatim_doc_comment)]
+    pub route: Vec<Route>,
+
+    /// Append a signature of another certificate profile to every file after its own, like a public trust
+    /// signature next to a private trust one. The account and endpoint default to -a and -e
+    #[arg(
+        long,
+        value_name = "PROFILE[,ACCOUNT[,ENDPOINT]]",
+        env = "TRUSTED_SIGNING_ALSO_PROFILE",
+        value_parser = route::parse_profile,
+        verbatim_doc_comment
+    )]
+    pub also_profile: Option<Profile>,
+
+    /// File digest algorithm
+    #[arg(long, value_enum, env = "TRUSTED_SIGNING_FD", default_value = "SHA256", ignore_case = true)]
+    pub fd: Digest,
+
+    /// RFC 3161 timestamp server
+    #[arg(long, value_name = "URL", env = "TRUSTED_SIGNING_TIMESTAMP_URL", default_value = TIMESTAMP_URL)]
+    pub tr: String,
+
+    /// Timestamp digest algorithm
+    #[arg(long, value_enum, env = "TRUSTED_SIGNING_TD", default_value = "SHA256", ignore_case = true)]
+    pub td: Digest,
+
+    /// When signtool signs a file but can't timestamp it and --tr keeps failing, keep the signature without a
+    /// timestamp and list the file for `trusted-signing-cli timestamp --pending`. Such a signature stops being
+    /// valid when the certificate expires, a few days later for Trusted Signing
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_DEFER_TIMESTAMP_ON_FAILURE",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub defer_timestamp_on_failure: bool,
+
+    /// Description of the signed content, shown in the UAC prompt
+    #[arg(long, env = "TRUSTED_SIGNING_DESCRIPTION")]
+    pub description: Option<String>,
+
+    /// Add the signature next to the existing ones (signtool /as) instead of replacing them
+    #[arg(long)]
+    pub append_signature: bool,
+
+    /// Write a detached PKCS#7 signature of every file into this directory, `app.bin` gets `app.bin.p7`, and leave
+    /// the files as they are. For firmware images and data files that can't hold a signature
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = [
+            "append_signature", "also_profile", "deep", "burn_bundle", "nuget", "vsix_tool", "clickonce",
+            "squirrel_releases", "driver_package", "catalog", "pipe"
+        ],
+        verbatim_doc_comment
+    )]
+    pub detached_p7: Option<PathBuf>,
+
+    /// OID of the content type of --detached-p7 signatures, PKCS#7 data by default
+    #[arg(long, value_name = "OID", default_value = "1.2.840.113549.1.7.1", requires = "detached_p7")]
+    pub p7_content_type: String,
+
+    /// What --detached-p7 signatures hold: the signature alone, or the file with it (embedded)
+    #[arg(long, value_enum, value_name = "CONTENT", default_value = "detached-signed-data", requires = "detached_p7")]
+    pub p7_content: P7Content,
+
+    /// The arguments of signtool as words with placeholders, for environments needing them in another order or with
+    /// switches of their own in between. Placeholders: {fd} {tr} {td} {dlib} {dmdf} {file} {extra} and, dropped
+    /// with the switch before them when unset, {description?} {as?} {ph?} {p7?} {p7co?} {p7ce?}. {file}, {dlib}
+    /// and {dmdf} are required. The default is `sign /v /fd {fd} /tr {tr} /td {td} /d {description?} {as?}
+    /// {ph?} /p7 {p7?} /p7co {p7co?} /p7ce {p7ce?} {extra} /dlib {dlib} /dmdf {dmdf} {file}`
+    #[arg(long, value_name = "TEMPLATE", env = "TRUSTED_SIGNING_SIGNTOOL_TEMPLATE")]
+    pub signtool_template: Option<String>,
+
+    /// Argument to pass signtool as well, repeatable. Goes where the template has {extra}, before /dlib by default
+    #[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+    pub signtool_extra_arg: Vec<String>,
+
+    /// Sign .nupkg files with `dotnet nuget sign` and the certificate of --nuget-certificate-fingerprint,
+    /// the other files with signtool
+    #[arg(long, requires = "nuget_certificate_fingerprint", verbatim_doc_comment)]
+    pub nuget: bool,
+
+    /// SHA-256 fingerprint of the certificate in the certificate store to sign NuGet packages with
+    #[arg(long, value_name = "FINGERPRINT", env = "TRUSTED_SIGNING_NUGET_CERTIFICATE_FINGERPRINT")]
+    pub nuget_certificate_fingerprint: Option<String>,
+
+    /// dotnet to sign NuGet packages with, found on the PATH by default
+    #[arg(long, value_name = "PATH", env = "DOTNET_PATH")]
+    pub dotnet_path: Option<String>,
+
+    /// Microsoft's sign tool to sign .vsix files with, signtool can't sign Visual Studio extensions
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_VSIX_TOOL")]
+    pub vsix_tool: Option<String>,
+
+    /// Sign .application and .vsto deployments and their application manifests with mage and the certificate
+    /// of --clickonce-certificate-hash, the other files with signtool
+    #[arg(long, requires = "clickonce_certificate_hash", verbatim_doc_comment)]
+    pub clickonce: bool,
+
+    /// SHA-1 thumbprint of the certificate in the certificate store to sign ClickOnce manifests with
+    #[arg(long, value_name = "THUMBPRINT", env = "TRUSTED_SIGNING_CLICKONCE_CERTIFICATE_HASH")]
+    pub clickonce_certificate_hash: Option<String>,
+
+    /// mage.exe to sign ClickOnce manifests with, found in the Windows SDKs by default
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_MAGE_PATH")]
+    pub mage_path: Option<String>,
+
+    /// Sign the packages inside .msixbundle and .appxbundle files too, unpacking and bundling them with makeappx,
+    /// signtool alone only signs the outer bundle
+    #[arg(long, verbatim_doc_comment)]
+    pub deep: bool,
+
+    /// makeappx.exe to unpack bundles with, the one next to signtool by default
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_MAKEAPPX_PATH", requires = "deep")]
+    pub makeappx_path: Option<String>,
+
+    /// Sign WiX Burn bundles in two steps: the engine, detached from the bundle and reattached with insignia,
+    /// then the bundle. Other executables are signed as usual
+    #[arg(long, verbatim_doc_comment)]
+    pub burn_bundle: bool,
+
+    /// insignia.exe to detach Burn engines with, or the WiX Toolset directory holding it. The toolset in %WIX%
+    /// or the newest one installed by default
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_WIX_PATH", requires = "burn_bundle", verbatim_doc_comment)]
+    pub wix_path: Option<String>,
+
+    /// Sign the Squirrel releases in this directory: its executables, the binaries inside its full and delta
+    /// packages, which are packed again, then Setup.exe. The RELEASES file gets the new SHA1 and size of
+    /// the packages and is checked against them at the end
+    #[arg(long, value_name = "DIR", verbatim_doc_comment)]
+    pub squirrel_releases: Option<PathBuf>,
+
+    /// After signing, rewrite the sha512 and size of the files the latest.yml of electron-builder references, or
+    /// every latest*.yml of this directory, keeping the rest of it and a copy as it was in `<name>.bak`
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_UPDATE_LATEST_YML")]
+    pub update_latest_yml: Option<PathBuf>,
+
+    /// After signing, write the SHA-256 of every file signed to this file, like SHA256SUMS for a release. Failed
+    /// and skipped files are left out
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_CHECKSUMS_FILE")]
+    pub checksums_file: Option<PathBuf>,
+
+    /// Directory the paths in the --checksums-file are relative to, its own directory by default
+    #[arg(long, value_name = "DIR", requires = "checksums_file")]
+    pub checksums_base: Option<PathBuf>,
+
+    /// How the --checksums-file lines are written
+    #[arg(long, value_enum, default_value = "gnu", requires = "checksums_file")]
+    pub checksums_format: checksums::Format,
+
+    /// Sign the driver package in this directory: its binaries with page hashes first, then the catalogs its INF
+    /// files name, once they are checked to cover the signed binaries
+    #[arg(long, value_name = "DIR", verbatim_doc_comment)]
+    pub driver_package: Option<PathBuf>,
+
+    /// Sign the PowerShell module in this directory: its .ps1, .psm1 and .psd1 files, each checked to keep its
+    /// encoding, then a catalog over everything in the module for Test-FileCatalog, named after the module
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["catalog", "driver_package"], verbatim_doc_comment)]
+    pub ps_module: Option<PathBuf>,
+
+    /// Sign the external cabinets an MSI references in its Media table before the MSI, looked up next to it.
+    /// Cabinets embedded in the MSI are signed with it as before
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_MSI_WITH_CABS",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub msi_with_cabs: bool,
+
+    /// Generate the catalogs of the --driver-package with inf2cat after its binaries are signed, for these
+    /// Windows versions, like 10_X64,10_ARM64
+    #[arg(long, value_name = "OS", requires = "driver_package", verbatim_doc_comment)]
+    pub inf2cat_os: Option<String>,
+
+    /// Inf2Cat.exe to generate catalogs with, found in the Windows Driver Kit by default
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_INF2CAT_PATH", requires = "inf2cat_os")]
+    pub inf2cat_path: Option<String>,
+
+    /// Generate this catalog over the files of --catalog-covers with makecat, sign it after the other files and
+    /// check it validates every file it lists. For scripts and data files that can't carry a signature themselves
+    #[arg(long, value_name = "PATH.cat", requires = "catalog_covers", verbatim_doc_comment)]
+    pub catalog: Option<PathBuf>,
+
+    /// A file or directory for the --catalog to list, directories with every file under them
+    #[arg(long, value_name = "PATH", requires = "catalog")]
+    pub catalog_covers: Vec<PathBuf>,
+
+    /// Hash the --catalog lists its files by
+    #[arg(long, value_enum, value_name = "HASH", default_value = "SHA256", requires = "catalogs")]
+    pub catalog_hash: CatalogHash,
+
+    /// An attribute of the --catalog, like OSAttr=2:10.0
+    #[arg(long, value_name = "NAME=VALUE", value_parser = catalog::parse_attribute, requires = "catalogs")]
+    pub catalog_attribute: Vec<(String, String)>,
+
+    /// makecat.exe to generate the --catalog with, the one of the Windows SDK by default
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_MAKECAT_PATH", requires = "catalogs")]
+    pub makecat_path: Option<String>,
+
+    /// Subject of the certificate, e.g. "CN=Fabrikam, O=Fabrikam, C=US", MSIX and APPX packages and bundles
+    /// whose Publisher differs fail before anything is signed. Read from an earlier signature of the run or the
+    /// certificate profile by default
+    #[arg(long, visible_alias = "expected-publisher", value_name = "SUBJECT", verbatim_doc_comment)]
+    pub publisher: Option<String>,
+
+    /// Sign MSIX and APPX packages and bundles whose Publisher isn't the certificate subject all the same
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_SKIP_PUBLISHER_CHECK",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub skip_publisher_check: bool,
+
+    /// Correlation id attached to the signing requests, a new one is generated for every run by default
+    #[arg(long, value_name = "ID", env = "TRUSTED_SIGNING_CORRELATION_ID")]
+    pub correlation_id: Option<String>,
+
+    /// Generate a new correlation id for every file instead of one per run
+    #[arg(long, conflicts_with = "correlation_id")]
+    pub correlation_per_file: bool,
+
+    /// Skip files signtool can't sign instead of failing
+    /// The env var accepts true/false, yes/no, on/off and 1/0
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_IGNORE_UNSUPPORTED",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub ignore_unsupported: bool,
+
+    /// Skip empty files and files that don't start like their type, like a truncated EXE, instead of failing
+    /// The env var accepts true/false, yes/no, on/off and 1/0
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_SKIP_INVALID",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub skip_invalid: bool,
+
+    /// Extensions of the files to sign with signtool, instead of the built-in list
+    #[arg(long, value_name = "EXTENSIONS", env = "TRUSTED_SIGNING_EXTENSIONS", value_delimiter = ',')]
+    pub extensions: Option<Vec<String>>,
+
+    /// Extensions to sign with signtool in addition to --extensions or the built-in list, like plugin for a
+    /// renamed DLL
+    #[arg(long, value_name = "EXTENSIONS", env = "TRUSTED_SIGNING_EXTRA_EXTENSIONS", value_delimiter = ',')]
+    pub extra_extensions: Vec<String>,
+
+    /// Log level, overrides RUST_LOG
+    /// Child process output is logged at debug level
+    #[arg(long, value_enum, verbatim_doc_comment)]
+    pub log_level: Option<LogLevel>,
+
+    /// Also write debug-level diagnostics to a file, whatever the console shows: the resolved settings without
+    /// secrets, the command lines and output of the tools run, timings and retries. Every run writes files of its
+    /// own named after PATH with the start time and process id, logs/trusted-signing-cli.log in the config dir
+    /// without PATH
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_LOG_FILE", num_args = 0..=1, verbatim_doc_comment)]
+    pub log_file: Option<Option<PathBuf>>,
+
+    /// Size in MB after which --log-file goes on in a new file
+    #[arg(long, value_name = "MB", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub log_file_size: u64,
+
+    /// How many files of --log-file are kept, of this run and earlier ones, the oldest are removed
+    #[arg(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub log_file_keep: u64,
+
+    /// Check that the account and certificate profile exist right after the login
+    /// Needs read access to the code signing account, without it this is only a warning
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_VALIDATE_PROFILE",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub validate_profile: bool,
+
+    /// Skip the login when azure cli is still logged in with these credentials from an earlier run
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_REUSE_SESSION",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub reuse_session: bool,
+
+    /// Fail instead of warning when other users could write the config directory or the cached signing client,
+    /// which signtool loads
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_STRICT_PERMISSIONS",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub strict_permissions: bool,
+
+    /// Log in even when every file is skipped, by --marker-files, --ignore-unsupported or --skip-invalid, so
+    /// broken credentials fail runs that had nothing to sign
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_STRICT_AUTH",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub strict_auth: bool,
+
+    /// Compare the local clock with the signing endpoint's before signing, and warn when it is more than five
+    /// minutes off, which fails timestamps and tokens. On by default, pass false to skip the request
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_CHECK_CLOCK",
+        value_name = "BOOL",
+        default_value_t = true,
+        action = ArgAction::Set,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub check_clock: bool,
+
+    /// Fail instead of warning when the clock is off
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_STRICT_CLOCK",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub strict_clock: bool,
+
+    /// Before logging in, resolve, connect and complete a TLS handshake with the signing endpoint and the timestamp
+    /// server, failing with the step that was blocked. Warns when a proxy seems to inspect TLS
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_PREFLIGHT_NETWORK",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub preflight_network: bool,
+
+    /// Fast path for tools calling once per file, like Inno Setup's SignTool: reuses the azure cli
+    /// session and metadata file of earlier calls and prints only errors
+    #[arg(long, verbatim_doc_comment)]
+    pub single: bool,
+
+    /// Sign the single file electron-builder's sign hook passes, with the digest and nesting of
+    /// ELECTRON_BUILDER_SIGN_HASH and ELECTRON_BUILDER_SIGN_NESTED, implies --single
+    #[arg(long, verbatim_doc_comment)]
+    pub electron_builder_hook: bool,
+
+    /// Sign the file @electron/windows-sign passes with signtool's arguments, as its signToolPath.
+    /// Set TRUSTED_SIGNING_WINDOWS_SIGN_HOOK or pass this through signWithParams, implies --single
+    #[arg(long, verbatim_doc_comment, conflicts_with = "electron_builder_hook")]
+    pub windows_sign_hook: bool,
+
+    /// Sign the file piped into stdin and write the signed file to stdout, everything else goes to stderr
+    #[arg(
+        long,
+        requires = "kind",
+        conflicts_with_all = [
+            "file", "tauri", "manifest", "driver_package", "squirrel_releases", "catalog", "single",
+            "electron_builder_hook", "windows_sign_hook", "output", "events", "run", "ps_module", "job"
+        ]
+    )]
+    pub pipe: bool,
+
+    /// Extension of the file given to --pipe, signtool tells the file type by it, e.g. exe or msi
+    #[arg(long = "type", value_name = "EXTENSION", requires = "pipe")]
+    pub kind: Option<String>,
+
+    /// Largest file --pipe accepts, in MiB
+    #[arg(long, value_name = "MIB", default_value_t = 1024, requires = "pipe")]
+    pub max_input_size: u64,
+
+    /// Directory for the temporary files of a run, like unpacked bundles, repacked packages and stdin. Checked to
+    /// be writable with room to spare before anything is signed. By default they go next to the file they are for,
+    /// on its volume, or to the system temp directory
+    #[arg(long, env = scratch::ENV, value_name = "DIR")]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Show the azure account details returned by the login
+    #[arg(long, short = 'v')]
+    pub verbose: bool,
+
+    /// Show tenant, subscription and object ids instead of placeholders like tenant:***1
+    #[arg(long)]
+    pub no_redact: bool,
+
+    /// Print nothing on success and only a short error on failure
+    /// Reports requested with --output or --report-file are still written
+    #[arg(long, short = 'q', conflicts_with_all = ["verbose", "log_level"], verbatim_doc_comment)]
+    pub quiet: bool,
+
+    /// Output format, json prints a report of every file to stdout
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// Also write a report file in this format, even when signing fails part-way
+    #[arg(long, value_enum, requires = "report_file")]
+    pub report: Option<ReportFormat>,
+
+    /// Path of the report file
+    #[arg(long, requires = "report")]
+    pub report_file: Option<PathBuf>,
+
+    /// Write the raw duration of every phase in milliseconds to this file
+    #[arg(long, value_name = "PATH")]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Keep signing the remaining files after a failure
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Sign with an endpoint that isn't a host of Trusted Signing, like a region it added since this release, with a
+    /// warning instead of refusing it
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_ALLOW_CUSTOM_ENDPOINT",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub allow_custom_endpoint: bool,
+
+    /// Endpoint to sign a file at when signing it fails with a service-side error, like a 5xx or a timeout during a
+    /// regional outage, repeat it for more in the order they are tried. The account must exist in that region too.
+    /// Login, role and profile errors fail the same everywhere and don't fail over
+    #[arg(
+        long,
+        value_name = "ENDPOINT",
+        env = "TRUSTED_SIGNING_FALLBACK_ENDPOINT",
+        value_delimiter = ',',
+        value_parser = endpoint::normalize
+    )]
+    pub fallback_endpoint: Vec<String>,
+
+    /// Fail files signtool signed with warnings, like a skipped timestamp, instead of only reporting them
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_FAIL_ON_WARNINGS",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub fail_on_warnings: bool,
+
+    /// Allow SHA-2 only: refuse SHA1 for --fd, --td and --catalog-hash, and fail files whose signature or
+    /// timestamp turns out to use a weaker digest, like the SHA1 countersignatures some authorities return
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_REQUIRE_SHA2",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub require_sha2: bool,
+
+    /// Check signed PE images, PE32 and PE32+, against their headers: the sections within the file and the image,
+    /// and the certificate table well-formed, aligned and ending the file. Fails images that verify but wouldn't
+    /// load, a CheckSum that doesn't match the file is fixed instead. Files that aren't PE images aren't checked
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_VALIDATE_PE",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub validate_pe: bool,
+
+    /// Fail signed PE images built for another machine, read from their COFF header after signing. Catches a
+    /// binary of the wrong architecture headed for an installer, files that aren't PE images aren't checked
+    #[arg(long, value_enum, value_name = "ARCH", env = "TRUSTED_SIGNING_EXPECT_ARCH")]
+    pub expect_arch: Option<Arch>,
+
+    /// Fail signed PE images of another subsystem, like a console build where a GUI one belongs
+    #[arg(long, value_enum, value_name = "SUBSYSTEM", env = "TRUSTED_SIGNING_EXPECT_SUBSYSTEM")]
+    pub expect_subsystem: Option<Subsystem>,
+
+    /// Fail signed files whose signing certificate has another subject. `dn:CN=Contoso, O=Contoso, C=US` matches
+    /// the whole distinguished name in any order, `regex:<pattern>` a regular expression, anything else a part of
+    /// it, all ignoring case. Read from PE images, catalogs and detached signatures, other files aren't checked
+    #[arg(
+        long,
+        value_name = "SUBJECT",
+        env = "TRUSTED_SIGNING_EXPECT_SUBJECT",
+        value_parser = certificate::parse_subject
+    )]
+    pub expect_subject: Option<SubjectMatch>,
+
+    /// Fail signed files whose signing certificate has another SHA-1 thumbprint, more of them comma separated or
+    /// repeated are any of them. Trusted Signing issues a new certificate every few days, pin the subject instead
+    #[arg(
+        long,
+        value_name = "HEX",
+        env = "TRUSTED_SIGNING_EXPECT_THUMBPRINT",
+        value_parser = certificate::parse_thumbprint,
+        value_delimiter = ','
+    )]
+    pub expect_thumbprint: Vec<String>,
+
+    /// Skip files already signed by this signer, leaving third-party binaries as their vendor signed them. A SHA-1
+    /// thumbprint, or a subject like --expect-subject takes, repeated or comma separated for more. Read from the
+    /// signatures of PE images, catalogs and detached signatures, nested ones included
+    #[arg(
+        long,
+        value_name = "SUBJECT|HEX",
+        env = "TRUSTED_SIGNING_EXCLUDE_SIGNED_BY",
+        value_parser = certificate::parse_protected,
+        value_delimiter = ','
+    )]
+    pub exclude_signed_by: Vec<certificate::Protected>,
+
+    /// Hold signed files to this subject of the signing certificate, a distinguished name compared in any order
+    /// and ignoring case. Read from PE images, catalogs and detached signatures like --expect-subject
+    #[arg(long, value_name = "DN", env = "TRUSTED_SIGNING_PIN_SUBJECT", value_parser = pin::parse_subject)]
+    pub pin_subject: Option<String>,
+
+    /// Record the subject, issuer and CA chain of the first file signed in this file when it doesn't exist, and
+    /// hold the files of later runs to them. The thumbprint is recorded but not compared, the leaf changes every
+    /// few days
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_PIN_FILE")]
+    pub pin_file: Option<PathBuf>,
+
+    /// What a file signed with another identity than pinned comes to
+    #[arg(long, value_enum, env = "TRUSTED_SIGNING_PIN_MODE", default_value = "fail")]
+    pub pin_mode: PinMode,
+
+    /// Record the identity in --pin-file when it changed, with a warning naming the old and the new one, for a
+    /// planned change of the identity
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_UPDATE_PIN",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        requires = "pin_file"
+    )]
+    pub update_pin: bool,
+
+    /// After signing, build the chain of every signature with online CRL and OCSP checks and fail files with a
+    /// revoked certificate or a chain that doesn't verify. The status of every certificate lands in the report
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_VERIFY_REVOCATION",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub verify_revocation: bool,
+
+    /// What --verify-revocation does when a revocation status can't be checked, like without network
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_REVOCATION_OFFLINE",
+        value_enum,
+        default_value = "warn",
+        requires = "verify_revocation"
+    )]
+    pub revocation_offline: Offline,
+
+    /// Wait up to this long, like 30s or 2m, for a file that doesn't exist yet or is still growing, until it was
+    /// left alone for a second, for build tools that write or rename it after starting this
+    #[arg(long, value_name = "DURATION", env = "TRUSTED_SIGNING_WAIT_FOR_FILE", value_parser = input::parse_duration)]
+    pub wait_for_file: Option<Duration>,
+
+    /// Stop the run after this long, like 30m, for a CI job with a time budget. No file is started when one as
+    /// slow as the slowest so far wouldn't fit in what is left, and no fallback endpoint is tried when the attempt
+    /// wouldn't. Files still signing at the end are cancelled. The summary and the report list the files signed,
+    /// the run exits with 9
+    #[arg(long, value_name = "DURATION", env = "TRUSTED_SIGNING_MAX_RUNTIME", value_parser = input::parse_duration)]
+    pub max_runtime: Option<Duration>,
+
+    /// Give every signed file back the creation, modification and access times it had before signing, for
+    /// incremental packaging and delta updates. A time that can't be restored is a warning
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_PRESERVE_TIMESTAMPS",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub preserve_timestamps: bool,
+
+    /// Warn after the login when the certificate profile isn't active or reports an expiry, like that of its identity
+    /// validation, within this many days. Needs read access to the profile, 0 turns the check off
+    #[arg(long, value_name = "DAYS", env = "TRUSTED_SIGNING_WARN_EXPIRY", default_value_t = 14)]
+    pub warn_expiry: i64,
+
+    /// Warn once the signatures this machine counted for the account this month pass N. The count is a local
+    /// estimate, signatures from other machines aren't in it
+    #[arg(long, value_name = "N", env = "TRUSTED_SIGNING_QUOTA_WARN")]
+    pub quota_warn: Option<u64>,
+
+    /// Refuse to sign once the signatures this machine counted for the account this month reach N, a guard against
+    /// runaway pipelines. `quota reset` starts the count over
+    #[arg(long, value_name = "N", env = "TRUSTED_SIGNING_QUOTA_HARD_LIMIT")]
+    pub quota_hard_limit: Option<u64>,
+
+    /// Print `<sha256>  <path>` of every signed file to stdout once it is signed, in the format sha256sum reads
+    #[arg(long, conflicts_with_all = ["pipe", "output", "events"])]
+    pub print_hashes: bool,
+
+    /// After signing `app.exe`, write `app.exe.signed` with its SHA-256 and when it was signed, and skip files whose
+    /// marker still matches them. For incremental builds that sign the same outputs again
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_MARKER_FILES",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub marker_files: bool,
+
+    /// Write the marker files into this directory instead of next to the files
+    #[arg(long, value_name = "DIR", requires = "marker_files")]
+    pub marker_dir: Option<PathBuf>,
+
+    /// Append a JSON line per signing operation to this file
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Append the outcome of every file to this journal as it completes, for --resume. Runs of 100 files or more
+    /// keep one in the config directory without it. The journal is deleted once a run signed everything
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_JOURNAL", conflicts_with = "pipe")]
+    pub journal: Option<PathBuf>,
+
+    /// Pick up the run the journal is of: files it records as signed that weren't changed since are skipped. A
+    /// journal of other files or of another profile, endpoint or options is started over
+    #[arg(long, conflicts_with = "pipe")]
+    pub resume: bool,
+
+    /// Also write an event per signed, skipped or failed file and per run to the Windows Application log, what
+    /// the audit log records as insertion strings. The event source is registered when it isn't, which needs an
+    /// administrator once, without it signing goes on with a warning
+    /// The env var accepts true/false, yes/no, on/off and 1/0
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_EVENTLOG",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub eventlog: bool,
+
+    /// Event source the --eventlog events are written as
+    #[arg(long, value_name = "NAME", default_value = "trusted-signing-cli", requires = "eventlog")]
+    pub eventlog_source: String,
+
+    /// Write an in-toto like statement of the signed files and their digests to this file
+    #[arg(long, value_name = "PATH")]
+    pub attestation_file: Option<PathBuf>,
+
+    /// Command run before each file is signed, like a malware scan. `{file}` is replaced with the path,
+    /// the program runs without a shell and its output is logged
+    #[arg(long, value_name = "COMMAND", value_parser = hooks::parse, verbatim_doc_comment)]
+    pub pre_sign_cmd: Option<String>,
+
+    /// What a --pre-sign-cmd exiting non-zero does to the file
+    #[arg(long, value_enum, default_value = "fail", requires = "pre_sign_cmd")]
+    pub pre_sign_failure: PreSignFailure,
+
+    /// Command run after each file is signed, with `{file}` and `{status}`
+    #[arg(long, value_name = "COMMAND", value_parser = hooks::parse)]
+    pub post_sign_cmd: Option<String>,
+
+    /// Command run for each file that failed, with `{file}`, `{status}` and `{error}`
+    #[arg(long, value_name = "COMMAND", value_parser = hooks::parse)]
+    pub on_failure_cmd: Option<String>,
+
+    /// Command run once before any file is signed, the run fails when it does
+    #[arg(long, value_name = "COMMAND", value_parser = hooks::parse)]
+    pub pre_run_cmd: Option<String>,
+
+    /// Command run once after all files, with `{status}` (succeeded or failed), `{error}`, `{signed}`, `{failed}`
+    /// and `{total}`
+    #[arg(long, value_name = "COMMAND", value_parser = hooks::parse, verbatim_doc_comment)]
+    pub post_run_cmd: Option<String>,
+
+    /// Stream events while signing, ndjson prints one JSON object per line to stdout
+    /// The last event carries the full report, so this can't be combined with --output json
+    #[arg(long, value_enum, verbatim_doc_comment, conflicts_with = "output")]
+    pub events: Option<EventFormat>,
+
+    /// Format output for a CI system, detected from the environment by default
+    #[arg(long, value_enum)]
+    pub ci_format: Option<CiFormat>,
+
+    /// Never wait for input, on when CI is set unless `--ci=false`: --az-arg --use-device-code is refused, child
+    /// processes get a closed stdin, prompts fail with an error and there is no progress bar or color unless
+    /// --color always asks for it
+    #[arg(
+        long,
+        env = "CI",
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value = "false",
+        default_missing_value = "true",
+        action = ArgAction::Set,
+        value_parser = ci::parse_enabled
+    )]
+    pub ci: bool,
+
+    /// Append a markdown summary of the run to $GITHUB_STEP_SUMMARY, done by default in GitHub Actions unless
+    /// --ci-format picks another format. Nothing is written outside Actions
+    /// The env var accepts true/false, yes/no, on/off and 1/0
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_GITHUB_SUMMARY",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new(),
+        verbatim_doc_comment
+    )]
+    pub github_summary: bool,
+
+    /// POST the results of the run as JSON to this URL: the status, the counts, the duration, the signing
+    /// account and profile, the failed files with why, and the CI run. Failing to deliver only warns
+    #[cfg(feature = "webhook")]
+    #[arg(long, env = "TRUSTED_SIGNING_WEBHOOK", value_name = "URL", value_parser = webhook::parse_url)]
+    pub webhook: Option<String>,
+
+    /// A header sent with the --webhook post, as 'Name: value', repeatable
+    #[cfg(feature = "webhook")]
+    #[arg(long, env = "TRUSTED_SIGNING_WEBHOOK_HEADER", value_name = "HEADER", value_parser = webhook::parse_header)]
+    pub webhook_header: Vec<(String, String)>,
+
+    /// When --webhook is posted to
+    #[cfg(feature = "webhook")]
+    #[arg(long, env = "TRUSTED_SIGNING_WEBHOOK_ON", value_enum, default_value = "always")]
+    pub webhook_on: WebhookOn,
+
+    /// Export a trace of the run to this OTLP/HTTP collector, like http://localhost:4318, with spans for the
+    /// dependencies, the login and every file. OTEL_EXPORTER_OTLP_ENDPOINT and the other OTEL_* variables are
+    /// read as well. Failing to export only warns
+    #[cfg(feature = "otlp")]
+    #[arg(long, value_name = "URL", value_parser = otlp::parse_endpoint)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Color the output, NO_COLOR turns colors off unless this is set to always
+    #[arg(long, value_enum, default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Never show a progress bar
+    /// It is also hidden when stderr is not a terminal, in CI, or for only a few files
+    #[arg(long, verbatim_doc_comment)]
+    pub no_progress: bool,
+}
+
+impl Args {
+    /// Where the journal of the run goes, an explicit one or that of a large run or of --resume
+    pub fn journal_path(&self) -> Option<PathBuf> {
+        match &self.journal {
+            _ if self.pipe => None,
+            Some(path) => Some(path.clone()),
+            None if self.resume || self.file.len() >= journal::LARGE_RUN => Some(journal::default_path(&self.file)),
+            None => None,
+        }
+    }
+
+    /// Where files are signed unless routed elsewhere, the vault of --kv-uri with the Key Vault backend
+    pub fn target(&self) -> Target {
+        let value = |value: &Option<String>| value.clone().unwrap_or_default();
+        match self.backend {
+            Backend::TrustedSigning | Backend::Rest => Target {
+                endpoint: value(&self.endpoint),
+                account: value(&self.account),
+                certificate: value(&self.certificate),
+            },
+            Backend::KeyVault => Target {
+                account: keyvault::vault_name(self.kv_uri.as_deref().unwrap_or_default()).to_string(),
+                endpoint: value(&self.kv_uri),
+                certificate: value(&self.kv_certificate),
+            },
+            Backend::Pfx => {
+                let pfx = self.pfx.clone().unwrap_or_default();
+                Target {
+                    endpoint: pfx.display().to_string(),
+                    account: "local".to_string(),
+                    certificate: pfx.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                }
+            }
+        }
+    }
+
+    /// The password of --pfx, read from the variable --pfx-password-env names. Empty for a PFX without one
+    pub fn pfx_password(&self) -> Result<String, Error> {
+        let Some(var) = &self.pfx_password_env else {
+            return Ok(String::new());
+        };
+        std::env::var(var).map_err(|_| {
+            Error::Usage(format!("--pfx-password-env names {}, which is not set to the password of the PFX", var))
+        })
+    }
+
+    /// Extensions of the files signtool signs, from --extensions and --extra-extensions
+    pub fn extensions(&self) -> Vec<String> {
+        input::extensions(self.extensions.as_deref(), &self.extra_extensions)
+    }
+
+    /// Where --log-file writes, in the config dir without a path
+    pub fn log_file(&self) -> Option<LogFile> {
+        let path = self.log_file.clone()?;
+        Some(LogFile {
+            path: path.unwrap_or_else(|| config::dir().join("logs").join("trusted-signing-cli.log")),
+            max_size: self.log_file_size * 1024 * 1024,
+            keep: self.log_file_keep as usize,
+        })
+    }
+
+    /// Take the credentials from --sp-file or --sp-json-env when one is given
+    pub fn read_service_principal(&mut self) -> Result<(), Error> {
+        let Some(principal) = principal::read(self.sp_file.as_deref(), self.sp_json_env.as_deref())? else {
+            return Ok(());
+        };
+        debug!("signing as the service principal {} of {}", principal.client_id, principal.source);
+        self.azure_client_id = principal.client_id;
+        self.azure_tenant_id = principal.tenant_id;
+        self.azure_client_secret = Some(principal.client_secret);
+        self.subscription_id = principal.subscription_id;
+        Ok(())
+    }
+
+    /// The client secret, read from Credential Manager with --azure-client-secret-credman or from Key Vault with
+    /// --azure-client-secret-keyvault
+    pub fn client_secret(&self) -> Result<String, Error> {
+        let (credman, keyvault) = (&self.azure_client_secret_credman, &self.azure_client_secret_keyvault);
+        match (&self.azure_client_secret, credman, keyvault) {
+            (Some(secret), _, _) => Ok(secret.clone()),
+            (None, Some(target), _) => {
+                debug!("reading the client secret from the Credential Manager entry '{}'", target);
+                credman::read(target)
+            }
+            (None, None, Some(id)) => {
+                debug!("reading the client secret from Key Vault");
+                azure::key_vault_secret(&self.azure_cli_path, id, self.bootstrap_managed_identity)
+            }
+            (None, None, None) => Err(Error::Usage(
+                "the client secret is missing, pass --azure-client-secret, --azure-client-secret-credman or \
+                 --azure-client-secret-keyvault"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+/// Sign the artifacts of a Cargo build, with the same arguments as sign
+#[derive(clap::Args, Debug)]
+pub struct CargoArgs {
+    #[command(flatten)]
+    pub sign: Args,
+
+    #[command(flatten)]
+    pub selection: CargoSelection,
+}
+
+/// Which artifacts of the Cargo workspace to sign
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Cargo")]
+pub struct CargoSelection {
+    /// Sign the artifacts of the release profile
+    #[arg(long, short = 'r', conflicts_with = "cargo_profile")]
+    pub release: bool,
+
+    /// Sign the artifacts of this Cargo profile [default: dev], --profile selects the config profile
+    #[arg(long, value_name = "PROFILE-NAME")]
+    pub cargo_profile: Option<String>,
+
+    /// Target triple the artifacts were built for
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+
+    /// Path to the Cargo.toml of the workspace
+    #[arg(long, value_name = "PATH")]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Only sign the artifacts of this package, repeatable
+    #[arg(long, short = 'p', value_name = "SPEC")]
+    pub package: Vec<String>,
+
+    /// Only sign this binary, repeatable
+    #[arg(long, value_name = "NAME")]
+    pub bin: Vec<String>,
+
+    /// List the files that would be signed without signing them, no other argument is required
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl CargoSelection {
+    /// Cargo profile of the artifacts
+    pub fn profile(&self) -> &str {
+        match (self.release, &self.cargo_profile) {
+            (true, _) => "release",
+            (false, Some(profile)) => profile,
+            (false, None) => "dev",
+        }
+    }
+}
+
+/// Arguments of `print-signtool-args`
+#[derive(clap::Args, Debug)]
+pub struct SigntoolArgs {
+    #[command(flatten)]
+    pub sign: Args,
+
+    /// Write a .cmd or .ps1 running signtool with these arguments and the ones it is given instead,
+    /// the file is left alone when it is up to date
+    #[arg(long, value_name = "PATH", verbatim_doc_comment)]
+    pub emit_wrapper: Option<PathBuf>,
+}
+
+/// Arguments of `list-accounts`
+#[derive(clap::Args, Debug)]
+pub struct ListAccountsArgs {
+    #[command(flatten)]
+    pub sign: Args,
+
+    /// Only list the accounts in this subscription, can be given more than once
+    #[arg(long, value_name = "ID")]
+    pub subscription: Vec<String>,
+}
+
+/// Arguments of `serve`
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub sign: Args,
+
+    /// Address to listen on, only reachable from this machine by default
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8787")]
+    pub listen: SocketAddr,
+
+    /// Token clients have to send as `Authorization: Bearer <token>`
+    #[arg(long, env = "TRUSTED_SIGNING_SERVE_TOKEN", hide_env_values = true)]
+    pub token: String,
+
+    /// Largest file accepted, in MiB
+    #[arg(long, value_name = "MIB", default_value_t = 512)]
+    pub max_upload: u64,
+
+    /// Seconds a request may take from the upload to the signed file, waiting for other requests included
+    #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+    pub request_timeout: u64,
+}
+
+/// Arguments of `bench`
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    pub sign: Args,
+
+    /// How many files to sign
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub count: u64,
+
+    /// Size of each file, like 64KB or 1MB
+    #[arg(long, value_name = "SIZE", default_value = "64KB", value_parser = bench::parse_size)]
+    pub size: u64,
+
+    /// How many files are signed at once, each by a signer of its own
+    #[arg(long, value_name = "J", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=64))]
+    pub jobs: u64,
+
+    /// Sign them, the signatures count against the quota
+    #[arg(long)]
+    pub yes: bool,
+}
+
+/// Arguments of `daemon`
+#[derive(clap::Args, Debug)]
+pub struct DaemonArgs {
+    #[command(flatten)]
+    pub sign: Args,
+
+    /// Minutes without a request after which the daemon stops
+    #[arg(long, value_name = "MINUTES", env = "TRUSTED_SIGNING_DAEMON_IDLE_TIMEOUT", default_value_t = 30)]
+    pub idle_timeout: u64,
+}
+
+/// Write a profile for `sign --profile`, secrets are never written and stay in env vars
+#[derive(clap::Args, Debug)]
+pub struct InitArgs {
+    /// Config file to write the profile to [default: ~/.trusted-signing-cli/config.toml]
+    #[arg(long, value_name = "PATH", env = "TRUSTED_SIGNING_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Name of the profile
+    #[arg(long, default_value = "default")]
+    pub profile: String,
+
+    /// Signing Endpoint, a full URL or a region code like eus, wus2, weu or neu
+    #[arg(long, short = 'e', value_parser = endpoint::parse)]
+    pub endpoint: Option<String>,
+
+    /// Code Signing Account name
+    #[arg(long, short = 'a')]
+    pub account: Option<String>,
+
+    /// Certificate Profile name
+    #[arg(long, short = 'c')]
+    pub certificate: Option<String>,
+
+    /// Signtool path, discovered from the installed Windows SDKs by default
+    #[arg(long)]
+    pub sing_tool_path: Option<String>,
+
+    /// Azure CLI path, discovered from the install location and PATH by default
+    #[arg(long)]
+    pub azure_cli_path: Option<String>,
+
+    /// Azure client id
+    #[arg(long, env = "AZURE_CLIENT_ID")]
+    pub azure_client_id: Option<String>,
+
+    /// Azure tenant id
+    #[arg(long, env = "AZURE_TENANT_ID")]
+    pub azure_tenant_id: Option<String>,
+
+    /// Log in and acquire a signing token to check the credentials, the secret is read from AZURE_CLIENT_SECRET
+    #[arg(long)]
+    pub test_login: bool,
+
+    /// Don't prompt, take everything from flags
+    #[arg(long)]
+    pub non_interactive: bool,
+
+    /// Overwrite the profile when it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Verify signed files
+#[derive(clap::Args, Debug)]
+#[command(after_help = error::exit_codes_help())]
+pub struct VerifyArgs {
+    /// File(s) or globs of files to verify, or directories with --recursive
+    #[arg(value_name = "FILE(S)", required = true)]
+    pub file: Vec<String>,
+
+    /// Verify every file under the directories given that a sign would sign, by the built-in extensions or
+    /// --extensions and --extra-extensions. Files without a signature are listed, --require-all fails them
+    #[arg(long, short = 'r')]
+    pub recursive: bool,
+
+    /// Fail files found with --recursive that have no signature at all, not only those with an invalid one
+    #[arg(long, requires = "recursive")]
+    pub require_all: bool,
+
+    /// Leave out files matching this glob, like vendor/** or *.resources.dll, for files meant to stay unsigned.
+    /// Matched against the path under the directory walked or the path given, `*` crossing directories. Repeat
+    /// for more
+    #[arg(long, value_name = "GLOB", value_parser = verify::parse_exclude)]
+    pub exclude: Vec<glob::Pattern>,
+
+    /// Extensions of the files --recursive verifies, instead of the built-in list
+    #[arg(long, value_name = "EXTENSIONS", env = "TRUSTED_SIGNING_EXTENSIONS", value_delimiter = ',')]
+    pub extensions: Option<Vec<String>>,
+
+    /// Extensions --recursive verifies in addition to --extensions or the built-in list
+    #[arg(long, value_name = "EXTENSIONS", env = "TRUSTED_SIGNING_EXTRA_EXTENSIONS", value_delimiter = ',')]
+    pub extra_extensions: Vec<String>,
+
+    /// Signtool path, the default one or the newest of the Windows SDKs when not given
+    #[arg(long, env = "SIGNTOOL_PATH")]
+    pub sing_tool_path: Option<String>,
+
+    /// Verify signatures with `signtool verify` and its output instead of WinVerifyTrust, the only way off Windows
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_VERIFY_WITH_SIGNTOOL",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub verify_with_signtool: bool,
+
+    /// Fail files whose signing certificate has another subject. `dn:CN=Contoso, O=Contoso, C=US` matches
+    /// the whole distinguished name in any order, `regex:<pattern>` a regular expression, anything else a part of
+    /// it, all ignoring case. Read from PE images, catalogs and
+    /// detached signatures, other files fail
+    #[arg(
+        long,
+        value_name = "SUBJECT",
+        env = "TRUSTED_SIGNING_EXPECT_SUBJECT",
+        value_parser = certificate::parse_subject
+    )]
+    pub expect_subject: Option<SubjectMatch>,
+
+    /// Fail files whose signing certificate has another SHA-1 thumbprint, more of them comma separated or
+    /// repeated are any of them. Trusted Signing issues a new certificate every few days, pin the subject instead
+    #[arg(
+        long,
+        value_name = "HEX",
+        env = "TRUSTED_SIGNING_EXPECT_THUMBPRINT",
+        value_parser = certificate::parse_thumbprint,
+        value_delimiter = ','
+    )]
+    pub expect_thumbprint: Vec<String>,
+
+    /// Output format, json prints the details of every file to stdout
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+impl VerifyArgs {
+    /// Extensions of the files --recursive verifies, from --extensions and --extra-extensions
+    pub fn extensions(&self) -> Vec<String> {
+        input::extensions(self.extensions.as_deref(), &self.extra_extensions)
+    }
+}
+
+/// Timestamp signed files
+#[derive(clap::Args, Debug)]
+#[command(after_help = error::exit_codes_help())]
+pub struct TimestampArgs {
+    /// File(s) or globs of signed files to timestamp
+    #[arg(value_name = "FILE(S)", required_unless_present = "pending", conflicts_with = "pending")]
+    pub file: Vec<String>,
+
+    /// Timestamp the files --defer-timestamp-on-failure signed without one, those unchanged since, and take the
+    /// timestamped ones off the list
+    #[arg(long)]
+    pub pending: bool,
+
+    /// Timestamp authority, more of them comma separated or repeated are tried in order when one keeps failing
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "TRUSTED_SIGNING_TIMESTAMP_URL",
+        default_value = TIMESTAMP_URL,
+        value_delimiter = ','
+    )]
+    pub tr: Vec<String>,
+
+    /// Timestamp digest algorithm
+    #[arg(long, value_enum, env = "TRUSTED_SIGNING_TD", default_value = "SHA256", ignore_case = true)]
+    pub td: Digest,
+
+    /// How often a timestamp authority is tried again before the next one, a second longer after every failure
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub retries: u32,
+
+    /// Signtool path, the default one or the newest of the Windows SDKs when not given
+    #[arg(long, env = "SIGNTOOL_PATH")]
+    pub sing_tool_path: Option<String>,
+
+    /// Verify signatures with `signtool verify` and its output instead of WinVerifyTrust, the only way off Windows
+    #[arg(
+        long,
+        env = "TRUSTED_SIGNING_VERIFY_WITH_SIGNTOOL",
+        action = ArgAction::SetTrue,
+        value_parser = BoolishValueParser::new()
+    )]
+    pub verify_with_signtool: bool,
+
+    /// Output format, json prints the timestamp of every file to stdout
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+/// Remove signatures from files
+#[derive(clap::Args, Debug)]
+#[command(after_help = error::exit_codes_help())]
+pub struct UnsignArgs {
+    /// File(s) or globs of signed files to remove the signatures of
+    #[arg(value_name = "FILE(S)", required = true)]
+    pub file: Vec<String>,
+
+    /// Copy every file to `<file>.bak` before removing its signatures, refused when that copy exists already
+    #[arg(long)]
+    pub backup: bool,
+
+    /// Output format, json prints the signatures removed from every file to stdout
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+/// Print the signatures of files
+#[derive(clap::Args, Debug)]
+#[command(after_help = error::exit_codes_help())]
+pub struct InspectArgs {
+    /// File(s) or globs of files to inspect
+    #[arg(value_name = "FILE(S)", required = true)]
+    pub file: Vec<String>,
+
+    /// Fail when any of the files has no signature, instead of printing it with none
+    #[arg(long)]
+    pub fail_if_unsigned: bool,
+}
+
+/// Create a test signing certificate
+#[derive(clap::Args, Debug)]
+#[command(after_help = error::exit_codes_help())]
+pub struct GenerateTestCertArgs {
+    /// PFX file to write
+    #[arg(long, value_name = "PATH", default_value = "test.pfx")]
+    pub out: PathBuf,
+
+    /// Subject of the certificate, a distinguished name with a CN like `CN=MyApp Test Signing, O=Contoso`
+    #[arg(long, value_parser = testcert::parse_subject)]
+    pub subject: String,
+
+    /// Days the certificate is valid for
+    #[arg(long, value_name = "DAYS", default_value_t = 365, value_parser = clap::value_parser!(u32).range(1..=3650))]
+    pub validity_days: u32,
+
+    /// Key of the certificate
+    #[arg(long, value_enum, default_value = "ecdsa-p256")]
+    pub key_type: testcert::KeyType,
+
+    /// Environment variable holding the password to protect the PFX with. Without it a password is generated and
+    /// printed to stdout
+    #[arg(long, value_name = "VAR")]
+    pub password_env: Option<String>,
+
+    /// Replace the file at --out
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// List the known regions
+#[derive(clap::Args, Debug)]
+pub struct RegionsArgs {
+    /// Send a request to every endpoint and print how long it took to answer, to pick the closest one.
+    /// No credentials are needed
+    #[arg(long, verbatim_doc_comment)]
+    pub probe: bool,
+
+    /// Output format, json prints the regions to stdout
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+/// Reset the state of the tool
+#[derive(clap::Args, Debug)]
+pub struct PurgeArgs {
+    /// Remove everything listed without asking, needed when stdin is not a terminal
+    #[arg(long, short)]
+    pub yes: bool,
+
+    /// Also log the Azure CLI out of the account it is logged in as. That is the global az state, which other tools
+    /// use as well
+    #[arg(long)]
+    pub include_azure_session: bool,
+
+    /// Azure CLI path, only run with --include-azure-session
+    #[arg(long, env = "AZURE_CLI_PATH", default_value = azure::DEFAULT_CLI_PATH)]
+    pub azure_cli_path: String,
+}
+
+/// Update the executable from the GitHub releases
+#[derive(clap::Args, Debug)]
+pub struct SelfUpdateArgs {
+    /// Only tell whether a newer release is available
+    #[arg(long)]
+    pub check_only: bool,
+
+    /// Install the release with this tag, like v0.4.0, even when it is older
+    #[arg(long, value_name = "TAG")]
+    pub version: Option<String>,
+}
+
+/// Matches of the sign arguments, of `sign` and the commands taking the same arguments like `serve` or `config show`
+pub fn sign_matches(matches: &ArgMatches) -> Option<&ArgMatches> {
+    matches
+        .subcommand_matches("sign")
+        .or_else(|| matches.subcommand_matches("cargo"))
+        .or_else(|| matches.subcommand_matches("print-signtool-args"))
+        .or_else(|| matches.subcommand_matches("serve"))
+        .or_else(|| matches.subcommand_matches("daemon"))
+        .or_else(|| matches.subcommand_matches("sign-via-daemon"))
+        .or_else(|| matches.subcommand_matches("whoami"))
+        .or_else(|| matches.subcommand_matches("status"))
+        .or_else(|| matches.subcommand_matches("list-profiles"))
+        .or_else(|| matches.subcommand_matches("list-accounts"))
+        .or_else(|| matches.subcommand_matches("quota"))
+        .or_else(|| matches.subcommand_matches("bench"))
+        .or_else(|| matches.subcommand_matches("config").and_then(|config| config.subcommand_matches("show")))
+}
+
+/// Insert the `sign` subcommand when the arguments don't start with one,
+/// so the original flat invocation keeps working
+pub fn with_default_command(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    // no arguments at all is a sign, everything may come from the project config
+    let explicit = match args.get(1).map(|arg| arg.to_string_lossy()) {
+        None => false,
+        Some(first) => {
+            ["help", "-h", "--help", "-V", "--version"].contains(&first.as_ref())
+                || Cli::command().find_subcommand(first.as_ref()).is_some()
+        }
+    };
+    if !explicit {
+        args.insert(1, OsString::from("sign"));
+    }
+    args
+}
+
+/// Environment variable with arguments added to every command line, for build systems running a fixed command
+pub const ARGS_VAR: &str = "TRUSTED_SIGNING_CLI_ARGS";
+
+/// Split a command line like Windows programs do: whitespace separates arguments outside double quotes, `""` in
+/// quotes is a quote, and backslashes are literal unless a quote follows them, where each pair is one backslash
+fn split_windows(line: &str) -> Result<Vec<String>, String> {
+    let (mut words, mut word, mut quoted, mut started, mut backslashes) = (Vec::new(), String::new(), None, false, 0);
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if c == '\\' {
+            backslashes += 1;
+            started = true;
+            continue;
+        }
+        let escaped = c == '"' && backslashes % 2 == 1;
+        word.extend(std::iter::repeat_n('\\', if c == '"' { backslashes / 2 } else { backslashes }));
+        backslashes = 0;
+        match (c, quoted) {
+            ('"', _) if escaped => word.push('"'),
+            ('"', Some(_)) if chars.peek().is_some_and(|(_, next)| *next == '"') => {
+                chars.next();
+                word.push('"');
+            }
+            ('"', Some(_)) => quoted = None,
+            ('"', None) => quoted = Some(index),
+            (' ' | '\t' | '\n' | '\r', None) => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                }
+                started = false;
+                continue;
+            }
+            (c, _) => word.push(c),
+        }
+        started = true;
+    }
+    word.extend(std::iter::repeat_n('\\', backslashes));
+    if let Some(index) = quoted {
+        Err(format!("{} has a quote at character {} that is never closed", ARGS_VAR, index + 1))?;
+    }
+    if started {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Add `flags` to the end of `argv` but before a `--`, so they stay flags, and `files` after it, so names beginning
+/// with a dash stay files
+pub fn push_args(argv: &mut Vec<OsString>, flags: Vec<OsString>, files: Vec<OsString>) {
+    let separator = argv.iter().position(|arg| arg == "--");
+    let at = separator.unwrap_or(argv.len());
+    argv.splice(at..at, flags);
+    if separator.is_none() && files.iter().any(|file| file.to_string_lossy().starts_with('-')) {
+        argv.push(OsString::from("--"));
+    }
+    argv.extend(files);
+}
+
+/// Add the arguments of `value`, the content of [`ARGS_VAR`], to the end of `argv`, before a `--` so they stay
+/// flags. Returns the arguments added
+pub fn with_env_args(argv: &mut Vec<OsString>, value: &str) -> Result<Vec<String>, String> {
+    let added = split_windows(value)?;
+    push_args(argv, added.iter().map(OsString::from).collect(), Vec::new());
+    Ok(added)
+}
+
+/// The arguments [`with_env_args`] added, for the log, with the values of secrets masked
+pub fn describe_env_args(added: &[String]) -> String {
+    let secrets: Vec<String> = config::SECRETS.iter().map(|id| format!("--{}", id.replace('_', "-"))).collect();
+    let mut shown = Vec::new();
+    for (index, arg) in added.iter().enumerate() {
+        let secret_value = index > 0 && secrets.contains(&added[index - 1]);
+        match arg.split_once('=') {
+            _ if secret_value => shown.push("***".to_string()),
+            Some((flag, _)) if secrets.iter().any(|secret| secret == flag) => shown.push(format!("{}=***", flag)),
+            _ if arg.contains(' ') => shown.push(format!("\"{}\"", arg)),
+            _ => shown.push(arg.clone()),
+        }
+    }
+    shown.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap_complete::generate;
+    use duct::cmd;
+    use std::{io::ErrorKind, path::Path};
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn default_command() {
+        assert_eq!(
+            with_default_command(args(&["tsc", "-e", "url", "file.exe"])),
+            args(&["tsc", "sign", "-e", "url", "file.exe"])
+        );
+        assert_eq!(with_default_command(args(&["tsc", "completions", "bash"])), args(&["tsc", "completions", "bash"]));
+        assert_eq!(with_default_command(args(&["tsc", "--help"])), args(&["tsc", "--help"]));
+        assert_eq!(with_default_command(args(&["tsc"])), args(&["tsc", "sign"]));
+    }
+
+    #[test]
+    fn arguments_from_env() {
+        let split = |line: &str| split_windows(line).unwrap();
+        assert_eq!(split(r#"--verbose  --skip-signed"#), ["--verbose", "--skip-signed"]);
+        assert_eq!(split(r#"--description "My App 2" -x"#), ["--description", "My App 2", "-x"]);
+        assert_eq!(split(r#""C:\Program Files\a.exe" b"c d"e"#), [r"C:\Program Files\a.exe", "bc de"]);
+        assert_eq!(split(r#"a\\\"b "say ""hi""" c\\"#), [r#"a\"b"#, r#"say "hi""#, r"c\\"]);
+        assert_eq!(split(r#""" \\"x""#), ["", r"\x"]);
+        assert!(split("").is_empty());
+        assert!(split_windows(r#"--description "My App"#).unwrap_err().contains("character 15"));
+
+        let mut argv = args(&["tsc", "sign", "a.exe", "--", "-b.exe"]);
+        let added = with_env_args(&mut argv, "--verbose --feed-token t0k3n1").unwrap();
+        assert_eq!(argv, args(&["tsc", "sign", "a.exe", "--verbose", "--feed-token", "t0k3n1", "--", "-b.exe"]));
+        assert_eq!(describe_env_args(&added), "--verbose --feed-token ***");
+        assert_eq!(describe_env_args(&["--azure-client-secret=s".to_string()]), "--azure-client-secret=***");
+        assert!(with_env_args(&mut argv, " ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn files_after_separator() {
+        let mut argv = args(&["tsc", "-e", "eus", "-a", "acc", "-c", "prof", "--azure-client-secret", "s", "a.exe"]);
+        argv.extend(args(&["--", "-launcher.exe", "my app.exe", "--verbose"]));
+        push_args(&mut argv, args(&["--azure-client-id", "c", "--azure-tenant-id", "t"]), args(&["-more.exe"]));
+        assert_eq!(argv[10..15], args(&["--azure-client-id", "c", "--azure-tenant-id", "t", "--"]));
+        let Command::Sign(sign) = Cli::try_parse_from(with_default_command(argv)).unwrap().command else {
+            panic!("not a sign");
+        };
+        assert_eq!(sign.file, ["a.exe", "-launcher.exe", "my app.exe", "--verbose", "-more.exe"]);
+        assert!(!sign.verbose);
+
+        let mut argv = args(&["tsc", "sign"]);
+        push_args(&mut argv, args(&["--verbose"]), args(&["-a.exe", "b.exe"]));
+        assert_eq!(argv, args(&["tsc", "sign", "--verbose", "--", "-a.exe", "b.exe"]));
+
+        // --sp-file stands in for the credentials and can't be combined with them
+        let sp = |extra: &[&str]| {
+            let argv = ["tsc", "-e", "eus", "-a", "acc", "-c", "prof", "--sp-file", "sp.json"].iter().chain(extra);
+            Cli::try_parse_from(with_default_command(args(&argv.copied().collect::<Vec<_>>())))
+        };
+        let Command::Sign(sign) = sp(&["a.exe"]).unwrap().command else {
+            panic!("not a sign");
+        };
+        assert_eq!((sign.azure_client_id.as_str(), sign.sp_file.as_deref()), ("", Some(Path::new("sp.json"))));
+        let conflict = sp(&["--azure-client-id", "c", "a.exe"]).unwrap_err();
+        assert_eq!(conflict.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn inno_setup_command_line() {
+        // the sign tool of the README, `"trusted-signing-cli.exe" --single -e eus -a my-account -c public-trust $f`,
+        // Inno Setup replaces $f with the quoted path so it arrives as a single argument
+        let mut argv = args(&["tsc.exe", "--single", "-e", "eus", "-a", "my-account", "-c", "public-trust"]);
+        argv.push(OsString::from(r"C:\My Project\Output\My Setup.exe"));
+        // the credentials come from the environment
+        argv.extend(args(&["--azure-client-secret", "s", "--azure-client-id", "c", "--azure-tenant-id", "t"]));
+
+        let Command::Sign(sign) = Cli::try_parse_from(with_default_command(argv)).unwrap().command else {
+            panic!("not a sign");
+        };
+        assert!(sign.single);
+        assert_eq!(sign.file, [r"C:\My Project\Output\My Setup.exe"]);
+        assert_eq!(sign.endpoint.as_deref(), Some("https://eus.codesigning.azure.net"));
+    }
+
+    #[test]
+    fn completions_parse() {
+        let dir = tempfile::tempdir().unwrap();
+        let shells = [
+            (Shell::Bash, "bash", vec!["-n"]),
+            (Shell::Zsh, "zsh", vec!["-n"]),
+            (Shell::Fish, "fish", vec!["--no-execute"]),
+            (Shell::PowerShell, "pwsh", vec!["-NoProfile", "-NonInteractive", "-File"]),
+        ];
+
+        for (shell, program, flags) in shells {
+            let mut script = Vec::new();
+            generate(shell, &mut Cli::command(), "trusted-signing-cli", &mut script);
+            assert!(!script.is_empty());
+
+            let extension = if shell == Shell::PowerShell { "ps1" } else { program };
+            let path = dir.path().join(format!("completions.{}", extension));
+            std::fs::write(&path, script).unwrap();
+
+            // only check the syntax with the shells installed on this machine
+            let args = flags.into_iter().map(OsString::from).chain([path.into_os_string()]);
+            match cmd(program, args).stdout_null().stderr_capture().unchecked().run() {
+                Ok(output) => assert!(
+                    output.status.success(),
+                    "{} rejected the script: {}",
+                    program,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => panic!("{} could not be run: {}", program, err),
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    scratch,
+    signtool::{self, Digest},
+    xml,
+};
+use duct::cmd;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, error, warn};
+
+/// Where the Windows SDKs put mage.exe
+const MAGE_PATTERN: &str = r"C:\Program Files (x86)\Microsoft SDKs\Windows\*\bin\NETFX*Tools\mage.exe";
+
+/// How mage is asked to sign
+#[derive(Debug)]
+pub struct Options<'a> {
+    /// SHA-1 thumbprint of the certificate in the certificate store
+    pub certificate_hash: &'a str,
+    pub file_digest: Digest,
+    pub timestamp_url: &'a str,
+}
+
+/// Whether the file is a ClickOnce or VSTO deployment manifest, judged by its extension
+pub fn is_deployment(path: &str) -> bool {
+    let extension = Path::new(path).extension().unwrap_or_default();
+    extension.eq_ignore_ascii_case("application") || extension.eq_ignore_ascii_case("vsto")
+}
+
+/// Whether the file is an application manifest, signed with the deployment manifest referencing it
+pub fn is_manifest(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("manifest"))
+}
+
+/// The mage to run, `path` when given, then the newest one of the Windows SDKs and the PATH
+pub fn find(path: Option<&str>) -> Result<PathBuf, Error> {
+    if let Some(path) = path {
+        return match Path::new(path).is_file() {
+            true => Ok(PathBuf::from(path)),
+            false => Err(Error::MissingDependency {
+                component: "mage",
+                message: format!("mage '{}' was not found", path),
+            }),
+        };
+    }
+    let executable = if cfg!(windows) { "mage.exe" } else { "mage" };
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join(executable)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let Some(mage) = signtool::newest(MAGE_PATTERN).into_iter().chain(on_path).find(|candidate| candidate.is_file())
+    else {
+        Err(Error::MissingDependency {
+            component: "mage",
+            message: "mage.exe was not found, signing ClickOnce manifests needs it from the Windows SDK's .NET \
+                      Framework tools: install them with Visual Studio or pass --mage-path"
+                .to_string(),
+        })?
+    };
+    debug!("signing ClickOnce manifests with {}", mage.display());
+    Ok(mage)
+}
+
+/// The codebase of the application manifest a deployment manifest installs, as written in it
+fn codebase(deployment: &str) -> Option<String> {
+    xml::start_tags(deployment, "dependentAssembly")
+        .find(|tag| xml::attribute(tag, "dependencyType").as_deref() == Some("install"))
+        .and_then(|tag| xml::attribute(tag, "codebase"))
+}
+
+/// The codebase of the application manifest referenced by a deployment manifest and where it is, relative to it
+pub fn application_manifest(deployment: &Path) -> Result<(String, PathBuf), String> {
+    let content = fs::read_to_string(deployment)
+        .map_err(|err| format!("'{}' could not be read: {}", deployment.display(), err))?;
+    let codebase = codebase(&content)
+        .ok_or_else(|| format!("'{}' references no application manifest to install", deployment.display()))?;
+    let dir = deployment.parent().unwrap_or(Path::new(""));
+    // the codebase is a Windows path or a URL, relative to the deployment manifest
+    let path = codebase.split(['\\', '/']).fold(dir.to_path_buf(), |path, part| path.join(part));
+    Ok((codebase, path))
+}
+
+/// The signature algorithm mage names the file digest by, it only knows SHA1 and SHA256
+fn algorithm(digest: Digest) -> Result<&'static str, Error> {
+    match digest {
+        Digest::Sha1 => Ok("sha1RSA"),
+        Digest::Sha256 => Ok("sha256RSA"),
+        _ => Err(Error::Usage(format!(
+            "mage can't sign ClickOnce manifests with {}, pass --fd SHA256",
+            digest.name()
+        ))),
+    }
+}
+
+/// The signing arguments of mage shared by both manifests
+fn signing_arguments(options: &Options) -> Result<Vec<OsString>, Error> {
+    Ok([
+        "-CertHash",
+        options.certificate_hash,
+        "-TimestampUri",
+        options.timestamp_url,
+        "-Algorithm",
+        algorithm(options.file_digest)?,
+    ]
+    .map(OsString::from)
+    .to_vec())
+}
+
+/// Arguments of mage for signing the application manifest
+pub fn sign_arguments(manifest: &Path, options: &Options) -> Result<Vec<OsString>, Error> {
+    let mut args = vec![OsString::from("-Sign"), manifest.into()];
+    args.extend(signing_arguments(options)?);
+    Ok(args)
+}
+
+/// Arguments of mage for updating the deployment manifest with the hash of the signed application manifest and
+/// signing it, keeping the codebase it had
+pub fn update_arguments(
+    deployment: &Path,
+    manifest: &Path,
+    codebase: &str,
+    options: &Options,
+) -> Result<Vec<OsString>, Error> {
+    let mut args: Vec<OsString> = vec!["-Update".into(), deployment.into(), "-AppManifest".into(), manifest.into()];
+    args.extend(["-AppCodeBase", codebase].map(OsString::from));
+    args.extend(signing_arguments(options)?);
+    Ok(args)
+}
+
+/// Copies of files taken before they are changed, to put back when changing them fails part-way
+struct Backup {
+    dir: scratch::Dir,
+    files: Vec<PathBuf>,
+}
+
+impl Backup {
+    fn of(files: &[&Path]) -> Result<Self, String> {
+        let dir = scratch::dir("trusted-signing-clickonce", files.first().copied())
+            .map_err(|err| format!("the backup directory could not be created: {}", err))?;
+        for (index, file) in files.iter().enumerate() {
+            fs::copy(file, dir.path().join(index.to_string()))
+                .map_err(|err| format!("'{}' could not be backed up: {}", file.display(), err))?;
+        }
+        Ok(Self { dir, files: files.iter().map(|file| file.to_path_buf()).collect() })
+    }
+
+    fn restore(&self) -> Result<(), String> {
+        for (index, file) in self.files.iter().enumerate() {
+            fs::copy(self.dir.path().join(index.to_string()), file)
+                .map_err(|err| format!("'{}' could not be restored: {}", file.display(), err))?;
+        }
+        Ok(())
+    }
+}
+
+/// Run mage with `args`, failing with the deployment manifest as the file
+fn mage(mage: &Path, args: Vec<OsString>, file: &str, message: &str) -> Result<(), Error> {
+    let output = run_captured(cmd(mage, args)).map_err(|err| Error::MissingDependency {
+        component: "mage",
+        message: format!("mage '{}' could not be run: {}", mage.display(), err),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+/// Whether mage left a signature in the manifest
+fn verify(manifest: &Path, file: &str) -> Result<(), Error> {
+    let content = fs::read_to_string(manifest).unwrap_or_default();
+    match content.contains("<Signature") || content.contains(":Signature") {
+        true => Ok(()),
+        false => Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("mage reported success but '{}' has no signature", manifest.display()),
+            output: String::new(),
+        }),
+    }
+}
+
+/// Sign a ClickOnce or VSTO deployment with mage: the application manifest first, then the deployment manifest
+/// with its new hash. Both are put back as they were when either fails, a deployment with one half re-signed
+/// doesn't install
+pub fn sign(mage_path: &Path, options: &Options, file: &str) -> Result<(), Error> {
+    let deployment = Path::new(file);
+    let (codebase, manifest) = application_manifest(deployment)
+        .map_err(|message| Error::UnsupportedInput { path: file.to_string(), message })?;
+    if !manifest.is_file() {
+        Err(Error::UnsupportedInput {
+            path: file.to_string(),
+            message: format!("the application manifest '{}' of '{}' does not exist", manifest.display(), file),
+        })?;
+    }
+    let sign = sign_arguments(&manifest, options)?;
+    let update = update_arguments(deployment, &manifest, &codebase, options)?;
+    let backup = Backup::of(&[deployment, &manifest])?;
+
+    debug!("signing the application manifest {}", manifest.display());
+    let result = mage(mage_path, sign, file, &format!("mage could not sign '{}'", manifest.display()))
+        .and_then(|_| verify(&manifest, file))
+        .and_then(|_| mage(mage_path, update, file, &format!("mage could not update and sign '{}'", file)))
+        .and_then(|_| verify(deployment, file));
+    if result.is_err() {
+        match backup.restore() {
+            Ok(()) => warn!("put back '{}' and '{}' as they were before signing", file, manifest.display()),
+            Err(err) => error!("{}, the deployment may be inconsistent", err),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPLOYMENT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<asmv1:assembly xmlns="urn:schemas-microsoft-com:asm.v2" xmlns:asmv1="urn:schemas-microsoft-com:asm.v1">
+  <deployment install="true" mapFileExtensions="true" />
+  <dependency>
+    <dependentAssembly dependencyType="preRequisite" allowDelayedBinding="true">
+      <assemblyIdentity name="Microsoft.Windows.CommonLanguageRuntime" version="4.0.30319.0" />
+    </dependentAssembly>
+  </dependency>
+  <dependency>
+    <dependentAssembly dependencyType="install" codebase="Application Files\My&amp;App_1_0_0_3\MyApp.exe.manifest" size="4311">
+      <assemblyIdentity name="MyApp.exe" version="1.0.0.3" />
+    </dependentAssembly>
+  </dependency>
+</asmv1:assembly>
+"#;
+
+    #[test]
+    fn clickonce_deployment() {
+        assert!(is_deployment(r"publish\MyApp.application"));
+        assert!(is_deployment("MyAddIn.VSTO"));
+        assert!(is_manifest("MyApp.exe.manifest"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let deployment = dir.path().join("MyApp.application");
+        fs::write(&deployment, DEPLOYMENT).unwrap();
+        let (codebase, manifest) = application_manifest(&deployment).unwrap();
+        assert_eq!(codebase, r"Application Files\My&App_1_0_0_3\MyApp.exe.manifest");
+        let files = dir.path().join("Application Files");
+        assert_eq!(manifest, files.join("My&App_1_0_0_3").join("MyApp.exe.manifest"));
+
+        let options = Options {
+            certificate_hash: "0A1B2C",
+            file_digest: Digest::Sha256,
+            timestamp_url: "http://timestamp.acs.microsoft.com",
+        };
+        let args = update_arguments(Path::new("MyApp.application"), Path::new("MyApp.exe.manifest"), "app", &options);
+        let args: Vec<String> = args.unwrap().into_iter().map(|arg| arg.to_string_lossy().to_string()).collect();
+        assert_eq!(
+            args.join(" "),
+            "-Update MyApp.application -AppManifest MyApp.exe.manifest -AppCodeBase app -CertHash 0A1B2C \
+             -TimestampUri http://timestamp.acs.microsoft.com -Algorithm sha256RSA"
+        );
+        let options = Options { file_digest: Digest::Sha384, ..options };
+        assert!(sign_arguments(&manifest, &options).is_err());
+
+        fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+        fs::write(&manifest, "unsigned").unwrap();
+        let backup = Backup::of(&[&deployment, &manifest]).unwrap();
+        fs::write(&deployment, "half signed").unwrap();
+        fs::write(&manifest, "signed").unwrap();
+        backup.restore().unwrap();
+        assert_eq!(fs::read_to_string(&deployment).unwrap(), DEPLOYMENT);
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), "unsigned");
+    }
+}
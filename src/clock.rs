@@ -0,0 +1,125 @@
+//! Comparing the local clock with a server's. A clock minutes off fails timestamps and tokens with errors that
+//! don't mention the time
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "rest")]
+use reqwest::header::DATE;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Duration;
+use url::Url;
+
+/// How far the local clock may be off before it is warned about, Entra ID tolerates five minutes for tokens
+pub const MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// How long the server may take to answer, the check must stay cheap
+#[cfg(feature = "rest")]
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the local clock compares with a server's
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[schemars(rename = "ClockCheck")]
+pub struct Check {
+    /// The host whose Date header the clock was compared with
+    pub source: String,
+    /// How many seconds the local clock is ahead, negative when it is behind. `None` when the server couldn't tell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skew_seconds: Option<i64>,
+    /// Whether the skew is beyond [`MAX_SKEW`]
+    pub skewed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A number of seconds as `9m 12s`
+fn describe(seconds: u64) -> String {
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{}s", seconds),
+        (0, minutes, seconds) => format!("{}m {}s", minutes, seconds),
+        (hours, minutes, _) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+impl Check {
+    /// How far off the clock is, like `9m 12s ahead of eus.codesigning.azure.net`
+    pub fn offset(&self) -> Option<String> {
+        let skew = self.skew_seconds?;
+        let direction = if skew >= 0 { "ahead of" } else { "behind" };
+        Some(format!("{} {} {}", describe(skew.unsigned_abs()), direction, self.source))
+    }
+
+    /// What is wrong with the clock and how to fix it, when it is skewed
+    pub fn warning(&self) -> Option<String> {
+        let offset = self.offset().filter(|_| self.skewed)?;
+        Some(format!(
+            "the clock of this machine is {}, timestamps and azure tokens fail validation with a clock that far off. \
+             Sync it, with `w32tm /resync` on Windows or `timedatectl set-ntp true` on Linux",
+            offset
+        ))
+    }
+}
+
+/// Seconds the local clock is ahead of a server answering with `date` to a request sent at `sent` and answered at
+/// `received`, the server's time is taken to be halfway
+pub fn skew(sent: DateTime<Utc>, received: DateTime<Utc>, date: &str) -> Result<i64, String> {
+    let server = DateTime::parse_from_rfc2822(date).map_err(|err| format!("Date header '{}': {}", date, err))?;
+    let local = sent + (received - sent) / 2;
+    Ok((local - server.with_timezone(&Utc)).num_seconds())
+}
+
+/// Compare the local clock with the Date header `url` answers a HEAD request with, whatever its status
+pub async fn check(url: &str) -> Check {
+    let source = Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or(url.to_string());
+    let mut check = Check { source, skew_seconds: None, skewed: false, error: None };
+    let sent = Utc::now();
+    match date(url).await.and_then(|date| skew(sent, Utc::now(), &date)) {
+        Ok(skew) => {
+            check.skew_seconds = Some(skew);
+            check.skewed = skew.unsigned_abs() > MAX_SKEW.as_secs();
+        }
+        Err(err) => check.error = Some(err),
+    }
+    check
+}
+
+/// The Date header `url` answers a HEAD request with
+#[cfg(feature = "rest")]
+async fn date(url: &str) -> Result<String, String> {
+    let client = reqwest::Client::builder().timeout(TIMEOUT).build().map_err(|err| err.to_string())?;
+    match client.head(url).send().await {
+        Ok(response) => match response.headers().get(DATE).and_then(|date| date.to_str().ok()) {
+            Some(date) => Ok(date.to_string()),
+            None => Err("the server sent no Date header".to_string()),
+        },
+        Err(err) if err.is_timeout() => Err("the server didn't answer in time".to_string()),
+        Err(_) => Err("the server could not be reached".to_string()),
+    }
+}
+
+/// Without the rest feature there is no HTTP client to ask a server for the time with
+#[cfg(not(feature = "rest"))]
+async fn date(_url: &str) -> Result<String, String> {
+    Err("this build makes no HTTP requests, it is built without the rest feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skew_from_date() {
+        let sent = DateTime::parse_from_rfc3339("2026-03-02T10:09:10Z").unwrap().with_timezone(&Utc);
+        let received = sent + chrono::Duration::seconds(2);
+        assert_eq!(skew(sent, received, "Mon, 02 Mar 2026 10:00:00 GMT"), Ok(551));
+        assert_eq!(skew(sent, received, "Mon, 02 Mar 2026 10:09:11 GMT"), Ok(0));
+        assert!(skew(sent, received, "yesterday").is_err());
+
+        let source = "eus.codesigning.azure.net".to_string();
+        let check = Check { source, skew_seconds: Some(551), skewed: true, error: None };
+        let warning = check.warning().unwrap();
+        assert!(warning.starts_with("the clock of this machine is 9m 11s ahead of eus.codesigning.azure.net"));
+        let check = Check { skew_seconds: Some(-3), skewed: false, ..check };
+        assert_eq!(check.warning(), None);
+        assert_eq!(check.offset().as_deref(), Some("3s behind eus.codesigning.azure.net"));
+    }
+}
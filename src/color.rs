@@ -0,0 +1,57 @@
+use crate::ci;
+use clap::ValueEnum;
+use console::Term;
+use std::ffi::OsStr;
+
+/// When to use colors, accepted by `--color`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Colors when stderr is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Decide whether human output on stderr is colored and configure `console` accordingly.
+/// Checking the terminal features also switches Windows consoles to ANSI processing,
+/// which older conhost versions don't enable by default.
+pub fn init(choice: ColorChoice) -> bool {
+    // the log of a CI job isn't a terminal, whatever it claims
+    let supported = Term::stderr().features().colors_supported() && !ci::strict();
+    let enabled = enabled(choice, supported, std::env::var_os("NO_COLOR").as_deref());
+    console::set_colors_enabled_stderr(enabled);
+    enabled
+}
+
+/// Whether to color for `--color`, a stderr that `supported` colors and the value of `NO_COLOR`, which only
+/// counts when it isn't empty
+fn enabled(choice: ColorChoice, supported: bool, no_color: Option<&OsStr>) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => supported && no_color.is_none_or(|value| value.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_precedence() {
+        let set = Some(OsStr::new("1"));
+        let empty = Some(OsStr::new(""));
+        for no_color in [None, empty, set] {
+            // --color always and never win over NO_COLOR and the terminal
+            for supported in [false, true] {
+                assert!(enabled(ColorChoice::Always, supported, no_color));
+                assert!(!enabled(ColorChoice::Never, supported, no_color));
+            }
+            assert!(!enabled(ColorChoice::Auto, false, no_color));
+        }
+        assert!(enabled(ColorChoice::Auto, true, None));
+        assert!(enabled(ColorChoice::Auto, true, empty));
+        assert!(!enabled(ColorChoice::Auto, true, set));
+    }
+}
@@ -0,0 +1,570 @@
+use crate::cli::{self, Cli};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, ValueEnum};
+use directories::BaseDirs;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use toml::{Table, Value};
+
+/// Arguments selecting the config, a config file can't set them
+const SELECTORS: [&str; 6] = ["config", "profile", "no_project_config", "config_unset_vars", "env_file", "no_env_file"];
+
+/// Arguments that must never be committed to a project config
+/// The webhook URL often carries its token in the path
+pub const SECRETS: [&str; 4] = ["azure_client_secret", "feed_token", "webhook", "webhook_header"];
+
+/// Name of the project config, looked up from the current directory upwards
+pub const PROJECT_FILE: &str = ".trusted-signing.toml";
+
+/// Environment variable moving the directory holding the signing client, its metadata and the config file
+pub const DIR_ENV: &str = "TRUSTED_SIGNING_CONFIG_DIR";
+
+/// What a config value referencing an unset environment variable gets
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnsetVars {
+    /// The config is refused, naming the variable
+    #[default]
+    Error,
+    /// The reference is replaced with nothing
+    Empty,
+}
+
+/// Whether `name` can be an environment variable referenced in a config value, `ProgramFiles(x86)` included
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || "_()".contains(char))
+}
+
+/// Index of the `}` closing the `{` that `text` starts with, braces nested in between included
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, char) in text.char_indices() {
+        match char {
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(index),
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Expand the environment variables `value` references, read with `var`:
+///
+/// - `${VAR}` and `%VAR%` are the value of VAR, a set but empty variable too
+/// - `${VAR:-default}` is the value of VAR, or `default` when VAR is unset or empty. The default is expanded
+///   itself, so defaults can reference other variables
+/// - `$$` is `$` and `%%` is `%`, so `$${...}` is a literal `${...}`
+/// - a `$` or `%` not starting a reference is kept, like the `%` of `100% sure`
+///
+/// The values of variables aren't expanded again. An unterminated `${` fails, and so does an unset variable
+/// without a default unless `unset` is [`UnsetVars::Empty`]
+pub fn expand_vars(value: &str, var: &dyn Fn(&str) -> Option<String>, unset: UnsetVars) -> Result<String, String> {
+    let lookup = |name: &str| match (var(name), unset) {
+        (Some(value), _) => Ok(value),
+        (None, UnsetVars::Empty) => Ok(String::new()),
+        (None, UnsetVars::Error) => Err(format!("the environment variable {} is not set", name)),
+    };
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find(['$', '%']) {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$$") {
+            let end = match escaped.starts_with('{') {
+                true => closing_brace(escaped).ok_or("it has an unterminated $${")? + 1,
+                false => 0,
+            };
+            expanded.push('$');
+            expanded.push_str(&escaped[..end]);
+            rest = &escaped[end..];
+        } else if let Some(reference) = rest.strip_prefix('$').filter(|rest| rest.starts_with('{')) {
+            let end = closing_brace(reference).ok_or("it has an unterminated ${")?;
+            let inner = &reference[1..end];
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (inner, None),
+            };
+            if !is_var_name(name) {
+                Err(format!("'${{{}}}' doesn't name an environment variable", inner))?;
+            }
+            let found = match default {
+                Some(default) => match var(name).filter(|value| !value.is_empty()) {
+                    Some(value) => value,
+                    None => expand_vars(default, var, unset)?,
+                },
+                None => lookup(name)?,
+            };
+            expanded.push_str(&found);
+            rest = &reference[end + 1..];
+        } else if let Some(escaped) = rest.strip_prefix("%%") {
+            expanded.push('%');
+            rest = escaped;
+        } else {
+            let (char, after) = rest.split_at(1);
+            let percent = after.find('%').filter(|end| char == "%" && is_var_name(&after[..*end]));
+            match percent {
+                Some(end) => {
+                    expanded.push_str(&lookup(&after[..end])?);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    expanded.push_str(char);
+                    rest = after;
+                }
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expand the environment variables of every string value of a config, arrays included, see [`expand_vars`]
+fn expand_table(table: &mut Table, unset: UnsetVars) -> Result<(), String> {
+    let var = |name: &str| std::env::var(name).ok();
+    for (key, value) in table.iter_mut() {
+        let values: Vec<&mut Value> = match value {
+            Value::Array(values) => values.iter_mut().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            if let Value::String(text) = value {
+                let expanded = expand_vars(text, &var, unset);
+                *text = expanded.map_err(|err| format!("sets '{}' to '{}', but {}", key, text, err))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The directory holding the signing client: `override_dir` when set, otherwise in the home directory. Service
+/// accounts and SYSTEM may have none, then it is in `%PROGRAMDATA%` on Windows and the temporary directory
+/// elsewhere
+fn resolve_dir(override_dir: Option<OsString>, home: Option<PathBuf>, program_data: Option<OsString>) -> PathBuf {
+    if let Some(dir) = override_dir.filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    match (home, program_data.filter(|dir| !dir.is_empty())) {
+        (Some(home), _) => home.join(".trusted-signing-cli"),
+        (None, Some(program_data)) => PathBuf::from(program_data).join("trusted-signing-cli"),
+        (None, None) => std::env::temp_dir().join("trusted-signing-cli"),
+    }
+}
+
+/// Directory holding the signing client, its metadata and the config file, see [`DIR_ENV`]
+pub fn dir() -> PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        let program_data = std::env::var_os("PROGRAMDATA").filter(|_| cfg!(windows));
+        resolve_dir(std::env::var_os(DIR_ENV), home, program_data)
+    })
+    .clone()
+}
+
+/// The Trusted Signing dlib, once downloaded
+pub fn lib_path() -> PathBuf {
+    dir().join("lib").join("bin").join("x64").join("Azure.CodeSigning.Dlib.dll")
+}
+
+/// Read the list in the JSON file `path`, change it and write it back with the file locked, so concurrent runs
+/// don't lose each other's changes. A damaged file starts over as an empty list rather than stopping signing,
+/// `what` names the file in errors
+pub(crate) fn update_list<V, T>(path: &Path, what: &str, change: impl FnOnce(&mut Vec<V>) -> T) -> Result<T, String>
+where
+    V: Serialize + DeserializeOwned,
+{
+    let failed = |err: io::Error| format!("{} '{}' could not be updated: {}", what, path.display(), err);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(failed)?;
+    }
+    let file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path);
+    let mut file = file.map_err(failed)?;
+    file.lock().map_err(failed)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content).map_err(failed)?;
+    let mut list: Vec<V> = serde_json::from_str(&content).unwrap_or_default();
+    let result = change(&mut list);
+    let json = serde_json::to_string_pretty(&list).map_err(|err| err.to_string())?;
+    file.set_len(0).map_err(failed)?;
+    file.rewind().map_err(failed)?;
+    file.write_all(json.as_bytes()).map_err(failed)?;
+    Ok(result)
+}
+
+/// Default location of the config file
+pub fn default_path() -> PathBuf {
+    dir().join("config.toml")
+}
+
+/// Arguments merged with the config files, and what to log about it once logging is set up
+#[derive(Debug, Default)]
+pub struct Resolved {
+    pub argv: Vec<OsString>,
+    /// Which config files values were taken from
+    pub loaded: Vec<String>,
+    /// Which config file set an argument, by argument id
+    pub sources: Vec<(String, String)>,
+    pub warnings: Vec<String>,
+}
+
+/// Merge the profile selected with `--profile` and the project config into the arguments.
+/// Precedence is flag > env > profile > project config > default, a value is only added
+/// when nothing with a higher precedence set it.
+pub fn resolve(argv: Vec<OsString>) -> Result<Resolved, String> {
+    resolve_from(argv, &std::env::current_dir().unwrap_or_default())
+}
+
+fn resolve_from(argv: Vec<OsString>, cwd: &Path) -> Result<Resolved, String> {
+    let mut resolved = Resolved {
+        argv,
+        ..Resolved::default()
+    };
+    // anything clap rejects here is reported by the real parse
+    let Ok(matches) = Cli::command().ignore_errors(true).try_get_matches_from(&resolved.argv) else {
+        return Ok(resolved);
+    };
+    let Some(sign) = cli::sign_matches(&matches) else {
+        return Ok(resolved);
+    };
+    let unset = sign.try_get_one::<UnsetVars>("config_unset_vars").ok().flatten().copied().unwrap_or_default();
+
+    if let Some(name) = sign.get_one::<String>("profile") {
+        let path = sign.get_one::<PathBuf>("config").cloned().unwrap_or_else(default_path);
+        let content =
+            fs::read_to_string(&path).map_err(|err| format!("config '{}' could not be read: {}", path.display(), err))?;
+        let (profiles, warnings) = parse(&content, &path)?;
+        let mut profile = match profiles.get(name) {
+            Some(Value::Table(profile)) => profile.clone(),
+            Some(_) => Err(format!("profile '{}' in config '{}' must be a table", name, path.display()))?,
+            None => Err(format!("profile '{}' does not exist in config '{}'", name, path.display()))?,
+        };
+        // before anything checks the paths, so errors show them expanded
+        expand_table(&mut profile, unset)
+            .map_err(|err| format!("profile '{}' in config '{}' {}", name, path.display(), err))?;
+
+        resolved.warnings.extend(warnings);
+        let source = format!("profile '{}' from {}", name, path.display());
+        let (warnings, applied) = apply(&mut resolved.argv, sign, &profile);
+        resolved.sources.extend(applied.into_iter().map(|id| (id, source.clone())));
+        resolved.loaded.push(source);
+        resolved
+            .warnings
+            .extend(warnings.into_iter().map(|warning| format!("{} in profile '{}'", warning, name)));
+    }
+
+    // a partial parse may not have filled in the flag's default
+    if !matches!(sign.try_get_one::<bool>("no_project_config"), Ok(Some(true))) {
+        if let Some(path) = discover(cwd) {
+            let content = fs::read_to_string(&path)
+                .map_err(|err| format!("project config '{}' could not be read: {}", path.display(), err))?;
+            let mut project: Table = content
+                .parse()
+                .map_err(|err| format!("project config '{}' is not valid TOML: {}", path.display(), err))?;
+            expand_table(&mut project, unset).map_err(|err| format!("project config '{}' {}", path.display(), err))?;
+            let mut warnings = Vec::new();
+            for secret in SECRETS {
+                if project.remove(secret).or_else(|| project.remove(&secret.replace('_', "-"))).is_some() {
+                    warnings.push(format!("ignoring secret '{}', it doesn't belong in a project config", secret));
+                }
+            }
+            if let Some(include) = project.remove("include") {
+                let base = path.parent().unwrap_or(Path::new("."));
+                let (files, include_warnings) = expand(&include, base);
+                warnings.extend(include_warnings);
+                project.insert("file".to_string(), Value::Array(files.into_iter().map(Value::String).collect()));
+            }
+
+            // the profile values are command line flags by now, so they win
+            let matches = Cli::command()
+                .ignore_errors(true)
+                .try_get_matches_from(&resolved.argv)
+                .map_err(|err| err.to_string())?;
+            let sign = cli::sign_matches(&matches).expect("still the sign subcommand");
+            let source = format!("project config {}", path.display());
+            let (apply_warnings, applied) = apply(&mut resolved.argv, sign, &project);
+            warnings.extend(apply_warnings);
+            resolved.sources.extend(applied.into_iter().map(|id| (id, source.clone())));
+            resolved.loaded.push(source);
+            resolved
+                .warnings
+                .extend(warnings.into_iter().map(|warning| format!("{} in '{}'", warning, path.display())));
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Find the project config in `dir` or any of its parents
+fn discover(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|dir| dir.join(PROJECT_FILE)).find(|path| path.is_file())
+}
+
+/// Expand include globs relative to `base`, the directory of the project config
+fn expand(include: &Value, base: &Path) -> (Vec<String>, Vec<String>) {
+    let patterns = match include {
+        Value::String(pattern) => vec![pattern.as_str()],
+        Value::Array(patterns) => patterns.iter().filter_map(Value::as_str).collect(),
+        _ => return (Vec::new(), vec!["'include' must be a list of globs".to_string()]),
+    };
+
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    for pattern in patterns {
+        let absolute = base.join(pattern);
+        let matched: Vec<String> = match glob::glob(&absolute.to_string_lossy()) {
+            Ok(paths) => paths
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .map(|path| path.display().to_string())
+                .collect(),
+            Err(err) => {
+                warnings.push(format!("include '{}' is not a valid glob: {}", pattern, err));
+                continue;
+            }
+        };
+        if matched.is_empty() {
+            warnings.push(format!("include '{}' matched no files", pattern));
+        }
+        files.extend(matched);
+    }
+    (files, warnings)
+}
+
+/// The profiles of a config file, with warnings about keys that aren't known
+fn parse(content: &str, path: &Path) -> Result<(Table, Vec<String>), String> {
+    let mut table: Table = content
+        .parse()
+        .map_err(|err| format!("config '{}' is not valid TOML: {}", path.display(), err))?;
+    let warnings = table
+        .keys()
+        .filter(|key| *key != "profiles")
+        .map(|key| format!("unknown key '{}' in config '{}'", key, path.display()))
+        .collect();
+    let profiles = match table.remove("profiles") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => Err(format!("'profiles' in config '{}' must be a table", path.display()))?,
+        None => Table::new(),
+    };
+    Ok((profiles, warnings))
+}
+
+/// Append a flag for every config value not already set by the command line or environment,
+/// returning warnings and the ids of the arguments that were set
+fn apply(argv: &mut Vec<OsString>, sign: &ArgMatches, values: &Table) -> (Vec<String>, Vec<String>) {
+    let command = Cli::command();
+    let sign_command = command.find_subcommand("sign").expect("sign is a subcommand");
+    let mut warnings = Vec::new();
+    let mut applied = Vec::new();
+    let (mut flags, mut files) = (Vec::new(), Vec::new());
+
+    for (key, value) in values {
+        let id = key.replace('-', "_");
+        let Some(arg) = sign_command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && !SELECTORS.contains(&id.as_str()))
+        else {
+            warnings.push(format!("unknown key '{}'", key));
+            continue;
+        };
+        if matches!(sign.value_source(&id), Some(ValueSource::CommandLine | ValueSource::EnvVariable)) {
+            continue;
+        }
+
+        let flag = arg.get_long().map(|long| OsString::from(format!("--{}", long)));
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => value.to_string(),
+                Value::Datetime(_) | Value::Array(_) | Value::Table(_) => {
+                    warnings.push(format!("unsupported value for '{}'", key));
+                    continue;
+                }
+            };
+            match (arg.get_action().takes_values(), value.as_str()) {
+                (true, _) if flag.is_none() => files.push(OsString::from(value)),
+                (true, _) => flags.extend(flag.clone().into_iter().chain([OsString::from(value)])),
+                (false, "true") => flags.extend(flag.clone()),
+                (false, "false") => (),
+                (false, _) => warnings.push(format!("'{}' must be true or false", key)),
+            }
+        }
+        applied.push(id);
+    }
+    cli::push_args(argv, flags, files);
+
+    (warnings, applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve_with(argv: &[&str], config: &str) -> Resolved {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, config).unwrap();
+
+        let mut argv: Vec<OsString> = argv.iter().map(OsString::from).collect();
+        argv.extend([OsString::from("--config"), path.into_os_string()]);
+        resolve_from(argv, dir.path()).unwrap()
+    }
+
+    fn strings(argv: &[OsString]) -> Vec<String> {
+        argv.iter().map(|arg| arg.to_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn profile_fills_in_missing_values() {
+        let config = r#"
+            editor = "vim"
+
+            [profiles.release]
+            endpoint = "https://eus.codesigning.azure.net"
+            account = "from-profile"
+            sing-tool-path = 'C:\signtool.exe'
+            continue_on_error = true
+            verbose = false
+            colour = "always"
+        "#;
+        let resolved = resolve_with(&["tsc", "sign", "--profile", "release", "-a", "from-cli", "a.exe"], config);
+        let argv = strings(&resolved.argv);
+
+        assert!(argv.ends_with(&[
+            "--continue-on-error".to_string(),
+            "--endpoint".to_string(),
+            "https://eus.codesigning.azure.net".to_string(),
+            "--sing-tool-path".to_string(),
+            r"C:\signtool.exe".to_string(),
+        ]));
+        assert!(!argv.contains(&"from-profile".to_string()));
+        assert!(!argv.contains(&"--verbose".to_string()));
+        assert_eq!(resolved.warnings.len(), 2);
+        assert!(resolved.warnings[0].starts_with("unknown key 'editor'"));
+        assert_eq!(resolved.warnings[1], "unknown key 'colour' in profile 'release'");
+    }
+
+    #[test]
+    fn environment_variables_in_values() {
+        let var = |name: &str| match name {
+            "RUNNER_TEMP" => Some("/tmp/runner".to_string()),
+            "SDK_ROOT" => Some(r"C:\Program Files\sdk".to_string()),
+            "ProgramFiles(x86)" => Some(r"C:\Program Files (x86)".to_string()),
+            "EMPTY" => Some(String::new()),
+            "INNER" => Some("${RUNNER_TEMP}".to_string()),
+            _ => None,
+        };
+        let table = [
+            ("${RUNNER_TEMP}/out", Ok("/tmp/runner/out")),
+            (r"${SDK_ROOT}\signtool.exe", Ok(r"C:\Program Files\sdk\signtool.exe")),
+            (r"%SDK_ROOT%\signtool.exe", Ok(r"C:\Program Files\sdk\signtool.exe")),
+            (r"%ProgramFiles(x86)%\Windows Kits", Ok(r"C:\Program Files (x86)\Windows Kits")),
+            ("${EMPTY}x", Ok("x")),
+            ("${MISSING:-fallback}", Ok("fallback")),
+            ("${EMPTY:-fallback}", Ok("fallback")),
+            ("${MISSING:-${RUNNER_TEMP}/cache}", Ok("/tmp/runner/cache")),
+            ("${MISSING:-${ALSO_MISSING:-deep}}", Ok("deep")),
+            // values aren't expanded again
+            ("${INNER}", Ok("${RUNNER_TEMP}")),
+            ("$${RUNNER_TEMP}", Ok("${RUNNER_TEMP}")),
+            ("$${a${RUNNER_TEMP}}", Ok("${a${RUNNER_TEMP}}")),
+            ("$$%%", Ok("$%")),
+            ("100% sure, costs $5", Ok("100% sure, costs $5")),
+            ("50% to 60%", Ok("50% to 60%")),
+            ("a%20b%20c", Ok("a%20b%20c")),
+            ("${MISSING}", Err("the environment variable MISSING is not set")),
+            ("%MISSING%", Err("the environment variable MISSING is not set")),
+            ("${RUNNER_TEMP", Err("it has an unterminated ${")),
+            ("${1X}", Err("'${1X}' doesn't name an environment variable")),
+        ];
+        for (value, expected) in table {
+            let expanded = expand_vars(value, &var, UnsetVars::Error);
+            assert_eq!(expanded.as_deref(), expected.map_err(|err| err.to_string()).as_deref(), "{}", value);
+        }
+        assert_eq!(expand_vars("${MISSING}/x%MISSING%", &var, UnsetVars::Empty).unwrap(), "/x");
+
+        let mut table: Table = "sing-tool-path = '${TSC_TEST_SDK}/signtool.exe'\nverbose = true\n".parse().unwrap();
+        let err = expand_table(&mut table, UnsetVars::Error).unwrap_err();
+        let expected = "but the environment variable TSC_TEST_SDK is not set";
+        assert_eq!(err, format!("sets 'sing-tool-path' to '${{TSC_TEST_SDK}}/signtool.exe', {}", expected));
+        expand_table(&mut table, UnsetVars::Empty).unwrap();
+        assert_eq!(table["sing-tool-path"].as_str(), Some("/signtool.exe"));
+    }
+
+    #[test]
+    fn config_dir_without_home() {
+        let home = Some(PathBuf::from("home"));
+        assert_eq!(resolve_dir(None, home.clone(), None), Path::new("home").join(".trusted-signing-cli"));
+        assert_eq!(resolve_dir(Some("dir".into()), home.clone(), None), Path::new("dir"));
+        assert_eq!(resolve_dir(Some("".into()), home, None), Path::new("home").join(".trusted-signing-cli"));
+        let program_data = Some(OsString::from("ProgramData"));
+        assert_eq!(resolve_dir(None, None, program_data), Path::new("ProgramData").join("trusted-signing-cli"));
+        assert_eq!(resolve_dir(None, None, None), std::env::temp_dir().join("trusted-signing-cli"));
+
+        let temp = tempfile::tempdir().unwrap();
+        let home = temp.path().join("Jürgen Müller 用户");
+        let dir = resolve_dir(None, Some(home.clone()), None);
+        assert_eq!(dir, home.join(".trusted-signing-cli"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.toml"), "[profiles.release]\n").unwrap();
+        assert_eq!(fs::read_dir(&home).unwrap().next().unwrap().unwrap().file_name(), ".trusted-signing-cli");
+    }
+
+    #[test]
+    fn missing_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[profiles.release]\n").unwrap();
+
+        let argv = ["tsc", "sign", "--profile", "nightly", "--config", path.to_str().unwrap(), "a.exe"];
+        let err = resolve_from(argv.iter().map(OsString::from).collect(), dir.path()).unwrap_err();
+        assert!(err.starts_with("profile 'nightly' does not exist"));
+    }
+
+    #[test]
+    fn project_config() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("crates").join("app");
+        fs::create_dir_all(nested.join("dist")).unwrap();
+        fs::write(nested.join("dist").join("app.exe"), "").unwrap();
+        fs::write(nested.join("dist").join("app.pdb"), "").unwrap();
+        fs::write(
+            root.path().join("crates").join(PROJECT_FILE),
+            "account = \"project\"\ncertificate = \"project\"\nazure-client-secret = \"hunter2\"\ninclude = [\"app/dist/*.exe\"]\n",
+        )
+        .unwrap();
+        let config = "[profiles.release]\naccount = \"profile\"\n";
+        let config_path = root.path().join("config.toml");
+        fs::write(&config_path, config).unwrap();
+
+        let argv = ["tsc", "sign", "--profile", "release", "--config", config_path.to_str().unwrap()];
+        let resolved = resolve_from(argv.iter().map(OsString::from).collect(), &nested).unwrap();
+        let argv = strings(&resolved.argv);
+
+        // the profile wins over the project config, the include resolves next to the project config
+        assert!(argv.ends_with(&[
+            "--account".to_string(),
+            "profile".to_string(),
+            "--certificate".to_string(),
+            "project".to_string(),
+            nested.join("dist").join("app.exe").display().to_string(),
+        ]));
+        assert!(!argv.contains(&"hunter2".to_string()));
+        assert_eq!(resolved.loaded.len(), 2);
+        assert!(resolved.warnings[0].starts_with("ignoring secret 'azure_client_secret'"));
+
+        let argv = ["tsc", "sign", "--no-project-config", "a.exe"];
+        let resolved = resolve_from(argv.iter().map(OsString::from).collect(), &nested).unwrap();
+        assert!(resolved.loaded.is_empty());
+    }
+}
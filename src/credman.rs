@@ -0,0 +1,139 @@
+use crate::error::Error;
+
+/// Turn the password blob of a credential into text. `cmdkey` and the Credential Manager UI store UTF-16,
+/// other tools UTF-8, an ASCII secret in UTF-16 has a zero byte after every character
+#[cfg_attr(not(windows), allow(dead_code))]
+fn decode_blob(blob: &[u8]) -> Result<String, String> {
+    let ascii_utf16 = blob.iter().skip(1).step_by(2).all(|byte| *byte == 0);
+    let utf16 = !blob.is_empty() && blob.len().is_multiple_of(2) && ascii_utf16;
+    let text = match utf16 {
+        true => {
+            let units: Vec<u16> = blob.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16(&units).map_err(|_| "the credential's password is not valid UTF-16".to_string())?
+        }
+        false => String::from_utf8(blob.to_vec()).map_err(|_| "the credential's password is not text".to_string())?,
+    };
+    // like a secret from a file, a trailing newline isn't part of it
+    Ok(text.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// An error of the Credential Manager, telling a missing entry from one this user may not read
+#[cfg_attr(not(windows), allow(dead_code))]
+fn error(target: &str, code: u32, action: &str) -> Error {
+    match code {
+        // ERROR_NOT_FOUND
+        1168 => Error::Usage(format!(
+            "Credential Manager has no generic credential '{}', store one with \
+             `trusted-signing-cli config set-secret {}`",
+            target, target
+        )),
+        // ERROR_ACCESS_DENIED
+        5 => Error::Auth(format!("access to the Credential Manager entry '{}' was denied", target)),
+        // ERROR_NO_SUCH_LOGON_SESSION
+        1312 => Error::Auth(format!(
+            "Credential Manager isn't available in this logon session, it needs a user profile to {} '{}'",
+            action, target
+        )),
+        code => Error::Other(format!("Credential Manager could not {} '{}', error: {}", action, target, code)),
+    }
+}
+
+/// Read the password of the generic credential `target`
+#[cfg(windows)]
+pub fn read(target: &str) -> Result<String, Error> {
+    use windows_sys::Win32::{
+        Foundation::GetLastError,
+        Security::Credentials::{CredFree, CredReadW, CREDENTIALW, CRED_TYPE_GENERIC},
+    };
+
+    let name: Vec<u16> = target.encode_utf16().chain([0]).collect();
+    let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
+    // SAFETY: `name` is a null terminated wide string, `credential` receives a buffer freed below
+    if unsafe { CredReadW(name.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+        // SAFETY: plain query of the calling thread
+        return Err(error(target, unsafe { GetLastError() }, "read"));
+    }
+    // SAFETY: CredReadW succeeded, so `credential` points to a credential whose blob has the size it states
+    let blob = unsafe {
+        let credential = &*credential;
+        let size = credential.CredentialBlobSize as usize;
+        match credential.CredentialBlob.is_null() {
+            true => Vec::new(),
+            false => std::slice::from_raw_parts(credential.CredentialBlob, size).to_vec(),
+        }
+    };
+    // SAFETY: the buffer came from CredReadW and isn't used after this
+    unsafe { CredFree(credential as *const _) };
+    let secret = decode_blob(&blob).map_err(|err| Error::Usage(format!("'{}' in Credential Manager: {}", target, err)));
+    let secret = secret?;
+    if secret.is_empty() {
+        Err(Error::Usage(format!("the Credential Manager entry '{}' has an empty password", target)))?;
+    }
+    Ok(secret)
+}
+
+/// Store `secret` as the password of the generic credential `target`, for the current user on this machine
+#[cfg(windows)]
+pub fn write(target: &str, user_name: &str, secret: &str) -> Result<(), Error> {
+    use windows_sys::Win32::{
+        Foundation::GetLastError,
+        Security::Credentials::{CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC},
+    };
+
+    let mut name: Vec<u16> = target.encode_utf16().chain([0]).collect();
+    let mut user_name: Vec<u16> = user_name.encode_utf16().chain([0]).collect();
+    // UTF-16 like cmdkey, so both tools read what the other wrote
+    let mut blob: Vec<u8> = secret.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let credential = CREDENTIALW {
+        Flags: 0,
+        Type: CRED_TYPE_GENERIC,
+        TargetName: name.as_mut_ptr(),
+        Comment: std::ptr::null_mut(),
+        // SAFETY: FILETIME is plain integers, CredWriteW ignores it
+        LastWritten: unsafe { std::mem::zeroed() },
+        CredentialBlobSize: blob.len() as u32,
+        CredentialBlob: blob.as_mut_ptr(),
+        Persist: CRED_PERSIST_LOCAL_MACHINE,
+        AttributeCount: 0,
+        Attributes: std::ptr::null_mut(),
+        TargetAlias: std::ptr::null_mut(),
+        UserName: user_name.as_mut_ptr(),
+    };
+    // SAFETY: every pointer of `credential` points into the buffers above, which outlive the call
+    if unsafe { CredWriteW(&credential, 0) } == 0 {
+        // SAFETY: plain query of the calling thread
+        return Err(error(target, unsafe { GetLastError() }, "write"));
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn read(target: &str) -> Result<String, Error> {
+    Err(Error::Usage(format!(
+        "the Credential Manager entry '{}' can't be read, Credential Manager only exists on Windows",
+        target
+    )))
+}
+
+#[cfg(not(windows))]
+pub fn write(target: &str, _user_name: &str, _secret: &str) -> Result<(), Error> {
+    Err(Error::Usage(format!(
+        "the Credential Manager entry '{}' can't be written, Credential Manager only exists on Windows",
+        target
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_blobs() {
+        let utf16: Vec<u8> = "s3cr~t".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode_blob(&utf16).unwrap(), "s3cr~t");
+        assert_eq!(decode_blob(b"s3cr~t\r\n").unwrap(), "s3cr~t");
+        assert!(decode_blob(&[0xff, 0xfe, 0xfd]).is_err());
+        assert!(matches!(error("signing", 1168, "read"), Error::Usage(_)));
+        assert!(matches!(error("signing", 5, "read"), Error::Auth(_)));
+    }
+}
@@ -0,0 +1,575 @@
+//! `daemon`, a signer kept logged in for the tools signing one file per call, and `sign-via-daemon`, its client
+
+use crate::{
+    app,
+    cli::{Args, DaemonArgs},
+    config,
+    error::Error,
+    events::Events,
+    logging, permissions,
+    route::Target,
+    scratch, serve,
+    signer::{SignOutcome, SignOptions, Signer},
+};
+use clap::{builder::PossibleValue, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::{mpsc, oneshot},
+};
+use tracing::{info, warn};
+
+/// How long a login is used before the daemon logs in again, well within the lifetime of the tokens az hands out
+const REFRESH: Duration = Duration::from_secs(45 * 60);
+
+/// Largest request line accepted, a few thousand paths
+const MAX_REQUEST: u64 = 1024 * 1024;
+
+/// The private directory of the daemon's token, and of its socket off Windows
+fn dir() -> PathBuf {
+    config::dir().join("daemon")
+}
+
+/// The token clients send with every request, readable only by the user the daemon runs as
+fn token_path() -> PathBuf {
+    dir().join("token")
+}
+
+/// Files to sign with the daemon's arguments. The target fields are those the client was given, each has to be
+/// the daemon's, and so do its signing options
+#[derive(Serialize, Deserialize, Debug)]
+struct Request {
+    token: String,
+    files: Vec<String>,
+    endpoint: Option<String>,
+    account: Option<String>,
+    certificate: Option<String>,
+    #[serde(default)]
+    options: BTreeMap<String, String>,
+}
+
+/// A line the daemon answers with, one per file after the request was accepted
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+enum Reply {
+    Accepted { endpoint: String, account: String, certificate: String },
+    Signed { file: String, duration_ms: u64 },
+    Skipped { file: String, reason: String },
+    Failed { file: String, error: String },
+    Rejected { error: String },
+}
+
+/// A file waiting for the signer
+struct Job {
+    path: String,
+    reply: oneshot::Sender<(SignOutcome, Result<(), Error>)>,
+}
+
+/// When the daemon was last busy, for the idle shutdown
+struct Activity {
+    last: Mutex<Instant>,
+    in_flight: AtomicU64,
+}
+
+impl Activity {
+    fn idle_for(&self) -> Duration {
+        match self.in_flight.load(Ordering::SeqCst) {
+            0 => self.last.lock().unwrap().elapsed(),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+struct Daemon {
+    token: String,
+    target: Target,
+    options: BTreeMap<String, String>,
+    jobs: mpsc::Sender<Job>,
+    activity: Activity,
+}
+
+/// The options of `args` other than the target that change how a file is signed, by flag, as the daemon compares
+/// them
+fn signing_options(args: &Args) -> BTreeMap<String, String> {
+    let on = |set: bool| match set {
+        true => "on".to_string(),
+        false => "off".to_string(),
+    };
+    let shown = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+    let named = |value: Option<PossibleValue>| shown(value.map(|value| value.get_name().to_string()));
+    let debug = |value: &dyn std::fmt::Debug| format!("{:?}", value);
+    let detached = args.detached_p7.as_ref().map(|dir| std::path::absolute(dir).unwrap_or(dir.clone()));
+    [
+        ("--backend", named(args.backend.to_possible_value())),
+        ("--fd", args.fd.name().to_string()),
+        ("--tr", args.tr.clone()),
+        ("--td", args.td.name().to_string()),
+        ("--description", shown(args.description.clone())),
+        ("--append-signature", on(args.append_signature)),
+        ("--detached-p7", shown(detached.map(|dir| dir.display().to_string()))),
+        ("--also-profile", shown(args.also_profile.as_ref().map(|profile| format!("{:?}", profile)))),
+        ("--signtool-template", shown(args.signtool_template.clone())),
+        ("--signtool-extra-arg", args.signtool_extra_arg.join(" ")),
+        ("--ignore-unsupported", on(args.ignore_unsupported)),
+        ("--skip-invalid", on(args.skip_invalid)),
+        ("--require-sha2", on(args.require_sha2)),
+        ("--validate-pe", on(args.validate_pe)),
+        ("--expect-arch", named(args.expect_arch.and_then(|arch| arch.to_possible_value()))),
+        ("--expect-subsystem", named(args.expect_subsystem.and_then(|subsystem| subsystem.to_possible_value()))),
+        ("--expect-subject", shown(args.expect_subject.as_ref().map(|subject| format!("{:?}", subject)))),
+        ("--expect-thumbprint", args.expect_thumbprint.join(",")),
+        ("--exclude-signed-by", debug(&args.exclude_signed_by)),
+        ("--preserve-timestamps", on(args.preserve_timestamps)),
+        ("--defer-timestamp-on-failure", on(args.defer_timestamp_on_failure)),
+    ]
+    .into_iter()
+    .map(|(flag, value)| (flag.to_string(), value))
+    .collect()
+}
+
+/// Why a request is turned down, when it is
+fn rejection(request: &Request, token: &str, target: &Target, options: &BTreeMap<String, String>) -> Option<String> {
+    if !serve::same(request.token.as_bytes(), token.as_bytes()) {
+        return Some("the token doesn't match the daemon's, it was restarted or another user's".to_string());
+    }
+    let others = [
+        ("-e", &request.endpoint, &target.endpoint),
+        ("-a", &request.account, &target.account),
+        ("-c", &request.certificate, &target.certificate),
+    ];
+    let others: Vec<String> = others
+        .into_iter()
+        .filter_map(|(flag, given, daemon)| {
+            let given = given.as_deref()?;
+            (!given.eq_ignore_ascii_case(daemon)).then(|| format!("{} {}, the daemon's is {}", flag, given, daemon))
+        })
+        .collect();
+    // a client of an older version sends no options, it gets the daemon's
+    let others = others.into_iter().chain(request.options.iter().filter_map(|(flag, given)| {
+        let daemon = options.get(flag).map(String::as_str).unwrap_or("none");
+        (given != daemon).then(|| format!("{} {}, the daemon's is {}", flag, given, daemon))
+    }));
+    let others: Vec<String> = others.collect();
+    match others.is_empty() {
+        true => None,
+        false => Some(format!("the daemon signs with other arguments: {}", others.join(", "))),
+    }
+}
+
+/// Sign the files of the jobs one after another, on a thread of its own since the signer isn't `Send`. Logs in
+/// again once the login is older than [`REFRESH`], keeping the old one when that fails. Reports whether connecting
+/// worked before taking any jobs
+fn spawn_signer(options: SignOptions, connected: oneshot::Sender<Result<(), Error>>) -> mpsc::Sender<Job> {
+    let (jobs, mut queue) = mpsc::channel::<Job>(64);
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                let _ = connected.send(Err(Error::Other(format!("signer could not start: {}", err))));
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            let mut signer = Signer::new(options.clone(), Events::default());
+            if let Err(err) = signer.connect().await {
+                let _ = connected.send(Err(err));
+                return;
+            }
+            let _ = connected.send(Ok(()));
+            let mut logged_in = Instant::now();
+            while let Some(job) = queue.recv().await {
+                if logged_in.elapsed() >= REFRESH {
+                    info!("logging in again, the login is {} minutes old", logged_in.elapsed().as_secs() / 60);
+                    // a reused session would keep the old login
+                    let mut fresh = Signer::new(options.clone().reuse_session(false), Events::default());
+                    match fresh.connect().await {
+                        Ok(()) => (signer, logged_in) = (fresh, Instant::now()),
+                        Err(err) => warn!("logging in again failed, signing with the earlier login: {}", err),
+                    }
+                }
+                let (outcome, result) = signer.attempt(&job.path).await;
+                let _ = job.reply.send((outcome, result));
+            }
+        });
+    });
+    jobs
+}
+
+impl Daemon {
+    /// Answer the request on `stream`, a line of JSON, with a line of JSON for it and for every file
+    async fn handle(&self, stream: impl AsyncRead + AsyncWrite + Unpin) {
+        let (read, mut write) = tokio::io::split(stream);
+        let mut line = String::new();
+        let request = match BufReader::new(read.take(MAX_REQUEST)).read_line(&mut line).await {
+            // a client checking whether a daemon is running
+            Ok(0) => return,
+            Ok(_) => serde_json::from_str::<Request>(&line).map_err(|err| format!("the request is invalid: {}", err)),
+            Err(err) => Err(format!("the request could not be read: {}", err)),
+        };
+        let request = request.and_then(|request| match rejection(&request, &self.token, &self.target, &self.options) {
+            Some(rejection) => Err(rejection),
+            None => Ok(request),
+        });
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => {
+                warn!("rejected a request: {}", error);
+                let _ = send(&mut write, &Reply::Rejected { error }).await;
+                return;
+            }
+        };
+        let accepted = Reply::Accepted {
+            endpoint: self.target.endpoint.clone(),
+            account: self.target.account.clone(),
+            certificate: self.target.certificate.clone(),
+        };
+        if send(&mut write, &accepted).await.is_err() {
+            return;
+        }
+        for file in request.files {
+            let (reply, signed) = oneshot::channel();
+            let _ = self.jobs.send(Job { path: file.clone(), reply }).await;
+            let reply = match signed.await {
+                Ok((outcome, Ok(()))) => match outcome.skipped {
+                    Some(reason) => Reply::Skipped { file, reason: reason.to_string() },
+                    None => Reply::Signed { file, duration_ms: outcome.duration.as_millis() as u64 },
+                },
+                Ok((_, Err(err))) => Reply::Failed { file, error: logging::mask(&err.to_string()) },
+                Err(_) => Reply::Failed { file, error: "the signer stopped".to_string() },
+            };
+            match &reply {
+                Reply::Signed { file, duration_ms } => info!("signed {} in {:.1}s", file, *duration_ms as f64 / 1000.0),
+                Reply::Skipped { file, reason } => info!("skipped {}, {}", file, reason),
+                Reply::Failed { file, error } => warn!("{} could not be signed: {}", file, error),
+                _ => (),
+            }
+            // the client went away, the files it sent later aren't signed
+            if send(&mut write, &reply).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn send(write: &mut (impl AsyncWrite + Unpin), reply: &Reply) -> io::Result<()> {
+    let mut line = serde_json::to_string(reply).map_err(io::Error::other)?;
+    line.push('\n');
+    write.write_all(line.as_bytes()).await?;
+    write.flush().await
+}
+
+/// The named pipe of the current user, its ACL only lets the user and the system open it
+#[cfg(windows)]
+mod transport {
+    use crate::{error::Error, permissions::PrivateDescriptor};
+    use std::io;
+    use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions};
+    use windows_sys::Win32::{Foundation::ERROR_PIPE_BUSY, Security::SECURITY_ATTRIBUTES};
+
+    fn name() -> String {
+        let user = std::env::var("USERNAME").unwrap_or_default();
+        let user: String = user.chars().filter(|char| char.is_ascii_alphanumeric()).collect();
+        format!(r"\\.\pipe\trusted-signing-cli-{}", user.to_ascii_lowercase())
+    }
+
+    pub struct Listener {
+        descriptor: PrivateDescriptor,
+        next: NamedPipeServer,
+    }
+
+    impl Listener {
+        fn create(descriptor: &PrivateDescriptor, first: bool) -> io::Result<NamedPipeServer> {
+            let mut attributes = descriptor.attributes();
+            let attributes: *mut SECURITY_ATTRIBUTES = &mut attributes;
+            let mut options = ServerOptions::new();
+            options.first_pipe_instance(first).reject_remote_clients(true);
+            // SAFETY: `attributes` points to the descriptor, which outlives the call
+            unsafe { options.create_with_security_attributes_raw(name(), attributes.cast()) }
+        }
+
+        pub fn bind() -> Result<Self, Error> {
+            let descriptor = PrivateDescriptor::new().map_err(Error::Other)?;
+            let next = Listener::create(&descriptor, true).map_err(|err| match err.kind() {
+                io::ErrorKind::PermissionDenied => {
+                    Error::Usage(format!("a daemon is already running, listening on {}", name()))
+                }
+                _ => Error::Other(format!("{} could not be created: {}", name(), err)),
+            })?;
+            Ok(Listener { descriptor, next })
+        }
+
+        pub fn describe(&self) -> String {
+            name()
+        }
+
+        /// The next client, the pipe instance after it is created right away
+        pub async fn accept(&mut self) -> io::Result<NamedPipeServer> {
+            self.next.connect().await?;
+            let next = Listener::create(&self.descriptor, false)?;
+            Ok(std::mem::replace(&mut self.next, next))
+        }
+    }
+
+    /// The daemon's pipe, an error when no daemon is running
+    pub async fn connect() -> io::Result<NamedPipeClient> {
+        // the daemon creates the next instance right after taking a client
+        for _ in 0..50 {
+            match ClientOptions::new().open(name()) {
+                Err(err) if err.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await
+                }
+                opened => return opened,
+            }
+        }
+        ClientOptions::new().open(name())
+    }
+}
+
+/// A socket in the private directory of the daemon, which only the user can open
+#[cfg(not(windows))]
+mod transport {
+    use crate::error::Error;
+    use std::{fs, io, path::PathBuf};
+    use tokio::net::{UnixListener, UnixStream};
+
+    fn path() -> PathBuf {
+        super::dir().join("socket")
+    }
+
+    pub struct Listener {
+        listener: UnixListener,
+    }
+
+    impl Listener {
+        pub fn bind() -> Result<Self, Error> {
+            // a socket left behind by a daemon that didn't stop cleanly
+            if std::os::unix::net::UnixStream::connect(path()).is_ok() {
+                Err(Error::Usage(format!("a daemon is already running, listening on {}", path().display())))?;
+            }
+            let _ = fs::remove_file(path());
+            let listener = UnixListener::bind(path())
+                .map_err(|err| Error::Other(format!("{} could not be created: {}", path().display(), err)))?;
+            Ok(Listener { listener })
+        }
+
+        pub fn describe(&self) -> String {
+            path().display().to_string()
+        }
+
+        pub async fn accept(&mut self) -> io::Result<UnixStream> {
+            self.listener.accept().await.map(|(stream, _)| stream)
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(path());
+        }
+    }
+
+    /// The daemon's socket, an error when no daemon is running
+    pub async fn connect() -> io::Result<UnixStream> {
+        UnixStream::connect(path()).await
+    }
+}
+
+/// Write the token only the user can read, a new one every start
+fn write_token(token: &str) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let path = token_path();
+    let written = options.open(&path).and_then(|mut file| io::Write::write_all(&mut file, token.as_bytes()));
+    written.map_err(|err| Error::Other(format!("{} could not be written: {}", path.display(), err)))
+}
+
+/// Log in, then sign what clients send until Ctrl+C or `--idle-timeout` minutes without a request
+pub async fn run(mut args: DaemonArgs) -> Result<(), Error> {
+    permissions::create_private_dir(&dir()).map_err(Error::Other)?;
+    args.sign.read_service_principal()?;
+    if let Some(root) = &args.sign.temp_dir {
+        scratch::init(root, 0)?;
+    }
+    let mut listener = transport::Listener::bind()?;
+    app::discover_endpoint(&mut args.sign)?;
+
+    let options = app::sign_options(&args.sign, args.sign.correlation_id.clone())?;
+    let (connected, ready) = oneshot::channel();
+    let jobs = spawn_signer(options, connected);
+    ready.await.map_err(|_| Error::Other("the signer stopped".to_string()))??;
+
+    let token = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    write_token(&token)?;
+    let daemon = Arc::new(Daemon {
+        token,
+        target: args.sign.target(),
+        options: signing_options(&args.sign),
+        jobs,
+        activity: Activity { last: Mutex::new(Instant::now()), in_flight: AtomicU64::new(0) },
+    });
+
+    let idle = Duration::from_secs(args.idle_timeout * 60);
+    info!("listening on {}, stopping after {} minutes without a request", listener.describe(), args.idle_timeout);
+    let stopped = loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(stream) => {
+                    let daemon = daemon.clone();
+                    daemon.activity.in_flight.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(async move {
+                        daemon.handle(stream).await;
+                        *daemon.activity.last.lock().unwrap() = Instant::now();
+                        daemon.activity.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                Err(err) => break Err(Error::Other(format!("the daemon stopped listening: {}", err))),
+            },
+            () = tokio::time::sleep(Duration::from_secs(1)) => {
+                if daemon.activity.idle_for() >= idle {
+                    info!("no request for {} minutes, stopping", args.idle_timeout);
+                    break Ok(());
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("stopping");
+                break Ok(());
+            }
+        }
+    };
+    let _ = fs::remove_file(token_path());
+    stopped
+}
+
+/// Sign the files with the running daemon, printing what it did with each. None when no daemon is running, to
+/// sign without it
+pub async fn sign(args: &Args) -> Result<Option<()>, Error> {
+    let Ok(token) = fs::read_to_string(token_path()) else {
+        return Ok(None);
+    };
+    let Ok(stream) = transport::connect().await else {
+        return Ok(None);
+    };
+    // the daemon runs elsewhere
+    let files: Vec<String> = args
+        .file
+        .iter()
+        .map(|file| std::path::absolute(file).map(|path| path.display().to_string()).unwrap_or(file.clone()))
+        .collect();
+    let request = Request {
+        token: token.trim().to_string(),
+        files: files.clone(),
+        endpoint: args.endpoint.clone(),
+        account: args.account.clone(),
+        certificate: args.certificate.clone(),
+        options: signing_options(args),
+    };
+    let (read, mut write) = tokio::io::split(stream);
+    let mut line = serde_json::to_string(&request).map_err(|err| Error::Other(err.to_string()))?;
+    line.push('\n');
+    let lost = |err: io::Error| Error::Other(format!("the daemon could not be asked: {}", err));
+    write.write_all(line.as_bytes()).await.map_err(lost)?;
+
+    let mut lines = BufReader::new(read).lines();
+    let (mut answered, mut failed) = (0, 0);
+    while let Some(line) = lines.next_line().await.map_err(lost)? {
+        let reply: Reply =
+            serde_json::from_str(&line).map_err(|err| Error::Other(format!("the daemon answered wrongly: {}", err)))?;
+        match reply {
+            Reply::Accepted { certificate, account, .. } if !args.quiet => {
+                eprintln!("signing {} file(s) with {} of {} through the daemon", files.len(), certificate, account)
+            }
+            Reply::Accepted { .. } => (),
+            Reply::Signed { file, duration_ms } => {
+                answered += 1;
+                if !args.quiet {
+                    eprintln!("signed {} in {:.1}s", file, duration_ms as f64 / 1000.0);
+                }
+            }
+            Reply::Skipped { file, reason } => {
+                answered += 1;
+                if !args.quiet {
+                    eprintln!("skipped {}, {}", file, reason);
+                }
+            }
+            Reply::Failed { file, error } => {
+                (answered, failed) = (answered + 1, failed + 1);
+                eprintln!("{}", logging::mask(&format!("{} could not be signed: {}", file, error)));
+            }
+            Reply::Rejected { error } => Err(Error::Usage(format!("the daemon refused the files: {}", error)))?,
+        }
+    }
+    match (answered, failed) {
+        (answered, _) if answered < files.len() => Err(Error::Other(format!(
+            "the daemon stopped after {} of {} file(s)",
+            answered,
+            files.len()
+        ))),
+        (_, 0) => Ok(Some(())),
+        (total, failed) if failed == total => Err(Error::AllFailed { total }),
+        (total, failed) => Err(Error::PartialFailure { failed, total }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    #[test]
+    fn requests() {
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+        let mut request = Request {
+            token: "t0ken".to_string(),
+            files: vec!["C:\\dist\\app.exe".to_string()],
+            endpoint: None,
+            account: Some("ACC".to_string()),
+            certificate: None,
+            options: BTreeMap::new(),
+        };
+        let parse = |extra: &[&str]| {
+            let argv = ["tsc", "sign-via-daemon", "--azure-client-id", "c", "--azure-tenant-id", "t"];
+            let argv = argv.iter().chain(&["--azure-client-secret", "s", "a.exe"]).chain(extra);
+            let Command::SignViaDaemon(args) = Cli::try_parse_from(argv).unwrap().command else {
+                panic!("not a sign-via-daemon");
+            };
+            signing_options(&args)
+        };
+        let options = parse(&[]);
+        assert_eq!(rejection(&request, "t0ken", &target, &options), None);
+        assert!(rejection(&request, "t0keN", &target, &options).unwrap().contains("token doesn't match"));
+        request.options = parse(&[]);
+        assert_eq!(rejection(&request, "t0ken", &target, &options), None);
+        request.options = parse(&["--fd", "SHA384", "--append-signature", "--expect-arch", "x64"]);
+        let error = rejection(&request, "t0ken", &target, &options).unwrap();
+        assert_eq!(
+            error,
+            "the daemon signs with other arguments: --append-signature on, the daemon's is off, \
+             --expect-arch x64, the daemon's is none, --fd SHA384, the daemon's is SHA256"
+        );
+        request.options = options.clone();
+        request.certificate = Some("other".to_string());
+        let error = rejection(&request, "t0ken", &target, &options).unwrap();
+        assert_eq!(error, "the daemon signs with other arguments: -c other, the daemon's is prof");
+
+        let line = serde_json::to_string(&Reply::Signed { file: "a.exe".to_string(), duration_ms: 5 }).unwrap();
+        assert_eq!(line, r#"{"reply":"signed","file":"a.exe","duration_ms":5}"#);
+        let reply: Reply = serde_json::from_str(r#"{"reply":"rejected","error":"no"}"#).unwrap();
+        assert_eq!(reply, Reply::Rejected { error: "no".to_string() });
+    }
+}
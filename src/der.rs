@@ -0,0 +1,165 @@
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const UTF8_STRING: u8 = 0x0c;
+pub const PRINTABLE_STRING: u8 = 0x13;
+pub const IA5_STRING: u8 = 0x16;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const BMP_STRING: u8 = 0x1e;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+/// The constructed context-specific tag `[number]`
+pub const fn context(number: u8) -> u8 {
+    0xa0 | number
+}
+
+/// Encode a value with its tag and length
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match content.len() {
+        length @ 0..=0x7f => encoded.push(length as u8),
+        length => {
+            let bytes: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+            encoded.push(0x80 | bytes.len() as u8);
+            encoded.extend(bytes);
+        }
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+pub fn sequence(items: &[&[u8]]) -> Vec<u8> {
+    tlv(SEQUENCE, &items.concat())
+}
+
+/// A SET OF, its elements sorted by their encoding as DER wants
+pub fn set_of(mut items: Vec<Vec<u8>>) -> Vec<u8> {
+    items.sort();
+    tlv(SET, &items.concat())
+}
+
+pub fn oid(arcs: &[u64]) -> Vec<u8> {
+    let mut content = Vec::new();
+    let first = arcs[0] * 40 + arcs[1];
+    for arc in std::iter::once(first).chain(arcs[2..].iter().copied()) {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        content.extend(bytes.into_iter().rev());
+    }
+    tlv(OBJECT_IDENTIFIER, &content)
+}
+
+/// The dotted form of the content of an OBJECT IDENTIFIER, like `1.3.6.1.5.5.7.3.3`
+pub fn oid_text(content: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut arc = 0u64;
+    for byte in content {
+        arc = arc << 7 | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    let first = match arcs.first() {
+        Some(&first) => [first.min(80) / 40, first - first.min(80) / 40 * 40],
+        None => return String::new(),
+    };
+    first.iter().chain(&arcs[1..]).map(u64::to_string).collect::<Vec<_>>().join(".")
+}
+
+pub fn small_integer(value: u8) -> Vec<u8> {
+    tlv(INTEGER, &[value])
+}
+
+/// A non-negative INTEGER in as few bytes as DER allows, a leading zero keeps it positive
+pub fn integer(value: u64) -> Vec<u8> {
+    let bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+    match bytes.first() {
+        None => small_integer(0),
+        Some(first) if first & 0x80 != 0 => tlv(INTEGER, &[&[0], bytes.as_slice()].concat()),
+        Some(_) => tlv(INTEGER, &bytes),
+    }
+}
+
+pub fn null() -> Vec<u8> {
+    tlv(NULL, &[])
+}
+
+/// The big endian UTF-16 of a BMPString
+pub fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+pub fn bmp_string(text: &str) -> Vec<u8> {
+    tlv(BMP_STRING, &utf16(text))
+}
+
+/// A value read from DER
+#[derive(Debug, Clone, Copy)]
+pub struct Element<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    /// The whole encoding, tag and length included
+    pub encoded: &'a [u8],
+}
+
+/// Read the value at the start of `input`, returning it and what follows
+pub fn read(input: &[u8]) -> Option<(Element<'_>, &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let count = (first & 0x7f) as usize;
+            let bytes = rest.get(..count)?;
+            (bytes.iter().fold(0usize, |length, byte| length << 8 | *byte as usize), &rest[count..])
+        }
+        _ => return None,
+    };
+    let content = rest.get(..length)?;
+    let header = input.len() - rest.len();
+    Some((Element { tag, content, encoded: &input[..header + length] }, &rest[length..]))
+}
+
+/// The values inside a constructed value, in order
+pub fn children(content: &[u8]) -> Option<Vec<Element<'_>>> {
+    let mut children = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        let (element, next) = read(rest)?;
+        children.push(element);
+        rest = next;
+    }
+    Some(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(oid(&[1, 2, 840, 113549, 1, 7, 2]), [6, 9, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 1, 7, 2]);
+        assert_eq!(oid_text(read(&oid(&[2, 999, 3])).unwrap().0.content), "2.999.3");
+        let long = tlv(OCTET_STRING, &[7; 300]);
+        assert_eq!(long[..4], [4, 0x82, 1, 44]);
+        let (element, rest) = read(&long).unwrap();
+        assert_eq!((element.tag, element.content.len(), element.encoded.len()), (OCTET_STRING, 300, 304));
+        assert!(rest.is_empty());
+
+        let set = set_of(vec![small_integer(2), small_integer(1)]);
+        let values: Vec<&[u8]> = children(read(&set).unwrap().0.content).unwrap().iter().map(|e| e.content).collect();
+        assert_eq!(values, [[1], [2]]);
+        assert!(read(&long[..100]).is_none());
+        assert_eq!([integer(0), integer(2048), integer(128)], [vec![2, 1, 0], vec![2, 2, 8, 0], vec![2, 2, 0, 128]]);
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+/// Find an executable in the directories of PATH
+fn in_path(names: &[&str]) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Version of a `Windows Kits\10\bin\<version>` directory, for picking the newest SDK
+fn sdk_version(path: &Path) -> Vec<u32> {
+    path.components()
+        .filter_map(|component| component.as_os_str().to_str())
+        .find(|segment| segment.starts_with("10.") && segment.split('.').all(|part| part.parse::<u32>().is_ok()))
+        .map(|version| version.split('.').filter_map(|part| part.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Newest signtool of the installed Windows SDKs, or one on PATH
+pub fn signtool() -> Option<PathBuf> {
+    let program_files = std::env::var("ProgramFiles(x86)").unwrap_or_else(|_| r"C:\Program Files (x86)".to_string());
+    let pattern = Path::new(&program_files)
+        .join("Windows Kits")
+        .join("10")
+        .join("bin")
+        .join("10.*")
+        .join("x64")
+        .join("signtool.exe");
+    glob::glob(&pattern.to_string_lossy())
+        .ok()
+        .and_then(|paths| paths.filter_map(Result::ok).max_by_key(|path| sdk_version(path)))
+        .or_else(|| in_path(&["signtool.exe"]))
+}
+
+/// Azure CLI at its install location, or one on PATH
+pub fn azure_cli() -> Option<PathBuf> {
+    let program_files = std::env::var("ProgramFiles").unwrap_or_else(|_| r"C:\Program Files".to_string());
+    let default = Path::new(&program_files)
+        .join("Microsoft SDKs")
+        .join("Azure")
+        .join("CLI2")
+        .join("wbin")
+        .join("az.cmd");
+    match default.is_file() {
+        true => Some(default),
+        false => in_path(&["az.cmd", "az"]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newest_sdk() {
+        let older = Path::new("Windows Kits/10/bin/10.0.19041.0/x64/signtool.exe");
+        let newer = Path::new("Windows Kits/10/bin/10.0.22621.0/x64/signtool.exe");
+        assert!(sdk_version(newer) > sdk_version(older));
+        assert!(sdk_version(Path::new("tools/signtool.exe")).is_empty());
+    }
+}
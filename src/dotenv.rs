@@ -0,0 +1,139 @@
+use crate::cli::{self, Cli};
+use clap::CommandFactory;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A `KEY=value` assignment of a .env file
+#[derive(Debug, PartialEq, Eq)]
+struct Assignment {
+    key: String,
+    value: String,
+}
+
+/// Parse one line, `None` for blank lines and comments.
+/// Errors never contain the value, it may be a secret.
+fn parse_line(line: &str) -> Result<Option<Assignment>, &'static str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let Some((key, value)) = line.split_once('=') else {
+        return Err("expected KEY=value");
+    };
+    let key = key.trim();
+    if key.is_empty() || !key.chars().all(|char| char.is_ascii_alphanumeric() || char == '_') {
+        return Err("the key may only contain letters, digits and underscores");
+    }
+
+    let value = value.trim();
+    let value = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+            Some(end) if value[end + 2..].trim().is_empty() || value[end + 2..].trim().starts_with('#') => {
+                value[1..end + 1].to_string()
+            }
+            Some(_) => return Err("unexpected characters after the closing quote"),
+            None => return Err("the quoted value is not closed"),
+        },
+        _ => value.split(" #").next().unwrap_or("").trim().to_string(),
+    };
+
+    Ok(Some(Assignment {
+        key: key.to_string(),
+        value,
+    }))
+}
+
+/// Parse a .env file, with a warning per malformed line
+fn parse(content: &str, path: &Path) -> (Vec<Assignment>, Vec<String>) {
+    let mut assignments = Vec::new();
+    let mut warnings = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(assignment)) => assignments.push(assignment),
+            Ok(None) => (),
+            Err(err) => warnings.push(format!("{}:{}: {}, line ignored", path.display(), number + 1, err)),
+        }
+    }
+    (assignments, warnings)
+}
+
+/// The .env file that was loaded, and what to log about it once logging is set up
+#[derive(Debug, Default)]
+pub struct EnvFile {
+    pub path: Option<PathBuf>,
+    /// Variables taken from the file, not those the environment already had
+    pub keys: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Load `--env-file` or a `.env` in the current directory into the environment, before the arguments
+/// are parsed so clap picks the values up. Variables already set in the environment are kept.
+pub fn load(argv: &[OsString]) -> Result<EnvFile, String> {
+    let Ok(matches) = Cli::command().ignore_errors(true).try_get_matches_from(argv) else {
+        return Ok(EnvFile::default());
+    };
+    let Some(sign) = cli::sign_matches(&matches) else {
+        return Ok(EnvFile::default());
+    };
+    if matches!(sign.try_get_one::<bool>("no_env_file"), Ok(Some(true))) {
+        return Ok(EnvFile::default());
+    }
+
+    let path = match sign.try_get_one::<PathBuf>("env_file") {
+        Ok(Some(path)) => path.clone(),
+        _ => match Path::new(".env") {
+            path if path.is_file() => path.to_path_buf(),
+            _ => return Ok(EnvFile::default()),
+        },
+    };
+    let content =
+        fs::read_to_string(&path).map_err(|err| format!("env file '{}' could not be read: {}", path.display(), err))?;
+    let (assignments, warnings) = parse(&content, &path);
+    let mut keys = Vec::new();
+    for Assignment { key, value } in assignments {
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(&key, value);
+            keys.push(key);
+        }
+    }
+    Ok(EnvFile {
+        path: Some(path),
+        keys,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let assignment = |key: &str, value: &str| {
+            Ok(Some(Assignment {
+                key: key.to_string(),
+                value: value.to_string(),
+            }))
+        };
+        assert_eq!(parse_line("AZURE_CLIENT_ID=abc"), assignment("AZURE_CLIENT_ID", "abc"));
+        assert_eq!(parse_line("export AZURE_TENANT_ID = t # home"), assignment("AZURE_TENANT_ID", "t"));
+        assert_eq!(parse_line(r#"AZURE_CLIENT_SECRET="a#b c""#), assignment("AZURE_CLIENT_SECRET", "a#b c"));
+        assert_eq!(parse_line("EMPTY="), assignment("EMPTY", ""));
+        assert_eq!(parse_line("  # comment"), Ok(None));
+    }
+
+    #[test]
+    fn warnings_have_line_numbers_but_no_values() {
+        let content = "OK=1\nnot an assignment\nSECRET='hunter2\n";
+        let (assignments, warnings) = parse(content, Path::new(".env"));
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with(".env:2: expected KEY=value"));
+        assert!(warnings[1].starts_with(".env:3: the quoted value is not closed"));
+        assert!(!warnings.concat().contains("hunter2"));
+    }
+}
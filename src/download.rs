@@ -0,0 +1,274 @@
+//! Downloading a file over HTTPS, resuming it after a broken connection and trying again after server errors
+
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_RANGE, RANGE, USER_AGENT},
+    StatusCode,
+};
+use std::{
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+/// How often a download is tried, and the pause before the second try, doubled for each one after
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    pub attempts: u32,
+    pub pause: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry { attempts: 4, pause: Duration::from_secs(1) }
+    }
+}
+
+/// Why a download failed
+#[derive(Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// The server answered with this status, which trying again doesn't change
+    Status(StatusCode),
+    /// The connection failed or broke off, or the file could not be written
+    Other(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Status(status) => write!(f, "the server answered {}", status),
+            Failure::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+/// What went wrong with one try, and whether the next one may do better
+enum Attempt {
+    Retry(String),
+    Fail(Failure),
+}
+
+/// A file downloaded next to where it goes, so an interrupted download is never mistaken for a finished one
+fn partial(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// An error with what caused it, reqwest's alone says no more than that the request failed
+fn causes(err: &reqwest::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        // hyper repeats the io error it wraps
+        if !message.ends_with(&cause.to_string()) {
+            message.push_str(&format!(": {}", cause));
+        }
+        source = cause.source();
+    }
+    message
+}
+
+/// Statuses a server answers with when it is busy or restarting
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// One try, continuing the partial file with a range request when there is one
+async fn attempt(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    authorization: Option<&str>,
+) -> Result<u64, Attempt> {
+    let written = fs::metadata(part).map(|metadata| metadata.len()).unwrap_or(0);
+    let mut request = client.get(url).header(USER_AGENT, concat!("trusted-signing-cli/", env!("CARGO_PKG_VERSION")));
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    if written > 0 {
+        request = request.header(RANGE, format!("bytes={}-", written));
+    }
+    let mut response =
+        request.send().await.map_err(|err| Attempt::Retry(format!("the request failed: {}", causes(&err))))?;
+    let status = response.status();
+    // a server ignoring the range sends all of it again
+    let resumed = status == StatusCode::PARTIAL_CONTENT
+        && response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .is_some_and(|range| range.starts_with(&format!("bytes {}-", written)));
+    match status {
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            let _ = fs::remove_file(part);
+            return Err(Attempt::Retry("the partial download didn't fit the file, starting over".to_string()));
+        }
+        status if is_transient(status) => return Err(Attempt::Retry(format!("the server answered {}", status))),
+        status if !status.is_success() => return Err(Attempt::Fail(Failure::Status(status))),
+        _ => (),
+    }
+    let expected = response.content_length().map(|length| length + if resumed { written } else { 0 });
+    let mut file = match resumed {
+        true => fs::OpenOptions::new().append(true).open(part),
+        false => fs::File::create(part),
+    }
+    .map_err(|err| Attempt::Fail(Failure::Other(format!("'{}' could not be written: {}", part.display(), err))))?;
+    let mut total = if resumed { written } else { 0 };
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                file.write_all(&chunk).map_err(|err| {
+                    Attempt::Fail(Failure::Other(format!("'{}' could not be written: {}", part.display(), err)))
+                })?;
+                total += chunk.len() as u64;
+            }
+            Ok(None) => break,
+            Err(err) => {
+                let message = format!("the download broke off after {} bytes: {}", total, causes(&err));
+                return Err(Attempt::Retry(message));
+            }
+        }
+    }
+    match expected {
+        Some(expected) if total < expected => {
+            Err(Attempt::Retry(format!("the download broke off after {} of {} bytes", total, expected)))
+        }
+        _ => Ok(total),
+    }
+}
+
+/// Download `url` to `path`, sending `authorization` as the `Authorization` header. A broken connection is
+/// resumed where it stopped and server errors are tried again, up to `retry.attempts` times in all. Returns the
+/// size of the file
+pub async fn file(url: &str, path: &Path, authorization: Option<&str>, retry: Retry) -> Result<u64, Failure> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .read_timeout(Duration::from_secs(60))
+        .build()
+        .map_err(|err| Failure::Other(format!("no HTTP client: {}", err)))?;
+    let part = partial(path);
+    // what an earlier run left isn't known to be of this file
+    let _ = fs::remove_file(&part);
+    let mut pause = retry.pause;
+    for number in 1..=retry.attempts.max(1) {
+        match attempt(&client, url, &part, authorization).await {
+            Ok(size) => {
+                fs::rename(&part, path).map_err(|err| {
+                    Failure::Other(format!("'{}' could not be written: {}", path.display(), err))
+                })?;
+                debug!("downloaded {} bytes from {}", size, url);
+                return Ok(size);
+            }
+            Err(Attempt::Fail(failure)) => {
+                let _ = fs::remove_file(&part);
+                return Err(failure);
+            }
+            Err(Attempt::Retry(message)) if number < retry.attempts => {
+                warn!("{}, trying again in {}s ({} of {})", message, pause.as_secs(), number + 1, retry.attempts);
+                tokio::time::sleep(pause).await;
+                pause *= 2;
+            }
+            Err(Attempt::Retry(message)) => {
+                let _ = fs::remove_file(&part);
+                return Err(Failure::Other(format!("{}, gave up after {} tries", message, retry.attempts)));
+            }
+        }
+    }
+    unreachable!("the last attempt returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    /// What the server does with a request, in the order they come
+    #[derive(Clone, Copy)]
+    enum Answer {
+        /// The whole body, or the rest of it for a range request
+        Body,
+        /// Headers for the whole body, then only this many bytes of it
+        BreakOff(usize),
+        Status(u16),
+    }
+
+    /// A server answering each request with the next answer, returning its URL and the range headers it got
+    async fn serve(answers: Vec<Answer>) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/client.nupkg", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        tokio::spawn(async move {
+            for answer in answers {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    head.push(byte[0]);
+                }
+                let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+                let range = head.lines().find_map(|line| line.strip_prefix("range: bytes=")).map(str::to_string);
+                let start: usize = range.as_deref().map_or(0, |range| range.trim_end_matches('-').parse().unwrap());
+                seen.lock().unwrap().push(range.clone());
+                let response = match (answer, range) {
+                    (Answer::Status(status), _) => format!("HTTP/1.1 {} Oops\r\ncontent-length: 0\r\n\r\n", status),
+                    (Answer::BreakOff(sent), _) => {
+                        let head = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", BODY.len());
+                        head + std::str::from_utf8(&BODY[..sent]).unwrap()
+                    }
+                    (Answer::Body, Some(_)) => format!(
+                        "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes {}-{}/{}\r\ncontent-length: {}\r\n\r\n{}",
+                        start,
+                        BODY.len() - 1,
+                        BODY.len(),
+                        BODY.len() - start,
+                        std::str::from_utf8(&BODY[start..]).unwrap()
+                    ),
+                    (Answer::Body, None) => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                        BODY.len(),
+                        std::str::from_utf8(BODY).unwrap()
+                    ),
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        (url, ranges)
+    }
+
+    #[tokio::test]
+    async fn resumes_and_retries() {
+        let retry = Retry { attempts: 3, pause: Duration::ZERO };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("client.nupkg");
+
+        let (url, ranges) = serve(vec![Answer::BreakOff(10), Answer::Body]).await;
+        assert_eq!(file(&url, &path, None, retry).await, Ok(BODY.len() as u64));
+        assert_eq!(fs::read(&path).unwrap(), BODY);
+        assert_eq!(*ranges.lock().unwrap(), [None, Some("10-".to_string())]);
+        assert!(!partial(&path).exists());
+
+        let (url, _) = serve(vec![Answer::Status(503), Answer::Status(502), Answer::Body]).await;
+        assert_eq!(file(&url, &path, Some("Bearer t"), retry).await, Ok(BODY.len() as u64));
+
+        // not worth another try
+        let (url, ranges) = serve(vec![Answer::Status(401), Answer::Body]).await;
+        assert_eq!(file(&url, &path, None, retry).await, Err(Failure::Status(StatusCode::UNAUTHORIZED)));
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+
+        let (url, _) = serve(vec![Answer::Status(500); 3]).await;
+        let failure = file(&url, &dir.path().join("other.nupkg"), None, retry).await.unwrap_err();
+        assert_eq!(failure.to_string(), "the server answered 500 Internal Server Error, gave up after 3 tries");
+        assert!(!dir.path().join("other.nupkg").exists());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    signtool,
+};
+use duct::cmd;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where the Windows Driver Kit puts Inf2Cat, it only comes as an x86 build
+const INF2CAT_PATTERN: &str = r"C:\Program Files (x86)\Windows Kits\10\bin\*\x86\Inf2Cat.exe";
+
+/// Extensions of the binaries of a driver package, they are signed before the catalogs covering them
+const BINARY_EXTENSIONS: [&str; 3] = ["dll", "exe", "sys"];
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Whether the file is a catalog, judged by its extension
+pub fn is_catalog(path: &str) -> bool {
+    has_extension(path, &["cat"])
+}
+
+/// Whether the file is a binary a catalog can cover, judged by its extension
+pub fn is_binary(path: &str) -> bool {
+    has_extension(path, &BINARY_EXTENSIONS)
+}
+
+/// Move the catalogs after every other file, which otherwise keep their order. Returns whether anything moved,
+/// a catalog signed before a binary it lists doesn't cover the binary's final state
+pub fn order(files: &mut Vec<String>) -> bool {
+    let before = files.clone();
+    files.sort_by_key(|file| is_catalog(file));
+    *files != before
+}
+
+/// The catalogs an INF names with `CatalogFile=` or `CatalogFile.<platform>=` in its [Version] section
+fn catalog_names(inf: &str) -> Vec<String> {
+    let mut section = String::new();
+    let mut names = Vec::new();
+    for line in inf.lines() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = name.trim().to_ascii_lowercase();
+        } else if let (true, Some((key, value))) = (section == "version", line.split_once('=')) {
+            let value = value.trim().trim_matches('"');
+            if key.trim().to_ascii_lowercase().starts_with("catalogfile") && !names.iter().any(|name| name == value) {
+                names.push(value.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// The text of an INF, which is UTF-16 when it starts with a byte order mark
+fn inf_text(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xff, 0xfe]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => crate::encoding::decode(bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes)),
+    }
+}
+
+/// The files of the driver package in `dir` in the order they are signed: the binaries, then the catalogs.
+/// A catalog an INF names has to exist unless it is about to be generated
+pub fn files(dir: &Path, generate: bool) -> Result<Vec<String>, Error> {
+    if !dir.is_dir() {
+        Err(Error::Usage(format!("driver package '{}' is not a directory", dir.display())))?;
+    }
+    let pattern = dir.join("**").join("*");
+    let mut paths: Vec<PathBuf> = glob::glob(&pattern.to_string_lossy())
+        .map_err(|err| err.to_string())?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    let text = |path: &PathBuf| path.display().to_string();
+
+    let mut catalogs: Vec<PathBuf> = paths.iter().filter(|path| is_catalog(&text(path))).cloned().collect();
+    let infs: Vec<&PathBuf> = paths.iter().filter(|path| has_extension(&text(path), &["inf"])).collect();
+    for inf in &infs {
+        let content = fs::read(inf).map_err(|err| format!("'{}' could not be read: {}", inf.display(), err))?;
+        for name in catalog_names(&inf_text(&content)) {
+            let catalog = inf.parent().unwrap_or(dir).join(&name);
+            if !catalog.is_file() && !generate {
+                Err(Error::Usage(format!(
+                    "'{}' names the catalog '{}', which doesn't exist: generate it with inf2cat or pass --inf2cat-os",
+                    inf.display(),
+                    name
+                )))?;
+            }
+            if !catalogs.iter().any(|known| known.as_os_str().eq_ignore_ascii_case(catalog.as_os_str())) {
+                catalogs.push(catalog);
+            }
+        }
+    }
+    if catalogs.is_empty() {
+        Err(Error::Usage(format!(
+            "'{}' is not a driver package, it has neither a catalog nor an INF naming one",
+            dir.display()
+        )))?;
+    }
+    let binaries = paths.iter().map(text).filter(|path| is_binary(path));
+    Ok(binaries.chain(catalogs.iter().map(text)).collect())
+}
+
+/// The Inf2Cat to run, `path` when given, otherwise the one of the newest Windows Driver Kit or on the PATH
+pub fn find_inf2cat(path: Option<&str>) -> Result<PathBuf, Error> {
+    if let Some(path) = path {
+        return match Path::new(path).is_file() {
+            true => Ok(PathBuf::from(path)),
+            false => Err(Error::MissingDependency {
+                component: "inf2cat",
+                message: format!("inf2cat '{}' was not found", path),
+            }),
+        };
+    }
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join("Inf2Cat.exe")).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let Some(inf2cat) = signtool::newest(INF2CAT_PATTERN).into_iter().chain(on_path).find(|path| path.is_file())
+    else {
+        Err(Error::MissingDependency {
+            component: "inf2cat",
+            message: "Inf2Cat.exe was not found, generating driver catalogs needs it from the Windows Driver Kit: \
+                      install the WDK or pass --inf2cat-path"
+                .to_string(),
+        })?
+    };
+    debug!("generating catalogs with {}", inf2cat.display());
+    Ok(inf2cat)
+}
+
+/// Generate the catalogs of the driver package in `dir` from its INF files and the binaries as they are now,
+/// `os` is the list Inf2Cat takes, like `10_X64,10_ARM64`
+pub fn generate(inf2cat: &Path, dir: &Path, os: &str) -> Result<(), Error> {
+    let args = [format!("/driver:{}", dir.display()), format!("/os:{}", os)];
+    let output = run_captured(cmd(inf2cat, &args)).map_err(|err| Error::MissingDependency {
+        component: "inf2cat",
+        message: format!("inf2cat '{}' could not be run: {}", inf2cat.display(), err),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: dir.display().to_string(),
+            message: format!(
+                "inf2cat could not generate the catalogs of '{}', error: {}",
+                dir.display(),
+                output.status
+            ),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+fn u16_at(image: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(image.get(offset..offset + 2)?.try_into().ok()?) as usize)
+}
+
+pub(crate) fn u32_at(image: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(image.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+/// Where the checksum and the entry of the certificate table are in the headers of a PE image
+pub(crate) fn header_offsets(image: &[u8]) -> Option<(usize, usize)> {
+    let header = u32_at(image, 0x3c)?;
+    if image.get(header..header + 4)? != b"PE\0\0" {
+        return None;
+    }
+    let optional = header + 24;
+    let directories = match u16_at(image, optional)? {
+        0x10b => optional + 96,
+        0x20b => optional + 112,
+        _ => return None,
+    };
+    Some((optional + 64, directories + 4 * 8))
+}
+
+/// Whether a PE image has a certificate table, judged by the headers at its start
+pub fn is_signed(headers: &[u8]) -> bool {
+    header_offsets(headers).and_then(|(_, certificates)| u32_at(headers, certificates)).is_some_and(|table| table > 0)
+}
+
+/// Offset and size of the certificate table of a PE image, judged by the headers at its start
+pub fn certificate_table(headers: &[u8]) -> Option<(usize, usize)> {
+    let (_, certificates) = header_offsets(headers)?;
+    let (table, size) = (u32_at(headers, certificates)?, u32_at(headers, certificates + 4)?);
+    (table > 0 && size > 0).then_some((table, size))
+}
+
+/// The Authenticode hash of a PE image, the hash a catalog lists it by: everything but the checksum, the entry
+/// of the certificate table and the table itself. Signing only changes those, so the hash stays the same
+fn pe_hash<D: Digest>(image: &[u8]) -> Option<Vec<u8>> {
+    let (checksum, certificates) = header_offsets(image)?;
+    let (table, size) = (u32_at(image, certificates)?, u32_at(image, certificates + 4)?);
+    let end = match table {
+        0 => image.len(),
+        table => table.min(image.len()),
+    };
+    if certificates + 8 > end {
+        return None;
+    }
+
+    let mut hasher = D::new();
+    hasher.update(&image[..checksum]);
+    hasher.update(&image[checksum + 4..certificates]);
+    hasher.update(&image[certificates + 8..end]);
+    match table {
+        // an unsigned image is hashed as if padded to 8 bytes, like signing pads it
+        0 => hasher.update(vec![0; (8 - image.len() % 8) % 8]),
+        table => hasher.update(image.get(table + size..).unwrap_or_default()),
+    }
+    Some(hasher.finalize().to_vec())
+}
+
+/// The binaries of `binaries` that `catalog` doesn't list by their SHA1 or SHA256 Authenticode hash
+pub fn uncovered(catalog: &Path, binaries: &[String]) -> Result<Vec<String>, Error> {
+    let content =
+        fs::read(catalog).map_err(|err| format!("catalog '{}' could not be read: {}", catalog.display(), err))?;
+    let listed = |hash: Option<Vec<u8>>| hash.is_some_and(|hash| content.windows(hash.len()).any(|part| part == hash));
+    let mut missing = Vec::new();
+    for binary in binaries {
+        let image = fs::read(binary).map_err(|err| format!("'{}' could not be read: {}", binary, err))?;
+        if !listed(pe_hash::<Sha256>(&image)) && !listed(pe_hash::<Sha1>(&image)) {
+            missing.push(binary.clone());
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal PE32+ image: headers of one section, then its data
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x200];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c] = 0x80;
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        image.extend(b"driver code");
+        image
+    }
+
+    #[test]
+    fn catalogs_cover_binaries() {
+        let unsigned = image();
+        // what signing changes: the checksum, the certificate table entry and the appended table
+        let mut signed = unsigned.clone();
+        signed.resize(unsigned.len().next_multiple_of(8), 0);
+        let table = signed.len() as u32;
+        signed[0x98 + 64..0x98 + 68].copy_from_slice(&0x1234u32.to_le_bytes());
+        let entry = 0x98 + 112 + 4 * 8;
+        signed[entry..entry + 4].copy_from_slice(&table.to_le_bytes());
+        signed[entry + 4..entry + 8].copy_from_slice(&16u32.to_le_bytes());
+        signed.extend([0xaa; 16]);
+        assert_eq!(pe_hash::<Sha256>(&unsigned), pe_hash::<Sha256>(&signed));
+        assert!(is_signed(&signed) && !is_signed(&unsigned));
+        assert_ne!(pe_hash::<Sha256>(&unsigned), pe_hash::<Sha256>(&[unsigned.as_slice(), b"x"].concat()));
+        assert_eq!(pe_hash::<Sha256>(b"MZ not a PE"), None);
+
+        let inf = "[Version]\r\nSignature=\"$WINDOWS NT$\"\r\nCatalogFile = fabrikam.cat ; signed\r\n\
+                   CatalogFile.NTamd64=fabrikam64.cat\r\n[Strings]\r\nCatalogFile=not.cat\r\n";
+        assert_eq!(catalog_names(inf), ["fabrikam.cat", "fabrikam64.cat"]);
+        let utf16: Vec<u8> = [0xff, 0xfe].into_iter().chain(inf.encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        assert_eq!(inf_text(&utf16), inf);
+        let mut files = ["fabrikam.cat", "fabrikam.sys", "setup.exe"].map(String::from).to_vec();
+        assert!(order(&mut files));
+        assert_eq!(files, ["fabrikam.sys", "setup.exe", "fabrikam.cat"]);
+        assert!(!order(&mut files));
+    }
+}
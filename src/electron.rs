@@ -0,0 +1,397 @@
+use crate::{error::Error, hash, signtool::Digest};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Digest electron-builder asks for, `sha1` or `sha256`
+pub const HASH_VAR: &str = "ELECTRON_BUILDER_SIGN_HASH";
+
+/// Whether the signature is added next to an earlier one, `isNest` of the sign configuration
+pub const NESTED_VAR: &str = "ELECTRON_BUILDER_SIGN_NESTED";
+
+/// One call of electron-builder's sign hook
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hook {
+    /// Digest to sign with, none for the SHA-1 pass Trusted Signing can't do
+    pub digest: Option<Digest>,
+    pub nested: bool,
+}
+
+/// The hook call described by the environment, refusing to guess when the variables are missing
+pub fn from_env() -> Result<Hook, Error> {
+    let var = |name: &str| std::env::var(name).ok();
+    parse(var(HASH_VAR).as_deref(), var(NESTED_VAR).as_deref())
+}
+
+fn parse(hash: Option<&str>, nested: Option<&str>) -> Result<Hook, Error> {
+    let (Some(hash), Some(nested)) = (hash, nested) else {
+        Err(Error::Usage(format!(
+            "--electron-builder-hook is meant to be run by electron-builder's sign hook, {} and {} must be set",
+            HASH_VAR, NESTED_VAR
+        )))?
+    };
+    let digest = match hash.to_ascii_lowercase().as_str() {
+        "sha1" => None,
+        "sha256" => Some(Digest::Sha256),
+        "sha384" => Some(Digest::Sha384),
+        "sha512" => Some(Digest::Sha512),
+        hash => Err(Error::Usage(format!("{} '{}' is not a known digest", HASH_VAR, hash)))?,
+    };
+    let nested = match nested.to_ascii_lowercase().as_str() {
+        "true" | "1" => true,
+        "false" | "0" | "" => false,
+        nested => Err(Error::Usage(format!("{} '{}' must be true or false", NESTED_VAR, nested)))?,
+    };
+    Ok(Hook { digest, nested })
+}
+
+/// Flag of the @electron/windows-sign mode, passed through `signWithParams` since `signToolPath` takes no arguments
+pub const WINDOWS_SIGN_FLAG: &str = "--windows-sign-hook";
+
+/// The same as an environment variable, for setting it next to `signToolPath`
+pub const WINDOWS_SIGN_VAR: &str = "TRUSTED_SIGNING_WINDOWS_SIGN_HOOK";
+
+/// signtool options @electron/windows-sign passes that Trusted Signing has no use for, with whether they take a value
+const IGNORED_OPTIONS: [(&str, bool); 9] = [
+    ("/a", false),
+    ("/debug", false),
+    ("/du", true),
+    ("/f", true),
+    ("/n", true),
+    ("/p", true),
+    ("/sha1", true),
+    ("/t", true),
+    ("/v", false),
+];
+
+/// Whether the command line is a call of @electron/windows-sign, which runs `signToolPath` like signtool
+pub fn windows_sign_requested(argv: &[OsString]) -> bool {
+    let var = std::env::var(WINDOWS_SIGN_VAR).unwrap_or_default().to_ascii_lowercase();
+    argv.iter().any(|arg| arg == WINDOWS_SIGN_FLAG) || ["true", "1", "yes", "on"].contains(&var.as_str())
+}
+
+/// Turn the signtool command line of @electron/windows-sign, `<exe> sign /fd sha256 /tr <url> /td sha256 [/d ..]
+/// [/as] [signWithParams..] <file>`, into the sign arguments. Options of `signWithParams` that aren't signtool's
+/// are kept, so they can carry `--profile` and the like
+pub fn windows_sign_arguments(argv: Vec<OsString>) -> Result<Vec<OsString>, Error> {
+    let usage = |message: String| Error::Usage(format!("{} {}", WINDOWS_SIGN_FLAG, message));
+    let mut argv = argv.into_iter();
+    let program = argv.next().unwrap_or_default();
+    let mut rest: Vec<OsString> = argv.collect();
+    if rest.first().is_some_and(|arg| arg == "sign") {
+        rest.remove(0);
+    }
+    let file = rest.pop().ok_or_else(|| usage("expects the file to sign last, like signtool".to_string()))?;
+    if file.to_string_lossy().starts_with('-') {
+        Err(usage(format!("expects the file to sign last, not '{}'", file.to_string_lossy())))?;
+    }
+
+    let mut args = vec![program, "sign".into(), WINDOWS_SIGN_FLAG.into()];
+    let mut rest = rest.into_iter();
+    while let Some(arg) = rest.next() {
+        let text = arg.to_string_lossy().to_string();
+        let option = text.to_ascii_lowercase();
+        let mut value = |name: &str| {
+            let value = rest.next().map(|value| value.to_string_lossy().to_string());
+            value.ok_or_else(|| usage(format!("got {} without a value", name)))
+        };
+        // paths on Linux and macOS start with a slash too, signtool options are a single word
+        let signtool = option.strip_prefix('/').is_some_and(|name| name.chars().all(|c| c.is_ascii_alphanumeric()));
+        match option.as_str() {
+            "/fd" | "/td" => {
+                let value = value(&text)?.to_ascii_uppercase();
+                args.extend([option.replace('/', "--").into(), value.into()]);
+            }
+            "/tr" => args.extend(["--tr".into(), value(&text)?.into()]),
+            "/d" => args.extend(["--description".into(), value(&text)?.into()]),
+            "/as" => args.push("--append-signature".into()),
+            _ if text == WINDOWS_SIGN_FLAG => (),
+            // signWithParams given as a string keeps the quotes of quoted words
+            _ if !signtool => args.push(text.trim_matches('"').into()),
+            option => match IGNORED_OPTIONS.iter().find(|(name, _)| *name == option) {
+                Some((_, true)) => {
+                    value(&text)?;
+                }
+                Some((_, false)) => (),
+                None => Err(usage(format!("doesn't know the signtool option {}", text)))?,
+            },
+        }
+    }
+    args.push(file);
+    Ok(args)
+}
+
+/// What --update-latest-yml did to an update file of electron-builder
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct LatestYml {
+    pub path: String,
+    /// The file as it was, copied next to it when anything was rewritten
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    /// The files whose sha512 and size were rewritten
+    pub rewritten: Vec<String>,
+    /// The files referenced that aren't next to it, their entries are left as they are
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+/// A `key: value` line of a latest.yml, the key maybe starting an item of a list
+struct Line<'a> {
+    /// Everything before the value, kept as it is
+    head: &'a str,
+    key: &'a str,
+    /// Column of the key, the keys of a list item share it
+    column: usize,
+    item: bool,
+    value: &'a str,
+    /// The spaces and the line break after the value
+    tail: &'a str,
+}
+
+fn parse_line(line: &str) -> Option<Line<'_>> {
+    let content = line.trim_end();
+    let rest = content.trim_start_matches(' ');
+    let (item, rest) = match rest.strip_prefix("- ") {
+        Some(after) => (true, after.trim_start_matches(' ')),
+        None => (false, rest),
+    };
+    let (key, value) = rest.split_once(':')?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let value = value.trim_start();
+    Some(Line {
+        head: &content[..content.len() - value.len()],
+        key,
+        column: content.len() - rest.len(),
+        item,
+        value,
+        tail: &line[content.len()..],
+    })
+}
+
+/// A value without the quotes around it, with the quote
+fn unquote(value: &str) -> (&str, Option<char>) {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|value| value.strip_suffix(quote)) {
+            return (inner, Some(quote));
+        }
+    }
+    (value, None)
+}
+
+/// The file a list item or the top level names with `url` or `path`, and the lines of its `sha512` and `size`
+#[derive(Default)]
+struct Entry {
+    file: Option<String>,
+    sha512: Option<usize>,
+    size: Option<usize>,
+}
+
+fn entries(lines: &[&str]) -> Vec<Entry> {
+    // the top level first, then the items of `files`
+    let mut entries = vec![Entry::default()];
+    let (mut current, mut column) = (0, 0);
+    for (index, line) in lines.iter().enumerate() {
+        let Some(line) = parse_line(line) else {
+            continue;
+        };
+        if line.item {
+            entries.push(Entry::default());
+            (current, column) = (entries.len() - 1, line.column);
+        } else if line.column == 0 {
+            (current, column) = (0, 0);
+        } else if line.column != column {
+            continue;
+        }
+        let entry = &mut entries[current];
+        match line.key {
+            "url" | "path" => entry.file = Some(unquote(line.value).0.to_string()),
+            "sha512" => entry.sha512 = Some(index),
+            "size" => entry.size = Some(index),
+            _ => (),
+        }
+    }
+    entries
+}
+
+/// `text` of a latest.yml with the sha512 and size of the files it references as they are in `dir` now, the files
+/// whose entries changed and the files that aren't there
+fn rewritten(text: &str, dir: &Path) -> io::Result<(String, Vec<String>, Vec<String>)> {
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(str::to_string).collect();
+    let entries = entries(&text.split_inclusive('\n').collect::<Vec<_>>());
+    let (mut rewritten, mut missing) = (Vec::new(), Vec::new());
+    let mut digests: HashMap<String, (String, String)> = HashMap::new();
+    for entry in &entries {
+        let Some(file) = &entry.file else {
+            continue;
+        };
+        // an absolute url points at the file uploaded next to it
+        let name = match file.contains("://") {
+            true => file.rsplit('/').next().unwrap_or(file),
+            false => file,
+        };
+        let path = dir.join(name);
+        if !path.is_file() {
+            if !missing.iter().any(|known| known == name) {
+                missing.push(name.to_string());
+            }
+            continue;
+        }
+        if !digests.contains_key(name) {
+            let size = fs::metadata(&path)?.len().to_string();
+            digests.insert(name.to_string(), (hash::sha512_base64_file(&path)?, size));
+        }
+        let (sha512, size) = &digests[name];
+        let mut changed = false;
+        for (index, value) in [(entry.sha512, sha512), (entry.size, size)] {
+            let Some(line) = index.and_then(|index| parse_line(&lines[index])) else {
+                continue;
+            };
+            let (old, quote) = unquote(line.value);
+            if old == value {
+                continue;
+            }
+            let quote = quote.map(String::from).unwrap_or_default();
+            let new = format!("{}{}{}{}{}", line.head, quote, value, quote, line.tail);
+            lines[index.expect("parsed")] = new;
+            changed = true;
+        }
+        if changed && !rewritten.iter().any(|known| known == name) {
+            rewritten.push(name.to_string());
+        }
+    }
+    Ok((lines.concat(), rewritten, missing))
+}
+
+/// The update files at `path`: the file itself or the latest*.yml of a directory
+fn latest_ymls(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let entries = fs::read_dir(path).map_err(|err| format!("'{}' could not be read: {}", path.display(), err))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| {
+            let name = file.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+            name.starts_with("latest") && name.ends_with(".yml") && file.is_file()
+        })
+        .collect();
+    if files.is_empty() {
+        Err(Error::Usage(format!("'{}' holds no latest*.yml of electron-builder", path.display())))?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Rewrite the sha512 and size of the files the latest.yml at `path` references, or every latest*.yml of the
+/// directory, as they are after signing. A file that changes is copied to `<name>.bak` first
+pub fn update_latest_yml(path: &Path) -> Result<Vec<LatestYml>, Error> {
+    let mut updated = Vec::new();
+    for file in latest_ymls(path)? {
+        let failed = |err: io::Error| format!("'{}' could not be updated: {}", file.display(), err);
+        let text = fs::read_to_string(&file).map_err(failed)?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let (text, rewritten, missing) = rewritten(&text, dir).map_err(failed)?;
+        let backup = match rewritten.is_empty() {
+            true => None,
+            false => {
+                let mut backup = file.clone().into_os_string();
+                backup.push(".bak");
+                let backup = PathBuf::from(backup);
+                fs::copy(&file, &backup).map_err(failed)?;
+                // written next to it and renamed, electron-updater never sees half a file
+                let mut temporary = tempfile::NamedTempFile::new_in(dir).map_err(failed)?;
+                io::Write::write_all(&mut temporary, text.as_bytes()).map_err(failed)?;
+                temporary.persist(&file).map_err(|err| failed(err.error))?;
+                Some(backup.display().to_string())
+            }
+        };
+        updated.push(LatestYml { path: file.display().to_string(), backup, rewritten, missing });
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    /// Command lines @electron/windows-sign runs signToolPath with, and what they become
+    const WINDOWS_SIGN_ARGV: &str = include_str!("../tests/fixtures/windows-sign-argv.json");
+
+    #[test]
+    fn hook_from_variables() {
+        assert_eq!(
+            parse(Some("sha256"), Some("true")).unwrap(),
+            Hook {
+                digest: Some(Digest::Sha256),
+                nested: true
+            }
+        );
+        assert_eq!(parse(Some("sha1"), Some("false")).unwrap().digest, None);
+        assert!(parse(None, Some("false")).is_err());
+        assert!(parse(Some("md5"), Some("false")).is_err());
+    }
+
+    #[test]
+    fn windows_sign_command_lines() {
+        let cases: Vec<serde_json::Value> = serde_json::from_str(WINDOWS_SIGN_ARGV).unwrap();
+        let strings = |value: &serde_json::Value| -> Vec<OsString> {
+            value.as_array().unwrap().iter().map(|arg| OsString::from(arg.as_str().unwrap())).collect()
+        };
+        for case in &cases {
+            let argv = strings(&case["argv"]);
+            let translated = windows_sign_arguments(argv).unwrap();
+            assert_eq!(translated, strings(&case["expected"]), "{}", case["comment"]);
+
+            let credentials = ["--azure-client-secret", "s", "--azure-client-id", "c", "--azure-tenant-id", "t"];
+            let translated = translated.into_iter().chain(credentials.map(OsString::from));
+            let Command::Sign(sign) = Cli::try_parse_from(translated).unwrap().command else {
+                panic!("not a sign");
+            };
+            assert!(sign.windows_sign_hook);
+            assert_eq!(sign.file.len(), 1);
+            assert!(sign.file[0].contains("My App-win32-x64"));
+        }
+        let argv = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert!(windows_sign_requested(&argv(&["tsc", "sign", "/fd", "sha256", "--windows-sign-hook", "a.exe"])));
+        assert!(windows_sign_arguments(argv(&["tsc", "sign", "/fd", "sha256", "/ph", "a.exe"])).is_err());
+        assert!(windows_sign_arguments(argv(&["tsc", "sign", "/fd", "a.exe"])).is_err());
+        assert!(windows_sign_arguments(argv(&["tsc", "sign", "--windows-sign-hook"])).is_err());
+    }
+
+    #[test]
+    fn latest_yml_rewritten() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("App-Setup-1.0.0.exe"), b"MZ signed").unwrap();
+        let latest = dir.path().join("latest.yml");
+        let text = "version: 1.0.0\r\nfiles:\r\n  - url: App-Setup-1.0.0.exe\r\n    sha512: b2xk\r\n    size: 3\r\n\
+                    \x20   blockMapSize: 120\r\n  - url: 'App-1.0.0-arm64.exe'\r\n    sha512: 'b2xk'\r\n    size: 3\r\n\
+                    path: App-Setup-1.0.0.exe\r\nsha512: b2xk\r\nreleaseDate: '2026-10-14T10:00:00.000Z'\r\n";
+        fs::write(&latest, text).unwrap();
+
+        let updated = update_latest_yml(dir.path()).unwrap();
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].rewritten, ["App-Setup-1.0.0.exe"]);
+        assert_eq!(updated[0].missing, ["App-1.0.0-arm64.exe"]);
+        assert_eq!(fs::read_to_string(updated[0].backup.as_ref().unwrap()).unwrap(), text);
+        let sha512 = hash::sha512_base64_file(dir.path().join("App-Setup-1.0.0.exe")).unwrap();
+        let expected = text
+            .replacen("sha512: b2xk\r\n    size: 3\r\n", &format!("sha512: {}\r\n    size: 9\r\n", sha512), 1)
+            .replace("\nsha512: b2xk", &format!("\nsha512: {}", sha512));
+        assert_eq!(fs::read_to_string(&latest).unwrap(), expected);
+
+        // signed again with the same result, nothing to do
+        fs::remove_file(dir.path().join("latest.yml.bak")).unwrap();
+        let updated = update_latest_yml(&latest).unwrap();
+        assert!(updated[0].rewritten.is_empty() && updated[0].backup.is_none());
+        assert!(update_latest_yml(&dir.path().join("releases")).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+/// Decode the output of a child process.
+/// Windows tools like signtool write in the console code page when their output is redirected,
+/// so on non-English systems their bytes are not UTF-8 and would otherwise turn into mojibake.
+pub fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => decode_code_page(bytes).unwrap_or_else(|| String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
+
+#[cfg(windows)]
+fn decode_code_page(bytes: &[u8]) -> Option<String> {
+    use windows_sys::Win32::{
+        Globalization::{GetOEMCP, MultiByteToWideChar},
+        System::Console::GetConsoleOutputCP,
+    };
+
+    let length = i32::try_from(bytes.len()).ok()?;
+    // SAFETY: plain queries without arguments
+    let code_page = match unsafe { GetConsoleOutputCP() } {
+        0 => unsafe { GetOEMCP() },
+        code_page => code_page,
+    };
+
+    // SAFETY: the input pointer and length describe `bytes`, the first call only measures
+    let wide_length = unsafe { MultiByteToWideChar(code_page, 0, bytes.as_ptr(), length, std::ptr::null_mut(), 0) };
+    if wide_length <= 0 {
+        return None;
+    }
+    let mut wide = vec![0u16; wide_length as usize];
+    // SAFETY: `wide` has room for exactly `wide_length` code units
+    let written = unsafe { MultiByteToWideChar(code_page, 0, bytes.as_ptr(), length, wide.as_mut_ptr(), wide_length) };
+    if written <= 0 {
+        return None;
+    }
+    wide.truncate(written as usize);
+
+    Some(String::from_utf16_lossy(&wide))
+}
+
+#[cfg(not(windows))]
+fn decode_code_page(_bytes: &[u8]) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_is_kept() {
+        assert_eq!(decode("Signiert: Übersicht.exe".as_bytes()), "Signiert: Übersicht.exe");
+    }
+
+    #[test]
+    fn invalid_utf8_still_decodes() {
+        // "Gr\xfc\xdfe" is latin-1/cp1252 and not valid UTF-8
+        let decoded = decode(b"Gr\xfc\xdfe");
+        assert!(decoded.starts_with("Gr"));
+        assert!(decoded.ends_with('e'));
+    }
+}
@@ -0,0 +1,241 @@
+use serde::Serialize;
+#[cfg(feature = "rest")]
+use std::time::Instant;
+use std::time::Duration;
+
+/// Region codes of Trusted Signing endpoints, with the Azure region they're in
+pub const REGIONS: [(&str, &str); 9] = [
+    ("eus", "East US"),
+    ("jpe", "Japan East"),
+    ("neu", "North Europe"),
+    ("scus", "South Central US"),
+    ("wcus", "West Central US"),
+    ("weu", "West Europe"),
+    ("wus", "West US"),
+    ("wus2", "West US 2"),
+    ("wus3", "West US 3"),
+];
+
+const HOST_SUFFIX: &str = ".codesigning.azure.net";
+
+/// Host suffixes of the clouds Trusted Signing is offered in. It has no endpoints in the sovereign clouds yet, their
+/// suffixes go here once it does, until then they need `--allow-custom-endpoint`
+const HOST_SUFFIXES: [&str; 1] = [HOST_SUFFIX];
+
+/// Canonical endpoint URL of a region
+pub fn url(region: &str) -> String {
+    format!("https://{}{}", region, HOST_SUFFIX)
+}
+
+/// The region code of an Azure location like `westus2`, the name of the region without spaces as ARM writes it
+pub fn region_of(location: &str) -> Option<&'static str> {
+    REGIONS
+        .iter()
+        .find(|(_, name)| name.replace(' ', "").eq_ignore_ascii_case(location))
+        .map(|(code, _)| *code)
+}
+
+/// How long a probed endpoint gets to answer
+#[cfg(feature = "rest")]
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A known region, as `regions` lists it
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub url: String,
+    /// Whether `-e <code>` expands to the URL, other regions need their full URL
+    pub shorthand: bool,
+    /// How long the endpoint took to answer when probed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Why a probed endpoint couldn't be reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The known regions, from the table `-e` expands region codes with
+pub fn regions() -> Vec<Region> {
+    REGIONS
+        .iter()
+        .map(|(code, name)| Region {
+            code,
+            name,
+            url: url(code),
+            shorthand: parse(code).is_ok_and(|expanded| expanded == url(code)),
+            latency_ms: None,
+            error: None,
+        })
+        .collect()
+}
+
+/// Time until `url` answers an unauthenticated request, connecting included. Any HTTP status counts, the service
+/// answers without credentials too
+#[cfg(feature = "rest")]
+pub async fn probe(url: &str) -> Result<Duration, String> {
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build().map_err(|err| err.to_string())?;
+    let started = Instant::now();
+    match client.get(url).send().await {
+        Ok(_) => Ok(started.elapsed()),
+        Err(err) if err.is_timeout() => Err(format!("no answer within {}s", PROBE_TIMEOUT.as_secs())),
+        Err(err) => Err(format!("could not be reached: {}", err)),
+    }
+}
+
+/// Without the rest feature there is no HTTP client to probe endpoints with
+#[cfg(not(feature = "rest"))]
+pub async fn probe(_url: &str) -> Result<Duration, String> {
+    Err("this build makes no HTTP requests, it is built without the rest feature".to_string())
+}
+
+/// Number of single character edits turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Error for an unknown region, suggesting the closest known one
+fn unknown_region(region: &str) -> String {
+    let (closest, name) = REGIONS
+        .iter()
+        .min_by_key(|(code, _)| edit_distance(region, code))
+        .expect("there are regions");
+    let known: Vec<&str> = REGIONS.iter().map(|(code, _)| *code).collect();
+    format!(
+        "unknown region '{}', did you mean '{}' ({})? Known regions: {}",
+        region,
+        closest,
+        name,
+        known.join(", ")
+    )
+}
+
+/// Normalize `-e`: a region code like `eus` expands to its URL, a URL is trimmed, lowercased and must use https.
+/// The host isn't checked, [`check`] does that
+pub fn normalize(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    let lower = value.to_ascii_lowercase();
+
+    let address = match lower.split_once("://") {
+        Some(("https", rest)) => rest,
+        Some(("http", _)) => {
+            return Err(format!(
+                "endpoint '{}' must use https, not http, Trusted Signing only answers on https://",
+                value
+            ))
+        }
+        Some((scheme, _)) => return Err(format!("endpoint '{}' must use https, not {}", value, scheme)),
+        None if lower.contains('.') => &lower,
+        None => {
+            return match REGIONS.iter().any(|(code, _)| *code == lower) {
+                true => Ok(url(&lower)),
+                false => Err(unknown_region(&lower)),
+            }
+        }
+    };
+    let address = address.trim_end_matches('/');
+    let (host, path) = address.split_once(['/', '?', '#']).unwrap_or((address, ""));
+    if host == "portal.azure.com" {
+        return Err(format!(
+            "endpoint '{}' is the Azure portal, pass the Account URI the overview of the code signing account shows, \
+             like https://eus{}",
+            value, HOST_SUFFIX
+        ));
+    }
+    if host.is_empty() || host.contains(['@', ' ']) {
+        return Err(format!("endpoint '{}' has no valid host", value));
+    }
+    if !path.is_empty() {
+        let path = &address[host.len()..];
+        return Err(format!("endpoint '{}' must be the URL of the region only, without '{}'", value, path));
+    }
+    Ok(format!("https://{}", host))
+}
+
+/// Check that a normalized endpoint is one of Trusted Signing, `https://<region>.codesigning.azure.net`
+pub fn check(url: &str) -> Result<(), String> {
+    let host = url.strip_prefix("https://").unwrap_or(url);
+    let Some(region) = HOST_SUFFIXES.iter().find_map(|suffix| host.strip_suffix(suffix)) else {
+        return Err(format!(
+            "endpoint '{}' is not a Trusted Signing endpoint, expected https://<region>{}",
+            url, HOST_SUFFIX
+        ));
+    };
+    // regions added after this table was written are accepted as full URLs
+    match !region.is_empty() && region.chars().all(|char| char.is_ascii_alphanumeric()) {
+        true => Ok(()),
+        false => Err(format!("endpoint '{}' has an invalid region '{}'", url, region)),
+    }
+}
+
+/// Parse `-e`: a region code like `eus` or a full endpoint URL, returning the canonical URL
+pub fn parse(value: &str) -> Result<String, String> {
+    let url = normalize(value)?;
+    check(&url)?;
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions() {
+        assert_eq!(parse("eus").unwrap(), "https://eus.codesigning.azure.net");
+        assert_eq!(parse("WUS2").unwrap(), "https://wus2.codesigning.azure.net");
+        assert_eq!((region_of("westus2"), region_of("SouthCentralUS")), (Some("wus2"), Some("scus")));
+        assert_eq!(region_of("westus4"), None);
+        for region in super::regions() {
+            assert!(region.shorthand);
+            assert_eq!(parse(&region.url).unwrap(), region.url);
+        }
+    }
+
+    #[test]
+    fn messy_urls() {
+        let normalized = [
+            ("  https://weu.codesigning.azure.net/  ", "https://weu.codesigning.azure.net"),
+            ("https://weu.codesigning.azure.net///", "https://weu.codesigning.azure.net"),
+            ("HTTPS://EUS.CodeSigning.Azure.Net", "https://eus.codesigning.azure.net"),
+            ("neu.codesigning.azure.net/", "https://neu.codesigning.azure.net"),
+            ("\teus\n", "https://eus.codesigning.azure.net"),
+            ("https://signing.contoso.com", "https://signing.contoso.com"),
+        ];
+        for (value, url) in normalized {
+            assert_eq!(normalize(value).as_deref(), Ok(url), "{:?}", value);
+        }
+        let rejected = [
+            ("http://eus.codesigning.azure.net", "must use https, not http,"),
+            ("ftp://eus.codesigning.azure.net", "must use https, not ftp"),
+            ("https://portal.azure.com/#@contoso/resource", "is the Azure portal"),
+            ("https://eus.codesigning.azure.net/codesigningaccounts/acc", "without '/codesigningaccounts/acc'"),
+            ("https://eus.codesigning.azure.net?api-version=1", "without '?api-version=1'"),
+            ("https:///", "no valid host"),
+        ];
+        for (value, message) in rejected {
+            let err = normalize(value).unwrap_err();
+            assert!(err.contains(message), "{:?}: {}", value, err);
+        }
+        assert_eq!(parse("https://weu.codesigning.azure.net/").unwrap(), "https://weu.codesigning.azure.net");
+        assert!(check("https://signing.contoso.com").unwrap_err().contains("not a Trusted Signing endpoint"));
+        assert!(check("https://codesigning.azure.net.contoso.com").is_err());
+    }
+
+    #[test]
+    fn suggestions() {
+        assert!(parse("weur").unwrap_err().contains("did you mean 'weu'"));
+        assert_eq!(parse("https://wus4.codesigning.azure.net").unwrap(), "https://wus4.codesigning.azure.net");
+        assert!(parse("https://a.b.codesigning.azure.net").is_err());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
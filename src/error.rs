@@ -0,0 +1,266 @@
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Serialize, Serializer};
+use std::{borrow::Cow, fmt, time::Duration};
+
+/// Exit codes returned by the CLI, stable so pipelines can branch on them
+pub mod exit_code {
+    pub const OTHER: u8 = 1;
+    pub const USAGE: u8 = 2;
+    pub const AUTH: u8 = 3;
+    pub const MISSING_DEPENDENCY: u8 = 4;
+    pub const SIGNING: u8 = 5;
+    pub const PARTIAL_FAILURE: u8 = 6;
+    pub const VERIFICATION: u8 = 7;
+    pub const QUOTA: u8 = 8;
+    pub const DEADLINE: u8 = 9;
+    /// 128 + SIGINT, like shells report a process stopped by Ctrl+C
+    pub const INTERRUPTED: u8 = 130;
+}
+
+/// Every exit code with its meaning, in the order printed by `--help`
+pub const EXIT_CODES: [(u8, &str); 10] = [
+    (exit_code::OTHER, "any other error"),
+    (exit_code::USAGE, "invalid arguments or configuration"),
+    (exit_code::AUTH, "azure authentication failed"),
+    (exit_code::MISSING_DEPENDENCY, "azure cli, signtool or the signing client is missing"),
+    (exit_code::SIGNING, "signing failed"),
+    (exit_code::PARTIAL_FAILURE, "some files failed with --continue-on-error"),
+    (exit_code::VERIFICATION, "signature verification failed"),
+    (exit_code::QUOTA, "the signatures used this month reached quota --warn-at"),
+    (exit_code::DEADLINE, "the run stopped at --max-runtime"),
+    (exit_code::INTERRUPTED, "interrupted by Ctrl+C"),
+];
+
+/// Exit code table for the help output
+pub fn exit_codes_help() -> String {
+    EXIT_CODES.iter().fold(String::from("Exit codes:\n"), |help, (code, meaning)| {
+        help + &format!("  {}  {}\n", code, meaning)
+    })
+}
+
+/// Why a run failed, each kind maps to its own exit code
+#[derive(Debug)]
+pub enum Error {
+    /// Arguments or configuration that can't work
+    Usage(String),
+    /// Logging in to azure failed
+    Auth(String),
+    /// The azure cli, signtool or the signing client is not available
+    MissingDependency { component: &'static str, message: String },
+    /// The signing client could not be downloaded or unpacked
+    Download { url: String, message: String },
+    /// An input file that can't be signed
+    UnsupportedInput { path: String, message: String },
+    /// An input file that is empty or broken, like an EXE a crashed linker left behind
+    InvalidInput { path: String, message: String },
+    /// signtool failed on a file, with the output it printed
+    Signing { path: String, message: String, output: String },
+    /// Every file failed with --continue-on-error
+    AllFailed { total: usize },
+    PartialFailure { failed: usize, total: usize },
+    /// Files `verify` found not validly signed
+    VerificationFailed { failed: usize, total: usize },
+    /// The account used as many signatures as `quota --warn-at` warns at, or more
+    QuotaReached { account: String, used: u64, warn_at: u64 },
+    /// The build of --run failed, the run exits with its code
+    Build { command: String, code: Option<i32>, message: String },
+    /// Ctrl+C stopped the run
+    Interrupted,
+    /// The run stopped at --max-runtime, before the files left
+    DeadlineExceeded { max_runtime: Duration },
+    Other(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Usage(_) => exit_code::USAGE,
+            Error::Auth(_) => exit_code::AUTH,
+            Error::MissingDependency { .. } | Error::Download { .. } => exit_code::MISSING_DEPENDENCY,
+            Error::UnsupportedInput { .. } | Error::InvalidInput { .. } => exit_code::USAGE,
+            Error::Signing { .. } | Error::AllFailed { .. } => exit_code::SIGNING,
+            Error::PartialFailure { .. } => exit_code::PARTIAL_FAILURE,
+            Error::VerificationFailed { .. } => exit_code::VERIFICATION,
+            Error::QuotaReached { .. } => exit_code::QUOTA,
+            // codes beyond a byte, like the NTSTATUS of a crash on Windows, can't be passed on
+            Error::Build { code, .. } => {
+                code.and_then(|code| u8::try_from(code).ok()).filter(|code| *code != 0).unwrap_or(exit_code::OTHER)
+            }
+            Error::Interrupted => exit_code::INTERRUPTED,
+            Error::DeadlineExceeded { .. } => exit_code::DEADLINE,
+            Error::Other(_) => exit_code::OTHER,
+        }
+    }
+
+    /// Machine readable name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Usage(_) => "usage",
+            Error::Auth(_) => "auth",
+            Error::MissingDependency { .. } => "missing_dependency",
+            Error::Download { .. } => "download",
+            Error::UnsupportedInput { .. } => "unsupported_input",
+            Error::InvalidInput { .. } => "invalid_input",
+            Error::Signing { .. } => "signing",
+            Error::AllFailed { .. } => "all_failed",
+            Error::PartialFailure { .. } => "partial_failure",
+            Error::VerificationFailed { .. } => "verification_failed",
+            Error::QuotaReached { .. } => "quota_reached",
+            Error::Build { .. } => "build",
+            Error::Interrupted => "interrupted",
+            Error::DeadlineExceeded { .. } => "deadline_exceeded",
+            Error::Other(_) => "other",
+        }
+    }
+
+    /// The message without the captured child output
+    fn message(&self) -> String {
+        match self {
+            Error::Usage(message)
+            | Error::Auth(message)
+            | Error::MissingDependency { message, .. }
+            | Error::Download { message, .. }
+            | Error::UnsupportedInput { message, .. }
+            | Error::InvalidInput { message, .. }
+            | Error::Signing { message, .. }
+            | Error::Build { message, .. }
+            | Error::Other(message) => message.clone(),
+            Error::AllFailed { total } => format!("none of the {} files could be signed", total),
+            Error::PartialFailure { failed, total } => format!("{} of {} files could not be signed", failed, total),
+            Error::VerificationFailed { failed, total } => {
+                format!("{} of {} files are not validly signed and timestamped", failed, total)
+            }
+            Error::QuotaReached { account, used, warn_at } => format!(
+                "account {} used {} signatures this month, --warn-at is {}",
+                account, used, warn_at
+            ),
+            Error::Interrupted => "interrupted by Ctrl+C".to_string(),
+            Error::DeadlineExceeded { max_runtime } => format!(
+                "the run stopped at --max-runtime {}s, the files left weren't signed",
+                max_runtime.as_secs()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Signing { message, output, .. } if !output.is_empty() => write!(f, "{}\n\r{}", message, output),
+            error => f.write_str(&error.message()),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+/// Error object of the JSON report
+#[derive(Serialize, JsonSchema)]
+struct ErrorObject<'a> {
+    /// Kind of the error, like `signing`
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    component: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<&'a str>,
+}
+
+impl JsonSchema for Error {
+    fn schema_name() -> Cow<'static, str> {
+        "Error".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        ErrorObject::json_schema(generator)
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut object = ErrorObject {
+            kind: self.kind(),
+            path: None,
+            component: None,
+            url: None,
+            message: self.message(),
+            output: None,
+        };
+        match self {
+            Error::Auth(_) => object.component = Some("azure cli"),
+            Error::MissingDependency { component, .. } => object.component = Some(component),
+            Error::Download { url, .. } => {
+                object.component = Some("signing client");
+                object.url = Some(url);
+            }
+            Error::UnsupportedInput { path, .. } | Error::InvalidInput { path, .. } => object.path = Some(path),
+            Error::Signing { path, output, .. } => {
+                object.path = Some(path);
+                object.output = Some(output);
+            }
+            Error::Build { .. } => object.component = Some("build"),
+            Error::Usage(_)
+            | Error::AllFailed { .. }
+            | Error::PartialFailure { .. }
+            | Error::VerificationFailed { .. }
+            | Error::QuotaReached { .. }
+            | Error::Interrupted
+            | Error::DeadlineExceeded { .. }
+            | Error::Other(_) => (),
+        }
+        object.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_stable() {
+        assert_eq!(Error::Other(String::new()).exit_code(), 1);
+        assert_eq!(Error::Usage(String::new()).exit_code(), 2);
+        assert_eq!(Error::Auth(String::new()).exit_code(), 3);
+        let missing = Error::MissingDependency {
+            component: "signtool",
+            message: String::new(),
+        };
+        assert_eq!(missing.exit_code(), 4);
+        assert_eq!(Error::AllFailed { total: 2 }.exit_code(), 5);
+        assert_eq!(Error::PartialFailure { failed: 1, total: 2 }.exit_code(), 6);
+        assert_eq!(Error::VerificationFailed { failed: 1, total: 2 }.exit_code(), 7);
+        assert_eq!(Error::QuotaReached { account: "acc".to_string(), used: 5, warn_at: 5 }.exit_code(), 8);
+        assert_eq!(Error::Interrupted.exit_code(), 130);
+        assert_eq!(Error::DeadlineExceeded { max_runtime: Duration::from_secs(60) }.exit_code(), 9);
+
+        let codes: Vec<u8> = EXIT_CODES.iter().map(|(code, _)| *code).collect();
+        assert_eq!(codes, (1..=9).chain([130]).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn json_object() {
+        let error = Error::Signing {
+            path: "a.exe".to_string(),
+            message: "signtool could not sign the file".to_string(),
+            output: "SignTool Error: denied".to_string(),
+        };
+        assert_eq!(error.to_string(), "signtool could not sign the file\n\rSignTool Error: denied");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "kind": "signing",
+                "path": "a.exe",
+                "message": "signtool could not sign the file",
+                "output": "SignTool Error: denied",
+            })
+        );
+    }
+}
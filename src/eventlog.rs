@@ -0,0 +1,290 @@
+use crate::{audit::AuditEntry, logging, report::Report, report::Status, route::Target};
+use tracing::warn;
+
+/// Event ids, one per kind of event so collectors can filter on them
+pub const RUN_FINISHED: u32 = 1000;
+pub const FILE_SIGNED: u32 = 1001;
+pub const FILE_SKIPPED: u32 = 1002;
+pub const FILE_FAILED: u32 = 1003;
+
+/// Message file of the event source. The one .NET registers its sources with, present wherever .NET Framework 4
+/// is, and it renders every event id as the first insertion string
+#[cfg_attr(not(windows), allow(dead_code))]
+const MESSAGE_FILE: &str = r"%SystemRoot%\Microsoft.NET\Framework64\v4.0.30319\EventLogMessages.dll";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Information,
+    Warning,
+    Error,
+}
+
+/// An event to write, its first insertion string the message and the others the fields in a fixed order
+#[derive(Debug, PartialEq, Eq)]
+pub struct Event {
+    pub id: u32,
+    pub level: Level,
+    pub strings: Vec<String>,
+}
+
+/// The event of one file, from what its audit entry records. `skip_reason` is set for skipped files
+pub fn file_event(entry: &AuditEntry, skip_reason: Option<&str>) -> Event {
+    let (id, level, message) = match entry.status {
+        Status::Signed => {
+            let sha256 = entry.sha256_after.as_deref().unwrap_or("not hashed");
+            (FILE_SIGNED, Level::Information, format!("Signed {} (SHA-256 {})", entry.path, sha256))
+        }
+        Status::Skipped => {
+            let reason = skip_reason.unwrap_or("skipped");
+            (FILE_SKIPPED, Level::Warning, format!("Skipped {}: {}", entry.path, reason))
+        }
+        Status::Failed | Status::NotAttempted => {
+            let error = entry.error.unwrap_or("it was not signed");
+            (FILE_FAILED, Level::Error, format!("Failed to sign {}: {}", entry.path, error))
+        }
+    };
+    let message = format!("{} with {} / {} at {}", message, entry.account, entry.certificate_profile, entry.endpoint);
+    let fields = [
+        Some(message.as_str()),
+        Some(entry.path.as_str()),
+        entry.sha256_before.as_deref(),
+        entry.sha256_after.as_deref(),
+        Some(entry.endpoint),
+        Some(entry.account),
+        Some(entry.certificate_profile),
+        entry.certificate.map(|certificate| certificate.thumbprint.as_str()),
+        Some(entry.azure_client_id),
+        entry.signtool_version,
+        entry.error.or(skip_reason),
+        entry.client.as_deref(),
+    ];
+    let strings = fields.into_iter().map(|field| logging::mask(field.unwrap_or_default())).collect();
+    Event { id, level, strings }
+}
+
+/// The event of a finished run: an error when it failed, a warning when files were skipped
+pub fn run_event(target: &Target, report: &Report, error: Option<&str>) -> Event {
+    let summary = &report.summary;
+    let level = match (error, summary.skipped) {
+        (Some(_), _) => Level::Error,
+        (None, 0) => Level::Information,
+        (None, _) => Level::Warning,
+    };
+    let message = format!(
+        "Signing {}: {} signed, {} failed, {} skipped of {} file(s) with {} / {} at {}",
+        match error {
+            Some(_) => "failed",
+            None => "succeeded",
+        },
+        summary.signed,
+        summary.failed,
+        summary.skipped,
+        report.files.len(),
+        target.account,
+        target.certificate,
+        target.endpoint
+    );
+    let counts = [summary.signed, summary.failed, summary.skipped, report.files.len()].map(|count| count.to_string());
+    let fields = [
+        Some(message.as_str()),
+        Some(target.endpoint.as_str()),
+        Some(target.account.as_str()),
+        Some(target.certificate.as_str()),
+        Some(counts[0].as_str()),
+        Some(counts[1].as_str()),
+        Some(counts[2].as_str()),
+        Some(counts[3].as_str()),
+        report.correlation_id.as_deref(),
+        error,
+    ];
+    let strings = fields.into_iter().map(|field| logging::mask(field.unwrap_or_default())).collect();
+    Event { id: RUN_FINISHED, level, strings }
+}
+
+/// The Application log, written to as an event source
+pub struct EventLog {
+    #[cfg(windows)]
+    handle: windows_sys::Win32::Foundation::HANDLE,
+}
+
+// SAFETY: an event log handle may be reported to from any thread
+#[cfg(windows)]
+unsafe impl Send for EventLog {}
+// SAFETY: as above, ReportEventW doesn't need the calls on one handle serialized
+#[cfg(windows)]
+unsafe impl Sync for EventLog {}
+
+/// Register `source` under the Application log when it isn't, which needs an administrator
+#[cfg(windows)]
+fn register(source: &str) -> Result<(), String> {
+    use windows_sys::Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::Registry::{
+            RegCloseKey, RegCreateKeyExW, RegOpenKeyExW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE, KEY_READ,
+            KEY_WRITE, REG_DWORD, REG_EXPAND_SZ, REG_OPTION_NON_VOLATILE,
+        },
+    };
+
+    let wide = |text: &str| -> Vec<u16> { text.encode_utf16().chain([0]).collect() };
+    let key = wide(&format!(r"SYSTEM\CurrentControlSet\Services\EventLog\Application\{}", source));
+    let mut handle: HKEY = std::ptr::null_mut();
+    // SAFETY: `key` is a null terminated wide string, the key opened is closed right after
+    if unsafe { RegOpenKeyExW(HKEY_LOCAL_MACHINE, key.as_ptr(), 0, KEY_READ, &mut handle) } == ERROR_SUCCESS {
+        // SAFETY: `handle` was opened above
+        unsafe { RegCloseKey(handle) };
+        return Ok(());
+    }
+    let (class, security, disposition) = (std::ptr::null(), std::ptr::null(), std::ptr::null_mut());
+    // SAFETY: as above, the class and security attributes are optional
+    let created = unsafe {
+        let (root, options) = (HKEY_LOCAL_MACHINE, REG_OPTION_NON_VOLATILE);
+        RegCreateKeyExW(root, key.as_ptr(), 0, class, options, KEY_WRITE, security, &mut handle, disposition)
+    };
+    match created {
+        ERROR_SUCCESS => (),
+        // ERROR_ACCESS_DENIED
+        5 => Err(format!(
+            "the event source '{}' isn't registered and registering it needs an administrator, run once elevated \
+             or register it with New-EventLog -LogName Application -Source {}",
+            source, source
+        ))?,
+        code => Err(format!("the event source '{}' could not be registered, error: {}", source, code))?,
+    }
+    let file: Vec<u8> = wide(MESSAGE_FILE).into_iter().flat_map(u16::to_le_bytes).collect();
+    // information, warning and error
+    let types = 7u32.to_le_bytes();
+    let values = [("EventMessageFile", REG_EXPAND_SZ, file.as_slice()), ("TypesSupported", REG_DWORD, &types)];
+    for (name, kind, data) in values {
+        let name = wide(name);
+        // SAFETY: `data` holds `data.len()` bytes of the type given, `handle` was created above
+        let set = unsafe { RegSetValueExW(handle, name.as_ptr(), 0, kind, data.as_ptr(), data.len() as u32) };
+        if set != ERROR_SUCCESS {
+            // SAFETY: `handle` was created above
+            unsafe { RegCloseKey(handle) };
+            Err(format!("the event source '{}' could not be registered, error: {}", source, set))?;
+        }
+    }
+    // SAFETY: `handle` was created above
+    unsafe { RegCloseKey(handle) };
+    tracing::info!("registered the event source '{}' in the Application log", source);
+    Ok(())
+}
+
+impl EventLog {
+    /// Write to the Application log as `source`, registering it first when it isn't. Signing goes on without the
+    /// event log when neither is possible, so None comes with a warning rather than an error
+    #[cfg(windows)]
+    pub fn open(source: &str) -> Option<Self> {
+        use windows_sys::Win32::System::EventLog::RegisterEventSourceW;
+
+        if let Err(err) = register(source) {
+            warn!("{}, signing goes on without writing to the event log", err);
+            return None;
+        }
+        let name: Vec<u16> = source.encode_utf16().chain([0]).collect();
+        // SAFETY: `name` is a null terminated wide string, the handle is deregistered on drop
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        if handle.is_null() {
+            // SAFETY: plain query of the calling thread
+            let code = unsafe { windows_sys::Win32::Foundation::GetLastError() };
+            warn!("the event source '{}' could not be opened, error: {}, signing goes on without it", source, code);
+            return None;
+        }
+        Some(EventLog { handle })
+    }
+
+    #[cfg(not(windows))]
+    pub fn open(source: &str) -> Option<Self> {
+        warn!("the event source '{}' can't be written to, the Windows Event Log only exists on Windows", source);
+        None
+    }
+
+    /// Write `event`, a failure only warns
+    #[cfg(windows)]
+    pub fn write(&self, event: &Event) {
+        use windows_sys::Win32::System::EventLog::{
+            ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+        };
+
+        let kind = match event.level {
+            Level::Information => EVENTLOG_INFORMATION_TYPE,
+            Level::Warning => EVENTLOG_WARNING_TYPE,
+            Level::Error => EVENTLOG_ERROR_TYPE,
+        };
+        let wide = |text: &String| -> Vec<u16> { text.encode_utf16().chain([0]).collect() };
+        let strings: Vec<Vec<u16>> = event.strings.iter().map(wide).collect();
+        let pointers: Vec<*const u16> = strings.iter().map(|text| text.as_ptr()).collect();
+        let (count, sid, data) = (pointers.len() as u16, std::ptr::null_mut(), std::ptr::null());
+        // SAFETY: `pointers` holds `count` null terminated wide strings that outlive the call, no raw data
+        let reported = unsafe { ReportEventW(self.handle, kind, 0, event.id, sid, count, 0, pointers.as_ptr(), data) };
+        if reported == 0 {
+            // SAFETY: plain query of the calling thread
+            let code = unsafe { windows_sys::Win32::Foundation::GetLastError() };
+            warn!("event {} could not be written to the event log, error: {}", event.id, code);
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn write(&self, _event: &Event) {}
+}
+
+#[cfg(windows)]
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from RegisterEventSourceW and isn't used after this
+        unsafe { windows_sys::Win32::System::EventLog::DeregisterEventSource(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_of_files_and_runs() {
+        let mut entry = AuditEntry {
+            timestamp: "2026-10-14T09:00:00.000Z".to_string(),
+            path: r"C:\build\app.exe".to_string(),
+            sha256_before: Some("aa".to_string()),
+            sha256_after: Some("bb".to_string()),
+            endpoint: "https://eus.codesigning.azure.net",
+            account: "acc",
+            certificate_profile: "prof",
+            certificate: None,
+            azure_client_id: "cid",
+            signtool_version: Some("10.0.22621.0"),
+            dlib_version: "1.0.60",
+            status: Status::Signed,
+            error: None,
+            client: None,
+        };
+        let event = file_event(&entry, None);
+        assert_eq!((event.id, event.level), (FILE_SIGNED, Level::Information));
+        let message = r"Signed C:\build\app.exe (SHA-256 bb) with acc / prof at https://eus.codesigning.azure.net";
+        assert_eq!(event.strings[0], message);
+        assert_eq!(event.strings.len(), 12);
+        assert_eq!(event.strings[3], "bb");
+        assert_eq!(event.strings[7], "");
+
+        entry.status = Status::Skipped;
+        let event = file_event(&entry, Some("its marker is current"));
+        assert_eq!((event.id, event.level), (FILE_SKIPPED, Level::Warning));
+        assert_eq!(event.strings[10], "its marker is current");
+        entry.status = Status::Failed;
+        entry.error = Some("signtool failed");
+        assert_eq!(file_event(&entry, None).level, Level::Error);
+
+        let target = Target {
+            endpoint: entry.endpoint.to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+        let mut report = Report::new(&["a.exe".to_string(), "b.exe".to_string()]);
+        report.skip(1, "unsupported");
+        report.finish(std::time::Duration::ZERO);
+        let event = run_event(&target, &report, None);
+        assert_eq!((event.id, event.level), (RUN_FINISHED, Level::Warning));
+        assert!(event.strings[0].starts_with("Signing succeeded: 0 signed, 0 failed, 1 skipped of 2 file(s)"));
+        assert_eq!(run_event(&target, &report, Some("2 of 2 files could not be signed")).level, Level::Error);
+    }
+}
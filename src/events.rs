@@ -0,0 +1,117 @@
+use crate::{
+    logging,
+    report::{FileReport, Report},
+};
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// Format of the event stream
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventFormat {
+    /// One JSON object per line on stdout
+    Ndjson,
+}
+
+/// Resolved configuration of a run, without secrets
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct RunConfig<'a> {
+    pub endpoint: &'a str,
+    pub account: &'a str,
+    pub certificate_profile: &'a str,
+    pub azure_client_id: &'a str,
+    pub azure_cli_path: &'a str,
+    pub sign_tool_path: &'a str,
+    pub files: &'a [String],
+}
+
+/// Something that happened during a run
+#[derive(Serialize, JsonSchema, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    RunStarted {
+        config: RunConfig<'a>,
+    },
+    PhaseStarted {
+        phase: &'a str,
+    },
+    PhaseFinished {
+        phase: &'a str,
+        duration_ms: u64,
+    },
+    DownloadProgress {
+        url: &'a str,
+        bytes: u64,
+        finished: bool,
+    },
+    FileStarted {
+        path: &'a str,
+        index: usize,
+        total: usize,
+    },
+    FileFinished {
+        #[serde(flatten)]
+        file: &'a FileReport,
+    },
+    RunFinished {
+        #[serde(flatten)]
+        report: &'a Report,
+    },
+}
+
+/// Receives every event of a run
+pub trait Sink {
+    fn emit(&mut self, seq: u64, event: &Event);
+}
+
+/// Fans events out to all configured sinks, numbering them as they go
+#[derive(Default)]
+pub struct Events {
+    seq: u64,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Events {
+    pub fn new(format: Option<EventFormat>) -> Self {
+        let mut events = Events::default();
+        if let Some(EventFormat::Ndjson) = format {
+            events.sinks.push(Box::new(Ndjson));
+        }
+        events
+    }
+
+    /// Also send events to `sink`
+    pub fn add(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        self.seq += 1;
+        for sink in &mut self.sinks {
+            sink.emit(self.seq, &event);
+        }
+    }
+}
+
+/// Event envelope written by the NDJSON sink
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Envelope<'a> {
+    /// Number of the event in the run, from 1
+    pub(crate) seq: u64,
+    #[serde(flatten)]
+    pub(crate) event: &'a Event<'a>,
+}
+
+/// Writes each event as a single JSON line to stdout
+struct Ndjson;
+
+impl Sink for Ndjson {
+    fn emit(&mut self, seq: u64, event: &Event) {
+        let line = serde_json::to_string(&Envelope { seq, event }).expect("events are always serializable");
+        let line = logging::mask(&line);
+        let mut stdout = io::stdout().lock();
+        // a consumer that went away must not fail the signing
+        let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+    }
+}
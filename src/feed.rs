@@ -0,0 +1,134 @@
+use crate::error::Error;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{header::AUTHORIZATION, StatusCode, Url};
+use tracing::debug;
+
+/// NuGet package of the Trusted Signing client
+const PACKAGE_ID: &str = "Microsoft.Trusted.Signing.Client";
+
+/// Resource type of a v3 service index pointing at the flat container
+const PACKAGE_BASE_ADDRESS: &str = "PackageBaseAddress/3.0.0";
+
+/// Download link of the package on nuget.org
+fn nuget_org_url(version: &str) -> String {
+    format!("https://www.nuget.org/api/v2/package/{}/{}", PACKAGE_ID, version)
+}
+
+/// Package in a flat container, ids and versions are lowercase there
+fn flat_container_url(base: &str, version: &str) -> String {
+    let id = PACKAGE_ID.to_lowercase();
+    let version = version.to_lowercase();
+    format!("{}/{}/{}/{}.{}.nupkg", base.trim_end_matches('/'), id, version, id, version)
+}
+
+/// The file the package from `url` is saved as, `microsoft.trusted.signing.client.<version>.nupkg` whatever the
+/// last segment of the URL is, `1.0.60` on nuget.org and the file name in a flat container
+pub fn package_file(url: &str, version: &str) -> Result<String, String> {
+    Url::parse(url).map_err(|err| format!("'{}' is not a valid URL: {}", url, err))?;
+    Ok(format!("{}.{}.nupkg", PACKAGE_ID.to_lowercase(), version.to_lowercase()))
+}
+
+/// Flat container of a v3 service index
+fn base_address(index: &str) -> Option<String> {
+    let index: serde_json::Value = serde_json::from_str(index).ok()?;
+    index["resources"]
+        .as_array()?
+        .iter()
+        .find(|resource| resource["@type"].as_str() == Some(PACKAGE_BASE_ADDRESS))
+        .and_then(|resource| resource["@id"].as_str())
+        .map(str::to_string)
+}
+
+/// `Authorization` header for a feed token, `user:password` (like `az:<PAT>` for Azure Artifacts)
+/// is sent as basic auth, anything else as a bearer token
+pub fn authorization(token: &str) -> String {
+    match token.contains(':') {
+        true => format!("Basic {}", STANDARD.encode(token)),
+        false => format!("Bearer {}", token),
+    }
+}
+
+/// Error for a feed refusing the request, explaining what to do about it
+pub fn unauthorized(url: &str, status: StatusCode, token: bool) -> Error {
+    let hint = match token {
+        true => "the token in TRUSTED_SIGNING_FEED_TOKEN was rejected, check that it is valid and may read packages",
+        false => "the feed needs credentials, set TRUSTED_SIGNING_FEED_TOKEN to a token with read access",
+    };
+    Error::Download {
+        url: url.to_string(),
+        message: format!("package feed answered {}: {}", status, hint),
+    }
+}
+
+/// Check `--dlib-feed`: an http(s) URL of a v3 service index or a flat container
+pub fn parse(value: &str) -> Result<String, String> {
+    match Url::parse(value) {
+        Ok(url) if ["https", "http"].contains(&url.scheme()) => Ok(value.trim_end_matches('/').to_string()),
+        Ok(url) => Err(format!("package feed '{}' must use https or http, not {}", value, url.scheme())),
+        Err(err) => Err(format!("package feed '{}' is not a URL: {}", value, err)),
+    }
+}
+
+/// Download URL of the Trusted Signing client, from nuget.org unless a feed is given.
+/// A v3 service index (`.../index.json`) is looked up for its flat container.
+pub async fn package_url(feed: Option<&str>, token: Option<&str>, version: &str) -> Result<String, Error> {
+    let Some(feed) = feed else {
+        return Ok(nuget_org_url(version));
+    };
+    if !feed.ends_with("index.json") {
+        return Ok(flat_container_url(feed, version));
+    }
+
+    debug!("reading package feed index {}", feed);
+    let download_error = |message: String| Error::Download {
+        url: feed.to_string(),
+        message,
+    };
+    let mut request = reqwest::Client::new().get(feed);
+    if let Some(token) = token {
+        request = request.header(AUTHORIZATION, authorization(token));
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|err| download_error(format!("package feed could not be read: {}", err)))?;
+    let status = response.status();
+    if [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN].contains(&status) {
+        return Err(unauthorized(feed, status, token.is_some()));
+    }
+    if !status.is_success() {
+        return Err(download_error(format!("package feed answered {}", status)));
+    }
+    let index = response
+        .text()
+        .await
+        .map_err(|err| download_error(format!("package feed could not be read: {}", err)))?;
+    let base = base_address(&index)
+        .ok_or_else(|| download_error(format!("package feed index has no {} resource", PACKAGE_BASE_ADDRESS)))?;
+    Ok(flat_container_url(&base, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        let index = r#"{"version": "3.0.0", "resources": [
+            {"@id": "https://pkgs.dev.azure.com/org/_packaging/feed/nuget/v3/query2/", "@type": "SearchQueryService"},
+            {"@id": "https://pkgs.dev.azure.com/org/_packaging/feed/nuget/v3/flat2/", "@type": "PackageBaseAddress/3.0.0"}
+        ]}"#;
+        let base = base_address(index).unwrap();
+        assert_eq!(
+            flat_container_url(&base, "1.0.60"),
+            "https://pkgs.dev.azure.com/org/_packaging/feed/nuget/v3/flat2/microsoft.trusted.signing.client/1.0.60/microsoft.trusted.signing.client.1.0.60.nupkg"
+        );
+        for url in [nuget_org_url("1.0.60"), flat_container_url(&base, "1.0.60")] {
+            assert_eq!(package_file(&url, "1.0.60").unwrap(), "microsoft.trusted.signing.client.1.0.60.nupkg");
+        }
+        assert!(package_file("not a url", "1.0.60").is_err());
+        assert!(parse("ftp://mirror/nuget").is_err());
+        assert_eq!(authorization("az:pat"), "Basic YXo6cGF0");
+        assert_eq!(authorization("token"), "Bearer token");
+    }
+}
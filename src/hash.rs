@@ -0,0 +1,35 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256, Sha512};
+use std::{fs::File, io, path::Path};
+
+/// Digest of a file, streamed so large MSIs don't have to fit in memory
+fn digest<D: Digest + io::Write>(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Hex encoded digest of a file
+fn digest_file<D: Digest + io::Write>(path: impl AsRef<Path>) -> io::Result<String> {
+    digest::<D>(path).map(|digest| hex(&digest))
+}
+
+/// Hex encoded SHA-256 of a file
+pub fn sha256_file(path: impl AsRef<Path>) -> io::Result<String> {
+    digest_file::<Sha256>(path)
+}
+
+/// Hex encoded SHA-512 of a file
+pub fn sha512_file(path: impl AsRef<Path>) -> io::Result<String> {
+    digest_file::<Sha512>(path)
+}
+
+/// SHA-512 of a file in base64, how electron-builder writes it into latest.yml
+pub fn sha512_base64_file(path: impl AsRef<Path>) -> io::Result<String> {
+    Ok(STANDARD.encode(digest::<Sha512>(path)?))
+}
+
+/// Lowercase hex encoding
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,217 @@
+/// What a known signtool or dlib failure means and what to do about it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hint {
+    /// Short name of the cause, for tests and logs
+    pub cause: &'static str,
+    pub explanation: &'static str,
+    pub next_step: &'static str,
+}
+
+/// The known failures, the text one shows up with in the output: any of it picks the hint. The first match wins,
+/// so the specific dlib messages come before the HRESULTs signtool wraps them in
+const RULES: [(&[&str], Hint); 10] = [
+    (
+        &["status: 403", "(forbidden)", "0x80190193", "authorizationfailed"],
+        Hint {
+            cause: "signer role missing",
+            explanation: "the identity signing isn't allowed to use the certificate profile",
+            next_step: "assign it the \"Trusted Signing Certificate Profile Signer\" role on the account or the \
+                        profile, role assignments can take a few minutes to apply",
+        },
+    ),
+    (
+        &["status: 404", "(not found)", "0x80190194"],
+        Hint {
+            cause: "account or profile not found",
+            explanation: "the account or certificate profile doesn't exist at this endpoint",
+            next_step: "check -a and -c against the portal, names are case sensitive, and that -e is the region \
+                        the account was created in",
+        },
+    ),
+    (
+        &["status: 429", "too many requests", "0x801901ad", "quota"],
+        Hint {
+            cause: "quota exceeded",
+            explanation: "the account is throttled or used up its signatures for the month",
+            next_step: "retry later, sign fewer files per run, or check the quota of the account's tier in the \
+                        portal",
+        },
+    ),
+    (
+        &["status: 401", "(unauthorized)", "0x80190191"],
+        Hint {
+            cause: "token rejected",
+            explanation: "the service rejected the access token",
+            next_step: "check --azure-tenant-id is the tenant of the account and the client secret hasn't expired",
+        },
+    ),
+    (
+        &[
+            "defaultazurecredential failed",
+            "credentialunavailableexception",
+            "authenticationfailedexception",
+            "aadsts",
+        ],
+        Hint {
+            cause: "authentication failed",
+            explanation: "the dlib could not get a token for Trusted Signing",
+            next_step: "check the AZURE_* credentials with `trusted-signing-cli whoami`, or log in with `az login`",
+        },
+    ),
+    (
+        &[
+            "no such host is known",
+            "name or service not known",
+            "a connection attempt failed",
+            "no connection could be made",
+            "the remote name could not be resolved",
+            "0x80072ee7",
+            "0x80072efd",
+            "0x80072ee2",
+        ],
+        Hint {
+            cause: "endpoint unreachable",
+            explanation: "the Trusted Signing endpoint could not be reached",
+            next_step: "check -e names a region like https://eus.codesigning.azure.net and that a proxy or firewall \
+                        lets HTTPS to it through",
+        },
+    ),
+    (
+        &["you must install or update .net", "hostfxr", "could not load file or assembly", "0x8007007e"],
+        Hint {
+            cause: ".NET runtime missing",
+            explanation: "the dlib or the .NET runtime it needs could not be loaded",
+            next_step: "install the .NET 8 runtime for x64 from https://dotnet.microsoft.com/download/dotnet/8.0, \
+                        `trusted-signing-cli status` shows what was found",
+        },
+    ),
+    (
+        &["0x800700c1", "is not a valid win32 application"],
+        Hint {
+            cause: "architecture mismatch",
+            explanation: "signtool and the dlib are built for different architectures",
+            next_step: "use the x64 signtool from the Windows SDK with --sing-tool-path, the dlib is x64",
+        },
+    ),
+    (
+        &["0x80131500", "0x80131501", "0x80131509", "0x80131515", "0x80131620"],
+        Hint {
+            cause: ".NET exception",
+            explanation: "the dlib failed with a .NET exception",
+            next_step: "the lines above name it, run with --verbose for the dlib's full output",
+        },
+    ),
+    (
+        &["signersign() failed", "0x80004005"],
+        Hint {
+            cause: "dlib failed",
+            explanation: "the dlib failed without saying why, most often for a missing .NET runtime or a failed \
+                          login",
+            next_step: "run `trusted-signing-cli status` and `trusted-signing-cli whoami`, then again with --verbose \
+                        for the dlib's own output",
+        },
+    ),
+];
+
+/// Output of a failure on the service's side, like a 5xx or a timeout, that another region may not have
+const SERVICE_SIDE: [&str; 14] = [
+    "status: 500",
+    "status: 502",
+    "status: 503",
+    "status: 504",
+    "(internal server error)",
+    "(service unavailable)",
+    "(gateway timeout)",
+    "0x801901f4",
+    "0x801901f6",
+    "0x801901f7",
+    "timed out",
+    "taskcanceledexception",
+    "a connection attempt failed",
+    "0x80072ee2",
+];
+
+/// Output of signtool failing only to timestamp a file, the signing itself worked
+const TIMESTAMP_FAILURE: [&str; 4] = [
+    "timestamp server either could not be reached",
+    "attempting to timestamp",
+    "attempting to time stamp",
+    "0x80096005",
+];
+
+/// The causes that fail the same at every endpoint
+const DETERMINISTIC: [&str; 4] =
+    ["signer role missing", "account or profile not found", "token rejected", "authentication failed"];
+
+/// The hint for a failure by the output of signtool and the dlib, when it is a known one
+pub fn hint(output: &str) -> Option<&'static Hint> {
+    let output = output.to_lowercase();
+    RULES
+        .iter()
+        .find(|(patterns, _)| patterns.iter().any(|pattern| output.contains(pattern)))
+        .map(|(_, hint)| hint)
+}
+
+/// `message` with the hint for `output` on a line of its own, unchanged without one
+pub fn explain(message: String, output: &str) -> String {
+    match hint(output) {
+        Some(hint) => format!("{}\nhint: {}, {}", message, hint.explanation, hint.next_step),
+        None => message,
+    }
+}
+
+/// Whether the output of a failure is signtool failing to timestamp, so the file can be signed without a timestamp
+pub fn is_timestamp_failure(output: &str) -> bool {
+    let lowercase = output.to_lowercase();
+    TIMESTAMP_FAILURE.iter().any(|pattern| lowercase.contains(pattern))
+}
+
+/// Whether the output of a failure looks service-side, so signing at another endpoint may work. Failures a hint
+/// names as a login, role or profile problem never are
+pub fn is_service_side(output: &str) -> bool {
+    let lowercase = output.to_lowercase();
+    SERVICE_SIDE.iter().any(|pattern| lowercase.contains(pattern))
+        && !hint(output).is_some_and(|hint| DETERMINISTIC.contains(&hint.cause))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_for_transcripts() {
+        let cause = |output: &str| hint(output).map(|hint| hint.cause);
+        let unexpected = "SignTool Error: An unexpected internal error has occurred.\r\n\
+                          Error information: \"SignerSign() failed.\" (-2147467259/0x80004005)";
+        assert_eq!(cause(unexpected), Some("dlib failed"));
+        let forbidden = format!(
+            "Trusted Signing\r\nAzure.RequestFailedException: Service request failed.\r\n\
+             Status: 403 (Forbidden)\r\n{}",
+            unexpected
+        );
+        assert_eq!(cause(&forbidden), Some("signer role missing"));
+        assert_eq!(cause("Status: 404 (Not Found)\r\nErrorCode: NotFound"), Some("account or profile not found"));
+        assert_eq!(cause("Service request failed.\r\nStatus: 429 (Too Many Requests)"), Some("quota exceeded"));
+        let login = "Azure.Identity.CredentialUnavailableException: DefaultAzureCredential failed to retrieve a token";
+        assert_eq!(cause(login), Some("authentication failed"));
+        let dns = "System.Net.Http.HttpRequestException: No such host is known. (weu.codesigning.azure.net:443)";
+        assert_eq!(cause(dns), Some("endpoint unreachable"));
+        let runtime = "You must install or update .NET to run this application.\r\n\
+                       SignTool Error: An unexpected internal error has occurred.";
+        assert_eq!(cause(runtime), Some(".NET runtime missing"));
+        assert_eq!(cause("0x80131500 Exception of type 'System.Exception' was thrown"), Some(".NET exception"));
+        assert_eq!(cause("SignTool Error: No file digest algorithm specified."), None);
+
+        assert_eq!(explain("failed".to_string(), "all fine"), "failed");
+        assert!(explain("failed".to_string(), &forbidden).starts_with("failed\nhint: the identity signing"));
+
+        assert!(is_service_side("Service request failed.\r\nStatus: 503 (Service Unavailable)"));
+        assert!(is_service_side("System.Threading.Tasks.TaskCanceledException: The operation was canceled."));
+        assert!(!is_service_side(&forbidden));
+        assert!(!is_service_side(&format!("{}\r\nThe request timed out", login)));
+        assert!(!is_service_side(unexpected));
+        let unreachable = "SignTool Error: The specified timestamp server either could not be reached or\r\nreturned \
+                           an invalid response.";
+        assert!(is_timestamp_failure(unreachable) && !is_timestamp_failure(&forbidden));
+    }
+}
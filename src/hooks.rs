@@ -0,0 +1,104 @@
+use crate::{encoding, interrupt, logging};
+use clap::ValueEnum;
+use duct::cmd;
+use tracing::{debug, info};
+
+/// What a failing --pre-sign-cmd does to its file
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreSignFailure {
+    /// The file fails like a failed signing, and the run stops unless --continue-on-error is set
+    Fail,
+    /// The file is skipped and the run goes on
+    Skip,
+}
+
+/// Split a command template into words, single or double quotes keep spaces in a word
+fn split(template: &str) -> Result<Vec<String>, String> {
+    let (mut words, mut word, mut quote, mut started) = (Vec::new(), String::new(), None, false);
+    for c in template.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                started = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                }
+                started = false;
+            }
+            (None, c) => {
+                word.push(c);
+                started = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        Err(format!("command '{}' has an unclosed quote", template))?;
+    }
+    if started {
+        words.push(word);
+    }
+    match words.is_empty() {
+        true => Err("the command is empty".to_string()),
+        false => Ok(words),
+    }
+}
+
+/// Check a hook command template, for clap
+pub fn parse(template: &str) -> Result<String, String> {
+    split(template).map(|_| template.to_string())
+}
+
+/// The words of a template with `{name}` replaced by the values of `placeholders`, other braces are kept
+fn render(template: &str, placeholders: &[(&str, &str)]) -> Result<Vec<String>, String> {
+    let words = split(template)?;
+    let replace = |word: String| {
+        placeholders
+            .iter()
+            .fold(word, |word, (name, value)| word.replace(&format!("{{{}}}", name), value))
+    };
+    Ok(words.into_iter().map(replace).collect())
+}
+
+/// Run the hook `name`, its output goes to the log line by line. Programs are run directly, without a shell,
+/// so placeholders never need quoting
+pub fn run(name: &str, template: &str, placeholders: &[(&str, &str)]) -> Result<(), String> {
+    let words = render(template, placeholders)?;
+    debug!("running the {} command: {}", name, logging::mask(&words.join(" ")));
+    let output = interrupt::without_input(cmd(&words[0], &words[1..]))
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(|err| format!("the {} command '{}' could not be run: {}", name, words[0], err))?;
+    for line in logging::mask(&encoding::decode(&output.stdout)).lines() {
+        info!("{}: {}", name, line);
+    }
+    for line in logging::mask(&encoding::decode(&output.stderr)).lines() {
+        info!("{}: {}", name, line);
+    }
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(format!("the {} command failed, error: {}", name, output.status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_templates() {
+        assert_eq!(
+            render(r#"pwsh -File "C:\ci\scan file.ps1" {file} '' "{status}""#, &[("file", "a b.exe"), ("status", "ok")])
+                .unwrap(),
+            [r"pwsh", "-File", r"C:\ci\scan file.ps1", "a b.exe", "", "ok"]
+        );
+        assert_eq!(render("notify {error} {unknown}", &[("error", "")]).unwrap(), ["notify", "", "{unknown}"]);
+        assert!(parse("scan 'a.exe").is_err());
+        assert!(parse("  ").is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{azure, ci, cli::InitArgs, config, discover, endpoint, error::Error, logging, permissions};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Password, Select};
+use std::{fs, io::IsTerminal, path::Path};
+use toml::{Table, Value};
+
+/// Ask for a value unless a flag already gave it
+fn ask(theme: &ColorfulTheme, prompt: &str, given: Option<String>, default: Option<String>) -> Result<String, Error> {
+    if let Some(given) = given {
+        return Ok(given);
+    }
+    let mut input = Input::<String>::with_theme(theme).with_prompt(prompt);
+    if let Some(default) = default {
+        input = input.default(default);
+    }
+    input.interact_text().map_err(|err| Error::Other(format!("prompt failed: {}", err)))
+}
+
+/// Offer the known regions, or any endpoint URL
+fn ask_endpoint(theme: &ColorfulTheme) -> Result<String, Error> {
+    let mut items: Vec<String> = endpoint::REGIONS
+        .iter()
+        .map(|(code, name)| format!("{:<5} {}", code, name))
+        .collect();
+    items.push("other endpoint URL".to_string());
+    let selected = Select::with_theme(theme)
+        .with_prompt("Region of the code signing account")
+        .items(&items)
+        .default(0)
+        .interact()
+        .map_err(|err| Error::Other(format!("prompt failed: {}", err)))?;
+    match endpoint::REGIONS.get(selected) {
+        Some((code, _)) => Ok(endpoint::url(code)),
+        None => Input::<String>::with_theme(theme)
+            .with_prompt("Endpoint URL")
+            .validate_with(|value: &String| endpoint::parse(value).map(|_| ()))
+            .interact_text()
+            .map_err(|err| Error::Other(format!("prompt failed: {}", err)))
+            .and_then(|value| endpoint::parse(&value).map_err(Error::Usage)),
+    }
+}
+
+/// Log in with the service principal and acquire a signing token, the secret only comes from the
+/// environment or a prompt and is never written
+fn test_login(theme: Option<&ColorfulTheme>, args: &InitArgs, cli_path: &str) -> Result<(), Error> {
+    let missing = |name: &str| Error::Usage(format!("{} is needed for the test login", name));
+    let tenant_id = match (&args.azure_tenant_id, theme) {
+        (Some(tenant_id), _) => tenant_id.clone(),
+        (None, Some(theme)) => ask(theme, "Azure tenant id", None, None)?,
+        (None, None) => return Err(missing("AZURE_TENANT_ID")),
+    };
+    let client_id = match (&args.azure_client_id, theme) {
+        (Some(client_id), _) => client_id.clone(),
+        (None, Some(theme)) => ask(theme, "Azure client id", None, None)?,
+        (None, None) => return Err(missing("AZURE_CLIENT_ID")),
+    };
+    let secret = match (std::env::var("AZURE_CLIENT_SECRET"), theme) {
+        (Ok(secret), _) => secret,
+        (Err(_), Some(theme)) => Password::with_theme(theme)
+            .with_prompt("Azure client secret (only used for the test, never saved)")
+            .interact()
+            .map_err(|err| Error::Other(format!("prompt failed: {}", err)))?,
+        (Err(_), None) => return Err(missing("AZURE_CLIENT_SECRET")),
+    };
+    logging::register_secret(&secret);
+
+    azure::login(cli_path, &tenant_id, &client_id, &secret, false).map_err(Error::Auth)?;
+    let expires_on = azure::access_token_expiry(cli_path).map_err(Error::Auth)?;
+    eprintln!("test login succeeded, the signing token is valid until {}", expires_on);
+    Ok(())
+}
+
+/// Add the profile to the config file, keeping the file as it is unless the profile is replaced
+fn write(path: &Path, name: &str, profile: Table, force: bool) -> Result<(), Error> {
+    let existing = match fs::read_to_string(path) {
+        Ok(existing) => Some(existing),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => Err(format!("config '{}' could not be read: {}", path.display(), err))?,
+    };
+    let mut profiles = Table::new();
+    profiles.insert(name.to_string(), Value::Table(profile));
+    let mut section = Table::new();
+    section.insert("profiles".to_string(), Value::Table(profiles));
+
+    let content = match existing {
+        None => toml::to_string(&section).map_err(|err| err.to_string())?,
+        Some(existing) => {
+            let mut table: Table = existing
+                .parse()
+                .map_err(|err| Error::Usage(format!("config '{}' is not valid TOML: {}", path.display(), err)))?;
+            let exists = table
+                .get("profiles")
+                .and_then(Value::as_table)
+                .is_some_and(|profiles| profiles.contains_key(name));
+            match (exists, force) {
+                (true, false) => {
+                    return Err(Error::Usage(format!(
+                        "profile '{}' already exists in config '{}', pass --force to overwrite it",
+                        name,
+                        path.display()
+                    )))
+                }
+                (true, true) => {
+                    let Some(Value::Table(profiles)) = table.get_mut("profiles") else {
+                        unreachable!("checked above");
+                    };
+                    profiles.insert(name.to_string(), section["profiles"][name].clone());
+                    toml::to_string(&table).map_err(|err| err.to_string())?
+                }
+                // appending keeps the comments of the existing file
+                (false, _) => format!(
+                    "{}\n{}",
+                    existing.trim_end(),
+                    toml::to_string(&section).map_err(|err| err.to_string())?
+                ),
+            }
+        }
+    };
+
+    match path.parent() {
+        Some(dir) if dir == config::dir() && !dir.exists() => permissions::create_private_dir(dir)?,
+        Some(dir) => fs::create_dir_all(dir)
+            .map_err(|err| format!("directory '{}' could not be created: {}", dir.display(), err))?,
+        None => (),
+    }
+    fs::write(path, content).map_err(|err| format!("config '{}' could not be written: {}", path.display(), err))?;
+    Ok(())
+}
+
+/// `init`: gather the values for a profile and write it to the config file
+pub fn run(args: InitArgs) -> Result<(), Error> {
+    let path = args.config.clone().unwrap_or_else(config::default_path);
+    let interactive = !args.non_interactive;
+    if interactive && !std::io::stdin().is_terminal() {
+        Err(Error::Usage("stdin is not a terminal, pass --non-interactive".to_string()))?;
+    }
+    if interactive && ci::strict() {
+        Err(ci::prompt_refused("pass --non-interactive"))?;
+    }
+
+    let signtool = args
+        .sing_tool_path
+        .clone()
+        .or_else(|| discover::signtool().map(|path| path.display().to_string()));
+    let azure_cli = args
+        .azure_cli_path
+        .clone()
+        .or_else(|| discover::azure_cli().map(|path| path.display().to_string()));
+    if args.sing_tool_path.is_none() {
+        match &signtool {
+            Some(signtool) => eprintln!("found signtool at {}", signtool),
+            None => eprintln!("signtool was not found, install the Windows SDK or pass --sing-tool-path"),
+        }
+    }
+    if args.azure_cli_path.is_none() {
+        match &azure_cli {
+            Some(azure_cli) => eprintln!("found azure cli at {}", azure_cli),
+            None => eprintln!("azure cli was not found, install it or pass --azure-cli-path"),
+        }
+    }
+
+    let theme = ColorfulTheme::default();
+    let (endpoint, account, certificate, signtool, azure_cli, test) = match interactive {
+        true => {
+            let endpoint = match args.endpoint.clone() {
+                Some(endpoint) => endpoint,
+                None => ask_endpoint(&theme)?,
+            };
+            let account = ask(&theme, "Code signing account name", args.account.clone(), None)?;
+            let certificate = ask(&theme, "Certificate profile name", args.certificate.clone(), None)?;
+            let signtool = ask(&theme, "Signtool path", args.sing_tool_path.clone(), signtool)?;
+            let azure_cli = ask(&theme, "Azure CLI path", args.azure_cli_path.clone(), azure_cli)?;
+            let test = args.test_login
+                || Confirm::with_theme(&theme)
+                    .with_prompt("Log in and acquire a signing token to test the credentials?")
+                    .default(true)
+                    .interact()
+                    .map_err(|err| Error::Other(format!("prompt failed: {}", err)))?;
+            (endpoint, account, certificate, Some(signtool), Some(azure_cli), test)
+        }
+        false => {
+            let missing: Vec<&str> = [
+                ("--endpoint", args.endpoint.is_none()),
+                ("--account", args.account.is_none()),
+                ("--certificate", args.certificate.is_none()),
+            ]
+            .into_iter()
+            .filter_map(|(flag, missing)| missing.then_some(flag))
+            .collect();
+            if !missing.is_empty() {
+                Err(Error::Usage(format!("--non-interactive needs {}", missing.join(", "))))?;
+            }
+            (
+                args.endpoint.clone().unwrap_or_default(),
+                args.account.clone().unwrap_or_default(),
+                args.certificate.clone().unwrap_or_default(),
+                signtool,
+                azure_cli,
+                args.test_login,
+            )
+        }
+    };
+
+    if test {
+        let Some(azure_cli) = &azure_cli else {
+            return Err(Error::MissingDependency {
+                component: "azure cli",
+                message: "the test login needs the azure cli, pass --azure-cli-path".to_string(),
+            });
+        };
+        test_login(interactive.then_some(&theme), &args, azure_cli)?;
+    }
+
+    let mut profile = Table::new();
+    let values = [
+        ("endpoint", Some(endpoint)),
+        ("account", Some(account)),
+        ("certificate", Some(certificate)),
+        ("azure-client-id", args.azure_client_id.clone()),
+        ("azure-tenant-id", args.azure_tenant_id.clone()),
+        ("sing-tool-path", signtool),
+        ("azure-cli-path", azure_cli),
+    ];
+    for (key, value) in values {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            profile.insert(key.to_string(), Value::String(value));
+        }
+    }
+    write(&path, &args.profile, profile, args.force)?;
+
+    eprintln!("wrote profile '{}' to {}", args.profile, path.display());
+    eprintln!("secrets are never written to the config, set AZURE_CLIENT_SECRET in the environment");
+    let config = match args.config {
+        Some(config) => format!(" --config {}", config.display()),
+        None => String::new(),
+    };
+    eprintln!("sign with: trusted-signing-cli --profile {}{} <file>", args.profile, config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_added_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let profile = |account: &str| Table::from_iter([("account".to_string(), Value::String(account.to_string()))]);
+        fs::write(&path, "# shared signing settings\n[profiles.release]\naccount = \"release\"\n").unwrap();
+
+        write(&path, "nightly", profile("nightly"), false).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# shared signing settings"));
+        assert!(content.contains("[profiles.nightly]\naccount = \"nightly\""));
+
+        let err = write(&path, "release", profile("other"), false).unwrap_err();
+        assert!(err.to_string().contains("pass --force"));
+        write(&path, "release", profile("other"), true).unwrap();
+        let profiles: Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+        assert_eq!(profiles["profiles"]["release"]["account"].as_str(), Some("other"));
+        assert_eq!(profiles["profiles"]["nightly"]["account"].as_str(), Some("nightly"));
+    }
+}
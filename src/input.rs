@@ -0,0 +1,255 @@
+use crate::{
+    config::{self, UnsetVars},
+    interrupt,
+};
+use std::{
+    borrow::Cow,
+    ffi::OsString,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// How long a file waited for with --wait-for-file has to be left alone before it counts as written
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// How often a file waited for is looked at
+const POLL: Duration = Duration::from_millis(250);
+
+/// Extensions signtool can sign with an Authenticode signature, `.node` being the DLLs of Node.js native modules.
+/// The list --extensions replaces and --extra-extensions adds to
+pub const DEFAULT_EXTENSIONS: [&str; 21] = [
+    "appx", "appxbundle", "cab", "cat", "dll", "efi", "exe", "js", "msi", "msix", "msixbundle", "msp", "node", "ocx",
+    "ps1", "ps1xml", "psd1", "psm1", "sys", "vbs", "wsf",
+];
+
+/// The extensions to sign: `replace` instead of the defaults when given, plus `extra`. Lowercase and without
+/// leading dots, so a list like `.DLL, exe` works
+pub fn extensions(replace: Option<&[String]>, extra: &[String]) -> Vec<String> {
+    let defaults: Vec<String> = DEFAULT_EXTENSIONS.iter().map(|extension| extension.to_string()).collect();
+    let mut extensions: Vec<String> = Vec::new();
+    for extension in replace.unwrap_or(&defaults).iter().chain(extra) {
+        let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+        if !extension.is_empty() && !extensions.contains(&extension) {
+            extensions.push(extension);
+        }
+    }
+    extensions
+}
+
+/// Whether the file is to be signed with signtool, judged by its extension being one of `extensions`, which
+/// are lowercase like [`extensions`] returns them
+pub fn is_supported(path: &str, extensions: &[String]) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|supported| extension.eq_ignore_ascii_case(supported)))
+}
+
+/// Extensions of PE images, their DOS and PE headers are checked before signing
+const PE_EXTENSIONS: [&str; 8] = ["cpl", "dll", "efi", "exe", "node", "ocx", "scr", "sys"];
+
+/// Extensions of Windows Installer files, which are OLE compound files
+const MSI_EXTENSIONS: [&str; 3] = ["msi", "msm", "msp"];
+
+/// Signature every OLE compound file starts with
+const COMPOUND_FILE: [u8; 8] = [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+
+/// Check the start of a file with `extension` looks like its file type, `file` being at its start
+fn check_header(extension: &str, mut file: impl Read + Seek) -> Result<(), String> {
+    let mut start = Vec::new();
+    file.by_ref().take(64).read_to_end(&mut start).map_err(|err| err.to_string())?;
+    if start.is_empty() {
+        Err("the file is empty")?;
+    }
+    if PE_EXTENSIONS.contains(&extension) {
+        if start.len() < 64 || &start[..2] != b"MZ" {
+            Err("it has no DOS header, it is not a PE image or is truncated")?;
+        }
+        let offset = u32::from_le_bytes([start[60], start[61], start[62], start[63]]);
+        let mut signature = [0; 4];
+        file.seek(SeekFrom::Start(offset.into())).map_err(|err| err.to_string())?;
+        if file.read_exact(&mut signature).is_err() || &signature != b"PE\0\0" {
+            Err("its PE header is missing, the image is truncated or corrupt")?;
+        }
+    } else if MSI_EXTENSIONS.contains(&extension) && !start.starts_with(&COMPOUND_FILE) {
+        Err("it is not a compound file, the installer is truncated or corrupt")?;
+    }
+    Ok(())
+}
+
+/// A cheap check that a file isn't empty and, for PE images and installers, starts like one. A crashed linker
+/// leaves files behind signtool only fails on with "This file format cannot be signed"
+pub fn check_structure(path: &str) -> Result<(), String> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let file = File::open(path).map_err(|err| format!("'{}' could not be read: {}", path, err))?;
+    check_header(&extension, file).map_err(|err| format!("'{}' is invalid, {}", path, err))
+}
+
+/// `path` as the signing tools are given it: a relative path beginning with a dash gets `./` in front, or they
+/// would take it for one of their options
+pub fn tool_path(path: &str) -> Cow<'_, str> {
+    match path.starts_with('-') {
+        true => Cow::Owned(format!(".{}{}", std::path::MAIN_SEPARATOR, path)),
+        false => Cow::Borrowed(path),
+    }
+}
+
+/// The path in the value of the environment variable `name`, taken as it is, without trimming or unquoting
+fn path_from(name: &str, value: Option<OsString>) -> Result<String, String> {
+    let problem = match value.map(OsString::into_string) {
+        Some(Ok(path)) if !path.is_empty() => return Ok(path),
+        Some(Ok(_)) => "is empty",
+        Some(Err(_)) => "is not valid unicode",
+        None => "is not set",
+    };
+    Err(format!("--file-from-env {}: the environment variable {} {}", name, name, problem))
+}
+
+/// The file to sign named by the environment variable `name`, for `--file-from-env`. The path never passes
+/// through a shell, quotes and spaces in it stay as they are
+pub fn from_env(name: &str) -> Result<String, String> {
+    path_from(name, std::env::var_os(name))
+}
+
+/// `path` with the `%VAR%` and `${VAR}` it references expanded, variables that aren't set fail
+fn expand_with(path: &str, var: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    config::expand_vars(path, var, UnsetVars::Error)
+        .map_err(|err| format!("--expand-env-in-paths: the file '{}' can't be expanded, {}", path, err))
+}
+
+/// A file argument with the environment variables it references expanded, for `--expand-env-in-paths`, when
+/// whatever passed it didn't interpolate them
+pub fn expand_env(path: &str) -> Result<String, String> {
+    expand_with(path, &|name| std::env::var(name).ok())
+}
+
+/// A duration like `30`, `30s`, `2m` or `500ms`, seconds without a unit
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("'{}' is not a duration like 30s, 2m or 500ms", value))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        _ => Err(format!("'{}' has an unknown unit, use ms, s or m", value)),
+    }
+}
+
+/// Whether a file of `size` counts as written: nothing touched it for a while, or its size stayed the same since
+/// `since`, for file systems with coarse or odd modification times
+fn settled(untouched: Duration, size: u64, last: Option<(u64, Instant)>) -> (bool, (u64, Instant)) {
+    let since = match last {
+        Some((previous, since)) if previous == size => since,
+        _ => Instant::now(),
+    };
+    (untouched >= SETTLE || since.elapsed() >= SETTLE, (size, since))
+}
+
+/// Wait until `file` exists and nobody writes to it anymore, for build tools still writing or renaming it, up to
+/// `timeout`. Returns how long it waited, Err with that when it gave up or Ctrl+C was pressed
+pub async fn wait_for(file: &str, timeout: Duration) -> Result<Duration, Duration> {
+    let started = Instant::now();
+    let mut last = None;
+    loop {
+        if let Some(metadata) = fs::metadata(file).ok().filter(|metadata| metadata.is_file()) {
+            let untouched = metadata.modified().ok().and_then(|modified| modified.elapsed().ok()).unwrap_or_default();
+            let (done, seen) = settled(untouched, metadata.len(), last);
+            if done {
+                return Ok(started.elapsed());
+            }
+            last = Some(seen);
+        }
+        if started.elapsed() >= timeout || interrupt::interrupted() {
+            return Err(started.elapsed());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(POLL.min(timeout.saturating_sub(started.elapsed()))) => {}
+            _ = interrupt::requested() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_extensions() {
+        let defaults = extensions(None, &[]);
+        assert!(is_supported("app.exe", &defaults));
+        assert!(is_supported(r"C:\dist\Setup.MSI", &defaults));
+        assert!(!is_supported("notes.txt", &defaults));
+        assert!(!is_supported("exe", &defaults));
+
+        let extra = extensions(None, &[".SCR".to_string(), "plugin".to_string(), "exe".to_string()]);
+        assert_eq!(extra.len(), DEFAULT_EXTENSIONS.len() + 2);
+        assert!(is_supported("audio.Plugin", &extra));
+        let replaced = extensions(Some(&["dll".to_string(), " exe".to_string()]), &["cpl".to_string()]);
+        assert_eq!(replaced, ["dll", "exe", "cpl"]);
+        assert!(!is_supported("install.ps1", &replaced));
+    }
+
+    #[test]
+    fn file_structure() {
+        use std::io::Cursor;
+
+        let mut image = vec![0u8; 0x84];
+        image[..2].copy_from_slice(b"MZ");
+        image[60] = 0x80;
+        image[0x80..].copy_from_slice(b"PE\0\0");
+        assert_eq!(check_header("exe", Cursor::new(&image)), Ok(()));
+        assert!(check_header("dll", Cursor::new(&image[..0x82])).unwrap_err().contains("PE header"));
+        assert!(check_header("sys", Cursor::new(&image[..40])).unwrap_err().contains("DOS header"));
+        assert!(check_header("exe", Cursor::new(b"")).unwrap_err().contains("empty"));
+
+        let msi = [COMPOUND_FILE.as_slice(), &[0; 8]].concat();
+        assert_eq!(check_header("msi", Cursor::new(&msi)), Ok(()));
+        assert!(check_header("msi", Cursor::new(b"PK\x03\x04")).is_err());
+        assert_eq!(check_header("ps1", Cursor::new(b"Write-Host")), Ok(()));
+    }
+
+    #[test]
+    fn dash_paths() {
+        assert_eq!(tool_path("dist/-launcher.exe"), "dist/-launcher.exe");
+        assert_eq!(tool_path("-launcher.exe"), format!(".{}-launcher.exe", std::path::MAIN_SEPARATOR));
+        assert!(is_supported("-launcher.exe", &extensions(None, &[])));
+    }
+
+    #[test]
+    fn durations_and_settling() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert!(parse_duration("1h").is_err() && parse_duration("soon").is_err());
+
+        assert!(settled(Duration::from_secs(5), 10, None).0);
+        let (done, seen) = settled(Duration::ZERO, 10, None);
+        assert!(!done);
+        let long_ago = Instant::now() - Duration::from_secs(2);
+        assert!(settled(Duration::ZERO, 10, Some((10, long_ago))).0);
+        assert!(!settled(Duration::ZERO, 12, Some((10, long_ago))).0);
+        assert!(!settled(Duration::ZERO, 10, Some(seen)).0);
+    }
+
+    #[test]
+    fn path_from_environment() {
+        let path = r#"C:\a b\"quoted" $HOME\app.exe"#;
+        assert_eq!(path_from("APP", Some(path.into())).as_deref(), Ok(path));
+        assert!(path_from("APP", None).unwrap_err().contains("APP is not set"));
+        assert!(path_from("APP", Some("".into())).unwrap_err().contains("APP is empty"));
+
+        let var = |name: &str| (name == "GITHUB_WORKSPACE").then(|| r"D:\a\app".to_string());
+        let path = r"%GITHUB_WORKSPACE%\src-tauri\target\release\app.exe";
+        assert_eq!(expand_with(path, &var).unwrap(), r"D:\a\app\src-tauri\target\release\app.exe");
+        assert_eq!(expand_with("${GITHUB_WORKSPACE}/100%.exe", &var).unwrap(), r"D:\a\app/100%.exe");
+        let err = expand_with(r"%RUNNER_TEMP%\app.exe", &var).unwrap_err();
+        assert!(err.ends_with("the environment variable RUNNER_TEMP is not set"), "{}", err);
+    }
+}
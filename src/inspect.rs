@@ -0,0 +1,325 @@
+use crate::{
+    authenticode,
+    certificate::{self, SigningCertificate},
+    der::{self, Element},
+    driver, unsign,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+const RFC3161_COUNTERSIGNATURE: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 3, 3, 1];
+const COUNTERSIGNATURE: &[u64] = &[1, 2, 840, 113549, 1, 9, 6];
+const SIGNING_TIME: &[u64] = &[1, 2, 840, 113549, 1, 9, 5];
+const TST_INFO: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 1, 4];
+
+/// The names of the digest algorithms by OID, as signtool prints them
+pub(crate) const DIGESTS: [(&str, &str); 5] = [
+    ("1.2.840.113549.2.5", "MD5"),
+    ("1.3.14.3.2.26", "SHA1"),
+    ("2.16.840.1.101.3.4.2.1", "SHA256"),
+    ("2.16.840.1.101.3.4.2.2", "SHA384"),
+    ("2.16.840.1.101.3.4.2.3", "SHA512"),
+];
+
+/// The countersignature of a signature by a timestamp authority
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Timestamp {
+    /// `rfc3161`, or `authenticode` for the older countersignatures of signtool /t
+    pub kind: &'static str,
+    /// RFC 3339, UTC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// Digest algorithm the authority signed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Digest algorithm of the hash of the signature an RFC 3161 authority timestamped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imprint_digest: Option<String>,
+    /// The certificate of the timestamp authority
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authority: Option<SigningCertificate>,
+}
+
+/// One signature of a file
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Position among the signatures of the file, the primary signature is 0
+    pub index: usize,
+    /// The index of the signature this one is nested in, appended with signtool /as
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nested_in: Option<usize>,
+    /// Digest algorithm, like SHA256, or its OID when unknown
+    pub digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<SigningCertificate>,
+    /// The signing certificate and its issuers, as far as the signature holds them
+    pub chain: Vec<SigningCertificate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+}
+
+/// A digest algorithm found in the signatures of a file
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct ObservedDigest {
+    /// The index of the signature it was found in
+    pub signature: usize,
+    /// `signature`, `timestamp` or `timestamp imprint`
+    pub of: &'static str,
+    pub digest: String,
+}
+
+/// The signatures embedded in a file, empty when it is unsigned
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Inspection {
+    pub path: String,
+    pub signatures: Vec<Signature>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The name of the digest algorithm `oid`
+fn digest_name(oid: &[u8]) -> String {
+    let oid = der::oid_text(oid);
+    DIGESTS.iter().find(|(known, _)| *known == oid).map_or(oid, |(_, name)| name.to_string())
+}
+
+/// The name of the digest algorithm of an AlgorithmIdentifier
+fn algorithm(identifier: &Element) -> Option<String> {
+    Some(digest_name(der::children(identifier.content)?.first()?.content))
+}
+
+/// The digest algorithm of the first signer of a SignedData
+fn signer_digest(signed_data: &[Element]) -> Option<String> {
+    let signer_info = der::children(signed_data.last().filter(|element| element.tag == der::SET)?.content)?;
+    algorithm(der::children(signer_info.first()?.content)?.get(2)?)
+}
+
+/// Whether `digest` is one of SHA-2, as `--require-sha2` asks for
+pub fn is_sha2(digest: &str) -> bool {
+    ["SHA256", "SHA384", "SHA512"].contains(&digest)
+}
+
+/// Every digest algorithm the signatures of a file use, their timestamps included
+pub fn observed_digests(signatures: &[Signature]) -> Vec<ObservedDigest> {
+    let mut observed = Vec::new();
+    for signature in signatures {
+        let mut add = |of, digest: &str| {
+            observed.push(ObservedDigest { signature: signature.index, of, digest: digest.to_string() })
+        };
+        add("signature", &signature.digest);
+        if let Some(timestamp) = &signature.timestamp {
+            timestamp.digest.iter().for_each(|digest| add("timestamp", digest));
+            timestamp.imprint_digest.iter().for_each(|digest| add("timestamp imprint", digest));
+        }
+    }
+    observed
+}
+
+/// `leaf` followed by its issuers among `certificates`, up to a self-signed one or one whose issuer is missing
+fn chain(leaf: Option<SigningCertificate>, certificates: &[SigningCertificate]) -> Vec<SigningCertificate> {
+    let mut chain: Vec<SigningCertificate> = leaf.into_iter().collect();
+    while let Some(last) = chain.last().filter(|last| last.subject != last.issuer) {
+        let issuer = certificates.iter().find(|certificate| {
+            certificate.subject == last.issuer && !chain.iter().any(|known| known.thumbprint == certificate.thumbprint)
+        });
+        match issuer {
+            Some(issuer) => chain.push(issuer.clone()),
+            None => break,
+        }
+    }
+    chain
+}
+
+/// The RFC 3161 timestamp of the token in a countersignature attribute
+fn rfc3161(token: &[u8]) -> Timestamp {
+    let signed_data = authenticode::signed_data(token);
+    let tst_info = || {
+        let encapsulated = der::children(signed_data.as_ref()?.get(2)?.content)?;
+        let [content_type, explicit] = encapsulated.as_slice() else {
+            return None;
+        };
+        if content_type.encoded != der::oid(TST_INFO) {
+            return None;
+        }
+        let octets = der::read(explicit.content)?.0;
+        // version, policy, message imprint, serial number, then the time
+        der::children(der::read(octets.content)?.0.content)
+    };
+    let tst_info = tst_info().unwrap_or_default();
+    let imprint = tst_info.get(2).and_then(|imprint| der::children(imprint.content));
+    Timestamp {
+        kind: "rfc3161",
+        time: tst_info.get(4).and_then(certificate::time),
+        digest: signed_data.as_deref().and_then(signer_digest),
+        imprint_digest: imprint.as_ref().and_then(|imprint| algorithm(imprint.first()?)),
+        authority: authenticode::signing_certificate(token).and_then(certificate::details),
+    }
+}
+
+/// The timestamp of an Authenticode countersignature, a SignerInfo whose certificate is among `certificates`
+fn countersignature(signer_info: &Element, certificates: Option<&Element>) -> Timestamp {
+    let fields = der::children(signer_info.content).unwrap_or_default();
+    let time = || {
+        let attributes = fields.iter().find(|field| field.tag == der::context(0))?;
+        for attribute in der::children(attributes.content)? {
+            if let [oid, values] = der::children(attribute.content)?[..] {
+                if oid.encoded == der::oid(SIGNING_TIME) {
+                    return certificate::time(der::children(values.content)?.first()?);
+                }
+            }
+        }
+        None
+    };
+    let authority = certificates
+        .zip(fields.get(1))
+        .and_then(|(certificates, id)| authenticode::issued_to(certificates, id))
+        .and_then(certificate::details);
+    let digest = fields.get(2).and_then(algorithm);
+    Timestamp { kind: "authenticode", time: time(), digest, imprint_digest: None, authority }
+}
+
+/// Add the signature of `pkcs7` to `signatures`, then the ones nested in it
+fn read_signature(pkcs7: &[u8], nested_in: Option<usize>, signatures: &mut Vec<Signature>) -> Option<()> {
+    let signed_data = authenticode::signed_data(pkcs7)?;
+    let certificates = signed_data.iter().find(|element| element.tag == der::context(0));
+    let signer_info = der::children(signed_data.last().filter(|element| element.tag == der::SET)?.content)?;
+    let signer = der::children(signer_info.first()?.content)?;
+    let digest = algorithm(signer.get(2)?)?;
+    let all: Vec<SigningCertificate> = certificates
+        .and_then(|certificates| der::children(certificates.content))
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|certificate| certificate::details(certificate.encoded))
+        .collect();
+    let leaf = certificates
+        .and_then(|certificates| authenticode::issued_to(certificates, signer.get(1)?))
+        .and_then(certificate::details);
+
+    let mut timestamp = None;
+    let mut nested = Vec::new();
+    if let Some(unsigned) = signer.last().filter(|element| element.tag == der::context(1)) {
+        for attribute in der::children(unsigned.content)? {
+            let [oid, values] = der::children(attribute.content)?[..] else {
+                continue;
+            };
+            let values = der::children(values.content)?;
+            match oid.encoded {
+                oid if oid == der::oid(RFC3161_COUNTERSIGNATURE) => {
+                    timestamp = values.first().map(|token| rfc3161(token.encoded))
+                }
+                oid if oid == der::oid(COUNTERSIGNATURE) => {
+                    timestamp = values.first().map(|signer_info| countersignature(signer_info, certificates))
+                }
+                oid if oid == der::oid(authenticode::NESTED_SIGNATURE) => {
+                    nested.extend(values.iter().map(|value| value.encoded))
+                }
+                _ => (),
+            }
+        }
+    }
+    let index = signatures.len();
+    signatures.push(Signature { index, nested_in, digest, signer: leaf.clone(), chain: chain(leaf, &all), timestamp });
+    for pkcs7 in nested {
+        read_signature(pkcs7, Some(index), signatures)?;
+    }
+    Some(())
+}
+
+/// The PKCS#7 signatures embedded in a file, by its format
+pub(crate) fn embedded(path: &str) -> Result<Vec<Vec<u8>>, String> {
+    if !Path::new(path).is_file() {
+        Err("file not found")?;
+    }
+    if driver::is_catalog(path) || path.to_ascii_lowercase().ends_with(".p7") {
+        return Ok(vec![fs::read(path).map_err(|err| err.to_string())?]);
+    }
+    if let Some((begin, end)) = unsign::script_markers(path) {
+        let script = fs::read(path).map_err(|err| err.to_string())?;
+        let script = String::from_utf8(script).map_err(|_| "only UTF-8 scripts are supported")?;
+        return Ok(unsign::script_signature(&script, begin, end).into_iter().collect());
+    }
+    let mut headers = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(4096).read_to_end(&mut headers))
+        .map_err(|err| err.to_string())?;
+    match driver::header_offsets(&headers) {
+        Some(_) => certificate::pe_signatures(path),
+        None => Err(format!(
+            "reading the signatures of .{} files is not supported, only of PE images, catalogs, detached PKCS#7 \
+             and scripts",
+            Path::new(path).extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase()
+        )),
+    }
+}
+
+/// The signatures embedded in a file, read offline without signtool
+pub fn inspect(path: &str) -> Inspection {
+    let mut inspection = Inspection { path: path.to_string(), ..Default::default() };
+    let result = embedded(path).and_then(|signatures| {
+        for pkcs7 in signatures {
+            read_signature(&pkcs7, None, &mut inspection.signatures)
+                .ok_or("a signature is not a valid PKCS#7 SignedData")?;
+        }
+        Ok(())
+    });
+    inspection.error = result.err();
+    inspection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certificate_chains() {
+        let certificate = |subject: &str, issuer: &str| SigningCertificate {
+            subject: subject.to_string(),
+            issuer: issuer.to_string(),
+            thumbprint: subject.to_string(),
+            serial: "01".to_string(),
+            not_before: "2026-10-14T00:00:00Z".to_string(),
+            not_after: "2026-10-17T00:00:00Z".to_string(),
+            eku: Vec::new(),
+        };
+        let (leaf, intermediate, root) =
+            (certificate("CN=App", "CN=CA"), certificate("CN=CA", "CN=Root"), certificate("CN=Root", "CN=Root"));
+        let subjects = |chain: Vec<SigningCertificate>| chain.into_iter().map(|c| c.subject).collect::<Vec<_>>();
+        let all = [root.clone(), leaf.clone(), intermediate.clone()];
+        assert_eq!(subjects(chain(Some(leaf.clone()), &all)), ["CN=App", "CN=CA", "CN=Root"]);
+        assert_eq!(subjects(chain(Some(leaf), &[root])), ["CN=App"]);
+        assert!(chain(None, &all).is_empty());
+        assert_eq!(digest_name(&der::oid(&[2, 16, 840, 1, 101, 3, 4, 2, 1])[2..]), "SHA256");
+
+        let timestamp = Timestamp {
+            kind: "rfc3161",
+            time: None,
+            digest: Some("SHA256".to_string()),
+            imprint_digest: Some("SHA1".to_string()),
+            authority: None,
+        };
+        let signature = Signature {
+            index: 0,
+            nested_in: None,
+            digest: "SHA256".to_string(),
+            signer: None,
+            chain: Vec::new(),
+            timestamp: Some(timestamp),
+        };
+        let observed = observed_digests(&[signature]);
+        let weak: Vec<_> = observed.into_iter().filter(|observed| !is_sha2(&observed.digest)).collect();
+        assert_eq!(weak, [ObservedDigest { signature: 0, of: "timestamp imprint", digest: "SHA1".to_string() }]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("setup.ps1").display().to_string();
+        fs::write(&script, "Write-Host hi\r\n").unwrap();
+        assert_eq!(inspect(&script), Inspection { path: script.clone(), ..Default::default() });
+        let msi = dir.path().join("setup.msi").display().to_string();
+        fs::write(&msi, "msi").unwrap();
+        assert!(inspect(&msi).error.unwrap().contains("not supported"));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    ci,
+    error::{exit_code, Error},
+    scratch,
+};
+use duct::{Expression, Handle};
+use std::{
+    io,
+    process::Output,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+/// Set by the first Ctrl+C, no child process is started after it
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Set once --max-runtime is over or too little of it is left for another file, stops the run like Ctrl+C
+static DEADLINE_EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+/// When --max-runtime is over, and what it was
+static DEADLINE: OnceLock<(Instant, Duration)> = OnceLock::new();
+
+/// Wakes whatever waits for Ctrl+C
+static NOTIFY: Notify = Notify::const_new();
+
+/// The child processes running right now, killed by Ctrl+C
+static CHILDREN: Mutex<Vec<Arc<Handle>>> = Mutex::new(Vec::new());
+
+/// Whether Ctrl+C was pressed or --max-runtime stopped the run
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst) || DEADLINE_EXCEEDED.load(Ordering::SeqCst)
+}
+
+/// The error of a stopped run, Ctrl+C taking precedence over --max-runtime
+pub fn error() -> Error {
+    match (INTERRUPTED.load(Ordering::SeqCst), DEADLINE.get()) {
+        (false, Some((_, max_runtime))) => Error::DeadlineExceeded { max_runtime: *max_runtime },
+        _ => Error::Interrupted,
+    }
+}
+
+/// Stop the run in `max_runtime`, killing the child processes running then like Ctrl+C does
+pub fn set_deadline(max_runtime: Duration) {
+    let deadline = Instant::now() + max_runtime;
+    if DEADLINE.set((deadline, max_runtime)).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        tokio::time::sleep_until(deadline.into()).await;
+        if !interrupted() {
+            warn!("--max-runtime is over, cancelling the files being signed");
+        }
+        stop_for_deadline();
+        for child in CHILDREN.lock().unwrap().iter() {
+            kill(child);
+        }
+    });
+}
+
+/// Stop the run before --max-runtime is over, no other file fits in what is left of it
+pub fn stop_for_deadline() {
+    DEADLINE_EXCEEDED.store(true, Ordering::SeqCst);
+    NOTIFY.notify_waiters();
+}
+
+/// What is left of --max-runtime, `None` without one
+pub fn remaining() -> Option<Duration> {
+    DEADLINE.get().map(|(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Whether work taking `duration` fits in what is left of --max-runtime
+pub fn fits(duration: Duration) -> bool {
+    remaining().is_none_or(|remaining| remaining >= duration)
+}
+
+/// A timeout of its own cut to what is left of --max-runtime
+pub fn capped(timeout: Duration) -> Duration {
+    remaining().map_or(timeout, |remaining| remaining.min(timeout))
+}
+
+/// Handle Ctrl+C from now on: the first one kills the child processes running and stops the run, which then
+/// reports the files signed so far. A second one exits at once
+pub fn install() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                eprintln!("interrupted again, removing the temporary files and exiting");
+                scratch::remove_all();
+                std::process::exit(exit_code::INTERRUPTED.into());
+            }
+            warn!("interrupted, stopping after the files signed so far, press Ctrl+C again to exit at once");
+            for child in CHILDREN.lock().unwrap().iter() {
+                kill(child);
+            }
+            NOTIFY.notify_waiters();
+        }
+    });
+}
+
+/// Wait for Ctrl+C or the end of --max-runtime, to stop work that is awaited instead of run as a child process
+pub async fn requested() {
+    let notified = NOTIFY.notified();
+    if !interrupted() {
+        notified.await;
+    }
+}
+
+/// Kill a child process, on Windows with the processes it started, like the Python behind az.cmd. Elsewhere the
+/// Ctrl+C of a terminal reaches those itself
+fn kill(child: &Handle) {
+    debug!("killing {:?}", child.pids());
+    if cfg!(windows) {
+        for pid in child.pids() {
+            let pid = pid.to_string();
+            let _ = std::process::Command::new("taskkill").args(["/F", "/T", "/PID", &pid]).output();
+        }
+    }
+    let _ = child.kill();
+}
+
+/// `expression` with a closed stdin in CI mode, so a child process asking for input fails instead of waiting
+pub fn without_input(expression: Expression) -> Expression {
+    match ci::strict() {
+        true => expression.stdin_null(),
+        false => expression,
+    }
+}
+
+/// Run a child process to its end, or until Ctrl+C kills it. None is started once Ctrl+C was pressed
+pub fn output(expression: Expression) -> io::Result<Output> {
+    if interrupted() {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+    }
+    let child = Arc::new(without_input(expression).start()?);
+    CHILDREN.lock().unwrap().push(child.clone());
+    // Ctrl+C may have come in between
+    if interrupted() {
+        kill(&child);
+    }
+    let output = child.wait().cloned();
+    CHILDREN.lock().unwrap().retain(|running| !Arc::ptr_eq(running, &child));
+    output
+}
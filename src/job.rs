@@ -0,0 +1,257 @@
+//! `--job`: the files to sign with their settings as one JSON document, for orchestrators that would rather hand over
+//! a job than assemble command lines
+
+use crate::{
+    cli::Args,
+    endpoint,
+    error::Error,
+    report::OutputFormat,
+    route::Route,
+    signtool::Digest,
+};
+use glob::Pattern;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_path_to_error::Segment;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+use tracing::debug;
+
+/// A job: what to sign, with what, and how to go on after a failure
+#[derive(Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// The schema the document follows, not read
+    #[serde(rename = "$schema", default)]
+    pub schema: Option<String>,
+    /// What every file is signed with unless it says otherwise. Flags and the config fill in what is left out
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub execution: Execution,
+    /// The files to sign, in order
+    pub files: Vec<JobFile>,
+}
+
+/// The settings of every file of a job
+#[derive(Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    /// Signing endpoint, a full URL or a region code like eus, like -e
+    pub endpoint: Option<String>,
+    /// Code signing account, like -a
+    pub account: Option<String>,
+    /// Certificate profile, like -c
+    pub certificate_profile: Option<String>,
+    /// Like --fd
+    pub file_digest: Option<Digest>,
+    /// RFC 3161 timestamp server, like --tr
+    pub timestamp_url: Option<String>,
+    /// Like --td
+    pub timestamp_digest: Option<Digest>,
+    /// Like --description
+    pub description: Option<String>,
+}
+
+/// How a job goes on after a failure
+#[derive(Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Execution {
+    /// Keep signing the remaining files after a failure, like --continue-on-error
+    pub continue_on_error: Option<bool>,
+    /// Endpoints a file is tried at again after a service-side failure, like --fallback-endpoint
+    #[serde(default)]
+    pub fallback_endpoints: Vec<String>,
+}
+
+/// A file of a job
+#[derive(Deserialize, JsonSchema, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobFile {
+    pub path: String,
+    /// Description of this file instead of the default one
+    pub description: Option<String>,
+    /// Certificate profile of this file instead of the default one, in the same account
+    pub certificate_profile: Option<String>,
+    /// Where the signed file goes. The file at `path` is copied there, and only the copy is signed
+    pub output: Option<String>,
+}
+
+/// The JSON pointer of a path serde_path_to_error tracked, like `/files/2/path`
+fn pointer(path: &serde_path_to_error::Path) -> String {
+    path.iter()
+        .map(|segment| match segment {
+            Segment::Seq { index } => format!("/{}", index),
+            Segment::Map { key } => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+            Segment::Enum { variant } => format!("/{}", variant),
+            Segment::Unknown => "/?".to_string(),
+        })
+        .collect()
+}
+
+/// The error of the value at `pointer`
+fn invalid(pointer: &str, message: impl std::fmt::Display) -> Error {
+    Error::Usage(format!("the job is invalid at {}: {}", pointer, message))
+}
+
+/// Read a job, checking its fields and values and naming where a bad one is with a JSON pointer
+pub fn parse(content: &str) -> Result<Job, Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(content);
+    let job: Job = serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+        let pointer = pointer(err.path());
+        match (pointer.as_str(), err.inner().is_syntax() || err.inner().is_eof()) {
+            ("" | ".", _) | (_, true) => Error::Usage(format!("the job is invalid: {}", err.inner())),
+            (pointer, false) => invalid(pointer, err.inner()),
+        }
+    })?;
+    deserializer.end().map_err(|err| Error::Usage(format!("the job is invalid: {}", err)))?;
+
+    if let Some(endpoint) = &job.defaults.endpoint {
+        endpoint::normalize(endpoint).map_err(|err| invalid("/defaults/endpoint", err))?;
+    }
+    for (index, endpoint) in job.execution.fallback_endpoints.iter().enumerate() {
+        endpoint::normalize(endpoint).map_err(|err| invalid(&format!("/execution/fallback_endpoints/{}", index), err))?;
+    }
+    let blank = |value: &Option<String>| value.as_ref().is_some_and(|value| value.trim().is_empty());
+    if blank(&job.defaults.account) {
+        Err(invalid("/defaults/account", "it is empty"))?;
+    }
+    if blank(&job.defaults.certificate_profile) {
+        Err(invalid("/defaults/certificate_profile", "it is empty"))?;
+    }
+    if job.files.is_empty() {
+        Err(invalid("/files", "it lists no files"))?;
+    }
+    // where each file written to was listed first
+    let mut written: HashMap<&str, usize> = HashMap::new();
+    for (index, file) in job.files.iter().enumerate() {
+        let at = |field: &str| format!("/files/{}/{}", index, field);
+        if file.path.trim().is_empty() {
+            Err(invalid(&at("path"), "it is empty"))?;
+        }
+        if blank(&file.certificate_profile) {
+            Err(invalid(&at("certificate_profile"), "it is empty"))?;
+        }
+        let (field, target) = match &file.output {
+            Some(output) if output.trim().is_empty() => Err(invalid(&at("output"), "it is empty"))?,
+            Some(output) if Path::new(output) == Path::new(&file.path) => {
+                Err(invalid(&at("output"), "it is the path, leave it out to sign the file in place"))?
+            }
+            Some(output) => ("output", output.as_str()),
+            None => ("path", file.path.as_str()),
+        };
+        if let Some(first) = written.insert(target, index) {
+            Err(invalid(&at(field), format!("'{}' is signed by /files/{} already", target, first)))?;
+        }
+    }
+    Ok(job)
+}
+
+/// Read the job at `source`, `-` reading it from stdin
+pub fn read(source: &str) -> Result<Job, Error> {
+    let content = match source {
+        "-" => {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|err| Error::Usage(format!("the job could not be read from stdin: {}", err)))?;
+            content
+        }
+        path => fs::read_to_string(path)
+            .map_err(|err| Error::Usage(format!("the job '{}' could not be read: {}", path, err)))?,
+    };
+    parse(&content)
+}
+
+/// Sign the files of `job` with the settings it gives over those of `args`, printing the JSON report to stdout.
+/// Files with an output are copied there first, the copies get signed
+pub fn apply(job: Job, args: &mut Args) -> Result<(), Error> {
+    let Job { defaults, execution, files, .. } = job;
+    if let Some(endpoint) = defaults.endpoint {
+        args.endpoint = Some(endpoint::normalize(&endpoint)?);
+    }
+    args.account = defaults.account.or(args.account.take());
+    args.certificate = defaults.certificate_profile.or(args.certificate.take());
+    args.fd = defaults.file_digest.unwrap_or(args.fd);
+    args.tr = defaults.timestamp_url.unwrap_or(std::mem::take(&mut args.tr));
+    args.td = defaults.timestamp_digest.unwrap_or(args.td);
+    args.description = defaults.description.or(args.description.take());
+    args.continue_on_error = execution.continue_on_error.unwrap_or(args.continue_on_error);
+    for endpoint in &execution.fallback_endpoints {
+        args.fallback_endpoint.push(endpoint::normalize(endpoint)?);
+    }
+    args.output = OutputFormat::Json;
+
+    let mut routes = Vec::new();
+    for file in files {
+        let path = match file.output {
+            Some(output) => {
+                let failed = |err: io::Error| format!("'{}' could not be copied to '{}': {}", file.path, output, err);
+                let dir = Path::new(&output).parent().filter(|dir| !dir.as_os_str().is_empty());
+                dir.map(fs::create_dir_all).transpose().map_err(failed)?;
+                fs::copy(&file.path, &output).map_err(failed)?;
+                debug!("copied {} to {} to sign it there", file.path, output);
+                output
+            }
+            None => file.path,
+        };
+        if let Some(certificate) = file.certificate_profile {
+            let pattern = Pattern::new(&Pattern::escape(&path)).map_err(|err| err.to_string())?;
+            routes.push(Route { pattern, certificate, account: None, endpoint: None });
+        }
+        if let Some(description) = file.description {
+            args.file_descriptions.push((path.clone(), description));
+        }
+        args.file.push(path);
+    }
+    // the files of the job go to their profile whatever --route says
+    args.route.splice(0..0, routes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointers_to_bad_values() {
+        let job = parse(
+            r#"{
+                "defaults": {
+                    "endpoint": "eus", "account": "acc", "certificate_profile": "prof", "file_digest": "SHA384"
+                },
+                "execution": { "continue_on_error": true },
+                "files": [
+                    { "path": "app.exe", "description": "My App" },
+                    { "path": "setup.msi", "certificate_profile": "installers", "output": "signed/setup.msi" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(job.defaults.file_digest, Some(Digest::Sha384));
+        assert_eq!(job.files[1].output.as_deref(), Some("signed/setup.msi"));
+
+        let error = |document: &str| parse(document).unwrap_err().to_string();
+        let defaults =
+            |defaults: &str| error(&format!(r#"{{ "defaults": {}, "files": [{{ "path": "a.exe" }}] }}"#, defaults));
+        assert!(error(r#"{ "files": [{ "path": "a.exe" }, { "path": 3 }] }"#)
+            .starts_with("the job is invalid at /files/1/path: invalid type"));
+        assert!(defaults(r#"{ "profile": "p" }"#).starts_with("the job is invalid at /defaults/profile: unknown"));
+        assert!(defaults(r#"{ "file_digest": "MD5" }"#).contains("/defaults/file_digest: unknown variant"));
+        assert!(defaults(r#"{ "endpoint": "nowhere" }"#).contains("at /defaults/endpoint: "));
+        assert!(defaults(r#"{ "account": " " }"#).ends_with("at /defaults/account: it is empty"));
+        assert!(error(r#"{ "files": [] }"#).ends_with("at /files: it lists no files"));
+        assert!(error(r#"{ "files": [{ "path": "a.exe" }, { "path": "b.exe", "output": "a.exe" }] }"#)
+            .ends_with("at /files/1/output: 'a.exe' is signed by /files/0 already"));
+        assert!(error(r#"{ "files": [{ "path": "a.exe", "output": "a.exe" }] }"#)
+            .contains("/files/0/output: it is the path"));
+        assert!(error(r#"{ "files": [{ "path": "a.exe" }] } trailing"#)
+            .starts_with("the job is invalid: trailing characters"));
+        assert!(error(r#"{ "files": "#).starts_with("the job is invalid: EOF"));
+        assert!(error(r#"{}"#).contains("missing field `files`"));
+    }
+}
@@ -0,0 +1,230 @@
+//! The journal of a run: the outcome of every file appended as it completes, so `--resume` picks a run of
+//! thousands of files up where it was interrupted instead of signing everything again
+
+use crate::{
+    config, hash,
+    report::{FileReport, Status},
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, warn};
+
+/// Runs of this many files get a journal without --journal
+pub const LARGE_RUN: usize = 100;
+
+/// Version of the journal format, journals of another version are started over
+const VERSION: u32 = 1;
+
+/// The first line of a journal, what its entries are valid for
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Header {
+    version: u32,
+    /// SHA-256 of the effective configuration
+    config: String,
+    /// SHA-256 of the files of the run
+    files: String,
+    /// RFC 3339, UTC
+    started_at: String,
+}
+
+/// The outcome of a file, a line of the journal
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    path: String,
+    status: Status,
+    /// SHA-256 of the file as signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256_after: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// RFC 3339, UTC
+    completed_at: String,
+}
+
+fn sha256(text: &str) -> String {
+    hash::hex(&Sha256::digest(text.as_bytes()))
+}
+
+fn absolute(file: &str) -> String {
+    std::path::absolute(file).unwrap_or_else(|_| file.into()).display().to_string()
+}
+
+/// SHA-256 of the files of a run, whatever their order
+fn input_set(files: &[String]) -> String {
+    let mut files: Vec<String> = files.iter().map(|file| absolute(file)).collect();
+    files.sort();
+    sha256(&files.join("\n"))
+}
+
+/// Where the journal of a run of `files` goes without --journal, in the config directory
+pub fn default_path(files: &[String]) -> PathBuf {
+    config::dir().join("journals").join(format!("{}.jsonl", &input_set(files)[..16]))
+}
+
+/// An open journal
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    /// The files an earlier run signed, with their SHA-256 once signed
+    signed: HashMap<String, String>,
+}
+
+impl Journal {
+    /// Open the journal at `path` for a run of `files` with the effective configuration `config`. With `resume` the
+    /// entries of an earlier run of the same files and configuration are kept, otherwise the journal starts over
+    pub fn open(path: &Path, files: &[String], config: &str, resume: bool) -> Result<Journal, String> {
+        let header = Header {
+            version: VERSION,
+            config: sha256(config),
+            files: input_set(files),
+            started_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        let failed = |err: std::io::Error| format!("the journal '{}' could not be written: {}", path.display(), err);
+        let signed = match resume {
+            true => Self::read(path, &header),
+            false => None,
+        };
+        let signed = match signed {
+            Some(signed) => signed,
+            None => {
+                let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                fs::create_dir_all(dir).map_err(failed)?;
+                let mut temp = tempfile::NamedTempFile::new_in(dir).map_err(failed)?;
+                let line = serde_json::to_string(&header).map_err(|err| err.to_string())?;
+                temp.write_all(format!("{}\n", line).as_bytes()).map_err(failed)?;
+                temp.as_file().sync_all().map_err(failed)?;
+                temp.persist(path).map_err(|err| failed(err.error))?;
+                HashMap::new()
+            }
+        };
+        let file = OpenOptions::new().append(true).open(path).map_err(failed)?;
+        Ok(Journal { path: path.to_path_buf(), file, signed })
+    }
+
+    /// The files signed by the run that wrote the journal at `path`, `None` when it has to start over
+    fn read(path: &Path, header: &Header) -> Option<HashMap<String, String>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                debug!("no journal to resume at {}: {}", path.display(), err);
+                return None;
+            }
+        };
+        let mut lines = content.lines();
+        let recorded: Option<Header> = lines.next().and_then(|line| serde_json::from_str(line).ok());
+        let stale = match recorded {
+            None => Some("it is not a journal"),
+            Some(recorded) if recorded.version != header.version => Some("it was written by another version"),
+            Some(recorded) if recorded.files != header.files => Some("it was written for other files"),
+            Some(recorded) if recorded.config != header.config => Some("it was written with another configuration"),
+            Some(_) => None,
+        };
+        if let Some(stale) = stale {
+            warn!("the journal {} can't be resumed, {}. Every file is signed again", path.display(), stale);
+            return None;
+        }
+        let mut signed = HashMap::new();
+        // the last line is cut short when the run was killed while writing it
+        for entry in lines.filter_map(|line| serde_json::from_str::<Entry>(line).ok()) {
+            match (entry.status, entry.sha256_after) {
+                (Status::Signed, Some(sha256)) => signed.insert(entry.path, sha256),
+                _ => signed.remove(&entry.path),
+            };
+        }
+        Some(signed)
+    }
+
+    /// Whether an earlier run signed `file` and it is still the file it signed
+    pub fn is_done(&self, file: &str) -> bool {
+        let Some(recorded) = self.signed.get(&absolute(file)) else {
+            return false;
+        };
+        match hash::sha256_file(file) {
+            Ok(sha256) if &sha256 == recorded => true,
+            _ => {
+                debug!("{} changed since the journal recorded it as signed", file);
+                false
+            }
+        }
+    }
+
+    /// Append the outcome of a file and flush it to disk before returning
+    pub fn record(&mut self, file: &FileReport) -> Result<(), String> {
+        let entry = Entry {
+            path: absolute(&file.path),
+            status: file.status,
+            sha256_after: file.sha256_after.clone().filter(|_| file.status == Status::Signed),
+            error: file.error.clone(),
+            completed_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        };
+        let line = serde_json::to_string(&entry).map_err(|err| err.to_string())? + "\n";
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|err| format!("the journal '{}' could not be written: {}", self.path.display(), err))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Delete the journal once the run succeeded
+    pub fn remove(self) -> Result<(), String> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+            .map_err(|err| format!("the journal '{}' could not be deleted: {}", self.path.display(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Report;
+
+    #[test]
+    fn resumed_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.jsonl");
+        let [app, setup] = ["app.exe", "setup.msi"].map(|name| dir.path().join(name).display().to_string());
+        for file in [&app, &setup] {
+            fs::write(file, b"signed").unwrap();
+        }
+        let files = [app.clone(), setup.clone()];
+        let report = |path: &str, status| FileReport {
+            sha256_after: Some(hash::sha256_file(path).unwrap()),
+            status,
+            ..Report::new(&[path.to_string()]).files.remove(0)
+        };
+        let signed = |path: &str| report(path, Status::Signed);
+        let mut journal = Journal::open(&path, &files, "profile=a", true).unwrap();
+        assert!(!journal.is_done(&app));
+        journal.record(&signed(&app)).unwrap();
+        journal.record(&signed(&setup)).unwrap();
+        journal.record(&report(&setup, Status::Failed)).unwrap();
+        drop(journal);
+
+        // in another order, the last outcome of a file counts
+        let reversed = [setup.clone(), app.clone()];
+        let journal = Journal::open(&path, &reversed, "profile=a", true).unwrap();
+        assert!(journal.is_done(&app) && !journal.is_done(&setup));
+        fs::write(&app, b"rebuilt").unwrap();
+        assert!(!journal.is_done(&app));
+        journal.remove().unwrap();
+        assert!(!path.exists());
+
+        fs::write(&app, b"signed").unwrap();
+        Journal::open(&path, &files, "profile=a", false).unwrap().record(&signed(&app)).unwrap();
+        assert!(!Journal::open(&path, &files, "profile=b", true).unwrap().is_done(&app));
+        Journal::open(&path, &files, "profile=a", false).unwrap().record(&signed(&app)).unwrap();
+        assert!(!Journal::open(&path, &files[..1], "profile=a", true).unwrap().is_done(&app));
+        assert!(default_path(&files).starts_with(config::dir().join("journals")));
+        assert_eq!(default_path(&files), default_path(&reversed));
+    }
+}
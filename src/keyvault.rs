@@ -0,0 +1,151 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    signtool::Digest,
+};
+use duct::cmd;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// AzureSignTool as `dotnet tool install --global AzureSignTool` puts it on the PATH
+pub const DEFAULT_TOOL: &str = "AzureSignTool";
+
+/// How AzureSignTool is asked to sign
+#[derive(Debug)]
+pub struct Options<'a> {
+    pub vault_uri: &'a str,
+    pub certificate: &'a str,
+    pub tenant_id: &'a str,
+    pub client_id: &'a str,
+    /// Empty to authenticate with the managed identity of the machine
+    pub client_secret: &'a str,
+    pub file_digest: Digest,
+    pub timestamp_url: &'a str,
+    pub timestamp_digest: Digest,
+    pub description: Option<&'a str>,
+    pub append: bool,
+}
+
+/// Check a Key Vault URI like `https://my-vault.vault.azure.net`, returning it without a trailing slash
+pub fn parse_uri(uri: &str) -> Result<String, String> {
+    let uri = uri.trim().trim_end_matches('/');
+    let host = uri.strip_prefix("https://").unwrap_or_default();
+    match !host.is_empty() && !host.contains('/') && host.contains('.') {
+        true => Ok(uri.to_string()),
+        false => Err(format!("'{}' is not a Key Vault URI like https://my-vault.vault.azure.net", uri)),
+    }
+}
+
+/// The name of the vault at `uri`, the first label of its host
+pub fn vault_name(uri: &str) -> &str {
+    let host = uri.strip_prefix("https://").unwrap_or(uri);
+    host.split('.').next().unwrap_or(host)
+}
+
+/// The AzureSignTool to run, `tool` itself when it is a path, otherwise looked up on the PATH
+pub fn find(tool: &str) -> Result<PathBuf, Error> {
+    let path = Path::new(tool);
+    let found = match path.components().count() > 1 {
+        true => path.is_file().then(|| path.to_path_buf()),
+        false => std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .flat_map(|dir| [dir.join(tool), dir.join(format!("{}.exe", tool))])
+                .find(|candidate| candidate.is_file())
+        }),
+    };
+    let tool = found.ok_or_else(|| Error::MissingDependency {
+        component: "AzureSignTool",
+        message: format!(
+            "AzureSignTool '{}' was not found, signing with Key Vault needs it: install it with \
+             `dotnet tool install --global AzureSignTool` or pass --azure-sign-tool-path",
+            tool
+        ),
+    })?;
+    debug!("signing with Key Vault through {}", tool.display());
+    Ok(tool)
+}
+
+/// Arguments of `AzureSignTool sign` for signing `file`
+pub fn arguments(file: &str, options: &Options) -> Vec<OsString> {
+    let mut args: Vec<OsString> = ["sign", "-kvu", options.vault_uri, "-kvc", options.certificate]
+        .map(OsString::from)
+        .to_vec();
+    match options.client_secret.is_empty() {
+        true => args.push("-kvm".into()),
+        false => args.extend(
+            ["-kvt", options.tenant_id, "-kvi", options.client_id, "-kvs", options.client_secret].map(OsString::from),
+        ),
+    }
+    args.extend(
+        [
+            "-fd",
+            &options.file_digest.name().to_ascii_lowercase(),
+            "-tr",
+            options.timestamp_url,
+            "-td",
+            &options.timestamp_digest.name().to_ascii_lowercase(),
+        ]
+        .map(OsString::from),
+    );
+    if let Some(description) = options.description {
+        args.extend(["-d", description].map(OsString::from));
+    }
+    if options.append {
+        args.push("-as".into());
+    }
+    args.push(file.into());
+    args
+}
+
+/// Sign a file in place with the Key Vault certificate through AzureSignTool
+pub fn sign(tool: &Path, options: &Options, file: &str) -> Result<(), Error> {
+    let message = format!("AzureSignTool could not sign '{}' with Key Vault certificate {}", file, options.certificate);
+    let output = run_captured(cmd(tool, arguments(file, options))).map_err(|err| Error::Signing {
+        path: file.to_string(),
+        message: format!("{}, error: {:?}", message, &err),
+        output: String::new(),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_sign_tool_arguments() {
+        assert_eq!(parse_uri("https://my-vault.vault.azure.net/").unwrap(), "https://my-vault.vault.azure.net");
+        assert!(parse_uri("my-vault").is_err());
+        assert_eq!(vault_name("https://my-vault.vault.azure.net"), "my-vault");
+
+        let options = Options {
+            vault_uri: "https://my-vault.vault.azure.net",
+            certificate: "ev-cert",
+            tenant_id: "tid",
+            client_id: "cid",
+            client_secret: "",
+            file_digest: Digest::Sha256,
+            timestamp_url: "http://timestamp.digicert.com",
+            timestamp_digest: Digest::Sha384,
+            description: None,
+            append: true,
+        };
+        let args: Vec<String> =
+            arguments("app.exe", &options).into_iter().map(|arg| arg.to_string_lossy().to_string()).collect();
+        assert_eq!(
+            args.join(" "),
+            "sign -kvu https://my-vault.vault.azure.net -kvc ev-cert -kvm -fd sha256 \
+             -tr http://timestamp.digicert.com -td sha384 -as app.exe"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Sign files with Trusted Signing from Rust, the library behind `trusted-signing-cli`.
+//!
+//! ```no_run
+//! use trusted_signing_cli::{Events, SignOptions, Signer};
+//!
+//! # async fn sign() -> Result<(), trusted_signing_cli::SignError> {
+//! let options = SignOptions::new("eus", "my-account", "public-trust")?.credentials("tenant", "client", "secret");
+//! let mut signer = Signer::new(options, Events::default());
+//! let outcome = signer.sign_file("target/release/app.exe").await?;
+//! println!("signed {} with {}", outcome.path, outcome.target.certificate);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Progress is reported as [`Event`]s to the [`Sink`]s added to the [`Events`] of the signer.
+//! Nothing here prints, failures come back as [`SignError`].
+
+pub mod arch;
+pub mod attestation;
+pub mod audit;
+pub mod authenticode;
+pub mod azure;
+pub mod burn;
+pub mod catalog;
+pub mod certificate;
+pub mod checksums;
+pub mod clickonce;
+pub mod clock;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod driver;
+pub mod endpoint;
+pub mod error;
+pub mod eventlog;
+pub mod events;
+#[cfg(feature = "download")]
+pub mod feed;
+pub mod hash;
+pub mod input;
+pub mod inspect;
+pub mod keyvault;
+pub mod manifest;
+pub mod metrics;
+pub mod msix;
+pub mod nuget;
+pub mod pe;
+pub mod pin;
+pub mod report;
+pub mod rest;
+pub mod revocation;
+pub mod route;
+pub mod schema;
+pub mod session;
+pub mod signer;
+pub mod signtool;
+pub mod squirrel;
+pub mod tauri;
+pub mod timestamp;
+pub mod unsign;
+pub mod verify;
+pub mod vsix;
+
+// used by the binaries, not part of the library API
+#[doc(hidden)]
+pub mod app;
+#[doc(hidden)]
+pub mod bench;
+#[doc(hidden)]
+pub mod build;
+#[doc(hidden)]
+pub mod cabinet;
+#[doc(hidden)]
+pub mod cargo;
+#[doc(hidden)]
+pub mod ci;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod color;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod credman;
+#[doc(hidden)]
+pub mod daemon;
+#[doc(hidden)]
+pub mod der;
+#[doc(hidden)]
+pub mod discover;
+#[doc(hidden)]
+pub mod dotenv;
+#[doc(hidden)]
+pub mod electron;
+#[doc(hidden)]
+pub mod encoding;
+#[doc(hidden)]
+pub mod hints;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod init;
+#[doc(hidden)]
+pub mod interrupt;
+#[doc(hidden)]
+pub mod job;
+#[doc(hidden)]
+pub mod journal;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod marker;
+#[doc(hidden)]
+pub mod network;
+#[doc(hidden)]
+#[cfg(feature = "otlp")]
+pub mod otlp;
+#[doc(hidden)]
+pub mod pending;
+#[doc(hidden)]
+pub mod permissions;
+#[doc(hidden)]
+pub mod pipe;
+#[doc(hidden)]
+pub mod profiles;
+#[doc(hidden)]
+pub mod principal;
+#[doc(hidden)]
+pub mod progress;
+#[doc(hidden)]
+pub mod psmodule;
+#[doc(hidden)]
+pub mod purge;
+#[doc(hidden)]
+pub mod quota;
+#[doc(hidden)]
+pub mod redact;
+#[doc(hidden)]
+pub mod scratch;
+#[doc(hidden)]
+pub mod serve;
+#[doc(hidden)]
+pub mod settings;
+#[doc(hidden)]
+pub mod smoke;
+#[doc(hidden)]
+pub mod status;
+#[doc(hidden)]
+pub mod testcert;
+#[doc(hidden)]
+pub mod unzip;
+#[doc(hidden)]
+#[cfg(feature = "download")]
+pub mod update;
+#[doc(hidden)]
+#[cfg(feature = "webhook")]
+pub mod webhook;
+#[doc(hidden)]
+pub mod whoami;
+#[doc(hidden)]
+pub mod wintrust;
+#[doc(hidden)]
+pub mod wrapper;
+#[doc(hidden)]
+pub mod xml;
+
+pub use error::Error as SignError;
+pub use events::{Event, Events, Sink};
+pub use route::{Route, Target};
+pub use signer::{Backend, Mechanism, SignOptions, SignOutcome, Signer};
+pub use signtool::Digest;
@@ -0,0 +1,375 @@
+use crate::{encoding, interrupt, progress, redact};
+use clap::ValueEnum;
+use duct::Expression;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{Arc, Mutex},
+};
+use tracing::{debug, Level};
+use tracing_subscriber::{
+    fmt::{
+        self,
+        format::FmtSpan,
+        time::{uptime, SystemTime},
+    },
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+/// Target of what only goes to --log-file, like the resolved configuration
+pub const DIAGNOSTICS: &str = "diagnostics";
+
+/// Log level accepted by `--log-level`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+}
+
+/// Values that must never show up in anything we print
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Secrets shorter than this are only masked as a whole word, so `y` doesn't turn `specify` into `specif***`
+const SHORT_SECRET: usize = 8;
+
+/// Register a value to be masked in all logged child output, once however often it is registered
+pub fn register_secret(secret: &str) {
+    let mut secrets = SECRETS.lock().unwrap();
+    if !secret.is_empty() && !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// All registered secrets
+pub fn secrets() -> Vec<String> {
+    SECRETS.lock().unwrap().clone()
+}
+
+/// Replace every registered secret in `text` with `***` and redact known identifiers. Secrets are also
+/// masked escaped the way `{:?}` writes them, as in the command lines of child processes
+pub fn mask(text: &str) -> String {
+    let masked = SECRETS.lock().unwrap().iter().fold(text.to_string(), |text, secret| {
+        let escaped = secret.escape_debug().to_string();
+        let whole = secret.chars().count() < SHORT_SECRET;
+        replace(&replace(&text, secret, whole), &escaped, whole)
+    });
+    redact::apply(&masked)
+}
+
+/// `text` with `secret` replaced by `***`, only where no letter, digit or `_` is next to it when `whole`
+fn replace(text: &str, secret: &str, whole: bool) -> String {
+    if !whole {
+        return text.replace(secret, "***");
+    }
+    let word = |char: Option<char>| char.is_some_and(|char| char.is_alphanumeric() || char == '_');
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = 0;
+    for (start, _) in text.match_indices(secret) {
+        let end = start + secret.len();
+        // overlapping a match masked already
+        if start < rest || word(text[..start].chars().next_back()) || word(text[end..].chars().next()) {
+            continue;
+        }
+        masked.push_str(&text[rest..start]);
+        masked.push_str("***");
+        rest = end;
+    }
+    masked.push_str(&text[rest..]);
+    masked
+}
+
+/// Build the filter: `--log-level` wins over `RUST_LOG`, which wins over the default (info)
+fn filter(level: Option<LogLevel>) -> Result<EnvFilter, String> {
+    if let Some(level) = level {
+        return Ok(EnvFilter::new(format!("{}={}", env!("CARGO_CRATE_NAME"), Level::from(level))));
+    }
+
+    match std::env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => EnvFilter::try_new(&directives)
+            .map_err(|err| format!("RUST_LOG '{}' is not a valid filter: {}", directives, err)),
+        _ => Ok(EnvFilter::new(format!("{}=info", env!("CARGO_CRATE_NAME")))),
+    }
+}
+
+/// Masks what is written to `W` a line at a time, so a secret split across writes is masked all the same. What
+/// is left of a line is written when it is dropped
+struct Masking<W: Write> {
+    out: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Masking<W> {
+    fn new(out: W) -> Self {
+        Masking { out, pending: Vec::new() }
+    }
+}
+
+impl<W: Write> Write for Masking<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(end) = self.pending.iter().rposition(|byte| *byte == b'\n') {
+            let lines: Vec<u8> = self.pending.drain(..=end).collect();
+            self.out.write_all(mask(&String::from_utf8_lossy(&lines)).as_bytes())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Drop for Masking<W> {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            let rest = std::mem::take(&mut self.pending);
+            let _ = self.out.write_all(mask(&String::from_utf8_lossy(&rest)).as_bytes());
+        }
+    }
+}
+
+/// Writes to stderr without tearing through the progress bar
+struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        progress::suspend(|| io::stderr().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Where --log-file writes diagnostics and when it starts the next file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+    /// Names the files, `logs/tsc.log` gives `logs/tsc-20260302T101500-4242.log`
+    pub path: PathBuf,
+    /// Size after which the run goes on in a new file
+    pub max_size: u64,
+    /// How many files named after `path` are kept, the oldest are removed
+    pub keep: usize,
+}
+
+impl LogFile {
+    /// The `part`th file of the run started at `started`, with the process id so concurrent runs never share one
+    fn name(&self, started: &str, part: u32) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.path.extension().map_or("log".into(), |extension| extension.to_string_lossy());
+        let name = match part {
+            1 => format!("{}-{}-{}.{}", stem, started, std::process::id(), extension),
+            part => format!("{}-{}-{}.{}.{}", stem, started, std::process::id(), part, extension),
+        };
+        self.path.with_file_name(name)
+    }
+
+    /// Remove the oldest files of earlier runs and parts, so `keep` are left with the one about to be written
+    fn prune(&self) {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy().to_string() + "-";
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&stem))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+        files.sort();
+        let remove = files.len().saturating_sub(self.keep.saturating_sub(1));
+        for (_, path) in files.into_iter().take(remove) {
+            // another run may still be writing it, on Windows that keeps it
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// The file of --log-file being written, started over in a new one past the size limit
+struct Rotating {
+    log_file: LogFile,
+    started: String,
+    part: u32,
+    file: fs::File,
+    written: u64,
+}
+
+impl Rotating {
+    fn open(log_file: LogFile) -> Result<(Self, PathBuf), String> {
+        if let Some(dir) = log_file.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|err| format!("'{}' could not be created: {}", dir.display(), err))?;
+        }
+        let started = chrono::Utc::now().format("%Y%m%dT%H%M%S").to_string();
+        let path = log_file.name(&started, 1);
+        log_file.prune();
+        let file = fs::File::create_new(&path)
+            .map_err(|err| format!("log file '{}' could not be created: {}", path.display(), err))?;
+        Ok((Rotating { log_file, started, part: 1, file, written: 0 }, path))
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        if self.written > 0 && self.written + text.len() as u64 > self.log_file.max_size {
+            self.part += 1;
+            self.log_file.prune();
+            self.file = fs::File::create_new(self.log_file.name(&self.started, self.part))?;
+            self.written = 0;
+        }
+        self.file.write_all(text.as_bytes())?;
+        self.written += text.len() as u64;
+        Ok(())
+    }
+}
+
+/// Writes diagnostics to the log file, each event at once
+#[derive(Clone)]
+struct Diagnostics(Arc<Mutex<Rotating>>);
+
+impl Write for Diagnostics {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(&String::from_utf8_lossy(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().file.flush()
+    }
+}
+
+/// Install the global tracing subscriber, logging to stderr
+pub fn init(level: Option<LogLevel>, ansi: bool) -> Result<(), String> {
+    init_with_file(level, ansi, None).map(|_| ())
+}
+
+/// Install the global tracing subscriber, logging to stderr and, at debug level whatever stderr shows, to
+/// `log_file`. Returns the file written
+pub fn init_with_file(
+    level: Option<LogLevel>,
+    ansi: bool,
+    log_file: Option<LogFile>,
+) -> Result<Option<PathBuf>, String> {
+    let console = fmt::layer()
+        .with_writer(|| Masking::new(Stderr))
+        .with_ansi(ansi)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false)
+        .with_timer(uptime())
+        .with_filter(filter(level)?);
+    let (diagnostics, path) = match log_file {
+        Some(log_file) => {
+            let (rotating, path) = Rotating::open(log_file)?;
+            let writer = Diagnostics(Arc::new(Mutex::new(rotating)));
+            let level = match level {
+                Some(LogLevel::Trace) => Level::TRACE,
+                _ => Level::DEBUG,
+            };
+            let layer = fmt::layer()
+                .with_writer(move || Masking::new(writer.clone()))
+                .with_ansi(false)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_target(false)
+                .with_timer(SystemTime)
+                .with_filter(EnvFilter::new(format!("{}={},{}=debug", env!("CARGO_CRATE_NAME"), level, DIAGNOSTICS)));
+            (Some(layer), Some(path))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(console)
+        .with(diagnostics)
+        .try_init()
+        .map_err(|err| format!("logger could not be initialized: {}", err))?;
+    Ok(path)
+}
+
+/// Run a child process with its output captured, re-emitting every line at debug level.
+/// The exit status is not checked here, callers decide what a failure means.
+pub fn run_captured(expression: Expression) -> Result<Output, std::io::Error> {
+    debug!("running {:?}", expression);
+    let output = interrupt::output(expression.stdout_capture().stderr_capture().unchecked())?;
+    debug!("it exited with {}", output.status);
+
+    redact::learn_json(&encoding::decode(&output.stdout));
+    for line in mask(&encoding::decode(&output.stdout)).lines() {
+        debug!("{}", line);
+    }
+    for line in mask(&encoding::decode(&output.stderr)).lines() {
+        debug!("{}", line);
+    }
+
+    Ok(output)
+}
+
+/// Captured child output to attach to an error, stderr first since that's where tools complain
+pub fn failure_output(output: &Output) -> String {
+    let stderr = encoding::decode(&output.stderr);
+    let stdout = encoding::decode(&output.stdout);
+    mask(format!("{}{}", stderr, stdout).trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_whole_lines() {
+        register_secret("Zq9-split-secret-Zq9");
+        register_secret("q7z");
+        let mut out = Vec::new();
+        {
+            let mut masking = Masking::new(&mut out);
+            masking.write_all(b"token Zq9-split-").unwrap();
+            masking.write_all(b"secret-Zq9 sent\nsecret q7z, not xq7z or q7z_1").unwrap();
+            assert_eq!(String::from_utf8_lossy(masking.out), "token *** sent\n");
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "token *** sent\nsecret ***, not xq7z or q7z_1");
+        assert_eq!(replace("yy y_y y-y", "y", true), "yy y_y ***-***");
+        assert_eq!(replace("ayb", "y", false), "a***b");
+    }
+
+    #[test]
+    fn rotates_and_prunes() {
+        let dir = tempfile::tempdir().unwrap();
+        for old in ["tsc-20260301T101500-1.log", "tsc-20260301T111500-2.log", "other.log"] {
+            fs::write(dir.path().join(old), "earlier run\n").unwrap();
+        }
+        let log_file = LogFile { path: dir.path().join("tsc.log"), max_size: 16, keep: 3 };
+        let (mut rotating, path) = Rotating::open(log_file).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with("tsc-") && name.ends_with(&format!("-{}.log", std::process::id())), "{}", name);
+        rotating.write("0123456789\n").unwrap();
+        rotating.write("next part\n").unwrap();
+
+        let entries = fs::read_dir(dir.path()).unwrap();
+        let mut names: Vec<String> = entries.map(|entry| entry.unwrap().file_name().to_string_lossy().into()).collect();
+        names.sort();
+        // the oldest run is gone, other.log isn't one of them
+        assert_eq!(names.len(), 4, "{:?}", names);
+        assert!(!names.contains(&"tsc-20260301T101500-1.log".to_string()));
+        assert!(names.contains(&"other.log".to_string()));
+        let second = path.with_file_name(name.replace(".log", ".2.log"));
+        assert_eq!(fs::read_to_string(second).unwrap(), "next part\n");
+    }
+}
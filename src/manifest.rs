@@ -0,0 +1,288 @@
+use crate::{error::Error, hash, input};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, info, warn};
+
+/// The release tool that wrote an artifact manifest
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// cargo-dist's dist-manifest.json, its recorded checksums are updated after signing
+    Dist,
+    /// cargo-packager's output, the paths it printed one per line
+    Packager,
+}
+
+/// The hashes a manifest or checksum file can record, of a file after signing
+struct Hashes {
+    sha256: String,
+    sha512: String,
+}
+
+impl Hashes {
+    fn of(path: &Path) -> Result<Self, String> {
+        let read = |err| format!("'{}' could not be hashed: {}", path.display(), err);
+        Ok(Self {
+            sha256: hash::sha256_file(path).map_err(read)?,
+            sha512: hash::sha512_file(path).map_err(read)?,
+        })
+    }
+
+    /// The hash of an algorithm as cargo-dist names it
+    fn get(&self, algorithm: &str) -> Option<&str> {
+        match algorithm {
+            "sha256" => Some(&self.sha256),
+            "sha512" => Some(&self.sha512),
+            _ => None,
+        }
+    }
+
+    /// The hash of the same algorithm as `hex`, told apart by length
+    fn like(&self, hex: &str) -> Option<&str> {
+        match hex.len() {
+            64 => Some(&self.sha256),
+            128 => Some(&self.sha512),
+            _ => None,
+        }
+    }
+}
+
+/// Read a manifest, the relative paths in it are relative to the current directory like the tools write them
+fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path)
+        .map_err(|err| Error::Usage(format!("manifest '{}' could not be read: {}", path.display(), err)))
+}
+
+fn parse_dist(content: &str) -> Result<Value, Error> {
+    let manifest: Value = serde_json::from_str(content)
+        .map_err(|err| Error::Usage(format!("the dist manifest is not valid JSON: {}", err)))?;
+    if !manifest["artifacts"].is_object() {
+        Err(Error::Usage("the dist manifest has no artifacts, is it a dist-manifest.json?".to_string()))?;
+    }
+    Ok(manifest)
+}
+
+/// The signable Windows artifacts of a dist manifest, with where they are
+fn dist_artifacts<'a>(manifest: &'a Value, base: &Path, extensions: &[String]) -> Vec<(&'a str, PathBuf)> {
+    let windows = |triple: &Value| triple.as_str().is_some_and(|triple| triple.contains("windows"));
+    let artifacts = manifest["artifacts"].as_object().into_iter().flatten();
+    artifacts
+        .filter(|(_, artifact)| {
+            artifact["target_triples"].as_array().is_some_and(|triples| triples.iter().any(windows))
+        })
+        .filter_map(|(name, artifact)| Some((name.as_str(), artifact["path"].as_str()?)))
+        .filter(|(_, path)| input::is_supported(path, extensions))
+        .map(|(name, path)| (name, base.join(path)))
+        .collect()
+}
+
+/// The paths cargo-packager printed, lines that aren't an existing file are its other output
+fn packager_files(content: &str, base: &Path, extensions: &[String]) -> Vec<PathBuf> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && input::is_supported(line, extensions))
+        .map(|line| base.join(line))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// The files of a manifest to sign, in the order the manifest lists them
+pub fn files(path: &Path, format: Format, extensions: &[String]) -> Result<Vec<String>, Error> {
+    let content = read(path)?;
+    let files = match format {
+        Format::Dist => {
+            let manifest = parse_dist(&content)?;
+            let mut files = Vec::new();
+            for (name, path) in dist_artifacts(&manifest, Path::new(""), extensions) {
+                match path.is_file() {
+                    true => files.push(path),
+                    // a manifest of the whole release lists the artifacts other runners build too
+                    false => warn!("{} is not in '{}', skipping it", name, path.display()),
+                }
+            }
+            files
+        }
+        Format::Packager => packager_files(&content, Path::new(""), extensions),
+    };
+    if files.is_empty() {
+        Err(Error::Usage(format!("no Windows files to sign in manifest '{}'", path.display())))?;
+    }
+    debug!("{} files to sign from {}", files.len(), path.display());
+    Ok(files.into_iter().map(|path| path.display().to_string()).collect())
+}
+
+/// Replace the hashes of the file `name` in the lines of a checksum file, a file of just a hash is taken as its own
+fn update_checksums(content: &str, name: &str, hashes: &Hashes) -> String {
+    let single = content.lines().filter(|line| !line.trim().is_empty()).count() == 1;
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let mut tokens = line.split_whitespace();
+            let (Some(hex), named) = (tokens.next(), tokens.next().map(|file| file.trim_start_matches('*'))) else {
+                return line.to_string();
+            };
+            match (hashes.like(hex), named) {
+                (Some(new), Some(file)) if file == name => line.replacen(hex, new, 1),
+                (Some(new), None) if single => line.replacen(hex, new, 1),
+                _ => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Rewrite a checksum file with the hashes of `name`, returning whether anything changed
+fn rewrite_checksum_file(path: &Path, name: &str, hashes: &Hashes) -> Result<bool, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("checksum file '{}' could not be read: {}", path.display(), err))?;
+    let updated = update_checksums(&content, name, hashes);
+    if updated == content {
+        return Ok(false);
+    }
+    fs::write(path, updated)
+        .map_err(|err| format!("checksum file '{}' could not be written: {}", path.display(), err))?;
+    Ok(true)
+}
+
+/// Update the checksums recorded for the `signed` artifacts, inline and in checksum files, returning whether the
+/// manifest itself changed
+fn update_dist(
+    manifest: &mut Value,
+    base: &Path,
+    signed: &[PathBuf],
+    extensions: &[String],
+) -> Result<bool, String> {
+    let signed: Vec<PathBuf> = signed.iter().filter_map(|path| path.canonicalize().ok()).collect();
+    let updates: Vec<(String, String, Hashes)> = dist_artifacts(manifest, base, extensions)
+        .into_iter()
+        .filter(|(_, path)| path.canonicalize().is_ok_and(|path| signed.contains(&path)))
+        .map(|(name, path)| {
+            let file = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            Ok((name.to_string(), file, Hashes::of(&path)?))
+        })
+        .collect::<Result<_, String>>()?;
+    let unified: Vec<PathBuf> = manifest["artifacts"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, artifact)| artifact["kind"] == "unified-checksum")
+        .filter_map(|(_, artifact)| artifact["path"].as_str().map(|path| base.join(path)))
+        .collect();
+
+    let mut changed = false;
+    for (name, file, hashes) in &updates {
+        let checksum = manifest["artifacts"][name]["checksum"].as_str().map(str::to_string);
+        if let Some(checksum) = checksum {
+            match manifest["artifacts"][&checksum]["path"].as_str() {
+                Some(path) if !base.join(path).is_file() => warn!("checksum file {} of {} is missing", path, name),
+                Some(path) => {
+                    if rewrite_checksum_file(&base.join(path), file, hashes)? {
+                        info!("updated the checksum of {} in {}", file, path);
+                    }
+                }
+                None => warn!("checksum artifact {} of {} is not in the manifest", checksum, name),
+            }
+        }
+        for path in &unified {
+            if path.is_file() && rewrite_checksum_file(path, file, hashes)? {
+                info!("updated the checksum of {} in {}", file, path.display());
+            }
+        }
+        if let Some(checksums) = manifest["artifacts"][name]["checksums"].as_object_mut() {
+            for (algorithm, value) in checksums {
+                match hashes.get(algorithm) {
+                    Some(new) => {
+                        changed |= value.as_str() != Some(new);
+                        *value = Value::String(new.to_string());
+                    }
+                    None => {
+                        warn!("the {} checksum of {} can't be updated, only sha256 and sha512 can", algorithm, name)
+                    }
+                }
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Update the hashes a manifest records for the `signed` files, so an upload step checking them sees the signed
+/// files. cargo-packager doesn't record any
+pub fn update(path: &Path, format: Format, signed: &[String], extensions: &[String]) -> Result<(), Error> {
+    if format == Format::Packager || signed.is_empty() {
+        return Ok(());
+    }
+    let mut manifest = parse_dist(&read(path)?)?;
+    let signed: Vec<PathBuf> = signed.iter().map(PathBuf::from).collect();
+    if update_dist(&mut manifest, Path::new(""), &signed, extensions)? {
+        let json = serde_json::to_string_pretty(&manifest).map_err(|err| err.to_string())?;
+        fs::write(path, json + "\n")
+            .map_err(|err| format!("manifest '{}' could not be written: {}", path.display(), err))?;
+        info!("updated the checksums in {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIST: &str = include_str!("../tests/fixtures/dist-manifest.json");
+    const PACKAGER: &str = include_str!("../tests/fixtures/packager-output.txt");
+
+    #[test]
+    fn dist_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = parse_dist(DIST).unwrap();
+        let extensions = input::extensions(None, &[]);
+        let artifacts = dist_artifacts(&manifest, dir.path(), &extensions);
+        let names: Vec<&str> = artifacts.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["myapp-installer.ps1", "myapp-x86_64-pc-windows-msvc.msi"]);
+
+        let distrib = dir.path().join("target/distrib");
+        fs::create_dir_all(&distrib).unwrap();
+        let msi = distrib.join("myapp-x86_64-pc-windows-msvc.msi");
+        fs::write(&msi, "signed msi").unwrap();
+        let stale = "1f0bd6f2bb2cd58f2e2a0d1bcbf9c1c16fb0b1f6c1d0c4a2b4e0b4f1f2a3b4c5";
+        let checksum = distrib.join("myapp-x86_64-pc-windows-msvc.msi.sha256");
+        fs::write(&checksum, format!("{}  *myapp-x86_64-pc-windows-msvc.msi\n", stale)).unwrap();
+        let other = format!("{}  myapp-x86_64-unknown-linux-gnu.tar.xz\n", "0".repeat(64));
+        let unified = distrib.join("sha256.sum");
+        fs::write(&unified, format!("{}{}  myapp-x86_64-pc-windows-msvc.msi\n", other, stale)).unwrap();
+
+        assert!(update_dist(&mut manifest, dir.path(), std::slice::from_ref(&msi), &extensions).unwrap());
+        let sha256 = hash::sha256_file(&msi).unwrap();
+        let artifact = &manifest["artifacts"]["myapp-x86_64-pc-windows-msvc.msi"];
+        assert_eq!(artifact["checksums"]["sha256"], sha256.as_str());
+        assert_eq!(
+            fs::read_to_string(&checksum).unwrap(),
+            format!("{}  *myapp-x86_64-pc-windows-msvc.msi\n", sha256)
+        );
+        assert_eq!(
+            fs::read_to_string(&unified).unwrap(),
+            format!("{}{}  myapp-x86_64-pc-windows-msvc.msi\n", other, sha256)
+        );
+        // signed again with nothing new, the manifest is left alone
+        assert!(!update_dist(&mut manifest, dir.path(), &[msi], &extensions).unwrap());
+    }
+
+    #[test]
+    fn packager_output() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(packager_files(PACKAGER, dir.path(), &input::extensions(None, &[])).is_empty());
+        fs::create_dir_all(dir.path().join("dist")).unwrap();
+        for name in ["myapp_1.2.0_x64-setup.exe", "myapp_1.2.0_x64_en-US.msi"] {
+            fs::write(dir.path().join("dist").join(name), "").unwrap();
+        }
+        let files = packager_files(PACKAGER, dir.path(), &input::extensions(None, &[]));
+        assert_eq!(
+            files,
+            [
+                dir.path().join("./dist/myapp_1.2.0_x64-setup.exe"),
+                dir.path().join("./dist/myapp_1.2.0_x64_en-US.msi"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::hash;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Extension of a marker file, `app.exe` gets `app.exe.signed`
+const EXTENSION: &str = "signed";
+
+/// What a marker file records about the file it was written for, once signed
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Marker {
+    /// SHA-256 of the file as signed
+    pub sha256: String,
+    /// RFC 3339 time it was signed
+    pub signed_at: String,
+}
+
+/// The marker of `file`, next to it or in `dir`
+pub fn path(file: &str, dir: Option<&Path>) -> PathBuf {
+    let file = Path::new(file);
+    let name = format!("{}.{}", file.file_name().unwrap_or_default().to_string_lossy(), EXTENSION);
+    match dir {
+        Some(dir) => dir.join(name),
+        None => file.with_file_name(name),
+    }
+}
+
+/// Whether the marker at `marker` records the file as it is now, a missing, unreadable or stale marker doesn't
+pub fn is_current(marker: &Path, file: &str) -> bool {
+    let Ok(content) = fs::read_to_string(marker) else {
+        return false;
+    };
+    let recorded: Marker = match serde_json::from_str(&content) {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            debug!("ignoring the marker {}: {}", marker.display(), err);
+            return false;
+        }
+    };
+    match hash::sha256_file(file) {
+        Ok(sha256) if sha256 == recorded.sha256 => true,
+        Ok(_) => {
+            debug!("ignoring the marker {}, {} changed since it was signed", marker.display(), file);
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Write the marker of a signed file, replacing a stale one
+pub fn write(marker: &Path, recorded: &Marker) -> Result<(), String> {
+    if let Some(dir) = marker.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| format!("'{}' could not be created: {}", dir.display(), err))?;
+    }
+    let content = serde_json::to_string_pretty(recorded).map_err(|err| err.to_string())?;
+    fs::write(marker, content + "\n")
+        .map_err(|err| format!("the marker '{}' could not be written: {}", marker.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_and_stale_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.exe").display().to_string();
+        fs::write(&file, b"signed").unwrap();
+        let marker = path(&file, None);
+        assert_eq!(marker, dir.path().join("app.exe.signed"));
+        assert_eq!(path("dist/app.exe", Some(Path::new("markers"))), Path::new("markers").join("app.exe.signed"));
+        assert!(!is_current(&marker, &file));
+
+        let recorded = Marker { sha256: hash::sha256_file(&file).unwrap(), signed_at: "2026-10-14T10:00:00Z".into() };
+        write(&marker, &recorded).unwrap();
+        assert!(is_current(&marker, &file));
+        fs::write(&file, b"rebuilt").unwrap();
+        assert!(!is_current(&marker, &file));
+        fs::write(&marker, b"not json").unwrap();
+        assert!(!is_current(&marker, &file));
+    }
+}
@@ -0,0 +1,166 @@
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::{fs, path::Path, time::Duration};
+
+/// Durations of every phase of a run, phases can be recorded any number of times
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    phases: Vec<(&'static str, Vec<Duration>)>,
+}
+
+/// Aggregated durations of a phase
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
+pub struct PhaseStats {
+    pub phase: &'static str,
+    pub count: usize,
+    pub total_ms: u64,
+    pub min_ms: u64,
+    pub median_ms: u64,
+    pub max_ms: u64,
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+impl Metrics {
+    /// Record one sample of `phase`
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, samples)) => samples.push(duration),
+            None => self.phases.push((phase, vec![duration])),
+        }
+    }
+
+    /// Record a file signed in `duration`, `verify` of it spent checking the signed file. The checks are their own
+    /// phase, so a slow check doesn't look like slow signing
+    pub fn record_file(&mut self, duration: Duration, verify: Option<Duration>) {
+        self.record("sign", duration.saturating_sub(verify.unwrap_or_default()));
+        if let Some(verify) = verify {
+            self.record("verify", verify);
+        }
+    }
+
+    /// Aggregates in the order the phases first ran
+    pub fn stats(&self) -> Vec<PhaseStats> {
+        self.phases
+            .iter()
+            .map(|(phase, samples)| {
+                let mut sorted = samples.clone();
+                sorted.sort();
+                let middle = sorted.len() / 2;
+                let median = if sorted.len() % 2 == 0 {
+                    (sorted[middle - 1] + sorted[middle]) / 2
+                } else {
+                    sorted[middle]
+                };
+                PhaseStats {
+                    phase,
+                    count: sorted.len(),
+                    total_ms: millis(sorted.iter().sum()),
+                    min_ms: millis(sorted[0]),
+                    median_ms: millis(median),
+                    max_ms: millis(sorted[sorted.len() - 1]),
+                }
+            })
+            .collect()
+    }
+
+    /// One line per phase for the summary, with min/median/max for repeated phases
+    pub fn summary_lines(&self) -> Vec<String> {
+        self.stats()
+            .iter()
+            .map(|stats| {
+                let seconds = |ms: u64| format!("{:.1}s", ms as f64 / 1000.0);
+                if stats.count == 1 {
+                    format!("{:<15}{:>6}", stats.phase, seconds(stats.total_ms))
+                } else {
+                    format!(
+                        "{:<15}{:>6}  min {} / median {} / max {}",
+                        stats.phase,
+                        seconds(stats.total_ms),
+                        seconds(stats.min_ms),
+                        seconds(stats.median_ms),
+                        seconds(stats.max_ms)
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Write every raw sample in milliseconds, for charting across builds
+    pub fn write_raw(&self, path: &Path) -> Result<(), String> {
+        let raw: serde_json::Map<String, serde_json::Value> = self
+            .phases
+            .iter()
+            .map(|(phase, samples)| (phase.to_string(), samples.iter().map(|sample| millis(*sample)).collect()))
+            .collect();
+        let content = serde_json::to_string_pretty(&raw).map_err(|err| format!("metrics could not be serialized: {:?}", err))?;
+        fs::write(path, content).map_err(|err| format!("metrics file '{}' could not be written: {:?}", path.display(), err))
+    }
+}
+
+impl Serialize for Metrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.stats().serialize(serializer)
+    }
+}
+
+impl JsonSchema for Metrics {
+    fn schema_name() -> Cow<'static, str> {
+        "Metrics".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        Vec::<PhaseStats>::json_schema(generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let mut metrics = Metrics::default();
+        metrics.record("login", Duration::from_millis(900));
+        for ms in [300, 100, 200, 400] {
+            metrics.record("sign", Duration::from_millis(ms));
+        }
+
+        assert_eq!(
+            metrics.stats(),
+            vec![
+                PhaseStats {
+                    phase: "login",
+                    count: 1,
+                    total_ms: 900,
+                    min_ms: 900,
+                    median_ms: 900,
+                    max_ms: 900
+                },
+                PhaseStats {
+                    phase: "sign",
+                    count: 4,
+                    total_ms: 1000,
+                    min_ms: 100,
+                    median_ms: 250,
+                    max_ms: 400
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_apart_from_sign() {
+        let mut metrics = Metrics::default();
+        metrics.record_file(Duration::from_millis(500), Some(Duration::from_millis(120)));
+        // signing failed, nothing was checked
+        metrics.record_file(Duration::from_millis(80), None);
+
+        let stats = metrics.stats();
+        let phases: Vec<_> = stats.iter().map(|stats| (stats.phase, stats.count, stats.total_ms)).collect();
+        assert_eq!(phases, [("sign", 2, 460), ("verify", 1, 120)]);
+    }
+}
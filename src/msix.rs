@@ -0,0 +1,237 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    signtool, xml,
+};
+use duct::cmd;
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where a bundle keeps the manifest listing its packages
+const BUNDLE_MANIFEST: &str = "AppxMetadata/AppxBundleManifest.xml";
+
+/// Where a package keeps its manifest
+const PACKAGE_MANIFEST: &str = "AppxManifest.xml";
+
+/// Where the Windows SDKs put makeappx.exe
+const MAKEAPPX_PATTERN: &str = r"C:\Program Files (x86)\Windows Kits\10\bin\*\x64\makeappx.exe";
+
+/// A package inside a bundle
+#[derive(Debug, PartialEq, Eq)]
+pub struct Package {
+    pub file_name: String,
+    /// `application` or `resource`
+    pub kind: String,
+    pub architecture: Option<String>,
+}
+
+/// The identity and packages of a bundle, as its bundle manifest lists them
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bundle {
+    pub name: String,
+    pub publisher: String,
+    pub version: String,
+    pub packages: Vec<Package>,
+}
+
+impl Bundle {
+    /// Read the bundle manifest of the bundle at `path`
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let invalid = |err: String| format!("'{}' is not a valid bundle: {}", path.display(), err);
+        let file = File::open(path).map_err(|err| invalid(err.to_string()))?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|err| invalid(err.to_string()))?;
+        let mut content = String::new();
+        archive
+            .by_name(BUNDLE_MANIFEST)
+            .map_err(|err| invalid(format!("{}: {}", BUNDLE_MANIFEST, err)))?
+            .read_to_string(&mut content)
+            .map_err(|err| invalid(format!("{}: {}", BUNDLE_MANIFEST, err)))?;
+        Self::parse(&content).map_err(invalid)
+    }
+
+    fn parse(manifest: &str) -> Result<Self, String> {
+        let identity = xml::start_tags(manifest, "Identity").next().ok_or("the bundle manifest has no Identity")?;
+        let get = |name: &str| {
+            xml::attribute(identity, name).ok_or_else(|| format!("the bundle manifest Identity has no {}", name))
+        };
+        let packages: Vec<Package> = xml::start_tags(manifest, "Package")
+            .filter_map(|tag| {
+                Some(Package {
+                    file_name: xml::attribute(tag, "FileName")?,
+                    kind: xml::attribute(tag, "Type").unwrap_or_else(|| "application".to_string()),
+                    architecture: xml::attribute(tag, "Architecture"),
+                })
+            })
+            .collect();
+        if packages.is_empty() {
+            Err("the bundle manifest lists no packages")?;
+        }
+        Ok(Self { name: get("Name")?, publisher: get("Publisher")?, version: get("Version")?, packages })
+    }
+}
+
+/// Whether the file is an MSIX or APPX bundle, judged by its extension
+pub fn is_bundle(path: &str) -> bool {
+    let extension = Path::new(path).extension().unwrap_or_default();
+    extension.eq_ignore_ascii_case("msixbundle") || extension.eq_ignore_ascii_case("appxbundle")
+}
+
+/// Whether the file is an MSIX or APPX package or bundle, judged by its extension
+pub fn is_package(path: &str) -> bool {
+    let extension = Path::new(path).extension().unwrap_or_default();
+    is_bundle(path) || extension.eq_ignore_ascii_case("msix") || extension.eq_ignore_ascii_case("appx")
+}
+
+/// The Publisher of the Identity in the manifest of a package or bundle
+fn manifest_publisher(manifest: &str) -> Option<String> {
+    xml::attribute(xml::start_tags(manifest, "Identity").next()?, "Publisher")
+}
+
+/// The Publisher of the package or bundle at `path`, from its package or bundle manifest
+pub fn publisher(path: &Path) -> Result<String, String> {
+    let manifest = match is_bundle(&path.to_string_lossy()) {
+        true => BUNDLE_MANIFEST,
+        false => PACKAGE_MANIFEST,
+    };
+    let invalid = |err: String| format!("'{}' has no readable {}: {}", path.display(), manifest, err);
+    let file = File::open(path).map_err(|err| invalid(err.to_string()))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|err| invalid(err.to_string()))?;
+    let mut content = String::new();
+    archive
+        .by_name(manifest)
+        .map_err(|err| invalid(err.to_string()))?
+        .read_to_string(&mut content)
+        .map_err(|err| invalid(err.to_string()))?;
+    manifest_publisher(&content).ok_or_else(|| invalid("its Identity has no Publisher".to_string()))
+}
+
+/// The relative distinguished names of a subject like `CN=Fabrikam, O="Fabrikam, Inc", C=US`, normalized
+fn names(subject: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let (mut name, mut quoted) = (String::new(), false);
+    for c in subject.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' | ';' if !quoted => names.push(std::mem::take(&mut name)),
+            _ => name.push(c),
+        }
+    }
+    names.push(name);
+    let mut names: Vec<String> = names
+        .iter()
+        .filter_map(|name| name.split_once('='))
+        .map(|(key, value)| format!("{}={}", key.trim().to_ascii_uppercase(), value.trim()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Whether a package Publisher is the subject of the certificate, Windows refuses to install it otherwise
+pub fn same_subject(publisher: &str, subject: &str) -> bool {
+    names(publisher) == names(subject)
+}
+
+/// The makeappx to run, `path` when given, then the one next to signtool, the newest one of the Windows SDKs and
+/// the PATH
+pub fn find_makeappx(path: Option<&str>, sign_tool_path: &str) -> Result<PathBuf, Error> {
+    if let Some(path) = path {
+        return match Path::new(path).is_file() {
+            true => Ok(PathBuf::from(path)),
+            false => Err(Error::MissingDependency {
+                component: "makeappx",
+                message: format!("makeappx '{}' was not found", path),
+            }),
+        };
+    }
+    let executable = if cfg!(windows) { "makeappx.exe" } else { "makeappx" };
+    let beside_signtool = Path::new(sign_tool_path).with_file_name(executable);
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join(executable)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let Some(makeappx) = [beside_signtool]
+        .into_iter()
+        .chain(signtool::newest(MAKEAPPX_PATTERN))
+        .chain(on_path)
+        .find(|candidate| candidate.is_file())
+    else {
+        Err(Error::MissingDependency {
+            component: "makeappx",
+            message: "makeappx.exe was not found, deep signing bundles needs it from the Windows SDK like signtool: \
+                      install the SDK or pass --makeappx-path"
+                .to_string(),
+        })?
+    };
+    debug!("unpacking bundles with {}", makeappx.display());
+    Ok(makeappx)
+}
+
+/// Run makeappx with `args`, failing with the bundle as the file
+fn makeappx(makeappx: &Path, args: Vec<OsString>, file: &str, message: String) -> Result<(), Error> {
+    let output = run_captured(cmd(makeappx, args)).map_err(|err| Error::MissingDependency {
+        component: "makeappx",
+        message: format!("makeappx '{}' could not be run: {}", makeappx.display(), err),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+/// Unpack the packages of the bundle `file` into `dir`
+pub fn unbundle(makeappx_path: &Path, file: &str, dir: &Path) -> Result<(), Error> {
+    let args = ["unbundle", "/p", file, "/d"].map(OsString::from).into_iter().chain([dir.into(), "/o".into()]);
+    makeappx(makeappx_path, args.collect(), file, format!("makeappx could not unpack '{}'", file))
+}
+
+/// Bundle the packages in `dir` into `output` with the version the bundle `file` had
+pub fn bundle(makeappx_path: &Path, dir: &Path, version: &str, output: &Path, file: &str) -> Result<(), Error> {
+    let args: Vec<OsString> =
+        vec!["bundle".into(), "/d".into(), dir.into(), "/p".into(), output.into(), "/bv".into(), version.into()];
+    let args = args.into_iter().chain(["/o".into()]).collect();
+    makeappx(makeappx_path, args, file, format!("makeappx could not bundle the signed packages of '{}'", file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Bundle xmlns="http://schemas.microsoft.com/appx/2013/bundle" SchemaVersion="5.0">
+  <Identity Name="Fabrikam.App" Publisher="CN=Fabrikam, O=&quot;Fabrikam, Inc&quot;, C=US" Version="1.4.0.0" />
+  <Packages>
+    <Package Type="application" Version="1.4.0.0" Architecture="x64" FileName="App_1.4.0.0_x64.msix" Size="1" />
+    <Package Type="application" Version="1.4.0.0" Architecture="arm64" FileName="App_1.4.0.0_arm64.msix" />
+    <Package Type="resource" Version="1.4.0.0" ResourceId="split.scale-200" FileName="App_scale-200.msix" />
+  </Packages>
+</Bundle>"#;
+
+    #[test]
+    fn bundle_manifest() {
+        assert!(is_bundle(r"AppPackages\App_1.4.0.0_x64_arm64.MSIXBUNDLE"));
+        assert!(!is_bundle("App_1.4.0.0_x64.msix"));
+
+        let bundle = Bundle::parse(MANIFEST).unwrap();
+        assert_eq!(bundle.version, "1.4.0.0");
+        assert_eq!(bundle.publisher, r#"CN=Fabrikam, O="Fabrikam, Inc", C=US"#);
+        let architectures: Vec<_> = bundle.packages.iter().map(|package| package.architecture.as_deref()).collect();
+        assert_eq!(architectures, [Some("x64"), Some("arm64"), None]);
+        assert_eq!(bundle.packages[2].kind, "resource");
+
+        assert!(same_subject(&bundle.publisher, r#"C=US, cn=Fabrikam, O="Fabrikam, Inc""#));
+        assert!(!same_subject(&bundle.publisher, "CN=Fabrikam, O=Fabrikam, C=US"));
+        assert!(Bundle::parse("<Bundle><Identity Name='a' Publisher='CN=a' Version='1.0.0.0'/></Bundle>").is_err());
+
+        let package = r#"<Package><Identity Name="Fabrikam.App" Publisher="CN=Contoso" Version="1.0.0.0"/></Package>"#;
+        assert_eq!(manifest_publisher(package).as_deref(), Some("CN=Contoso"));
+        assert!(is_package("App_1.4.0.0_x64.Appx") && !is_package("App.msi"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Connecting to the signing endpoint and the timestamp server before signing, so a blocked network fails with a
+//! message naming the host and the step instead of an opaque error of the signing client on the first file
+
+use crate::certificate;
+use url::Url;
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{
+        self,
+        client::{
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+            WebPkiServerVerifier,
+        },
+        crypto::ring,
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        DigitallySignedStruct, RootCertStore, SignatureScheme,
+    },
+    TlsConnector,
+};
+
+/// How long each step may take, a port a firewall drops never answers
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Issuers of the certificates of the endpoints and the timestamp servers. A proxy inspecting TLS presents
+/// certificates of its own CA, which the signing client refuses
+const EXPECTED_ISSUERS: [&str; 2] = ["O=Microsoft Corporation", "O=DigiCert Inc"];
+
+/// What connecting to a host came to, with how long each step took
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Probe {
+    pub host: String,
+    pub port: u16,
+    /// The address the host resolved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<u64>,
+    /// The handshake, `None` for plain http
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<u64>,
+    /// Issuer of the certificate the host presented, also when the handshake failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Whether the issuer isn't Microsoft's or DigiCert's
+    pub intercepted: bool,
+    /// The step that failed and why
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Probe {
+    /// The steps with their timings, like `dns 12ms, connect 30ms, tls 85ms`
+    pub fn timings(&self) -> String {
+        let steps = [("dns", self.dns_ms), ("connect", self.connect_ms), ("tls", self.tls_ms)];
+        let steps: Vec<String> =
+            steps.iter().filter_map(|(step, ms)| ms.map(|ms| format!("{} {}ms", step, ms))).collect();
+        steps.join(", ")
+    }
+
+    /// Why signing can't reach the host, naming what to allow
+    pub fn problem(&self) -> Option<String> {
+        let error = self.error.as_deref()?;
+        let host = &self.host;
+        Some(match (self.dns_ms, self.connect_ms) {
+            (None, _) => format!(
+                "{} could not be resolved ({}), check the DNS of this machine and that it may resolve outside hosts",
+                host, error
+            ),
+            (Some(_), None) => format!(
+                "{}:{} could not be connected to ({}), a firewall is likely blocking it. Allow outbound connections \
+                 to {} on port {}",
+                host, self.port, error, host, self.port
+            ),
+            (Some(_), Some(_)) => match self.intercepted {
+                true => format!(
+                    "the TLS handshake with {} failed ({}). The certificate was issued by '{}', a proxy inspecting \
+                     TLS seems to sit in between. Exempt {} from TLS inspection",
+                    host,
+                    error,
+                    self.issuer.as_deref().unwrap_or_default(),
+                    host
+                ),
+                false => format!("the TLS handshake with {} failed ({})", host, error),
+            },
+        })
+    }
+
+    /// The warning for a handshake that worked with a certificate not issued by Microsoft
+    pub fn warning(&self) -> Option<String> {
+        let issuer = self.issuer.as_deref().filter(|_| self.intercepted && self.error.is_none())?;
+        Some(format!(
+            "the certificate of {} was issued by '{}', not by Microsoft. A proxy seems to inspect TLS, which breaks \
+             the signing client. Exempt {} from TLS inspection",
+            self.host, issuer, self.host
+        ))
+    }
+}
+
+/// Whether a certificate of this issuer could be one of Microsoft's hosts
+fn is_expected(issuer: &str) -> bool {
+    EXPECTED_ISSUERS.iter().any(|expected| issuer.contains(expected))
+}
+
+/// Checks the certificate like any client but keeps the one presented, so a refused certificate can be named
+#[derive(Debug)]
+struct Recording {
+    inner: Arc<WebPkiServerVerifier>,
+    presented: Mutex<Option<Vec<u8>>>,
+}
+
+impl ServerCertVerifier for Recording {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        *self.presented.lock().unwrap() = Some(end_entity.to_vec());
+        self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// A client trusting the certificate store of the system, like the signing client does
+fn verifier() -> Result<Arc<Recording>, String> {
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+    if added == 0 {
+        Err("the certificate store of the system holds no usable root")?;
+    }
+    let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::new(ring::default_provider()))
+        .build()
+        .map_err(|err| err.to_string())?;
+    Ok(Arc::new(Recording { inner, presented: Mutex::new(None) }))
+}
+
+/// Run a step with [`TIMEOUT`], returning how long it took
+async fn timed<T, E: ToString>(step: impl Future<Output = Result<T, E>>) -> Result<(T, u64), String> {
+    let started = Instant::now();
+    match tokio::time::timeout(TIMEOUT, step).await {
+        Ok(Ok(value)) => Ok((value, started.elapsed().as_millis() as u64)),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err(format!("no answer within {}s", TIMEOUT.as_secs())),
+    }
+}
+
+/// Resolve the host of `url`, connect to it and, for https, complete a TLS handshake
+pub async fn probe(url: &str) -> Probe {
+    let mut probe = Probe::default();
+    let Some((url, host)) = Url::parse(url).ok().and_then(|url| Some((url.clone(), url.host_str()?.to_string())))
+    else {
+        probe.host = url.to_string();
+        probe.error = Some("not a URL with a host".to_string());
+        return probe;
+    };
+    probe.host = host.clone();
+    probe.port = url.port_or_known_default().unwrap_or(443);
+
+    let address = match timed(tokio::net::lookup_host((host.as_str(), probe.port))).await {
+        Ok((mut addresses, ms)) => {
+            probe.dns_ms = Some(ms);
+            addresses.next()
+        }
+        Err(err) => {
+            probe.error = Some(err);
+            return probe;
+        }
+    };
+    let Some(address) = address else {
+        probe.dns_ms = None;
+        probe.error = Some("no address".to_string());
+        return probe;
+    };
+    probe.address = Some(address.ip().to_string());
+    let stream = match timed(TcpStream::connect(address)).await {
+        Ok((stream, ms)) => {
+            probe.connect_ms = Some(ms);
+            stream
+        }
+        Err(err) => {
+            probe.error = Some(err);
+            return probe;
+        }
+    };
+    if url.scheme() != "https" {
+        return probe;
+    }
+
+    let verifier = match verifier() {
+        Ok(verifier) => verifier,
+        Err(err) => {
+            probe.error = Some(err);
+            return probe;
+        }
+    };
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map(|config| config.dangerous().with_custom_certificate_verifier(verifier.clone()).with_no_client_auth());
+    let (config, name) = match (config, ServerName::try_from(host.clone())) {
+        (Ok(config), Ok(name)) => (config, name),
+        (Err(err), _) => {
+            probe.error = Some(err.to_string());
+            return probe;
+        }
+        (_, Err(err)) => {
+            probe.error = Some(err.to_string());
+            return probe;
+        }
+    };
+    let handshake = timed(TlsConnector::from(Arc::new(config)).connect(name, stream)).await;
+    let presented = verifier.presented.lock().unwrap().take();
+    probe.issuer = presented.and_then(|der| certificate::details(&der)).map(|certificate| certificate.issuer);
+    probe.intercepted = probe.issuer.as_deref().is_some_and(|issuer| !is_expected(issuer));
+    match handshake {
+        Ok((_, ms)) => probe.tls_ms = Some(ms),
+        Err(err) => probe.error = Some(err),
+    }
+    probe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn steps_and_messages() {
+        // accepted, but without TLS
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let probe = probe(&format!("http://127.0.0.1:{}/", port)).await;
+        assert!(probe.error.is_none() && probe.tls_ms.is_none(), "{:?}", probe);
+        assert!(probe.timings().starts_with("dns ") && probe.timings().contains(", connect "));
+        drop(listener);
+        let refused = super::probe(&format!("http://127.0.0.1:{}/", port)).await;
+        assert!(refused.problem().unwrap().contains("a firewall is likely blocking it"), "{:?}", refused);
+
+        let intercepted = Probe {
+            host: "eus.codesigning.azure.net".to_string(),
+            port: 443,
+            dns_ms: Some(3),
+            connect_ms: Some(20),
+            issuer: Some("CN=Zscaler Intermediate Root CA, O=Zscaler Inc.".to_string()),
+            intercepted: true,
+            error: Some("invalid peer certificate: UnknownIssuer".to_string()),
+            ..Probe::default()
+        };
+        assert!(intercepted.problem().unwrap().contains("issued by 'CN=Zscaler Intermediate Root CA"));
+        assert!(is_expected("CN=Microsoft Azure RSA TLS Issuing CA 07, O=Microsoft Corporation, C=US"));
+        assert!(!is_expected("CN=Zscaler Intermediate Root CA, O=Zscaler Inc."));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    signtool::Digest,
+};
+use duct::cmd;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where the .NET installer puts dotnet on Windows, tried after the PATH
+const DEFAULT_DOTNET_PATH: &str = r"C:\Program Files\dotnet\dotnet.exe";
+
+/// How `dotnet nuget sign` is asked to sign
+#[derive(Debug)]
+pub struct Options<'a> {
+    /// SHA-256 fingerprint of the certificate in the certificate store
+    pub certificate_fingerprint: &'a str,
+    pub file_digest: Digest,
+    pub timestamp_url: &'a str,
+    pub timestamp_digest: Digest,
+    /// Keep an existing signature, signing such a package fails then
+    pub append: bool,
+}
+
+/// Whether the file is a NuGet package, judged by its extension
+pub fn is_package(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("nupkg"))
+}
+
+/// The dotnet to run, `path` when given, then DOTNET_HOST_PATH as set inside `dotnet` builds,
+/// then the PATH and the default install location. It must have an SDK, the runtime alone can't sign
+pub fn find(path: Option<&str>) -> Result<PathBuf, Error> {
+    let executable = if cfg!(windows) { "dotnet.exe" } else { "dotnet" };
+    let on_path = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join(executable)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let candidates: Vec<PathBuf> = match path {
+        Some(path) => vec![PathBuf::from(path)],
+        None => std::env::var_os("DOTNET_HOST_PATH")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(on_path)
+            .chain([PathBuf::from(DEFAULT_DOTNET_PATH)])
+            .collect(),
+    };
+    let Some(dotnet) = candidates.into_iter().find(|candidate| candidate.is_file()) else {
+        Err(Error::MissingDependency {
+            component: "dotnet",
+            message: format!(
+                "dotnet {}was not found, signing NuGet packages needs the .NET SDK 6 or later: \
+                 install it from https://dot.net or pass --dotnet-path",
+                path.map(|path| format!("'{}' ", path)).unwrap_or_default()
+            ),
+        })?
+    };
+    let output = run_captured(cmd(&dotnet, ["--list-sdks"])).map_err(|err| Error::MissingDependency {
+        component: "dotnet",
+        message: format!("dotnet '{}' could not be run: {}", dotnet.display(), err),
+    })?;
+    if !output.status.success() || output.stdout.iter().all(u8::is_ascii_whitespace) {
+        Err(Error::MissingDependency {
+            component: "dotnet",
+            message: format!(
+                "dotnet '{}' has no SDK, signing NuGet packages needs the .NET SDK 6 or later from https://dot.net",
+                dotnet.display()
+            ),
+        })?;
+    }
+    debug!("signing NuGet packages with {}", dotnet.display());
+    Ok(dotnet)
+}
+
+/// Arguments of `dotnet` for signing the package `file`
+pub fn arguments(file: &str, options: &Options) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "nuget",
+        "sign",
+        file,
+        "--certificate-fingerprint",
+        options.certificate_fingerprint,
+        "--hash-algorithm",
+        options.file_digest.name(),
+        "--timestamper",
+        options.timestamp_url,
+        "--timestamp-hash-algorithm",
+        options.timestamp_digest.name(),
+    ]
+    .map(OsString::from)
+    .to_vec();
+    if !options.append {
+        args.push(OsString::from("--overwrite"));
+    }
+    args
+}
+
+/// Sign a NuGet package in place with `dotnet nuget sign`
+pub fn sign(dotnet: &Path, options: &Options, file: &str) -> Result<(), Error> {
+    if options.file_digest == Digest::Sha1 || options.timestamp_digest == Digest::Sha1 {
+        Err(Error::Usage(
+            "NuGet packages can't be signed with SHA1, pass --fd and --td SHA256 or stronger".to_string(),
+        ))?;
+    }
+    let message = format!("dotnet nuget sign could not sign the package '{}'", file);
+    let output = run_captured(cmd(dotnet, arguments(file, options))).map_err(|err| Error::Signing {
+        path: file.to_string(),
+        message: format!("{}, error: {:?}", message, &err),
+        output: String::new(),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nuget_sign_arguments() {
+        assert!(is_package(r"out\My.Library.1.2.0.NUPKG"));
+        assert!(!is_package("My.Library.1.2.0.snupkg"));
+
+        let options = Options {
+            certificate_fingerprint: "3F9001EA83C560D7",
+            file_digest: Digest::Sha256,
+            timestamp_url: "http://timestamp.acs.microsoft.com",
+            timestamp_digest: Digest::Sha384,
+            append: false,
+        };
+        let args: Vec<String> =
+            arguments("lib.nupkg", &options).into_iter().map(|arg| arg.to_string_lossy().to_string()).collect();
+        assert_eq!(
+            args.join(" "),
+            "nuget sign lib.nupkg --certificate-fingerprint 3F9001EA83C560D7 --hash-algorithm SHA256 \
+             --timestamper http://timestamp.acs.microsoft.com --timestamp-hash-algorithm SHA384 --overwrite"
+        );
+    }
+}
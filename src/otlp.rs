@@ -0,0 +1,458 @@
+//! Exporting a trace of a run over OTLP, so signing shows up as spans next to the other steps of a build. The spans
+//! are collected from the events of the run and posted as OTLP/HTTP JSON once it is over
+
+use crate::{
+    events::{Event, Sink},
+    hash, logging,
+    report::{FileReport, Report, Status},
+    route::Target,
+};
+use reqwest::{header::CONTENT_TYPE, Url};
+use serde_json::{json, Value};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::debug;
+
+/// How long the export may take unless OTEL_EXPORTER_OTLP_TIMEOUT says otherwise, like the OpenTelemetry SDKs
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Check `--otlp-endpoint`: an http(s) URL
+pub fn parse_endpoint(value: &str) -> Result<String, String> {
+    match Url::parse(value) {
+        Ok(url) if ["https", "http"].contains(&url.scheme()) => Ok(value.to_string()),
+        Ok(url) => Err(format!("the OTLP endpoint must use https or http, not {}", url.scheme())),
+        Err(err) => Err(format!("the OTLP endpoint is not a URL: {}", err)),
+    }
+}
+
+/// `%XX` escapes of the values of OTEL_EXPORTER_OTLP_HEADERS and OTEL_RESOURCE_ATTRIBUTES decoded
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| value.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A list like `key1=value1,key2=value2`
+fn pairs(list: &str, what: &str) -> Result<Vec<(String, String)>, String> {
+    list.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Ok((decode(key.trim()), decode(value.trim()))),
+            _ => Err(format!("{} holds '{}', not key=value", what, pair.trim())),
+        })
+        .collect()
+}
+
+/// The ids of a W3C `traceparent`, like `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+fn traceparent(value: &str) -> Result<([u8; 16], [u8; 8]), String> {
+    let invalid = || format!("TRACEPARENT '{}' is not a W3C traceparent", value);
+    let hex = |part: &str| -> Option<Vec<u8>> {
+        let bytes: Option<Vec<u8>> = (0..part.len())
+            .step_by(2)
+            .map(|index| part.get(index..index + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+            .collect();
+        bytes.filter(|bytes| bytes.iter().any(|byte| *byte != 0))
+    };
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    let [version, trace, span, _flags] = parts[..] else {
+        return Err(invalid());
+    };
+    match (version.len(), trace.len(), span.len()) {
+        (2, 32, 16) if version != "ff" => (),
+        _ => return Err(invalid()),
+    }
+    let trace = hex(trace).and_then(|trace| trace.try_into().ok()).ok_or_else(invalid)?;
+    let span = hex(span).and_then(|span| span.try_into().ok()).ok_or_else(invalid)?;
+    Ok((trace, span))
+}
+
+/// Where and how the trace is exported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The URL the spans are posted to
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+    /// Attributes of the resource, `service.name` first
+    pub resource: Vec<(String, String)>,
+    /// The trace and span of TRACEPARENT, the span of the run becomes a child of it
+    pub parent: Option<([u8; 16], [u8; 8])>,
+}
+
+impl Config {
+    /// The export `--otlp-endpoint` and the standard OTEL_* variables read with `var` ask for, `None` without an
+    /// endpoint or when OTEL_SDK_DISABLED or OTEL_TRACES_EXPORTER turn traces off
+    pub fn from_env(endpoint: Option<&str>, var: impl Fn(&str) -> Option<String>) -> Result<Option<Config>, String> {
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let disabled = var("OTEL_SDK_DISABLED").is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+        let exporter = var("OTEL_TRACES_EXPORTER");
+        if disabled || exporter.as_deref().is_some_and(|exporter| exporter.trim() == "none") {
+            return Ok(None);
+        }
+        // the base URL gets the path of traces, the one for traces alone is used as it is
+        let base = |base: &str| match base.trim_end_matches('/') {
+            traces if traces.ends_with("/v1/traces") => traces.to_string(),
+            base => format!("{}/v1/traces", base),
+        };
+        let url = match (endpoint, var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"), var("OTEL_EXPORTER_OTLP_ENDPOINT")) {
+            (Some(endpoint), _, _) => base(endpoint),
+            (None, Some(traces), _) => parse_endpoint(&traces)?,
+            (None, None, Some(endpoint)) => base(&parse_endpoint(&endpoint)?),
+            (None, None, None) => return Ok(None),
+        };
+        let protocol = var("OTEL_EXPORTER_OTLP_TRACES_PROTOCOL").or_else(|| var("OTEL_EXPORTER_OTLP_PROTOCOL"));
+        match protocol.as_deref().map(str::trim) {
+            // an OTLP/HTTP receiver takes JSON as well as protobuf
+            None | Some("http/json") | Some("http/protobuf") => (),
+            Some(protocol) => Err(format!("traces are exported with OTLP over HTTP, not {}", protocol))?,
+        }
+
+        let mut headers = Vec::new();
+        for name in ["OTEL_EXPORTER_OTLP_HEADERS", "OTEL_EXPORTER_OTLP_TRACES_HEADERS"] {
+            for (key, value) in pairs(&var(name).unwrap_or_default(), name)? {
+                headers.retain(|(known, _): &(String, String)| !known.eq_ignore_ascii_case(&key));
+                headers.push((key, value));
+            }
+        }
+        let timeout = match var("OTEL_EXPORTER_OTLP_TRACES_TIMEOUT").or_else(|| var("OTEL_EXPORTER_OTLP_TIMEOUT")) {
+            Some(ms) => ms
+                .trim()
+                .parse()
+                .map(Duration::from_millis)
+                .map_err(|_| format!("the OTLP timeout is given in milliseconds, not '{}'", ms))?,
+            None => TIMEOUT,
+        };
+
+        let mut resource = pairs(&var("OTEL_RESOURCE_ATTRIBUTES").unwrap_or_default(), "OTEL_RESOURCE_ATTRIBUTES")?;
+        let service = var("OTEL_SERVICE_NAME")
+            .or_else(|| resource.iter().find(|(key, _)| key == "service.name").map(|(_, name)| name.clone()))
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        resource.retain(|(key, _)| key != "service.name" && key != "service.version");
+        resource.insert(0, ("service.name".to_string(), service));
+        resource.insert(1, ("service.version".to_string(), env!("CARGO_PKG_VERSION").to_string()));
+        let parent = var("TRACEPARENT").map(|value| traceparent(&value)).transpose()?;
+        Ok(Some(Config { url, headers, timeout, resource, parent }))
+    }
+}
+
+/// Nanoseconds since the Unix epoch, how OTLP gives times
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+fn span_id() -> [u8; 8] {
+    let bytes = *uuid::Uuid::new_v4().as_bytes();
+    bytes[..8].try_into().expect("8 of 16 bytes")
+}
+
+fn string(value: &str) -> Value {
+    json!({ "stringValue": value })
+}
+
+/// 64-bit integers are strings in the JSON encoding of OTLP
+fn int(value: u64) -> Value {
+    json!({ "intValue": value.to_string() })
+}
+
+/// A span of the run, closed once it has an end
+#[derive(Debug)]
+struct Span {
+    name: String,
+    id: [u8; 8],
+    start: u64,
+    end: Option<u64>,
+    attributes: Vec<(&'static str, Value)>,
+    error: Option<String>,
+}
+
+impl Span {
+    fn to_json(&self, trace_id: &str, parent: &[u8], end: u64) -> Value {
+        let attributes: Vec<Value> =
+            self.attributes.iter().map(|(key, value)| json!({ "key": key, "value": value })).collect();
+        let mut span = json!({
+            "traceId": trace_id,
+            "spanId": hash::hex(&self.id),
+            "parentSpanId": hash::hex(parent),
+            "name": self.name,
+            // internal
+            "kind": 1,
+            "startTimeUnixNano": self.start.to_string(),
+            "endTimeUnixNano": self.end.unwrap_or(end).max(self.start).to_string(),
+            "attributes": attributes,
+        });
+        if let Some(error) = &self.error {
+            span["status"] = json!({ "code": 2, "message": error });
+        }
+        span
+    }
+}
+
+/// The attributes and the outcome of the span of a finished file
+fn finish_file(span: &mut Span, file: &FileReport) {
+    let name = Path::new(&file.path).file_name().map(|name| name.to_string_lossy().into_owned());
+    span.attributes.push(("file.name", string(&name.unwrap_or_else(|| file.path.clone()))));
+    if let Some(size) = file.size {
+        span.attributes.push(("file.size", int(size)));
+    }
+    let status = serde_json::to_value(file.status).unwrap_or_default();
+    span.attributes.push(("trusted_signing.status", string(status.as_str().unwrap_or_default())));
+    if let Some(mechanism) = file.mechanism.and_then(|mechanism| serde_json::to_value(mechanism).ok()) {
+        span.attributes.push(("trusted_signing.mechanism", string(mechanism.as_str().unwrap_or_default())));
+    }
+    if let Some(profile) = &file.certificate_profile {
+        span.attributes.push(("trusted_signing.certificate_profile", string(profile)));
+    }
+    if let Some(endpoint) = &file.endpoint {
+        span.attributes.push(("trusted_signing.endpoint", string(endpoint)));
+    }
+    span.attributes.push(("trusted_signing.retries", int(file.retries.into())));
+    if let Some(kind) = file.error_kind {
+        span.attributes.push(("error.type", string(kind)));
+    }
+    if let Some(reason) = &file.skip_reason {
+        span.attributes.push(("trusted_signing.skip_reason", string(reason)));
+    }
+    if file.status == Status::Failed {
+        span.error = Some(file.reason().unwrap_or("signing failed").to_string());
+    }
+    span.end = Some(now());
+}
+
+/// Turns the events of a run into spans
+struct Recorder(Arc<Mutex<Vec<Span>>>);
+
+impl Sink for Recorder {
+    fn emit(&mut self, _seq: u64, event: &Event) {
+        let mut spans = self.0.lock().unwrap();
+        match event {
+            // the login is announced once it is over, the duration says when it started
+            Event::PhaseFinished { phase, duration_ms } => {
+                let end = now();
+                spans.push(Span {
+                    name: phase.to_string(),
+                    id: span_id(),
+                    start: end.saturating_sub(duration_ms * 1_000_000),
+                    end: Some(end),
+                    attributes: Vec::new(),
+                    error: None,
+                });
+            }
+            Event::FileStarted { path, index, .. } => spans.push(Span {
+                name: "sign file".to_string(),
+                id: span_id(),
+                start: now(),
+                end: None,
+                attributes: vec![("file.path", string(path)), ("trusted_signing.index", int(*index as u64))],
+                error: None,
+            }),
+            Event::FileFinished { file } => {
+                if let Some(span) = spans.iter_mut().rev().find(|span| span.name == "sign file" && span.end.is_none()) {
+                    finish_file(span, file);
+                }
+            }
+            Event::RunStarted { .. }
+            | Event::PhaseStarted { .. }
+            | Event::DownloadProgress { .. }
+            | Event::RunFinished { .. } => (),
+        }
+    }
+}
+
+/// The trace of one run, exported when it is over
+pub struct Trace {
+    config: Config,
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    started: u64,
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+impl Trace {
+    pub fn new(config: Config) -> Self {
+        let trace_id = match config.parent {
+            Some((trace_id, _)) => trace_id,
+            None => *uuid::Uuid::new_v4().as_bytes(),
+        };
+        Trace { config, trace_id, span_id: span_id(), started: now(), spans: Arc::default() }
+    }
+
+    /// The sink recording the spans of the phases and files, to add to the events of the run
+    pub fn sink(&self) -> Box<dyn Sink> {
+        Box::new(Recorder(self.spans.clone()))
+    }
+
+    /// The OTLP request of the trace: the run with the phases and files under it. Spans still open, of a run
+    /// stopped by an error or Ctrl+C, end now
+    pub fn payload(&self, target: &Target, report: &Report, error: Option<&str>) -> Value {
+        let end = now();
+        let trace_id = hash::hex(&self.trace_id);
+        let summary = &report.summary;
+        let mut attributes = vec![
+            ("trusted_signing.endpoint", string(&target.endpoint)),
+            ("trusted_signing.account", string(&target.account)),
+            ("trusted_signing.certificate_profile", string(&target.certificate)),
+            ("trusted_signing.files", int(report.files.len() as u64)),
+            ("trusted_signing.signed", int(summary.signed as u64)),
+            ("trusted_signing.failed", int(summary.failed as u64)),
+            ("trusted_signing.skipped", int(summary.skipped as u64)),
+        ];
+        if let Some(correlation_id) = &report.correlation_id {
+            attributes.push(("trusted_signing.correlation_id", string(correlation_id)));
+        }
+        let run = Span {
+            name: "sign".to_string(),
+            id: self.span_id,
+            start: self.started,
+            end: Some(end),
+            attributes,
+            error: error.map(str::to_string),
+        };
+        let parent = self.config.parent.map(|(_, span_id)| span_id.to_vec()).unwrap_or_default();
+        let mut spans = vec![run.to_json(&trace_id, &parent, end)];
+        for span in self.spans.lock().unwrap().iter() {
+            let mut json = span.to_json(&trace_id, &self.span_id, end);
+            if span.end.is_none() {
+                json["status"] = json!({ "code": 2, "message": "the run ended before it finished" });
+            }
+            spans.push(json);
+        }
+        let resource: Vec<Value> =
+            self.config.resource.iter().map(|(key, value)| json!({ "key": key, "value": string(value) })).collect();
+        json!({
+            "resourceSpans": [{
+                "resource": { "attributes": resource },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+
+    /// Post the trace, once. Its failure is only for a warning, the run's outcome is settled
+    pub async fn export(&self, target: &Target, report: &Report, error: Option<&str>) -> Result<(), String> {
+        let body = logging::mask(&self.payload(target, report, error).to_string());
+        let host = Url::parse(&self.config.url).ok().and_then(|url| url.host_str().map(str::to_string));
+        let host = host.unwrap_or_else(|| self.config.url.clone());
+        let client = reqwest::Client::builder().timeout(self.config.timeout).build().map_err(|err| err.to_string())?;
+        let mut request = client.post(&self.config.url).header(CONTENT_TYPE, "application/json").body(body);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("exported the trace {} to {}", hash::hex(&self.trace_id), host);
+                Ok(())
+            }
+            Ok(response) => Err(format!("the OTLP endpoint at {} answered {}", host, response.status())),
+            Err(err) if err.is_timeout() => Err(format!("the OTLP endpoint at {} didn't answer in time", host)),
+            Err(_) => Err(format!("the OTLP endpoint at {} could not be reached", host)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Events;
+    use std::collections::HashMap;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[tokio::test]
+    async fn spans_of_a_run() {
+        let env = HashMap::from([
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318/"),
+            ("OTEL_EXPORTER_OTLP_HEADERS", "x-api-key=s%3Dcr3t, x-team=build"),
+            ("OTEL_RESOURCE_ATTRIBUTES", "service.name=release,ci.job=42"),
+            ("TRACEPARENT", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        ]);
+        let var = |name: &str| env.get(name).map(|value| value.to_string());
+        let config = Config::from_env(None, var).unwrap().unwrap();
+        assert_eq!(config.url, "http://collector:4318/v1/traces");
+        assert_eq!(config.headers[0], ("x-api-key".to_string(), "s=cr3t".to_string()));
+        assert_eq!(config.resource[0], ("service.name".to_string(), "release".to_string()));
+        assert_eq!(config.parent.map(|(_, span)| hash::hex(&span)).as_deref(), Some("00f067aa0ba902b7"));
+        let flag = Config::from_env(Some("https://otel.example.com"), |_| None).unwrap().unwrap();
+        assert_eq!(flag.url, "https://otel.example.com/v1/traces");
+        assert_eq!(Config::from_env(None, |_| None), Ok(None));
+        let disabled = |name: &str| (name == "OTEL_SDK_DISABLED").then(|| "true".to_string());
+        assert_eq!(Config::from_env(Some("https://otel.example.com"), disabled), Ok(None));
+        let grpc = |name: &str| (name == "OTEL_EXPORTER_OTLP_PROTOCOL").then(|| "grpc".to_string());
+        assert!(Config::from_env(Some("https://otel.example.com"), grpc).is_err());
+        assert!(traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_err());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let trace = Trace::new(Config::from_env(Some(&endpoint), |_| None).unwrap().unwrap());
+        let files = ["app.exe".to_string(), "lib.dll".to_string()];
+        let mut report = Report::new(&files);
+        let mut events = Events::default();
+        events.add(trace.sink());
+        events.emit(Event::PhaseFinished { phase: "login", duration_ms: 900 });
+        events.emit(Event::FileStarted { path: "app.exe", index: 0, total: 2 });
+        report.files[0].status = Status::Signed;
+        report.files[0].size = Some(4096);
+        report.files[0].retries = 1;
+        events.emit(Event::FileFinished { file: &report.files[0] });
+        events.emit(Event::FileStarted { path: "lib.dll", index: 1, total: 2 });
+        report.finish(Duration::from_secs(2));
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+        let payload = trace.payload(&target, &report, Some("the run was interrupted"));
+        let spans = &payload["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 4);
+        assert_eq!((&spans[0]["name"], &spans[0]["status"]["code"]), (&json!("sign"), &json!(2)));
+        assert_eq!(spans[1]["name"], "login");
+        assert_eq!(spans[2]["parentSpanId"], spans[0]["spanId"]);
+        let attribute = |span: &Value, key: &str| {
+            let attributes = span["attributes"].as_array().unwrap();
+            attributes.iter().find(|attribute| attribute["key"] == key).map(|attribute| attribute["value"].clone())
+        };
+        assert_eq!(attribute(&spans[2], "file.size"), Some(json!({ "intValue": "4096" })));
+        assert_eq!(attribute(&spans[2], "trusted_signing.retries"), Some(json!({ "intValue": "1" })));
+        // interrupted while signing
+        assert_eq!(spans[3]["status"]["code"], 2);
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 8192];
+            while !String::from_utf8_lossy(&request).contains("\"scopeSpans\"") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend(&buffer[..read]);
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        trace.export(&target, &report, None).await.unwrap();
+        assert!(server.await.unwrap().starts_with("POST /v1/traces "));
+        let failure = trace.export(&target, &report, None).await.unwrap_err();
+        assert!(failure.ends_with("could not be reached"), "{}", failure);
+    }
+}
@@ -0,0 +1,283 @@
+//! `--validate-pe`: the headers of a signed PE image checked against the file, so an image that verifies but
+//! doesn't load fails the run instead of shipping
+
+use crate::{authenticode, error::Error};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+use tracing::{debug, warn};
+
+/// How much of the start of an image is read for its headers and section table
+const HEADERS: u64 = 64 * 1024;
+
+/// The size of a section header
+const SECTION: usize = 40;
+
+/// Where the parts of a signed image are, once its headers made sense
+#[derive(Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// PE32 or PE32+
+    pub format: &'static str,
+    /// Offset of the CheckSum of the optional header
+    pub checksum_offset: usize,
+    pub checksum: u32,
+    /// Offset and size of the certificate table
+    pub certificates: (u64, u64),
+}
+
+fn u16_at(image: &[u8], offset: usize) -> Result<u16, String> {
+    let bytes = image.get(offset..offset + 2).ok_or("its headers are cut short")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(image: &[u8], offset: usize) -> Result<u32, String> {
+    let bytes = image.get(offset..offset + 4).ok_or("its headers are cut short")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Check the headers at the start of a signed image of `len` bytes against each other and the file: the section
+/// table within the headers, the sections within the file and the image, and the certificate table after the
+/// sections, aligned, and at the very end of the file
+pub fn layout(headers: &[u8], len: u64) -> Result<Layout, String> {
+    let header = u32_at(headers, 0x3c)? as usize;
+    if headers.get(header..header + 4) != Some(b"PE\0\0") {
+        Err(format!("there is no PE signature at {:#x}, where its DOS header points", header))?;
+    }
+    let coff = header + 4;
+    let sections = u16_at(headers, coff + 2)? as usize;
+    let optional_size = u16_at(headers, coff + 16)? as usize;
+    let optional = coff + 20;
+    let (format, count_offset) = match u16_at(headers, optional)? {
+        0x10b => ("PE32", optional + 92),
+        0x20b => ("PE32+", optional + 108),
+        magic => Err(format!("its optional header magic is {:#06x}, neither PE32 nor PE32+", magic))?,
+    };
+    let (section_alignment, size_of_image) = (u32_at(headers, optional + 32)?, u32_at(headers, optional + 56)?);
+    let size_of_headers = u32_at(headers, optional + 60)?;
+    let directories = u32_at(headers, count_offset)? as usize;
+    if directories < 5 {
+        Err(format!("its {} optional header has {} data directories, none for certificates", format, directories))?;
+    }
+    if count_offset + 4 + directories * 8 > optional + optional_size {
+        Err(format!(
+            "its {} data directories don't fit in its {} optional header of {} bytes",
+            directories, format, optional_size
+        ))?;
+    }
+
+    let table = optional + optional_size;
+    let table_end = table + sections * SECTION;
+    if table_end > headers.len() {
+        Err(format!("its section table of {} sections runs past the first {} KiB", sections, HEADERS / 1024))?;
+    }
+    if table_end as u64 > size_of_headers as u64 || size_of_headers as u64 > len {
+        Err(format!(
+            "its SizeOfHeaders is {} bytes, the section table ends at {} and the file is {} bytes",
+            size_of_headers, table_end, len
+        ))?;
+    }
+    let mut data_end = size_of_headers as u64;
+    for section in (table..table_end).step_by(SECTION) {
+        let name = String::from_utf8_lossy(&headers[section..section + 8]).trim_end_matches('\0').to_string();
+        let (virtual_size, address) = (u32_at(headers, section + 8)?, u32_at(headers, section + 12)?);
+        let (raw_size, raw_offset) = (u32_at(headers, section + 16)?, u32_at(headers, section + 20)?);
+        let raw_end = raw_offset as u64 + raw_size as u64;
+        if raw_size > 0 && raw_end > len {
+            Err(format!("its section {} ends at {}, past the end of the file at {}", name, raw_end, len))?;
+        }
+        if raw_size > 0 {
+            data_end = data_end.max(raw_end);
+        }
+        let mapped = match virtual_size {
+            0 => raw_size,
+            size => size,
+        } as u64;
+        let virtual_end = (address as u64 + mapped).next_multiple_of(section_alignment.max(1) as u64);
+        if virtual_end > size_of_image as u64 {
+            Err(format!(
+                "its section {} ends at RVA {:#x}, past its SizeOfImage of {:#x}",
+                name, virtual_end, size_of_image
+            ))?;
+        }
+    }
+
+    let entry = count_offset + 4 + 4 * 8;
+    let (offset, size) = (u32_at(headers, entry)? as u64, u32_at(headers, entry + 4)? as u64);
+    if offset == 0 || size == 0 {
+        Err("its security directory is empty, it has no certificate table")?;
+    }
+    if offset % 8 != 0 {
+        Err(format!("its certificate table at {} isn't aligned to 8 bytes", offset))?;
+    }
+    if offset < data_end {
+        Err(format!("its certificate table at {} overlaps its sections, which end at {}", offset, data_end))?;
+    }
+    match offset + size {
+        end if end > len => Err(format!("its certificate table ends at {}, past the end of the file at {}", end, len))?,
+        end if end < len => Err(format!("{} bytes follow its certificate table, which must end the file", len - end))?,
+        _ => (),
+    }
+    Ok(Layout {
+        format,
+        checksum_offset: optional + 64,
+        checksum: u32_at(headers, optional + 64)?,
+        certificates: (offset, size),
+    })
+}
+
+/// Check the WIN_CERTIFICATE entries of a certificate table: aligned to 8 bytes, of revision 1 or 2, each a
+/// PKCS#7 SignedData, and nothing else in the table. How many there are
+pub fn certificate_table(table: &[u8]) -> Result<usize, String> {
+    let (mut offset, mut entries) = (0, 0);
+    while offset < table.len() {
+        let length = u32_at(table, offset)
+            .map_err(|_| format!("its certificate table ends inside the entry at {}", offset))? as usize;
+        if length < 8 || offset + length > table.len() {
+            Err(format!("the certificate entry at {} of its table claims {} bytes", offset, length))?;
+        }
+        let (revision, kind) = (u16_at(table, offset + 4)?, u16_at(table, offset + 6)?);
+        if revision != 0x100 && revision != 0x200 {
+            Err(format!("the certificate entry at {} of its table is of revision {:#06x}", offset, revision))?;
+        }
+        if kind != 2 || authenticode::signed_data(&table[offset + 8..offset + length]).is_none() {
+            Err(format!("the certificate entry at {} of its table isn't a PKCS#7 SignedData", offset))?;
+        }
+        entries += 1;
+        // the padding of the last entry may be left out of the table
+        offset += length.next_multiple_of(8);
+    }
+    match entries {
+        0 => Err("its certificate table has no entries".to_string()),
+        entries => Ok(entries),
+    }
+}
+
+/// The PE checksum of an image read from `reader`, leaving out the checksum at `checksum_offset`
+pub fn checksum(mut reader: impl Read, checksum_offset: u64) -> io::Result<u32> {
+    let (mut sum, mut len) = (0u64, 0u64);
+    let mut low = None;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            match low.take() {
+                None => low = Some(byte),
+                Some(low) => {
+                    // `len` is the offset of the word's second byte
+                    let word = len - 1;
+                    if word != checksum_offset && word != checksum_offset + 2 {
+                        sum += u16::from_le_bytes([low, byte]) as u64;
+                        sum = (sum & 0xffff) + (sum >> 16);
+                    }
+                }
+            }
+            len += 1;
+        }
+    }
+    if let Some(low) = low {
+        sum += low as u64;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    Ok(((sum & 0xffff) + (sum >> 16) + len) as u32)
+}
+
+/// Validate a signed file when it is a PE image, fixing a CheckSum that doesn't match the file: it isn't covered
+/// by the signature, and drivers with a wrong one don't load. Other files aren't checked
+pub fn validate(file: &str) -> Result<(), Error> {
+    let inconsistent = |message: String| Error::Signing {
+        path: file.to_string(),
+        message: format!("'{}' was signed, but its PE image is inconsistent: {}", file, message),
+        output: String::new(),
+    };
+    let failed = |err: io::Error| format!("'{}' could not be read to validate it: {}", file, err);
+    let mut opened = File::open(file).map_err(failed)?;
+    let len = opened.metadata().map_err(failed)?.len();
+    let mut headers = Vec::new();
+    Read::by_ref(&mut opened).take(HEADERS).read_to_end(&mut headers).map_err(failed)?;
+    if !headers.starts_with(b"MZ") {
+        debug!("{} isn't a PE image, not validating it", file);
+        return Ok(());
+    }
+    let layout = layout(&headers, len).map_err(inconsistent)?;
+    let (offset, size) = layout.certificates;
+    let mut table = vec![0; size as usize];
+    opened.seek(SeekFrom::Start(offset)).and_then(|_| opened.read_exact(&mut table)).map_err(failed)?;
+    let entries = certificate_table(&table).map_err(inconsistent)?;
+
+    opened.rewind().map_err(failed)?;
+    let computed = checksum(io::BufReader::new(opened), layout.checksum_offset as u64).map_err(failed)?;
+    // linkers leave the CheckSum of most images 0, only drivers need one
+    if layout.checksum != 0 && layout.checksum != computed {
+        warn!("the CheckSum of {} is {:#010x}, not {:#010x}, fixing it", file, layout.checksum, computed);
+        let failed = |err: io::Error| format!("the CheckSum of '{}' could not be fixed: {}", file, err);
+        let mut writable = OpenOptions::new().write(true).open(file).map_err(failed)?;
+        writable.seek(SeekFrom::Start(layout.checksum_offset as u64)).map_err(failed)?;
+        writable.write_all(&computed.to_le_bytes()).and_then(|_| writable.sync_data()).map_err(failed)?;
+    }
+    debug!("{} is a consistent {} image with {} certificate(s)", file, layout.format, entries);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unsign;
+    use std::fs;
+
+    const PE32: &[u8] = include_bytes!("../tests/fixtures/pe/signed-pe32.bin");
+    const PE32_PLUS: &[u8] = include_bytes!("../tests/fixtures/pe/signed-pe32-plus.bin");
+
+    #[test]
+    fn signed_images() {
+        let len = PE32.len() as u64;
+        let pe32 = layout(PE32, len).unwrap();
+        assert_eq!((pe32.format, pe32.checksum_offset, pe32.certificates), ("PE32", 0xd8, (0x400, len - 0x400)));
+        let plus = layout(PE32_PLUS, len).unwrap();
+        assert_eq!((plus.format, plus.checksum_offset), ("PE32+", 0xd8));
+        for (image, layout) in [(PE32, &pe32), (PE32_PLUS, &plus)] {
+            assert_eq!(certificate_table(&image[0x400..]), Ok(1));
+            assert_eq!(checksum(image, 0xd8).unwrap(), layout.checksum);
+            let odd = &image[..len as usize - 1];
+            assert_eq!(checksum(odd, 0xd8).unwrap(), unsign::pe_checksum(odd, 0xd8));
+        }
+
+        let error = |image: &[u8]| layout(image, image.len() as u64).unwrap_err();
+        assert!(error(&[PE32, b"overlay"].concat()).starts_with("7 bytes follow its certificate table"));
+        assert!(layout(PE32, len - 8).unwrap_err().contains("past the end of the file"));
+        let mut image = PE32.to_vec();
+        // the section of raw data claims more than there is
+        image[0x178 + 16..0x178 + 20].copy_from_slice(&0x208u32.to_le_bytes());
+        assert_eq!(error(&image), "its certificate table at 1024 overlaps its sections, which end at 1032");
+        image[0x178 + 20..0x178 + 24].copy_from_slice(&0x800u32.to_le_bytes());
+        assert_eq!(error(&image), "its section .text ends at 2568, past the end of the file at 1240");
+        let mut image = PE32_PLUS.to_vec();
+        image[0x98 + 56..0x98 + 60].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert_eq!(error(&image), "its section .text ends at RVA 0x2000, past its SizeOfImage of 0x1000");
+        image[0x98..0x9a].copy_from_slice(&0x107u16.to_le_bytes());
+        assert_eq!(error(&image), "its optional header magic is 0x0107, neither PE32 nor PE32+");
+
+        let mut table = PE32[0x400..].to_vec();
+        let padded = [&table[..], &[0; 8]].concat();
+        assert_eq!(certificate_table(&padded).unwrap_err(), "the certificate entry at 216 of its table claims 0 bytes");
+        table[6] = 1;
+        assert!(certificate_table(&table).unwrap_err().ends_with("at 0 of its table isn't a PKCS#7 SignedData"));
+
+        // a stale CheckSum is fixed, an image with data after the table fails
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.exe");
+        let mut image = PE32_PLUS.to_vec();
+        image[0xd8..0xdc].copy_from_slice(&1u32.to_le_bytes());
+        fs::write(&path, &image).unwrap();
+        validate(&path.display().to_string()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), PE32_PLUS);
+        fs::write(&path, [PE32_PLUS, &[0; 8]].concat()).unwrap();
+        let err = validate(&path.display().to_string()).unwrap_err().to_string();
+        assert!(err.contains("was signed, but its PE image is inconsistent: 8 bytes follow"), "{}", err);
+        fs::write(&path, b"# SIG # Begin signature block").unwrap();
+        validate(&path.display().to_string()).unwrap();
+    }
+}
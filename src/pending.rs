@@ -0,0 +1,112 @@
+//! `--defer-timestamp-on-failure`: the files signed without a timestamp because no timestamp authority answered,
+//! listed in the config directory until `trusted-signing-cli timestamp --pending` countersigns them
+
+use crate::{config, hash};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A file signed without a timestamp
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Absolute path of the file
+    pub path: String,
+    /// SHA-256 of the file once signed, a file changed since isn't timestamped
+    pub sha256: String,
+    /// RFC 3339 time it was signed
+    pub signed_at: String,
+}
+
+/// Where the list is kept
+fn path() -> PathBuf {
+    config::dir().join("pending-timestamps.json")
+}
+
+/// List `entry`, in place of an earlier entry of the same file
+fn add_to(list: &Path, entry: Entry) -> Result<(), String> {
+    config::update_list(list, "the pending timestamps", |entries: &mut Vec<Entry>| {
+        entries.retain(|listed| listed.path != entry.path);
+        entries.push(entry);
+    })
+}
+
+/// Forget the entries of `paths`, returns how many there were
+fn remove_from(list: &Path, paths: &[String]) -> Result<usize, String> {
+    config::update_list(list, "the pending timestamps", |entries: &mut Vec<Entry>| {
+        let before = entries.len();
+        entries.retain(|entry| !paths.contains(&entry.path));
+        before - entries.len()
+    })
+}
+
+/// The entries of `list`, none when it doesn't exist
+fn read(list: &Path) -> Vec<Entry> {
+    fs::read_to_string(list).ok().and_then(|content| serde_json::from_str(&content).ok()).unwrap_or_default()
+}
+
+/// The path `file` is listed under, absolute so `timestamp --pending` finds it from anywhere
+pub fn listed_path(file: &str) -> String {
+    std::path::absolute(file).unwrap_or_else(|_| PathBuf::from(file)).display().to_string()
+}
+
+/// List a file signed without a timestamp
+pub fn add(entry: Entry) -> Result<(), String> {
+    add_to(&path(), entry)
+}
+
+/// Forget the files of `paths`, once they got a timestamp
+pub fn remove(paths: &[String]) -> Result<usize, String> {
+    match paths.is_empty() {
+        true => Ok(0),
+        false => remove_from(&path(), paths),
+    }
+}
+
+/// The files waiting for a timestamp, oldest first
+pub fn all() -> Vec<Entry> {
+    read(&path())
+}
+
+/// Why the file of `entry` can't get its timestamp anymore: it is gone, or changed since it was signed
+pub fn changed(entry: &Entry) -> Option<String> {
+    match hash::sha256_file(&entry.path) {
+        Ok(sha256) if sha256.eq_ignore_ascii_case(&entry.sha256) => None,
+        Ok(sha256) => Some(format!(
+            "it changed since it was signed without a timestamp, its SHA-256 is {} instead of {}, sign it again",
+            sha256, entry.sha256
+        )),
+        Err(err) => Some(format!("it could not be read to check it is the file signed: {}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_until_timestamped() {
+        let temp = tempfile::tempdir().unwrap();
+        let list = temp.path().join("pending-timestamps.json");
+        let file = temp.path().join("app.exe");
+        fs::write(&file, b"signed").unwrap();
+        let entry = Entry {
+            path: file.display().to_string(),
+            sha256: hash::sha256_file(&file).unwrap(),
+            signed_at: "2026-10-14T09:00:00.000Z".to_string(),
+        };
+        assert!(read(&list).is_empty());
+        add_to(&list, Entry { sha256: "00".to_string(), ..entry.clone() }).unwrap();
+        add_to(&list, entry.clone()).unwrap();
+        assert_eq!(read(&list), std::slice::from_ref(&entry));
+        assert_eq!(changed(&entry), None);
+
+        fs::write(&file, b"signed again").unwrap();
+        assert!(changed(&entry).unwrap().starts_with("it changed since it was signed"));
+        fs::remove_file(&file).unwrap();
+        assert!(changed(&entry).unwrap().starts_with("it could not be read"));
+        assert_eq!(remove_from(&list, std::slice::from_ref(&entry.path)).unwrap(), 1);
+        assert!(read(&list).is_empty());
+    }
+}
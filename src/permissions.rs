@@ -0,0 +1,302 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The ACL of the config directory: full control for the current user and the system, nothing inherited from the
+/// parent, and inherited by everything created in it
+#[cfg_attr(not(windows), allow(dead_code))]
+fn private_sddl(user_sid: &str) -> String {
+    format!("D:P(A;OICI;FA;;;{})(A;OICI;FA;;;SY)", user_sid)
+}
+
+/// Accounts that may write the signing client without it being a problem, besides the current user: SYSTEM,
+/// Administrators and TrustedInstaller, all of which could replace it anyway
+#[cfg_attr(not(windows), allow(dead_code))]
+const TRUSTED_SIDS: [&str; 3] =
+    ["S-1-5-18", "S-1-5-32-544", "S-1-5-80-956008885-3418522649-1831038044-1853292631-2271478464"];
+
+/// Rights that let an account replace a file or what is in a directory: write and append data, delete a child,
+/// delete, write the DACL or owner, generic write and generic all
+#[cfg_attr(not(windows), allow(dead_code))]
+const WRITE_RIGHTS: u32 = 0x2 | 0x4 | 0x40 | 0x1_0000 | 0x4_0000 | 0x8_0000 | 0x4000_0000 | 0x1000_0000;
+
+/// The paths between `dir` and `file`, both included, the ones whose permissions decide who can replace `file`
+pub fn chain(dir: &Path, file: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> =
+        file.ancestors().take_while(|path| path.starts_with(dir)).map(Path::to_path_buf).collect();
+    paths.reverse();
+    paths
+}
+
+/// Create `dir` and its missing parents so only the current user can read or write it, on Windows with an ACL
+/// granting only the user and the system access, elsewhere with mode 0700
+#[cfg(not(windows))]
+pub fn create_private_dir(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|err| format!("'{}' could not be created: {}", dir.display(), err))
+}
+
+/// Why other users could write the existing `paths`, nothing when they can't
+#[cfg(not(windows))]
+pub fn insecure(paths: &[PathBuf]) -> Vec<String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    paths
+        .iter()
+        .filter_map(|path| {
+            let mode = fs::symlink_metadata(path).ok()?.permissions().mode();
+            (mode & 0o022 != 0)
+                .then(|| format!("'{}' is writable by other users (mode {:o})", path.display(), mode & 0o777))
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{ffi::c_void, path::Path, ptr};
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, GetLastError, LocalFree, HANDLE},
+        Security::{
+            Authorization::ConvertSidToStringSidW,
+            GetTokenInformation, TokenUser, PSID, TOKEN_QUERY, TOKEN_USER,
+        },
+        System::Threading::{GetCurrentProcess, OpenProcessToken},
+    };
+
+    /// A path as a null terminated wide string
+    pub fn wide(path: &Path) -> Vec<u16> {
+        use std::os::windows::ffi::OsStrExt;
+        path.as_os_str().encode_wide().chain([0]).collect()
+    }
+
+    /// The text form of a SID, like `S-1-5-18`
+    pub fn sid_string(sid: PSID) -> Option<String> {
+        let mut text = ptr::null_mut();
+        // SAFETY: `sid` is a valid SID, `text` receives a buffer freed below
+        if unsafe { ConvertSidToStringSidW(sid, &mut text) } == 0 {
+            return None;
+        }
+        // SAFETY: the buffer is a null terminated wide string from ConvertSidToStringSidW
+        let string = unsafe {
+            let length = (0..).take_while(|&index| *text.add(index) != 0).count();
+            String::from_utf16_lossy(std::slice::from_raw_parts(text, length))
+        };
+        // SAFETY: the buffer came from ConvertSidToStringSidW and isn't used after this
+        unsafe { LocalFree(text as *mut c_void) };
+        Some(string)
+    }
+
+    /// The SID of the user this process runs as
+    pub fn current_user() -> Result<String, String> {
+        let mut token: HANDLE = ptr::null_mut();
+        // SAFETY: the pseudo handle of the current process needs no closing, `token` is closed below
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+            // SAFETY: plain query of the calling thread
+            return Err(format!("the process token could not be opened, error: {}", unsafe { GetLastError() }));
+        }
+        let mut size = 0u32;
+        // SAFETY: asking for the size only, the call fails with the size needed
+        unsafe { GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut size) };
+        // u64 for the alignment of the pointers in TOKEN_USER
+        let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+        // SAFETY: `buffer` has at least `size` bytes
+        let read = unsafe { GetTokenInformation(token, TokenUser, buffer.as_mut_ptr().cast(), size, &mut size) };
+        // SAFETY: `token` came from OpenProcessToken and isn't used after this
+        unsafe { CloseHandle(token) };
+        if read == 0 {
+            // SAFETY: plain query of the calling thread
+            return Err(format!("the user of the process could not be read, error: {}", unsafe { GetLastError() }));
+        }
+        // SAFETY: GetTokenInformation wrote a TOKEN_USER to the start of `buffer`
+        let user = unsafe { &*(buffer.as_ptr() as *const TOKEN_USER) };
+        sid_string(user.User.Sid).ok_or_else(|| "the SID of the user could not be read".to_string())
+    }
+}
+
+/// A security descriptor with the ACL of [`private_sddl`] for the current user, freed when dropped
+#[cfg(windows)]
+pub struct PrivateDescriptor(windows_sys::Win32::Security::PSECURITY_DESCRIPTOR);
+
+#[cfg(windows)]
+impl PrivateDescriptor {
+    pub fn new() -> Result<Self, String> {
+        use windows_sys::Win32::{
+            Foundation::GetLastError,
+            Security::Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        };
+
+        let sddl: Vec<u16> = private_sddl(&windows::current_user()?).encode_utf16().chain([0]).collect();
+        let mut descriptor = std::ptr::null_mut();
+        // SAFETY: `sddl` is a null terminated wide string, `descriptor` receives a buffer freed on drop
+        let converted = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            )
+        };
+        match converted {
+            // SAFETY: plain query of the calling thread
+            0 => Err(format!("the private ACL could not be built, error: {}", unsafe { GetLastError() })),
+            _ => Ok(PrivateDescriptor(descriptor)),
+        }
+    }
+
+    /// Attributes for creating an object with this descriptor, valid while it lives
+    pub fn attributes(&self) -> windows_sys::Win32::Security::SECURITY_ATTRIBUTES {
+        windows_sys::Win32::Security::SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<windows_sys::Win32::Security::SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.0,
+            bInheritHandle: 0,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PrivateDescriptor {
+    fn drop(&mut self) {
+        // SAFETY: the descriptor came from ConvertStringSecurityDescriptorToSecurityDescriptorW and isn't used after
+        unsafe { windows_sys::Win32::Foundation::LocalFree(self.0) };
+    }
+}
+
+/// Create `dir` and its missing parents so only the current user can read or write it, on Windows with an ACL
+/// granting only the user and the system access, elsewhere with mode 0700
+#[cfg(windows)]
+pub fn create_private_dir(dir: &Path) -> Result<(), String> {
+    use windows_sys::Win32::{
+        Foundation::{GetLastError, ERROR_ALREADY_EXISTS},
+        Storage::FileSystem::CreateDirectoryW,
+    };
+
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("'{}' could not be created: {}", parent.display(), err))?;
+    }
+    let descriptor =
+        PrivateDescriptor::new().map_err(|err| format!("'{}' could not be created: {}", dir.display(), err))?;
+    let attributes = descriptor.attributes();
+    let path = windows::wide(dir);
+    // SAFETY: `path` is a null terminated wide string and `attributes` points to a valid security descriptor
+    let created = unsafe { CreateDirectoryW(path.as_ptr(), &attributes) };
+    // SAFETY: plain query of the calling thread, before anything else can change it
+    let error = unsafe { GetLastError() };
+    match (created, error) {
+        (0, ERROR_ALREADY_EXISTS) => Ok(()),
+        (0, error) => Err(format!("'{}' could not be created, error: {}", dir.display(), error)),
+        _ => Ok(()),
+    }
+}
+
+/// Why other users could write the existing `paths`, nothing when they can't: an owner or an ACE allowing write
+/// access for anyone but the current user, the system, Administrators or TrustedInstaller
+#[cfg(windows)]
+pub fn insecure(paths: &[PathBuf]) -> Vec<String> {
+    use windows_sys::Win32::{
+        Foundation::LocalFree,
+        Security::{
+            Authorization::{GetNamedSecurityInfoW, SE_FILE_OBJECT},
+            GetAce, ACCESS_ALLOWED_ACE, ACL, DACL_SECURITY_INFORMATION,
+            OWNER_SECURITY_INFORMATION, PSID,
+        },
+    };
+    /// ACCESS_ALLOWED_ACE_TYPE, and the INHERIT_ONLY_ACE flag of ACEs that only apply to children
+    const ACCESS_ALLOWED: u8 = 0;
+    const INHERIT_ONLY: u8 = 0x8;
+
+    let user = match windows::current_user() {
+        Ok(user) => user,
+        Err(err) => return vec![err],
+    };
+    let trusted = |sid: &str| sid == user || TRUSTED_SIDS.contains(&sid);
+    let mut problems = Vec::new();
+    for path in paths.iter().filter(|path| path.exists()) {
+        let name = windows::wide(path);
+        let (mut owner, mut dacl, mut descriptor): (PSID, *mut ACL, _) =
+            (std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+        // SAFETY: `name` is a null terminated wide string, the out pointers point into `descriptor`, freed below
+        let error = unsafe {
+            GetNamedSecurityInfoW(
+                name.as_ptr(),
+                SE_FILE_OBJECT,
+                OWNER_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION,
+                &mut owner,
+                std::ptr::null_mut(),
+                &mut dacl,
+                std::ptr::null_mut(),
+                &mut descriptor,
+            )
+        };
+        if error != 0 {
+            problems.push(format!("the ACL of '{}' could not be read, error: {}", path.display(), error));
+            continue;
+        }
+        if let Some(owner) = windows::sid_string(owner).filter(|owner| !trusted(owner)) {
+            problems.push(format!("'{}' is owned by {}", path.display(), owner));
+        }
+        let mut writers: Vec<String> = Vec::new();
+        match dacl.is_null() {
+            true => writers.push("everyone (it has no ACL)".to_string()),
+            // SAFETY: `dacl` points to the ACL in `descriptor`, its ACEs are read through GetAce
+            false => unsafe {
+                for index in 0..(*dacl).AceCount as u32 {
+                    let mut ace = std::ptr::null_mut();
+                    if GetAce(dacl, index, &mut ace) == 0 {
+                        continue;
+                    }
+                    let ace = &*(ace as *const ACCESS_ALLOWED_ACE);
+                    let applies = ace.Header.AceType == ACCESS_ALLOWED && ace.Header.AceFlags & INHERIT_ONLY == 0;
+                    if !applies || ace.Mask & WRITE_RIGHTS == 0 {
+                        continue;
+                    }
+                    let sid = &ace.SidStart as *const u32 as PSID;
+                    match windows::sid_string(sid) {
+                        Some(sid) if trusted(&sid) => (),
+                        Some(sid) => writers.push(sid),
+                        None => writers.push("an unreadable SID".to_string()),
+                    }
+                }
+            },
+        }
+        if !writers.is_empty() {
+            problems.push(format!("'{}' is writable by {}", path.display(), writers.join(", ")));
+        }
+        // SAFETY: the descriptor came from GetNamedSecurityInfoW and isn't used after this
+        unsafe { LocalFree(descriptor) };
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_directory() {
+        let sddl = private_sddl("S-1-5-21-1-2-3-1001");
+        // protected, so nothing is inherited from the home directory, and inherited by the signing client
+        assert!(sddl.starts_with("D:P(A;OICI;FA;;;S-1-5-21-1-2-3-1001)"));
+        assert!(!sddl.contains(";WD)") && !sddl.contains(";BU)") && !sddl.contains(";AU)"));
+
+        let home = tempfile::tempdir().unwrap();
+        let dir = home.path().join(".trusted-signing-cli");
+        create_private_dir(&dir).unwrap();
+        create_private_dir(&dir).unwrap();
+        let dlib = dir.join("lib").join("Azure.CodeSigning.Dlib.dll");
+        assert_eq!(chain(&dir, &dlib), [dir.clone(), dir.join("lib"), dlib.clone()]);
+        assert_eq!(insecure(&chain(&dir, &dlib)), Vec::<String>::new());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+            assert!(insecure(&chain(&dir, &dir))[0].contains("writable by other users (mode 777)"));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Pinning the identity behind a certificate profile, so a change of the subject or of the CAs issuing it is
+//! noticed. Trusted Signing issues a new leaf every few days, the thumbprint is recorded but not compared
+
+use crate::certificate::{self, SigningCertificate};
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// What a signed file with another identity than pinned comes to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PinMode {
+    /// The file fails
+    #[default]
+    Fail,
+    /// The file is signed, with a warning
+    Warn,
+}
+
+/// The identity a file was signed with, what a pin file holds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub subject: String,
+    pub issuer: String,
+    /// Subjects of the CAs above the issuer, as far as the signature includes them
+    #[serde(default)]
+    pub chain: Vec<String>,
+    /// The leaf when the pin was recorded, not compared
+    #[serde(default)]
+    pub thumbprint: String,
+    /// RFC 3339, UTC
+    #[serde(default)]
+    pub pinned_at: String,
+}
+
+impl Identity {
+    /// The identity of a certificate chain, the signing certificate first
+    pub fn of_chain(chain: &[SigningCertificate]) -> Option<Identity> {
+        let leaf = chain.first()?;
+        Some(Identity {
+            subject: leaf.subject.clone(),
+            issuer: leaf.issuer.clone(),
+            chain: chain.iter().skip(2).map(|certificate| certificate.subject.clone()).collect(),
+            thumbprint: leaf.thumbprint.clone(),
+            pinned_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        })
+    }
+
+    /// What changed from `pinned` to this identity, like `subject 'CN=A' is now 'CN=B'`
+    fn changes(&self, pinned: &Identity) -> Vec<String> {
+        let mut changes = Vec::new();
+        if !certificate::same_name(&pinned.subject, &self.subject) {
+            changes.push(format!("subject '{}' is now '{}'", pinned.subject, self.subject));
+        }
+        if !certificate::same_name(&pinned.issuer, &self.issuer) {
+            changes.push(format!("issuer '{}' is now '{}'", pinned.issuer, self.issuer));
+        }
+        let same_chain = pinned.chain.len() == self.chain.len()
+            && pinned.chain.iter().zip(&self.chain).all(|(one, other)| certificate::same_name(one, other));
+        if !same_chain {
+            changes.push(format!("chain [{}] is now [{}]", pinned.chain.join("; "), self.chain.join("; ")));
+        }
+        changes
+    }
+}
+
+/// Check `--pin-subject`: a distinguished name like `CN=Contoso, O=Contoso, C=US`
+pub fn parse_subject(value: &str) -> Result<String, String> {
+    certificate::parse_subject(&format!("dn:{}", value)).map(|_| value.trim().to_string())
+}
+
+/// The identity signed files are held to, by --pin-subject and --pin-file
+#[derive(Debug, Clone, Default)]
+pub struct Pin {
+    pub subject: Option<String>,
+    pub file: Option<PathBuf>,
+    pub mode: PinMode,
+    /// Record the identity observed in the pin file when it changed, instead of failing
+    pub update: bool,
+    /// What the pin file held, `None` until an identity is pinned
+    pinned: Option<Identity>,
+}
+
+impl Pin {
+    /// Read the pin file, a missing one is written after the first file signed
+    pub fn new(subject: Option<String>, file: Option<PathBuf>, mode: PinMode, update: bool) -> Result<Pin, String> {
+        let pinned = match &file {
+            Some(file) if file.exists() => {
+                let content = fs::read_to_string(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+                let identity = serde_json::from_str(&content)
+                    .map_err(|err| format!("{} is not a pin file: {}", file.display(), err))?;
+                Some(identity)
+            }
+            _ => None,
+        };
+        Ok(Pin { subject, file, mode, update, pinned })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subject.is_none() && self.file.is_none()
+    }
+
+    fn write(&mut self, identity: &Identity) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(identity).map_err(|err| err.to_string())?;
+        let failed = |err: std::io::Error| format!("the pin could not be written to {}: {}", file.display(), err);
+        fs::write(file, json + "\n").map_err(failed)?;
+        self.pinned = Some(identity.clone());
+        Ok(())
+    }
+
+    /// Check the identity `path` was signed with, pinning it when nothing is pinned yet. A mismatch is an error,
+    /// or a warning with --pin-mode warn and when --update-pin records it
+    pub fn check(&mut self, path: &str, observed: &Identity) -> Result<Option<String>, String> {
+        let other_subject = |subject: &&String| !certificate::same_name(subject, &observed.subject);
+        if let Some(subject) = self.subject.as_ref().filter(other_subject) {
+            let message =
+                format!("'{}' was signed by '{}', not the --pin-subject '{}'", path, observed.subject, subject);
+            return match self.mode {
+                PinMode::Fail => Err(message),
+                PinMode::Warn => Ok(Some(message)),
+            };
+        }
+        let Some(pinned) = &self.pinned else {
+            self.write(observed)?;
+            return Ok(None);
+        };
+        let changes = observed.changes(pinned);
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        let file = self.file.as_ref().map(|file| file.display().to_string()).unwrap_or_default();
+        let changes = changes.join(", ");
+        if self.update {
+            self.write(observed)?;
+            return Ok(Some(format!("the identity pinned in {} was updated: {}", file, changes)));
+        }
+        let message = format!(
+            "'{}' was signed by another identity than pinned in {}: {}. Pass --update-pin if the change is planned",
+            path, file, changes
+        );
+        match self.mode {
+            PinMode::Fail => Err(message),
+            PinMode::Warn => Ok(Some(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_and_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pin.json");
+        let identity = Identity {
+            subject: "CN=Fabrikam, O=Fabrikam, C=US".to_string(),
+            issuer: "CN=Microsoft ID Verified CS EOC CA 01".to_string(),
+            chain: vec!["CN=Microsoft ID Verified Code Signing PCA 2021".to_string()],
+            thumbprint: "3F9001EA83C560D712C24CF213C3D312CB3BFF51".to_string(),
+            pinned_at: "2026-10-14T10:00:00Z".to_string(),
+        };
+        let mut pin = Pin::new(None, Some(file.clone()), PinMode::Fail, false).unwrap();
+        assert_eq!(pin.check("a.exe", &identity), Ok(None));
+        let pinned: Identity = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(pinned, identity);
+
+        // a new leaf of the same identity
+        let mut pin = Pin::new(None, Some(file.clone()), PinMode::Fail, false).unwrap();
+        let subject = "C=US, O=Fabrikam, CN=Fabrikam".to_string();
+        let rotated = Identity { thumbprint: "0".repeat(40), subject, ..identity };
+        assert_eq!(pin.check("a.exe", &rotated), Ok(None));
+        let other = Identity { subject: "CN=Contoso".to_string(), ..rotated };
+        let err = pin.check("a.exe", &other).unwrap_err();
+        assert!(err.contains("subject 'CN=Fabrikam, O=Fabrikam, C=US' is now 'CN=Contoso'"), "{}", err);
+        assert!(!err.contains("issuer"));
+
+        pin.mode = PinMode::Warn;
+        assert!(pin.check("a.exe", &other).unwrap().unwrap().ends_with("Pass --update-pin if the change is planned"));
+        pin.update = true;
+        assert!(pin.check("a.exe", &other).unwrap().unwrap().starts_with("the identity pinned in"));
+        assert_eq!(Pin::new(None, Some(file.clone()), PinMode::Fail, false).unwrap().check("a.exe", &other), Ok(None));
+
+        let mut pin = Pin::new(Some(parse_subject("CN=Fabrikam").unwrap()), None, PinMode::Fail, false).unwrap();
+        assert!(pin.check("a.exe", &other).unwrap_err().ends_with("not the --pin-subject 'CN=Fabrikam'"));
+        assert!(parse_subject("Fabrikam").is_err());
+        fs::write(&file, "not json").unwrap();
+        assert!(Pin::new(None, Some(file), PinMode::Fail, false).is_err());
+    }
+}
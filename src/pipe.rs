@@ -0,0 +1,77 @@
+//! `--pipe`, signing a file streamed through stdin and stdout
+
+use crate::{error::Error, input, scratch};
+use std::{
+    fs::File,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Check the extension of `--type` and that stdin and stdout are redirected, before anything is read
+pub fn check(kind: &str, extensions: &[String]) -> Result<String, Error> {
+    let extension = kind.trim_start_matches('.').to_ascii_lowercase();
+    if !input::is_supported(&format!("input.{}", extension), extensions) {
+        Err(Error::Usage(format!("--type {} is not a file type signtool can sign", kind)))?;
+    }
+    if io::stdin().is_terminal() {
+        Err(Error::Usage(
+            "--pipe signs the file piped into stdin, e.g. `type app.exe | trusted-signing-cli ...`".to_string(),
+        ))?;
+    }
+    // a terminal would mangle the binary, and the file would be lost
+    if io::stdout().is_terminal() {
+        Err(Error::Usage("--pipe writes the signed file to stdout, redirect it to a file".to_string()))?;
+    }
+    Ok(extension)
+}
+
+/// Write `input` to a file with `extension` in a new directory only this user can read, at most `limit` bytes.
+/// The directory and file are removed when it is dropped, whether signing worked or not
+pub fn spool(input: impl Read, extension: &str, limit: u64) -> Result<(scratch::Dir, PathBuf), Error> {
+    let dir = scratch::dir("trusted-signing-pipe", None)
+        .map_err(|err| format!("temp directory for stdin could not be created: {}", err))?;
+    let path = dir.path().join(format!("input.{}", extension));
+    let mut file = File::create(&path).map_err(|err| format!("temp file for stdin could not be created: {}", err))?;
+    // one byte over the limit is enough to tell
+    let size =
+        io::copy(&mut input.take(limit + 1), &mut file).map_err(|err| format!("stdin could not be read: {}", err))?;
+    if size == 0 {
+        Err(Error::Usage("stdin is empty, pipe the file to sign into it".to_string()))?;
+    }
+    if size > limit {
+        Err(Error::Usage(format!(
+            "stdin is larger than {} MiB, raise --max-input-size to sign it",
+            limit / 1024 / 1024
+        )))?;
+    }
+    file.sync_all().map_err(|err| format!("temp file for stdin could not be written: {}", err))?;
+    Ok((dir, path))
+}
+
+/// Copy the signed file to stdout, byte for byte
+pub fn emit(path: &Path) -> Result<(), Error> {
+    let mut file = File::open(path).map_err(|err| format!("signed file could not be read: {}", err))?;
+    let mut stdout = io::stdout().lock();
+    io::copy(&mut file, &mut stdout)
+        .and_then(|_| stdout.flush())
+        .map_err(|err| Error::Other(format!("signed file could not be written to stdout: {}", err)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spooled_input() {
+        let binary: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let (dir, path) = spool(binary.as_slice(), "exe", 4096).unwrap();
+        assert_eq!(path.file_name().unwrap(), "input.exe");
+        assert_eq!(std::fs::read(&path).unwrap(), binary);
+        drop(dir);
+        assert!(!path.exists());
+
+        assert!(spool(binary.as_slice(), "exe", 4095).is_err());
+        assert!(spool(io::empty(), "exe", 4096).is_err());
+    }
+}
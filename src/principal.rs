@@ -0,0 +1,143 @@
+//! `--sp-file` and `--sp-json-env`: the service principal as the one JSON document `az ad sp create-for-rbac
+//! --sdk-auth` prints, instead of its client id, secret and tenant given one by one
+
+use crate::{error::Error, logging, redact};
+use clap::ArgMatches;
+use serde_json::{Map, Value};
+use std::{fmt, fs, path::Path};
+
+/// Authority of the public Azure cloud, the only one Trusted Signing is in
+const PUBLIC_AUTHORITY: &str = "https://login.microsoftonline.com";
+
+/// A service principal read from an sdk-auth document
+#[derive(Clone, PartialEq, Eq)]
+pub struct ServicePrincipal {
+    pub client_id: String,
+    pub client_secret: String,
+    pub tenant_id: String,
+    /// The subscription `-a` is looked up in, when the document names one
+    pub subscription_id: Option<String>,
+    /// The argument it was read with, `sp_file` or `sp_json_env`
+    pub credential: &'static str,
+    /// Where it was read from, like `--sp-file 'sp.json'` or `--sp-json-env AZURE_CREDENTIALS`
+    pub source: String,
+}
+
+// the secret never ends up in a log
+impl fmt::Debug for ServicePrincipal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServicePrincipal")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"***")
+            .field("tenant_id", &self.tenant_id)
+            .field("subscription_id", &self.subscription_id)
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+/// The string `field` of `document`, an error naming it when it is missing, empty or not a string
+fn field(document: &Map<String, Value>, name: &str, source: &str) -> Result<String, Error> {
+    match document.get(name) {
+        Some(Value::String(value)) if !value.trim().is_empty() => Ok(value.trim().to_string()),
+        Some(Value::String(_)) => Err(format!("the service principal of {} has an empty `{}`", source, name)),
+        Some(_) => Err(format!("`{}` of the service principal of {} is not a string", name, source)),
+        None => Err(format!("the service principal of {} has no `{}`", source, name)),
+    }
+    .map_err(Error::Usage)
+}
+
+/// Read an sdk-auth document: `clientId`, `clientSecret` and `tenantId` are needed, `subscriptionId` is taken when
+/// there, and a cloud other than the public one is refused. The secret is masked and the ids redacted from then on
+pub fn parse(content: &str, credential: &'static str, source: &str) -> Result<ServicePrincipal, Error> {
+    let document: Value = serde_json::from_str(content)
+        .map_err(|err| Error::Usage(format!("the service principal of {} is not JSON: {}", source, err)))?;
+    let Value::Object(document) = document else {
+        Err(Error::Usage(format!("the service principal of {} is not a JSON object", source)))?
+    };
+    let client_secret = field(&document, "clientSecret", source)?;
+    let client_id = field(&document, "clientId", source)?;
+    let tenant_id = field(&document, "tenantId", source)?;
+    let subscription_id = match document.contains_key("subscriptionId") {
+        true => Some(field(&document, "subscriptionId", source)?),
+        false => None,
+    };
+    if document.contains_key("activeDirectoryEndpointUrl") {
+        let authority = field(&document, "activeDirectoryEndpointUrl", source)?;
+        if !authority.trim_end_matches('/').eq_ignore_ascii_case(PUBLIC_AUTHORITY) {
+            Err(Error::Usage(format!(
+                "the service principal of {} logs in at {}, Trusted Signing is only in the public Azure cloud of {}",
+                source, authority, PUBLIC_AUTHORITY
+            )))?
+        }
+    }
+    logging::register_secret(&client_secret);
+    redact::register("tenant", &tenant_id);
+    if let Some(subscription_id) = &subscription_id {
+        redact::register("subscription", subscription_id);
+    }
+    let source = source.to_string();
+    Ok(ServicePrincipal { client_id, client_secret, tenant_id, subscription_id, credential, source })
+}
+
+/// The service principal of `--sp-file` or `--sp-json-env`, `None` when neither is given
+pub fn read(file: Option<&Path>, env: Option<&str>) -> Result<Option<ServicePrincipal>, Error> {
+    match (file, env) {
+        (Some(file), _) => {
+            let source = format!("--sp-file '{}'", file.display());
+            let content = fs::read_to_string(file)
+                .map_err(|err| Error::Usage(format!("{} could not be read: {}", source, err)))?;
+            parse(&content, "sp_file", &source).map(Some)
+        }
+        (None, Some(var)) => {
+            let source = format!("--sp-json-env {}", var);
+            let content = std::env::var(var).map_err(|err| Error::Usage(format!("{}: {}", source, err)))?;
+            parse(&content, "sp_json_env", &source).map(Some)
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// The service principal of the sign arguments in `sign`
+pub fn from_matches(sign: &ArgMatches) -> Result<Option<ServicePrincipal>, Error> {
+    let file = sign.try_get_one::<std::path::PathBuf>("sp_file").ok().flatten();
+    let env = sign.try_get_one::<String>("sp_json_env").ok().flatten();
+    read(file.map(|file| file.as_path()), env.map(String::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sdk_auth_documents() {
+        let document = r#"{
+            "clientId": "11111111-2222-3333-4444-555555555555",
+            "clientSecret": "s3cr3t-from-sdk-auth",
+            "subscriptionId": "66666666-7777-8888-9999-000000000000",
+            "tenantId": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+            "activeDirectoryEndpointUrl": "https://login.microsoftonline.com",
+            "resourceManagerEndpointUrl": "https://management.azure.com/"
+        }"#;
+        let principal = parse(document, "sp_file", "--sp-file 'sp.json'").unwrap();
+        assert_eq!(principal.client_id, "11111111-2222-3333-4444-555555555555");
+        assert_eq!(principal.subscription_id.as_deref(), Some("66666666-7777-8888-9999-000000000000"));
+        assert!(!format!("{:?}", principal).contains("s3cr3t"));
+        assert!(!logging::mask("secret s3cr3t-from-sdk-auth").contains("s3cr3t"));
+
+        let error = |document: &str| parse(document, "sp_file", "--sp-file 'sp.json'").unwrap_err().to_string();
+        assert_eq!(
+            error(r#"{ "clientId": "id", "clientSecret": "secret" }"#),
+            "the service principal of --sp-file 'sp.json' has no `tenantId`"
+        );
+        let blank = r#"{ "clientId": "id", "clientSecret": " ", "tenantId": "t" }"#;
+        assert!(error(blank).ends_with("has an empty `clientSecret`"));
+        assert!(error(r#"{ "clientId": 3, "clientSecret": "s", "tenantId": "t" }"#).starts_with("`clientId` of"));
+        assert!(error("[]").ends_with("is not a JSON object"));
+        let china = r#"{ "clientId": "id", "clientSecret": "s", "tenantId": "t",
+                         "activeDirectoryEndpointUrl": "https://login.chinacloudapi.cn" }"#;
+        assert!(error(china).contains("only in the public Azure cloud"));
+        assert_eq!(read(None, None).unwrap(), None);
+        assert!(read(Some(Path::new("missing-sp.json")), None).unwrap_err().to_string().contains("could not be read"));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{
+    azure::{self, CertificateProfile, CodeSigningAccount},
+    error::Error,
+    whoami,
+};
+use clap::ArgMatches;
+
+/// Log in like `sign` does and list the certificate profiles of the account of `-a`
+pub fn run(sign: &ArgMatches) -> Result<Vec<CertificateProfile>, Error> {
+    let Some(account) = sign.try_get_one::<String>("account").ok().flatten() else {
+        Err(Error::Usage("no code signing account, pass -a (or AZURE_TRUSTED_SIGNING_ACCOUNT)".to_string()))?
+    };
+    let login = whoami::log_in(sign)?;
+    azure::certificate_profiles(&login.cli_path, account)
+}
+
+/// Log in like `sign` does and list the code signing accounts in the subscriptions of `--subscription`, or in
+/// every one the principal can read
+pub fn accounts(sign: &ArgMatches) -> Result<Vec<CodeSigningAccount>, Error> {
+    let subscriptions: Vec<String> =
+        sign.try_get_many::<String>("subscription").ok().flatten().into_iter().flatten().cloned().collect();
+    let login = whoami::log_in(sign)?;
+    azure::code_signing_accounts(&login.cli_path, &subscriptions)
+}
+
+/// Aligned table of the accounts, the name and endpoint of each as `-a` and `-e` take them
+pub fn accounts_table(accounts: &[CodeSigningAccount]) -> String {
+    if accounts.is_empty() {
+        return "no code signing account is visible to this principal, it needs the Reader role on the account or \
+                its resource group\n"
+            .to_string();
+    }
+    let widths = [
+        accounts.iter().map(|account| account.name.len()).max().unwrap_or(0),
+        accounts.iter().map(|account| account.endpoint.as_deref().map_or(0, str::len)).max().unwrap_or(0),
+    ];
+    let mut table = String::from("Code signing accounts\n");
+    for account in accounts {
+        let endpoint = match &account.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("unknown endpoint for {}", account.location),
+        };
+        table.push_str(&format!(
+            "  -a {:<name$}  -e {:<endpoint_width$}  resource group {} in subscription {}\n",
+            account.name,
+            endpoint,
+            account.resource_group,
+            account.subscription_id,
+            name = widths[0],
+            endpoint_width = widths[1]
+        ));
+    }
+    table
+}
+
+/// Aligned table of the profiles
+pub fn table(account: &str, profiles: &[CertificateProfile]) -> String {
+    if profiles.is_empty() {
+        return format!("account '{}' has no certificate profiles\n", account);
+    }
+    let width = profiles.iter().map(|profile| profile.name.len()).max().unwrap_or(0);
+    let type_width = profiles.iter().map(|profile| profile.profile_type.len()).max().unwrap_or(0);
+    let mut table = format!("Certificate profiles of {}\n", account);
+    for profile in profiles {
+        let validity = match (&profile.valid_from, &profile.valid_to) {
+            (Some(from), Some(to)) => format!("  valid {} to {}", from, to),
+            _ => "  no active certificate".to_string(),
+        };
+        table.push_str(&format!(
+            "  {:<width$}  {:<type_width$}  {}{}\n",
+            profile.name, profile.profile_type, profile.status, validity
+        ));
+        if let Some(subject) = &profile.subject {
+            table.push_str(&format!("  {:<width$}  {}\n", "", subject));
+        }
+    }
+    table
+}
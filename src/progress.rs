@@ -0,0 +1,80 @@
+use crate::ci;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{io::IsTerminal, sync::Mutex, time::Duration};
+
+/// Runs with fewer files than this don't get a progress bar
+const MIN_FILES: usize = 5;
+
+/// The bar currently drawn on stderr, so log lines can be printed around it
+static ACTIVE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Run `f` with the active progress bar (if any) hidden, used for every write to stderr
+pub fn suspend<F: FnOnce() -> R, R>(f: F) -> R {
+    let bar = ACTIVE.lock().unwrap().clone();
+    match bar {
+        Some(bar) => bar.suspend(f),
+        None => f(),
+    }
+}
+
+/// Progress of a multi-file run, tracked by completions rather than loop position
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    completed: u64,
+    failed: u64,
+}
+
+impl Progress {
+    /// Only draws when enough files are queued, stderr is a TTY and we are not running in CI
+    pub fn new(total: usize, disabled: bool) -> Self {
+        let enabled = !disabled
+            && total >= MIN_FILES
+            && std::io::stderr().is_terminal()
+            && std::env::var_os("CI").is_none()
+            && !ci::strict();
+
+        let bar = enabled.then(|| {
+            let bar = ProgressBar::new(total as u64).with_style(
+                ProgressStyle::with_template("{elapsed_precise} [{bar:30}] {pos}/{len} eta {eta} {msg}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar.enable_steady_tick(Duration::from_millis(500));
+            *ACTIVE.lock().unwrap() = Some(bar.clone());
+            bar
+        });
+
+        Progress {
+            bar,
+            completed: 0,
+            failed: 0,
+        }
+    }
+
+    /// A file started signing
+    pub fn start(&self, file: &str) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(format!("{} (failed: {})", file, self.failed));
+        }
+    }
+
+    /// A file finished signing, successfully or not
+    pub fn complete(&mut self, success: bool) {
+        self.completed += 1;
+        if !success {
+            self.failed += 1;
+        }
+        if let Some(bar) = &self.bar {
+            bar.set_position(self.completed);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+            *ACTIVE.lock().unwrap() = None;
+        }
+    }
+}
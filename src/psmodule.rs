@@ -0,0 +1,178 @@
+use crate::{authenticode, error::Error, unsign};
+use std::path::{Path, PathBuf};
+
+/// The script files of a PowerShell module that get signed
+const SCRIPTS: [&str; 3] = ["ps1", "psm1", "psd1"];
+
+/// What a script is encoded in, by its byte order mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    /// UTF-8 without a BOM, or ASCII
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    fn of(bytes: &[u8]) -> Self {
+        match bytes {
+            [0xef, 0xbb, 0xbf, ..] => Encoding::Utf8Bom,
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            _ => Encoding::Utf8,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8 without BOM",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16Be => "UTF-16 BE",
+        }
+    }
+
+    /// The text of `bytes`, the BOM of UTF-8 kept as its character so offsets match the bytes
+    fn decode(self, bytes: &[u8]) -> Option<String> {
+        let units = |swap: fn([u8; 2]) -> u16| -> Vec<u16> {
+            bytes.chunks_exact(2).map(|unit| swap([unit[0], unit[1]])).collect()
+        };
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le => String::from_utf16(&units(u16::from_le_bytes)).ok(),
+            Encoding::Utf16Be => String::from_utf16(&units(u16::from_be_bytes)).ok(),
+        }
+    }
+
+    fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => text.as_bytes().to_vec(),
+            Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+}
+
+/// Whether `path` is one of the scripts of a module that get signed
+pub fn is_script(path: &str) -> bool {
+    let extension = Path::new(path).extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    SCRIPTS.contains(&extension.as_str())
+}
+
+/// The scripts of the module in `dir`, sorted
+pub fn files(dir: &Path) -> Result<Vec<String>, Error> {
+    if !dir.is_dir() {
+        Err(Error::Usage(format!("PowerShell module '{}' is not a directory", dir.display())))?;
+    }
+    let pattern = Path::new(&glob::Pattern::escape(&dir.to_string_lossy())).join("**").join("*");
+    let mut scripts: Vec<String> = glob::glob(&pattern.to_string_lossy())
+        .map_err(|err| err.to_string())?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .map(|path| path.display().to_string())
+        .filter(|path| is_script(path))
+        .collect();
+    scripts.sort();
+    if scripts.is_empty() {
+        Err(Error::Usage(format!("PowerShell module '{}' has no .ps1, .psm1 or .psd1 files", dir.display())))?;
+    }
+    Ok(scripts)
+}
+
+/// The catalog of the module in `dir`, named after the module and next to its manifest like New-FileCatalog's
+pub fn catalog(dir: &Path) -> PathBuf {
+    let absolute = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let name = absolute.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or("module".to_string());
+    dir.join(format!("{}.cat", name))
+}
+
+/// Check a script signtool signed against its content before: the same encoding, every byte up to the signature
+/// block unchanged, an earlier block aside, and a block that holds a signature. These are what make
+/// Get-AuthenticodeSignature report a script as HashMismatch or UnknownError
+pub fn check(path: &str, before: &[u8], after: &[u8]) -> Result<(), String> {
+    let (begin, end) = unsign::script_markers(path).ok_or("it is not a script")?;
+    let (encoding, signed) = (Encoding::of(before), Encoding::of(after));
+    if encoding != signed {
+        Err(format!("its encoding changed from {} to {} while signing", encoding.name(), signed.name()))?;
+    }
+    let text = encoding.decode(before).ok_or_else(|| format!("it isn't valid {}", encoding.name()))?;
+    // signing again replaces the earlier block, which starts on a line of its own
+    let kept = match text.find(begin) {
+        Some(start) => text[..start].trim_end_matches(['\r', '\n']),
+        None => text.as_str(),
+    };
+    if !after.starts_with(&encoding.encode(kept)) {
+        Err("its content changed while signing, beyond the signature block appended".to_string())?;
+    }
+    let signed = encoding.decode(after).ok_or_else(|| format!("it isn't valid {} once signed", encoding.name()))?;
+    let pkcs7 = unsign::script_signature(&signed[kept.len()..], begin, end)
+        .ok_or("its signature block is missing or not base64 between its begin and end lines")?;
+    match authenticode::signing_certificate(&pkcs7) {
+        Some(_) => Ok(()),
+        None => Err("its signature block holds no PKCS#7 signature with a signing certificate".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{der, signtool::Digest};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// A signature by a certificate reduced to what is read of it
+    fn pkcs7() -> Vec<u8> {
+        let name = der::sequence(&[&der::bmp_string("Contoso")]);
+        let tbs = der::sequence(&[
+            &der::tlv(der::context(0), &der::small_integer(2)),
+            &der::small_integer(1),
+            &der::sequence(&[]),
+            &name,
+            &der::sequence(&[]),
+            &name,
+        ]);
+        let content = der::sequence(&[&der::sequence(&[&der::oid(&[1, 3, 6, 1, 4, 1, 311, 2, 1, 15])])]);
+        let signable = authenticode::Signable::new(content, Digest::Sha256, None).unwrap();
+        signable.signed_data(&[der::sequence(&[&tbs])], &[0x5a; 256]).unwrap()
+    }
+
+    #[test]
+    fn scripts_keep_their_encoding() {
+        let temp = tempfile::tempdir().unwrap();
+        let module = temp.path().join("Tools");
+        std::fs::create_dir_all(module.join("Public")).unwrap();
+        for file in ["Tools.psd1", "Tools.psm1", "Public/Get-Thing.ps1", "Tools.dll", "en-US/about.txt"] {
+            let path = module.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let scripts = files(&module).unwrap();
+        assert_eq!(scripts.len(), 3);
+        assert!(scripts.iter().all(|script| is_script(script)));
+        assert_eq!(catalog(&module), module.join("Tools.cat"));
+
+        // a block around a PKCS#7 that isn't one, the check reads it as such
+        let block = |pkcs7: &[u8]| {
+            let encoded = STANDARD.encode(pkcs7);
+            format!("\r\n# SIG # Begin signature block\r\n# {}\r\n# SIG # End signature block\r\n", encoded)
+        };
+        let script = "\u{feff}Write-Output 'é'\r\n";
+        let unsigned = block(b"not a signature");
+        let after = format!("{}{}", script, unsigned);
+        let error = check("a.ps1", script.as_bytes(), after.as_bytes()).unwrap_err();
+        assert!(error.contains("no PKCS#7"), "{}", error);
+        let signed = format!("{}{}", script, block(&pkcs7()));
+        check("a.ps1", script.as_bytes(), signed.as_bytes()).unwrap();
+        // signing again replaces the block
+        check("a.psm1", signed.as_bytes(), signed.as_bytes()).unwrap();
+        let stripped = &after.as_bytes()[3..];
+        let error = check("a.ps1", script.as_bytes(), stripped).unwrap_err();
+        assert_eq!(error, "its encoding changed from UTF-8 with BOM to UTF-8 without BOM while signing");
+        let reencoded = format!("\u{feff}Write-Output 'e'\r\n{}", unsigned);
+        assert!(check("a.ps1", script.as_bytes(), reencoded.as_bytes()).unwrap_err().contains("content changed"));
+
+        let wide: Vec<u8> = [0xff, 0xfe].into_iter().chain(Encoding::Utf16Le.encode("dir\r\n")).collect();
+        assert_eq!(Encoding::of(&wide), Encoding::Utf16Le);
+        assert_eq!(Encoding::Utf16Le.decode(&wide).unwrap(), "\u{feff}dir\r\n");
+    }
+}
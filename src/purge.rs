@@ -0,0 +1,158 @@
+use crate::{azure, ci, cli::PurgeArgs, config, error::Error};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Something purge removes
+#[derive(Debug, PartialEq, Eq)]
+enum Item {
+    /// A file or directory in the config directory, with what it is
+    Path(PathBuf, &'static str),
+    /// The account the Azure CLI is logged in as, with --include-azure-session
+    AzureSession(azure::Account),
+}
+
+/// What an entry of the config directory is, by its name
+fn describe(name: &str, is_dir: bool) -> &'static str {
+    match name {
+        "config.toml" => "config file with the profiles",
+        "lib" => "Trusted Signing signing client",
+        "session" => "marker of the last azure cli login",
+        _ if name.starts_with("metadata-") && name.ends_with(".json") => "metadata kept by --keep-metadata",
+        // the temporary files of a run that was killed before it could remove them
+        _ if name.starts_with("metadata-") || name.starts_with(".tmp") => "leftover temporary file",
+        _ if name.to_ascii_lowercase().ends_with(".zip") => "download of the signing client",
+        _ if is_dir => "directory",
+        _ => "file",
+    }
+}
+
+/// The entries of the config directory, nothing when there is none
+fn entries(dir: &Path) -> Result<Vec<Item>, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => Err(format!("'{}' could not be read: {}", dir.display(), err))?,
+    };
+    let mut entries: Vec<fs::DirEntry> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.path());
+    let items = entries.iter().map(|entry| {
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        Item::Path(entry.path(), describe(&entry.file_name().to_string_lossy(), is_dir))
+    });
+    Ok(items.collect())
+}
+
+impl Item {
+    fn describe(&self) -> String {
+        match self {
+            Item::Path(path, what) => format!("{} ({})", path.display(), what),
+            Item::AzureSession(account) => {
+                format!("azure cli session of {} in tenant {}", account.user_name, account.tenant_id)
+            }
+        }
+    }
+
+    fn remove(&self, cli_path: &str) -> Result<(), String> {
+        match self {
+            // a link is removed, not what it points to
+            Item::Path(path, _) if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) => {
+                fs::remove_dir_all(path)
+            }
+            Item::Path(path, _) => fs::remove_file(path),
+            Item::AzureSession(account) => return azure::logout(cli_path, &account.user_name),
+        }
+        .map_err(|err| format!("'{}' could not be removed: {}", self.describe(), err))
+    }
+}
+
+/// List what a purge removes, ask unless --yes is given and remove it. The Azure CLI is only touched with
+/// --include-azure-session, its session is shared with everything else on the machine
+pub fn run(args: PurgeArgs) -> Result<(), Error> {
+    let dir = config::dir();
+    let mut items = entries(&dir)?;
+    match args.include_azure_session {
+        true => match azure::account(&args.azure_cli_path) {
+            Ok(account) => items.push(Item::AzureSession(account)),
+            Err(err) => debug!("azure cli is not logged in: {}", err),
+        },
+        false => {
+            if items.iter().any(|item| matches!(item, Item::Path(path, _) if path.ends_with("session"))) {
+                eprintln!("the azure cli session is kept, pass --include-azure-session to log it out too");
+            }
+        }
+    }
+    if items.is_empty() {
+        eprintln!("nothing to purge, '{}' is empty or doesn't exist", dir.display());
+        return Ok(());
+    }
+
+    eprintln!("purge removes:");
+    for item in &items {
+        eprintln!("  {}", item.describe());
+    }
+    if !args.yes {
+        if !io::stdin().is_terminal() {
+            Err(Error::Usage("stdin is not a terminal, pass --yes to purge without asking".to_string()))?;
+        }
+        if ci::strict() {
+            Err(ci::prompt_refused("pass --yes to purge without asking"))?;
+        }
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Remove all of it?")
+            .default(false)
+            .interact()
+            .map_err(|err| Error::Other(format!("prompt failed: {}", err)))?;
+        if !confirmed {
+            eprintln!("nothing was removed");
+            return Ok(());
+        }
+    }
+
+    // everything is tried, one entry that can't be removed (a signing client another run has open) doesn't keep
+    // the others
+    let failed: Vec<String> = items.iter().filter_map(|item| item.remove(&args.azure_cli_path).err()).collect();
+    if !failed.is_empty() {
+        Err(Error::Other(format!("purge was incomplete: {}", failed.join(", "))))?;
+    }
+    let _ = fs::remove_dir(&dir);
+    eprintln!("purged {} item(s)", items.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_dir_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("lib")).unwrap();
+        for name in ["session", "metadata-0123456789abcdef.json", "metadata-x1Yz2", "notes.txt"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let described: Vec<&str> = entries(dir.path())
+            .unwrap()
+            .iter()
+            .map(|item| match item {
+                Item::Path(_, what) => *what,
+                Item::AzureSession(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "Trusted Signing signing client",
+                "metadata kept by --keep-metadata",
+                "leftover temporary file",
+                "file",
+                "marker of the last azure cli login"
+            ]
+        );
+        assert!(entries(&dir.path().join("missing")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{azure, cli::QuotaCommand, config, error::Error, whoami};
+use chrono::{Datelike, TimeZone, Utc};
+use clap::ArgMatches;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The signatures of one account counted this month. Counted locally per successful signtool call, runs on other
+/// machines or with other tools aren't, so it is an estimate of what the service bills
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    pub endpoint: String,
+    pub account: String,
+    /// Calendar month in UTC, like 2026-10
+    pub month: String,
+    pub signatures: u64,
+}
+
+/// Where the counters are kept
+fn path() -> PathBuf {
+    config::dir().join("quota.json")
+}
+
+/// The month signatures count for now
+fn current_month() -> String {
+    chrono::Utc::now().format("%Y-%m").to_string()
+}
+
+/// Read the counters of `path`, change them and write them back with the file locked, so concurrent runs don't
+/// lose each other's counts
+fn update<T>(path: &Path, change: impl FnOnce(&mut Vec<Usage>) -> T) -> Result<T, String> {
+    config::update_list(path, "the quota counter", change)
+}
+
+/// Count one signature of `account` at `endpoint` in `month`, dropping the counters of earlier months
+fn count(counters: &mut Vec<Usage>, endpoint: &str, account: &str, month: &str) -> Usage {
+    counters.retain(|usage| usage.month == month);
+    let index = counters
+        .iter()
+        .position(|usage| usage.endpoint == endpoint && usage.account.eq_ignore_ascii_case(account))
+        .unwrap_or_else(|| {
+            let usage = Usage {
+                endpoint: endpoint.to_string(),
+                account: account.to_string(),
+                month: month.to_string(),
+                signatures: 0,
+            };
+            counters.push(usage);
+            counters.len() - 1
+        });
+    counters[index].signatures += 1;
+    counters[index].clone()
+}
+
+/// Count a successful signature of `account` at `endpoint` this month, returns the count so far
+pub fn record(endpoint: &str, account: &str) -> Result<Usage, String> {
+    let month = current_month();
+    update(&path(), |counters| count(counters, endpoint, account, &month))
+}
+
+/// The counters of this month, every account
+pub fn all() -> Vec<Usage> {
+    let month = current_month();
+    let counters: Vec<Usage> =
+        fs::read_to_string(path()).ok().and_then(|content| serde_json::from_str(&content).ok()).unwrap_or_default();
+    counters.into_iter().filter(|usage| usage.month == month).collect()
+}
+
+/// The signatures of `account` at `endpoint` counted this month
+pub fn usage(endpoint: &str, account: &str) -> Usage {
+    let found = all()
+        .into_iter()
+        .find(|usage| usage.endpoint == endpoint && usage.account.eq_ignore_ascii_case(account));
+    found.unwrap_or(Usage {
+        endpoint: endpoint.to_string(),
+        account: account.to_string(),
+        month: current_month(),
+        signatures: 0,
+    })
+}
+
+/// Remove the counters of `account`, or every counter, returns how many there were
+fn remove(counters: &mut Vec<Usage>, account: Option<&str>) -> usize {
+    let before = counters.len();
+    counters.retain(|usage| account.is_some_and(|account| !usage.account.eq_ignore_ascii_case(account)));
+    before - counters.len()
+}
+
+/// Forget the counts of `account`, or of every account, returns how many counters were removed
+pub fn reset(account: Option<&str>) -> Result<usize, String> {
+    update(&path(), |counters| remove(counters, account))
+}
+
+/// Signatures a Trusted Signing plan includes per month, the ones beyond are billed on top
+fn included(sku: &str) -> Option<u64> {
+    match sku.to_ascii_lowercase().as_str() {
+        "basic" => Some(5_000),
+        "premium" => Some(100_000),
+        _ => None,
+    }
+}
+
+/// The signatures of an account this month as the service counted them
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ServiceUsage {
+    pub account: String,
+    /// The plan of the account, like `Basic` or `Premium`
+    pub sku: Option<String>,
+    /// Calendar month in UTC, like 2026-10
+    pub month: String,
+    /// Signatures Azure Monitor counted as completed since the month began, its numbers can lag a few minutes
+    pub signatures: u64,
+    /// Signatures the plan includes per month, `None` for a plan this release doesn't know
+    pub included: Option<u64>,
+    /// What this machine counted for the account, see `quota show`
+    pub counted_locally: u64,
+}
+
+impl ServiceUsage {
+    /// The count against the plan, like `1234 of 5000 signatures (24.7%)`
+    pub fn line(&self) -> String {
+        match self.included {
+            Some(included) => format!(
+                "{} of {} signatures ({:.1}%)",
+                self.signatures,
+                included,
+                self.signatures as f64 * 100.0 / included as f64
+            ),
+            None => format!("{} signatures", self.signatures),
+        }
+    }
+
+    /// What `quota` prints
+    pub fn table(&self) -> String {
+        let plan = match (&self.sku, self.included) {
+            (Some(sku), Some(_)) => sku.clone(),
+            (Some(sku), None) => format!("{}, which this release doesn't know the included signatures of", sku),
+            (None, _) => "unknown".to_string(),
+        };
+        format!(
+            "Signatures of {} in {}\n  used            {}\n  plan            {}\n  counted here    {}\n",
+            self.account,
+            self.month,
+            self.line(),
+            plan,
+            self.counted_locally
+        )
+    }
+}
+
+/// Log in like `sign` does and ask Azure Monitor for the signatures the account of `-a` completed this month
+pub fn service(quota: &ArgMatches) -> Result<ServiceUsage, Error> {
+    let Some(account) = quota.try_get_one::<String>("account").ok().flatten() else {
+        Err(Error::Usage("no code signing account, pass -a (or AZURE_TRUSTED_SIGNING_ACCOUNT)".to_string()))?
+    };
+    let resource_id = quota.try_get_one::<String>("account_resource_id").ok().flatten();
+    let login = whoami::log_in(quota)?;
+    let plan = azure::account_plan(&login.cli_path, account, resource_id.map(String::as_str))?;
+    let now = Utc::now();
+    let start = Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0).single().unwrap_or(now);
+    let format = |time: chrono::DateTime<Utc>| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let signatures = azure::signatures_completed(&login.cli_path, &plan.id, &format(start), &format(now))?;
+    let endpoint = quota.try_get_one::<String>("endpoint").ok().flatten();
+    let counted_locally = all()
+        .iter()
+        .filter(|usage| usage.account.eq_ignore_ascii_case(account))
+        .filter(|usage| endpoint.is_none_or(|endpoint| usage.endpoint == *endpoint))
+        .map(|usage| usage.signatures)
+        .sum();
+    Ok(ServiceUsage {
+        account: account.clone(),
+        included: plan.sku.as_deref().and_then(included),
+        sku: plan.sku,
+        month: current_month(),
+        signatures,
+        counted_locally,
+    })
+}
+
+/// Print or reset the counters
+pub fn run(command: QuotaCommand) -> Result<(), Error> {
+    match command {
+        QuotaCommand::Show => {
+            let counters = all();
+            if counters.is_empty() {
+                eprintln!("no signatures were counted this month");
+            }
+            for usage in &counters {
+                println!("{:<40} {:<24} {:>8}", usage.endpoint, usage.account, usage.signatures);
+            }
+            eprintln!("counted by this machine only, an estimate: the service bills what every signer used");
+        }
+        QuotaCommand::Reset { account } => {
+            let removed = reset(account.as_deref()).map_err(Error::Other)?;
+            eprintln!("reset {} counter(s)", removed);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counted_per_account_and_month() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("quota.json");
+        let endpoint = "https://eus.codesigning.azure.net";
+        for _ in 0..2 {
+            update(&path, |counters| count(counters, endpoint, "acc", "2026-09")).unwrap();
+        }
+        let usage = update(&path, |counters| count(counters, endpoint, "ACC", "2026-09")).unwrap();
+        assert_eq!(usage.signatures, 3);
+        update(&path, |counters| count(counters, endpoint, "other", "2026-09")).unwrap();
+        // a new month starts from zero and forgets the last one
+        let usage = update(&path, |counters| count(counters, endpoint, "acc", "2026-10")).unwrap();
+        assert_eq!(usage.signatures, 1);
+        let counters: Vec<Usage> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(counters, [usage]);
+        update(&path, |counters| count(counters, endpoint, "other", "2026-10")).unwrap();
+        assert_eq!(update(&path, |counters| remove(counters, Some("Other"))).unwrap(), 1);
+        assert_eq!(update(&path, |counters| remove(counters, None)).unwrap(), 1);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(update(&path, |counters| count(counters, endpoint, "acc", "2026-10")).unwrap().signatures, 1);
+
+        let service = ServiceUsage {
+            account: "acc".to_string(),
+            sku: Some("Basic".to_string()),
+            month: "2026-10".to_string(),
+            signatures: 1234,
+            included: included("Basic"),
+            counted_locally: 2,
+        };
+        assert_eq!(service.line(), "1234 of 5000 signatures (24.7%)");
+        assert_eq!(ServiceUsage { included: included("Enterprise"), ..service }.line(), "1234 signatures");
+    }
+}
@@ -0,0 +1,130 @@
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+/// Whether identifiers are replaced, turned off with `--no-redact`
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Known identifiers, lowercase, with the placeholder replacing them
+static IDENTIFIERS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Length of a GUID in its `8-4-4-4-12` form
+const GUID_LEN: usize = 36;
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn is_guid(value: &str) -> bool {
+    value.len() == GUID_LEN
+        && value.char_indices().all(|(index, char)| match index {
+            8 | 13 | 18 | 23 => char == '-',
+            _ => char.is_ascii_hexdigit(),
+        })
+}
+
+/// Register a GUID of the given kind, it's printed as `<kind>:***<n>` from then on
+pub fn register(kind: &str, id: &str) {
+    if !is_guid(id) {
+        return;
+    }
+    let id = id.to_ascii_lowercase();
+    let mut identifiers = IDENTIFIERS.lock().unwrap();
+    if identifiers.iter().any(|(known, _)| *known == id) {
+        return;
+    }
+    let prefix = format!("{}:***", kind);
+    let number = identifiers.iter().filter(|(_, placeholder)| placeholder.starts_with(&prefix)).count() + 1;
+    identifiers.push((id, format!("{}{}", prefix, number)));
+}
+
+/// Register the identifiers found in a JSON response of az
+pub fn learn_json(text: &str) {
+    fn walk(value: &Value) {
+        match value {
+            Value::Array(values) => values.iter().for_each(walk),
+            Value::Object(object) => {
+                for (key, value) in object {
+                    match (key.as_str(), value) {
+                        ("tenantId" | "homeTenantId", Value::String(id)) => register("tenant", id),
+                        ("id" | "subscriptionId", Value::String(id)) => register("subscription", id),
+                        ("objectId" | "principalId", Value::String(id)) => register("object", id),
+                        (_, value) => walk(value),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if let Ok(value) = serde_json::from_str::<Value>(text) {
+        walk(&value);
+    }
+}
+
+/// Replace every registered identifier in `text`, in any letter case
+pub fn apply(text: &str) -> String {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let identifiers = IDENTIFIERS.lock().unwrap();
+    if identifiers.is_empty() {
+        return text.to_string();
+    }
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let placeholder = rest
+            .get(..GUID_LEN)
+            .filter(|candidate| is_guid(candidate))
+            .map(str::to_ascii_lowercase)
+            .and_then(|id| identifiers.iter().find(|(known, _)| *known == id))
+            .map(|(_, placeholder)| placeholder);
+        match placeholder {
+            Some(placeholder) => {
+                redacted.push_str(placeholder);
+                rest = &rest[GUID_LEN..];
+            }
+            None => {
+                let next = rest.chars().next().map_or(1, char::len_utf8);
+                redacted.push_str(&rest[..next]);
+                rest = &rest[next..];
+            }
+        }
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn az_output() {
+        let tenant = "0b5c7a3e-1111-4d2b-9a6f-7c1e2d3f4a5b";
+        register("tenant", &tenant.to_ascii_uppercase());
+        let account = r#"[
+          {
+            "cloudName": "AzureCloud",
+            "homeTenantId": "0b5c7a3e-1111-4d2b-9a6f-7c1e2d3f4a5b",
+            "id": "6e1f0d2c-2222-4b8a-8f3e-5d4c3b2a1f0e",
+            "isDefault": true,
+            "name": "Production",
+            "tenantId": "0b5c7a3e-1111-4d2b-9a6f-7c1e2d3f4a5b",
+            "user": { "name": "client", "type": "servicePrincipal" }
+          }
+        ]"#;
+        learn_json(account);
+
+        let redacted = apply(account);
+        assert!(!redacted.contains(tenant));
+        assert!(!redacted.contains("6e1f0d2c-2222-4b8a-8f3e-5d4c3b2a1f0e"));
+        assert!(redacted.contains(r#""tenantId": "tenant:***"#));
+        assert!(redacted.contains(r#""id": "subscription:***"#));
+        assert_eq!(apply("-t 0B5C7A3E-1111-4D2B-9A6F-7C1E2D3F4A5B ok"), apply(&format!("-t {} ok", tenant)));
+        assert_eq!(apply(&format!("ü{}", tenant)), format!("ü{}", apply(tenant)));
+    }
+}
@@ -0,0 +1,568 @@
+mod html;
+mod junit;
+mod markdown;
+
+use crate::{
+    build::Build,
+    certificate::SigningCertificate,
+    checksums,
+    clock, electron,
+    error::Error,
+    inspect::ObservedDigest,
+    metrics::Metrics,
+    quota, revocation,
+    route::Target,
+    signer::{Mechanism, Signature},
+};
+use clap::ValueEnum;
+use console::style;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Write, path::Path, time::Duration};
+
+/// Format of the final report
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable summary on stderr
+    Text,
+    /// JSON report on stdout, in addition to the summary
+    Json,
+}
+
+/// Format of the report written to --report-file
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// JUnit XML, one testcase per file
+    Junit,
+    /// A self-contained HTML page with the totals and a sortable table of the files, for people to review
+    Html,
+}
+
+/// What happened to a single file
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Signed,
+    Failed,
+    /// Deliberately not signed, e.g. an unsupported file with --ignore-unsupported
+    Skipped,
+    /// The run stopped before getting to this file
+    NotAttempted,
+}
+
+impl Status {
+    const ALL: [Status; 4] = [Status::Signed, Status::Failed, Status::Skipped, Status::NotAttempted];
+
+    fn label(self) -> &'static str {
+        match self {
+            Status::Signed => "signed",
+            Status::Failed => "failed",
+            Status::Skipped => "skipped",
+            Status::NotAttempted => "not attempted",
+        }
+    }
+
+    /// The label padded to `width` and colored for stderr, when colors are enabled
+    pub fn styled(self, width: usize) -> String {
+        let label = style(format!("{:<width$}", self.label())).for_stderr();
+        match self {
+            Status::Signed => label.green(),
+            Status::Failed => label.red(),
+            Status::Skipped => label.dim(),
+            Status::NotAttempted => label.yellow(),
+        }
+        .to_string()
+    }
+}
+
+/// Result of a single file
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct FileReport {
+    pub path: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Kind of the error, like `invalid_input` for an empty or truncated file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<&'static str>,
+    /// Why the file was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    /// Correlation id sent along with the signing of this file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Certificate profile the file was signed with, set once signing was attempted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate_profile: Option<String>,
+    /// Endpoint the file was signed at, a fallback one when its own failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Certificate profile of the signature appended with --also-profile, set once it was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub also_certificate_profile: Option<String>,
+    /// What signed the file, set once signing was attempted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mechanism: Option<Mechanism>,
+    /// The parts signed one after the other, like the engine and the Burn bundle around it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<Signature>,
+    /// SHA-256 of the file right before it was signed, when it was hashed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256_before: Option<String>,
+    /// SHA-256 of the signed file, what manifests and update feeds have to list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256_after: Option<String>,
+    /// What signtool warned about while signing the file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// The certificate the file was signed with, read back from its signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<SigningCertificate>,
+    /// The existing signer --exclude-signed-by protects the file from, when it was skipped for that
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected_by: Option<SigningCertificate>,
+    /// The detached PKCS#7 of the file with --detached-p7, the file itself is left as it was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_file: Option<String>,
+    /// The digest algorithms found in the signatures of the file after signing, with --require-sha2
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub digests: Vec<ObservedDigest>,
+    /// The revocation status of every certificate in the chains of the file after signing, with --verify-revocation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub revocation: Vec<revocation::Check>,
+    /// The MSI referencing this external cabinet, with --msi-with-cabs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cabinet_of: Option<String>,
+    /// How often signing was tried again at a fallback endpoint
+    #[serde(skip_serializing_if = "is_zero")]
+    pub retries: u32,
+    /// Signed with a local PFX under --backend pfx, a test signature Windows doesn't trust like a Trusted Signing one
+    #[serde(skip_serializing_if = "is_false")]
+    pub test_signed: bool,
+    /// Signed without a timestamp by --defer-timestamp-on-failure, the signature ends with the certificate until
+    /// `trusted-signing-cli timestamp --pending` adds one
+    #[serde(skip_serializing_if = "is_false")]
+    pub awaiting_timestamp: bool,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl FileReport {
+    /// One line reason of a failure, preferring the error line signtool printed.
+    /// Localized signtool builds may not print that prefix, then the first line is used.
+    pub fn reason(&self) -> Option<&str> {
+        self.error.as_deref().map(reason)
+    }
+}
+
+/// The line of `error` that explains it best
+fn reason(error: &str) -> &str {
+    error
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("SignTool Error:"))
+        .or_else(|| error.lines().next())
+        .unwrap_or("")
+}
+
+/// The line of the output of a child process in `error` that says why it failed, like the AADSTS error of a
+/// failed az login. The output comes after the first line
+fn detail(error: &str) -> Option<&str> {
+    let output: Vec<&str> = error.lines().skip(1).map(str::trim).filter(|line| !line.is_empty()).collect();
+    output.iter().find(|line| line.contains("AADSTS")).or(output.last()).copied()
+}
+
+/// Totals of a run
+#[derive(Serialize, JsonSchema, Debug, Default)]
+pub struct Summary {
+    pub signed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// The skipped files signed by a signer --exclude-signed-by protects
+    pub protected: usize,
+    /// The signed files without a timestamp yet, see --defer-timestamp-on-failure
+    pub awaiting_timestamp: usize,
+    pub not_attempted: usize,
+    pub bytes_signed: u64,
+    pub duration_ms: u64,
+}
+
+/// Everything that happened during a run
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct Report {
+    /// Correlation id shared by every file, unless each file gets its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Version of the Azure CLI, when it was used and could be told
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub azure_cli_version: Option<String>,
+    /// The --run build the files were signed after
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<Build>,
+    /// Certificate profiles that aren't active or expire within --warn-expiry
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub profile_warnings: Vec<String>,
+    /// How the local clock compared with the signing endpoint's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock: Option<clock::Check>,
+    /// Signatures counted locally this month per account signed with, an estimate of the service's quota
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quota: Vec<quota::Usage>,
+    /// The latest.yml files --update-latest-yml rewrote the entries of
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub latest_yml: Vec<electron::LatestYml>,
+    /// The file --checksums-file wrote
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<checksums::Checksums>,
+    pub summary: Summary,
+    pub files: Vec<FileReport>,
+    /// Durations per phase of the run
+    pub metrics: Metrics,
+    /// Why the run failed, set once it's over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+
+impl Report {
+    /// Start a report with every file not attempted yet
+    pub fn new(files: &[String]) -> Self {
+        Report {
+            correlation_id: None,
+            azure_cli_version: None,
+            build: None,
+            profile_warnings: Vec::new(),
+            clock: None,
+            quota: Vec::new(),
+            latest_yml: Vec::new(),
+            checksums: None,
+            summary: Summary {
+                not_attempted: files.len(),
+                ..Summary::default()
+            },
+            files: files
+                .iter()
+                .map(|path| FileReport {
+                    path: path.clone(),
+                    status: Status::NotAttempted,
+                    size: None,
+                    duration_ms: 0,
+                    error: None,
+                    error_kind: None,
+                    skip_reason: None,
+                    correlation_id: None,
+                    certificate_profile: None,
+                    endpoint: None,
+                    mechanism: None,
+                    also_certificate_profile: None,
+                    signatures: Vec::new(),
+                    sha256_before: None,
+                    sha256_after: None,
+                    warnings: Vec::new(),
+                    certificate: None,
+                    protected_by: None,
+                    signature_file: None,
+                    digests: Vec::new(),
+                    revocation: Vec::new(),
+                    cabinet_of: None,
+                    retries: 0,
+                    test_signed: false,
+                    awaiting_timestamp: false,
+                })
+                .collect(),
+            metrics: Metrics::default(),
+            error: None,
+        }
+    }
+
+    /// Record the outcome of the file at `index`
+    pub fn record(&mut self, index: usize, result: &Result<(), Error>, size: Option<u64>, duration: Duration) {
+        let file = &mut self.files[index];
+        file.size = size;
+        file.duration_ms = duration.as_millis() as u64;
+        match result {
+            Ok(()) => file.status = Status::Signed,
+            Err(err) => {
+                file.status = Status::Failed;
+                file.error = Some(err.to_string());
+                file.error_kind = Some(err.kind());
+            }
+        }
+    }
+
+    /// Record that the file at `index` was deliberately not signed
+    pub fn skip(&mut self, index: usize, reason: &str) {
+        let file = &mut self.files[index];
+        file.status = Status::Skipped;
+        file.skip_reason = Some(reason.to_string());
+    }
+
+    /// Compute the totals once the run is over
+    pub fn finish(&mut self, elapsed: Duration) {
+        let count = |status| self.files.iter().filter(|file| file.status == status).count();
+        self.summary = Summary {
+            signed: count(Status::Signed),
+            failed: count(Status::Failed),
+            skipped: count(Status::Skipped),
+            protected: self.files.iter().filter(|file| file.protected_by.is_some()).count(),
+            awaiting_timestamp: self.files.iter().filter(|file| file.awaiting_timestamp).count(),
+            not_attempted: count(Status::NotAttempted),
+            bytes_signed: self
+                .files
+                .iter()
+                .filter(|file| file.status == Status::Signed)
+                .filter_map(|file| file.size)
+                .sum(),
+            duration_ms: elapsed.as_millis() as u64,
+        };
+    }
+
+    /// Human readable summary table
+    pub fn summary_table(&self) -> String {
+        let mut table = String::from("Summary\n");
+        if let Some(build) = &self.build {
+            let seconds = build.duration_ms as f64 / 1000.0;
+            table.push_str(&format!("  {:<20}in {:.1}s  ({})\n", "built", seconds, build.command));
+        }
+        for status in Status::ALL {
+            let count = self.files.iter().filter(|file| file.status == status).count();
+            if count == 0 {
+                continue;
+            }
+            table.push_str(&format!("  {}{:>5}", status.styled(15), count));
+            if status == Status::Signed {
+                let mut details = vec![format_bytes(self.summary.bytes_signed)];
+                for mechanism in [Mechanism::Nuget, Mechanism::Vsix, Mechanism::ClickOnce] {
+                    let signed = self
+                        .files
+                        .iter()
+                        .filter(|file| file.status == Status::Signed && file.mechanism == Some(mechanism))
+                        .count();
+                    if signed > 0 {
+                        details.push(format!("{} with {}", signed, mechanism.tool()));
+                    }
+                }
+                match self.files.iter().filter(|file| file.test_signed).count() {
+                    0 => (),
+                    test_signed => details.push(format!("{} test-signed with a PFX", test_signed)),
+                }
+                let warned = |file: &&FileReport| file.status == Status::Signed && !file.warnings.is_empty();
+                match self.files.iter().filter(warned).count() {
+                    0 => (),
+                    warned => details.push(format!("{} with warnings", warned)),
+                }
+                if self.summary.awaiting_timestamp > 0 {
+                    let unstamped = format!("{} WITHOUT a timestamp", self.summary.awaiting_timestamp);
+                    details.push(style(unstamped).for_stderr().yellow().bold().to_string());
+                }
+                table.push_str(&format!("  ({})", details.join(", ")));
+            }
+            if status == Status::Skipped && self.summary.protected > 0 {
+                table.push_str(&format!("  ({} protected)", self.summary.protected));
+            }
+            table.push('\n');
+        }
+        table.push_str(&format!(
+            "  {:<15}{:>5}  in {:.1}s\n",
+            "total",
+            self.files.len(),
+            self.summary.duration_ms as f64 / 1000.0
+        ));
+        if let Some(correlation_id) = &self.correlation_id {
+            table.push_str(&format!("  correlation id {}\n", correlation_id));
+        }
+        if let Some(checksums) = &self.checksums {
+            table.push_str(&format!("  checksums of {} file(s) in {}", checksums.files, checksums.path));
+            match checksums.omitted.len() {
+                0 => table.push('\n'),
+                omitted => table.push_str(&format!(", {} failed or skipped left out\n", omitted)),
+            }
+        }
+        for usage in &self.quota {
+            table.push_str(&format!(
+                "  {} signature(s) of {} this month (local estimate)\n",
+                usage.signatures, usage.account
+            ));
+        }
+
+        let timings = self.metrics.summary_lines();
+        if !timings.is_empty() {
+            table.push_str("\nTimings:\n");
+            for line in timings {
+                table.push_str(&format!("  {}\n", line));
+            }
+        }
+
+        let protected: Vec<_> = self.files.iter().filter(|file| file.protected_by.is_some()).collect();
+        if !protected.is_empty() {
+            table.push_str("\nProtected files:\n");
+            for file in protected {
+                let signer = file.protected_by.as_ref().map(|signer| signer.subject.as_str()).unwrap_or_default();
+                table.push_str(&format!("  {}: signed by {}\n", file.path, signer));
+            }
+        }
+
+        let unstamped: Vec<_> = self.files.iter().filter(|file| file.awaiting_timestamp).collect();
+        if !unstamped.is_empty() {
+            let heading = format!("{} file(s) awaiting a timestamp:", unstamped.len());
+            table.push_str(&format!("\n{}\n", style(heading).for_stderr().yellow().bold()));
+            for file in unstamped {
+                table.push_str(&format!("  {}\n", file.path));
+            }
+            table.push_str(
+                "  their signatures stop being valid when the certificate expires, \
+                 run `trusted-signing-cli timestamp --pending` once the timestamp authority answers\n",
+            );
+        }
+
+        let failed: Vec<_> = self.files.iter().filter(|file| file.status == Status::Failed).collect();
+        if !failed.is_empty() {
+            table.push_str("\nFailed files:\n");
+            for file in failed {
+                table.push_str(&format!(
+                    "  {}: {}\n",
+                    style(&file.path).for_stderr().red(),
+                    file.reason().unwrap_or("")
+                ));
+            }
+        }
+
+        table
+    }
+
+    /// Short error block for --quiet, one line for the error, one for the reason the tool it ran gave, and one per
+    /// failed file
+    pub fn error_block(&self, error: &Error) -> String {
+        let error = error.to_string();
+        let mut block = format!("error: {}\n", error.lines().next().unwrap_or(""));
+        let failed = self.files.iter().filter(|file| file.status == Status::Failed);
+        // a failed file says why itself
+        if let Some(detail) = detail(&error).filter(|_| failed.clone().next().is_none()) {
+            block.push_str(&format!("  {}\n", detail));
+        }
+        for file in failed {
+            block.push_str(&format!("  {}: {}\n", file.path, file.reason().unwrap_or("")));
+        }
+        block
+    }
+
+    /// Write the report to `path` in the given format
+    pub fn write(&self, format: ReportFormat, path: &Path) -> Result<(), String> {
+        let content = match format {
+            ReportFormat::Junit => junit::render(self),
+            ReportFormat::Html => html::render(self),
+        };
+        fs::write(path, content).map_err(|err| format!("report '{}' could not be written: {:?}", path.display(), err))
+    }
+
+    /// Append the run as markdown to GitHub's job summary at `path`, which other steps write to as well
+    pub fn append_step_summary(&self, path: &Path, target: &Target, error: Option<&str>) -> Result<(), String> {
+        let failed = |err| format!("the job summary '{}' could not be written: {:?}", path.display(), err);
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path).map_err(failed)?;
+        file.write_all(markdown::render(self, target, error).as_bytes()).map_err(failed)
+    }
+
+    /// The report as pretty printed JSON
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|err| format!("report could not be serialized: {:?}", err))
+    }
+}
+
+/// Format a byte count with a binary unit
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Held by the tests setting whether `console` colors, it is global
+    static COLORS: Mutex<()> = Mutex::new(());
+
+    fn signing_error(message: &str, output: &str) -> Error {
+        Error::Signing {
+            path: "a.exe".to_string(),
+            message: message.to_string(),
+            output: output.to_string(),
+        }
+    }
+
+    #[test]
+    fn summary_after_fail_fast() {
+        let _colors = COLORS.lock().unwrap_or_else(|err| err.into_inner());
+        console::set_colors_enabled_stderr(false);
+        let files = vec!["a.exe".to_string(), "b.exe".to_string(), "c.exe".to_string()];
+        let mut report = Report::new(&files);
+        report.record(0, &Ok(()), Some(2048), Duration::from_millis(10));
+        report.files[0].warnings = vec!["the file was not timestamped".to_string()];
+        report.files[0].awaiting_timestamp = true;
+        report.record(1, &Err(signing_error("signtool failed", "details")), Some(10), Duration::from_millis(5));
+        report.finish(Duration::from_secs(2));
+
+        assert_eq!(report.summary.signed, 1);
+        assert_eq!(report.summary.failed, 1);
+        assert_eq!(report.summary.not_attempted, 1);
+        assert_eq!(report.summary.bytes_signed, 2048);
+
+        let table = report.summary_table();
+        assert!(table.contains("not attempted"));
+        assert!(table.contains("(2.0 KiB, 1 with warnings, 1 WITHOUT a timestamp)"));
+        assert!(table.contains("1 file(s) awaiting a timestamp:\n  a.exe\n"));
+        assert_eq!(report.summary.awaiting_timestamp, 1);
+        assert!(table.contains("b.exe: signtool failed\n"));
+        assert!(!table.contains("details"));
+    }
+
+    #[test]
+    fn json_without_colors() {
+        let _colors = COLORS.lock().unwrap_or_else(|err| err.into_inner());
+        console::set_colors_enabled(true);
+        console::set_colors_enabled_stderr(true);
+        let mut report = Report::new(&["a.exe".to_string(), "b.exe".to_string()]);
+        report.record(0, &Ok(()), Some(2048), Duration::from_millis(10));
+        report.files[0].warnings = vec!["the file was not timestamped".to_string()];
+        report.record(1, &Err(signing_error("signtool failed", "details")), Some(10), Duration::from_millis(5));
+        report.finish(Duration::from_secs(2));
+        report.error = Some(signing_error("1 file(s) could not be signed", "details"));
+
+        let table = report.summary_table();
+        let json = report.to_json().unwrap();
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+        assert!(table.contains('\x1b'), "{}", table);
+        assert!(!json.contains('\x1b'), "{}", json);
+    }
+
+    #[test]
+    fn reason_prefers_signtool_error() {
+        let mut report = Report::new(&["a.exe".to_string()]);
+        let error = signing_error(
+            "signtool 'signtool.exe' could not sign the file",
+            "Done Adding Additional Store\nSignTool Error: File not found: a.exe",
+        );
+        report.record(0, &Err(error), None, Duration::ZERO);
+
+        assert_eq!(report.files[0].reason(), Some("SignTool Error: File not found: a.exe"));
+    }
+}
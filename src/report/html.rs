@@ -0,0 +1,194 @@
+use super::{format_bytes, Report, Status};
+use crate::logging;
+
+/// Styles of the page, inline so it opens from a file share or an email attachment without a network
+const STYLE: &str = "
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2em; color: #1f2328; }
+h1 { font-size: 1.5em; margin: 0 0 .2em; }
+.meta { color: #59636e; margin: 0 0 1.5em; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; margin-bottom: 1.5em; }
+.card { border: 1px solid #d1d9e0; border-radius: 6px; padding: .8em 1.2em; min-width: 8em; }
+.card b { display: block; font-size: 1.6em; }
+.card.signed b { color: #1a7f37; }
+.card.failed b { color: #d1242f; }
+.test { border: 1px solid #bf8700; background: #fff8c5; border-radius: 6px; padding: .8em 1.2em; margin: 0 0 1em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #d1d9e0; padding: .4em .6em; text-align: left; vertical-align: top; }
+th { cursor: pointer; user-select: none; background: #f6f8fa; }
+th[data-order=asc]::after { content: ' \\25B2'; }
+th[data-order=desc]::after { content: ' \\25BC'; }
+td.number { text-align: right; white-space: nowrap; }
+.status-signed { color: #1a7f37; }
+.status-failed { color: #d1242f; }
+.status-skipped, .status-not-attempted, .reason { color: #59636e; }
+code { font-size: .9em; word-break: break-all; }
+pre { background: #f6f8fa; padding: .8em; overflow-x: auto; white-space: pre-wrap; }
+";
+
+/// Sorting the table by the column clicked, numbers by the value in `data-sort`
+const SCRIPT: &str = "
+document.querySelectorAll('th').forEach((th, column) => th.addEventListener('click', () => {
+  const body = th.closest('table').tBodies[0];
+  const ascending = th.dataset.order !== 'asc';
+  th.parentNode.querySelectorAll('th').forEach(other => delete other.dataset.order);
+  th.dataset.order = ascending ? 'asc' : 'desc';
+  const key = row => row.cells[column].dataset.sort ?? row.cells[column].textContent;
+  const compare = th.dataset.type === 'number' ? (a, b) => a - b : (a, b) => a.localeCompare(b);
+  [...body.rows]
+    .sort((a, b) => (ascending ? 1 : -1) * compare(key(a), key(b)))
+    .forEach(row => body.appendChild(row));
+}));
+";
+
+/// Escape text for HTML content and attributes
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+fn card(class: &str, value: &str, label: &str) -> String {
+    format!("<div class=\"card {}\"><b>{}</b>{}</div>\n", class, escape(value), label)
+}
+
+/// A single self-contained page of the run for people reviewing a release: cards of the totals and the profiles
+/// used, and a sortable table of the files with the output of the failed ones folded in
+pub fn render(report: &Report) -> String {
+    let summary = &report.summary;
+    let mut html = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    html.push_str(&format!("<title>Signing report</title>\n<style>{}</style>\n</head>\n<body>\n", STYLE));
+    html.push_str("<h1>Signing report</h1>\n");
+    let mut meta = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    if let Some(correlation_id) = &report.correlation_id {
+        meta.push_str(&format!(", correlation id {}", correlation_id));
+    }
+    html.push_str(&format!("<p class=\"meta\">{}</p>\n", escape(&meta)));
+    let test_signed = report.files.iter().filter(|file| file.test_signed).count();
+    if test_signed > 0 {
+        html.push_str(&format!(
+            "<p class=\"test\"><b>Test signatures:</b> {} file(s) were signed with a local PFX \
+             (<code>--backend pfx</code>), not for release</p>\n",
+            test_signed
+        ));
+    }
+    if summary.awaiting_timestamp > 0 {
+        html.push_str(&format!(
+            "<p class=\"test\"><b>No timestamp yet:</b> {} file(s) were signed without a timestamp, their \
+             signatures stop being valid when the certificate expires. Run \
+             <code>trusted-signing-cli timestamp --pending</code></p>\n",
+            summary.awaiting_timestamp
+        ));
+    }
+
+    // routes sign some files with other profiles
+    let mut profiles: Vec<&str> = report.files.iter().filter_map(|file| file.certificate_profile.as_deref()).collect();
+    profiles.sort();
+    profiles.dedup();
+    html.push_str("<div class=\"cards\">\n");
+    html.push_str(&card("signed", &summary.signed.to_string(), "signed"));
+    html.push_str(&card("failed", &summary.failed.to_string(), "failed"));
+    html.push_str(&card("skipped", &summary.skipped.to_string(), "skipped"));
+    if summary.not_attempted > 0 {
+        html.push_str(&card("not-attempted", &summary.not_attempted.to_string(), "not attempted"));
+    }
+    let duration = format!("{:.1}s", summary.duration_ms as f64 / 1000.0);
+    html.push_str(&card("duration", &duration, &format!("for {}", format_bytes(summary.bytes_signed))));
+    let profile_label = match profiles.len() {
+        1 => "certificate profile",
+        _ => "certificate profiles",
+    };
+    let profiles = match profiles.is_empty() {
+        true => "none".to_string(),
+        false => profiles.join(", "),
+    };
+    html.push_str(&card("profile", &profiles, profile_label));
+    html.push_str("</div>\n");
+
+    html.push_str("<table>\n<thead><tr><th>File</th><th>Status</th><th data-type=\"number\">Size</th>");
+    html.push_str("<th data-type=\"number\">Duration</th><th>SHA-256</th><th>Certificate subject</th></tr></thead>\n");
+    html.push_str("<tbody>\n");
+    for file in &report.files {
+        let (status, class) = match file.status {
+            Status::Signed if file.test_signed => ("test-signed", "signed"),
+            Status::Signed if file.awaiting_timestamp => ("signed, no timestamp", "signed"),
+            Status::Signed => ("signed", "signed"),
+            Status::Failed => ("failed", "failed"),
+            Status::Skipped => ("skipped", "skipped"),
+            Status::NotAttempted => ("not attempted", "not-attempted"),
+        };
+        let mut cell = format!("<code>{}</code>", escape(&file.path));
+        if let Some(msi) = &file.cabinet_of {
+            cell.push_str(&format!(" <span class=\"reason\">(cabinet of <code>{}</code>)</span>", escape(msi)));
+        }
+        if let (Status::Failed, Some(error)) = (file.status, &file.error) {
+            cell.push_str(&format!(
+                "<details><summary>{}</summary><pre>{}</pre></details>",
+                escape(file.reason().unwrap_or("signing failed")),
+                escape(error.trim_end())
+            ));
+        }
+        let mut status = format!("<span class=\"status-{}\">{}</span>", class, status);
+        if let Some(reason) = &file.skip_reason {
+            status.push_str(&format!("<br><span class=\"reason\">{}</span>", escape(reason)));
+        }
+        html.push_str(&format!(
+            "<tr><td data-sort=\"{}\">{}</td><td>{}</td><td class=\"number\" data-sort=\"{}\">{}</td>\
+             <td class=\"number\" data-sort=\"{}\">{:.1}s</td><td><code>{}</code></td><td>{}</td></tr>\n",
+            escape(&file.path),
+            cell,
+            status,
+            file.size.unwrap_or(0),
+            file.size.map(format_bytes).unwrap_or_default(),
+            file.duration_ms,
+            file.duration_ms as f64 / 1000.0,
+            file.sha256_after.as_deref().unwrap_or_default(),
+            escape(file.certificate.as_ref().map(|certificate| certificate.subject.as_str()).unwrap_or_default())
+        ));
+    }
+    html.push_str("</tbody>\n</table>\n");
+    html.push_str(&format!("<script>{}</script>\n</body>\n</html>\n", SCRIPT));
+    logging::mask(&html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn cards_table_and_failures() {
+        let files = ["dist/app.exe".to_string(), "dist/<b>.dll".to_string(), "setup.msi".to_string()];
+        let mut report = Report::new(&files);
+        report.files[0].status = Status::Signed;
+        report.files[0].size = Some(2048);
+        report.files[0].duration_ms = 1500;
+        report.files[0].sha256_after = Some("ab12".to_string());
+        report.files[0].certificate_profile = Some("prof".to_string());
+        report.files[0].test_signed = true;
+        report.files[1].status = Status::Failed;
+        report.files[1].error = Some("signtool failed\n\rSignTool Error: SignerSign() failed.".to_string());
+        report.skip(2, "its marker is current");
+        report.finish(Duration::from_secs(2));
+
+        let html = render(&report);
+        assert!(html.starts_with("<!DOCTYPE html>") && html.ends_with("</html>\n"));
+        assert!(html.contains("<div class=\"card signed\"><b>1</b>signed</div>"), "{}", html);
+        assert!(html.contains("<div class=\"card profile\"><b>prof</b>certificate profile</div>"));
+        assert!(html.contains("<td class=\"number\" data-sort=\"2048\">2.0 KiB</td>"));
+        assert!(html.contains("<code>dist/&lt;b&gt;.dll</code><details><summary>SignTool Error: SignerSign() failed."));
+        assert!(html.contains("<span class=\"reason\">its marker is current</span>"));
+        assert!(html.contains("<b>Test signatures:</b> 1 file(s)") && html.contains(">test-signed</span>"));
+        // nothing is loaded from elsewhere
+        assert!(!html.contains("http") && !html.contains("src=") && !html.contains("<link"));
+    }
+}
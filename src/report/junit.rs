@@ -0,0 +1,94 @@
+use super::{Report, Status};
+
+/// Escape text for XML attributes and content, dropping characters XML can't represent
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(char),
+            char if char < ' ' => (),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+fn seconds(milliseconds: u64) -> String {
+    format!("{:.3}", milliseconds as f64 / 1000.0)
+}
+
+/// JUnit XML with one testsuite per run and one testcase per file, in the shape Jenkins and Azure DevOps accept
+pub fn render(report: &Report) -> String {
+    let name = env!("CARGO_PKG_NAME");
+    let summary = &report.summary;
+    let tests = report.files.len();
+    let time = seconds(summary.duration_ms);
+    let skipped = summary.skipped + summary.not_attempted;
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{}\">\n",
+        name, tests, summary.failed, skipped, time
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{}\">\n",
+        name, tests, summary.failed, skipped, time
+    ));
+
+    for file in &report.files {
+        let testcase = format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{}\"",
+            name,
+            escape(&file.path),
+            seconds(file.duration_ms)
+        );
+        match file.status {
+            Status::Signed => xml.push_str(&format!("{}/>\n", testcase)),
+            Status::Failed => xml.push_str(&format!(
+                "{}>\n      <failure message=\"{}\" type=\"SigningFailed\">{}</failure>\n    </testcase>\n",
+                testcase,
+                escape(file.reason().unwrap_or("signing failed")),
+                escape(file.error.as_deref().unwrap_or(""))
+            )),
+            Status::Skipped => xml.push_str(&format!(
+                "{}>\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                testcase,
+                escape(file.skip_reason.as_deref().unwrap_or("skipped"))
+            )),
+            Status::NotAttempted => xml.push_str(&format!(
+                "{}>\n      <skipped message=\"not attempted, the run stopped before this file\"/>\n    </testcase>\n",
+                testcase
+            )),
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use std::time::Duration;
+
+    #[test]
+    fn testcases() {
+        let files = vec!["a & b.exe".to_string(), "c.exe".to_string(), "d.exe".to_string()];
+        let mut report = Report::new(&files);
+        report.record(0, &Ok(()), None, Duration::from_millis(1500));
+        report.record(1, &Err(Error::Other("exit status: 1\n<SignTool Error: \u{1b}boom>".to_string())), None, Duration::ZERO);
+        report.finish(Duration::from_secs(2));
+
+        let xml = render(&report);
+        assert!(xml.contains("tests=\"3\" failures=\"1\" errors=\"0\" skipped=\"1\" time=\"2.000\""));
+        assert!(xml.contains("<testcase classname=\"trusted-signing-cli\" name=\"a &amp; b.exe\" time=\"1.500\"/>"));
+        assert!(xml.contains("<failure message=\"exit status: 1\" type=\"SigningFailed\">exit status: 1\n&lt;SignTool Error: boom&gt;</failure>"));
+        assert!(xml.contains("<skipped message="));
+    }
+}
@@ -0,0 +1,121 @@
+use super::{format_bytes, Report, Status};
+use crate::{logging, route::Target};
+
+/// Escape text for a cell of a markdown table, which ends at a pipe or a line break. Backslashes are left alone,
+/// they escape nothing in the code spans paths are shown in
+fn cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// A markdown section of the run for GitHub's job summary: a table of the files, the totals and the certificate
+/// profiles used, and the output of the failed files in a collapsed block
+pub fn render(report: &Report, target: &Target, error: Option<&str>) -> String {
+    let summary = &report.summary;
+    let mut markdown = String::from("### Trusted Signing\n\n");
+    if !report.files.is_empty() {
+        markdown.push_str("| File | Status | Size | Duration | SHA-256 |\n| --- | --- | ---: | ---: | --- |\n");
+    }
+    for file in &report.files {
+        let status = match file.status {
+            Status::Signed if file.test_signed => "🧪 test-signed",
+            Status::Signed if file.awaiting_timestamp => "⚠️ signed, no timestamp",
+            Status::Signed => "✅ signed",
+            Status::Failed => "❌ failed",
+            Status::Skipped => "⏭️ skipped",
+            Status::NotAttempted => "not attempted",
+        };
+        let cabinet_of = file.cabinet_of.as_deref().map(|msi| format!(" (cabinet of `{}`)", cell(msi)));
+        markdown.push_str(&format!(
+            "| `{}`{} | {} | {} | {:.1}s | {} |\n",
+            cell(&file.path),
+            cabinet_of.unwrap_or_default(),
+            status,
+            file.size.map(format_bytes).unwrap_or_default(),
+            file.duration_ms as f64 / 1000.0,
+            file.sha256_after.as_deref().map(|sha256| format!("`{}`", sha256)).unwrap_or_default()
+        ));
+    }
+
+    // routes sign some files with other profiles
+    let mut profiles: Vec<&str> = report.files.iter().filter_map(|file| file.certificate_profile.as_deref()).collect();
+    profiles.sort();
+    profiles.dedup();
+    if profiles.is_empty() {
+        profiles.push(&target.certificate);
+    }
+    let profiles: Vec<String> = profiles.iter().map(|profile| format!("`{}`", cell(profile))).collect();
+    markdown.push_str(&format!(
+        "\n**{} signed**, {} failed, {} skipped of {} file(s) ({}) in {:.1}s with {} of `{}` at {}\n",
+        summary.signed,
+        summary.failed,
+        summary.skipped,
+        report.files.len(),
+        format_bytes(summary.bytes_signed),
+        summary.duration_ms as f64 / 1000.0,
+        profiles.join(", "),
+        cell(&target.account),
+        target.endpoint
+    ));
+
+    if report.files.iter().any(|file| file.test_signed) {
+        markdown.push_str("\n> **Test signatures** of a local PFX (`--backend pfx`), not for release\n");
+    }
+    if summary.awaiting_timestamp > 0 {
+        markdown.push_str(&format!(
+            "\n> [!WARNING]\n> **{} file(s) signed without a timestamp**, their signatures stop being valid when the \
+             certificate expires. Run `trusted-signing-cli timestamp --pending`\n",
+            summary.awaiting_timestamp
+        ));
+    }
+
+    let failed: Vec<_> = report.files.iter().filter(|file| file.status == Status::Failed).collect();
+    if !failed.is_empty() || error.is_some() {
+        markdown.push_str(&format!("\n<details><summary>Failures ({})</summary>\n\n", failed.len()));
+        for file in &failed {
+            let output = file.error.as_deref().unwrap_or("signing failed");
+            markdown.push_str(&format!("`{}`\n\n```text\n{}\n```\n\n", cell(&file.path), output.trim_end()));
+        }
+        // a run failing for its files says so in their output already
+        if let Some(error) = error.filter(|_| failed.is_empty()) {
+            markdown.push_str(&format!("The run failed:\n\n```text\n{}\n```\n\n", error.trim_end()));
+        }
+        markdown.push_str("</details>\n");
+    }
+    logging::mask(&markdown) + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn files_totals_and_failures() {
+        let files = ["dist/app.exe".to_string(), "dist/a|b.dll".to_string()];
+        let mut report = Report::new(&files);
+        report.files[0].status = Status::Signed;
+        report.files[0].size = Some(2048);
+        report.files[0].duration_ms = 1500;
+        report.files[0].sha256_after = Some("ab12".to_string());
+        report.files[0].certificate_profile = Some("prof".to_string());
+        report.files[1].status = Status::Failed;
+        report.files[1].error = Some("SignerSign() failed.\n(-2146869243/0x80096005)".to_string());
+        report.finish(Duration::from_secs(2));
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+
+        let markdown = render(&report, &target, Some("1 of 2 files could not be signed"));
+        assert!(markdown.contains("| `dist/app.exe` | ✅ signed | 2.0 KiB | 1.5s | `ab12` |\n"), "{}", markdown);
+        assert!(markdown.contains("| `dist/a\\|b.dll` | ❌ failed |  | 0.0s |  |\n"), "{}", markdown);
+        assert!(markdown.contains("**1 signed**, 1 failed, 0 skipped of 2 file(s) (2.0 KiB) in 2.0s with `prof` of"));
+        assert!(markdown.contains("<details><summary>Failures (1)</summary>"));
+        assert!(markdown.contains("```text\nSignerSign() failed.\n(-2146869243/0x80096005)\n```"));
+
+        report.files.pop();
+        report.finish(Duration::from_secs(2));
+        assert!(!render(&report, &target, None).contains("<details>"));
+    }
+}
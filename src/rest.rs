@@ -0,0 +1,301 @@
+use crate::{
+    authenticode::{self, Signable},
+    error::Error,
+    logging::{self, run_captured},
+    route::Target,
+    scratch,
+    signtool::Digest,
+};
+#[cfg(feature = "rest")]
+use base64::{engine::general_purpose::STANDARD, Engine};
+use duct::cmd;
+#[cfg(feature = "rest")]
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+#[cfg(feature = "rest")]
+use serde::Deserialize;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "rest")]
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// osslsigncode as a package manager puts it on the PATH
+pub const DEFAULT_OSSLSIGNCODE: &str = "osslsigncode";
+
+/// Version of the Trusted Signing data plane the signing client uses too
+#[cfg(feature = "rest")]
+const API_VERSION: &str = "2022-06-15-preview";
+
+/// How often a signing operation is polled, and how long for at most
+#[cfg(feature = "rest")]
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+#[cfg(feature = "rest")]
+const POLL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Extensions of PE files, the only files the REST backend signs
+const PE_EXTENSIONS: [&str; 7] = ["cpl", "dll", "efi", "exe", "ocx", "scr", "sys"];
+
+/// How a file is signed through the REST API
+#[derive(Debug)]
+pub struct Options<'a> {
+    pub osslsigncode: &'a Path,
+    pub target: &'a Target,
+    /// Access token for https://codesigning.azure.net
+    pub token: &'a str,
+    pub file_digest: Digest,
+    pub timestamp_url: &'a str,
+    pub timestamp_digest: Digest,
+    pub description: Option<&'a str>,
+}
+
+/// A signing operation as the API reports it
+#[cfg(feature = "rest")]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Operation {
+    operation_id: Option<String>,
+    status: String,
+    signature: Option<String>,
+    signing_certificate: Option<String>,
+}
+
+/// Whether the file is a PE image, judged by its extension
+pub fn is_pe(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| PE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// The osslsigncode to run, `tool` itself when it is a path, otherwise looked up on the PATH
+pub fn find_osslsigncode(tool: &str) -> Result<PathBuf, Error> {
+    let path = Path::new(tool);
+    let found = match path.components().count() > 1 {
+        true => path.is_file().then(|| path.to_path_buf()),
+        false => std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .flat_map(|dir| [dir.join(tool), dir.join(format!("{}.exe", tool))])
+                .find(|candidate| candidate.is_file())
+        }),
+    };
+    let tool = found.ok_or_else(|| Error::MissingDependency {
+        component: "osslsigncode",
+        message: format!(
+            "osslsigncode '{}' was not found, the REST backend needs it to embed signatures: install it with your \
+             package manager (apt install osslsigncode, brew install osslsigncode) or pass --osslsigncode-path",
+            tool
+        ),
+    })?;
+    debug!("embedding signatures with {}", tool.display());
+    Ok(tool)
+}
+
+/// Run osslsigncode with `args`
+fn osslsigncode(tool: &Path, args: Vec<OsString>, file: &str, message: &str) -> Result<(), Error> {
+    let output = run_captured(cmd(tool, args)).map_err(|err| Error::MissingDependency {
+        component: "osslsigncode",
+        message: format!("osslsigncode '{}' could not be run: {}", tool.display(), err),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    Ok(())
+}
+
+/// The JWS algorithm the API names an RSA signature with this digest by
+#[cfg(feature = "rest")]
+fn algorithm(digest: Digest) -> &'static str {
+    match digest {
+        Digest::Sha1 | Digest::Sha256 => "RS256",
+        Digest::Sha384 => "RS384",
+        Digest::Sha512 => "RS512",
+    }
+}
+
+/// The URL of the signing operations of the target's certificate profile
+#[cfg(feature = "rest")]
+fn sign_url(target: &Target, operation: Option<&str>) -> String {
+    let base = format!(
+        "{}/codesigningaccounts/{}/certificateprofiles/{}/sign",
+        target.endpoint.trim_end_matches('/'),
+        target.account,
+        target.certificate
+    );
+    match operation {
+        Some(operation) => format!("{}/{}?api-version={}", base, operation, API_VERSION),
+        None => format!("{}?api-version={}", base, API_VERSION),
+    }
+}
+
+/// Read an API response, failing with what the API said
+#[cfg(feature = "rest")]
+async fn operation(response: reqwest::Response, file: &str) -> Result<Operation, Error> {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Auth(format!(
+            "Trusted Signing refused to sign '{}' ({}), does the principal have the Trusted Signing Certificate \
+             Profile Signer role? {}",
+            file, status, body
+        ))),
+        status if !status.is_success() => Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("Trusted Signing could not sign '{}', error: {}", file, status),
+            output: body,
+        }),
+        _ => serde_json::from_str(&body).map_err(|err| Error::Signing {
+            path: file.to_string(),
+            message: format!("Trusted Signing answered with an unexpected response: {}", err),
+            output: body,
+        }),
+    }
+}
+
+/// Have Trusted Signing sign `hash`, returning the signature and the certificates it was made with
+#[cfg(feature = "rest")]
+async fn sign_hash(options: &Options<'_>, hash: &[u8], file: &str) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let client = reqwest::Client::new();
+    let body = serde_json::json!({
+        "signatureAlgorithm": algorithm(options.file_digest),
+        "digest": STANDARD.encode(hash),
+    });
+    let request = client
+        .post(sign_url(options.target, None))
+        .bearer_auth(options.token)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    let response = request.send().await.map_err(|err| format!("Trusted Signing could not be reached: {}", err))?;
+    let mut operation = self::operation(response, file).await?;
+    let id = operation.operation_id.clone().ok_or_else(|| "Trusted Signing started no signing operation".to_string())?;
+    debug!("signing operation {} for {}", id, file);
+
+    let started = Instant::now();
+    while matches!(operation.status.as_str(), "InProgress" | "Running" | "NotStarted") {
+        if started.elapsed() > POLL_TIMEOUT {
+            Err(format!("signing operation {} did not finish in {}s", id, POLL_TIMEOUT.as_secs()))?;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let request = client.get(sign_url(options.target, Some(&id))).bearer_auth(options.token);
+        let response = request.send().await.map_err(|err| format!("Trusted Signing could not be reached: {}", err))?;
+        operation = self::operation(response, file).await?;
+    }
+    let decode = |value: Option<String>, what: &str| -> Result<Vec<u8>, Error> {
+        let value = value.ok_or_else(|| format!("the signing operation {} returned no {}", id, what))?;
+        Ok(STANDARD.decode(value).map_err(|err| format!("the {} of operation {} is not base64: {}", what, id, err))?)
+    };
+    match operation.status.as_str() {
+        "Succeeded" => {
+            let signature = decode(operation.signature, "signature")?;
+            Ok((signature, decode(operation.signing_certificate, "certificate")?))
+        }
+        status => Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("signing operation {} of '{}' ended as {}", id, file, status),
+            output: String::new(),
+        }),
+    }
+}
+
+/// Without the rest feature there is no HTTP client to call the API with
+#[cfg(not(feature = "rest"))]
+async fn sign_hash(_options: &Options<'_>, _hash: &[u8], _file: &str) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    Err(unavailable())
+}
+
+/// The error of --backend rest in a build without the rest feature
+#[cfg(not(feature = "rest"))]
+pub fn unavailable() -> Error {
+    Error::Usage("this build can't sign with --backend rest, it is built without the rest feature".to_string())
+}
+
+/// Sign a PE file in place: osslsigncode extracts the Authenticode data to sign, Trusted Signing signs the
+/// digest of it, osslsigncode embeds the resulting signature and adds an RFC 3161 timestamp
+pub async fn sign(options: &Options<'_>, file: &str) -> Result<(), Error> {
+    if !is_pe(file) {
+        Err(Error::UnsupportedInput {
+            path: file.to_string(),
+            message: format!("the REST backend only signs PE files like .exe and .dll, not '{}'", file),
+        })?;
+    }
+    let tool = options.osslsigncode;
+    let digest = options.file_digest.name().to_ascii_lowercase();
+    let work = scratch::dir("trusted-signing-rest", Some(Path::new(file)))
+        .map_err(|err| format!("a directory to sign '{}' in could not be created: {}", file, err))?;
+    let extension = Path::new(file).extension().unwrap_or_default().to_string_lossy().to_string();
+    let (data, signature) = (work.path().join("data.der"), work.path().join("signature.der"));
+    let signed = work.path().join(format!("signed.{}", extension));
+    let stamped = work.path().join(format!("stamped.{}", extension));
+
+    let args = ["extract-data", "-h", &digest, "-in", file, "-out"].map(OsString::from);
+    let args = args.into_iter().chain([data.clone().into()]).collect();
+    osslsigncode(tool, args, file, &format!("osslsigncode could not read '{}'", file))?;
+    let content = fs::read(&data).map_err(|err| err.to_string())?;
+    let signable = Signable::new(authenticode::indirect_data(&content)?, options.file_digest, options.description)?;
+
+    let (value, certificate) = sign_hash(options, &signable.hash(), file).await?;
+    let pkcs7 = signable.signed_data(&authenticode::certificates(&certificate)?, &value)?;
+    fs::write(&signature, pkcs7).map_err(|err| err.to_string())?;
+
+    let args: Vec<OsString> = vec![
+        "attach-signature".into(),
+        "-sigin".into(),
+        signature.into(),
+        "-in".into(),
+        file.into(),
+        "-out".into(),
+        signed.clone().into(),
+    ];
+    osslsigncode(tool, args, file, &format!("osslsigncode could not embed the signature into '{}'", file))?;
+    let args: Vec<OsString> = vec![
+        "add".into(),
+        "-ts".into(),
+        options.timestamp_url.into(),
+        "-h".into(),
+        options.timestamp_digest.name().to_ascii_lowercase().into(),
+        "-in".into(),
+        signed.into(),
+        "-out".into(),
+        stamped.clone().into(),
+    ];
+    osslsigncode(tool, args, file, &format!("osslsigncode could not timestamp '{}'", file))?;
+    scratch::replace(&stamped, Path::new(file))
+        .map_err(|err| format!("'{}' could not be replaced with the signed file: {}", file, err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pe_extensions() {
+        assert!(is_pe(r"target\x86_64-pc-windows-msvc\release\app.EXE"));
+        assert!(!is_pe("setup.msi"));
+    }
+
+    #[test]
+    #[cfg(feature = "rest")]
+    fn rest_requests() {
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net/".to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+        assert_eq!(
+            sign_url(&target, Some("a1b2")),
+            "https://eus.codesigning.azure.net/codesigningaccounts/acc/certificateprofiles/prof/sign/a1b2\
+             ?api-version=2022-06-15-preview"
+        );
+        assert_eq!(algorithm(Digest::Sha384), "RS384");
+        let operation: Operation =
+            serde_json::from_str(r#"{"operationId": "a1b2", "status": "Succeeded", "signature": "AAE="}"#).unwrap();
+        assert_eq!((operation.status.as_str(), operation.signing_certificate), ("Succeeded", None));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{authenticode, certificate, inspect};
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// CERT_TRUST_IS_REVOKED
+const IS_REVOKED: u32 = 0x4;
+/// CERT_TRUST_REVOCATION_STATUS_UNKNOWN
+const STATUS_UNKNOWN: u32 = 0x40;
+/// CERT_TRUST_IS_OFFLINE_REVOCATION, the revocation server couldn't be reached or its answer is out of date
+const OFFLINE: u32 = 0x0100_0000;
+
+/// What a revocation status that couldn't be checked, like without network, does to its file
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Offline {
+    /// A warning, the file counts as signed
+    Warn,
+    /// The file fails like a revoked one
+    Fail,
+}
+
+/// The revocation status of a certificate, or of a whole chain by its worst certificate
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+#[schemars(rename = "RevocationStatus")]
+pub enum Status {
+    Good,
+    /// No CRL or OCSP answer could be had, mostly without network
+    Unknown,
+    /// The chain doesn't verify for another reason, like an untrusted root
+    Invalid,
+    Revoked,
+}
+
+/// One certificate of a chain as Windows built it, the signing certificate first
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[schemars(rename = "ChainElement")]
+pub struct Element {
+    pub subject: String,
+    pub thumbprint: String,
+    pub status: Status,
+    /// The CERT_TRUST_* error flags Windows reported for the certificate, when there were any
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// The revocation check of the chain of one signature
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[schemars(rename = "RevocationCheck")]
+pub struct Check {
+    /// Index of the signature, in the order `inspect` lists them
+    pub signature: usize,
+    pub status: Status,
+    pub chain: Vec<Element>,
+}
+
+/// The CERT_TRUST_* error flags by their names
+const ERRORS: [(u32, &str); 12] = [
+    (0x1, "not_time_valid"),
+    (IS_REVOKED, "revoked"),
+    (0x8, "not_signature_valid"),
+    (0x10, "not_valid_for_usage"),
+    (0x20, "untrusted_root"),
+    (STATUS_UNKNOWN, "revocation_status_unknown"),
+    (0x80, "cyclic"),
+    (0x800, "invalid_name_constraints"),
+    (0x10000, "partial_chain"),
+    (0x0200_0000, "no_issuance_chain_policy"),
+    (0x0400_0000, "explicit_distrust"),
+    (OFFLINE, "offline_revocation"),
+];
+
+/// The status of a certificate by the error flags Windows gave it, with their names
+fn status(flags: u32) -> (Status, Vec<String>) {
+    let mut errors: Vec<String> =
+        ERRORS.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| name.to_string()).collect();
+    let unnamed = flags & !ERRORS.iter().fold(0, |all, (flag, _)| all | flag);
+    if unnamed != 0 {
+        errors.push(format!("{:#x}", unnamed));
+    }
+    let status = match flags {
+        flags if flags & IS_REVOKED != 0 => Status::Revoked,
+        flags if flags & !(STATUS_UNKNOWN | OFFLINE) != 0 => Status::Invalid,
+        0 => Status::Good,
+        _ => Status::Unknown,
+    };
+    (status, errors)
+}
+
+/// The signing certificate of `pkcs7` and of the signatures nested in it, each with the certificates that came with
+/// its signature to build the chain from
+fn signers<'a>(pkcs7: &'a [u8], found: &mut Vec<(&'a [u8], Vec<Vec<u8>>)>) {
+    if let Some(leaf) = authenticode::signing_certificate(pkcs7) {
+        found.push((leaf, authenticode::certificates(pkcs7).unwrap_or_default()));
+    }
+    for nested in authenticode::nested(pkcs7) {
+        signers(nested, found);
+    }
+}
+
+/// The chain Windows builds for `leaf` with online revocation checks, every certificate with its error flags
+#[cfg(windows)]
+fn chain(leaf: &[u8], extra: &[Vec<u8>]) -> Result<Vec<(Vec<u8>, u32)>, String> {
+    use windows_sys::Win32::{
+        Foundation::GetLastError,
+        Security::Cryptography::{
+            CertAddEncodedCertificateToStore, CertCloseStore, CertCreateCertificateContext, CertFreeCertificateChain,
+            CertFreeCertificateContext, CertGetCertificateChain, CertOpenStore, CERT_CHAIN_CONTEXT, CERT_CHAIN_PARA,
+            CERT_CHAIN_REVOCATION_CHECK_CHAIN, CERT_STORE_ADD_ALWAYS, CERT_STORE_PROV_MEMORY, X509_ASN_ENCODING,
+        },
+    };
+
+    let failed = |what: &str| {
+        // SAFETY: plain query of the calling thread
+        format!("{} failed with error {:#x}", what, unsafe { GetLastError() })
+    };
+    // SAFETY: a memory store takes no parameter and is closed below
+    let store = unsafe { CertOpenStore(CERT_STORE_PROV_MEMORY, 0, 0, 0, std::ptr::null()) };
+    if store.is_null() {
+        return Err(failed("CertOpenStore"));
+    }
+    for certificate in extra {
+        // SAFETY: the store is open, the certificate is a DER buffer of the length given
+        unsafe {
+            let (encoded, length) = (certificate.as_ptr(), certificate.len() as u32);
+            let added = std::ptr::null_mut();
+            CertAddEncodedCertificateToStore(store, X509_ASN_ENCODING, encoded, length, CERT_STORE_ADD_ALWAYS, added)
+        };
+    }
+    // SAFETY: `leaf` is a DER buffer of the length given, the context is freed below
+    let context = unsafe { CertCreateCertificateContext(X509_ASN_ENCODING, leaf.as_ptr(), leaf.len() as u32) };
+    if context.is_null() {
+        let err = failed("CertCreateCertificateContext");
+        // SAFETY: the store was opened above and isn't used after this
+        unsafe { CertCloseStore(store, 0) };
+        return Err(err);
+    }
+    // SAFETY: zeroed is how the parameters of a chain start, only their size is set
+    let mut parameters: CERT_CHAIN_PARA = unsafe { std::mem::zeroed() };
+    parameters.cbSize = std::mem::size_of::<CERT_CHAIN_PARA>() as u32;
+    let mut chain_context: *mut CERT_CHAIN_CONTEXT = std::ptr::null_mut();
+    // SAFETY: the default engine, the time now, `context` and `store` are valid, the chain is freed below
+    let built = unsafe {
+        let flags = CERT_CHAIN_REVOCATION_CHECK_CHAIN;
+        let reserved = std::ptr::null();
+        CertGetCertificateChain(0, context, std::ptr::null(), store, &parameters, flags, reserved, &mut chain_context)
+    };
+    let result = match built != 0 && !chain_context.is_null() {
+        false => Err(failed("CertGetCertificateChain")),
+        // SAFETY: the chain has the simple chains and elements it counts, each element a certificate context
+        true => unsafe {
+            let chain_context = &*chain_context;
+            let mut elements = Vec::new();
+            if chain_context.cChain > 0 {
+                let simple = &**chain_context.rgpChain;
+                for index in 0..simple.cElement as usize {
+                    let element = &**simple.rgpElement.add(index);
+                    let certificate = &*element.pCertContext;
+                    let der = std::slice::from_raw_parts(certificate.pbCertEncoded, certificate.cbCertEncoded as usize);
+                    elements.push((der.to_vec(), element.TrustStatus.dwErrorStatus));
+                }
+            }
+            Ok(elements)
+        },
+    };
+    // SAFETY: all three came from the calls above and aren't used after this
+    unsafe {
+        if !chain_context.is_null() {
+            CertFreeCertificateChain(chain_context);
+        }
+        CertFreeCertificateContext(context);
+        CertCloseStore(store, 0);
+    }
+    result
+}
+
+#[cfg(not(windows))]
+fn chain(_leaf: &[u8], _extra: &[Vec<u8>]) -> Result<Vec<(Vec<u8>, u32)>, String> {
+    Err("checking revocation builds the chain with Windows' CertGetCertificateChain, it needs Windows".to_string())
+}
+
+/// Build the chain of every signature of a signed file with online CRL and OCSP checks, the status of each
+/// certificate in them
+pub fn check(path: &str) -> Result<Vec<Check>, String> {
+    let mut checks = Vec::new();
+    for pkcs7 in inspect::embedded(path)? {
+        let mut found = Vec::new();
+        signers(&pkcs7, &mut found);
+        for (leaf, extra) in found {
+            let chain: Vec<Element> = chain(leaf, &extra)?
+                .into_iter()
+                .map(|(der, flags)| {
+                    let details = certificate::details(&der);
+                    let (status, errors) = status(flags);
+                    Element {
+                        subject: details.as_ref().map(|details| details.subject.clone()).unwrap_or_default(),
+                        thumbprint: details.map(|details| details.thumbprint).unwrap_or_default(),
+                        status,
+                        errors,
+                    }
+                })
+                .collect();
+            let status = chain.iter().map(|element| element.status).max().unwrap_or(Status::Invalid);
+            checks.push(Check { signature: checks.len(), status, chain });
+        }
+    }
+    match checks.is_empty() {
+        true => Err("it has no signature whose chain could be built".to_string()),
+        false => Ok(checks),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_by_flags() {
+        assert_eq!(status(0), (Status::Good, Vec::new()));
+        assert_eq!(status(IS_REVOKED | STATUS_UNKNOWN).0, Status::Revoked);
+        let (offline, errors) = status(STATUS_UNKNOWN | OFFLINE);
+        assert_eq!(offline, Status::Unknown);
+        assert_eq!(errors, ["revocation_status_unknown", "offline_revocation"]);
+        let (untrusted, errors) = status(0x20 | STATUS_UNKNOWN | 0x1000_0000);
+        assert_eq!(untrusted, Status::Invalid);
+        assert_eq!(errors, ["untrusted_root", "revocation_status_unknown", "0x10000000"]);
+        assert!(Status::Revoked > Status::Invalid && Status::Invalid > Status::Unknown);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::endpoint;
+use glob::Pattern;
+use std::path::Path;
+
+/// Where a file is signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub endpoint: String,
+    pub account: String,
+    pub certificate: String,
+}
+
+/// A `--route` rule sending the files matching a glob to another certificate profile,
+/// and optionally another account and endpoint
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub pattern: Pattern,
+    pub certificate: String,
+    pub account: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+/// A certificate profile, with the account and endpoint of the default target unless given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub certificate: String,
+    pub account: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+impl Profile {
+    /// The target of the profile, filled in from `default`
+    pub fn target(&self, default: &Target) -> Target {
+        Target {
+            endpoint: self.endpoint.clone().unwrap_or_else(|| default.endpoint.clone()),
+            account: self.account.clone().unwrap_or_else(|| default.account.clone()),
+            certificate: self.certificate.clone(),
+        }
+    }
+}
+
+/// Parse `profile[,account[,endpoint]]` of `what`, the flag value it is part of
+fn profile(value: &str, what: &str) -> Result<Profile, String> {
+    let mut parts = value.split(',').map(str::trim);
+    let certificate = match parts.next() {
+        Some(certificate) if !certificate.is_empty() => certificate.to_string(),
+        _ => return Err(format!("{} has no certificate profile", what)),
+    };
+    let account = parts.next().filter(|account| !account.is_empty()).map(str::to_string);
+    let endpoint = parts.next().map(endpoint::normalize).transpose()?;
+    if parts.next().is_some() {
+        return Err(format!("{} has more than profile, account and endpoint", what));
+    }
+    Ok(Profile { certificate, account, endpoint })
+}
+
+/// Parse `--also-profile`: `profile[,account[,endpoint]]`
+pub fn parse_profile(value: &str) -> Result<Profile, String> {
+    profile(value, &format!("--also-profile '{}'", value))
+}
+
+/// Parse `--route`: `glob=profile[,account[,endpoint]]`
+pub fn parse(value: &str) -> Result<Route, String> {
+    let Some((pattern, target)) = value.rsplit_once('=') else {
+        return Err(format!("route '{}' must look like glob=profile[,account[,endpoint]]", value));
+    };
+    let pattern = Pattern::new(pattern.trim()).map_err(|err| format!("route glob '{}' is invalid: {}", pattern, err))?;
+    let Profile { certificate, account, endpoint } = profile(target, &format!("route '{}'", value))?;
+
+    Ok(Route {
+        pattern,
+        certificate,
+        account,
+        endpoint,
+    })
+}
+
+impl Route {
+    /// Whether the glob matches the path as given or just the file name
+    fn matches(&self, file: &str) -> bool {
+        let name = Path::new(file).file_name().and_then(|name| name.to_str()).unwrap_or(file);
+        self.pattern.matches(file) || self.pattern.matches(name)
+    }
+}
+
+/// Target of a file, the first matching route or `default`, with a warning when more than one matched
+pub fn target(routes: &[Route], file: &str, default: &Target) -> (Target, Option<String>) {
+    let matching: Vec<&Route> = routes.iter().filter(|route| route.matches(file)).collect();
+    let Some(route) = matching.first() else {
+        return (default.clone(), None);
+    };
+
+    let warning = (matching.len() > 1).then(|| {
+        let patterns: Vec<&str> = matching.iter().map(|route| route.pattern.as_str()).collect();
+        format!("{} matches the routes {}, using the first one", file, patterns.join(", "))
+    });
+    let profile = Profile {
+        certificate: route.certificate.clone(),
+        account: route.account.clone(),
+        endpoint: route.endpoint.clone(),
+    };
+    (profile.target(default), warning)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routing() {
+        let default = Target {
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            account: "acc".to_string(),
+            certificate: "public".to_string(),
+        };
+        let routes = [
+            parse("*-updater.exe=private").unwrap(),
+            parse("internal/*=private, internal-acc, weu").unwrap(),
+        ];
+
+        assert_eq!(target(&routes, "dist/app.exe", &default), (default.clone(), None));
+        let (routed, warning) = target(&routes, "dist/app-updater.exe", &default);
+        assert_eq!(routed.certificate, "private");
+        assert_eq!(routed.account, "acc");
+        assert!(warning.is_none());
+        let (routed, warning) = target(&routes, "internal/tool-updater.exe", &default);
+        assert_eq!(routed.account, "acc");
+        assert!(warning.unwrap().contains("*-updater.exe, internal/*"));
+        let (routed, _) = target(&routes, "internal/tool.exe", &default);
+        assert_eq!(routed.account, "internal-acc");
+        assert_eq!(routed.endpoint, "https://weu.codesigning.azure.net");
+
+        assert!(parse("*.exe").is_err());
+        assert!(parse("*.exe=").is_err());
+        assert!(parse("*.exe=p,a,nowhere").unwrap_err().contains("unknown region"));
+        let also = parse_profile("private,,weu").unwrap();
+        let target = also.target(&default);
+        assert_eq!((target.account.as_str(), target.certificate.as_str()), ("acc", "private"));
+        assert!(parse_profile(" ,acc").unwrap_err().ends_with("has no certificate profile"));
+    }
+}
@@ -0,0 +1,163 @@
+//! JSON Schemas of the JSON report, the NDJSON events and the `--job` documents, generated from the types that write
+//! and read them
+
+use crate::{events::Envelope, job::Job, report::Report};
+use clap::ValueEnum;
+use schemars::generate::SchemaSettings;
+use serde_json::Value;
+
+/// Version of the JSON report, raised whenever a field is added, removed or changes its type
+pub const REPORT_VERSION: u32 = 1;
+
+/// Version of the NDJSON events, raised whenever an event or one of its fields changes
+pub const EVENTS_VERSION: u32 = 1;
+
+/// Version of the `--job` documents, raised whenever a field is added, removed or changes its type
+pub const JOB_VERSION: u32 = 1;
+
+/// What a schema describes
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The report `--output json` prints
+    Report,
+    /// One line of `--events ndjson`
+    Events,
+    /// The job `--job` reads
+    Job,
+}
+
+impl Format {
+    /// The file the schema is kept in in the repository, like `report-v1.json`
+    pub fn file_name(self) -> String {
+        match self {
+            Format::Report => format!("report-v{}.json", REPORT_VERSION),
+            Format::Events => format!("events-v{}.json", EVENTS_VERSION),
+            Format::Job => format!("job-v{}.json", JOB_VERSION),
+        }
+    }
+
+    /// The `$id` of the schema, the URL of its file
+    pub fn id(self) -> String {
+        format!("https://raw.githubusercontent.com/levminer/trusted-signing-cli/main/schemas/{}", self.file_name())
+    }
+}
+
+/// The schema of `format`, `$schema` and `$id` first
+pub fn schema(format: Format) -> Value {
+    // what is written, fields skipped when empty aren't required, and what is read, fields with a default aren't
+    let written = SchemaSettings::default().for_serialize().into_generator();
+    let generated = match format {
+        Format::Report => written.into_root_schema_for::<Report>(),
+        Format::Events => written.into_root_schema_for::<Envelope>(),
+        Format::Job => SchemaSettings::default().for_deserialize().into_generator().into_root_schema_for::<Job>(),
+    };
+    let mut schema = serde_json::Map::new();
+    schema.insert("$schema".to_string(), generated.get("$schema").cloned().unwrap_or_default());
+    schema.insert("$id".to_string(), format.id().into());
+    if format == Format::Events {
+        schema.insert("title".to_string(), "Event".into());
+    }
+    for (key, value) in generated.to_value().as_object().into_iter().flatten() {
+        schema.entry(key).or_insert_with(|| value.clone());
+    }
+    Value::Object(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        certificate::SigningCertificate,
+        clock,
+        error::Error,
+        events::{Event, RunConfig},
+        inspect::ObservedDigest,
+        revocation,
+        signer::{Mechanism, Signature},
+    };
+    use std::{fs, path::Path, time::Duration};
+
+    #[test]
+    fn report_and_events_validate() {
+        for format in [Format::Report, Format::Events, Format::Job] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schemas").join(format.file_name());
+            let kept: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap_or_default()).unwrap_or_default();
+            assert!(
+                kept == schema(format),
+                "{} is outdated, write it with `trusted-signing-cli schema {:?}`, under the next version when a field \
+                 changed",
+                path.display(),
+                format
+            );
+        }
+
+        let files = ["app.exe".to_string(), "notes.txt".to_string(), "lib.dll".to_string()];
+        let mut report = Report::new(&files);
+        report.correlation_id = Some("8d0c3f0e-1b7a-4c55-9d1e-2f3a4b5c6d7e".to_string());
+        report.clock = Some(clock::Check {
+            source: "eus.codesigning.azure.net".to_string(),
+            skew_seconds: Some(-2),
+            skewed: false,
+            error: None,
+        });
+        report.record(0, &Ok(()), Some(4096), Duration::from_millis(1200));
+        let signed = &mut report.files[0];
+        signed.mechanism = Some(Mechanism::Authenticode);
+        signed.certificate_profile = Some("prof".to_string());
+        signed.signatures = vec![Signature { part: "app.exe".to_string(), sha256: None, signed_at: String::new() }];
+        signed.certificate = Some(SigningCertificate {
+            subject: "CN=Fabrikam".to_string(),
+            issuer: "CN=Microsoft ID Verified CS EOC CA 01".to_string(),
+            thumbprint: "A1B2".to_string(),
+            serial: "33".to_string(),
+            not_before: "2026-03-01T00:00:00Z".to_string(),
+            not_after: "2026-03-04T00:00:00Z".to_string(),
+            eku: vec!["1.3.6.1.5.5.7.3.3".to_string()],
+        });
+        signed.digests = vec![ObservedDigest { signature: 0, of: "signature", digest: "SHA256".to_string() }];
+        signed.revocation = vec![revocation::Check { signature: 0, status: revocation::Status::Good, chain: Vec::new() }];
+        report.skip(1, "unsupported file type");
+        report.record(2, &Err(Error::Other("signtool failed".to_string())), None, Duration::ZERO);
+        report.metrics.record("sign", Duration::from_millis(1200));
+        report.finish(Duration::from_secs(2));
+        report.error = Some(Error::Other("1 of 3 files could not be signed".to_string()));
+
+        let validator = jsonschema::validator_for(&schema(Format::Report)).unwrap();
+        let json = serde_json::to_value(&report).unwrap();
+        let errors: Vec<String> = validator.iter_errors(&json).map(|err| err.to_string()).collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let validator = jsonschema::validator_for(&schema(Format::Events)).unwrap();
+        let config = RunConfig {
+            endpoint: "https://eus.codesigning.azure.net",
+            account: "acc",
+            certificate_profile: "prof",
+            azure_client_id: "cid",
+            azure_cli_path: "az",
+            sign_tool_path: "signtool",
+            files: &files,
+        };
+        let events = [
+            Event::RunStarted { config },
+            Event::PhaseFinished { phase: "login", duration_ms: 900 },
+            Event::FileFinished { file: &report.files[2] },
+            Event::RunFinished { report: &report },
+        ];
+        for (seq, event) in events.iter().enumerate() {
+            let json = serde_json::to_value(Envelope { seq: seq as u64 + 1, event }).unwrap();
+            let errors: Vec<String> = validator.iter_errors(&json).map(|err| err.to_string()).collect();
+            assert!(errors.is_empty(), "{:?}: {:?}", event, errors);
+        }
+        let unknown = serde_json::json!({"seq": 1, "type": "file-exploded"});
+        assert!(!validator.is_valid(&unknown));
+
+        let validator = jsonschema::validator_for(&schema(Format::Job)).unwrap();
+        let job = serde_json::json!({
+            "$schema": Format::Job.id(),
+            "defaults": {"endpoint": "eus", "file_digest": "SHA384"},
+            "files": [{"path": "app.exe", "output": "signed/app.exe"}]
+        });
+        assert!(validator.is_valid(&job));
+        assert!(!validator.is_valid(&serde_json::json!({"files": [{"path": "app.exe", "profile": "p"}]})));
+    }
+}
@@ -0,0 +1,167 @@
+//! Where the temporary directories of a run go: unpacked bundles, repacked packages, stdin, uploads. In --temp-dir
+//! when given, otherwise next to the file they are for, on its volume so the signed file is renamed over it, and in
+//! the system temp directory for the rest. Each is removed when dropped, a second Ctrl+C removes those left
+
+use crate::error::Error;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+use tempfile::TempDir;
+use tracing::debug;
+
+/// Environment variable of --temp-dir
+pub const ENV: &str = "TRUSTED_SIGNING_TMP";
+
+/// --temp-dir with less free space than this fails the run before anything is signed
+const MIN_FREE: u64 = 64 * 1024 * 1024;
+
+/// --temp-dir, once checked
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// The temporary directories not removed yet
+static LIVE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A temporary directory, removed with what is in it when dropped
+#[derive(Debug)]
+pub struct Dir(TempDir);
+
+impl Dir {
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        // the TempDir removes it after this
+        LIVE.lock().unwrap().retain(|path| path != self.0.path());
+    }
+}
+
+/// Put every temporary directory in `root` from now on, once it exists, is writable and has room for `needed`
+/// bytes, at least 64 MiB
+pub fn init(root: &Path, needed: u64) -> Result<(), Error> {
+    let failed = |err: io::Error| Error::Usage(format!("--temp-dir '{}' can't be used: {}", root.display(), err));
+    fs::create_dir_all(root).map_err(failed)?;
+    tempfile::Builder::new().prefix(".trusted-signing-probe").tempfile_in(root).map_err(failed)?;
+    let needed = needed.max(MIN_FREE);
+    match free_space(root) {
+        Some(free) if free < needed => Err(Error::Usage(format!(
+            "--temp-dir '{}' has {} MiB free, {} MiB are needed",
+            root.display(),
+            free / 1024 / 1024,
+            needed.div_ceil(1024 * 1024)
+        )))?,
+        Some(free) => debug!("temporary files go to {}, {} MiB free", root.display(), free / 1024 / 1024),
+        None => debug!("temporary files go to {}, its free space is unknown", root.display()),
+    }
+    let _ = ROOT.set(std::path::absolute(root).unwrap_or_else(|_| root.to_path_buf()));
+    Ok(())
+}
+
+/// A new temporary directory named after `prefix`: in --temp-dir, next to `near`, or in the system temp directory
+/// when there is neither or the directory of `near` isn't writable
+pub fn dir(prefix: &str, near: Option<&Path>) -> io::Result<Dir> {
+    let prefix = format!(".{}", prefix);
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix);
+    let parent = near.and_then(Path::parent).map(|parent| match parent.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => parent,
+    });
+    let created = match (ROOT.get(), parent) {
+        (Some(root), _) => builder.tempdir_in(root)?,
+        (None, Some(parent)) => builder.tempdir_in(parent).or_else(|err| {
+            debug!("no temporary directory in {}: {}, using the system one", parent.display(), err);
+            builder.tempdir()
+        })?,
+        (None, None) => builder.tempdir()?,
+    };
+    LIVE.lock().unwrap().push(created.path().to_path_buf());
+    Ok(Dir(created))
+}
+
+/// Replace `file` with `signed` from a temporary directory: renamed over it when they are on one volume, so it is
+/// never half written, copied otherwise
+pub fn replace(signed: &Path, file: &Path) -> io::Result<()> {
+    match fs::rename(signed, file) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            debug!("{} could not be renamed over {}: {}, copying it", signed.display(), file.display(), err);
+            fs::copy(signed, file).map(|_| ())
+        }
+    }
+}
+
+/// Remove the temporary directories still there, for an exit that doesn't drop them
+pub fn remove_all() {
+    for path in LIVE.lock().unwrap().drain(..) {
+        let _ = fs::remove_dir_all(&path);
+    }
+}
+
+/// Bytes the current user may still write to the volume of `dir`
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain data, zeroed is a valid value of it
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is null terminated, `stat` is written by statvfs
+    match unsafe { libc::statvfs(path.as_ptr(), &mut stat) } {
+        // the field types differ between platforms
+        #[allow(clippy::unnecessary_cast)]
+        0 => Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64)),
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide` is a null terminated wide string, the totals aren't asked for
+    match unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } {
+        0 => None,
+        _ => Some(available),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_the_file_and_removed() {
+        let temp = tempfile::tempdir().unwrap();
+        let file = temp.path().join("app.exe");
+        let dir = dir("trusted-signing-test", Some(&file)).unwrap();
+        let path = dir.path().to_path_buf();
+        assert_eq!(path.parent(), Some(temp.path()));
+        assert!(LIVE.lock().unwrap().contains(&path));
+
+        fs::write(&file, b"unsigned").unwrap();
+        fs::write(path.join("signed.exe"), b"signed").unwrap();
+        replace(&path.join("signed.exe"), &file).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"signed");
+        drop(dir);
+        assert!(!path.exists() && !LIVE.lock().unwrap().contains(&path));
+
+        assert!(free_space(temp.path()).is_some_and(|free| free > 0));
+        let taken = temp.path().join("taken");
+        fs::write(&taken, b"").unwrap();
+        assert!(init(&taken, 0).unwrap_err().to_string().starts_with("--temp-dir '"));
+        assert!(init(temp.path(), u64::MAX).unwrap_err().to_string().contains("MiB are needed"));
+    }
+}
@@ -0,0 +1,532 @@
+//! `serve`, signing files other machines send over HTTP with the credentials of this one
+
+use crate::{
+    app,
+    audit::{AuditEntry, AuditLog},
+    cli::ServeArgs,
+    error::Error,
+    eventlog::{self, EventLog},
+    events::Events,
+    input, logging,
+    report::Status,
+    scratch,
+    signer::{SignOutcome, Signer, DLIB_VERSION},
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{multipart::MultipartError, ConnectInfo, DefaultBodyLimit, FromRequest, Multipart, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot},
+};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+/// Header naming the file of a raw upload
+const FILE_NAME_HEADER: &str = "x-file-name";
+
+/// A file waiting for the signer, with the directory it was uploaded to so it outlives a timed out request
+struct Job {
+    dir: scratch::Dir,
+    path: PathBuf,
+    reply: oneshot::Sender<(scratch::Dir, SignOutcome, Result<(), Error>)>,
+}
+
+/// Counters of the status endpoint
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    signed: AtomicU64,
+    failed: AtomicU64,
+    rejected: AtomicU64,
+    in_flight: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+#[derive(Serialize)]
+struct StatusBody<'a> {
+    version: &'a str,
+    endpoint: &'a str,
+    account: &'a str,
+    certificate_profile: &'a str,
+    uptime_seconds: u64,
+    requests: u64,
+    signed: u64,
+    failed: u64,
+    rejected: u64,
+    in_flight: u64,
+    bytes_received: u64,
+    bytes_sent: u64,
+}
+
+struct Server {
+    token: String,
+    max_upload: u64,
+    /// Extensions of the files the signer signs, others are rejected before they are received
+    extensions: Vec<String>,
+    timeout: Duration,
+    started: Instant,
+    endpoint: String,
+    account: String,
+    certificate: String,
+    azure_client_id: String,
+    signtool_version: Option<String>,
+    jobs: mpsc::Sender<Job>,
+    audit_log: Option<Mutex<AuditLog>>,
+    /// The --eventlog events of the files, the server writes no run events
+    eventlog: Option<EventLog>,
+    counters: Counters,
+}
+
+/// Why a request was turned down, with the status to answer with
+struct Rejection(StatusCode, String);
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": logging::mask(&self.1) });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+/// A request that didn't end in a signed file, with what the signer did when it got that far
+struct Failure {
+    /// Name of the uploaded file, `-` before there is one
+    name: String,
+    outcome: Option<SignOutcome>,
+    rejection: Rejection,
+}
+
+impl Failure {
+    /// Turn a rejection into the failure of the file `name`
+    fn of(name: &str) -> impl Fn(Rejection) -> Failure + '_ {
+        move |rejection| Failure {
+            name: name.to_string(),
+            outcome: None,
+            rejection,
+        }
+    }
+}
+
+impl From<Rejection> for Failure {
+    fn from(rejection: Rejection) -> Self {
+        Failure::of("-")(rejection)
+    }
+}
+
+/// Compare in time independent of where the values differ, so the token can't be guessed byte by byte
+pub(crate) fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Whether the request carries the bearer token
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|sent| same(sent.trim().as_bytes(), token.as_bytes()))
+}
+
+/// The name an uploaded file is signed as, without any directories a client may have put in it
+fn file_name(sent: &str, extensions: &[String]) -> Result<String, Rejection> {
+    let name = sent.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    let mut components = Path::new(name).components();
+    let single = matches!((components.next(), components.next()), (Some(Component::Normal(_)), None));
+    // a drive like `C:evil.exe`, an alternate data stream like `app.exe:x` or a device like `NUL.exe` on Windows
+    let device = name.split('.').next().unwrap_or_default().trim_end();
+    if !single || name.contains(':') || name.chars().any(char::is_control) || is_device(device) {
+        Err(Rejection(StatusCode::BAD_REQUEST, format!("'{}' is not a file name", sent)))?;
+    }
+    if !input::is_supported(name, extensions) {
+        Err(Rejection(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("'{}' can't be signed, signtool doesn't support its extension", name),
+        ))?;
+    }
+    Ok(name.to_string())
+}
+
+/// Whether `stem` is a name Windows reserves for a device whatever the extension, like `CON` or `COM1`
+fn is_device(stem: &str) -> bool {
+    matches!(
+        stem.to_ascii_uppercase().as_bytes(),
+        b"CON" | b"PRN" | b"AUX" | b"NUL" | [b'C', b'O', b'M', b'1'..=b'9'] | [b'L', b'P', b'T', b'1'..=b'9']
+    )
+}
+
+/// Sign the files of the jobs one after another, on a thread of its own since the signer isn't `Send`.
+/// Reports whether connecting worked before taking any jobs
+fn spawn_signer(args: &ServeArgs, connected: oneshot::Sender<Result<Option<String>, Error>>) -> mpsc::Sender<Job> {
+    let (jobs, mut queue) = mpsc::channel::<Job>(64);
+    // the audit log and the response carry the hashes
+    let options = app::sign_options(&args.sign, args.sign.correlation_id.clone()).map(|options| options.hash(true));
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                let _ = connected.send(Err(Error::Other(format!("signer could not start: {}", err))));
+                return;
+            }
+        };
+        runtime.block_on(async move {
+            let mut signer = match options {
+                Ok(options) => Signer::new(options, Events::default()),
+                Err(err) => {
+                    let _ = connected.send(Err(err));
+                    return;
+                }
+            };
+            if let Err(err) = signer.connect().await {
+                let _ = connected.send(Err(err));
+                return;
+            }
+            let _ = connected.send(Ok(signer.signtool_version().map(str::to_string)));
+            while let Some(job) = queue.recv().await {
+                // the request timed out while it waited
+                if job.reply.is_closed() {
+                    continue;
+                }
+                let (outcome, result) = signer.attempt(&job.path.display().to_string()).await;
+                let _ = job.reply.send((job.dir, outcome, result));
+            }
+        });
+    });
+    jobs
+}
+
+impl Server {
+    fn audit(&self, client: SocketAddr, path: &str, outcome: Option<&SignOutcome>, error: Option<&str>) {
+        if self.audit_log.is_none() && self.eventlog.is_none() {
+            return;
+        }
+        let entry = AuditEntry {
+            timestamp: outcome
+                .map(|outcome| outcome.signed_at.clone())
+                .filter(|signed_at| !signed_at.is_empty())
+                .unwrap_or_else(|| chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+            path: path.to_string(),
+            sha256_before: outcome.and_then(|outcome| outcome.sha256_before.clone()),
+            sha256_after: outcome.and_then(|outcome| outcome.sha256_after.clone()),
+            endpoint: outcome.map_or(&self.endpoint, |outcome| &outcome.target.endpoint),
+            account: outcome.map_or(&self.account, |outcome| &outcome.target.account),
+            certificate_profile: outcome.map_or(&self.certificate, |outcome| &outcome.target.certificate),
+            certificate: outcome.and_then(|outcome| outcome.certificate.as_ref()),
+            azure_client_id: &self.azure_client_id,
+            signtool_version: self.signtool_version.as_deref(),
+            dlib_version: DLIB_VERSION,
+            status: match error {
+                Some(_) => Status::Failed,
+                None => Status::Signed,
+            },
+            error,
+            client: Some(client.to_string()),
+        };
+        if let Some(eventlog) = &self.eventlog {
+            eventlog.write(&eventlog::file_event(&entry, None));
+        }
+        if let Some(Err(err)) = self.audit_log.as_ref().map(|audit_log| audit_log.lock().unwrap().append(&entry)) {
+            warn!("{}", err);
+        }
+    }
+
+    /// Write the upload to `path`, giving up once it is larger than allowed
+    async fn receive(
+        &self,
+        mut chunks: impl Stream<Item = Result<Bytes, String>> + Unpin,
+        path: &Path,
+    ) -> Result<(), Rejection> {
+        let internal = |err: std::io::Error| Rejection(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+        let mut file = tokio::fs::File::create(path).await.map_err(internal)?;
+        let mut size = 0;
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(|err| Rejection(StatusCode::BAD_REQUEST, format!("upload failed: {}", err)))?;
+            size += chunk.len() as u64;
+            if size > self.max_upload {
+                Err(self.too_large())?;
+            }
+            file.write_all(&chunk).await.map_err(internal)?;
+        }
+        file.flush().await.map_err(internal)?;
+        self.counters.bytes_received.fetch_add(size, Ordering::Relaxed);
+        if size == 0 {
+            Err(Rejection(StatusCode::BAD_REQUEST, "the upload is empty".to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn too_large(&self) -> Rejection {
+        Rejection(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("files are limited to {} MiB", self.max_upload / 1024 / 1024),
+        )
+    }
+
+    /// Take the file of a multipart form or a raw body named by the file name header, and sign it
+    async fn sign(&self, request: Request) -> Result<(String, SignOutcome, Response), Failure> {
+        let length = request.headers().get(header::CONTENT_LENGTH).and_then(|value| value.to_str().ok()?.parse().ok());
+        if length.is_some_and(|length: u64| length > self.max_upload) {
+            Err(self.too_large())?;
+        }
+        let multipart = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data"));
+        let dir = scratch::dir("trusted-signing-upload", None)
+            .map_err(|err| Rejection(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+        let name = match multipart {
+            true => {
+                let mut form = Multipart::from_request(request, &())
+                    .await
+                    .map_err(|err| Rejection(StatusCode::BAD_REQUEST, err.body_text()))?;
+                let mut received = None;
+                let field_error = |err: MultipartError| Rejection(err.status(), err.body_text());
+                while let Some(field) = form.next_field().await.map_err(field_error)? {
+                    let Some(sent) = field.file_name().map(str::to_string) else {
+                        continue;
+                    };
+                    let name = file_name(&sent, &self.extensions).map_err(Failure::of(&sent))?;
+                    let chunks = field.map(|chunk| chunk.map_err(|err| err.body_text()));
+                    self.receive(chunks, &dir.path().join(&name)).await.map_err(Failure::of(&name))?;
+                    received = Some(name);
+                    break;
+                }
+                received.ok_or_else(|| Rejection(StatusCode::BAD_REQUEST, "the form has no file".to_string()))?
+            }
+            false => {
+                let missing = || {
+                    Rejection(
+                        StatusCode::BAD_REQUEST,
+                        format!("send the file as multipart/form-data or name it with the {} header", FILE_NAME_HEADER),
+                    )
+                };
+                let sent = request
+                    .headers()
+                    .get(FILE_NAME_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .ok_or_else(missing)?;
+                let name = file_name(&sent, &self.extensions).map_err(Failure::of(&sent))?;
+                let chunks = request.into_body().into_data_stream().map(|chunk| chunk.map_err(|err| err.to_string()));
+                self.receive(chunks, &dir.path().join(&name)).await.map_err(Failure::of(&name))?;
+                name
+            }
+        };
+
+        let (reply, signed) = oneshot::channel();
+        let job = Job {
+            path: dir.path().join(&name),
+            dir,
+            reply,
+        };
+        let stopped = || Rejection(StatusCode::SERVICE_UNAVAILABLE, "the signer stopped".to_string());
+        self.jobs.send(job).await.map_err(|_| Failure::of(&name)(stopped()))?;
+        let (dir, outcome, result) = signed.await.map_err(|_| Failure::of(&name)(stopped()))?;
+        if let Err(err) = result {
+            return Err(Failure {
+                rejection: Rejection(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+                name,
+                outcome: Some(outcome),
+            });
+        }
+
+        let file = tokio::fs::File::open(dir.path().join(&name))
+            .await
+            .map_err(|err| Failure::of(&name)(Rejection(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())))?;
+        let size = file.metadata().await.map(|metadata| metadata.len()).unwrap_or(0);
+        self.counters.bytes_sent.fetch_add(size, Ordering::Relaxed);
+        // the directory goes once the file is sent, after the file is closed so it can be removed on Windows too
+        let body = ReaderStream::new(file).map(move |chunk| {
+            let _dir = &dir;
+            chunk
+        });
+        let mut response = Response::new(Body::from_stream(body));
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+        headers.insert(header::CONTENT_LENGTH, size.into());
+        if let Ok(disposition) = format!("attachment; filename=\"{}\"", name.replace('"', "")).parse() {
+            headers.insert(header::CONTENT_DISPOSITION, disposition);
+        }
+        if let Some(sha256) = outcome.sha256_after.as_deref().and_then(|sha256| sha256.parse().ok()) {
+            headers.insert("x-sha256", sha256);
+        }
+        Ok((name, outcome, response))
+    }
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn status(State(server): State<Arc<Server>>, headers: HeaderMap) -> Response {
+    if !authorized(&headers, &server.token) {
+        return Rejection(StatusCode::UNAUTHORIZED, "missing or wrong token".to_string()).into_response();
+    }
+    let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let counters = &server.counters;
+    Json(StatusBody {
+        version: env!("CARGO_PKG_VERSION"),
+        endpoint: &server.endpoint,
+        account: &server.account,
+        certificate_profile: &server.certificate,
+        uptime_seconds: server.started.elapsed().as_secs(),
+        requests: count(&counters.requests),
+        signed: count(&counters.signed),
+        failed: count(&counters.failed),
+        rejected: count(&counters.rejected),
+        in_flight: count(&counters.in_flight),
+        bytes_received: count(&counters.bytes_received),
+        bytes_sent: count(&counters.bytes_sent),
+    })
+    .into_response()
+}
+
+async fn sign(
+    State(server): State<Arc<Server>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+) -> Response {
+    let started = Instant::now();
+    server.counters.requests.fetch_add(1, Ordering::Relaxed);
+    if !authorized(request.headers(), &server.token) {
+        server.counters.rejected.fetch_add(1, Ordering::Relaxed);
+        warn!("{} sent a request without the right token", client);
+        server.audit(client, "-", None, Some("unauthorized"));
+        return Rejection(StatusCode::UNAUTHORIZED, "missing or wrong token".to_string()).into_response();
+    }
+    server.counters.in_flight.fetch_add(1, Ordering::Relaxed);
+    let result = match tokio::time::timeout(server.timeout, server.sign(request)).await {
+        Ok(result) => result,
+        Err(_) => Err(Failure::from(Rejection(
+            StatusCode::GATEWAY_TIMEOUT,
+            format!("signing took longer than {} seconds", server.timeout.as_secs()),
+        ))),
+    };
+    server.counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+    match result {
+        Ok((name, outcome, response)) => {
+            server.counters.signed.fetch_add(1, Ordering::Relaxed);
+            info!("signed {} for {} in {:.1}s", name, client, started.elapsed().as_secs_f64());
+            server.audit(client, &name, Some(&outcome), None);
+            response
+        }
+        Err(Failure {
+            name,
+            outcome,
+            rejection: Rejection(status, message),
+        }) => {
+            let message = logging::mask(&message);
+            match outcome.is_some() {
+                true => server.counters.failed.fetch_add(1, Ordering::Relaxed),
+                false => server.counters.rejected.fetch_add(1, Ordering::Relaxed),
+            };
+            warn!("{} from {}: {} {}", name, client, status.as_u16(), message);
+            server.audit(client, &name, outcome.as_ref(), Some(&message));
+            Rejection(status, message).into_response()
+        }
+    }
+}
+
+/// Serve until Ctrl+C, letting the requests in flight finish
+pub async fn run(mut args: ServeArgs) -> Result<(), Error> {
+    // anyone who can reach the port gets files signed with the token, it must not be guessable
+    if args.token.trim().len() < 16 {
+        Err(Error::Usage("--token must be at least 16 characters".to_string()))?;
+    }
+    logging::register_secret(&args.token);
+    args.sign.read_service_principal()?;
+    if let Some(dir) = &args.sign.temp_dir {
+        scratch::init(dir, args.max_upload * 1024 * 1024)?;
+    }
+    let audit_log = args.sign.audit_log.as_deref().map(AuditLog::open).transpose()?.map(Mutex::new);
+    let eventlog = args.sign.eventlog.then(|| EventLog::open(&args.sign.eventlog_source)).flatten();
+
+    let (connected, ready) = oneshot::channel();
+    let jobs = spawn_signer(&args, connected);
+    let signtool_version = ready.await.map_err(|_| Error::Other("the signer stopped".to_string()))??;
+
+    let target = args.sign.target();
+    let server = Arc::new(Server {
+        token: args.token.clone(),
+        max_upload: args.max_upload * 1024 * 1024,
+        extensions: args.sign.extensions(),
+        timeout: Duration::from_secs(args.request_timeout),
+        started: Instant::now(),
+        endpoint: target.endpoint,
+        account: target.account,
+        certificate: target.certificate,
+        azure_client_id: args.sign.azure_client_id.clone(),
+        signtool_version,
+        jobs,
+        audit_log,
+        eventlog,
+        counters: Counters::default(),
+    });
+    let router = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/status", get(status))
+        .route("/sign", post(sign))
+        // uploads are streamed to disk and limited there
+        .layer(DefaultBodyLimit::disable())
+        .with_state(server);
+
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .map_err(|err| Error::Usage(format!("could not listen on {}: {}", args.listen, err)))?;
+    info!("listening on http://{}, POST files to /sign", args.listen);
+    axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+            info!("shutting down once the requests in flight are done");
+        })
+        .await
+        .map_err(|err| Error::Other(format!("server failed: {}", err)))?;
+    info!("stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uploads() {
+        let extensions = input::extensions(None, &[]);
+        assert_eq!(file_name("app.exe", &extensions).ok().as_deref(), Some("app.exe"));
+        assert_eq!(file_name(r"..\..\Windows\System32\app.dll", &extensions).ok().as_deref(), Some("app.dll"));
+        assert_eq!(file_name("../setup.msi", &extensions).ok().as_deref(), Some("setup.msi"));
+        assert!(file_name("dist/", &extensions).is_err_and(|rejection| rejection.0 == StatusCode::BAD_REQUEST));
+        for escape in ["C:evil.exe", "a.exe:x", "NUL.exe", "nul .exe", "com1.dll", "LPT9.msi", ".."] {
+            assert!(file_name(escape, &extensions).is_err_and(|rejection| rejection.0 == StatusCode::BAD_REQUEST));
+        }
+        assert_eq!(file_name("console.exe", &extensions).ok().as_deref(), Some("console.exe"));
+        assert_eq!(file_name("COM10.exe", &extensions).ok().as_deref(), Some("COM10.exe"));
+        let unsupported = file_name("notes.txt", &extensions);
+        assert!(unsupported.is_err_and(|rejection| rejection.0 == StatusCode::UNSUPPORTED_MEDIA_TYPE));
+
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "0123456789abcdef"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer 0123456789abcdef"));
+        assert!(authorized(&headers, "0123456789abcdef"));
+        assert!(!authorized(&headers, "0123456789abcdeF"));
+        assert!(!authorized(&headers, "0123456789abcdef0"));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{azure, config, hash};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::debug;
+
+/// How long after a login or check the session is trusted without asking az, which takes a second
+/// or more to start. A build signing dozens of files one call at a time asks once every so often
+const TRUSTED_FOR: Duration = Duration::from_secs(10 * 60);
+
+/// Marker of the last login, so later runs can tell az is still logged in with the same credentials
+fn path() -> PathBuf {
+    config::dir().join("session")
+}
+
+/// Fingerprint of the credentials, a rotated secret means logging in again
+fn fingerprint(tenant_id: &str, client_id: &str, client_secret: &str) -> String {
+    let credentials = format!("{}\n{}\n{}", tenant_id.to_lowercase(), client_id.to_lowercase(), client_secret);
+    hash::hex(&Sha256::digest(credentials.as_bytes()))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Fingerprint and time of the saved session
+fn read() -> Option<(String, u64)> {
+    let saved = fs::read_to_string(path()).ok()?;
+    let mut lines = saved.lines();
+    let fingerprint = lines.next()?.to_string();
+    let checked_at = lines.next().and_then(|line| line.parse().ok()).unwrap_or(0);
+    Some((fingerprint, checked_at))
+}
+
+/// When a sign last logged in or checked the session, with any credentials
+pub fn checked_at() -> Option<SystemTime> {
+    read().map(|(_, checked_at)| UNIX_EPOCH + Duration::from_secs(checked_at))
+}
+
+/// Remember a successful login or check
+pub fn save(tenant_id: &str, client_id: &str, client_secret: &str) -> Result<(), String> {
+    fs::write(path(), format!("{}\n{}\n", fingerprint(tenant_id, client_id, client_secret), now()))
+        .map_err(|err| format!("session marker '{}' could not be written: {:?}", path().display(), err))
+}
+
+/// Whether the last login used these credentials and az is still logged in as that principal,
+/// another tool may have logged az in as someone else since. Within a few minutes of the last
+/// login or check az isn't asked again.
+pub fn reusable(cli_path: &str, tenant_id: &str, client_id: &str, client_secret: &str) -> bool {
+    let expected = fingerprint(tenant_id, client_id, client_secret);
+    let Some((saved, checked_at)) = read().filter(|(saved, _)| *saved == expected) else {
+        debug!("no session of these credentials to reuse");
+        return false;
+    };
+    if now().saturating_sub(checked_at) < TRUSTED_FOR.as_secs() {
+        debug!("session checked {}s ago", now().saturating_sub(checked_at));
+        return true;
+    }
+    match azure::account(cli_path) {
+        Ok(account)
+            if account.tenant_id.eq_ignore_ascii_case(tenant_id) && account.user_name.eq_ignore_ascii_case(client_id) =>
+        {
+            if let Err(err) = fs::write(path(), format!("{}\n{}\n", saved, now())) {
+                debug!("session marker could not be updated: {:?}", err);
+            }
+            true
+        }
+        Ok(_) => {
+            debug!("azure cli is logged in as another principal");
+            false
+        }
+        Err(err) => {
+            debug!("azure cli session can't be reused: {}", err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_changes_with_the_secret() {
+        let saved = fingerprint("Tenant", "Client", "secret");
+        assert_eq!(saved, fingerprint("tenant", "client", "secret"));
+        assert_ne!(saved, fingerprint("tenant", "client", "rotated"));
+        assert!(!saved.contains("secret"));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{
+    azure,
+    cli::Cli,
+    config::{self, Resolved},
+    dotenv::EnvFile,
+    input,
+    signtool,
+};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory};
+use serde::Serialize;
+use std::path::Path;
+
+/// An effective setting of `config show`
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub name: String,
+    /// Secrets are masked, `None` when nothing set the argument
+    pub value: Option<String>,
+    pub source: String,
+}
+
+/// A tool a sign would run or load
+#[derive(Serialize, Debug)]
+pub struct Dependency {
+    pub path: String,
+    pub exists: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Why a sign would refuse it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problem: Option<String>,
+}
+
+impl Dependency {
+    /// The signtool at `path` with its file version, or the SDK version of its location when that can't be read,
+    /// and whether it is too old for the signing client
+    pub fn sign_tool(path: String) -> Self {
+        let exists = Path::new(&path).is_file();
+        let version = signtool::file_version(&path).map(|version| version.to_string());
+        Dependency {
+            exists,
+            version: version.or_else(|| signtool::version(&path).map(str::to_string)),
+            problem: exists.then(|| signtool::check_version(&path).err()).flatten(),
+            path,
+        }
+    }
+}
+
+/// Everything `config show` prints
+#[derive(Serialize, Debug)]
+pub struct Effective {
+    pub settings: Vec<Setting>,
+    pub sign_tool: Dependency,
+    pub azure_cli: Dependency,
+    pub signing_client: Dependency,
+    /// Extensions of the files signtool would sign
+    pub extensions: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Where an argument's value came from, a config file looks like a flag to clap
+fn source(id: &str, env: Option<&str>, sign: &ArgMatches, resolved: &Resolved, env_file: &EnvFile) -> String {
+    match sign.value_source(id) {
+        Some(ValueSource::CommandLine) => resolved
+            .sources
+            .iter()
+            .find(|(source_id, _)| source_id == id)
+            .map(|(_, source)| source.clone())
+            .unwrap_or_else(|| "flag".to_string()),
+        Some(ValueSource::EnvVariable) => {
+            let env = env.unwrap_or_default();
+            match (&env_file.path, env_file.keys.iter().any(|key| key == env)) {
+                (Some(path), true) => format!("env {} from {}", env, path.display()),
+                _ => format!("env {}", env),
+            }
+        }
+        Some(ValueSource::DefaultValue) => "default".to_string(),
+        _ => "unset".to_string(),
+    }
+}
+
+/// Where the value of the sign argument `id` came from, like `env AZURE_CLIENT_SECRET from .env`
+pub fn source_of(id: &str, sign: &ArgMatches, resolved: &Resolved, env_file: &EnvFile) -> String {
+    let command = Cli::command();
+    let sign_command = command.find_subcommand("sign").expect("sign is a subcommand");
+    let arg = sign_command.get_arguments().find(|arg| arg.get_id() == id);
+    source(id, arg.and_then(|arg| arg.get_env()).and_then(|env| env.to_str()), sign, resolved, env_file)
+}
+
+/// The settings a sign with the same arguments would use, nothing is signed and az is only asked for its version
+pub fn effective(sign: &ArgMatches, resolved: &Resolved, env_file: &EnvFile, dlib_version: &str) -> Effective {
+    let command = Cli::command();
+    let sign_command = command.find_subcommand("sign").expect("sign is a subcommand");
+
+    let settings = sign_command
+        .get_arguments()
+        .filter(|arg| !["help", "version"].contains(&arg.get_id().as_str()))
+        .map(|arg| {
+            let id = arg.get_id().as_str();
+            let env = arg.get_env().and_then(|env| env.to_str());
+            // parsed values where they are strings, so -e eus shows the endpoint URL
+            let values: Option<Vec<String>> = match sign.try_get_many::<String>(id) {
+                Ok(values) => values.map(|values| values.cloned().collect()),
+                Err(_) => sign
+                    .get_raw(id)
+                    .map(|values| values.map(|value| value.to_string_lossy().into_owned()).collect()),
+            };
+            let value = values.map(|values| {
+                match config::SECRETS.contains(&id) {
+                    true => "***".to_string(),
+                    false => values.join(", "),
+                }
+            });
+            Setting {
+                name: arg.get_long().unwrap_or(id).to_string(),
+                value,
+                source: source(id, env, sign, resolved, env_file),
+            }
+        })
+        .collect();
+
+    let path_of = |id: &str| {
+        sign.get_raw(id)
+            .into_iter()
+            .flatten()
+            .next()
+            .map(|value| value.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let sign_tool_path = path_of("sing_tool_path");
+    let azure_cli_path = path_of("azure_cli_path");
+    let lib_path = config::lib_path();
+    let azure_cli_exists = Path::new(&azure_cli_path).is_file();
+    let azure_cli_version = azure_cli_exists.then(|| azure::cli_version(&azure_cli_path)).flatten();
+    let mut warnings: Vec<String> = resolved.warnings.iter().chain(&env_file.warnings).cloned().collect();
+    warnings.extend(azure_cli_version.and_then(azure::baseline_warning));
+
+    let strings = |id: &str| -> Option<Vec<String>> {
+        let values = sign.try_get_many::<String>(id).ok().flatten()?;
+        Some(values.cloned().collect())
+    };
+    let extra = strings("extra_extensions").unwrap_or_default();
+    let extensions = input::extensions(strings("extensions").as_deref(), &extra);
+
+    Effective {
+        settings,
+        sign_tool: Dependency::sign_tool(sign_tool_path),
+        azure_cli: Dependency {
+            exists: azure_cli_exists,
+            version: azure_cli_version.map(|version| version.to_string()),
+            problem: None,
+            path: azure_cli_path,
+        },
+        signing_client: Dependency {
+            path: lib_path.display().to_string(),
+            exists: lib_path.is_file(),
+            version: Some(dlib_version.to_string()),
+            problem: None,
+        },
+        extensions,
+        warnings,
+    }
+}
+
+impl Effective {
+    /// Aligned table of the settings and dependencies
+    pub fn table(&self) -> String {
+        let width = self.settings.iter().map(|setting| setting.name.len()).max().unwrap_or(0);
+        let mut table = String::from("Settings\n");
+        for setting in &self.settings {
+            table.push_str(&format!(
+                "  {:<width$}  {}  ({})\n",
+                setting.name,
+                setting.value.as_deref().unwrap_or("-"),
+                setting.source,
+                width = width
+            ));
+        }
+
+        table.push_str("\nDependencies\n");
+        let dependencies = [
+            ("signtool", &self.sign_tool, "missing"),
+            ("azure cli", &self.azure_cli, "missing"),
+            ("signing client", &self.signing_client, "downloaded on the first sign"),
+        ];
+        for (name, dependency, absent) in dependencies {
+            let version = match &dependency.version {
+                Some(version) => format!(" {}", version),
+                None => String::new(),
+            };
+            let state = match (dependency.exists, &dependency.problem) {
+                (true, None) => "found",
+                (true, Some(_)) => "too old for the signing client",
+                (false, _) => absent,
+            };
+            table.push_str(&format!("  {:<15} {}{}  ({})\n", name, dependency.path, version, state));
+        }
+
+        table.push_str(&format!("\nExtensions\n  {}\n", self.extensions.join(", ")));
+        for warning in &self.warnings {
+            table.push_str(&format!("\nwarning: {}", warning));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+
+    #[test]
+    fn sources_and_masking() {
+        let argv = ["tsc", "config", "show", "-a", "from-flag", "--azure-client-secret", "hunter2", "--certificate", "c"];
+        let argv: Vec<OsString> = argv.iter().map(OsString::from).collect();
+        let mut resolved = Resolved {
+            argv,
+            ..Resolved::default()
+        };
+        resolved.sources.push(("certificate".to_string(), "profile 'release' from config.toml".to_string()));
+        let matches = Cli::command().ignore_errors(true).try_get_matches_from(&resolved.argv).unwrap();
+        let sign = crate::cli::sign_matches(&matches).unwrap();
+
+        let effective = effective(sign, &resolved, &EnvFile::default(), "1.0.60");
+        let setting = |name: &str| effective.settings.iter().find(|setting| setting.name == name).unwrap();
+        assert_eq!(setting("account").value.as_deref(), Some("from-flag"));
+        assert_eq!(setting("account").source, "flag");
+        assert_eq!(setting("certificate").source, "profile 'release' from config.toml");
+        assert_eq!(setting("azure-client-secret").value.as_deref(), Some("***"));
+        assert_eq!(setting("fd").source, "default");
+        assert_eq!(setting("description").value, None);
+        assert!(!effective.table().contains("hunter2"));
+        assert_eq!(effective.extensions.len(), input::DEFAULT_EXTENSIONS.len());
+    }
+}
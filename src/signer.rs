@@ -0,0 +1,2574 @@
+use crate::{
+    arch::{self, Arch, Subsystem},
+    azure::{self, ProfileCheck},
+    burn,
+    certificate::{self, SigningCertificate},
+    clickonce, clock, config, discover,
+    error::Error,
+    events::{Event, Events},
+    hash, hints, input,
+    inspect::{self, ObservedDigest},
+    interrupt,
+    keyvault, logging,
+    metrics::Metrics,
+    msix, network, nuget, pe, pending, permissions,
+    pin::{self, Pin},
+    psmodule, quota, redact, rest,
+    revocation::{self, Offline},
+    route::{self, Profile, Route, Target},
+    scratch, session,
+    signtool::{self, Detached, Digest, P7Content, Template, TIMESTAMP_URL},
+    squirrel, status, timestamp, unzip, verify, vsix,
+};
+#[cfg(feature = "download")]
+use crate::{download, feed};
+use clap::ValueEnum;
+#[cfg(feature = "download")]
+use reqwest::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::{
+    ffi::OsString,
+    fs,
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tempfile::NamedTempFile;
+use tracing::{debug, debug_span, info, warn};
+
+/// Version of the Microsoft.Trusted.Signing.Client package providing the dlib
+pub const DLIB_VERSION: &str = "1.0.60";
+
+/// Why --exclude-signed-by skipped a file
+pub const PROTECTED: &str = "signed by a protected signer";
+
+/// How often the timestamp authority is tried again after --defer-timestamp-on-failure signed a file without one
+const DEFERRED_RETRIES: u32 = 2;
+
+/// The digests in the signatures of a signed file, with an error for --require-sha2 when one of them isn't SHA-2
+/// or they can't be read
+fn sha2_only(file: &str) -> (Vec<ObservedDigest>, Result<(), Error>) {
+    let failure = |message: String| Err(Error::Signing { path: file.to_string(), message, output: String::new() });
+    let inspection = inspect::inspect(file);
+    if let Some(error) = inspection.error {
+        let message = format!("the signature of '{}' can't be checked for --require-sha2: {}", file, error);
+        return (Vec::new(), failure(message));
+    }
+    let digests = inspect::observed_digests(&inspection.signatures);
+    let weak: Vec<String> = digests
+        .iter()
+        .filter(|observed| !inspect::is_sha2(&observed.digest))
+        .map(|observed| match observed.of {
+            "signature" => format!("{} for signature {}", observed.digest, observed.signature),
+            of => format!("{} for the {} of signature {}", observed.digest, of, observed.signature),
+        })
+        .collect();
+    let result = match (digests.is_empty(), weak.is_empty()) {
+        (true, _) => failure(format!("'{}' has no signature to check for --require-sha2", file)),
+        (false, true) => Ok(()),
+        (false, false) => {
+            failure(format!("'{}' uses digests other than SHA-2 (--require-sha2): {}", file, weak.join(", ")))
+        }
+    };
+    (digests, result)
+}
+
+/// The creation, modification and access times of a file, for --preserve-timestamps
+fn file_times(file: &str) -> Result<fs::FileTimes, String> {
+    let metadata = fs::metadata(file).map_err(|err| err.to_string())?;
+    let times = fs::FileTimes::new()
+        .set_accessed(metadata.accessed().map_err(|err| err.to_string())?)
+        .set_modified(metadata.modified().map_err(|err| err.to_string())?);
+    #[cfg(windows)]
+    let times = {
+        use std::os::windows::fs::FileTimesExt;
+        times.set_created(metadata.created().map_err(|err| err.to_string())?)
+    };
+    Ok(times)
+}
+
+/// Set the times of a file back to the ones it had before signing, also when signing replaced it with a new file
+fn restore_times(file: &str, times: fs::FileTimes) -> Result<(), String> {
+    let file = fs::OpenOptions::new().write(true).open(file).map_err(|err| err.to_string())?;
+    file.set_times(times).map_err(|err| err.to_string())
+}
+
+/// The revocation checks of the chains of a signed file, with an error for --verify-revocation when a certificate is
+/// revoked or the chain doesn't verify, or its status couldn't be checked and `offline` fails that
+fn revocation_checked(file: &str, offline: Offline) -> (Vec<revocation::Check>, Result<(), Error>) {
+    let failure = |message: String| Err(Error::Signing { path: file.to_string(), message, output: String::new() });
+    let checks = match revocation::check(file) {
+        Ok(checks) => checks,
+        Err(err) => return (Vec::new(), failure(format!("the chain of '{}' could not be checked: {}", file, err))),
+    };
+    let worst = checks.iter().map(|check| check.status).max().unwrap_or(revocation::Status::Invalid);
+    let failed = |status: revocation::Status| {
+        let failed: Vec<String> = checks
+            .iter()
+            .filter(|check| check.status == status)
+            .flat_map(|check| check.chain.iter().filter(|element| element.status == status))
+            .map(|element| format!("{} ({})", element.subject, element.errors.join(", ")))
+            .collect();
+        failed.join("; ")
+    };
+    let result = match (worst, offline) {
+        (revocation::Status::Good, _) => Ok(()),
+        (revocation::Status::Unknown, Offline::Warn) => {
+            let unknown = failed(worst);
+            warn!("the revocation status of '{}' could not be checked, mostly without network: {}", file, unknown);
+            Ok(())
+        }
+        (revocation::Status::Unknown, Offline::Fail) => failure(format!(
+            "the revocation status of '{}' could not be checked and --revocation-offline is fail: {}",
+            file,
+            failed(worst)
+        )),
+        (revocation::Status::Invalid, _) => {
+            failure(format!("the chain of '{}' doesn't verify with revocation checks: {}", file, failed(worst)))
+        }
+        (revocation::Status::Revoked, _) => {
+            failure(format!("'{}' is signed with a revoked certificate: {}", file, failed(worst)))
+        }
+    };
+    (checks, result)
+}
+
+/// Warnings for the certificate profiles signed with that aren't active or expire within `days`. A principal that
+/// may not read them only gets a debug note, the check never fails the run
+fn expiry_warnings(options: &SignOptions, days: i64) -> Vec<String> {
+    let today = chrono::Utc::now().date_naive();
+    let mut warnings = Vec::new();
+    for (account, certificate) in options.profiles() {
+        let profiles = match azure::certificate_profiles(&options.azure_cli_path, &account) {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                debug!("the expiry of certificate profile {} could not be checked: {}", certificate, err);
+                continue;
+            }
+        };
+        match profiles.iter().find(|profile| profile.name.eq_ignore_ascii_case(&certificate)) {
+            Some(profile) => warnings.extend(azure::expiry_warnings(&account, profile, days, today)),
+            None => debug!("certificate profile {} is not among the profiles of {}", certificate, account),
+        }
+    }
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    warnings
+}
+
+/// Fail unless there is a signtool at `path`
+fn check_signtool(path: &str) -> Result<(), Error> {
+    match fs::metadata(path) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::MissingDependency {
+            component: "signtool",
+            message: format!("signtool {} does not exists, please specify PATH with env SIGNTOOL_PATH", path),
+        }),
+    }
+}
+
+/// Install the signing client from a local package, checking it is the one and the version this build expects and
+/// unpacking it like a downloaded one
+fn install_package(package: &Path, target_dir: &Path, lib_path: &Path) -> Result<(), Error> {
+    let invalid = |message: String| Error::MissingDependency { component: "signing client", message };
+    let nuspec = unzip::nuspec(package).map_err(invalid)?;
+    if !nuspec.to_ascii_lowercase().contains("<id>microsoft.trusted.signing.client</id>") {
+        Err(invalid(format!("'{}' is not a Microsoft.Trusted.Signing.Client package", package.display())))?;
+    }
+    let version = status::package_version(&nuspec).unwrap_or_default();
+    if version != DLIB_VERSION {
+        Err(Error::Usage(format!(
+            "'{}' is version {} of the signing client, this build of trusted-signing-cli needs {}",
+            package.display(),
+            match version.is_empty() {
+                true => "unknown",
+                false => &version,
+            },
+            DLIB_VERSION
+        )))?;
+    }
+    info!("installing Trusted Signing client {} from {}", version, package.display());
+    unzip::install(package, target_dir, unzip::MAX_SIZE)
+        .map_err(|err| invalid(format!("signing client can't be unzipped: {}", err)))?;
+    if !lib_path.is_file() {
+        Err(invalid(format!("'{}' has no {}", package.display(), lib_path.display())))?;
+    }
+    Ok(())
+}
+
+/// Metadata object
+#[derive(Serialize, Deserialize, Debug)]
+struct Metadata {
+    #[serde(rename = "Endpoint")]
+    endpoint: String,
+
+    #[serde(rename = "CodeSigningAccountName")]
+    code_signing_account_name: String,
+
+    #[serde(rename = "CertificateProfileName")]
+    certificate_profile: String,
+
+    /// Attached to the service requests, for tracing a signing on the Azure side
+    #[serde(rename = "CorrelationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+}
+
+impl Metadata {
+    fn new(target: &Target, correlation_id: Option<String>) -> Self {
+        Metadata {
+            endpoint: target.endpoint.clone(),
+            code_signing_account_name: target.account.clone(),
+            certificate_profile: target.certificate.clone(),
+            correlation_id,
+        }
+    }
+
+    /// Write the metadata to a new temp file in `dir`, removed again when dropped
+    fn write_temp(&self, dir: &Path) -> Result<NamedTempFile, Error> {
+        let mut file = tempfile::Builder::new()
+            .prefix("metadata-")
+            .suffix(".json")
+            .tempfile_in(dir)
+            .map_err(|err| format!("metadata file could not be created in '{}': {}", dir.display(), err))?;
+        serde_json::to_writer(&mut file, self).map_err(|err| format!("metadata file could not be written: {:?}", err))?;
+        debug!("using metadata {}", file.path().display());
+        Ok(file)
+    }
+
+    /// Write the metadata to a file in `dir` named after its content, kept for later runs with the
+    /// same metadata so they don't have to write it again
+    fn write_kept(&self, dir: &Path) -> Result<PathBuf, Error> {
+        let content =
+            serde_json::to_string(self).map_err(|err| format!("metadata file could not be written: {:?}", err))?;
+        let name = hash::hex(&Sha256::digest(content.as_bytes()));
+        let path = dir.join(format!("metadata-{}.json", &name[..16]));
+        if !path.is_file() {
+            // written next to it and renamed, a concurrent run never sees half a file
+            let mut file = tempfile::Builder::new()
+                .prefix("metadata-")
+                .tempfile_in(dir)
+                .map_err(|err| format!("metadata file could not be created in '{}': {}", dir.display(), err))?;
+            file.write_all(content.as_bytes())
+                .map_err(|err| format!("metadata file could not be written: {:?}", err))?;
+            file.persist(&path)
+                .map_err(|err| format!("metadata file '{}' could not be written: {}", path.display(), err))?;
+        }
+        debug!("using metadata {}", path.display());
+        Ok(path)
+    }
+
+    /// Write the metadata to a temp file, or to a kept one
+    fn write(&self, dir: &Path, keep: bool) -> Result<MetadataFile, Error> {
+        match keep {
+            true => self.write_kept(dir).map(MetadataFile::Kept),
+            false => self.write_temp(dir).map(MetadataFile::Temp),
+        }
+    }
+}
+
+/// A metadata file signtool reads
+#[derive(Debug)]
+enum MetadataFile {
+    /// Removed again when dropped
+    Temp(NamedTempFile),
+    /// Left in place for later runs
+    Kept(PathBuf),
+}
+
+impl MetadataFile {
+    fn path(&self) -> &Path {
+        match self {
+            MetadataFile::Temp(file) => file.path(),
+            MetadataFile::Kept(path) => path,
+        }
+    }
+}
+
+/// Where signatures come from
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Trusted Signing, through signtool and its dlib
+    TrustedSigning,
+    /// A certificate in Azure Key Vault, through AzureSignTool
+    #[value(name = "keyvault")]
+    #[serde(rename = "keyvault")]
+    KeyVault,
+    /// Experimental, Trusted Signing's REST API with osslsigncode, for PE files on Linux and macOS
+    Rest,
+    /// A local PFX through signtool, test signatures for development and pull request builds
+    Pfx,
+}
+
+/// How to sign, the library side of the `sign` arguments
+#[derive(Debug, Clone)]
+pub struct SignOptions {
+    backend: Backend,
+    /// With Key Vault the endpoint is the vault URI, the account its name and the certificate the Key Vault
+    /// certificate
+    target: Target,
+    azure_tenant_id: String,
+    azure_client_id: String,
+    azure_client_secret: String,
+    azure_cli_path: String,
+    sign_tool_path: String,
+    verify_with_signtool: bool,
+    file_digest: Digest,
+    timestamp_url: String,
+    timestamp_digest: Digest,
+    description: Option<String>,
+    file_descriptions: Vec<(PathBuf, String)>,
+    append_signature: bool,
+    detached_p7: Option<PathBuf>,
+    p7_content_type: String,
+    p7_content: P7Content,
+    signtool_template: Option<Template>,
+    signtool_extra_args: Vec<String>,
+    correlation_id: Option<String>,
+    correlation_per_file: bool,
+    routes: Vec<Route>,
+    also_profile: Option<Profile>,
+    ignore_unsupported: bool,
+    skip_invalid: bool,
+    extensions: Vec<String>,
+    validate_profile: bool,
+    show_account: bool,
+    reuse_session: bool,
+    keep_metadata: bool,
+    strict_permissions: bool,
+    check_clock: bool,
+    strict_clock: bool,
+    preflight_network: bool,
+    allow_custom_endpoint: bool,
+    fallback_endpoints: Vec<String>,
+    fail_on_warnings: bool,
+    require_sha2: bool,
+    validate_pe: bool,
+    expect_arch: Option<Arch>,
+    expect_subsystem: Option<Subsystem>,
+    expect_certificate: certificate::Expected,
+    exclude_signed_by: Vec<certificate::Protected>,
+    pin: Pin,
+    check_scripts: bool,
+    verify_revocation: Option<Offline>,
+    wait_for_file: Option<Duration>,
+    preserve_timestamps: bool,
+    defer_timestamp: bool,
+    warn_expiry: Option<i64>,
+    quota_warn: Option<u64>,
+    quota_hard_limit: Option<u64>,
+    #[cfg(feature = "download")]
+    dlib_feed: Option<String>,
+    #[cfg(feature = "download")]
+    feed_token: Option<String>,
+    dlib_package: Option<PathBuf>,
+    nuget_certificate: Option<String>,
+    dotnet_path: Option<String>,
+    vsix_tool: Option<String>,
+    clickonce_certificate: Option<String>,
+    mage_path: Option<String>,
+    deep_bundles: bool,
+    burn_bundles: bool,
+    wix_path: Option<String>,
+    squirrel: bool,
+    page_hashes: bool,
+    makeappx_path: Option<String>,
+    publisher: Option<String>,
+    skip_publisher_check: bool,
+    azure_sign_tool_path: String,
+    osslsigncode_path: String,
+    /// The PFX signtool signs with under --backend pfx
+    pfx: Option<PathBuf>,
+    pfx_password: String,
+    hash: bool,
+}
+
+impl SignOptions {
+    /// How signtool is asked to sign
+    fn signtool(&self) -> signtool::Options<'_> {
+        signtool::Options {
+            file_digest: self.file_digest,
+            timestamp_url: &self.timestamp_url,
+            timestamp_digest: self.timestamp_digest,
+            description: self.description.as_deref(),
+            append: self.append_signature,
+            page_hashes: false,
+            detached: self.detached_p7.as_deref().map(|dir| Detached {
+                dir,
+                content_type: &self.p7_content_type,
+                content: self.p7_content,
+            }),
+            extra: &self.signtool_extra_args,
+            template: self.signtool_template.as_ref(),
+        }
+    }
+
+    /// The description `file` is signed with
+    fn description_of(&self, file: &str) -> Option<&str> {
+        let path = std::path::absolute(file).unwrap_or_else(|_| file.into());
+        let own = self.file_descriptions.iter().find(|(described, _)| *described == path);
+        own.map(|(_, description)| description.as_str()).or(self.description.as_deref())
+    }
+
+    /// The accounts and certificate profiles of the target, the routes and --also-profile, each once
+    fn profiles(&self) -> Vec<(String, String)> {
+        let mut profiles = vec![(self.target.account.clone(), self.target.certificate.clone())];
+        for route in &self.routes {
+            let account = route.account.clone().unwrap_or_else(|| self.target.account.clone());
+            let profile = (account, route.certificate.clone());
+            if !profiles.contains(&profile) {
+                profiles.push(profile);
+            }
+        }
+        if let Some(also) = &self.also_profile {
+            let also = also.target(&self.target);
+            if !profiles.contains(&(also.account.clone(), also.certificate.clone())) {
+                profiles.push((also.account, also.certificate));
+            }
+        }
+        profiles
+    }
+
+    /// Check the endpoints of the target and the routes before anything is downloaded or logged in to. Unknown hosts
+    /// are only warned about when custom endpoints are allowed
+    fn check_endpoints(&self) -> Result<(), Error> {
+        let routed = self.routes.iter().filter_map(|route| route.endpoint.as_deref());
+        let also = self.also_profile.iter().filter_map(|also| also.endpoint.as_deref());
+        let fallbacks = self.fallback_endpoints.iter().map(String::as_str);
+        let mut endpoints: Vec<&str> =
+            std::iter::once(self.target.endpoint.as_str()).chain(routed).chain(also).chain(fallbacks).collect();
+        endpoints.dedup();
+        for endpoint in endpoints {
+            match (crate::endpoint::check(endpoint), self.allow_custom_endpoint) {
+                (Ok(()), _) => (),
+                (Err(err), true) => warn!("{}, signing with it as --allow-custom-endpoint is set", err),
+                (Err(err), false) => {
+                    Err(Error::Usage(format!("{}, pass --allow-custom-endpoint to use it anyway", err)))?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// How `dotnet nuget sign` is asked to sign, when packages are signed at all
+    fn nuget_options(&self) -> Option<nuget::Options<'_>> {
+        Some(nuget::Options {
+            certificate_fingerprint: self.nuget_certificate.as_deref()?,
+            file_digest: self.file_digest,
+            timestamp_url: &self.timestamp_url,
+            timestamp_digest: self.timestamp_digest,
+            append: self.append_signature,
+        })
+    }
+
+    /// Sign with `certificate` of `account` at `endpoint`, a URL or region code, everything else
+    /// has the defaults of the command line
+    pub fn new(endpoint: &str, account: &str, certificate: &str) -> Result<Self, Error> {
+        Ok(SignOptions {
+            backend: Backend::TrustedSigning,
+            target: Target {
+                endpoint: crate::endpoint::normalize(endpoint).map_err(Error::Usage)?,
+                account: account.to_string(),
+                certificate: certificate.to_string(),
+            },
+            azure_tenant_id: String::new(),
+            azure_client_id: String::new(),
+            azure_client_secret: String::new(),
+            azure_cli_path: azure::DEFAULT_CLI_PATH.to_string(),
+            sign_tool_path: signtool::DEFAULT_PATH.to_string(),
+            verify_with_signtool: false,
+            file_digest: Digest::Sha256,
+            timestamp_url: TIMESTAMP_URL.to_string(),
+            timestamp_digest: Digest::Sha256,
+            description: None,
+            file_descriptions: Vec::new(),
+            append_signature: false,
+            detached_p7: None,
+            p7_content_type: String::new(),
+            p7_content: P7Content::DetachedSignedData,
+            signtool_template: None,
+            signtool_extra_args: Vec::new(),
+            correlation_id: None,
+            correlation_per_file: false,
+            routes: Vec::new(),
+            also_profile: None,
+            ignore_unsupported: false,
+            skip_invalid: false,
+            extensions: input::extensions(None, &[]),
+            validate_profile: false,
+            show_account: false,
+            reuse_session: false,
+            keep_metadata: false,
+            strict_permissions: false,
+            check_clock: true,
+            strict_clock: false,
+            preflight_network: false,
+            allow_custom_endpoint: false,
+            fallback_endpoints: Vec::new(),
+            fail_on_warnings: false,
+            require_sha2: false,
+            validate_pe: false,
+            expect_arch: None,
+            expect_subsystem: None,
+            expect_certificate: Default::default(),
+            exclude_signed_by: Vec::new(),
+            pin: Pin::default(),
+            check_scripts: false,
+            verify_revocation: None,
+            wait_for_file: None,
+            preserve_timestamps: false,
+            defer_timestamp: false,
+            warn_expiry: None,
+            quota_warn: None,
+            quota_hard_limit: None,
+            #[cfg(feature = "download")]
+            dlib_feed: None,
+            #[cfg(feature = "download")]
+            feed_token: None,
+            dlib_package: None,
+            nuget_certificate: None,
+            dotnet_path: None,
+            vsix_tool: None,
+            clickonce_certificate: None,
+            mage_path: None,
+            deep_bundles: false,
+            burn_bundles: false,
+            wix_path: None,
+            squirrel: false,
+            page_hashes: false,
+            makeappx_path: None,
+            publisher: None,
+            skip_publisher_check: false,
+            azure_sign_tool_path: keyvault::DEFAULT_TOOL.to_string(),
+            osslsigncode_path: rest::DEFAULT_OSSLSIGNCODE.to_string(),
+            pfx: None,
+            pfx_password: String::new(),
+            hash: false,
+        })
+    }
+
+    /// Sign with the certificate and key of the PFX at `pfx` through signtool, for test signatures without a login,
+    /// the signing client or its metadata. An empty `password` is for a PFX without one
+    pub fn pfx(pfx: &Path, password: &str) -> Result<Self, Error> {
+        Ok(SignOptions {
+            backend: Backend::Pfx,
+            target: Target {
+                endpoint: pfx.display().to_string(),
+                account: "local".to_string(),
+                certificate: pfx.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            },
+            pfx: Some(pfx.to_path_buf()),
+            pfx_password: password.to_string(),
+            // any region, the target is replaced
+            ..SignOptions::new("eus", "", "")?
+        })
+    }
+
+    /// Sign with the Key Vault certificate `certificate` of the vault at `vault_uri` through AzureSignTool, the
+    /// credentials are the same. Routes pick another certificate of the vault
+    pub fn key_vault(vault_uri: &str, certificate: &str) -> Result<Self, Error> {
+        let vault_uri = keyvault::parse_uri(vault_uri).map_err(Error::Usage)?;
+        Ok(SignOptions {
+            backend: Backend::KeyVault,
+            target: Target {
+                account: keyvault::vault_name(&vault_uri).to_string(),
+                endpoint: vault_uri,
+                certificate: certificate.to_string(),
+            },
+            // any region, the target is replaced
+            ..SignOptions::new("eus", "", certificate)?
+        })
+    }
+
+    /// Service principal to log in to azure cli with
+    pub fn credentials(mut self, tenant_id: &str, client_id: &str, client_secret: &str) -> Self {
+        self.azure_tenant_id = tenant_id.to_string();
+        self.azure_client_id = client_id.to_string();
+        self.azure_client_secret = client_secret.to_string();
+        self
+    }
+
+    pub fn azure_cli_path(mut self, path: &str) -> Self {
+        self.azure_cli_path = path.to_string();
+        self
+    }
+
+    pub fn sign_tool_path(mut self, path: &str) -> Self {
+        self.sign_tool_path = path.to_string();
+        self
+    }
+
+    /// Verify signed files with `signtool verify` instead of WinVerifyTrust
+    pub fn verify_with_signtool(mut self, with_signtool: bool) -> Self {
+        self.verify_with_signtool = with_signtool;
+        self
+    }
+
+    /// Sign through Trusted Signing's REST API and osslsigncode instead of signtool, which works off Windows but
+    /// only for PE files
+    pub fn rest(mut self, rest: bool) -> Self {
+        self.backend = match rest {
+            true => Backend::Rest,
+            false => Backend::TrustedSigning,
+        };
+        self
+    }
+
+    /// osslsigncode to embed signatures with under the REST backend, a path or a name on the PATH
+    pub fn osslsigncode_path(mut self, path: &str) -> Self {
+        self.osslsigncode_path = path.to_string();
+        self
+    }
+
+    /// AzureSignTool to sign with under the Key Vault backend, a path or a name on the PATH
+    pub fn azure_sign_tool_path(mut self, path: &str) -> Self {
+        self.azure_sign_tool_path = path.to_string();
+        self
+    }
+
+    /// Digest of `/fd`
+    pub fn file_digest(mut self, digest: Digest) -> Self {
+        self.file_digest = digest;
+        self
+    }
+
+    /// Timestamp server of `/tr` and the digest of `/td`
+    pub fn timestamp(mut self, url: &str, digest: Digest) -> Self {
+        self.timestamp_url = url.to_string();
+        self.timestamp_digest = digest;
+        self
+    }
+
+    /// Description shown in the UAC prompt
+    pub fn description(mut self, description: Option<String>) -> Self {
+        self.description = description;
+        self
+    }
+
+    /// Descriptions of single files instead of --description, by path
+    pub fn file_descriptions(mut self, descriptions: Vec<(String, String)>) -> Self {
+        let absolute = |file: String| std::path::absolute(&file).unwrap_or_else(|_| file.into());
+        self.file_descriptions = descriptions.into_iter().map(|(file, text)| (absolute(file), text)).collect();
+        self
+    }
+
+    /// Add the signature next to the existing ones (`/as`), for a second signature with another digest
+    pub fn append_signature(mut self, append: bool) -> Self {
+        self.append_signature = append;
+        self
+    }
+
+    /// Write a detached PKCS#7 of every file into `dir` instead of signing the file, of the content type
+    /// `content_type`
+    pub fn detached_p7(mut self, dir: Option<PathBuf>, content_type: &str, content: P7Content) -> Self {
+        self.detached_p7 = dir;
+        self.p7_content_type = content_type.to_string();
+        self.p7_content = content;
+        self
+    }
+
+    /// Arrange the arguments of signtool with `template`, with `extra` for its `{extra}`
+    pub fn signtool_template(mut self, template: Option<Template>, extra: Vec<String>) -> Self {
+        self.signtool_template = template;
+        self.signtool_extra_args = extra;
+        self
+    }
+
+    /// Correlation id of every signing request
+    pub fn correlation_id(mut self, correlation_id: Option<String>) -> Self {
+        self.correlation_id = correlation_id;
+        self
+    }
+
+    /// Generate a correlation id for every file instead
+    pub fn correlation_per_file(mut self, per_file: bool) -> Self {
+        self.correlation_per_file = per_file;
+        self
+    }
+
+    /// Sign files matching the route with its certificate profile, the first matching route wins
+    pub fn route(mut self, route: Route) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Append a signature of this certificate profile after the one of the file's own, its account and endpoint
+    /// default to the target's
+    pub fn also_profile(mut self, profile: Option<Profile>) -> Self {
+        self.also_profile = profile;
+        self
+    }
+
+    /// Skip files signtool can't sign instead of failing
+    pub fn ignore_unsupported(mut self, ignore: bool) -> Self {
+        self.ignore_unsupported = ignore;
+        self
+    }
+
+    /// Skip empty files and files that don't start like their type, like a truncated EXE, instead of failing
+    pub fn skip_invalid(mut self, skip: bool) -> Self {
+        self.skip_invalid = skip;
+        self
+    }
+
+    /// Extensions of the files signtool signs, [`input::DEFAULT_EXTENSIONS`] unless changed
+    pub fn extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Check that the accounts and certificate profiles exist right after the login
+    pub fn validate_profile(mut self, validate: bool) -> Self {
+        self.validate_profile = validate;
+        self
+    }
+
+    /// Log the subscriptions the login returns
+    pub fn show_account(mut self, show: bool) -> Self {
+        self.show_account = show;
+        self
+    }
+
+    /// Skip the login when az is still logged in with these credentials from an earlier run
+    pub fn reuse_session(mut self, reuse: bool) -> Self {
+        self.reuse_session = reuse;
+        self
+    }
+
+    /// Keep the metadata files for later runs signing with the same metadata instead of writing
+    /// temp files, for tools calling once per file
+    pub fn keep_metadata(mut self, keep: bool) -> Self {
+        self.keep_metadata = keep;
+        self
+    }
+
+    /// Fail instead of warning when other users could replace the cached signing client
+    pub fn strict_permissions(mut self, strict: bool) -> Self {
+        self.strict_permissions = strict;
+        self
+    }
+
+    /// Compare the local clock with the endpoint's before signing, on by default
+    pub fn check_clock(mut self, check: bool) -> Self {
+        self.check_clock = check;
+        self
+    }
+
+    /// Fail instead of warning when the local clock is off by more than [`clock::MAX_SKEW`]
+    pub fn strict_clock(mut self, strict: bool) -> Self {
+        self.strict_clock = strict;
+        self
+    }
+
+    /// Connect to the endpoint and the timestamp server before anything else, failing with the step that didn't work
+    pub fn preflight_network(mut self, preflight: bool) -> Self {
+        self.preflight_network = preflight;
+        self
+    }
+
+    /// Sign with endpoints that aren't hosts of Trusted Signing, with a warning, instead of refusing them
+    pub fn allow_custom_endpoint(mut self, allow: bool) -> Self {
+        self.allow_custom_endpoint = allow;
+        self
+    }
+
+    /// Endpoint URLs to sign a file at in this order when signing it fails with a service-side error, like a 5xx or
+    /// a timeout
+    pub fn fallback_endpoints(mut self, endpoints: Vec<String>) -> Self {
+        self.fallback_endpoints = endpoints;
+        self
+    }
+
+    /// Fail a file signtool signed with warnings, like a skipped timestamp, instead of only reporting them
+    pub fn fail_on_warnings(mut self, fail: bool) -> Self {
+        self.fail_on_warnings = fail;
+        self
+    }
+
+    /// Fail a signed file whose signatures or timestamps use a digest weaker than SHA-2
+    pub fn require_sha2(mut self, require: bool) -> Self {
+        self.require_sha2 = require;
+        self
+    }
+
+    /// Check signed PE images against their headers and fix their CheckSum, other files aren't checked
+    pub fn validate_pe(mut self, validate: bool) -> Self {
+        self.validate_pe = validate;
+        self
+    }
+
+    /// Fail signed PE images built for another machine or subsystem than these, other files aren't checked
+    pub fn expect(mut self, arch: Option<Arch>, subsystem: Option<Subsystem>) -> Self {
+        self.expect_arch = arch;
+        self.expect_subsystem = subsystem;
+        self
+    }
+
+    /// Fail signed files whose signing certificate isn't the one expected, read from the signature embedded in PE
+    /// images and catalogs or the detached one
+    pub fn expect_certificate(mut self, expected: certificate::Expected) -> Self {
+        self.expect_certificate = expected;
+        self
+    }
+
+    /// Skip files already signed by one of these signers, whatever else would sign or sign them again
+    pub fn exclude_signed_by(mut self, protected: Vec<certificate::Protected>) -> Self {
+        self.exclude_signed_by = protected;
+        self
+    }
+
+    /// Hold signed files to the identity pinned, recording it in the pin file after the first file signed when it
+    /// holds none yet
+    pub fn pin(mut self, pin: Pin) -> Self {
+        self.pin = pin;
+        self
+    }
+
+    /// Check signed PowerShell scripts kept their encoding and every byte before the signature block, and got a
+    /// block with a signature
+    pub fn check_scripts(mut self, check: bool) -> Self {
+        self.check_scripts = check;
+        self
+    }
+
+    /// Check the chain of a signed file online for revoked certificates, with what a status that can't be checked
+    /// does to the file
+    pub fn verify_revocation(mut self, offline: Option<Offline>) -> Self {
+        self.verify_revocation = offline;
+        self
+    }
+
+    /// Wait up to `timeout` for a file that doesn't exist yet or is still being written before signing it
+    pub fn wait_for_file(mut self, timeout: Option<Duration>) -> Self {
+        self.wait_for_file = timeout;
+        self
+    }
+
+    /// Sign a file without a timestamp when signtool fails only to timestamp it and the timestamp authority keeps
+    /// failing, listing it for `trusted-signing-cli timestamp --pending`
+    pub fn defer_timestamp_on_failure(mut self, defer: bool) -> Self {
+        self.defer_timestamp = defer;
+        self
+    }
+
+    /// Give signed files back the creation, modification and access times they had before signing
+    pub fn preserve_timestamps(mut self, preserve: bool) -> Self {
+        self.preserve_timestamps = preserve;
+        self
+    }
+
+    /// Warn after the login when a certificate profile isn't active or expires within this many days
+    pub fn warn_expiry(mut self, days: Option<i64>) -> Self {
+        self.warn_expiry = days;
+        self
+    }
+
+    /// Warn once the signatures counted locally this month pass `warn`, refuse to sign past `hard_limit`
+    pub fn quota(mut self, warn: Option<u64>, hard_limit: Option<u64>) -> Self {
+        self.quota_warn = warn;
+        self.quota_hard_limit = hard_limit;
+        self
+    }
+
+    /// NuGet feed to download the signing client from instead of nuget.org
+    #[cfg(feature = "download")]
+    pub fn dlib_feed(mut self, feed: Option<String>, token: Option<String>) -> Self {
+        self.dlib_feed = feed;
+        self.feed_token = token;
+        self
+    }
+
+    /// A Microsoft.Trusted.Signing.Client .nupkg to install the signing client from instead of downloading it
+    pub fn dlib_package(mut self, package: Option<PathBuf>) -> Self {
+        self.dlib_package = package;
+        self
+    }
+
+    /// Sign .nupkg files with `dotnet nuget sign` and the certificate of this fingerprint in the certificate
+    /// store, instead of rejecting them. `dotnet_path` is found on the PATH when not given
+    pub fn nuget(mut self, certificate_fingerprint: Option<String>, dotnet_path: Option<String>) -> Self {
+        self.nuget_certificate = certificate_fingerprint;
+        self.dotnet_path = dotnet_path;
+        self
+    }
+
+    /// Sign .vsix files with Microsoft's `sign` tool at this path instead of rejecting them
+    pub fn vsix_tool(mut self, path: Option<String>) -> Self {
+        self.vsix_tool = path;
+        self
+    }
+
+    /// Sign ClickOnce and VSTO deployments with mage and the certificate of this SHA-1 thumbprint in the certificate
+    /// store, instead of rejecting them. mage is found in the Windows SDKs when `mage_path` is not given
+    pub fn clickonce(mut self, certificate_hash: Option<String>, mage_path: Option<String>) -> Self {
+        self.clickonce_certificate = certificate_hash;
+        self.mage_path = mage_path;
+        self
+    }
+
+    /// Sign the packages inside .msixbundle and .appxbundle files before the bundle itself, with makeappx at
+    /// `makeappx_path` or next to signtool. The Publisher of packages and bundles must be `publisher`, the subject
+    /// of the certificate when not given
+    pub fn deep_bundles(mut self, deep: bool, makeappx_path: Option<String>, publisher: Option<String>) -> Self {
+        self.deep_bundles = deep;
+        self.makeappx_path = makeappx_path;
+        self.publisher = publisher;
+        self
+    }
+
+    /// Sign MSIX and APPX packages and bundles whose Publisher isn't the certificate subject instead of failing
+    pub fn skip_publisher_check(mut self, skip: bool) -> Self {
+        self.skip_publisher_check = skip;
+        self
+    }
+
+    /// Sign WiX Burn bundles in two steps: their engine, detached and reattached with insignia at `wix_path` or
+    /// found in the WiX Toolset, then the bundle around it. A bundle is only replaced once it verifies
+    pub fn burn_bundles(mut self, burn_bundles: bool, wix_path: Option<String>) -> Self {
+        self.burn_bundles = burn_bundles;
+        self.wix_path = wix_path;
+        self
+    }
+
+    /// Sign the binaries inside the full and delta packages of Squirrel releases and pack them again, instead of
+    /// treating the packages as NuGet packages
+    pub fn squirrel(mut self, squirrel: bool) -> Self {
+        self.squirrel = squirrel;
+        self
+    }
+
+    /// Add page hashes to the signatures of PE files, for the binaries of a kernel-mode driver package
+    pub fn page_hashes(mut self, page_hashes: bool) -> Self {
+        self.page_hashes = page_hashes;
+        self
+    }
+
+    /// Compute the SHA-256 of every file before and after signing
+    pub fn hash(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
+}
+
+/// What signs a file
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mechanism {
+    /// signtool with the Trusted Signing dlib
+    Authenticode,
+    /// `dotnet nuget sign`, for NuGet packages
+    Nuget,
+    /// Microsoft's `sign` tool, for the OPC signature of Visual Studio extensions
+    Vsix,
+    /// mage, for the manifests of ClickOnce and VSTO deployments
+    #[serde(rename = "clickonce")]
+    ClickOnce,
+    /// signtool for the binaries inside a Squirrel package, which is packed again around them
+    Squirrel,
+}
+
+impl Mechanism {
+    /// The tool signing with this mechanism
+    pub fn tool(self) -> &'static str {
+        match self {
+            Mechanism::Authenticode => "signtool",
+            Mechanism::Nuget => "dotnet nuget sign",
+            Mechanism::Vsix => "sign",
+            Mechanism::ClickOnce => "mage",
+            Mechanism::Squirrel => "signtool",
+        }
+    }
+}
+
+/// What happened to a file
+#[derive(Debug, Clone)]
+pub struct SignOutcome {
+    pub path: String,
+    /// Why the file was not signed, when it was skipped
+    pub skipped: Option<&'static str>,
+    pub mechanism: Mechanism,
+    pub size: Option<u64>,
+    pub duration: Duration,
+    /// Where the file was signed, or would have been
+    pub target: Target,
+    pub correlation_id: Option<String>,
+    pub sha256_before: Option<String>,
+    pub sha256_after: Option<String>,
+    /// What signtool warned about, the file is signed all the same unless warnings fail it
+    pub warnings: Vec<String>,
+    /// The certificate the file was signed with, for the file types whose signature can be read
+    pub certificate: Option<SigningCertificate>,
+    /// The detached PKCS#7 written for the file, which is left as it was
+    pub signature_file: Option<String>,
+    /// The digests found in the signatures after signing, with --require-sha2
+    pub digests: Vec<ObservedDigest>,
+    /// The revocation checks of the chains after signing, with --verify-revocation
+    pub revocation: Vec<revocation::Check>,
+    /// RFC 3339 time signtool finished
+    pub signed_at: String,
+    /// The parts of the file signed one after the other, like the engine and the Burn bundle around it
+    pub signatures: Vec<Signature>,
+    /// Where the signature appended with --also-profile came from, once it was
+    pub also_signed: Option<Target>,
+    /// How often signing was tried again, at the next fallback endpoint
+    pub retries: u32,
+    /// The signer --exclude-signed-by matched, when that skipped the file
+    pub protected_by: Option<SigningCertificate>,
+    /// Signed without a timestamp by --defer-timestamp-on-failure, listed for `timestamp --pending`
+    pub awaiting_timestamp: bool,
+}
+
+impl SignOutcome {
+    /// A file about to be signed by `mechanism` at `target`, nothing happened to it yet
+    fn new(path: &str, mechanism: Mechanism, target: Target, correlation_id: Option<String>) -> Self {
+        SignOutcome {
+            path: path.to_string(),
+            skipped: None,
+            mechanism,
+            size: None,
+            duration: Duration::ZERO,
+            target,
+            correlation_id,
+            sha256_before: None,
+            sha256_after: None,
+            warnings: Vec::new(),
+            certificate: None,
+            signature_file: None,
+            digests: Vec::new(),
+            revocation: Vec::new(),
+            signed_at: String::new(),
+            signatures: Vec::new(),
+            also_signed: None,
+            retries: 0,
+            protected_by: None,
+            awaiting_timestamp: false,
+        }
+    }
+}
+
+/// One of the signatures a file got on its way to being signed
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// What was signed, like `engine` or `bundle`, or the name of the entry in a package
+    pub part: String,
+    /// SHA-256 of the part once signed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// RFC 3339 time the part was signed
+    pub signed_at: String,
+}
+
+/// State once the dependencies are in place and azure cli is logged in
+#[derive(Debug)]
+struct Connected {
+    config_dir: PathBuf,
+    lib_path: PathBuf,
+    /// One metadata file per target, written when the first file is routed there
+    metadata_files: Vec<(Target, MetadataFile)>,
+    /// Found when the first NuGet package is signed
+    dotnet: Option<PathBuf>,
+    /// Found when the first ClickOnce deployment is signed
+    mage: Option<PathBuf>,
+    /// Found when the first bundle is deep signed
+    makeappx: Option<PathBuf>,
+    /// Found when the first Burn bundle is signed
+    insignia: Option<PathBuf>,
+    /// AzureSignTool, signing everything signtool would with the Key Vault backend
+    azure_sign_tool: Option<PathBuf>,
+    /// osslsigncode, embedding the signatures of the REST backend
+    osslsigncode: Option<PathBuf>,
+    /// Access token of the REST backend and when it was acquired
+    token: Option<(String, Instant)>,
+    /// Certificate subject of every target a bundle was deep signed with, when it could be read
+    subjects: Vec<(Target, Option<String>)>,
+}
+
+/// Signs files, setting up the signing client and logging in once for all of them
+pub struct Signer {
+    options: SignOptions,
+    events: Events,
+    metrics: Metrics,
+    connected: Option<Connected>,
+    /// Version of the Azure CLI, once connect asked for it
+    azure_cli_version: Option<azure::Version>,
+    /// What signtool warned about while signing the current file
+    warnings: Vec<String>,
+    /// Profiles that can't sign or expire soon, found after the login
+    profile_warnings: Vec<String>,
+    /// How the local clock compared with the endpoint's, once connect checked it
+    clock: Option<clock::Check>,
+    /// This month's signatures counted locally for the accounts signed with
+    quota: Vec<quota::Usage>,
+    /// The fallback endpoint that signed the current file, when its own endpoint failed
+    failed_over: Option<String>,
+    /// The endpoints the current file failed at before another was tried
+    retries: u32,
+    /// Whether the current file was signed without a timestamp, the timestamp authority failing
+    timestamp_deferred: bool,
+}
+
+impl Signer {
+    /// Progress is reported to the sinks of `events`
+    pub fn new(options: SignOptions, events: Events) -> Self {
+        logging::register_secret(&options.azure_client_secret);
+        logging::register_secret(&options.pfx_password);
+        #[cfg(feature = "download")]
+        if let Some(token) = &options.feed_token {
+            logging::register_secret(token);
+        }
+        redact::register("tenant", &options.azure_tenant_id);
+        Signer {
+            options,
+            events,
+            metrics: Metrics::default(),
+            connected: None,
+            azure_cli_version: None,
+            warnings: Vec::new(),
+            profile_warnings: Vec::new(),
+            clock: None,
+            quota: Vec::new(),
+            failed_over: None,
+            retries: 0,
+            timestamp_deferred: false,
+        }
+    }
+
+    /// Events of the signer, to emit events of the caller's own in order with them
+    pub fn events(&mut self) -> &mut Events {
+        &mut self.events
+    }
+
+    /// Durations of the dependencies, login, sign and verify phases so far
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Version of the Windows SDK signtool ships with
+    pub fn signtool_version(&self) -> Option<&str> {
+        signtool::version(&self.options.sign_tool_path)
+    }
+
+    /// Version of the Azure CLI the signer logged in with, once connected
+    pub fn azure_cli_version(&self) -> Option<azure::Version> {
+        self.azure_cli_version
+    }
+
+    /// The certificate profiles found not active or expiring within --warn-expiry, once connected
+    pub fn profile_warnings(&self) -> &[String] {
+        &self.profile_warnings
+    }
+
+    /// How the local clock compared with the endpoint's, once connected with --check-clock
+    pub fn clock(&self) -> Option<&clock::Check> {
+        self.clock.as_ref()
+    }
+
+    /// This month's signatures counted locally for every account signed with so far, an estimate
+    pub fn quota(&self) -> &[quota::Usage] {
+        &self.quota
+    }
+
+    /// Announce the start of a phase
+    fn start_phase(&mut self, phase: &'static str) -> Instant {
+        self.events.emit(Event::PhaseStarted { phase });
+        Instant::now()
+    }
+
+    /// Record the duration of a phase and announce its end
+    fn finish_phase(&mut self, phase: &'static str, started: Instant) {
+        self.record_phase(phase, started.elapsed());
+    }
+
+    /// Record a phase that took `elapsed` and announce its end
+    fn record_phase(&mut self, phase: &'static str, elapsed: Duration) {
+        self.metrics.record(phase, elapsed);
+        self.events.emit(Event::PhaseFinished {
+            phase,
+            duration_ms: elapsed.as_millis() as u64,
+        });
+    }
+
+    /// Download the signing client from nuget.org or --dlib-feed and unpack it into `config_dir`
+    #[cfg(feature = "download")]
+    async fn fetch_client(&mut self, config_dir: &Path) -> Result<(), Error> {
+        let options = self.options.clone();
+        let _span = debug_span!("download").entered();
+        info!("downloading Trusted Signing client");
+        let link =
+            feed::package_url(options.dlib_feed.as_deref(), options.feed_token.as_deref(), DLIB_VERSION).await?;
+        let link = link.as_str();
+        let download_error = |message: String| Error::Download {
+            url: link.to_string(),
+            message,
+        };
+        let filename = feed::package_file(link, DLIB_VERSION)
+            .map_err(|err| download_error(format!("could not download signing client from {}: {}", link, err)))?;
+        let archive = config_dir.join(filename);
+        let authorization = options.feed_token.as_deref().map(feed::authorization);
+        if authorization.as_deref().is_some_and(|value| reqwest::header::HeaderValue::from_str(value).is_err()) {
+            Err(Error::Usage("TRUSTED_SIGNING_FEED_TOKEN is not a valid header value".to_string()))?;
+        }
+        self.events.emit(Event::DownloadProgress {
+            url: link,
+            bytes: 0,
+            finished: false,
+        });
+        let bytes = match download::file(link, &archive, authorization.as_deref(), Default::default()).await {
+            Ok(bytes) => bytes,
+            Err(download::Failure::Status(status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN))) => {
+                Err(feed::unauthorized(link, status, options.feed_token.is_some()))?
+            }
+            Err(err) => Err(download_error(format!("could not download signing client from {}: {}", link, err)))?,
+        };
+        self.events.emit(Event::DownloadProgress {
+            url: link,
+            bytes,
+            finished: true,
+        });
+        let target_dir = config_dir.join("lib");
+
+        let installed = unzip::install(&archive, &target_dir, unzip::MAX_SIZE)
+            .map_err(|err| download_error(format!("signing client can't be unzipped: {}", err)));
+        // unpacked, or broken and downloaded again by the next run
+        if let Err(err) = fs::remove_file(&archive) {
+            debug!("{} could not be removed: {}", archive.display(), err);
+        }
+        installed?;
+        Ok(())
+    }
+
+    /// Without the download feature the signing client has to be there already, or staged with --dlib-package
+    #[cfg(not(feature = "download"))]
+    async fn fetch_client(&mut self, _config_dir: &Path) -> Result<(), Error> {
+        Err(Error::Usage(format!(
+            "this build can't download the Trusted Signing client. Pass --dlib-package (or \
+             TRUSTED_SIGNING_DLIB_PACKAGE) with Microsoft.Trusted.Signing.Client.{}.nupkg, or unpack it into '{}' \
+             beforehand",
+            DLIB_VERSION,
+            config::dir().join("lib").display()
+        )))
+    }
+
+    /// Download the signing client unless it is there already, returning the path of the dlib
+    pub async fn download_client(&mut self) -> Result<PathBuf, Error> {
+        let options = self.options.clone();
+        let config_dir = config::dir();
+
+        // only this user may write the signing client signtool loads, and read the metadata
+        if !config_dir.exists() {
+            permissions::create_private_dir(&config_dir)
+                .map_err(|err| format!("config dir '{}' could not be created: {}", config_dir.display(), err))?;
+        }
+
+        // Check if lib is downloaded
+        let lib_path = config::lib_path();
+
+        // Install a package staged for build agents that can't download it
+        if let Some(package) = &options.dlib_package {
+            match lib_path.exists() {
+                true => debug!("the signing client is cached, {} is not installed", package.display()),
+                false => install_package(package, &config_dir.join("lib"), &lib_path)?,
+            }
+        }
+
+        // Download and extract lib
+        if !lib_path.exists() {
+            self.fetch_client(&config_dir).await?;
+        }
+        // an x86 or arm64 signtool loads the dlib for its machine only
+        let lib_path = arch::matching_dlib(Path::new(&options.sign_tool_path), &lib_path)
+            .map_err(|message| Error::MissingDependency { component: "signing client", message })?;
+        let insecure = permissions::insecure(&permissions::chain(&config_dir, &lib_path));
+        if !insecure.is_empty() {
+            let problems = format!(
+                "{}, another user could replace the signing client signtool loads. Restrict the permissions or run \
+                 `trusted-signing-cli purge` so the next sign recreates the directory for this user only",
+                insecure.join(", ")
+            );
+            match options.strict_permissions {
+                true => Err(Error::Other(format!("{} (--strict-permissions)", problems)))?,
+                false => warn!("{}", problems),
+            }
+        }
+        debug!("using signing client {}", lib_path.display());
+        Ok(lib_path)
+    }
+
+    /// Download the signing client if needed and log in, done by the first sign unless called before
+    pub async fn connect(&mut self) -> Result<(), Error> {
+        if self.connected.is_some() {
+            return Ok(());
+        }
+        let options = self.options.clone();
+
+        // a PFX signs locally, there is nothing to reach, log in to or download
+        if let Some(pfx) = &options.pfx {
+            check_signtool(&options.sign_tool_path)?;
+            if !pfx.is_file() {
+                Err(Error::Usage(format!("the PFX '{}' does not exist, pass it with --pfx", pfx.display())))?;
+            }
+            self.connected = Some(Connected {
+                config_dir: config::dir(),
+                lib_path: PathBuf::new(),
+                metadata_files: Vec::new(),
+                dotnet: None,
+                mage: None,
+                makeappx: None,
+                insignia: None,
+                azure_sign_tool: None,
+                osslsigncode: None,
+                token: None,
+                subjects: Vec::new(),
+            });
+            return Ok(());
+        }
+
+        if options.preflight_network {
+            let _span = debug_span!("preflight").entered();
+            let urls = [options.target.endpoint.as_str(), options.timestamp_url.as_str()];
+            let probes = futures_util::future::join_all(urls.map(network::probe)).await;
+            for probe in &probes {
+                match (probe.problem(), probe.warning()) {
+                    (Some(problem), _) => Err(Error::Other(problem))?,
+                    (None, Some(warning)) => warn!("{}", warning),
+                    (None, None) => info!("reached {} ({})", probe.host, probe.timings()),
+                }
+            }
+        }
+
+        // timestamps and tokens fail for a skewed clock without saying so
+        if options.check_clock {
+            let check = clock::check(&options.target.endpoint).await;
+            match (check.warning(), &check.error) {
+                (Some(warning), _) if options.strict_clock => {
+                    Err(Error::Other(format!("{} (--strict-clock)", warning)))?
+                }
+                (Some(warning), _) => warn!("{}", warning),
+                (None, Some(err)) => debug!("the clock could not be checked against {}: {}", check.source, err),
+                (None, None) => debug!("the clock is {}s off {}", check.skew_seconds.unwrap_or_default(), check.source),
+            }
+            self.clock = Some(check);
+        }
+
+        // AzureSignTool authenticates by itself and needs neither the dlib nor its metadata
+        if options.backend == Backend::KeyVault {
+            let azure_sign_tool = keyvault::find(&options.azure_sign_tool_path)?;
+            self.connected = Some(Connected {
+                config_dir: config::dir(),
+                lib_path: PathBuf::new(),
+                metadata_files: Vec::new(),
+                dotnet: None,
+                mage: None,
+                makeappx: None,
+                insignia: None,
+                azure_sign_tool: Some(azure_sign_tool),
+                osslsigncode: None,
+                token: None,
+                subjects: Vec::new(),
+            });
+            return Ok(());
+        }
+        options.check_endpoints()?;
+
+        if fs::metadata(&options.azure_cli_path).is_err() {
+            Err(Error::MissingDependency {
+                component: "azure cli",
+                message: format!(
+                    "azure cli {} does not exists, please specify PATH with env AZURE_CLI_PATH",
+                    &options.azure_cli_path
+                ),
+            })?;
+        }
+
+        self.azure_cli_version = azure::cli_version(&options.azure_cli_path);
+        if let Some(warning) = self.azure_cli_version.and_then(azure::baseline_warning) {
+            warn!("{}", warning);
+        }
+
+        let rest = options.backend == Backend::Rest;
+        #[cfg(not(feature = "rest"))]
+        if rest {
+            Err(rest::unavailable())?;
+        }
+        if !rest {
+            check_signtool(&options.sign_tool_path)?;
+        }
+        if let (false, Err(problem)) = (rest, signtool::check_version(&options.sign_tool_path)) {
+            let newer = discover::signtool()
+                .map(|path| path.display().to_string())
+                .filter(|path| *path != options.sign_tool_path && signtool::check_version(path).is_ok());
+            match newer {
+                // the default path names an SDK, another one installed is as good
+                Some(newer) if options.sign_tool_path == signtool::DEFAULT_PATH => {
+                    info!("{}, signing with {} instead", problem, newer);
+                    self.options.sign_tool_path = newer;
+                }
+                newer => Err(signtool::too_old(&problem, newer.as_deref()))?,
+            }
+        }
+
+        // the login doesn't need the signing client, az runs while it is downloaded
+        let dependencies_started = self.start_phase("dependencies");
+        let config_dir = config::dir();
+        let login = tokio::task::spawn_blocking({
+            let options = options.clone();
+            move || {
+                let started = Instant::now();
+                (login(&options), started.elapsed())
+            }
+        });
+        let login = async {
+            match login.await {
+                Ok((result, elapsed)) => result.map(|_| elapsed),
+                Err(err) => Err(Error::Auth(format!("the azure cli login stopped: {}", err))),
+            }
+        };
+        let dependencies = async {
+            match rest {
+                true => Ok((PathBuf::new(), Vec::new(), Some(rest::find_osslsigncode(&options.osslsigncode_path)?))),
+                false => {
+                    let lib_path = self.download_client().await?;
+                    // every invocation gets its own metadata, concurrent runs with other profiles must not share it
+                    let run_metadata = Metadata::new(&options.target, options.correlation_id.clone())
+                        .write(&config_dir, options.keep_metadata)?;
+                    Ok((lib_path, vec![(options.target.clone(), run_metadata)], None))
+                }
+            }
+        };
+        let ((lib_path, metadata_files, osslsigncode), login_elapsed) = overlap(dependencies, login).await?;
+        self.finish_phase("dependencies", dependencies_started);
+        // announced afterwards, so CI blocks of the phases stay nested
+        self.start_phase("login");
+        self.record_phase("login", login_elapsed);
+
+        if options.validate_profile {
+            let _span = debug_span!("validate").entered();
+            for (account, certificate) in &options.profiles() {
+                match azure::check_profile(&options.azure_cli_path, account, certificate)? {
+                    ProfileCheck::Exists => debug!("account {} and certificate profile {} exist", account, certificate),
+                    ProfileCheck::Missing(message) => Err(Error::Usage(message))?,
+                    ProfileCheck::Forbidden(err) => {
+                        warn!("account and certificate profile could not be checked: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(days) = options.warn_expiry {
+            let _span = debug_span!("expiry").entered();
+            self.profile_warnings = expiry_warnings(&options, days);
+        }
+
+        self.connected = Some(Connected {
+            config_dir,
+            lib_path,
+            metadata_files,
+            dotnet: None,
+            mage: None,
+            makeappx: None,
+            insignia: None,
+            azure_sign_tool: None,
+            osslsigncode,
+            token: None,
+            subjects: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// The tool a file is signed with
+    fn mechanism(&self, file: &str) -> Mechanism {
+        let options = &self.options;
+        match file {
+            file if options.squirrel && squirrel::is_package(file) => Mechanism::Squirrel,
+            file if options.nuget_certificate.is_some() && nuget::is_package(file) => Mechanism::Nuget,
+            file if options.vsix_tool.is_some() && vsix::is_package(file) => Mechanism::Vsix,
+            file if options.clickonce_certificate.is_some() && clickonce::is_deployment(file) => Mechanism::ClickOnce,
+            _ => Mechanism::Authenticode,
+        }
+    }
+
+    /// Why signing `file` would skip it, by --ignore-unsupported or --skip-invalid, checked without connecting
+    pub fn skip_reason(&self, file: &str) -> Option<&'static str> {
+        match protected_by(file, &self.options) {
+            Some(_) => Some(PROTECTED),
+            None => prepare(file, &self.options, self.mechanism(file)).ok().flatten(),
+        }
+    }
+
+    /// Sign a file, returning what happened to it even when signing failed. Connects first unless the file is
+    /// skipped
+    pub async fn attempt(&mut self, file: &str) -> (SignOutcome, Result<(), Error>) {
+        let (target, warning) = route::target(&self.options.routes, file, &self.options.target);
+        if let Some(warning) = warning {
+            warn!("{}", warning);
+        }
+        let mechanism = self.mechanism(file);
+        let correlation_id = match self.options.correlation_per_file {
+            true => Some(uuid::Uuid::new_v4().to_string()),
+            false => self.options.correlation_id.clone(),
+        };
+        let mut outcome = SignOutcome::new(file, mechanism, target, correlation_id);
+        if let Some(timeout) = self.options.wait_for_file {
+            if let Err(err) = wait_for(file, interrupt::capped(timeout)).await {
+                return (outcome, Err(err));
+            }
+        }
+        if let Some(signer) = protected_by(file, &self.options) {
+            info!("skipped {}: it is signed by {}, which --exclude-signed-by protects", file, signer.subject);
+            outcome.skipped = Some(PROTECTED);
+            outcome.protected_by = Some(signer);
+            return (outcome, Ok(()));
+        }
+        let result = prepare(file, &self.options, mechanism);
+        if let Ok(Some(reason)) = result {
+            info!("skipped {}: {}", file, reason);
+            outcome.skipped = Some(reason);
+            return (outcome, Ok(()));
+        }
+        // a file that can't be signed fails without a login
+        if result.is_ok() {
+            if let Err(err) = self.connect().await {
+                return (outcome, Err(err));
+            }
+        }
+        outcome.size = fs::metadata(file).map(|metadata| metadata.len()).ok();
+        let file_started = Instant::now();
+        let detached = self.options.detached_p7.is_some();
+        let hashed = self.options.hash || detached;
+        outcome.sha256_before = hashed.then(|| hash::sha256_file(file).ok()).flatten();
+        let times = match self.options.preserve_timestamps && !detached {
+            true => file_times(file)
+                .map_err(|err| warn!("the times of '{}' could not be read to restore them: {}", file, err))
+                .ok(),
+            false => None,
+        };
+        let script = match self.options.check_scripts && !detached && psmodule::is_script(file) {
+            true => fs::read(file).ok(),
+            false => None,
+        };
+        self.warnings.clear();
+        self.failed_over = None;
+        self.retries = 0;
+        self.timestamp_deferred = false;
+        let rest = mechanism == Mechanism::Authenticode && self.options.backend == Backend::Rest;
+        let tool_path = input::tool_path(file);
+        let result = match result {
+            Ok(_) if rest => self.sign_rest(&tool_path, &outcome).await,
+            result => result.and_then(|_| match mechanism {
+                Mechanism::Authenticode if self.options.deep_bundles && msix::is_bundle(file) => {
+                    self.sign_bundle(&tool_path, &outcome)
+                }
+                Mechanism::Authenticode if self.options.burn_bundles && burn::is_bundle(file) => {
+                    self.sign_burn_bundle(&tool_path, &mut outcome)
+                }
+                Mechanism::Authenticode => self
+                    .check_package_publisher(&tool_path, &outcome.target)
+                    .and_then(|_| self.sign(&tool_path, &outcome))
+                    .and_then(|_| self.sign_also(&tool_path, &mut outcome)),
+                Mechanism::Nuget => self.sign_package(&tool_path),
+                Mechanism::Vsix => self.sign_vsix(&tool_path, &outcome),
+                Mechanism::ClickOnce => self.sign_deployment(&tool_path),
+                Mechanism::Squirrel => self.sign_squirrel_package(&tool_path, &mut outcome),
+            }),
+        };
+        outcome.signed_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        outcome.warnings = std::mem::take(&mut self.warnings);
+        if let Some(endpoint) = self.failed_over.take() {
+            outcome.target.endpoint = endpoint;
+        }
+        outcome.retries = std::mem::take(&mut self.retries);
+        outcome.awaiting_timestamp = std::mem::take(&mut self.timestamp_deferred);
+        let verify_started = Instant::now();
+        let verified = result.is_ok();
+        let result = result.and_then(|()| self.check_signed(file, &mut outcome, script.as_deref()));
+        let verify = verified.then(|| verify_started.elapsed());
+        if let Some(times) = times {
+            if let Err(err) = restore_times(file, times) {
+                let warning = format!("the times of '{}' could not be restored: {}", file, err);
+                warn!("{}", warning);
+                outcome.warnings.push(warning);
+            }
+        }
+        outcome.duration = file_started.elapsed();
+        self.metrics.record_file(outcome.duration, verify);
+        (outcome, result)
+    }
+
+    /// Check `file` once it is signed, each check in turn until one fails. Fills in what the checks found, like
+    /// the certificate and the hash after signing
+    fn check_signed(&mut self, file: &str, outcome: &mut SignOutcome, script: Option<&[u8]>) -> Result<(), Error> {
+        let options = &self.options;
+        check_warnings(options, file, outcome)?;
+        if let Some(dir) = &options.detached_p7 {
+            outcome.signature_file = Some(detached_signature(file, dir, outcome.sha256_before.as_deref())?);
+        }
+        // what holds the signature
+        let signed = outcome.signature_file.clone().unwrap_or_else(|| file.to_string());
+        check_digests(options, &signed, outcome)?;
+        if let Some(before) = script {
+            check_script(file, before)?;
+        }
+        check_image(options, file, outcome)?;
+        check_arch(options, file, outcome)?;
+        check_revocation(options, &signed, outcome)?;
+        outcome.certificate = certificate::of_file(&signed);
+        if let (Some(certificate), Some(connected)) = (&outcome.certificate, self.connected.as_mut()) {
+            // the packages signed later in the run are checked against it
+            connected.subjects.retain(|(known, _)| *known != outcome.target);
+            connected.subjects.push((outcome.target.clone(), Some(certificate.subject.clone())));
+        }
+        check_certificate(&self.options, file, &signed, outcome)?;
+        check_pin(&mut self.options.pin, file, &signed, outcome)?;
+        outcome.sha256_after = self.options.hash.then(|| hash::sha256_file(file).ok()).flatten();
+        settle_timestamp(&self.options, file, outcome)
+    }
+
+    /// Run signtool with the metadata of the file's target
+    fn sign(&mut self, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+        if let Some(pfx) = &self.options.pfx {
+            let mut signtool = self.options.signtool();
+            signtool.description = self.options.description_of(file);
+            signtool.page_hashes = self.options.page_hashes && rest::is_pe(file);
+            let warnings =
+                signtool::sign_pfx(&self.options.sign_tool_path, pfx, &self.options.pfx_password, &signtool, file)?;
+            for warning in &warnings {
+                warn!("signtool warned while signing {}: {}", file, warning);
+            }
+            self.warnings.extend(warnings);
+            return Ok(());
+        }
+        let connected = self.connected.as_mut().expect("connected before signing");
+        if let Some(azure_sign_tool) = &connected.azure_sign_tool {
+            let options = &self.options;
+            let key_vault = keyvault::Options {
+                vault_uri: &outcome.target.endpoint,
+                certificate: &outcome.target.certificate,
+                tenant_id: &options.azure_tenant_id,
+                client_id: &options.azure_client_id,
+                client_secret: &options.azure_client_secret,
+                file_digest: options.file_digest,
+                timestamp_url: &options.timestamp_url,
+                timestamp_digest: options.timestamp_digest,
+                description: options.description_of(file),
+                append: options.append_signature,
+            };
+            return keyvault::sign(azure_sign_tool, &key_vault, file);
+        }
+        if let Some(dir) = &self.options.detached_p7 {
+            fs::create_dir_all(dir).map_err(|err| format!("'{}' could not be created: {}", dir.display(), err))?;
+        }
+        let target = &outcome.target;
+        if let Some(limit) = self.options.quota_hard_limit {
+            let usage = quota::usage(&target.endpoint, &target.account);
+            if usage.signatures >= limit {
+                Err(Error::Signing {
+                    path: file.to_string(),
+                    message: format!(
+                        "{} signatures of account {} were counted this month, --quota-hard-limit {} refuses more. \
+                         The count is a local estimate, `trusted-signing-cli quota reset` starts it over",
+                        usage.signatures, target.account, limit
+                    ),
+                    output: String::new(),
+                })?
+            }
+        }
+        let mut signtool = self.options.signtool();
+        signtool.description = self.options.description_of(file);
+        signtool.page_hashes = self.options.page_hashes && rest::is_pe(file);
+        let sign_tool_path = &self.options.sign_tool_path;
+        let fallbacks = self.options.fallback_endpoints.iter().filter(|endpoint| **endpoint != target.endpoint);
+        let endpoints: Vec<&String> = std::iter::once(&target.endpoint).chain(fallbacks).collect();
+        let mut signed = None;
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            let target = Target { endpoint: endpoint.to_string(), ..target.clone() };
+            let file_metadata = match self.options.correlation_per_file {
+                true => Some(Metadata::new(&target, outcome.correlation_id.clone()).write_temp(&connected.config_dir)?),
+                false => None,
+            };
+            let metadata_path = match &file_metadata {
+                Some(file_metadata) => file_metadata.path(),
+                None => metadata_for(
+                    &mut connected.metadata_files,
+                    &target,
+                    &self.options.correlation_id,
+                    &connected.config_dir,
+                    self.options.keep_metadata,
+                )?,
+            };
+            let attempted = Instant::now();
+            let mut result = signtool::sign(sign_tool_path, &connected.lib_path, metadata_path, &signtool, file);
+            let unstamped = |output: &str| hints::is_timestamp_failure(output) && self.options.defer_timestamp;
+            let deferred = matches!(&result, Err(Error::Signing { output, .. }) if unstamped(output));
+            if deferred && self.options.detached_p7.is_none() {
+                warn!("{} could not be timestamped, signing it without a timestamp to add one later", file);
+                signtool.timestamp_url = "";
+                result = signtool::sign(sign_tool_path, &connected.lib_path, metadata_path, &signtool, file);
+                self.timestamp_deferred = result.is_ok();
+            }
+            // only service-side errors fail over, a missing role or profile fails the same everywhere
+            let failover = index + 1 < endpoints.len()
+                && matches!(&result, Err(Error::Signing { output, .. }) if hints::is_service_side(output));
+            match result {
+                Ok(warnings) => {
+                    signed = Some((warnings, target));
+                    break;
+                }
+                // another attempt taking as long must fit in --max-runtime
+                Err(err) if failover && !interrupt::fits(attempted.elapsed()) => {
+                    let next = endpoints[index + 1];
+                    warn!("not trying {} at {}, what is left of --max-runtime is too short", file, next);
+                    Err(err)?
+                }
+                Err(_) if failover => {
+                    warn!(
+                        "signing {} at {} failed with a service-side error, trying {}",
+                        file,
+                        endpoint,
+                        endpoints[index + 1]
+                    );
+                    self.retries += 1;
+                }
+                Err(err) => Err(err)?,
+            }
+        }
+        let (warnings, target) = signed.expect("the last endpoint returns its error");
+        if self.timestamp_deferred {
+            let urls = [self.options.timestamp_url.clone()];
+            let digest = self.options.timestamp_digest;
+            match timestamp::stamp(sign_tool_path, &urls, digest, DEFERRED_RETRIES, file) {
+                Ok(url) => {
+                    info!("{} was signed without a timestamp, then {} timestamped it", file, url);
+                    self.timestamp_deferred = false;
+                }
+                Err(err) => warn!("{} stays without a timestamp for now: {}", file, err),
+            }
+        }
+        for warning in &warnings {
+            warn!("signtool warned while signing {}: {}", file, warning);
+        }
+        self.warnings.extend(warnings);
+        if target.endpoint != outcome.target.endpoint {
+            info!("{} was signed at the fallback endpoint {}", file, target.endpoint);
+            self.failed_over = Some(target.endpoint.clone());
+        }
+        self.count_signature(&target);
+        Ok(())
+    }
+
+    /// Append the signature of --also-profile to a file signtool signed with its own profile, checking a signature
+    /// was added when the file's signatures can be read
+    fn sign_also(&mut self, file: &str, outcome: &mut SignOutcome) -> Result<(), Error> {
+        let Some(target) = self.options.also_profile.as_ref().map(|also| also.target(&self.options.target)) else {
+            return Ok(());
+        };
+        let only_primary = |message: &str, output: String| Error::Signing {
+            path: file.to_string(),
+            message: format!(
+                "'{}' carries only the signature of profile {}, appending the one of profile {} failed: {}",
+                file, outcome.target.certificate, target.certificate, message
+            ),
+            output,
+        };
+        let before = inspect::inspect(file);
+        let connected = self.connected.as_mut().expect("connected before signing");
+        let file_metadata = match self.options.correlation_per_file {
+            true => Some(Metadata::new(&target, outcome.correlation_id.clone()).write_temp(&connected.config_dir)?),
+            false => None,
+        };
+        let metadata_path = match &file_metadata {
+            Some(file_metadata) => file_metadata.path(),
+            None => metadata_for(
+                &mut connected.metadata_files,
+                &target,
+                &self.options.correlation_id,
+                &connected.config_dir,
+                self.options.keep_metadata,
+            )?,
+        };
+        let mut signtool = self.options.signtool();
+        signtool.description = self.options.description_of(file);
+        signtool.append = true;
+        signtool.page_hashes = self.options.page_hashes && rest::is_pe(file);
+        let sign_tool_path = &self.options.sign_tool_path;
+        let warnings = match signtool::sign(sign_tool_path, &connected.lib_path, metadata_path, &signtool, file) {
+            Ok(warnings) => warnings,
+            Err(Error::Signing { message, output, .. }) => Err(only_primary(&message, output))?,
+            Err(err) => Err(only_primary(&err.to_string(), String::new()))?,
+        };
+        let after = inspect::inspect(file);
+        if before.error.is_none() && after.error.is_none() && after.signatures.len() <= before.signatures.len() {
+            Err(only_primary("signtool added no signature", String::new()))?;
+        }
+        for warning in &warnings {
+            warn!("signtool warned while appending the signature of {} to {}: {}", target.certificate, file, warning);
+        }
+        self.warnings.extend(warnings);
+        debug!("appended the signature of profile {} to {}", target.certificate, file);
+        self.count_signature(&target);
+        outcome.also_signed = Some(target);
+        Ok(())
+    }
+
+    /// Count a signature of `target` against the local quota estimate, warn the first time it passes --quota-warn
+    fn count_signature(&mut self, target: &Target) {
+        let usage = match quota::record(&target.endpoint, &target.account) {
+            Ok(usage) => usage,
+            Err(err) => return warn!("{}", err),
+        };
+        let counted =
+            self.quota.iter().position(|known| known.endpoint == usage.endpoint && known.account == usage.account);
+        // once per run and account: on its first signature or on the one that passes the threshold
+        let passed = |warn: u64| usage.signatures > warn && (counted.is_none() || usage.signatures == warn + 1);
+        if let Some(warn) = self.options.quota_warn.filter(|&warn| passed(warn)) {
+            warn!(
+                "{} signatures of account {} were counted this month, more than --quota-warn {} (a local estimate)",
+                usage.signatures, usage.account, warn
+            );
+        }
+        match counted {
+            Some(index) => self.quota[index] = usage,
+            None => self.quota.push(usage),
+        }
+    }
+
+    /// The subject the packages signed for `target` must have as Publisher: --publisher, the subject of a file
+    /// signed for it earlier in the run or the one of the certificate profile, looked up once per target
+    fn certificate_subject(&mut self, target: &Target) -> Result<Option<String>, Error> {
+        if let Some(publisher) = &self.options.publisher {
+            return Ok(Some(publisher.clone()));
+        }
+        let connected = self.connected.as_mut().expect("connected before signing");
+        if let Some((_, subject)) = connected.subjects.iter().find(|(known, _)| known == target) {
+            return Ok(subject.clone());
+        }
+        let subject = match self.options.backend {
+            // a Key Vault certificate or a PFX has no profile to read
+            Backend::KeyVault | Backend::Pfx => Ok(None),
+            _ => azure::certificate_subject(&self.options.azure_cli_path, &target.account, &target.certificate),
+        };
+        // without read access to the account signing works all the same
+        let subject = subject.unwrap_or_else(|err| {
+            debug!("{}", err);
+            None
+        });
+        if subject.is_none() {
+            warn!(
+                "the certificate subject of profile {} could not be read, pass --publisher to check the Publisher of \
+                 packages and bundles before signing them",
+                target.certificate
+            );
+        }
+        connected.subjects.push((target.clone(), subject.clone()));
+        Ok(subject)
+    }
+
+    /// Fail a package or bundle whose Publisher isn't the certificate subject, Windows installs it nowhere
+    fn check_publisher(&mut self, file: &str, publisher: &str, target: &Target) -> Result<(), Error> {
+        if self.options.skip_publisher_check {
+            return Ok(());
+        }
+        let Some(subject) = self.certificate_subject(target)? else {
+            return Ok(());
+        };
+        if !msix::same_subject(publisher, &subject) {
+            Err(Error::UnsupportedInput {
+                path: file.to_string(),
+                message: format!(
+                    "the Publisher '{}' of '{}' is not the certificate subject '{}' of profile {}, Windows won't \
+                     install it: set it as the Publisher in the package manifest, or pass --skip-publisher-check to \
+                     sign it anyway",
+                    publisher, file, subject, target.certificate
+                ),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// [`Self::check_publisher`] for an MSIX or APPX package or bundle signed as it is, other files pass. A package
+    /// whose manifest can't be read is left to signtool
+    fn check_package_publisher(&mut self, file: &str, target: &Target) -> Result<(), Error> {
+        if !msix::is_package(file) || self.options.detached_p7.is_some() || self.options.skip_publisher_check {
+            return Ok(());
+        }
+        match msix::publisher(Path::new(file)) {
+            Ok(publisher) => self.check_publisher(file, &publisher, target),
+            Err(err) => {
+                debug!("the Publisher isn't checked: {}", err);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sign the packages of a bundle, bundle them again with the version it had and sign the new bundle, which
+    /// replaces the original only once all of that worked
+    fn sign_bundle(&mut self, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+        let bundle = msix::Bundle::read(Path::new(file))
+            .map_err(|message| Error::UnsupportedInput { path: file.to_string(), message })?;
+        self.check_publisher(file, &bundle.publisher, &outcome.target)?;
+        let connected = self.connected.as_mut().expect("connected before signing");
+        let makeappx = match &connected.makeappx {
+            Some(makeappx) => makeappx.clone(),
+            None => connected
+                .makeappx
+                .insert(msix::find_makeappx(self.options.makeappx_path.as_deref(), &self.options.sign_tool_path)?)
+                .clone(),
+        };
+
+        let work = scratch::dir("trusted-signing-bundle", Some(Path::new(file)))
+            .map_err(|err| format!("a directory to unpack '{}' into could not be created: {}", file, err))?;
+        let (unpacked, packages) = (work.path().join("unpacked"), work.path().join("packages"));
+        fs::create_dir_all(&packages).map_err(|err| err.to_string())?;
+        msix::unbundle(&makeappx, file, &unpacked)?;
+        for package in &bundle.packages {
+            let path = packages.join(&package.file_name);
+            fs::rename(unpacked.join(&package.file_name), &path).map_err(|err| Error::UnsupportedInput {
+                path: file.to_string(),
+                message: format!("package {} of '{}' was not unpacked: {}", package.file_name, file, err),
+            })?;
+            self.sign(&path.display().to_string(), outcome)?;
+            info!(
+                "signed the {} package {} of {}",
+                package.architecture.as_deref().unwrap_or(&package.kind),
+                package.file_name,
+                file
+            );
+        }
+        let output = work.path().join(Path::new(file).file_name().unwrap_or_default());
+        msix::bundle(&makeappx, &packages, &bundle.version, &output, file)?;
+        let rebundled = msix::Bundle::read(&output).map_err(|message| Error::Signing {
+            path: file.to_string(),
+            message,
+            output: String::new(),
+        })?;
+        let identity = |bundle: &msix::Bundle| (bundle.name.clone(), bundle.publisher.clone(), bundle.version.clone());
+        if identity(&rebundled) != identity(&bundle) || rebundled.packages.len() != bundle.packages.len() {
+            Err(Error::Signing {
+                path: file.to_string(),
+                message: format!("bundling the signed packages of '{}' changed its identity or packages", file),
+                output: String::new(),
+            })?;
+        }
+        self.sign(&output.display().to_string(), outcome)?;
+        scratch::replace(&output, Path::new(file))
+            .map_err(|err| format!("'{}' could not be replaced with the signed bundle: {}", file, err))?;
+        Ok(())
+    }
+
+    /// Sign a Burn bundle: its engine first, which insignia detaches and reattaches, then the bundle. Everything
+    /// happens on a copy in a temporary directory, the bundle is only replaced once it verifies
+    fn sign_burn_bundle(&mut self, file: &str, outcome: &mut SignOutcome) -> Result<(), Error> {
+        let connected = self.connected.as_mut().expect("connected before signing");
+        let insignia = match &connected.insignia {
+            Some(insignia) => insignia.clone(),
+            None => connected.insignia.insert(burn::find_insignia(self.options.wix_path.as_deref())?).clone(),
+        };
+
+        let work = scratch::dir("trusted-signing-burn", Some(Path::new(file)))
+            .map_err(|err| format!("a directory to sign '{}' in could not be created: {}", file, err))?;
+        let name = Path::new(file).file_name().unwrap_or_default();
+        let (bundle, engine) = (work.path().join(name), work.path().join("engine.exe"));
+        fs::create_dir_all(work.path().join("signed")).map_err(|err| err.to_string())?;
+        let output = work.path().join("signed").join(name);
+        fs::copy(file, &bundle).map_err(|err| format!("'{}' could not be copied: {}", file, err))?;
+
+        let signature = |part, path: &Path| Signature {
+            part,
+            sha256: hash::sha256_file(path).ok(),
+            signed_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        };
+        burn::detach(&insignia, &bundle, &engine, file)?;
+        self.sign(&engine.display().to_string(), outcome)?;
+        outcome.signatures.push(signature("engine".to_string(), &engine));
+        info!("signed the Burn engine of {}", file);
+        burn::reattach(&insignia, &engine, &bundle, &output, file)?;
+        self.sign(&output.display().to_string(), outcome)?;
+        outcome.signatures.push(signature("bundle".to_string(), &output));
+
+        let verifier = verify::Verifier::new(self.options.verify_with_signtool, &self.options.sign_tool_path);
+        let verification = verify::verify(&verifier, &output.display().to_string());
+        if !verification.valid {
+            Err(Error::Signing {
+                path: file.to_string(),
+                message: format!(
+                    "the signed bundle '{}' doesn't verify, it was left as it was: {}",
+                    file,
+                    verification.error.unwrap_or_default()
+                ),
+                output: String::new(),
+            })?;
+        }
+        scratch::replace(&output, Path::new(file))
+            .map_err(|err| format!("'{}' could not be replaced with the signed bundle: {}", file, err))?;
+        Ok(())
+    }
+
+    /// Sign the binaries of a Squirrel package and pack it again around them, the package is only replaced once
+    /// the signed one is complete. Its RELEASES entry is brought up to date after the run
+    fn sign_squirrel_package(&mut self, file: &str, outcome: &mut SignOutcome) -> Result<(), Error> {
+        let work = scratch::dir("trusted-signing-squirrel", Some(Path::new(file)))
+            .map_err(|err| format!("a directory to unpack '{}' into could not be created: {}", file, err))?;
+        let invalid = |message| Error::UnsupportedInput { path: file.to_string(), message };
+        let binaries = squirrel::unpack(Path::new(file), work.path()).map_err(invalid)?;
+        if binaries.is_empty() {
+            info!("{} has no unsigned binaries, leaving it as it is", file);
+            return Ok(());
+        }
+        for binary in &binaries {
+            self.sign(&binary.path.display().to_string(), outcome)?;
+            outcome.signatures.push(Signature {
+                part: binary.name.clone(),
+                sha256: hash::sha256_file(&binary.path).ok(),
+                signed_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            });
+        }
+        let output = work.path().join(Path::new(file).file_name().unwrap_or_default());
+        squirrel::repack(Path::new(file), &binaries, &output).map_err(|message| Error::Signing {
+            path: file.to_string(),
+            message,
+            output: String::new(),
+        })?;
+        scratch::replace(&output, Path::new(file))
+            .map_err(|err| format!("'{}' could not be replaced with the signed package: {}", file, err))?;
+        let names: Vec<&str> = binaries.iter().map(|binary| binary.name.as_str()).collect();
+        info!("signed {} in {}", names.join(", "), file);
+        Ok(())
+    }
+
+    /// Sign through the REST API, with an access token that is renewed well before it expires
+    async fn sign_rest(&mut self, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+        let connected = self.connected.as_mut().expect("connected before signing");
+        let token = match &connected.token {
+            Some((token, acquired)) if acquired.elapsed() < Duration::from_secs(30 * 60) => token.clone(),
+            _ => {
+                let token = azure::access_token(&self.options.azure_cli_path).map_err(Error::Auth)?;
+                connected.token.insert((token, Instant::now())).0.clone()
+            }
+        };
+        let options = rest::Options {
+            osslsigncode: connected.osslsigncode.as_deref().expect("found when connecting"),
+            target: &outcome.target,
+            token: &token,
+            file_digest: self.options.file_digest,
+            timestamp_url: &self.options.timestamp_url,
+            timestamp_digest: self.options.timestamp_digest,
+            description: self.options.description_of(file),
+        };
+        rest::sign(&options, file).await
+    }
+
+    /// Run `dotnet nuget sign`, finding dotnet the first time
+    fn sign_package(&mut self, file: &str) -> Result<(), Error> {
+        let connected = self.connected.as_mut().expect("connected before signing");
+        let dotnet = match &connected.dotnet {
+            Some(dotnet) => dotnet,
+            None => connected.dotnet.insert(nuget::find(self.options.dotnet_path.as_deref())?),
+        };
+        let options = self.options.nuget_options().expect("packages are only signed with a certificate");
+        nuget::sign(dotnet, &options, file)
+    }
+
+    /// Run the `sign` tool with the file's target and the service principal
+    fn sign_vsix(&self, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+        let options = &self.options;
+        let tool = options.vsix_tool.as_deref().expect("VSIX packages are only signed with a tool");
+        let credentials = [
+            ("AZURE_TENANT_ID", options.azure_tenant_id.as_str()),
+            ("AZURE_CLIENT_ID", &options.azure_client_id),
+            ("AZURE_CLIENT_SECRET", &options.azure_client_secret),
+        ];
+        let vsix_options = vsix::Options {
+            target: &outcome.target,
+            file_digest: options.file_digest,
+            timestamp_url: &options.timestamp_url,
+            timestamp_digest: options.timestamp_digest,
+            description: options.description_of(file),
+        };
+        vsix::sign(tool, credentials, &vsix_options, file)
+    }
+
+    /// Run mage on both manifests of a deployment, finding it the first time
+    fn sign_deployment(&mut self, file: &str) -> Result<(), Error> {
+        let connected = self.connected.as_mut().expect("connected before signing");
+        let mage = match &connected.mage {
+            Some(mage) => mage,
+            None => connected.mage.insert(clickonce::find(self.options.mage_path.as_deref())?),
+        };
+        let options = clickonce::Options {
+            certificate_hash: self.options.clickonce_certificate.as_deref().expect("deployments need a certificate"),
+            file_digest: self.options.file_digest,
+            timestamp_url: &self.options.timestamp_url,
+        };
+        clickonce::sign(mage, &options, file)
+    }
+
+    /// Arguments for running signtool without this signer, like `signtool <arguments> file.exe`.
+    /// Downloads the signing client and writes a metadata file kept for later runs, no login needed
+    /// here but signtool authenticates with the azure cli session or the AZURE_* environment variables.
+    pub async fn signtool_arguments(&mut self) -> Result<Vec<OsString>, Error> {
+        self.options.check_endpoints()?;
+        if !self.options.signtool_template.as_ref().is_none_or(Template::ends_with_file) {
+            Err(Error::Usage(
+                "the files go after the arguments printed, --signtool-template has to end with {file}".to_string(),
+            ))?;
+        }
+        let lib_path = self.download_client().await?;
+        let metadata_path = Metadata::new(&self.options.target, self.options.correlation_id.clone())
+            .write_kept(&config::dir())?;
+        Ok(signtool::arguments(&lib_path, &metadata_path, &self.options.signtool()))
+    }
+
+    /// Sign a file, skipped files count as success
+    pub async fn sign_file(&mut self, file: &str) -> Result<SignOutcome, Error> {
+        let (outcome, result) = self.attempt(file).await;
+        result.map(|_| outcome)
+    }
+
+    /// Sign the files in order, stopping at the first failure
+    pub async fn sign_all<S: AsRef<str>>(&mut self, files: &[S]) -> Result<Vec<SignOutcome>, Error> {
+        let mut outcomes = Vec::with_capacity(files.len());
+        for file in files {
+            outcomes.push(self.sign_file(file.as_ref()).await?);
+        }
+        Ok(outcomes)
+    }
+}
+
+/// Metadata file of a target, written the first time a file is routed to it
+fn metadata_for<'a>(
+    files: &'a mut Vec<(Target, MetadataFile)>,
+    target: &Target,
+    correlation_id: &Option<String>,
+    dir: &Path,
+    keep: bool,
+) -> Result<&'a Path, Error> {
+    let index = match files.iter().position(|(existing, _)| existing == target) {
+        Some(index) => index,
+        None => {
+            files.push((target.clone(), Metadata::new(target, correlation_id.clone()).write(dir, keep)?));
+            files.len() - 1
+        }
+    };
+    Ok(files[index].1.path())
+}
+
+/// The detached PKCS#7 signtool wrote for `file` into `dir`, checking the file itself was left as it was
+fn detached_signature(file: &str, dir: &Path, sha256_before: Option<&str>) -> Result<String, Error> {
+    let signature_file = signtool::p7_path(dir, file);
+    if !signature_file.is_file() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("signtool wrote no detached signature '{}' for '{}'", signature_file.display(), file),
+            output: String::new(),
+        })?;
+    }
+    if sha256_before.is_none() || hash::sha256_file(file).ok().as_deref() != sha256_before {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("'{}' changed while its detached signature was written", file),
+            output: String::new(),
+        })?;
+    }
+    Ok(signature_file.display().to_string())
+}
+
+/// Log in to the azure cli unless the session saved by an earlier run can be reused. Blocks until az is done
+fn login(options: &SignOptions) -> Result<(), Error> {
+    let _span = debug_span!("login").entered();
+    let reused = options.reuse_session
+        && session::reusable(
+            &options.azure_cli_path,
+            &options.azure_tenant_id,
+            &options.azure_client_id,
+            &options.azure_client_secret,
+        );
+    if reused {
+        info!("reusing the azure cli session of {}", &options.azure_client_id);
+        return Ok(());
+    }
+    info!("logging in to azure cli as {}", &options.azure_client_id);
+    let account = azure::login(
+        &options.azure_cli_path,
+        &options.azure_tenant_id,
+        &options.azure_client_id,
+        &options.azure_client_secret,
+        options.show_account,
+    )
+    .map_err(Error::Auth)?;
+    if options.show_account {
+        info!("logged in, available subscriptions: {}", account.trim());
+    }
+    if let Err(err) = session::save(&options.azure_tenant_id, &options.azure_client_id, &options.azure_client_secret) {
+        debug!("{}", err);
+    }
+    Ok(())
+}
+
+/// Wait for the signing client and the login, which don't need each other. Both always finish; when both fail the
+/// signing client's error is returned, as when the login came after it, and the login's is logged
+async fn overlap<T, U>(
+    dependencies: impl Future<Output = Result<T, Error>>,
+    login: impl Future<Output = Result<U, Error>>,
+) -> Result<(T, U), Error> {
+    match tokio::join!(dependencies, login) {
+        (Ok(dependencies), Ok(login)) => Ok((dependencies, login)),
+        (Err(err), Ok(_)) | (Ok(_), Err(err)) => Err(err),
+        (Err(err), Err(login)) => {
+            warn!("the azure cli login failed as well: {}", logging::mask(&login.to_string()));
+            Err(err)
+        }
+    }
+}
+
+/// Wait for a file with --wait-for-file, failing like a missing file when it didn't show up in time
+async fn wait_for(file: &str, timeout: Duration) -> Result<(), Error> {
+    let waited = match input::wait_for(file, timeout).await {
+        Ok(waited) => {
+            debug!("{} was written after {:.1}s", file, waited.as_secs_f64());
+            return Ok(());
+        }
+        Err(waited) => waited.as_secs_f64(),
+    };
+    match Path::new(file).is_file() {
+        false => Err(Error::UnsupportedInput {
+            path: file.to_string(),
+            message: format!("'{}' does not exist or is not a file, after waiting {:.1}s for it", file, waited),
+        }),
+        true => Err(Error::InvalidInput {
+            path: file.to_string(),
+            message: format!("'{}' was still being written after waiting {:.1}s for it", file, waited),
+        }),
+    }
+}
+
+/// The existing signer of `file` that --exclude-signed-by protects, if any
+fn protected_by(file: &str, options: &SignOptions) -> Option<SigningCertificate> {
+    if options.exclude_signed_by.is_empty() {
+        return None;
+    }
+    certificate::signers_of_file(file)
+        .into_iter()
+        .find(|signer| options.exclude_signed_by.iter().any(|protected| protected.matches(signer)))
+}
+
+/// List a file signed without a timestamp for `timestamp --pending`, with its hash once every check passed
+/// The error of a check `file` failed once signed
+fn check_failed(file: &str, message: String) -> Error {
+    Error::Signing { path: file.to_string(), message, output: String::new() }
+}
+
+/// --fail-on-warnings: a file signed with warnings fails
+fn check_warnings(options: &SignOptions, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+    match options.fail_on_warnings && !outcome.warnings.is_empty() {
+        true => Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("'{}' was signed with warnings and --fail-on-warnings is set", file),
+            output: outcome.warnings.join("\n"),
+        }),
+        false => Ok(()),
+    }
+}
+
+/// --require-sha2: the signatures of `signed` use SHA-2 only
+fn check_digests(options: &SignOptions, signed: &str, outcome: &mut SignOutcome) -> Result<(), Error> {
+    if !options.require_sha2 || outcome.mechanism != Mechanism::Authenticode {
+        return Ok(());
+    }
+    let (digests, result) = sha2_only(signed);
+    outcome.digests = digests;
+    result
+}
+
+/// A PowerShell script still has what it had before signing `before` it, and PowerShell would accept its
+/// signature
+fn check_script(file: &str, before: &[u8]) -> Result<(), Error> {
+    let after = fs::read(file).map_err(|err| err.to_string());
+    after.and_then(|after| psmodule::check(file, before, &after)).map_err(|err| {
+        check_failed(file, format!("'{}' was signed, but PowerShell would not accept its signature: {}", file, err))
+    })
+}
+
+/// --validate-pe: the signed image still matches its headers
+fn check_image(options: &SignOptions, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+    // a detached signature leaves the image as it was
+    match options.validate_pe && options.detached_p7.is_none() && outcome.mechanism == Mechanism::Authenticode {
+        true => pe::validate(file),
+        false => Ok(()),
+    }
+}
+
+/// --expect-arch and --expect-subsystem: the image is the build expected
+fn check_arch(options: &SignOptions, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+    let expected = options.expect_arch.is_some() || options.expect_subsystem.is_some();
+    match expected && outcome.mechanism == Mechanism::Authenticode {
+        true => arch::check(file, options.expect_arch, options.expect_subsystem).map_err(|err| check_failed(file, err)),
+        false => Ok(()),
+    }
+}
+
+/// --verify-revocation: no certificate of the chains of `signed` is revoked
+fn check_revocation(options: &SignOptions, signed: &str, outcome: &mut SignOutcome) -> Result<(), Error> {
+    let Some(offline) = options.verify_revocation.filter(|_| outcome.mechanism == Mechanism::Authenticode) else {
+        return Ok(());
+    };
+    let (checks, result) = revocation_checked(signed, offline);
+    outcome.revocation = checks;
+    result
+}
+
+/// --expect-subject and --expect-thumbprint: the certificate `signed` was signed with is the one expected
+fn check_certificate(options: &SignOptions, file: &str, signed: &str, outcome: &SignOutcome) -> Result<(), Error> {
+    let expected = &options.expect_certificate;
+    match &outcome.certificate {
+        _ if expected.is_empty() => Ok(()),
+        Some(certificate) => expected.check(file, certificate).map_err(|err| check_failed(file, err)),
+        None if certificate::is_read(signed) => Err(check_failed(
+            file,
+            format!("the signing certificate of '{}' could not be read to check it", file),
+        )),
+        None => {
+            debug!("the certificate of {} isn't read, it is signed without checking it", file);
+            Ok(())
+        }
+    }
+}
+
+/// --pin-subject and --pin-file: the identity `signed` was signed with is the pinned one, a warning when the pin
+/// only warns
+fn check_pin(pin: &mut pin::Pin, file: &str, signed: &str, outcome: &mut SignOutcome) -> Result<(), Error> {
+    if pin.is_empty() || !certificate::is_read(signed) {
+        return Ok(());
+    }
+    let observed = certificate::chain_of_file(signed).and_then(|chain| pin::Identity::of_chain(&chain));
+    let checked = match observed {
+        Some(observed) => pin.check(file, &observed),
+        None => Err(format!("the signing certificate of '{}' could not be read to check the pin", file)),
+    };
+    if let Some(warning) = checked.map_err(|err| check_failed(file, err))? {
+        warn!("{}", warning);
+        outcome.warnings.push(warning);
+    }
+    Ok(())
+}
+
+/// --defer-timestamp-on-failure: list a file signed without a timestamp for `timestamp --pending`, and drop one
+/// signed again with a timestamp from the list
+fn settle_timestamp(options: &SignOptions, file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+    if outcome.awaiting_timestamp {
+        return await_timestamp(file, outcome);
+    }
+    if options.defer_timestamp {
+        // what an earlier run listed would only fail its hash check
+        let path = pending::listed_path(file);
+        if pending::all().iter().any(|entry| entry.path == path) {
+            if let Err(err) = pending::remove(&[path]) {
+                warn!("{}", err);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn await_timestamp(file: &str, outcome: &SignOutcome) -> Result<(), Error> {
+    let unlisted = |err: String| Error::Signing {
+        path: file.to_string(),
+        message: format!("'{}' was signed without a timestamp, but it could not be listed to get one: {}", file, err),
+        output: String::new(),
+    };
+    let sha256 = match &outcome.sha256_after {
+        Some(sha256) => sha256.clone(),
+        None => hash::sha256_file(file).map_err(|err| unlisted(err.to_string()))?,
+    };
+    let entry = pending::Entry { path: pending::listed_path(file), sha256, signed_at: outcome.signed_at.clone() };
+    pending::add(entry).map_err(unlisted)?;
+    warn!("{} is signed WITHOUT a timestamp, `trusted-signing-cli timestamp --pending` adds it later", file);
+    Ok(())
+}
+
+/// Check a file can be signed by `mechanism`, returning why it is skipped when it is
+fn prepare(file: &str, options: &SignOptions, mechanism: Mechanism) -> Result<Option<&'static str>, Error> {
+    if !Path::new(file).is_file() {
+        Err(Error::UnsupportedInput {
+            path: file.to_string(),
+            message: format!("'{}' does not exist or is not a file", file),
+        })?;
+    }
+    // the other tools were picked by the extension, and a detached signature can be made of any file
+    let detached = options.detached_p7.is_some();
+    if mechanism == Mechanism::Authenticode && !detached && !input::is_supported(file, &options.extensions) {
+        if options.ignore_unsupported {
+            return Ok(Some("unsupported file type"));
+        }
+        let hint = match file {
+            file if nuget::is_package(file) => "pass --nuget to sign NuGet packages with dotnet nuget sign",
+            file if vsix::is_package(file) => {
+                "VSIX packages need an OPC signature, pass --vsix-tool with the path of Microsoft's sign tool \
+                 (dotnet tool install --global sign --prerelease)"
+            }
+            file if clickonce::is_deployment(file) => "pass --clickonce to sign ClickOnce deployments with mage",
+            file if clickonce::is_manifest(file) => {
+                "an application manifest is signed together with the deployment manifest referencing it, \
+                 pass its .application or .vsto with --clickonce"
+            }
+            _ => "pass --ignore-unsupported to skip it, or --extra-extensions to sign it anyway",
+        };
+        Err(Error::UnsupportedInput {
+            path: file.to_string(),
+            message: format!("'{}' is not a file type signtool can sign, {}", file, hint),
+        })?;
+    }
+    if let Err(message) = input::check_structure(file) {
+        if options.skip_invalid {
+            warn!("{}, skipping it", message);
+            return Ok(Some("invalid file"));
+        }
+        Err(Error::InvalidInput {
+            path: file.to_string(),
+            message: format!("{}, pass --skip-invalid to skip it", message),
+        })?;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{authenticode, der};
+
+    #[test]
+    fn times_restored() {
+        let temp = tempfile::tempdir().unwrap();
+        let file = temp.path().join("app.exe");
+        fs::write(&file, b"MZ").unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(old).unwrap();
+        let file = file.to_str().unwrap();
+        let times = file_times(file).unwrap();
+        // signing replaces the file, like msix and squirrel do through a rename
+        fs::write(temp.path().join("signed"), b"MZ signed").unwrap();
+        fs::rename(temp.path().join("signed"), file).unwrap();
+        assert_ne!(fs::metadata(file).unwrap().modified().unwrap(), old);
+        restore_times(file, times).unwrap();
+        assert_eq!(fs::metadata(file).unwrap().modified().unwrap(), old);
+        assert!(restore_times(&format!("{}.missing", file), file_times(file).unwrap()).is_err());
+    }
+
+    #[test]
+    fn checks_after_signing() {
+        let temp = tempfile::tempdir().unwrap();
+        let file = temp.path().join("app.exe");
+        fs::write(&file, include_bytes!("../tests/fixtures/pe/x64-console.bin")).unwrap();
+        let file = file.to_str().unwrap();
+        let target = Target { endpoint: "eus".into(), account: "acc".into(), certificate: "prof".into() };
+        let mut outcome = SignOutcome::new(file, Mechanism::Authenticode, target, None);
+        let options = SignOptions::new("eus", "acc", "prof").unwrap();
+
+        outcome.warnings.push("the timestamp server was slow".into());
+        assert!(check_warnings(&options, file, &outcome).is_ok());
+        let failing = options.clone().fail_on_warnings(true);
+        let err = check_warnings(&failing, file, &outcome).unwrap_err();
+        assert!(matches!(&err, Error::Signing { output, .. } if output == "the timestamp server was slow"));
+
+        assert!(check_arch(&options.clone().expect(Some(Arch::X64), Some(Subsystem::Console)), file, &outcome).is_ok());
+        let arm64 = options.clone().expect(Some(Arch::Arm64), None);
+        let err = check_arch(&arm64, file, &outcome).unwrap_err().to_string();
+        assert!(err.contains("--expect-arch"), "{}", err);
+        // only the images signtool signs are checked
+        outcome.mechanism = Mechanism::Nuget;
+        assert!(check_arch(&arm64, file, &outcome).is_ok());
+
+        // nothing to check without --expect-subject or a pin
+        assert!(check_certificate(&options, file, file, &outcome).is_ok());
+        assert!(check_pin(&mut options.clone().pin, file, file, &mut outcome).is_ok());
+        assert!(settle_timestamp(&options, file, &outcome).is_ok());
+    }
+
+    #[test]
+    fn metadata_in_non_ascii_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("Jürgen Müller").join("构建 目录").join(".trusted-signing-cli");
+        fs::create_dir_all(&dir).unwrap();
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net".into(),
+            account: "Größe".into(),
+            certificate: "prof".into(),
+        };
+        let metadata = Metadata::new(&target, None);
+        for keep in [false, true] {
+            let file = metadata.write(&dir, keep).unwrap();
+            assert_eq!(file.path().parent(), Some(dir.as_path()));
+            let written: Metadata = serde_json::from_slice(&fs::read(file.path()).unwrap()).unwrap();
+            assert_eq!(written.code_signing_account_name, "Größe");
+            let options = SignOptions::new("eus", "Größe", "prof").unwrap();
+            let args = signtool::arguments(&dir.join("lib.dll"), file.path(), &options.signtool());
+            assert!(args.contains(&file.path().as_os_str().to_os_string()));
+        }
+        let err = metadata.write(&dir.join("missing"), false).unwrap_err().to_string();
+        assert!(err.contains("Jürgen Müller") && err.contains("构建 目录"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn skipped_without_login() {
+        let temp = tempfile::tempdir().unwrap();
+        let notes = temp.path().join("notes.txt");
+        fs::write(&notes, "not signable").unwrap();
+        let notes = notes.to_str().unwrap();
+        let options = SignOptions::new("eus", "acc", "prof").unwrap().azure_cli_path("/missing/az");
+        let mut signer = Signer::new(options.clone().ignore_unsupported(true), Events::default());
+        assert_eq!(signer.skip_reason(notes), Some("unsupported file type"));
+        let (outcome, result) = signer.attempt(notes).await;
+        assert!(result.is_ok() && outcome.skipped.is_some());
+
+        let mut signer = Signer::new(options.clone(), Events::default());
+        assert_eq!(signer.skip_reason(notes), None);
+        assert!(matches!(signer.attempt(notes).await.1, Err(Error::UnsupportedInput { .. })));
+
+        // a catalog the vendor signed, a PKCS#7 as a whole
+        let (vendor_certificate, _) = crate::testcert::generate("CN=Fabrikam Ltd", 30, Default::default()).unwrap();
+        let content = der::sequence(&[&der::sequence(&[&der::oid(&[1, 3, 6, 1, 4, 1, 311, 10, 1])])]);
+        let signable = authenticode::Signable::new(content, Digest::Sha256, None).unwrap();
+        let vendor = temp.path().join("vendor.cat");
+        fs::write(&vendor, signable.signed_data(&[vendor_certificate], &[0x5a; 64]).unwrap()).unwrap();
+        let vendor = vendor.to_str().unwrap();
+        let protects = |value: &str| vec![certificate::parse_protected(value).unwrap()];
+        let mut signer = Signer::new(options.clone().exclude_signed_by(protects("fabrikam")), Events::default());
+        assert_eq!(signer.skip_reason(vendor), Some(PROTECTED));
+        assert_eq!(signer.skip_reason(notes), None);
+        let (outcome, result) = signer.attempt(vendor).await;
+        assert!(result.is_ok() && outcome.skipped == Some(PROTECTED));
+        assert!(outcome.protected_by.unwrap().subject.starts_with("CN=Fabrikam Ltd"));
+        let signer = Signer::new(options.exclude_signed_by(protects("Contoso")), Events::default());
+        assert_ne!(signer.skip_reason(vendor), Some(PROTECTED));
+    }
+
+    #[tokio::test]
+    async fn download_overlaps_login() {
+        let log = std::sync::Mutex::new(Vec::new());
+        let step = |name: &'static str, pause: u64, result: Result<&'static str, &'static str>| {
+            let log = &log;
+            async move {
+                log.lock().unwrap().push(format!("{} started", name));
+                tokio::time::sleep(Duration::from_millis(pause)).await;
+                log.lock().unwrap().push(format!("{} finished", name));
+                result.map_err(|message| Error::Other(message.to_string()))
+            }
+        };
+
+        let joined = overlap(step("download", 20, Ok("lib")), step("login", 40, Ok("az"))).await.unwrap();
+        assert_eq!(joined, ("lib", "az"));
+        let order = ["download started", "login started", "download finished", "login finished"];
+        assert_eq!(*log.lock().unwrap(), order);
+
+        // a failing login doesn't cut the download short, and the download's error comes first
+        log.lock().unwrap().clear();
+        let err = overlap(step("download", 40, Ok("lib")), step("login", 0, Err("denied"))).await.unwrap_err();
+        assert_eq!(err.to_string(), "denied");
+        assert_eq!(log.lock().unwrap().last().map(String::as_str), Some("download finished"));
+        let both = overlap(step("download", 0, Err::<&str, _>("offline")), step("login", 0, Err("denied"))).await;
+        assert_eq!(both.unwrap_err().to_string(), "offline");
+    }
+}
@@ -0,0 +1,619 @@
+use crate::{
+    error::Error,
+    hints,
+    logging::{self, run_captured},
+};
+use clap::ValueEnum;
+use duct::cmd;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::{
+    ffi::{OsStr, OsString},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Where the Windows SDK installs signtool, used unless --sing-tool-path says otherwise
+pub const DEFAULT_PATH: &str = r"C:\Program Files (x86)\Windows Kits\10\bin\10.0.22000.0\x64\signtool.exe";
+
+/// Timestamp authority of Trusted Signing, used unless --tr says otherwise
+pub const TIMESTAMP_URL: &str = "http://timestamp.acs.microsoft.com";
+
+/// Digest algorithm for `/fd` and `/td`
+#[derive(ValueEnum, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[value(rename_all = "UPPER")]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Digest {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Digest {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Digest::Sha1 => "SHA1",
+            Digest::Sha256 => "SHA256",
+            Digest::Sha384 => "SHA384",
+            Digest::Sha512 => "SHA512",
+        }
+    }
+}
+
+/// What a detached PKCS#7 holds (`/p7ce`)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum P7Content {
+    /// The signed content with the signature
+    Embedded,
+    /// Only the signature, the signed data part of a detached PKCS#7
+    DetachedSignedData,
+    /// Only the signature, as a whole detached PKCS#7
+    Pkcs7DetachedSignedData,
+}
+
+impl P7Content {
+    fn name(self) -> &'static str {
+        match self {
+            P7Content::Embedded => "Embedded",
+            P7Content::DetachedSignedData => "DetachedSignedData",
+            P7Content::Pkcs7DetachedSignedData => "Pkcs7DetachedSignedData",
+        }
+    }
+}
+
+/// The PKCS#7 signature signtool writes into a directory instead of signing the file itself (`/p7`)
+#[derive(Debug, Clone, Copy)]
+pub struct Detached<'a> {
+    pub dir: &'a Path,
+    /// OID of the content type (`/p7co`)
+    pub content_type: &'a str,
+    pub content: P7Content,
+}
+
+/// The PKCS#7 signtool writes for `file` into `dir`, `app.bin` gets `app.bin.p7`
+pub fn p7_path(dir: &Path, file: &str) -> PathBuf {
+    let name = Path::new(file).file_name().unwrap_or_default().to_string_lossy();
+    dir.join(format!("{}.p7", name))
+}
+
+/// How signtool is asked to sign
+#[derive(Debug)]
+pub struct Options<'a> {
+    pub file_digest: Digest,
+    /// Timestamp server of `/tr`, empty leaves out `/tr` and `/td` to sign without a timestamp
+    pub timestamp_url: &'a str,
+    pub timestamp_digest: Digest,
+    pub description: Option<&'a str>,
+    /// Append the signature to the existing ones instead of replacing them
+    pub append: bool,
+    /// Add page hashes (`/ph`), which kernel-mode code integrity checks as pages are loaded
+    pub page_hashes: bool,
+    /// Write a detached signature instead of signing the file
+    pub detached: Option<Detached<'a>>,
+    /// Arguments from --signtool-extra-arg, `{extra}` of the template
+    pub extra: &'a [String],
+    /// How the arguments of signing with the dlib are arranged, [DEFAULT_TEMPLATE] without one
+    pub template: Option<&'a Template>,
+}
+
+/// The arguments of signing with the dlib as a --signtool-template, what signtool gets without one
+pub const DEFAULT_TEMPLATE: &str = "sign /v /fd {fd} /tr {tr} /td {td} /d {description?} {as?} {ph?} /p7 {p7?} \
+                                    /p7co {p7co?} /p7ce {p7ce?} {extra} /dlib {dlib} /dmdf {dmdf} {file}";
+
+/// The placeholders of a template, whether they may be unset (written `{name?}`) and what they are
+const PLACEHOLDERS: [(&str, bool, &str); 13] = [
+    ("fd", false, "the file digest"),
+    ("tr", false, "the timestamp URL"),
+    ("td", false, "the timestamp digest"),
+    ("dlib", false, "the signing client"),
+    ("dmdf", false, "the metadata file"),
+    ("file", false, "the file to sign"),
+    ("extra", false, "the --signtool-extra-arg arguments, as many words as given"),
+    ("description", true, "--description"),
+    ("as", true, "/as with --append-signature"),
+    ("ph", true, "/ph when page hashes are added"),
+    ("p7", true, "the directory of --detached-p7"),
+    ("p7co", true, "--p7-content-type with --detached-p7"),
+    ("p7ce", true, "--p7-content with --detached-p7"),
+];
+
+/// The arguments of `signtool` as words with `{placeholders}`, like [DEFAULT_TEMPLATE]. A word in double quotes may
+/// hold spaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    words: Vec<String>,
+}
+
+/// The placeholders in `word`, with their byte ranges
+fn placeholders(word: &str) -> Result<Vec<(std::ops::Range<usize>, &str)>, String> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = word[offset..].find('{').map(|start| offset + start) {
+        let end = word[start..].find('}').ok_or_else(|| format!("'{}' has a {{ without its }}", word))? + start;
+        found.push((start..end + 1, &word[start + 1..end]));
+        offset = end + 1;
+    }
+    Ok(found)
+}
+
+impl Template {
+    /// Read a --signtool-template, checking it has known placeholders, `?` on the ones that may be unset only,
+    /// `{extra}` as a word of its own, and `{file}`, `{dlib}` and `{dmdf}` once each
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let (mut words, mut word, mut quoted, mut started) = (Vec::new(), String::new(), false, false);
+        for char in text.chars() {
+            match char {
+                '"' => (quoted, started) = (!quoted, true),
+                char if char.is_whitespace() && !quoted => {
+                    if started {
+                        words.push(std::mem::take(&mut word));
+                    }
+                    started = false;
+                }
+                char => {
+                    word.push(char);
+                    started = true;
+                }
+            }
+        }
+        if quoted {
+            return Err("--signtool-template has a \" without its closing \"".to_string());
+        }
+        if started {
+            words.push(word);
+        }
+        let mut counts = [0; PLACEHOLDERS.len()];
+        for word in &words {
+            for (_, name) in placeholders(word)? {
+                let (bare, marked) = match name.strip_suffix('?') {
+                    Some(bare) => (bare, true),
+                    None => (name, false),
+                };
+                let Some(index) = PLACEHOLDERS.iter().position(|(known, _, _)| *known == bare) else {
+                    let known: Vec<String> = PLACEHOLDERS
+                        .iter()
+                        .map(|(name, optional, _)| format!("{{{}{}}}", name, if *optional { "?" } else { "" }))
+                        .collect();
+                    let known = known.join(" ");
+                    return Err(format!("{{{}}} is not a placeholder of --signtool-template, it knows {}", name, known));
+                };
+                match (PLACEHOLDERS[index].1, marked) {
+                    (true, false) => Err(format!("{{{}}} may be unset, write it {{{}?}}", bare, bare))?,
+                    (false, true) => Err(format!("{{{}}} is always set, write it without the ?", bare))?,
+                    _ => (),
+                }
+                if bare == "extra" && word != "{extra}" {
+                    Err(format!("{{extra}} is any number of words, it can't be part of '{}'", word))?;
+                }
+                counts[index] += 1;
+            }
+        }
+        for required in ["file", "dlib", "dmdf"] {
+            let index = PLACEHOLDERS.iter().position(|(name, _, _)| *name == required).expect("a placeholder");
+            match counts[index] {
+                0 => Err(format!("--signtool-template has no {{{}}}, signtool can't sign without it", required))?,
+                1 => (),
+                _ => Err(format!("--signtool-template has {{{}}} more than once", required))?,
+            }
+        }
+        Ok(Template { words })
+    }
+
+    /// Whether `{file}` is the last word, so the files can go after the other arguments
+    pub fn ends_with_file(&self) -> bool {
+        self.words.last().is_some_and(|word| word == "{file}")
+    }
+
+    /// The arguments with the placeholders replaced by `value`. A word with a placeholder `value` has none for is
+    /// dropped, together with the switch before it when the placeholder is the whole word, like `/d {description?}`.
+    /// A `{file}` without a file is dropped too
+    fn render(&self, value: &dyn Fn(&str) -> Vec<OsString>) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+        // whether the last argument is a switch of the template, which goes with a word dropped after it
+        let mut switch = false;
+        for word in &self.words {
+            let found = placeholders(word).expect("a parsed template");
+            if found.is_empty() {
+                args.push(word.into());
+                switch = word.starts_with(['/', '-']);
+                continue;
+            }
+            let values: Vec<Vec<OsString>> =
+                found.iter().map(|(_, name)| value(name.trim_end_matches('?'))).collect();
+            let whole = found.len() == 1 && found[0].0 == (0..word.len());
+            if whole && found[0].1 == "extra" {
+                args.extend(values.into_iter().flatten());
+            } else if values.iter().any(Vec::is_empty) {
+                if whole && switch {
+                    args.pop();
+                }
+            } else if whole {
+                args.extend(values.into_iter().flatten());
+            } else {
+                let mut arg = OsString::new();
+                let mut offset = 0;
+                for ((range, _), value) in found.iter().zip(&values) {
+                    arg.push(&word[offset..range.start]);
+                    value.iter().for_each(|value| arg.push(value));
+                    offset = range.end;
+                }
+                arg.push(&word[offset..]);
+                args.push(arg);
+            }
+            switch = false;
+        }
+        args
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::parse(DEFAULT_TEMPLATE).expect("the default template is valid")
+    }
+}
+
+/// Version of the Windows SDK signtool ships with, taken from its
+/// `Windows Kits\10\bin\<version>\<arch>\signtool.exe` location
+pub fn version(sign_tool_path: &str) -> Option<&str> {
+    sign_tool_path
+        .split(['\\', '/'])
+        .find(|segment| segment.starts_with("10.") && segment.split('.').all(|part| part.parse::<u32>().is_ok()))
+}
+
+/// The file version of an executable, from the fixed part of its version resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileVersion(pub [u16; 4]);
+
+impl fmt::Display for FileVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [major, minor, build, revision] = self.0;
+        write!(f, "{}.{}.{}.{}", major, minor, build, revision)
+    }
+}
+
+/// The oldest signtool Microsoft documents for signing with the Trusted Signing client, of the Windows 11 SDK.
+/// Older ones fail on `/dlib` or `/dmdf` with an invalid option error
+pub const MINIMUM_VERSION: FileVersion = FileVersion([10, 0, 22621, 755]);
+
+/// Where to get a signtool new enough
+const SDK_HINT: &str = "Install the Windows 11 SDK 10.0.22621.755 or later \
+                        (https://developer.microsoft.com/windows/downloads/windows-sdk/) or pass the signtool of one \
+                        with --sing-tool-path";
+
+/// Signature of the VS_FIXEDFILEINFO after the `VS_VERSION_INFO` key
+const FIXED_FILE_INFO: u32 = 0xfeef04bd;
+
+/// The file version in the version resource of a PE image. The resource is found by its key rather than through
+/// the resource directory, the fixed info after it is aligned to 4 bytes from the start of the VS_VERSIONINFO
+fn fixed_file_version(image: &[u8]) -> Option<FileVersion> {
+    let key: Vec<u8> = "VS_VERSION_INFO\0".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let position = image.windows(key.len()).position(|window| window == key.as_slice())?;
+    // the length, value length and type come before the key
+    let fixed = position.checked_sub(6)? + (6 + key.len()).next_multiple_of(4);
+    let u32_at = |offset: usize| Some(u32::from_le_bytes(image.get(offset..offset + 4)?.try_into().ok()?));
+    if u32_at(fixed)? != FIXED_FILE_INFO {
+        return None;
+    }
+    let (most, least) = (u32_at(fixed + 8)?, u32_at(fixed + 12)?);
+    Some(FileVersion([(most >> 16) as u16, most as u16, (least >> 16) as u16, least as u16]))
+}
+
+/// The file version of the signtool at `path`, None when it can't be read
+pub fn file_version(path: &str) -> Option<FileVersion> {
+    let image = fs::read(path).map_err(|err| debug!("signtool '{}' could not be read: {}", path, err)).ok()?;
+    let version = fixed_file_version(&image);
+    if version.is_none() {
+        debug!("signtool '{}' has no version resource, its version isn't checked", path);
+    }
+    version
+}
+
+/// Check the signtool at `path` is new enough for the signing client. One whose version can't be read passes
+pub fn check_version(path: &str) -> Result<(), String> {
+    match file_version(path) {
+        Some(version) if version < MINIMUM_VERSION => Err(format!(
+            "signtool '{}' is version {}, signing with the Trusted Signing client needs {} or later for /dlib and \
+             /dmdf",
+            path, version, MINIMUM_VERSION
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The error for a signtool too old, pointing at a newer one found installed or the SDK
+pub fn too_old(problem: &str, newer: Option<&str>) -> Error {
+    let hint = match newer {
+        Some(newer) => format!("A newer one is installed at '{}', pass it with --sing-tool-path", newer),
+        None => SDK_HINT.to_string(),
+    };
+    Error::MissingDependency { component: "signtool", message: format!("{}. {}", problem, hint) }
+}
+
+/// The numbers in a path, to order SDK versions like v8.1A before v10.0A
+fn version_key(path: &Path) -> Vec<u32> {
+    path.to_string_lossy()
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+/// The tool of the newest SDK matching the glob `pattern`, like `Windows Kits\10\bin\*\x64\makeappx.exe`
+pub(crate) fn newest(pattern: &str) -> Option<PathBuf> {
+    let installed = glob::glob(pattern).into_iter().flatten().flatten();
+    installed.max_by_key(|path| version_key(path))
+}
+
+/// Arguments of `signtool sign` before the key to sign with, what [DEFAULT_TEMPLATE] has before `/dlib`
+fn sign_arguments(options: &Options) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "sign",
+        "/v",
+        "/fd",
+        options.file_digest.name(),
+        "/tr",
+        options.timestamp_url,
+        "/td",
+        options.timestamp_digest.name(),
+    ]
+    .map(OsString::from)
+    .to_vec();
+    if let Some(description) = options.description {
+        args.extend(["/d", description].map(OsString::from));
+    }
+    if options.append {
+        args.push(OsString::from("/as"));
+    }
+    if options.page_hashes {
+        args.push(OsString::from("/ph"));
+    }
+    if let Some(detached) = options.detached {
+        args.extend([
+            OsString::from("/p7"),
+            detached.dir.into(),
+            OsString::from("/p7co"),
+            detached.content_type.into(),
+            OsString::from("/p7ce"),
+            detached.content.name().into(),
+        ]);
+    }
+    args.extend(options.extra.iter().map(OsString::from));
+    args
+}
+
+/// Arguments of `signtool` for signing `file` with the dlib and metadata, arranged by the template
+fn template_arguments(lib_path: &Path, metadata_path: &Path, options: &Options, file: Option<&str>) -> Vec<OsString> {
+    let default = Template::default();
+    let template = options.template.unwrap_or(&default);
+    let detached = options.detached;
+    template.render(&|name| {
+        let value: Option<&OsStr> = match name {
+            "fd" => Some(options.file_digest.name().as_ref()),
+            "tr" => Some(options.timestamp_url.as_ref()).filter(|url: &&OsStr| !url.is_empty()),
+            "td" => (!options.timestamp_url.is_empty()).then_some(options.timestamp_digest.name().as_ref()),
+            "dlib" => Some(lib_path.as_os_str()),
+            "dmdf" => Some(metadata_path.as_os_str()),
+            "file" => file.map(OsStr::new),
+            "extra" => return options.extra.iter().map(OsString::from).collect(),
+            "description" => options.description.map(OsStr::new),
+            "as" => options.append.then_some("/as".as_ref()),
+            "ph" => options.page_hashes.then_some("/ph".as_ref()),
+            "p7" => detached.map(|detached| detached.dir.as_os_str()),
+            "p7co" => detached.map(|detached| detached.content_type.as_ref()),
+            "p7ce" => detached.map(|detached| detached.content.name().as_ref()),
+            _ => None,
+        };
+        value.map(OsString::from).into_iter().collect()
+    })
+}
+
+/// Arguments of `signtool` for signing with the dlib and metadata, the files to sign go after them
+pub fn arguments(lib_path: &Path, metadata_path: &Path, options: &Options) -> Vec<OsString> {
+    template_arguments(lib_path, metadata_path, options, None)
+}
+
+/// Arguments of `signtool` for signing with the certificate and key of a PFX, `/p` only for a password
+pub fn pfx_arguments(pfx: &Path, password: &str, options: &Options) -> Vec<OsString> {
+    let mut args = sign_arguments(options);
+    args.extend([OsString::from("/f"), pfx.into()]);
+    if !password.is_empty() {
+        args.extend(["/p", password].map(OsString::from));
+    }
+    args
+}
+
+/// The exit code of signtool when it signed but printed warnings
+const COMPLETED_WITH_WARNINGS: i32 = 2;
+
+/// The warnings signtool printed, like a skipped timestamp
+fn warnings(output: &str) -> Vec<String> {
+    let warnings = output.lines().filter_map(|line| line.trim().strip_prefix("SignTool Warning:"));
+    warnings.map(|warning| warning.trim().to_string()).filter(|warning| !warning.is_empty()).collect()
+}
+
+/// Sign a single file with signtool, returns the warnings it printed
+pub fn sign(
+    sign_tool_path: &str,
+    lib_path: &Path,
+    metadata_path: &Path,
+    options: &Options,
+    file: &str,
+) -> Result<Vec<String>, Error> {
+    run(sign_tool_path, template_arguments(lib_path, metadata_path, options, Some(file)), file)
+}
+
+/// Sign a single file with a PFX, for test signatures without Trusted Signing. Returns the warnings signtool printed
+pub fn sign_pfx(
+    sign_tool_path: &str,
+    pfx: &Path,
+    password: &str,
+    options: &Options,
+    file: &str,
+) -> Result<Vec<String>, Error> {
+    let mut args = pfx_arguments(pfx, password, options);
+    args.push(file.into());
+    run(sign_tool_path, args, file)
+}
+
+/// Run `signtool sign` on `file`, with its warnings on success
+fn run(sign_tool_path: &str, args: Vec<OsString>, file: &str) -> Result<Vec<String>, Error> {
+    let message = format!("signtool '{}' could not sign the file '{}'", sign_tool_path, file);
+    let output = run_captured(cmd(sign_tool_path, args)).map_err(|err| Error::Signing {
+        path: file.to_string(),
+        message: format!("{}, error: {:?}", message, &err),
+        output: String::new(),
+    })?;
+    let with_warnings = output.status.code() == Some(COMPLETED_WITH_WARNINGS);
+    if !output.status.success() && !with_warnings {
+        let message = format!("{}, error: {}", message, output.status);
+        let output = logging::failure_output(&output);
+        Err(Error::Signing { path: file.to_string(), message: hints::explain(message, &output), output })?;
+    }
+
+    let mut warnings = warnings(&logging::failure_output(&output));
+    if with_warnings && warnings.is_empty() {
+        warnings.push("signtool completed with warnings".to_string());
+    }
+    Ok(warnings)
+}
+
+/// Add an RFC 3161 timestamp to the signature of a signed file, without signing it again
+pub fn timestamp(sign_tool_path: &str, url: &str, digest: Digest, file: &str) -> Result<(), String> {
+    let args = ["timestamp", "/tr", url, "/td", digest.name(), file];
+    let output = run_captured(cmd(sign_tool_path, args))
+        .map_err(|err| format!("signtool '{}' could not be run: {}", sign_tool_path, err))?;
+    match output.status.success() {
+        true => Ok(()),
+        false => {
+            let output_text = logging::failure_output(&output);
+            let message = format!("signtool timestamp failed, error: {}\n{}", output.status, output_text);
+            Err(message.trim_end().to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_from_path() {
+        assert_eq!(
+            version(r"C:\Program Files (x86)\Windows Kits\10\bin\10.0.22621.0\x64\signtool.exe"),
+            Some("10.0.22621.0")
+        );
+        assert_eq!(version(r"C:\tools\signtool.exe"), None);
+        assert!(version_key(Path::new(r"v8.1A\NETFX 4.5.1 Tools")) < version_key(Path::new(r"v10.0A\NETFX 4.8 Tools")));
+
+        // a VS_VERSIONINFO with its key, the padding and the fixed info of 10.0.22000.1
+        let mut image = b"MZ and a resource section ".to_vec();
+        image.extend([0x5c, 0x03, 0x34, 0x00, 0x00, 0x00]);
+        image.extend("VS_VERSION_INFO\0".encode_utf16().flat_map(u16::to_le_bytes));
+        image.extend([0, 0]);
+        for value in [FIXED_FILE_INFO, 0x10000, 0xa_0000, 0x55f0_0001] {
+            image.extend(u32::to_le_bytes(value));
+        }
+        let version = fixed_file_version(&image).unwrap();
+        assert_eq!(version.to_string(), "10.0.22000.1");
+        assert!(version < MINIMUM_VERSION && FileVersion([10, 0, 26100, 1]) > MINIMUM_VERSION);
+        assert_eq!(fixed_file_version(&image[..image.len() - 20]), None);
+    }
+
+    #[test]
+    fn warning_lines() {
+        let output = "Done Adding Additional Store\r\nSignTool Warning: Signing succeeded, but an error occurred while \
+                      attempting to timestamp: app.exe\r\nSuccessfully signed: app.exe\r\n";
+        let expected = "Signing succeeded, but an error occurred while attempting to timestamp: app.exe";
+        assert_eq!(warnings(output), [expected]);
+        assert!(warnings("Successfully signed: app.exe").is_empty());
+    }
+
+    #[test]
+    fn detached_signatures() {
+        let (dir, content_type) = (Path::new("signatures"), "1.2.840.113549.1.7.1");
+        let detached = Detached { dir, content_type, content: P7Content::Embedded };
+        let options = Options {
+            file_digest: Digest::Sha256,
+            timestamp_url: TIMESTAMP_URL,
+            timestamp_digest: Digest::Sha256,
+            description: None,
+            append: false,
+            page_hashes: false,
+            detached: Some(detached),
+            extra: &[],
+            template: None,
+        };
+        let args = arguments(Path::new("dlib.dll"), Path::new("metadata.json"), &options);
+        let p7 = ["/p7", "signatures", "/p7co", "1.2.840.113549.1.7.1", "/p7ce", "Embedded"].map(OsString::from);
+        assert!(args.windows(p7.len()).any(|window| window == p7));
+        assert_eq!(p7_path(dir, "firmware/image.bin"), dir.join("image.bin.p7"));
+
+        let args = pfx_arguments(Path::new("dev.pfx"), "hunter2", &Options { detached: None, ..options });
+        let args: Vec<String> = args.into_iter().map(|arg| arg.to_string_lossy().to_string()).collect();
+        assert!(args.join(" ").ends_with("/td SHA256 /f dev.pfx /p hunter2"), "{:?}", args);
+        assert!(!pfx_arguments(Path::new("dev.pfx"), "", &options).contains(&OsString::from("/p")));
+    }
+
+    #[test]
+    fn templates() {
+        let (lib, metadata) = (Path::new(r"C:\lib\dlib.dll"), Path::new("metadata.json"));
+        let detached = Detached { dir: Path::new("p7 dir"), content_type: "1.2.3", content: P7Content::Embedded };
+        let extra = ["/sha1".to_string(), "AB12".to_string()];
+        let plain = Options {
+            file_digest: Digest::Sha384,
+            timestamp_url: TIMESTAMP_URL,
+            timestamp_digest: Digest::Sha256,
+            description: None,
+            append: false,
+            page_hashes: false,
+            detached: None,
+            extra: &[],
+            template: None,
+        };
+        // the default template is what signtool got before templates, every switch set or not
+        let everything = Options {
+            description: Some("My App"),
+            append: true,
+            page_hashes: true,
+            detached: Some(detached),
+            extra: &extra,
+            ..plain
+        };
+        for options in [&plain, &everything] {
+            let mut expected = sign_arguments(options);
+            expected.extend(["/dlib".into(), lib.into(), "/dmdf".into(), metadata.into()]);
+            assert_eq!(arguments(lib, metadata, options), expected);
+            expected.push("app.exe".into());
+            assert_eq!(template_arguments(lib, metadata, options, Some("app.exe")), expected);
+        }
+
+        let text = r#"sign /fd {fd} "/n=Contoso Ltd" /d {description?} /x{p7?}y /tr {tr} {extra} {file} /dlib {dlib}"#;
+        let template = Template::parse(&format!("{} /dmdf {{dmdf}}", text)).unwrap();
+        assert!(!template.ends_with_file());
+        let options = Options { template: Some(&template), ..plain };
+        let args = template_arguments(lib, metadata, &options, Some("app.exe"));
+        let expected = format!(
+            "sign /fd SHA384 /n=Contoso Ltd /tr {} app.exe /dlib {} /dmdf metadata.json",
+            TIMESTAMP_URL,
+            lib.display()
+        );
+        assert_eq!(args.iter().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>().join(" "), expected);
+        let options = Options { template: Some(&template), ..everything };
+        let args = template_arguments(lib, metadata, &options, Some("app.exe"));
+        let words = ["/n=Contoso Ltd", "/d", "My App", "/xp7 diry", "/sha1", "AB12"].map(OsString::from);
+        assert!(words.iter().all(|word| args.contains(word)), "{:?}", args);
+        let args = template_arguments(lib, metadata, &Options { timestamp_url: "", ..plain }, Some("app.exe"));
+        assert!(!args.contains(&OsString::from("/tr")) && !args.contains(&OsString::from("/td")), "{:?}", args);
+
+        assert!(Template::parse(DEFAULT_TEMPLATE).unwrap().ends_with_file());
+        let error = |text: &str| Template::parse(text).unwrap_err();
+        assert!(error("sign /dlib {dlib} {file}").contains("no {dmdf}"));
+        assert!(error("sign /dlib {dlib} /dmdf {dmdf} {file} {file}").contains("more than once"));
+        assert!(error("sign {fd?} /dlib {dlib} /dmdf {dmdf} {file}").contains("always set"));
+        assert!(error("sign /d {description} /dlib {dlib} /dmdf {dmdf} {file}").contains("write it {description?}"));
+        assert!(error("sign {sha1} /dlib {dlib} /dmdf {dmdf} {file}").contains("{sha1} is not a placeholder"));
+        assert!(error("sign /x{extra} /dlib {dlib} /dmdf {dmdf} {file}").contains("can't be part of"));
+        assert!(error("sign \"/n /dlib {dlib} /dmdf {dmdf} {file}").contains("without its closing"));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{driver, unsign};
+use serde::Serialize;
+use std::time::Duration;
+
+/// The stages of a smoke test in the order they run
+pub const STAGES: [&str; 4] = ["dependencies", "login", "signing", "verification"];
+
+/// How a stage of the smoke test went
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// An earlier stage failed
+    Skipped,
+}
+
+/// One stage of the smoke test
+#[derive(Serialize, Debug)]
+pub struct Stage {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `smoke-test` found
+#[derive(Serialize, Debug)]
+pub struct SmokeTest {
+    pub endpoint: String,
+    pub account: String,
+    pub certificate_profile: String,
+    pub stages: Vec<Stage>,
+    /// Signatures of the account's quota the test used, one once it got to signing
+    pub signatures_used: usize,
+}
+
+impl SmokeTest {
+    pub fn new(endpoint: &str, account: &str, certificate_profile: &str) -> Self {
+        SmokeTest {
+            endpoint: endpoint.to_string(),
+            account: account.to_string(),
+            certificate_profile: certificate_profile.to_string(),
+            stages: Vec::new(),
+            signatures_used: 0,
+        }
+    }
+
+    /// Record the result of the next stage, the stages after a failed one are skipped
+    pub fn record(&mut self, duration: Duration, result: Result<(), String>) {
+        let name = STAGES[self.stages.len()];
+        let (outcome, error) = match result {
+            Ok(()) => (Outcome::Passed, None),
+            Err(error) => (Outcome::Failed, Some(error)),
+        };
+        self.stages.push(Stage { name, outcome, duration_ms: duration.as_millis() as u64, error });
+        if outcome == Outcome::Failed {
+            for name in &STAGES[self.stages.len()..] {
+                self.stages.push(Stage { name, outcome: Outcome::Skipped, duration_ms: 0, error: None });
+            }
+        }
+    }
+
+    /// Whether every stage passed
+    pub fn passed(&self) -> bool {
+        self.stages.len() == STAGES.len() && self.stages.iter().all(|stage| stage.outcome == Outcome::Passed)
+    }
+
+    /// Human readable summary with one line per stage
+    pub fn table(&self) -> String {
+        let mut table = format!("Smoke test of {} / {} at {}\n", self.account, self.certificate_profile, self.endpoint);
+        for stage in &self.stages {
+            let outcome = match stage.outcome {
+                Outcome::Passed => "passed",
+                Outcome::Failed => "FAILED",
+                Outcome::Skipped => "skipped",
+            };
+            let seconds = stage.duration_ms as f64 / 1000.0;
+            table.push_str(&format!("  {:<13} {:<8} {:.1}s\n", stage.name, outcome, seconds));
+            if let Some(error) = &stage.error {
+                for line in error.lines() {
+                    table.push_str(&format!("    {}\n", line));
+                }
+            }
+        }
+        table.push_str(&format!("  quota used    {} signature(s)\n", self.signatures_used));
+        table
+    }
+}
+
+/// A tiny x64 console executable that only returns, to sign without touching real build output
+pub fn stub_exe() -> Vec<u8> {
+    let mut image = vec![0u8; 0x400];
+    // DOS header pointing to the PE header
+    image[..2].copy_from_slice(b"MZ");
+    image[0x3c] = 0x80;
+    image[0x80..0x84].copy_from_slice(b"PE\0\0");
+    // COFF header: AMD64, one section, the optional header of PE32+, an executable for large addresses
+    let coff = [(0x84, 0x8664u32, 2), (0x86, 1, 2), (0x94, 0xf0, 2), (0x96, 0x22, 2)];
+    // optional header: entry point and code at 0x1000, image base, alignments, Windows 6.0, sizes, console,
+    // NX and dynamic base, stack and heap, 16 data directories
+    let optional = [
+        (0x98, 0x20b, 2),
+        (0x9c, 0x200, 4),
+        (0xa8, 0x1000, 4),
+        (0xac, 0x1000, 4),
+        (0xb0, 0x4000_0000, 4),
+        (0xb4, 1, 4),
+        (0xb8, 0x1000, 4),
+        (0xbc, 0x200, 4),
+        (0xc0, 6, 2),
+        (0xc8, 6, 2),
+        (0xd0, 0x2000, 4),
+        (0xd4, 0x200, 4),
+        (0xdc, 3, 2),
+        (0xde, 0x8160, 2),
+        (0xe0, 0x10_0000, 4),
+        (0xe8, 0x1000, 4),
+        (0xf0, 0x10_0000, 4),
+        (0xf8, 0x1000, 4),
+        (0x104, 16, 4),
+    ];
+    // the .text section, readable and executable code
+    let section = [(0x190, 1, 4), (0x194, 0x1000, 4), (0x198, 0x200, 4), (0x19c, 0x200, 4), (0x1ac, 0x6000_0020, 4)];
+    for (offset, value, size) in coff.into_iter().chain(optional).chain(section) {
+        image[offset..offset + size].copy_from_slice(&u32::to_le_bytes(value)[..size]);
+    }
+    image[0x188..0x18d].copy_from_slice(b".text");
+    // ret
+    image[0x200] = 0xc3;
+    if let Some((checksum, _)) = driver::header_offsets(&image) {
+        let sum = unsign::pe_checksum(&image, checksum);
+        image[checksum..checksum + 4].copy_from_slice(&sum.to_le_bytes());
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input;
+
+    #[test]
+    fn stub_and_stages() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("smoke-test.exe");
+        std::fs::write(&path, stub_exe()).unwrap();
+        input::check_structure(path.to_str().unwrap()).unwrap();
+        let image = stub_exe();
+        assert!(!driver::is_signed(&image));
+        assert_eq!(driver::header_offsets(&image), Some((0x98 + 64, 0x98 + 112 + 4 * 8)));
+
+        let mut smoke = SmokeTest::new("https://eus.codesigning.azure.net", "acc", "prof");
+        smoke.record(Duration::from_secs(1), Ok(()));
+        smoke.record(Duration::from_secs(2), Err("AADSTS7000215: Invalid client secret".to_string()));
+        let outcomes: Vec<Outcome> = smoke.stages.iter().map(|stage| stage.outcome).collect();
+        assert_eq!(outcomes, [Outcome::Passed, Outcome::Failed, Outcome::Skipped, Outcome::Skipped]);
+        assert!(!smoke.passed());
+        assert!(smoke.table().contains("  login         FAILED   2.0s\n    AADSTS7000215"));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{driver, error::Error, hash, input, rest};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// The file listing the packages of a release with their SHA1 and size, Squirrel checks both before applying one
+pub const RELEASES: &str = "RELEASES";
+
+/// Whether the file is a full or delta package Squirrel made, judged by its name
+pub fn is_package(path: &str) -> bool {
+    let name = Path::new(path).file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    name.ends_with("-full.nupkg") || name.ends_with("-delta.nupkg")
+}
+
+/// Whether the file is the installer, Setup.exe or Setup.msi, possibly prefixed with the app id
+fn is_setup(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    name.ends_with("setup.exe") || name.ends_with("setup.msi")
+}
+
+/// The files of the releases in `dir` in the order they are signed: the loose executables, the packages, then
+/// the installers, which carry the newest full package
+pub fn files(dir: &Path, extensions: &[String]) -> Result<Vec<String>, Error> {
+    if !dir.join(RELEASES).is_file() {
+        Err(Error::Usage(format!(
+            "'{}' is not a Squirrel releases directory, it has no {} file",
+            dir.display(),
+            RELEASES
+        )))?;
+    }
+    let entries = fs::read_dir(dir).map_err(|err| format!("'{}' could not be read: {}", dir.display(), err))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(Result::ok).map(|entry| entry.path()).collect();
+    paths.retain(|path| path.is_file());
+    paths.sort();
+    let text = |path: &PathBuf| path.display().to_string();
+
+    let (setups, others): (Vec<PathBuf>, Vec<PathBuf>) = paths.into_iter().partition(|path| is_setup(path));
+    let (packages, loose): (Vec<PathBuf>, Vec<PathBuf>) = others.into_iter().partition(|path| is_package(&text(path)));
+    let loose = loose.iter().map(text).filter(|path| input::is_supported(path, extensions));
+    Ok(loose.chain(packages.iter().map(text)).chain(setups.iter().map(text)).collect())
+}
+
+/// A binary of a package, unpacked to be signed
+#[derive(Debug)]
+pub struct Binary {
+    /// Name of the entry in the package, like `lib/net45/app.exe`
+    pub name: String,
+    pub path: PathBuf,
+}
+
+fn open(package: &Path) -> Result<zip::ZipArchive<File>, String> {
+    let file = File::open(package).map_err(|err| format!("'{}' could not be read: {}", package.display(), err))?;
+    zip::ZipArchive::new(file).map_err(|err| format!("'{}' is not a valid package: {}", package.display(), err))
+}
+
+/// Unpack the binaries of `package` into `dir`. Binaries already signed, like the runtime DLLs of their vendor, and
+/// the diffs of a delta package stay in it
+pub fn unpack(package: &Path, dir: &Path) -> Result<Vec<Binary>, String> {
+    let mut archive = open(package)?;
+    let mut binaries = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|err| err.to_string())?;
+        let name = entry.name().to_string();
+        if entry.is_dir() || !rest::is_pe(&name) {
+            continue;
+        }
+        let mut image = Vec::new();
+        entry.read_to_end(&mut image).map_err(|err| format!("{} could not be unpacked: {}", name, err))?;
+        if driver::is_signed(&image) {
+            debug!("{} of {} is signed already", name, package.display());
+            continue;
+        }
+        let path = dir.join(index.to_string()).join(Path::new(&name).file_name().unwrap_or_default());
+        fs::create_dir_all(path.parent().unwrap_or(dir)).map_err(|err| err.to_string())?;
+        fs::write(&path, image).map_err(|err| format!("{} could not be unpacked: {}", name, err))?;
+        binaries.push(Binary { name, path });
+    }
+    Ok(binaries)
+}
+
+/// Write `package` to `output` with the signed `binaries` in place of their entries, everything else is copied as
+/// it is, in the same order
+pub fn repack(package: &Path, binaries: &[Binary], output: &Path) -> Result<(), String> {
+    let mut archive = open(package)?;
+    let file = File::create(output).map_err(|err| format!("'{}' could not be created: {}", output.display(), err))?;
+    let mut writer = zip::ZipWriter::new(file);
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index).map_err(|err| err.to_string())?;
+        let Some(binary) = binaries.iter().find(|binary| binary.name == entry.name()) else {
+            writer.raw_copy_file(entry).map_err(|err| err.to_string())?;
+            continue;
+        };
+        let options = zip::write::FileOptions::default()
+            .compression_method(entry.compression())
+            .last_modified_time(entry.last_modified())
+            .large_file(fs::metadata(&binary.path).map(|metadata| metadata.len()).unwrap_or(0) >= u32::MAX as u64);
+        writer.start_file(binary.name.as_str(), options).map_err(|err| err.to_string())?;
+        let mut signed = File::open(&binary.path).map_err(|err| err.to_string())?;
+        io::copy(&mut signed, &mut writer).map_err(|err| format!("{} could not be packed: {}", binary.name, err))?;
+    }
+    writer.finish().map_err(|err| format!("'{}' could not be written: {}", output.display(), err))?;
+    Ok(())
+}
+
+/// The next word of `text` and what follows it
+fn word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (end > 0).then(|| text.split_at(end))
+}
+
+/// An entry of a RELEASES file, `SHA1 file size` and maybe a comment like `# 10%` for a staged rollout
+#[derive(Debug, PartialEq, Eq)]
+struct Entry<'a> {
+    sha1: &'a str,
+    file_name: &'a str,
+    size: u64,
+    /// The comment after the size, with the whitespace before it
+    rest: &'a str,
+}
+
+fn entry(line: &str) -> Option<Entry<'_>> {
+    let (sha1, rest) = word(line)?;
+    let (file_name, rest) = word(rest)?;
+    let (size, rest) = word(rest)?;
+    let valid = sha1.len() == 40 && sha1.chars().all(|c| c.is_ascii_hexdigit()) && !file_name.contains("://");
+    Some(Entry { sha1, file_name, size: size.parse().ok().filter(|_| valid)?, rest })
+}
+
+/// SHA1 and size of a file, the SHA1 in the case of `like`, Squirrel writes it uppercase
+fn sha1_and_size(path: &Path, like: &str) -> io::Result<(String, u64)> {
+    let mut hasher = Sha1::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    let sha1 = hash::hex(&hasher.finalize());
+    match like.chars().any(|c| c.is_ascii_uppercase()) {
+        true => Ok((sha1.to_ascii_uppercase(), size)),
+        false => Ok((sha1, size)),
+    }
+}
+
+/// The RELEASES file with the SHA1 and size of every package in `dir` as it is now, None when nothing changed
+fn updated(releases: &str, dir: &Path) -> Result<Option<String>, String> {
+    let mut text = String::with_capacity(releases.len());
+    let mut changed = false;
+    for line in releases.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let Some(entry) = entry(content).filter(|entry| dir.join(entry.file_name).is_file()) else {
+            text.push_str(line);
+            continue;
+        };
+        let path = dir.join(entry.file_name);
+        let (sha1, size) = sha1_and_size(&path, entry.sha1)
+            .map_err(|err| format!("'{}' could not be read: {}", path.display(), err))?;
+        changed |= !sha1.eq_ignore_ascii_case(entry.sha1) || size != entry.size;
+        text.push_str(&format!("{} {} {}{}{}", sha1, entry.file_name, size, entry.rest, &line[content.len()..]));
+    }
+    Ok(changed.then_some(text))
+}
+
+/// Write the SHA1 and size of the packages in `dir` as they are now to its RELEASES file, returns whether
+/// anything changed
+pub fn update_releases(dir: &Path) -> Result<bool, Error> {
+    let path = dir.join(RELEASES);
+    let releases =
+        fs::read_to_string(&path).map_err(|err| format!("'{}' could not be read: {}", path.display(), err))?;
+    let Some(text) = updated(&releases, dir)? else {
+        return Ok(false);
+    };
+    // written next to it and renamed, Squirrel never sees half a file
+    let mut file = tempfile::NamedTempFile::new_in(dir).map_err(|err| err.to_string())?;
+    io::Write::write_all(&mut file, text.as_bytes()).map_err(|err| err.to_string())?;
+    file.persist(&path).map_err(|err| format!("'{}' could not be written: {}", path.display(), err))?;
+    Ok(true)
+}
+
+/// Check that every package the RELEASES file of `dir` lists has the SHA1 and size listed for it, packages of
+/// earlier releases that aren't in `dir` anymore are left out
+pub fn verify_releases(dir: &Path) -> Result<(), Error> {
+    let path = dir.join(RELEASES);
+    let releases =
+        fs::read_to_string(&path).map_err(|err| format!("'{}' could not be read: {}", path.display(), err))?;
+    let mut mismatched = Vec::new();
+    for entry in releases.lines().filter_map(entry).filter(|entry| dir.join(entry.file_name).is_file()) {
+        let package = dir.join(entry.file_name);
+        match sha1_and_size(&package, entry.sha1) {
+            Ok((sha1, size)) if sha1.eq_ignore_ascii_case(entry.sha1) && size == entry.size => (),
+            Ok((sha1, size)) => mismatched.push(format!("{} is {} of {} bytes", entry.file_name, sha1, size)),
+            Err(err) => mismatched.push(format!("{} could not be read: {}", entry.file_name, err)),
+        }
+    }
+    if !mismatched.is_empty() {
+        Err(Error::Signing {
+            path: path.display().to_string(),
+            message: format!(
+                "'{}' doesn't match its packages, Squirrel would refuse them: {}",
+                path.display(),
+                mismatched.join(", ")
+            ),
+            output: String::new(),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("App-1.0.0-full.nupkg"), b"signed package").unwrap();
+        let releases = "E3F67244E4166A65310C816221A12685C83F8E6F App-1.0.0-full.nupkg 600725\r\n\
+                        e3f67244e4166a65310c816221a12685c83f8e6f  App-0.9.0-full.nupkg 500000 # 10%\r\n\
+                        not an entry\n";
+        let text = updated(releases, dir.path()).unwrap().unwrap();
+        let sha1 = hash::hex(&Sha1::digest(b"signed package")).to_ascii_uppercase();
+        let unchanged: String = releases.split_inclusive('\n').skip(1).collect();
+        assert_eq!(text, format!("{} App-1.0.0-full.nupkg 14\r\n{}", sha1, unchanged));
+        assert_eq!(updated(&text, dir.path()).unwrap(), None);
+        let staged = entry("e3f67244e4166a65310c816221a12685c83f8e6f App-0.9.0-full.nupkg 500000 # 10%").unwrap();
+        assert_eq!((staged.size, staged.rest), (500000, " # 10%"));
+        assert_eq!(entry("E3F6 App-1.0.0-full.nupkg 1"), None);
+        assert!(is_package("releases/App-1.0.0-delta.nupkg") && !is_package("App.1.0.0.nupkg"));
+        assert!(is_setup(Path::new("releases/AppSetup.exe")) && !is_setup(Path::new("releases/Update.exe")));
+    }
+}
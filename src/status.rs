@@ -0,0 +1,243 @@
+use crate::{azure, clock, config, endpoint, network, principal, session, settings::Dependency, signtool, wintrust};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ArgMatches;
+use serde::Serialize;
+use std::{fs, time::SystemTime};
+use tracing::debug;
+
+/// The cached signing client
+#[derive(Serialize, Debug)]
+pub struct SigningClient {
+    pub path: String,
+    pub cached: bool,
+    /// The version of the package it came from, `None` when it isn't cached or doesn't say
+    pub version: Option<String>,
+    /// The version a download would fetch
+    pub expected_version: &'static str,
+    pub downloaded_at: Option<String>,
+}
+
+/// The azure cli session a sign would reuse
+#[derive(Serialize, Debug, Default)]
+pub struct AzureSession {
+    /// When a sign last logged in or checked the session, `None` when none did
+    pub checked_at: Option<String>,
+    /// The client id or user az is logged in as
+    pub logged_in_as: Option<String>,
+    pub tenant_id: Option<String>,
+    /// Whether az is logged in as the principal of --azure-client-id, `None` when it isn't given
+    pub signing_principal: Option<bool>,
+    /// When the current Trusted Signing token expires, az acquires a new one before
+    pub token_expires_on: Option<String>,
+    /// Why az couldn't be asked, everything az would report is `None` then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The root Trusted Signing signatures chain to, which a machine needs to verify them
+#[derive(Serialize, Debug)]
+pub struct TrustRoot {
+    pub name: &'static str,
+    pub thumbprint: &'static str,
+    /// Whether it is in the root store of the machine, `None` off Windows
+    pub present: Option<bool>,
+}
+
+/// Everything `status` prints
+#[derive(Serialize, Debug)]
+pub struct Status {
+    pub signing_client: SigningClient,
+    pub sign_tool: Dependency,
+    pub azure_session: AzureSession,
+    /// The local clock compared with the endpoint's, or the timestamp server's without --endpoint
+    pub clock: clock::Check,
+    /// Connecting to the endpoint, when given, and the timestamp server
+    pub network: Vec<network::Probe>,
+    pub trust_root: TrustRoot,
+}
+
+fn time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// The version in the nuspec of the package, unpacked next to the dlib and written when it was downloaded
+pub(crate) fn package_version(nuspec: &str) -> Option<String> {
+    let start = nuspec.find("<version>")? + "<version>".len();
+    let end = start + nuspec[start..].find("</version>")?;
+    Some(nuspec[start..end].trim().to_string()).filter(|version| !version.is_empty())
+}
+
+fn signing_client(dlib_version: &'static str) -> SigningClient {
+    let lib_path = config::lib_path();
+    let nuspec = fs::read_dir(config::dir().join("lib"))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("nuspec")));
+    let cached = lib_path.is_file();
+    // the zip doesn't keep modification times, the files have the time it was unpacked
+    let downloaded_at = nuspec.as_ref().unwrap_or(&lib_path).metadata().and_then(|metadata| metadata.modified());
+    SigningClient {
+        path: lib_path.display().to_string(),
+        cached,
+        version: nuspec.and_then(|nuspec| package_version(&fs::read_to_string(nuspec).ok()?)),
+        expected_version: dlib_version,
+        downloaded_at: downloaded_at.ok().filter(|_| cached).map(time),
+    }
+}
+
+/// Ask az who it is logged in as and when its token expires, the error of the first that fails is kept
+fn azure_session(cli_path: &str, client_id: Option<&str>) -> AzureSession {
+    let mut session = AzureSession {
+        checked_at: session::checked_at().map(time),
+        ..AzureSession::default()
+    };
+    let account = match azure::account(cli_path) {
+        Ok(account) => account,
+        Err(err) => {
+            session.error = Some(err);
+            return session;
+        }
+    };
+    session.signing_principal = client_id.map(|client_id| client_id.eq_ignore_ascii_case(&account.user_name));
+    session.logged_in_as = Some(account.user_name);
+    session.tenant_id = Some(account.tenant_id);
+    match azure::access_token_expiry(cli_path) {
+        Ok(expires_on) => session.token_expires_on = Some(expires_on),
+        Err(err) => session.error = Some(err),
+    }
+    session
+}
+
+/// A snapshot of the cached signing client, the signtool a sign would run, the azure cli session and the clock.
+/// Only az is run and a HEAD request made, nothing is downloaded or logged in and no credentials are needed
+pub async fn run(sign: &ArgMatches, dlib_version: &'static str) -> Status {
+    let get = |id: &str| {
+        let value = sign.get_raw(id).into_iter().flatten().next();
+        value.map(|value| value.to_string_lossy().into_owned())
+    };
+    let sign_tool_path = get("sing_tool_path").unwrap_or_default();
+    let cli_path = get("azure_cli_path").unwrap_or_else(|| azure::DEFAULT_CLI_PATH.to_string());
+    let endpoint = get("endpoint").and_then(|value| endpoint::parse(&value).ok());
+    let timestamp_url = get("tr").unwrap_or_else(|| signtool::TIMESTAMP_URL.to_string());
+    let principal = principal::from_matches(sign).unwrap_or_else(|err| {
+        debug!("{}", err);
+        None
+    });
+    let client_id = principal.map(|principal| principal.client_id).or_else(|| get("azure_client_id"));
+    let urls: Vec<&str> = endpoint.iter().map(String::as_str).chain([timestamp_url.as_str()]).collect();
+    let (clock, network) = tokio::join!(
+        clock::check(endpoint.as_deref().unwrap_or(signtool::TIMESTAMP_URL)),
+        futures_util::future::join_all(urls.into_iter().map(network::probe))
+    );
+    Status {
+        signing_client: signing_client(dlib_version),
+        sign_tool: Dependency::sign_tool(sign_tool_path),
+        azure_session: azure_session(&cli_path, client_id.as_deref()),
+        clock,
+        network,
+        trust_root: TrustRoot {
+            name: wintrust::ROOT_NAME,
+            thumbprint: wintrust::ROOT_THUMBPRINT,
+            present: wintrust::root_present(),
+        },
+    }
+}
+
+/// Whether the root is there, and what it means when it isn't
+fn trust_root(root: &TrustRoot) -> String {
+    match root.present {
+        Some(true) => format!("{} (in the machine's root store)", root.name),
+        Some(false) => format!(
+            "{} (missing: files signed through Trusted Signing fail to verify here, update the root store)",
+            root.name
+        ),
+        None => format!("{} (not checked, the root store is only looked at on Windows)", root.name),
+    }
+}
+
+impl Status {
+    /// Aligned table of the status
+    pub fn table(&self) -> String {
+        let client = &self.signing_client;
+        let cached = match (client.cached, &client.version, &client.downloaded_at) {
+            (false, _, _) => format!("not cached, the first sign downloads {}", client.expected_version),
+            (true, Some(version), _) if *version != client.expected_version => {
+                format!("{}, this release expects {}", version, client.expected_version)
+            }
+            (true, version, downloaded_at) => format!(
+                "{}, downloaded {}",
+                version.as_deref().unwrap_or("unknown version"),
+                downloaded_at.as_deref().unwrap_or("at an unknown time")
+            ),
+        };
+        let sign_tool = &self.sign_tool;
+        let sign_tool = match (sign_tool.exists, &sign_tool.version, &sign_tool.problem) {
+            (false, _, _) => format!("{} (missing)", sign_tool.path),
+            (true, version, Some(_)) => format!(
+                "{} ({}, too old: the signing client needs {} or later)",
+                sign_tool.path,
+                version.as_deref().unwrap_or("unknown version"),
+                signtool::MINIMUM_VERSION
+            ),
+            (true, Some(version), None) => format!("{} (found, {})", sign_tool.path, version),
+            (true, None, None) => format!("{} (found)", sign_tool.path),
+        };
+        let session = &self.azure_session;
+        let logged_in = match (&session.logged_in_as, session.signing_principal, &session.error) {
+            (Some(user), Some(false), _) => format!("{}, not the signing principal", user),
+            (Some(user), _, _) => user.clone(),
+            (None, _, Some(err)) => format!("unknown, {}", err.lines().next().unwrap_or_default()),
+            (None, _, None) => "-".to_string(),
+        };
+        let token = match (&session.token_expires_on, &session.logged_in_as, &session.error) {
+            (Some(expires_on), _, _) => format!("expires {}", expires_on),
+            (None, Some(_), Some(err)) => format!("unknown, {}", err.lines().next().unwrap_or_default()),
+            _ => "-".to_string(),
+        };
+        let check = &self.clock;
+        let clock = match (check.offset(), &check.error) {
+            (Some(offset), _) if check.skewed => format!("{}, too far off: timestamps and tokens fail", offset),
+            (Some(offset), _) => offset,
+            (None, err) => format!("unknown, {}", err.as_deref().unwrap_or("not checked")),
+        };
+        let rows = [
+            ("signing client", format!("{} ({})", client.path, cached)),
+            ("signtool", sign_tool),
+            ("last login", session.checked_at.clone().unwrap_or_else(|| "none by this tool".to_string())),
+            ("azure cli", logged_in),
+            ("tenant", session.tenant_id.clone().unwrap_or_else(|| "-".to_string())),
+            ("token", token),
+            ("clock", clock),
+            ("root CA", trust_root(&self.trust_root)),
+        ];
+        let mut table = String::from("Status\n");
+        for (name, value) in rows {
+            table.push_str(&format!("  {:<15} {}\n", name, value));
+        }
+        for probe in &self.network {
+            let reached = match (probe.problem(), probe.intercepted) {
+                (Some(problem), _) => problem,
+                (None, true) => format!("{} ({})", probe.warning().unwrap_or_default(), probe.timings()),
+                (None, false) => format!("{} reached ({})", probe.host, probe.timings()),
+            };
+            table.push_str(&format!("  {:<15} {}\n", "network", reached));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nuspec_version() {
+        let nuspec = "<?xml version=\"1.0\"?>\n<package><metadata><id>Microsoft.Trusted.Signing.Client</id>\
+                      <version>1.0.60</version></metadata></package>";
+        assert_eq!(package_version(nuspec).as_deref(), Some("1.0.60"));
+        assert_eq!(package_version("<package><version></version></package>"), None);
+        assert_eq!(package_version("<package/>"), None);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::{
+    cargo,
+    error::Error,
+    logging::{self, run_captured},
+};
+use clap::ValueEnum;
+use duct::cmd;
+use serde_json::Value;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// The minisign private key the Tauri CLI signs updater artifacts with, the key itself or a path to it
+pub const PRIVATE_KEY_VAR: &str = "TAURI_SIGNING_PRIVATE_KEY";
+
+/// Which outputs of a Tauri build to sign
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Only the app exe, after `tauri build --no-bundle` and before `tauri bundle`,
+    /// so the installers ship the signed exe
+    PreBundle,
+    /// Only the NSIS and MSI installers
+    PostBundle,
+    /// The app exe, then the installers
+    All,
+}
+
+/// Windows bundle formats and the extension of their installers
+const INSTALLERS: [(&str, &str); 2] = [("nsis", "exe"), ("msi", "msi")];
+
+/// What tauri.conf.json says about the outputs, for Tauri 1 and 2
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Config {
+    product_name: Option<String>,
+    main_binary_name: Option<String>,
+    version: Option<String>,
+    /// Windows bundle formats to build
+    bundles: Vec<&'static str>,
+    /// `createUpdaterArtifacts: "v1Compatible"`, the updater downloads zips of the installers
+    zipped_updater: bool,
+}
+
+/// Read tauri.conf.json, `dir` is where a version pointing at a package.json is resolved from
+fn config(content: &str, dir: &Path) -> Result<Config, String> {
+    let conf: Value = serde_json::from_str(content).map_err(|err| format!("tauri.conf.json is not valid JSON: {}", err))?;
+    // Tauri 1 keeps the name and version under `package` and the bundle under `tauri`
+    let string = |value: &Value| value.as_str().map(str::to_string);
+    let product_name = string(&conf["productName"]).or_else(|| string(&conf["package"]["productName"]));
+    let version = string(&conf["version"]).or_else(|| string(&conf["package"]["version"]));
+    let version = match version {
+        Some(version) if version.ends_with(".json") => fs::read_to_string(dir.join(&version))
+            .ok()
+            .and_then(|package| serde_json::from_str::<Value>(&package).ok())
+            .and_then(|package| string(&package["version"])),
+        version => version,
+    };
+    let targets = match &conf["bundle"]["targets"] {
+        Value::Null => &conf["tauri"]["bundle"]["targets"],
+        targets => targets,
+    };
+    let bundles = match targets {
+        Value::Null => INSTALLERS.map(|(format, _)| format).to_vec(),
+        Value::String(target) if target == "all" => INSTALLERS.map(|(format, _)| format).to_vec(),
+        Value::String(target) => INSTALLERS.iter().map(|(format, _)| *format).filter(|format| format == target).collect(),
+        Value::Array(targets) => INSTALLERS
+            .iter()
+            .map(|(format, _)| *format)
+            .filter(|format| targets.iter().any(|target| target.as_str() == Some(format)))
+            .collect(),
+        _ => Err("bundle.targets of tauri.conf.json must be \"all\" or a list of formats".to_string())?,
+    };
+    Ok(Config {
+        product_name,
+        main_binary_name: string(&conf["mainBinaryName"]),
+        version,
+        bundles,
+        zipped_updater: conf["bundle"]["createUpdaterArtifacts"].as_str() == Some("v1Compatible")
+            || conf["tauri"]["updater"]["active"].as_bool() == Some(true),
+    })
+}
+
+/// The src-tauri directory, `path` itself when it holds the config
+fn src_tauri(path: &Path) -> Result<PathBuf, Error> {
+    [path.to_path_buf(), path.join("src-tauri")]
+        .into_iter()
+        .find(|dir| dir.join("tauri.conf.json").is_file())
+        .ok_or_else(|| Error::Usage(format!("no tauri.conf.json in '{}' or its src-tauri directory", path.display())))
+}
+
+/// The src-tauri directory and its tauri.conf.json
+fn read_config(path: &Path) -> Result<(PathBuf, Config), Error> {
+    let dir = src_tauri(path)?;
+    let conf_path = dir.join("tauri.conf.json");
+    let content = fs::read_to_string(&conf_path)
+        .map_err(|err| Error::Usage(format!("'{}' could not be read: {}", conf_path.display(), err)))?;
+    let conf = config(&content, &dir).map_err(Error::Usage)?;
+    Ok((dir, conf))
+}
+
+/// The files of a Tauri build to sign, in the order to sign them
+pub fn outputs(path: &Path, stage: Stage) -> Result<Vec<String>, Error> {
+    let (dir, conf) = read_config(path)?;
+
+    let manifest_path = dir.join("Cargo.toml");
+    let metadata = cargo::metadata(Some(&manifest_path))?;
+    let release = metadata.target_directory().join("release");
+    let (cargo_version, bins) = metadata.package(&manifest_path).unwrap_or_default();
+    let version = conf.version.as_deref().unwrap_or(cargo_version);
+    debug!("tauri app {:?} {} in {}", conf.product_name, version, release.display());
+
+    let mut files = Vec::new();
+    if stage != Stage::PostBundle {
+        // a main binary name is what the CLI renames the cargo binary to
+        let names = conf.main_binary_name.iter().map(String::as_str).chain(bins).chain(conf.product_name.as_deref());
+        let exe = names.map(|name| release.join(format!("{}.exe", name))).find(|exe| exe.is_file());
+        let Some(exe) = exe else {
+            Err(Error::Usage(format!(
+                "the app exe is not in '{}', run `tauri build --no-bundle` (or `tauri build`) first",
+                release.display()
+            )))?
+        };
+        files.push(exe.display().to_string());
+    }
+
+    if stage != Stage::PreBundle {
+        if conf.bundles.is_empty() {
+            Err(Error::Usage(
+                "bundle.targets of tauri.conf.json has no Windows installer, add nsis or msi".to_string(),
+            ))?;
+        }
+        let mut installers = Vec::new();
+        for (format, extension) in INSTALLERS.iter().filter(|(format, _)| conf.bundles.contains(format)) {
+            let mut found: Vec<String> = fs::read_dir(release.join("bundle").join(format))
+                .into_iter()
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|found| found.eq_ignore_ascii_case(extension)))
+                // older versions pile up in the bundle directory
+                .filter(|path| {
+                    version.is_empty() || path.file_name().is_some_and(|name| name.to_string_lossy().contains(version))
+                })
+                .map(|path| path.display().to_string())
+                .collect();
+            found.sort();
+            installers.extend(found);
+        }
+        if installers.is_empty() {
+            Err(Error::Usage(format!(
+                "no {} installers of version {} in '{}', run `tauri build` (or `tauri bundle`) first",
+                conf.bundles.join(" or "),
+                version,
+                release.join("bundle").display()
+            )))?;
+        }
+        files.extend(installers);
+    }
+    Ok(files)
+}
+
+/// The Tauri CLI for --tauri-updater: `cli_path` when given, otherwise the one installed with npm in the project,
+/// then `tauri` or `cargo-tauri` on the PATH
+fn find_cli(cli_path: Option<&str>, project: &Path) -> Result<PathBuf, Error> {
+    if let Some(path) = cli_path {
+        return match Path::new(path).is_file() {
+            true => Ok(PathBuf::from(path)),
+            false => Err(Error::MissingDependency {
+                component: "tauri",
+                message: format!("the Tauri CLI '{}' was not found", path),
+            }),
+        };
+    }
+    let names: &[&str] = match cfg!(windows) {
+        true => &["tauri.cmd", "tauri.exe", "cargo-tauri.exe"],
+        false => &["tauri", "cargo-tauri"],
+    };
+    // the project is the directory above src-tauri
+    let node_modules = [project.to_path_buf(), project.join("..")].map(|dir| dir.join("node_modules").join(".bin"));
+    let on_path = std::env::var_os("PATH").map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>());
+    let dirs = node_modules.into_iter().chain(on_path.unwrap_or_default());
+    let mut candidates = dirs.flat_map(|dir| names.iter().map(move |name| dir.join(name)));
+    let Some(cli) = candidates.find(|candidate| candidate.is_file()) else {
+        Err(Error::MissingDependency {
+            component: "tauri",
+            message: "the Tauri CLI was not found, --tauri-updater signs the updater artifacts with it: install \
+                      @tauri-apps/cli or tauri-cli, or pass --tauri-cli-path"
+                .to_string(),
+        })?
+    };
+    debug!("signing updater artifacts with {}", cli.display());
+    Ok(cli)
+}
+
+/// Check everything --tauri-updater needs before anything is signed, so a run doesn't stop with the installers
+/// signed and their updater signatures stale. Returns the Tauri CLI
+pub fn check_updater(path: &Path, stage: Stage, cli_path: Option<&str>) -> Result<PathBuf, Error> {
+    if stage == Stage::PreBundle {
+        let message = "--tauri-updater signs the installers, it can't be used with --tauri-stage pre-bundle";
+        Err(Error::Usage(message.to_string()))?;
+    }
+    let (dir, conf) = read_config(path)?;
+    if conf.zipped_updater {
+        Err(Error::Usage(
+            "--tauri-updater signs the installers themselves as updater artifacts, the zips of Tauri 1 and \
+             createUpdaterArtifacts \"v1Compatible\" aren't rebuilt"
+                .to_string(),
+        ))?;
+    }
+    if std::env::var(PRIVATE_KEY_VAR).unwrap_or_default().trim().is_empty() {
+        Err(Error::Usage(format!(
+            "--tauri-updater signs the updater artifacts with the private key in {}, it is not set",
+            PRIVATE_KEY_VAR
+        )))?;
+    }
+    find_cli(cli_path, &dir)
+}
+
+/// Sign `installer` for the updater with the Tauri CLI, which writes `<installer>.sig`, and return the signature.
+/// The key and its password come from the environment
+pub fn sign_updater(cli: &Path, installer: &str) -> Result<String, Error> {
+    let output = run_captured(cmd!(cli, "signer", "sign", installer).stdin_null()).map_err(|err| {
+        Error::MissingDependency {
+            component: "tauri",
+            message: format!("the Tauri CLI '{}' could not be run: {}", cli.display(), err),
+        }
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: installer.to_string(),
+            message: format!("the Tauri CLI could not sign '{}' for the updater, error: {}", installer, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    let signature = format!("{}.sig", installer);
+    let signature = fs::read_to_string(&signature)
+        .map_err(|err| format!("the updater signature '{}' could not be read: {}", signature, err))?;
+    Ok(signature.trim().to_string())
+}
+
+/// Whether `url` of latest.json downloads `file`, by its name as it is, percent encoded or uploaded to a GitHub
+/// release, which replaces spaces with dots
+fn downloads(url: &str, file: &str) -> bool {
+    let name = url.rsplit('/').next().unwrap_or(url);
+    [file.to_string(), file.replace(' ', "%20"), file.replace(' ', ".")].iter().any(|candidate| candidate == name)
+}
+
+/// Set the signature, and the size where there is one, of the platforms of latest.json downloading one of the
+/// `signed` installers, returning the platforms updated. The static format lists platforms, the dynamic one has a
+/// single url at the top
+fn updated_latest_json(json: &mut Value, signed: &[(String, String, u64)]) -> Vec<String> {
+    let mut updated = Vec::new();
+    let mut update = |name: &str, platform: &mut Value| {
+        let Some(url) = platform["url"].as_str() else {
+            return;
+        };
+        let Some((_, signature, size)) = signed.iter().find(|(file, _, _)| downloads(url, file)) else {
+            return;
+        };
+        platform["signature"] = Value::from(signature.as_str());
+        if platform.get("size").is_some() {
+            platform["size"] = Value::from(*size);
+        }
+        updated.push(name.to_string());
+    };
+    match json.get_mut("platforms").and_then(Value::as_object_mut) {
+        Some(platforms) => platforms.iter_mut().for_each(|(name, platform)| update(name, platform)),
+        None => update("url", json),
+    }
+    updated
+}
+
+/// Rewrite the signatures in the latest.json at `path` for the `signed` installers: their path, updater signature
+/// and size. Returns the platforms updated
+pub fn update_latest_json(path: &Path, signed: &[(String, String, u64)]) -> Result<Vec<String>, Error> {
+    let failed = |err: String| Error::Other(format!("'{}' could not be updated: {}", path.display(), err));
+    let content = fs::read_to_string(path).map_err(|err| failed(err.to_string()))?;
+    let mut json: Value = serde_json::from_str(&content).map_err(|err| failed(err.to_string()))?;
+    let names: Vec<(String, String, u64)> = signed
+        .iter()
+        .map(|(file, signature, size)| {
+            let name = Path::new(file).file_name().unwrap_or_default().to_string_lossy().to_string();
+            (name, signature.clone(), *size)
+        })
+        .collect();
+    let updated = updated_latest_json(&mut json, &names);
+    if !updated.is_empty() {
+        let text = serde_json::to_string_pretty(&json).map_err(|err| failed(err.to_string()))? + "\n";
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut file = tempfile::NamedTempFile::new_in(dir).map_err(|err| failed(err.to_string()))?;
+        io::Write::write_all(&mut file, text.as_bytes()).map_err(|err| failed(err.to_string()))?;
+        file.persist(path).map_err(|err| failed(err.to_string()))?;
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tauri_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let v2 = r#"{"productName": "My App", "mainBinaryName": "my-app", "version": "1.2.0",
+            "bundle": {"targets": ["nsis", "dmg"]}}"#;
+        assert_eq!(
+            config(v2, dir.path()).unwrap(),
+            Config {
+                product_name: Some("My App".to_string()),
+                main_binary_name: Some("my-app".to_string()),
+                version: Some("1.2.0".to_string()),
+                bundles: vec!["nsis"],
+                zipped_updater: false,
+            }
+        );
+
+        fs::write(dir.path().join("package.json"), r#"{"version": "0.3.1"}"#).unwrap();
+        let v1 = r#"{"package": {"productName": "app", "version": "./package.json"},
+            "tauri": {"bundle": {"targets": "all"}}}"#;
+        let v1 = config(v1, dir.path()).unwrap();
+        assert_eq!(v1.version.as_deref(), Some("0.3.1"));
+        assert_eq!(v1.bundles, ["nsis", "msi"]);
+
+        assert!(config(r#"{"bundle": {"targets": "deb"}}"#, dir.path()).unwrap().bundles.is_empty());
+        let zipped = r#"{"bundle": {"createUpdaterArtifacts": "v1Compatible"}}"#;
+        assert!(config(zipped, dir.path()).unwrap().zipped_updater);
+    }
+
+    #[test]
+    fn latest_json_signatures() {
+        let mut json = serde_json::json!({"version": "1.2.0", "platforms": {
+            "windows-x86_64": {
+                "signature": "old",
+                "url": "https://github.com/o/r/releases/download/v1.2.0/My.App_1.2.0_x64-setup.exe"
+            },
+            "windows-x86_64-msi": {
+                "signature": "old",
+                "url": "https://example.com/My%20App_1.2.0_x64_en-US.msi",
+                "size": 1
+            },
+            "darwin-aarch64": {"signature": "mac", "url": "https://example.com/My.App.app.tar.gz"}
+        }});
+        let signed = [
+            ("My App_1.2.0_x64-setup.exe".to_string(), "nsis".to_string(), 10),
+            ("My App_1.2.0_x64_en-US.msi".to_string(), "msi".to_string(), 20),
+        ];
+        assert_eq!(updated_latest_json(&mut json, &signed), ["windows-x86_64", "windows-x86_64-msi"]);
+        let platforms = &json["platforms"];
+        assert_eq!(platforms["windows-x86_64"]["signature"], "nsis");
+        assert!(platforms["windows-x86_64"].get("size").is_none());
+        let msi = &platforms["windows-x86_64-msi"];
+        assert_eq!((msi["signature"].as_str(), msi["size"].as_u64()), (Some("msi"), Some(20)));
+        assert_eq!(platforms["darwin-aarch64"]["signature"], "mac");
+
+        let url = "https://example.com/My%20App_1.2.0_x64-setup.exe";
+        let mut dynamic = serde_json::json!({"url": url, "signature": ""});
+        assert_eq!(updated_latest_json(&mut dynamic, &signed), ["url"]);
+        assert_eq!(dynamic["signature"], "nsis");
+    }
+}
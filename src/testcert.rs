@@ -0,0 +1,399 @@
+//! `generate-test-cert`: a self-signed code signing certificate in a password protected PFX, what
+//! `--backend pfx` signs pull request builds with
+
+use crate::{
+    certificate::{self, SigningCertificate},
+    cli::GenerateTestCertArgs,
+    der,
+    error::Error,
+};
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use chrono::{DateTime, Datelike, Utc};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{self, EcdsaKeyPair, KeyPair},
+};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{fs, io::Write, path::Path};
+use tracing::info;
+
+/// The organizational unit every test certificate gets, so its signatures can't pass for release ones
+pub const TEST_OU: &str = "TEST SIGNING ONLY - trusted-signing-cli";
+
+const CODE_SIGNING: &[u64] = &[1, 3, 6, 1, 5, 5, 7, 3, 3];
+const EC_PUBLIC_KEY: &[u64] = &[1, 2, 840, 10045, 2, 1];
+const BASIC_CONSTRAINTS: &[u64] = &[2, 5, 29, 19];
+const KEY_USAGE: &[u64] = &[2, 5, 29, 15];
+const EXTENDED_KEY_USAGE: &[u64] = &[2, 5, 29, 37];
+const SUBJECT_KEY_IDENTIFIER: &[u64] = &[2, 5, 29, 14];
+
+const DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 1];
+const KEY_BAG: &[u64] = &[1, 2, 840, 113549, 1, 12, 10, 1, 2];
+const CERT_BAG: &[u64] = &[1, 2, 840, 113549, 1, 12, 10, 1, 3];
+const X509_CERTIFICATE: &[u64] = &[1, 2, 840, 113549, 1, 9, 22, 1];
+const FRIENDLY_NAME: &[u64] = &[1, 2, 840, 113549, 1, 9, 20];
+const LOCAL_KEY_ID: &[u64] = &[1, 2, 840, 113549, 1, 9, 21];
+const PBES2: &[u64] = &[1, 2, 840, 113549, 1, 5, 13];
+const PBKDF2: &[u64] = &[1, 2, 840, 113549, 1, 5, 12];
+const HMAC_SHA256: &[u64] = &[1, 2, 840, 113549, 2, 9];
+const AES256_CBC: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 1, 42];
+const SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+
+/// Iterations of the key derivations protecting the PFX, those of OpenSSL
+const ITERATIONS: u32 = 2048;
+
+/// The key of a test certificate. ECDSA keys are generated without a dependency on the platform, signtool signs
+/// with them on Windows 10 and later
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum KeyType {
+    /// ECDSA on P-256, signing with SHA-256
+    #[default]
+    #[value(name = "ecdsa-p256")]
+    EcdsaP256,
+    /// ECDSA on P-384, signing with SHA-384
+    #[value(name = "ecdsa-p384")]
+    EcdsaP384,
+}
+
+impl KeyType {
+    fn algorithm(self) -> &'static signature::EcdsaSigningAlgorithm {
+        match self {
+            KeyType::EcdsaP256 => &signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+            KeyType::EcdsaP384 => &signature::ECDSA_P384_SHA384_ASN1_SIGNING,
+        }
+    }
+
+    fn curve(self) -> &'static [u64] {
+        match self {
+            KeyType::EcdsaP256 => &[1, 2, 840, 10045, 3, 1, 7],
+            KeyType::EcdsaP384 => &[1, 3, 132, 0, 34],
+        }
+    }
+
+    /// ecdsa-with-SHA256 or ecdsa-with-SHA384
+    fn signature_algorithm(self) -> Vec<u8> {
+        match self {
+            KeyType::EcdsaP256 => der::sequence(&[&der::oid(&[1, 2, 840, 10045, 4, 3, 2])]),
+            KeyType::EcdsaP384 => der::sequence(&[&der::oid(&[1, 2, 840, 10045, 4, 3, 3])]),
+        }
+    }
+}
+
+/// Check `--subject`: a distinguished name of known attributes with a CN, like `CN=MyApp Test Signing, O=Contoso`
+pub fn parse_subject(value: &str) -> Result<String, String> {
+    let parts = certificate::parts(value);
+    let unknown = parts.iter().find(|(key, _)| {
+        !certificate::ATTRIBUTES.iter().any(|(_, short)| short.eq_ignore_ascii_case(key)) && key != "ST"
+    });
+    if let Some((key, _)) = unknown {
+        return Err(format!("'{}' is not an attribute of a distinguished name like CN, O, OU, L, S or C", key));
+    }
+    if !parts.iter().any(|(key, value)| key == "CN" && !value.is_empty()) {
+        return Err(format!("'{}' has no CN, like CN=MyApp Test Signing", value));
+    }
+    if let Some((_, country)) = parts.iter().find(|(key, value)| key == "C" && value.len() != 2) {
+        return Err(format!("the country '{}' is not a code of two letters like US", country));
+    }
+    Ok(value.trim().to_string())
+}
+
+/// The subject as written with [TEST_OU] after its CN
+fn test_subject(subject: &str) -> Vec<(String, String)> {
+    let mut parts = certificate::parts(subject);
+    if !parts.iter().any(|(key, value)| key == "OU" && value == TEST_OU) {
+        let cn = parts.iter().position(|(key, _)| key == "CN").unwrap_or(0);
+        parts.insert(cn + 1, ("OU".to_string(), TEST_OU.to_string()));
+    }
+    parts
+}
+
+/// A Name of `parts`, written most specific first like Windows prints them and encoded the other way around
+fn name(parts: &[(String, String)]) -> Vec<u8> {
+    let relative: Vec<Vec<u8>> = parts
+        .iter()
+        .rev()
+        .map(|(key, value)| {
+            let key = if key == "ST" { "S" } else { key.as_str() };
+            let (oid, _) = certificate::ATTRIBUTES.iter().find(|(_, short)| short.eq_ignore_ascii_case(key)).unwrap();
+            let arcs: Vec<u64> = oid.split('.').filter_map(|arc| arc.parse().ok()).collect();
+            let value = match key {
+                "C" => der::tlv(der::PRINTABLE_STRING, value.to_ascii_uppercase().as_bytes()),
+                "E" => der::tlv(der::IA5_STRING, value.as_bytes()),
+                _ => der::tlv(der::UTF8_STRING, value.as_bytes()),
+            };
+            der::set_of(vec![der::sequence(&[&der::oid(&arcs), &value])])
+        })
+        .collect();
+    der::tlv(der::SEQUENCE, &relative.concat())
+}
+
+/// A UTCTime until 2049, a GeneralizedTime after
+fn time(time: DateTime<Utc>) -> Vec<u8> {
+    match time.year() {
+        ..2050 => der::tlv(der::UTC_TIME, time.format("%y%m%d%H%M%SZ").to_string().as_bytes()),
+        _ => der::tlv(der::GENERALIZED_TIME, time.format("%Y%m%d%H%M%SZ").to_string().as_bytes()),
+    }
+}
+
+fn extension(oid: &[u64], critical: bool, value: &[u8]) -> Vec<u8> {
+    let critical = match critical {
+        true => der::tlv(der::BOOLEAN, &[0xff]),
+        false => Vec::new(),
+    };
+    der::sequence(&[&der::oid(oid), &critical, &der::tlv(der::OCTET_STRING, value)])
+}
+
+fn random(rng: &SystemRandom, length: usize) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![0; length];
+    rng.fill(&mut bytes).map_err(|_| "the system's random number generator failed".to_string())?;
+    Ok(bytes)
+}
+
+/// A self-signed code signing certificate for `subject` valid for `days` from now, as DER, with its PKCS#8 key
+pub fn generate(subject: &str, days: u32, key_type: KeyType) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let rng = SystemRandom::new();
+    let failed = |err: ring::error::Unspecified| Error::Other(format!("the test key could not be generated: {}", err));
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(key_type.algorithm(), &rng).map_err(failed)?;
+    let key = EcdsaKeyPair::from_pkcs8(key_type.algorithm(), pkcs8.as_ref(), &rng)
+        .map_err(|err| Error::Other(format!("the test key could not be read: {}", err)))?;
+    let public_key = key.public_key().as_ref();
+
+    let mut serial = random(&rng, 16)?;
+    // positive without a leading zero
+    serial[0] = serial[0] & 0x7f | 0x40;
+    let name = name(&test_subject(subject));
+    // a few minutes back, for clocks slightly behind
+    let not_before = Utc::now() - chrono::Duration::minutes(5);
+    let not_after = not_before + chrono::Duration::days(days as i64);
+    let key_info = der::sequence(&[
+        &der::sequence(&[&der::oid(EC_PUBLIC_KEY), &der::oid(key_type.curve())]),
+        &der::tlv(der::BIT_STRING, &[&[0], public_key].concat()),
+    ]);
+    let extensions = [
+        extension(BASIC_CONSTRAINTS, true, &der::sequence(&[])),
+        // digitalSignature
+        extension(KEY_USAGE, true, &der::tlv(der::BIT_STRING, &[7, 0x80])),
+        extension(EXTENDED_KEY_USAGE, false, &der::sequence(&[&der::oid(CODE_SIGNING)])),
+        extension(SUBJECT_KEY_IDENTIFIER, false, &der::tlv(der::OCTET_STRING, &Sha1::digest(public_key))),
+    ];
+    let tbs = der::sequence(&[
+        &der::tlv(der::context(0), &der::small_integer(2)),
+        &der::tlv(der::INTEGER, &serial),
+        &key_type.signature_algorithm(),
+        &name,
+        &der::sequence(&[&time(not_before), &time(not_after)]),
+        &name,
+        &key_info,
+        &der::tlv(der::context(3), &der::tlv(der::SEQUENCE, &extensions.concat())),
+    ]);
+    let signature =
+        key.sign(&rng, &tbs).map_err(|err| Error::Other(format!("the test certificate could not be signed: {}", err)))?;
+    let certificate = der::sequence(&[
+        &tbs,
+        &key_type.signature_algorithm(),
+        &der::tlv(der::BIT_STRING, &[&[0], signature.as_ref()].concat()),
+    ]);
+    Ok((certificate, pkcs8.as_ref().to_vec()))
+}
+
+/// AES-256-CBC with PKCS#7 padding
+fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = aes::Aes256::new(GenericArray::from_slice(key));
+    let padding = 16 - data.len() % 16;
+    let mut padded = data.to_vec();
+    padded.resize(data.len() + padding, padding as u8);
+    let mut previous = iv.to_vec();
+    for block in padded.chunks_mut(16) {
+        block.iter_mut().zip(&previous).for_each(|(byte, chained)| *byte ^= chained);
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        previous = block.to_vec();
+    }
+    padded
+}
+
+/// The key derivation of PKCS#12 (RFC 7292 appendix B) with SHA-256, which the MAC of a PFX is keyed with.
+/// `id` is 3 for the MAC key
+fn pkcs12_key(password: &str, salt: &[u8], id: u8, iterations: u32, size: usize) -> Vec<u8> {
+    const BLOCK: usize = 64;
+    // the password as a NUL terminated BMPString
+    let password = [der::utf16(password), vec![0, 0]].concat();
+    let fill = |data: &[u8]| -> Vec<u8> {
+        (0..data.len().div_ceil(BLOCK) * BLOCK).map(|i| data[i % data.len()]).collect()
+    };
+    let mut input = [fill(salt), fill(&password)].concat();
+    let mut key = Vec::new();
+    while key.len() < size {
+        let mut hash = Sha256::new().chain_update([id; BLOCK]).chain_update(&input).finalize();
+        for _ in 1..iterations {
+            hash = Sha256::digest(hash);
+        }
+        key.extend_from_slice(&hash);
+        let addend: Vec<u8> = (0..BLOCK).map(|i| hash[i % hash.len()]).collect();
+        for block in input.chunks_mut(BLOCK) {
+            // block = block + addend + 1, modulo 2^512
+            let mut carry = 1u16;
+            for (byte, add) in block.iter_mut().zip(&addend).rev() {
+                let sum = *byte as u16 + *add as u16 + carry;
+                *byte = sum as u8;
+                carry = sum >> 8;
+            }
+        }
+    }
+    key.truncate(size);
+    key
+}
+
+/// A PKCS#12 attribute with a single value
+fn attribute(oid: &[u64], value: &[u8]) -> Vec<u8> {
+    der::sequence(&[&der::oid(oid), &der::set_of(vec![value.to_vec()])])
+}
+
+/// ContentInfo of type data
+fn data(content: &[u8]) -> Vec<u8> {
+    der::sequence(&[&der::oid(DATA), &der::tlv(der::context(0), &der::tlv(der::OCTET_STRING, content))])
+}
+
+/// A PFX of `certificate` and its PKCS#8 key, the key encrypted with `password` (PBES2, PBKDF2 with HMAC-SHA256
+/// and AES-256-CBC) and the whole protected by an HMAC-SHA256
+pub fn pfx(certificate: &[u8], pkcs8: &[u8], password: &str, friendly_name: &str) -> Result<Vec<u8>, Error> {
+    let rng = SystemRandom::new();
+    let attributes = der::set_of(vec![
+        attribute(LOCAL_KEY_ID, &der::tlv(der::OCTET_STRING, &Sha1::digest(certificate))),
+        attribute(FRIENDLY_NAME, &der::bmp_string(friendly_name)),
+    ]);
+
+    let (salt, iv) = (random(&rng, 16)?, random(&rng, 16)?);
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt, ITERATIONS, &mut key);
+    let kdf = der::sequence(&[
+        &der::oid(PBKDF2),
+        &der::sequence(&[
+            &der::tlv(der::OCTET_STRING, &salt),
+            &der::integer(ITERATIONS as u64),
+            &der::sequence(&[&der::oid(HMAC_SHA256), &der::null()]),
+        ]),
+    ]);
+    let scheme = der::sequence(&[&der::oid(AES256_CBC), &der::tlv(der::OCTET_STRING, &iv)]);
+    let encrypted_key = der::sequence(&[
+        &der::sequence(&[&der::oid(PBES2), &der::sequence(&[&kdf, &scheme])]),
+        &der::tlv(der::OCTET_STRING, &encrypt(&key, &iv, pkcs8)),
+    ]);
+    let key_bag = der::sequence(&[&der::oid(KEY_BAG), &der::tlv(der::context(0), &encrypted_key), &attributes]);
+    let certificate = der::sequence(&[
+        &der::oid(X509_CERTIFICATE),
+        &der::tlv(der::context(0), &der::tlv(der::OCTET_STRING, certificate)),
+    ]);
+    let cert_bag = der::sequence(&[&der::oid(CERT_BAG), &der::tlv(der::context(0), &certificate), &attributes]);
+    let safe = der::sequence(&[&data(&der::sequence(&[&cert_bag])), &data(&der::sequence(&[&key_bag]))]);
+
+    let mac_salt = random(&rng, 16)?;
+    let mac_key = pkcs12_key(password, &mac_salt, 3, ITERATIONS, 32);
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&mac_key).expect("HMAC takes keys of any length");
+    mac.update(&safe);
+    let mac_data = der::sequence(&[
+        &der::sequence(&[
+            &der::sequence(&[&der::oid(SHA256), &der::null()]),
+            &der::tlv(der::OCTET_STRING, &mac.finalize().into_bytes()),
+        ]),
+        &der::tlv(der::OCTET_STRING, &mac_salt),
+        &der::integer(ITERATIONS as u64),
+    ]);
+    Ok(der::sequence(&[&der::small_integer(3), &data(&safe), &mac_data]))
+}
+
+/// A password of 24 letters and digits
+fn generate_password() -> Result<String, Error> {
+    const CHARACTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz23456789";
+    let bytes = random(&SystemRandom::new(), 24)?;
+    Ok(bytes.iter().map(|byte| CHARACTERS[*byte as usize % CHARACTERS.len()] as char).collect())
+}
+
+/// Write the PFX next to `path` and rename it, replacing a file there only with `force`
+fn write(path: &Path, pfx: &[u8], force: bool) -> Result<(), Error> {
+    let failed = |err: std::io::Error| format!("'{}' could not be written: {}", path.display(), err);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(failed)?;
+    let mut temporary = tempfile::NamedTempFile::new_in(dir).map_err(failed)?;
+    temporary.write_all(pfx).map_err(failed)?;
+    match force {
+        true => temporary.persist(path).map(|_| ()).map_err(|err| failed(err.error))?,
+        false => temporary.persist_noclobber(path).map(|_| ()).map_err(|err| match path.exists() {
+            true => Error::Usage(format!("'{}' exists already, pass --force to replace it", path.display())),
+            false => Error::Other(failed(err.error)),
+        })?,
+    }
+    Ok(())
+}
+
+/// Generate the certificate and write it, printing its thumbprint and when it expires
+pub fn run(args: GenerateTestCertArgs) -> Result<(), Error> {
+    if args.out.exists() && !args.force {
+        Err(Error::Usage(format!("'{}' exists already, pass --force to replace it", args.out.display())))?;
+    }
+    let password = match &args.password_env {
+        Some(var) => match std::env::var(var) {
+            Ok(password) if !password.is_empty() => password,
+            _ => Err(Error::Usage(format!("--password-env names {}, which is not set to a password", var)))?,
+        },
+        None => generate_password()?,
+    };
+    crate::logging::register_secret(&password);
+    let (certificate, pkcs8) = generate(&args.subject, args.validity_days, args.key_type)?;
+    let details: SigningCertificate = certificate::details(&certificate)
+        .ok_or_else(|| "the generated certificate could not be read back".to_string())?;
+    let friendly_name = format!("{} (test signing)", details.subject);
+    write(&args.out, &pfx(&certificate, &pkcs8, &password, &friendly_name)?, args.force)?;
+    info!("wrote the test certificate {} to {}", details.subject, args.out.display());
+    info!("thumbprint {}", details.thumbprint);
+    info!(
+        "it expires {} (in {} days), run generate-test-cert --force before then. Windows trusts its signatures only \
+         where it is installed as a trusted root, never ship them",
+        details.not_after, args.validity_days
+    );
+    if args.password_env.is_none() {
+        info!("the password was generated and is printed to stdout, keep it for --pfx-password-env");
+        println!("{}", password);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certificate_and_pfx() {
+        assert!(parse_subject("CN=MyApp Test Signing, O=Contoso, C=US").is_ok());
+        assert!(parse_subject("O=Contoso").unwrap_err().contains("no CN"));
+        assert!(parse_subject("CN=a, X=b").is_err() && parse_subject("CN=a, C=USA").is_err());
+
+        let (certificate, pkcs8) = generate("CN=MyApp Test Signing, O=Contoso, C=US", 30, KeyType::EcdsaP256).unwrap();
+        let details = certificate::details(&certificate).unwrap();
+        assert_eq!(details.subject, format!("CN=MyApp Test Signing, OU={}, O=Contoso, C=US", TEST_OU));
+        assert_eq!((details.issuer, details.eku), (details.subject.clone(), vec!["1.3.6.1.5.5.7.3.3".to_string()]));
+        let days = chrono::DateTime::parse_from_rfc3339(&details.not_after).unwrap().with_timezone(&Utc) - Utc::now();
+        assert!((29..=30).contains(&days.num_days()), "{}", details.not_after);
+
+        // longer than a hash, the derivation goes on with the input incremented
+        let salt = [0x0a, 0x58, 0xcf, 0x64, 0x53, 0x0d, 0x82, 0x3f];
+        let key = pkcs12_key("smeg", &salt, 3, 2, 40);
+        assert_eq!((key.len(), &key[..32]), (40, &pkcs12_key("smeg", &salt, 3, 2, 32)[..]));
+        assert_ne!(key[..32], pkcs12_key("smeg", &salt, 1, 2, 32));
+
+        let pfx = pfx(&certificate, &pkcs8, "hunter2", "test").unwrap();
+        let parts = der::children(der::read(&pfx).unwrap().0.content).unwrap();
+        assert_eq!((parts.len(), parts[0].encoded), (3, der::small_integer(3).as_slice()));
+        assert!(pfx.windows(certificate.len()).any(|window| window == certificate));
+        assert!(!pfx.windows(pkcs8.len()).any(|window| window == pkcs8));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.pfx");
+        write(&path, b"first", false).unwrap();
+        assert!(matches!(write(&path, b"second", false), Err(Error::Usage(_))));
+        write(&path, b"second", true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    certificate, input,
+    signtool::{self, Digest},
+    verify::{self, Verifier},
+};
+use serde::Serialize;
+use std::{path::Path, thread, time::Duration};
+use tracing::warn;
+
+/// What timestamping a signed file came to
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Timestamped {
+    pub path: String,
+    /// The timestamp authority that countersigned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_url: Option<String>,
+    /// When it countersigned, as signtool verify reports it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The timestamp authorities in the order they are tried, each `retries` more times after it failed, with how
+/// long to wait before the attempt
+fn attempts(urls: &[String], retries: u32) -> Vec<(&str, Duration)> {
+    let mut attempts = Vec::new();
+    for url in urls {
+        for attempt in 0..=retries {
+            attempts.push((url.as_str(), Duration::from_secs(attempt.into())));
+        }
+    }
+    attempts
+}
+
+/// Countersign the signature of a file with the first of the authorities of `urls` that answers, returns which one
+/// did or the last error
+pub fn stamp<'a>(
+    sign_tool_path: &str,
+    urls: &'a [String],
+    digest: Digest,
+    retries: u32,
+    path: &str,
+) -> Result<&'a str, String> {
+    let mut error = None;
+    for (url, wait) in attempts(urls, retries) {
+        thread::sleep(wait);
+        match signtool::timestamp(sign_tool_path, url, digest, &input::tool_path(path)) {
+            Ok(()) => return Ok(url),
+            Err(err) => {
+                warn!("{} could not timestamp {}: {}", url, path, err);
+                error = Some(err);
+            }
+        }
+    }
+    Err(error.unwrap_or_else(|| "no timestamp authority to try".to_string()))
+}
+
+/// Add a timestamp to the existing signature of a file, trying the authorities of `urls` in order, then check
+/// `verifier` finds the countersignature. A file without a signature is an error
+pub fn timestamp(
+    sign_tool_path: &str,
+    verifier: &Verifier,
+    urls: &[String],
+    digest: Digest,
+    retries: u32,
+    path: &str,
+) -> Timestamped {
+    let mut timestamped = Timestamped { path: path.to_string(), ..Default::default() };
+    if !Path::new(path).is_file() {
+        timestamped.error = Some("file not found".to_string());
+        return timestamped;
+    }
+    if certificate::of_file(path).is_none() && verify::verify(verifier, path).subject.is_none() {
+        timestamped.error = Some("the file has no signature to add a timestamp to, sign it first".to_string());
+        return timestamped;
+    }
+
+    match stamp(sign_tool_path, urls, digest, retries, path) {
+        Ok(url) => timestamped.timestamp_url = Some(url.to_string()),
+        Err(error) => {
+            timestamped.error = Some(error);
+            return timestamped;
+        }
+    }
+    timestamped.timestamp = verify::verify(verifier, path).timestamp;
+    if timestamped.timestamp.is_none() {
+        let error = "signtool timestamped the file, but verifying it finds no countersignature";
+        timestamped.error = Some(error.to_string());
+    }
+    timestamped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_order() {
+        let urls = ["http://timestamp.acs.microsoft.com", "http://timestamp.digicert.com"].map(String::from);
+        let attempts = attempts(&urls, 1);
+        assert_eq!(
+            attempts,
+            [
+                (urls[0].as_str(), Duration::ZERO),
+                (urls[0].as_str(), Duration::from_secs(1)),
+                (urls[1].as_str(), Duration::ZERO),
+                (urls[1].as_str(), Duration::from_secs(1)),
+            ]
+        );
+        let verifier = Verifier::Signtool("signtool".to_string());
+        let missing = timestamp("signtool", &verifier, &urls, Digest::Sha256, 0, "missing.exe");
+        assert_eq!(missing.error.as_deref(), Some("file not found"));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{authenticode, driver, input};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use std::{fs, io::Write, path::Path};
+use tracing::debug;
+
+/// The lines signtool puts around the signature of a script, by the extensions of the scripts that use them
+const SCRIPT_BLOCKS: [(&[&str], &str, &str); 3] = [
+    (
+        &["ps1", "psd1", "psm1", "ps1xml", "psc1", "cdxml"],
+        "# SIG # Begin signature block",
+        "# SIG # End signature block",
+    ),
+    (&["vbs"], "'' SIG '' Begin signature block", "'' SIG '' End signature block"),
+    (&["js"], "// SIG // Begin signature block", "// SIG // End signature block"),
+];
+
+/// What removing the signatures of a file came to
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Unsigned {
+    pub path: String,
+    /// Signatures removed, nested ones included
+    pub removed: usize,
+    /// Copy of the file as it was, for --backup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The signature of `pkcs7` and the ones nested in it
+fn signatures(pkcs7: &[u8]) -> usize {
+    1 + authenticode::nested_signatures(pkcs7)
+}
+
+/// The PE checksum of an image: its 16 bit words summed with the carries folded back in, leaving out the checksum
+/// itself, plus its length
+pub(crate) fn pe_checksum(image: &[u8], checksum: usize) -> u32 {
+    let mut sum = 0u64;
+    for (index, word) in image.chunks(2).enumerate() {
+        if index * 2 == checksum || index * 2 == checksum + 2 {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u64;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    ((sum & 0xffff) + (sum >> 16) + image.len() as u64) as u32
+}
+
+/// A PE image without its certificate table, with how many signatures the table held. Data after the table
+/// would be lost, so it is an error
+fn unsign_pe(mut image: Vec<u8>) -> Result<(Vec<u8>, usize), String> {
+    let (checksum, certificates) = driver::header_offsets(&image).ok_or("its PE headers are invalid")?;
+    let Some((table, size)) = driver::certificate_table(&image) else {
+        return Ok((image, 0));
+    };
+    if table + size < image.len() {
+        Err("data follows its certificate table, removing the table would lose it")?;
+    }
+    let entries = image.get(table..table + size).ok_or("its certificate table is truncated")?;
+    // WIN_CERTIFICATE entries: length, revision and type, 2 for PKCS#7 SignedData, each aligned to 8 bytes
+    let mut removed = 0;
+    let mut offset = 0;
+    while offset + 8 <= entries.len() {
+        let length = driver::u32_at(entries, offset).unwrap_or_default();
+        if length < 8 {
+            break;
+        }
+        if entries[offset + 6..offset + 8] == [2, 0] {
+            removed += entries.get(offset + 8..offset + length).map(signatures).unwrap_or(1);
+        }
+        offset += length.next_multiple_of(8);
+    }
+    image.truncate(table);
+    image[certificates..certificates + 8].fill(0);
+    let sum = pe_checksum(&image, checksum);
+    image[checksum..checksum + 4].copy_from_slice(&sum.to_le_bytes());
+    Ok((image, removed))
+}
+
+/// The lines around the signature block of a script, by its extension
+pub(crate) fn script_markers(path: &str) -> Option<(&'static str, &'static str)> {
+    let extension = Path::new(path).extension()?.to_string_lossy().to_ascii_lowercase();
+    let (_, begin, end) = SCRIPT_BLOCKS.iter().find(|(extensions, ..)| extensions.contains(&extension.as_str()))?;
+    Some((begin, end))
+}
+
+/// The PKCS#7 of the signature block from `start` to `stop` of a script, its lines after the first base64 behind
+/// the comment prefix of `begin`
+fn decode_block(script: &str, start: usize, stop: usize, begin: &str) -> Option<Vec<u8>> {
+    let prefix = begin[..begin.find("SIG")?].trim_end();
+    let encoded: String =
+        script[start..stop].lines().skip(1).map(|line| line.trim().trim_start_matches(prefix).trim()).collect();
+    STANDARD.decode(encoded).ok()
+}
+
+/// The PKCS#7 in the signature block of a script, None without one
+pub(crate) fn script_signature(script: &str, begin: &str, end: &str) -> Option<Vec<u8>> {
+    let start = script.find(begin)?;
+    let stop = script[start..].find(end)? + start;
+    decode_block(script, start, stop, begin)
+}
+
+/// A script without the signature block signtool appended, with how many signatures the block held
+fn unsign_script(script: &str, begin: &str, end: &str) -> Result<(String, usize), String> {
+    let Some(start) = script.find(begin) else {
+        return Ok((script.to_string(), 0));
+    };
+    let stop = script[start..].find(end).ok_or("its signature block has no end")? + start;
+    let after = script[stop..].find('\n').map(|line_end| stop + line_end + 1).unwrap_or(script.len());
+    let removed = decode_block(script, start, stop, begin).map(|pkcs7| signatures(&pkcs7)).unwrap_or(1);
+    // signtool starts the block on a line of its own
+    let before = script[..start].strip_suffix("\r\n").or_else(|| script[..start].strip_suffix('\n'));
+    Ok((format!("{}{}", before.unwrap_or(&script[..start]), &script[after..]), removed))
+}
+
+/// The file without its signatures, with how many there were, or why its format isn't supported
+fn unsigned_content(path: &str, content: Vec<u8>) -> Result<(Vec<u8>, usize), String> {
+    if driver::header_offsets(&content).is_some() {
+        return unsign_pe(content);
+    }
+    let Some((begin, end)) = script_markers(path) else {
+        Err(format!(
+            "removing signatures is not supported for .{} files, only for PE images and scripts: sign the \
+             unsigned build output again instead",
+            Path::new(path).extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase()
+        ))?
+    };
+    let script = String::from_utf8(content).map_err(|_| "only UTF-8 scripts are supported")?;
+    unsign_script(&script, begin, end).map(|(script, removed)| (script.into_bytes(), removed))
+}
+
+/// Write `content` over `path` through a temporary file next to it, so a failure leaves the file as it was
+fn replace(path: &Path, content: &[u8]) -> Result<(), String> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(dir).map_err(|err| err.to_string())?;
+    file.write_all(content).map_err(|err| err.to_string())?;
+    file.persist(path).map_err(|err| format!("'{}' could not be written: {}", path.display(), err))?;
+    Ok(())
+}
+
+/// Remove every signature of a file, first copying it to `<file>.bak` with `backup`, then check it is still
+/// intact. A file without signatures is left alone
+pub fn unsign(path: &str, backup: bool) -> Unsigned {
+    let mut unsigned = Unsigned { path: path.to_string(), ..Default::default() };
+    let result = (|| {
+        let content = fs::read(path).map_err(|err| format!("'{}' could not be read: {}", path, err))?;
+        let (content, removed) = unsigned_content(path, content)?;
+        if removed == 0 {
+            debug!("{} has no signature", path);
+            return Ok(());
+        }
+        if backup {
+            let copy = format!("{}.bak", path);
+            if Path::new(&copy).exists() {
+                Err(format!("the backup '{}' already exists", copy))?;
+            }
+            fs::copy(path, &copy).map_err(|err| format!("the backup '{}' could not be written: {}", copy, err))?;
+            unsigned.backup = Some(copy);
+        }
+        replace(Path::new(path), &content)?;
+        unsigned.removed = removed;
+        input::check_structure(path)?;
+        if driver::header_offsets(&content).is_some() && driver::is_signed(&content) {
+            Err("it still has a certificate table")?;
+        }
+        Ok::<(), String>(())
+    })();
+    unsigned.error = result.err();
+    unsigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_removed() {
+        // a PE32+ image with a certificate table of two PKCS#7 entries
+        let mut image = vec![0u8; 0x200];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c] = 0x80;
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x98..0x9a].copy_from_slice(&0x20bu16.to_le_bytes());
+        image.extend(b"app code");
+        let unsigned = image.clone();
+        let entry = 0x98 + 112 + 4 * 8;
+        image[entry..entry + 8].copy_from_slice(&[0x08, 0x02, 0, 0, 32, 0, 0, 0]);
+        for _ in 0..2 {
+            image.extend([12, 0, 0, 0, 0, 2, 2, 0, 0x30, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        let (stripped, removed) = unsign_pe(image.clone()).unwrap();
+        assert_eq!(removed, 2);
+        assert!(!driver::is_signed(&stripped));
+        assert_eq!(&stripped[..0x98 + 64], &unsigned[..0x98 + 64]);
+        assert_eq!(stripped[0x98 + 68..], unsigned[0x98 + 68..]);
+        assert_eq!(driver::u32_at(&stripped, 0x98 + 64), Some(pe_checksum(&unsigned, 0x98 + 64) as usize));
+        assert_eq!(unsign_pe(unsigned.clone()).unwrap(), (unsigned, 0));
+        assert!(unsign_pe([image.as_slice(), b"overlay"].concat()).is_err());
+
+        let (begin, end) = script_markers("setup.PS1").unwrap();
+        let script = format!("Write-Host hi\r\n\r\n{}\r\n# MIIB\r\n# AA==\r\n{}\r\n", begin, end);
+        assert_eq!(unsign_script(&script, begin, end).unwrap(), ("Write-Host hi\r\n".to_string(), 1));
+        assert_eq!(unsign_script("Write-Host hi\r\n", begin, end).unwrap().1, 0);
+        assert!(unsigned_content("setup.msi", b"msi".to_vec()).unwrap_err().contains("not supported for .msi"));
+    }
+}
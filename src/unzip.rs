@@ -0,0 +1,227 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use tracing::warn;
+use zip::result::ZipError;
+
+/// Most the signing client package may unpack to, it is a few MB. A corrupt or hostile archive could claim less in
+/// its headers than it inflates to, so what is written is counted
+pub const MAX_SIZE: u64 = 512 * 1024 * 1024;
+
+/// The path of an entry relative to the target, `None` for anything that could end up outside of it: absolute
+/// paths, drive letters and `..` climbing above the target. `a/../b` stays inside and is `b`
+fn entry_path(name: &str) -> Option<PathBuf> {
+    if name.starts_with(['/', '\\']) {
+        return None;
+    }
+    let mut path = PathBuf::new();
+    for segment in name.split(['/', '\\']) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                if !path.pop() {
+                    return None;
+                }
+            }
+            // C:, and NTFS streams like file:stream
+            segment if segment.contains(':') => return None,
+            segment => path.push(segment),
+        }
+    }
+    // whatever the platform makes of it, only plain names may be left
+    path.components().all(|component| matches!(component, Component::Normal(_))).then_some(path)
+}
+
+/// Times extracting is tried while the archive or the files written are locked
+const ATTEMPTS: u32 = 5;
+
+/// Why extracting failed, with whether another process holding a file open caused it, which clears up after a
+/// moment. Windows Defender scans a fresh download for a few seconds
+#[derive(Debug)]
+pub struct Failure {
+    pub message: String,
+    pub locked: bool,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Whether an I/O error is a sharing violation or access denied on Windows, what a file held open gives
+fn is_locked(err: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    cfg!(windows) && (err.kind() == io::ErrorKind::PermissionDenied || matches!(err.raw_os_error(), Some(32 | 33)))
+}
+
+fn io_failure(message: String, err: io::Error) -> Failure {
+    Failure { locked: is_locked(&err), message: format!("{}: {}", message, err) }
+}
+
+fn zip_failure(message: String, err: ZipError) -> Failure {
+    match err {
+        ZipError::Io(err) => io_failure(message, err),
+        err => Failure { locked: false, message: format!("{}: {}", message, err) },
+    }
+}
+
+fn invalid(message: String) -> Failure {
+    Failure { locked: false, message }
+}
+
+/// Extract `archive` into `target`, refusing entries that would be written outside of it and archives unpacking
+/// to more than `limit` bytes
+pub fn extract(archive: &Path, target: &Path, limit: u64) -> Result<(), Failure> {
+    let file =
+        File::open(archive).map_err(|err| io_failure(format!("'{}' could not be read", archive.display()), err))?;
+    let mut zip =
+        zip::ZipArchive::new(file).map_err(|err| zip_failure(format!("'{}' is not a zip", archive.display()), err))?;
+    let mut written = 0u64;
+    for index in 0..zip.len() {
+        let entry = zip.by_index(index).map_err(|err| zip_failure(format!("entry {} is invalid", index), err))?;
+        let Some(relative) = entry_path(entry.name()) else {
+            Err(invalid(format!(
+                "'{}' has an entry outside of the directory it unpacks to: {}",
+                archive.display(),
+                entry.name()
+            )))?
+        };
+        let path = target.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)
+                .map_err(|err| io_failure(format!("'{}' could not be created", path.display()), err))?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| io_failure(format!("'{}' could not be created", parent.display()), err))?;
+        }
+        let name = entry.name().to_string();
+        let mut output = File::create(&path)
+            .map_err(|err| io_failure(format!("'{}' could not be created", path.display()), err))?;
+        // one byte over the limit is enough to tell
+        written += io::copy(&mut entry.take(limit - written + 1), &mut output)
+            .map_err(|err| io_failure(format!("{} could not be unpacked", name), err))?;
+        if written > limit {
+            Err(invalid(format!(
+                "'{}' unpacks to more than {} MB, it is not the signing client",
+                archive.display(),
+                limit >> 20
+            )))?;
+        }
+    }
+    Ok(())
+}
+
+/// How long to wait before attempt `attempt` of extracting, doubling from a second up to 8
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << (attempt - 2).min(3))
+}
+
+/// Extract `archive` next to `target`, then move it into place, replacing what was there. A file held open is
+/// waited for and tried again a few times, a broken archive is not, and nothing half unpacked is left behind
+pub fn install(archive: &Path, target: &Path, limit: u64) -> Result<(), String> {
+    let staging = target.with_extension("partial");
+    let attempt_install = || {
+        if staging.exists() {
+            fs::remove_dir_all(&staging)
+                .map_err(|err| io_failure(format!("'{}' could not be removed", staging.display()), err))?;
+        }
+        extract(archive, &staging, limit)?;
+        if target.exists() {
+            fs::remove_dir_all(target)
+                .map_err(|err| io_failure(format!("'{}' could not be removed", target.display()), err))?;
+        }
+        fs::rename(&staging, target).map_err(|err| {
+            io_failure(format!("'{}' could not be moved to '{}'", staging.display(), target.display()), err)
+        })
+    };
+    let mut attempt = 1;
+    loop {
+        match attempt_install() {
+            Ok(()) => return Ok(()),
+            Err(failure) if failure.locked && attempt < ATTEMPTS => {
+                attempt += 1;
+                let wait = backoff(attempt);
+                warn!("{}, trying again in {}s (attempt {} of {})", failure, wait.as_secs(), attempt, ATTEMPTS);
+                thread::sleep(wait);
+            }
+            Err(failure) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(failure.message);
+            }
+        }
+    }
+}
+
+/// The nuspec at the root of a NuGet package, read without unpacking the package
+pub fn nuspec(archive: &Path) -> Result<String, String> {
+    let file = File::open(archive).map_err(|err| format!("'{}' could not be read: {}", archive.display(), err))?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|err| format!("'{}' is not a zip: {}", archive.display(), err))?;
+    let name = zip
+        .file_names()
+        .find(|name| !name.contains(['/', '\\']) && name.to_ascii_lowercase().ends_with(".nuspec"))
+        .map(str::to_string)
+        .ok_or_else(|| format!("'{}' has no nuspec, it is not a NuGet package", archive.display()))?;
+    let entry = zip.by_name(&name).map_err(|err| err.to_string())?;
+    let mut nuspec = String::new();
+    // a nuspec is a few KB
+    entry.take(1024 * 1024).read_to_string(&mut nuspec).map_err(|err| format!("{} could not be read: {}", name, err))?;
+    Ok(nuspec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn archive(dir: &Path, entries: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join("package.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, content) in entries {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn malicious_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("lib");
+        let escaping = ["../evil.dll", "bin/../../evil.dll", "/etc/evil", "\\evil.dll", "C:/evil.dll", "..\\..\\x"];
+        for name in escaping {
+            let zip = archive(dir.path(), &[("bin/ok.dll", b"ok"), (name, b"evil")]);
+            let err = extract(&zip, &target, MAX_SIZE).unwrap_err();
+            assert!(err.message.contains("outside of the directory") && !err.locked, "{}: {}", name, err);
+        }
+        assert!(!dir.path().join("evil.dll").exists());
+
+        let zip = archive(dir.path(), &[("bin/x64/./a.dll", b"a"), ("bin/tmp/../b.dll", b"b")]);
+        extract(&zip, &target, MAX_SIZE).unwrap();
+        assert_eq!(fs::read(target.join("bin/x64/a.dll")).unwrap(), b"a");
+        assert_eq!(fs::read(target.join("bin/b.dll")).unwrap(), b"b");
+
+        let zip = archive(dir.path(), &[("a.bin", &[0; 600]), ("b.bin", &[0; 600])]);
+        assert!(extract(&zip, &target, 1000).unwrap_err().message.contains("unpacks to more than"));
+        assert!(nuspec(&zip).unwrap_err().contains("no nuspec"));
+
+        // a broken archive is not tried again, and leaves nothing behind
+        fs::write(dir.path().join("broken.nupkg"), b"PK not a zip").unwrap();
+        let err = install(&dir.path().join("broken.nupkg"), &target, MAX_SIZE).unwrap_err();
+        assert!(err.contains("is not a zip"), "{}", err);
+        assert!(!target.with_extension("partial").exists());
+        install(&zip, &target, MAX_SIZE).unwrap();
+        assert!(target.join("a.bin").is_file() && !target.join("bin").exists());
+        assert_eq!([2, 3, 4, 5, 6].map(|attempt| backoff(attempt).as_secs()), [1, 2, 4, 8, 8]);
+
+        let zip = archive(dir.path(), &[("lib/x.nuspec", b"nested"), ("Client.NUSPEC", b"<version>1.0</version>")]);
+        assert_eq!(nuspec(&zip).unwrap(), "<version>1.0</version>");
+    }
+}
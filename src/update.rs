@@ -0,0 +1,294 @@
+use crate::{
+    cli::SelfUpdateArgs,
+    error::Error,
+    hash,
+    logging::{self, run_captured},
+};
+use duct::cmd;
+use reqwest::{
+    header::{ACCEPT, AUTHORIZATION, USER_AGENT},
+    StatusCode,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Repository the releases are published in
+const REPOSITORY: &str = "levminer/trusted-signing-cli";
+
+/// A GitHub release
+#[derive(Deserialize, Debug)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Target triple of the release asset for this machine, releases are built for these only
+fn host_target() -> Option<String> {
+    let suffix = match std::env::consts::OS {
+        "windows" => "pc-windows-msvc",
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        _ => return None,
+    };
+    match std::env::consts::ARCH {
+        arch @ ("x86_64" | "aarch64") => Some(format!("{}-{}", arch, suffix)),
+        _ => None,
+    }
+}
+
+/// Name of the release archive for a target, like cargo-dist names them
+fn asset_name(target: &str) -> String {
+    format!("trusted-signing-cli-{}.zip", target)
+}
+
+/// The numbers of a version like `v0.4.0`, a pre-release suffix is ignored
+fn version(tag: &str) -> Vec<u64> {
+    let version = tag.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next().unwrap_or_default();
+    version.split('.').map(|number| number.parse().unwrap_or(0)).collect()
+}
+
+/// The SHA-256 for `name` in a checksum file, either a bare hash or `<hash> [*]<name>` lines
+fn checksum(content: &str, name: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let hash = fields.next().filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))?;
+        match fields.next() {
+            None => Some(hash.to_ascii_lowercase()),
+            Some(file) if file.trim_start_matches('*') == name => Some(hash.to_ascii_lowercase()),
+            Some(_) => None,
+        }
+    })
+}
+
+/// Whether `exe` is in the bin directory of Cargo, which keeps its own records of what it installed
+fn installed_by_cargo(exe: &Path) -> bool {
+    let homes = [
+        std::env::var_os("CARGO_HOME").map(PathBuf::from),
+        directories::BaseDirs::new().map(|dirs| dirs.home_dir().join(".cargo")),
+    ];
+    let parent = exe.parent().and_then(|parent| parent.canonicalize().ok());
+    homes.into_iter().flatten().any(|home| home.join("bin").canonicalize().ok() == parent)
+}
+
+/// GET `url` from GitHub, with the GITHUB_TOKEN when set so runners aren't rate limited
+async fn get(url: &str, accept: &str) -> Result<reqwest::Response, Error> {
+    let download_error = |message: String| Error::Download {
+        url: url.to_string(),
+        message,
+    };
+    let mut request = reqwest::Client::new()
+        .get(url)
+        .header(USER_AGENT, concat!("trusted-signing-cli/", env!("CARGO_PKG_VERSION")))
+        .header(ACCEPT, accept);
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        logging::register_secret(&token);
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let response = request.send().await.map_err(|err| download_error(format!("GitHub could not be reached: {}", err)))?;
+    match response.status() {
+        StatusCode::NOT_FOUND => Err(download_error("GitHub answered 404 Not Found".to_string())),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => Err(download_error(format!(
+            "GitHub answered {}, set GITHUB_TOKEN when the rate limit was reached",
+            response.status()
+        ))),
+        status if !status.is_success() => Err(download_error(format!("GitHub answered {}", status))),
+        _ => Ok(response),
+    }
+}
+
+/// The latest release, or the one tagged `tag`
+async fn release(tag: Option<&str>) -> Result<Release, Error> {
+    let url = match tag {
+        Some(tag) => format!("https://api.github.com/repos/{}/releases/tags/{}", REPOSITORY, tag),
+        None => format!("https://api.github.com/repos/{}/releases/latest", REPOSITORY),
+    };
+    debug!("looking up the release at {}", url);
+    let body = get(&url, "application/vnd.github+json").await?.text().await.map_err(|err| Error::Download {
+        url: url.clone(),
+        message: format!("the release could not be read: {}", err),
+    })?;
+    serde_json::from_str(&body).map_err(|err| Error::Download {
+        url,
+        message: format!("GitHub answered with an unexpected release: {}", err),
+    })
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, Error> {
+    let bytes = get(url, "application/octet-stream").await?.bytes().await.map_err(|err| Error::Download {
+        url: url.to_string(),
+        message: format!("the download failed: {}", err),
+    })?;
+    Ok(bytes.to_vec())
+}
+
+/// The executable named `name` in the release archive, wherever in it
+fn extract(archive: &[u8], name: &str) -> Result<Vec<u8>, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(archive)).map_err(|err| err.to_string())?;
+    let index = (0..archive.len())
+        .find(|index| {
+            archive
+                .by_index(*index)
+                .is_ok_and(|entry| entry.is_file() && Path::new(entry.name()).file_name() == Some(name.as_ref()))
+        })
+        .ok_or_else(|| format!("the release archive has no {}", name))?;
+    let mut content = Vec::new();
+    archive.by_index(index).and_then(|mut entry| Ok(entry.read_to_end(&mut content)?)).map_err(|err| err.to_string())?;
+    Ok(content)
+}
+
+/// Check the Authenticode signature of the new executable, releases are signed with Trusted Signing
+fn verify_signature(exe: &Path) -> Result<(), Error> {
+    if !cfg!(windows) {
+        debug!("not checking the signature of {}, Authenticode is only checked on Windows", exe.display());
+        return Ok(());
+    }
+    let script = format!(
+        "(Get-AuthenticodeSignature -LiteralPath '{}').Status",
+        exe.display().to_string().replace('\'', "''")
+    );
+    let output = run_captured(cmd!("powershell", "-NoProfile", "-NonInteractive", "-Command", script))
+        .map_err(|err| format!("the signature of the new version could not be checked: {}", err))?;
+    let status = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match output.status.success() && status == "Valid" {
+        true => Ok(()),
+        false => Err(Error::Other(format!(
+            "the new version is not validly signed ({}), it was not installed: {}",
+            status,
+            logging::failure_output(&output)
+        ))),
+    }
+}
+
+/// Put `new` in place of the running `exe`. Windows locks a running executable but lets it be renamed,
+/// so it is moved aside first and moved back when the new one can't take its place
+fn replace(exe: &Path, new: &Path) -> Result<(), String> {
+    let old = exe.with_extension("old");
+    let _ = fs::remove_file(&old);
+    fs::rename(exe, &old).map_err(|err| format!("{} could not be moved aside: {}", exe.display(), err))?;
+    if let Err(err) = fs::rename(new, exe) {
+        let _ = fs::rename(&old, exe);
+        Err(format!("{} could not be replaced: {}", exe.display(), err))?;
+    }
+    // still running on Windows, the next update removes it
+    let _ = fs::remove_file(&old);
+    Ok(())
+}
+
+/// Look up the release and replace the running executable with it
+pub async fn run(args: SelfUpdateArgs) -> Result<(), Error> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release = release(args.version.as_deref()).await?;
+    let newer = version(&release.tag_name) > version(current);
+    match (newer, &args.version) {
+        (_, Some(_)) if version(&release.tag_name) == version(current) => {
+            eprintln!("{} is already installed", release.tag_name);
+            return Ok(());
+        }
+        (false, None) => {
+            eprintln!("trusted-signing-cli {} is up to date, the latest release is {}", current, release.tag_name);
+            return Ok(());
+        }
+        _ => eprintln!("trusted-signing-cli {} is installed, {} is available", current, release.tag_name),
+    }
+    if args.check_only {
+        return Ok(());
+    }
+
+    let exe = std::env::current_exe().map_err(|err| format!("the running executable could not be found: {}", err))?;
+    if installed_by_cargo(&exe) {
+        Err(Error::Usage(format!(
+            "{} was installed by Cargo, update it with `cargo install trusted-signing-cli --force` (or \
+             `cargo binstall trusted-signing-cli`) so Cargo's records stay right",
+            exe.display()
+        )))?;
+    }
+    let target = host_target().ok_or_else(|| {
+        format!("no release is built for {} {}", std::env::consts::OS, std::env::consts::ARCH)
+    })?;
+    let name = asset_name(&target);
+    let find = |name: &str| release.assets.iter().find(|asset| asset.name == name);
+    let asset = find(&name).ok_or_else(|| format!("release {} has no {}", release.tag_name, name))?;
+    let sums = find(&format!("{}.sha256", name))
+        .ok_or_else(|| format!("release {} has no checksum for {}, it was not installed", release.tag_name, name))?;
+
+    eprintln!("downloading {}", asset.browser_download_url);
+    let archive = download(&asset.browser_download_url).await?;
+    let sums = String::from_utf8_lossy(&download(&sums.browser_download_url).await?).to_string();
+    let expected = checksum(&sums, &name).ok_or_else(|| format!("the checksum file of {} lists no hash", name))?;
+    let actual = hash::hex(&Sha256::digest(&archive));
+    if actual != expected {
+        Err(Error::Download {
+            url: asset.browser_download_url.clone(),
+            message: format!("the checksum doesn't match, expected {} but got {}", expected, actual),
+        })?;
+    }
+
+    let file_name = exe.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let content = extract(&archive, &file_name).map_err(|err| format!("{} could not be unpacked: {}", name, err))?;
+    let new = exe.with_extension("new");
+    fs::write(&new, content).map_err(|err| format!("{} could not be written: {}", new.display(), err))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755)).map_err(|err| err.to_string())?;
+    }
+    let installed = verify_signature(&new).and_then(|_| Ok(replace(&exe, &new)?));
+    if installed.is_err() {
+        let _ = fs::remove_file(&new);
+    }
+    installed?;
+    eprintln!("updated {} to {}", exe.display(), release.tag_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn release_assets() {
+        assert!(version("v0.10.0") > version("0.9.3"));
+        assert_eq!(version("v1.2.0-beta.1"), [1, 2, 0]);
+        assert_eq!(asset_name("x86_64-pc-windows-msvc"), "trusted-signing-cli-x86_64-pc-windows-msvc.zip");
+
+        let hash = "ab".repeat(32);
+        let name = "trusted-signing-cli-aarch64-pc-windows-msvc.zip";
+        assert_eq!(checksum(&format!("{} *{}\n", hash, name), name), Some(hash.clone()));
+        assert_eq!(checksum(&format!("{}  other.zip\n", hash), name), None);
+        assert_eq!(checksum(&hash.to_uppercase(), name), Some(hash));
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive.start_file("trusted-signing-cli-x86_64/trusted-signing-cli.exe", Default::default()).unwrap();
+        archive.write_all(b"MZ").unwrap();
+        let archive = archive.finish().unwrap().into_inner();
+        assert_eq!(extract(&archive, "trusted-signing-cli.exe").unwrap(), b"MZ");
+        assert!(extract(&archive, "cargo-trusted-sign.exe").is_err());
+    }
+
+    #[test]
+    fn swap_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let (exe, new) = (dir.path().join("trusted-signing-cli.exe"), dir.path().join("trusted-signing-cli.new"));
+        fs::write(&exe, "old").unwrap();
+        fs::write(&new, "new").unwrap();
+        replace(&exe, &new).unwrap();
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "new");
+        assert!(!new.exists() && !exe.with_extension("old").exists());
+        assert!(replace(&exe, &new).is_err());
+        assert_eq!(fs::read_to_string(&exe).unwrap(), "new");
+    }
+}
@@ -0,0 +1,433 @@
+use crate::{discover, error::Error, input, logging::run_captured, signtool, wintrust};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use duct::cmd;
+use serde::Serialize;
+use std::path::Path;
+
+/// The primary signature of a file under the default Authenticode policy, as `signtool verify /pa /v` reports it
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Verification {
+    pub path: String,
+    /// Signed, chained to a trusted root under the default Authenticode policy and timestamped
+    pub valid: bool,
+    /// Without a signature at all, rather than an invalid one
+    pub unsigned: bool,
+    pub status: Status,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    /// SHA1 thumbprint of the signing certificate
+    pub thumbprint: Option<String>,
+    /// Digest algorithm of the file hash, like sha256
+    pub digest: Option<String>,
+    /// When the timestamp authority countersigned, RFC 3339 in local time when the date could be read
+    pub timestamp: Option<String>,
+    /// Subject of the certificate of the timestamp authority
+    pub timestamp_authority: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What came of verifying a file
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Valid,
+    Unsigned,
+    #[default]
+    Invalid,
+    /// The chain ends in a root this machine doesn't trust, the signature may well be fine elsewhere
+    UntrustedRoot,
+}
+
+/// The issuing CAs of Trusted Signing certificates start with this, they chain to [wintrust::ROOT_NAME]
+const TRUSTED_SIGNING_ISSUER: &str = "Microsoft ID Verified";
+
+/// A certificate of a chain signtool prints, root first
+#[derive(Default)]
+struct Certificate {
+    issued_to: Option<String>,
+    issued_by: Option<String>,
+    sha1: Option<String>,
+}
+
+/// The date signtool prints, like `Tue Oct 14 10:00:00 2025` in local time
+fn timestamp(date: &str) -> String {
+    NaiveDateTime::parse_from_str(date, "%a %b %e %H:%M:%S %Y")
+        .ok()
+        .and_then(|date| Local.from_local_datetime(&date).single())
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_else(|| date.to_string())
+}
+
+/// Read the details of the primary signature from the verbose output of `signtool verify`
+fn parse(path: &str, output: &str) -> Verification {
+    let mut verification = Verification { path: path.to_string(), ..Default::default() };
+    let (mut signing, mut timestamping) = (Vec::<Certificate>::new(), Vec::<Certificate>::new());
+    let mut chain = None;
+    for line in output.lines().map(str::trim) {
+        if line.starts_with("Signature Index:") && !line.starts_with("Signature Index: 0") {
+            // later signatures are nested or appended ones, only the primary is reported
+            break;
+        }
+        if let Some(digest) = line.strip_prefix("Hash of file (").and_then(|rest| rest.split_once(')')) {
+            verification.digest = Some(digest.0.to_ascii_lowercase());
+        } else if line.starts_with("Signing Certificate Chain:") {
+            chain = Some(&mut signing);
+        } else if line.starts_with("Timestamp Verified by:") {
+            chain = Some(&mut timestamping);
+        } else if let Some(date) = line.strip_prefix("The signature is timestamped:") {
+            verification.timestamp = Some(timestamp(date.trim()));
+            chain = None;
+        } else if let Some(error) = line.strip_prefix("SignTool Error:") {
+            verification.error.get_or_insert_with(|| error.trim().to_string());
+        } else if let Some(chain) = chain.as_mut() {
+            let value = |prefix: &str| line.strip_prefix(prefix).map(|value| value.trim().to_string());
+            if let Some(issued_to) = value("Issued to:") {
+                chain.push(Certificate { issued_to: Some(issued_to), ..Default::default() });
+            } else if let Some(certificate) = chain.last_mut() {
+                certificate.issued_by = certificate.issued_by.take().or_else(|| value("Issued by:"));
+                certificate.sha1 = certificate.sha1.take().or_else(|| value("SHA1 hash:"));
+            }
+        }
+    }
+    if let Some(leaf) = signing.pop() {
+        verification.subject = leaf.issued_to;
+        verification.issuer = leaf.issued_by;
+        verification.thumbprint = leaf.sha1.map(|sha1| sha1.to_ascii_uppercase());
+    }
+    verification.timestamp_authority = timestamping.pop().and_then(|leaf| leaf.issued_to);
+    // signtool wraps the message over two lines
+    let words: Vec<&str> = output.split_whitespace().collect();
+    if words.join(" ").contains("terminated in a root certificate which is not trusted") {
+        verification.status = Status::UntrustedRoot;
+    }
+    verification
+}
+
+/// The signtool to verify with, `path` when given, otherwise the default one or the newest of the Windows SDKs
+pub fn find_signtool(path: Option<&str>) -> Result<String, Error> {
+    let found = match path {
+        Some(path) => Path::new(path).is_file().then(|| path.to_string()),
+        None => match Path::new(signtool::DEFAULT_PATH).is_file() {
+            true => Some(signtool::DEFAULT_PATH.to_string()),
+            false => discover::signtool().map(|path| path.display().to_string()),
+        },
+    };
+    found.ok_or_else(|| Error::MissingDependency {
+        component: "signtool",
+        message: format!(
+            "signtool{} was not found, verifying needs it from the Windows SDK: install the SDK or pass \
+             --sing-tool-path",
+            path.map(|path| format!(" '{}'", path)).unwrap_or_default()
+        ),
+    })
+}
+
+/// What verifies signatures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verifier {
+    /// WinVerifyTrust in process, on Windows
+    WinVerifyTrust,
+    /// `signtool verify` at this path, reading what it prints
+    Signtool(String),
+}
+
+impl Verifier {
+    /// WinVerifyTrust on Windows unless `with_signtool`, otherwise the signtool at `sign_tool_path`
+    pub fn new(with_signtool: bool, sign_tool_path: &str) -> Self {
+        match cfg!(windows) && !with_signtool {
+            true => Verifier::WinVerifyTrust,
+            false => Verifier::Signtool(sign_tool_path.to_string()),
+        }
+    }
+}
+
+/// The verifier of a command: WinVerifyTrust on Windows, signtool found like [find_signtool] with `with_signtool`
+/// and elsewhere
+pub fn verifier(with_signtool: bool, sign_tool_path: Option<&str>) -> Result<Verifier, Error> {
+    match cfg!(windows) && !with_signtool {
+        true => Ok(Verifier::WinVerifyTrust),
+        false => find_signtool(sign_tool_path).map(Verifier::Signtool),
+    }
+}
+
+/// Verify the Authenticode signature of a file with the default policy, nothing is sent anywhere
+pub fn verify(verifier: &Verifier, path: &str) -> Verification {
+    if !Path::new(path).is_file() {
+        return Verification { path: path.to_string(), error: Some("file not found".to_string()), ..Default::default() };
+    }
+    let verification = match verifier {
+        Verifier::WinVerifyTrust => wintrust::verify(path),
+        Verifier::Signtool(sign_tool_path) => signtool_verify(sign_tool_path, path),
+    };
+    settle(verification, wintrust::root_present)
+}
+
+/// Set the status of a verification, explaining an untrusted root of a Trusted Signing signature by whether
+/// `root_present` finds the root in the machine store
+fn settle(mut verification: Verification, root_present: impl FnOnce() -> Option<bool>) -> Verification {
+    verification.status = match verification.status {
+        _ if verification.valid => Status::Valid,
+        _ if verification.unsigned => Status::Unsigned,
+        Status::UntrustedRoot => Status::UntrustedRoot,
+        _ => Status::Invalid,
+    };
+    let trusted_signing = verification.issuer.as_ref().is_some_and(|issuer| issuer.starts_with(TRUSTED_SIGNING_ISSUER));
+    if verification.status != Status::UntrustedRoot || !trusted_signing {
+        return verification;
+    }
+    let explanation = match root_present() {
+        Some(false) => format!(
+            "the chain ends in {}, which isn't in the root store of this machine. The signature is fine, the root \
+             store is out of date: Windows adds the root with its automatic root certificate updates, which are \
+             turned off or can't reach the internet here, like on offline server images. Import the root into \
+             LocalMachine\\Root, or let Windows update its roots",
+            wintrust::ROOT_NAME
+        ),
+        Some(true) => format!(
+            "the chain ends in a root this machine doesn't trust, although {} is in its root store: the root may be \
+             disabled or distrusted by policy here",
+            wintrust::ROOT_NAME
+        ),
+        None => format!(
+            "the chain ends in a root this machine doesn't trust, the signature is fine where {} is trusted: the \
+             root store of the machine verifying it is out of date",
+            wintrust::ROOT_NAME
+        ),
+    };
+    verification.error = Some(explanation);
+    verification
+}
+
+/// Verify with `signtool verify /pa /v`, reading the details from its English output
+fn signtool_verify(sign_tool_path: &str, path: &str) -> Verification {
+    let output = match run_captured(cmd!(sign_tool_path, "verify", "/pa", "/v", input::tool_path(path).as_ref())) {
+        Ok(output) => output,
+        Err(err) => {
+            let error = Some(format!("signtool could not be run: {}", err));
+            return Verification { path: path.to_string(), error, ..Default::default() };
+        }
+    };
+    let text = String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr);
+    let mut verification = parse(path, &text);
+    verification.valid = output.status.success() && verification.timestamp.is_some();
+    verification.unsigned = !output.status.success() && text.contains("No signature found");
+    if output.status.success() && verification.timestamp.is_none() {
+        let error = "the signature is not timestamped, it stops being valid when the certificate expires";
+        verification.error = Some(error.to_string());
+    } else if !output.status.success() && verification.error.is_none() {
+        verification.error = Some(format!("signtool verify failed, error: {}", output.status));
+    }
+    verification
+}
+
+/// Why a file found with --recursive isn't verified
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Skip {
+    /// It matches an --exclude pattern
+    Excluded,
+    /// Its extension isn't one signtool signs by the extension rules of a sign
+    NotSignable,
+}
+
+/// A file left out of the verification, with the rule that left it out
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Skipped {
+    pub path: String,
+    pub skip: Skip,
+    pub reason: String,
+}
+
+/// Counts of a verification, of the files verified and of those left out
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub verified: usize,
+    pub unsigned: usize,
+    pub invalid: usize,
+    pub excluded: usize,
+    pub not_signable: usize,
+}
+
+impl Summary {
+    pub fn new(verifications: &[Verification], skipped: &[Skipped]) -> Self {
+        let count = |skip| skipped.iter().filter(|skipped| skipped.skip == skip).count();
+        Summary {
+            verified: verifications.iter().filter(|verification| verification.valid).count(),
+            unsigned: verifications.iter().filter(|verification| verification.unsigned).count(),
+            invalid: verifications.iter().filter(|verification| !verification.valid && !verification.unsigned).count(),
+            excluded: count(Skip::Excluded),
+            not_signable: count(Skip::NotSignable),
+        }
+    }
+}
+
+/// Check an `--exclude` glob
+pub fn parse_exclude(value: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(value).map_err(|err| format!("'{}' is not a valid glob: {}", value, err))
+}
+
+/// The first of `exclude` matching `path`, a `*` matching across directories too and case ignored on Windows
+fn excluded<'a>(path: &Path, exclude: &'a [glob::Pattern]) -> Option<&'a glob::Pattern> {
+    let options = glob::MatchOptions {
+        case_sensitive: !cfg!(windows),
+        require_literal_separator: false,
+        require_literal_leading_dot: false,
+    };
+    let path = path.to_string_lossy().replace('\\', "/");
+    exclude.iter().find(|pattern| pattern.matches_with(&path, options))
+}
+
+/// The files of `paths` to verify with the ones left out. With `recursive` a directory stands for the files under
+/// it that a sign would sign by `extensions`, other files are verified whatever they are. `exclude` matches the
+/// path under the directory walked, or the path as given
+pub fn select(
+    paths: &[String],
+    recursive: bool,
+    extensions: &[String],
+    exclude: &[glob::Pattern],
+) -> Result<(Vec<String>, Vec<Skipped>), Error> {
+    let (mut files, mut skipped) = (Vec::new(), Vec::new());
+    let mut skip = |path: String, skip, reason: String| skipped.push(Skipped { path, skip, reason });
+    for path in paths {
+        if !recursive || !Path::new(path).is_dir() {
+            match excluded(Path::new(path), exclude) {
+                Some(pattern) => skip(path.clone(), Skip::Excluded, format!("matches --exclude '{}'", pattern)),
+                None => files.push(path.clone()),
+            }
+            continue;
+        }
+        let pattern = Path::new(&glob::Pattern::escape(path)).join("**").join("*");
+        let mut found: Vec<_> = glob::glob(&pattern.to_string_lossy())
+            .map_err(|err| Error::Usage(format!("'{}' can't be walked: {}", path, err)))?
+            .filter_map(Result::ok)
+            .filter(|found| found.is_file())
+            .collect();
+        found.sort();
+        for found in found {
+            let relative = found.strip_prefix(path).unwrap_or(&found);
+            let display = found.display().to_string();
+            match excluded(relative, exclude) {
+                Some(pattern) => skip(display, Skip::Excluded, format!("matches --exclude '{}'", pattern)),
+                None if !input::is_supported(&display, extensions) => {
+                    let extension = found.extension().map(|extension| extension.to_string_lossy().to_string());
+                    let reason = match extension {
+                        Some(extension) => format!(".{} isn't one of the extensions signed", extension),
+                        None => "it has no extension".to_string(),
+                    };
+                    skip(display, Skip::NotSignable, reason)
+                }
+                None => files.push(display),
+            }
+        }
+    }
+    Ok((files, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "Verifying: target\\release\\app.exe
+
+Signature Index: 0 (Primary Signature)
+Hash of file (sha256): 8F3C2A6E59D1A0B7E1D0C2F4E44F6C1A2B3C4D5E6F708192A3B4C5D6E7F80910
+
+Signing Certificate Chain:
+    Issued to: Microsoft Identity Verification Root Certificate Authority 2020
+    Issued by: Microsoft Identity Verification Root Certificate Authority 2020
+    Expires:   Sun Apr 16 18:44:40 2045
+    SHA1 hash: F40042E2E5F7E8EF8189FED15519AECE42C3BFA2
+
+        Issued to: Microsoft ID Verified CS EOC CA 01
+        Issued by: Microsoft Identity Verification Root Certificate Authority 2020
+        Expires:   Wed Apr 13 17:31:53 2026
+        SHA1 hash: 67ECADC0760FEBF4BE8D652DD7C2E1E9C4A5D8F6
+
+            Issued to: Fabrikam, Inc.
+            Issued by: Microsoft ID Verified CS EOC CA 01
+            Expires:   Fri Oct 17 04:02:11 2025
+            SHA1 hash: 0a1b2c3d4e5f60718293a4b5c6d7e8f901234567
+
+The signature is timestamped: Tue Oct 14 10:00:00 2025
+Timestamp Verified by:
+    Issued to: Microsoft Identity Verification Root Certificate Authority 2020
+    Issued by: Microsoft Identity Verification Root Certificate Authority 2020
+    Expires:   Sun Apr 16 18:44:40 2045
+    SHA1 hash: F40042E2E5F7E8EF8189FED15519AECE42C3BFA2
+
+        Issued to: Microsoft Public RSA Time Stamping Authority
+        Issued by: Microsoft Public RSA Timestamping CA 2020
+        Expires:   Thu Nov 19 19:42:30 2026
+        SHA1 hash: 2B2F4F4C2E8D21B7C4A9D0E6F1A3B5C7D9E1F3A5
+
+Signature Index: 1
+Hash of file (sha1): 0000
+
+Successfully verified: target\\release\\app.exe
+";
+
+    #[test]
+    fn verbose_output() {
+        let verification = parse("app.exe", OUTPUT);
+        assert_eq!(verification.subject.as_deref(), Some("Fabrikam, Inc."));
+        assert_eq!(verification.issuer.as_deref(), Some("Microsoft ID Verified CS EOC CA 01"));
+        assert_eq!(verification.thumbprint.as_deref(), Some("0A1B2C3D4E5F60718293A4B5C6D7E8F901234567"));
+        assert_eq!(verification.digest.as_deref(), Some("sha256"));
+        assert!(verification.timestamp.unwrap().starts_with("2025-10-14T10:00:00"));
+        assert_eq!(verification.timestamp_authority.as_deref(), Some("Microsoft Public RSA Time Stamping Authority"));
+
+        let unsigned = parse("app.exe", "File: app.exe\nSignTool Error: No signature found.\n");
+        assert_eq!((unsigned.subject, unsigned.error.as_deref()), (None, Some("No signature found.")));
+        assert_eq!(timestamp("not a date"), "not a date");
+        assert_eq!(Verifier::new(true, "signtool.exe"), Verifier::Signtool("signtool.exe".to_string()));
+
+        let untrusted = OUTPUT.replace(
+            "Signature Index: 0",
+            "SignTool Error: A certificate chain processed, but terminated in a root\n        certificate which is \
+             not trusted by the trust provider.\n\nSignature Index: 0",
+        );
+        let missing = settle(parse("app.exe", &untrusted), || Some(false));
+        assert_eq!(missing.status, Status::UntrustedRoot);
+        assert!(missing.error.unwrap().contains("Root Certificate Authority 2020, which isn't in the root store"));
+        assert_eq!(settle(parse("app.exe", OUTPUT), || None).status, Status::Invalid);
+        let mut valid = parse("app.exe", OUTPUT);
+        valid.valid = true;
+        assert_eq!(settle(valid, || unreachable!()).status, Status::Valid);
+        assert_eq!(Verifier::new(false, "signtool.exe") == Verifier::WinVerifyTrust, cfg!(windows));
+    }
+
+    #[test]
+    fn tree_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["app.exe", "README.txt", "LICENSE", "bin/tool.DLL", "vendor/redist/vc.dll", ".hidden/x.ps1"] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let root = dir.path().display().to_string();
+        let extensions = input::extensions(None, &[]);
+        let exclude = [parse_exclude("vendor/**").unwrap()];
+        let (files, skipped) = select(std::slice::from_ref(&root), true, &extensions, &exclude).unwrap();
+        let relative = |path: &str| Path::new(path).strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/");
+        let files: Vec<_> = files.iter().map(|file| relative(file)).collect();
+        assert_eq!(files, [".hidden/x.ps1", "app.exe", "bin/tool.DLL"]);
+        let skipped: Vec<_> = skipped.iter().map(|skipped| (relative(&skipped.path), skipped.skip)).collect();
+        assert_eq!(
+            skipped,
+            [
+                ("LICENSE".to_string(), Skip::NotSignable),
+                ("README.txt".to_string(), Skip::NotSignable),
+                ("vendor/redist/vc.dll".to_string(), Skip::Excluded)
+            ]
+        );
+
+        // a file given is verified whatever its extension, unless excluded
+        let readme = dir.path().join("README.txt").display().to_string();
+        let given = std::slice::from_ref(&readme);
+        assert_eq!(select(given, false, &extensions, &[]).unwrap().0, given);
+        let (files, skipped) = select(&[readme], false, &extensions, &[parse_exclude("*.txt").unwrap()]).unwrap();
+        assert_eq!((files.len(), skipped[0].reason.as_str()), (0, "matches --exclude '*.txt'"));
+        assert!(parse_exclude("[").is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    error::Error,
+    logging::{self, run_captured},
+    route::Target,
+    signtool::Digest,
+};
+use duct::cmd;
+use std::{ffi::OsString, fs::File, path::Path};
+
+/// Where an OPC package keeps its signature parts
+const SIGNATURE_PARTS: &str = "package/services/digital-signature/";
+
+/// How Microsoft's `sign` tool is asked to sign a VSIX
+#[derive(Debug)]
+pub struct Options<'a> {
+    pub target: &'a Target,
+    pub file_digest: Digest,
+    pub timestamp_url: &'a str,
+    pub timestamp_digest: Digest,
+    pub description: Option<&'a str>,
+}
+
+/// Whether the file is a Visual Studio extension, judged by its extension
+pub fn is_package(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("vsix"))
+}
+
+/// Arguments of `sign code trusted-signing` for signing `file`
+pub fn arguments(file: &str, options: &Options) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "code",
+        "trusted-signing",
+        "--trusted-signing-endpoint",
+        &options.target.endpoint,
+        "--trusted-signing-account",
+        &options.target.account,
+        "--trusted-signing-certificate-profile",
+        &options.target.certificate,
+        "--file-digest",
+        &options.file_digest.name().to_ascii_lowercase(),
+        "--timestamp-url",
+        options.timestamp_url,
+        "--timestamp-digest",
+        &options.timestamp_digest.name().to_ascii_lowercase(),
+    ]
+    .map(OsString::from)
+    .to_vec();
+    if let Some(description) = options.description {
+        args.extend(["--description", description].map(OsString::from));
+    }
+    args.push(file.into());
+    args
+}
+
+/// Whether the package opens as a zip and carries an OPC signature
+fn verify(file: &str) -> Result<(), String> {
+    let archive = File::open(file).map_err(|err| err.to_string())?;
+    let archive =
+        zip::ZipArchive::new(archive).map_err(|err| format!("it doesn't open as a package anymore: {}", err))?;
+    let signed = archive.file_names().any(|name| name.starts_with(SIGNATURE_PARTS));
+    match signed {
+        true => Ok(()),
+        false => Err("it has no signature".to_string()),
+    }
+}
+
+/// Sign a VSIX in place with the `sign` tool at `tool`, which authenticates through the environment like
+/// signtool's dlib does, so the service principal is handed to it there
+pub fn sign(tool: &str, credentials: [(&str, &str); 3], options: &Options, file: &str) -> Result<(), Error> {
+    if options.file_digest == Digest::Sha1 {
+        Err(Error::Usage("VSIX packages can't be signed with SHA1, pass --fd SHA256 or stronger".to_string()))?;
+    }
+    let message = format!("sign tool '{}' could not sign the package '{}'", tool, file);
+    let expression = credentials
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .fold(cmd(tool, arguments(file, options)), |expression, (name, value)| expression.env(name, value));
+    let output = run_captured(expression).map_err(|err| Error::MissingDependency {
+        component: "vsix tool",
+        message: format!(
+            "{}, error: {:?}. Install it with `dotnet tool install --global sign --prerelease`",
+            message, err
+        ),
+    })?;
+    if !output.status.success() {
+        Err(Error::Signing {
+            path: file.to_string(),
+            message: format!("{}, error: {}", message, output.status),
+            output: logging::failure_output(&output),
+        })?;
+    }
+    verify(file).map_err(|err| Error::Signing {
+        path: file.to_string(),
+        message: format!("sign tool '{}' reported success for '{}' but {}", tool, file, err),
+        output: logging::failure_output(&output),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn vsix_packages() {
+        assert!(is_package(r"bin\Release\MyExtension.VSIX"));
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+        let options = Options {
+            target: &target,
+            file_digest: Digest::Sha256,
+            timestamp_url: "http://timestamp.acs.microsoft.com",
+            timestamp_digest: Digest::Sha256,
+            description: Some("My Extension"),
+        };
+        let args: Vec<String> =
+            arguments("ext.vsix", &options).into_iter().map(|arg| arg.to_string_lossy().to_string()).collect();
+        assert_eq!(args[..2], ["code", "trusted-signing"]);
+        assert!(args.join(" ").contains("--file-digest sha256 --timestamp-url http://timestamp.acs.microsoft.com"));
+        assert_eq!(args[args.len() - 3..], ["--description", "My Extension", "ext.vsix"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ext.vsix");
+        let write = |names: &[&str]| {
+            let mut package = zip::ZipWriter::new(File::create(&path).unwrap());
+            for name in names {
+                package.start_file(*name, zip::write::FileOptions::default()).unwrap();
+                package.write_all(b"<xml/>").unwrap();
+            }
+            package.finish().unwrap();
+        };
+        let path = path.to_str().unwrap();
+        write(&["extension.vsixmanifest", "[Content_Types].xml"]);
+        assert!(verify(path).is_err());
+        write(&["extension.vsixmanifest", "package/services/digital-signature/xml-signature/sig.psdsxs"]);
+        assert!(verify(path).is_ok());
+    }
+}
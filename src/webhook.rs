@@ -0,0 +1,214 @@
+//! Posting the results of a run to a webhook, for chat channels and dashboards
+
+use crate::{ci, logging, report::Report, route::Target};
+use clap::ValueEnum;
+use reqwest::{
+    header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    StatusCode, Url,
+};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// How often a webhook is posted to when it answers with a server error or can't be reached
+const ATTEMPTS: u32 = 3;
+
+/// How long a post may take, a slow webhook must not hold up the pipeline
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When --webhook is posted to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookOn {
+    /// Only when the run failed
+    Failure,
+    /// After every run
+    Always,
+}
+
+/// Check `--webhook`: an http(s) URL
+pub fn parse_url(value: &str) -> Result<String, String> {
+    match Url::parse(value) {
+        Ok(url) if ["https", "http"].contains(&url.scheme()) => Ok(value.to_string()),
+        Ok(url) => Err(format!("webhook must use https or http, not {}", url.scheme())),
+        Err(err) => Err(format!("webhook is not a URL: {}", err)),
+    }
+}
+
+/// Check `--webhook-header`: `Name: value`
+pub fn parse_header(value: &str) -> Result<(String, String), String> {
+    let (name, header) = value.split_once(':').ok_or("a webhook header is written as 'Name: value'")?;
+    let (name, header) = (name.trim(), header.trim());
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("'{}' is not a valid header name", name))?;
+    HeaderValue::from_str(header).map_err(|_| format!("the value of header '{}' is not a valid header value", name))?;
+    Ok((name.to_string(), header.to_string()))
+}
+
+/// A file that failed, with the line explaining why
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Failed {
+    pub path: String,
+    pub reason: String,
+}
+
+/// What is posted to the webhook. Only counts, names and reasons, never arguments, so no secret is sent; what
+/// signtool printed is masked like the log
+#[derive(Serialize, Debug)]
+pub struct Payload {
+    /// `succeeded` or `failed`
+    pub status: &'static str,
+    pub signed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub not_attempted: usize,
+    pub total: usize,
+    pub duration_ms: u64,
+    pub endpoint: String,
+    pub account: String,
+    pub certificate_profile: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Why the run failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub failed_files: Vec<Failed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ci: Option<ci::Metadata>,
+}
+
+impl Payload {
+    pub fn new(target: &Target, report: &Report, error: Option<&str>, ci: Option<ci::Metadata>) -> Self {
+        let summary = &report.summary;
+        let failed_files = report
+            .files
+            .iter()
+            .filter_map(|file| Some((file, file.reason()?)))
+            .map(|(file, reason)| Failed { path: file.path.clone(), reason: logging::mask(reason) })
+            .collect();
+        Payload {
+            status: match error {
+                Some(_) => "failed",
+                None => "succeeded",
+            },
+            signed: summary.signed,
+            failed: summary.failed,
+            skipped: summary.skipped,
+            not_attempted: summary.not_attempted,
+            total: report.files.len(),
+            duration_ms: summary.duration_ms,
+            endpoint: target.endpoint.clone(),
+            account: target.account.clone(),
+            certificate_profile: target.certificate.clone(),
+            correlation_id: report.correlation_id.clone(),
+            error: error.map(logging::mask),
+            failed_files,
+            ci,
+        }
+    }
+}
+
+/// The host of the webhook for messages, its path and query often hold a token
+fn host(url: &str) -> String {
+    Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_else(|| "webhook".to_string())
+}
+
+/// Statuses a webhook answers with when it is busy or restarting
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Post `payload` to `url` with `headers`, trying again after a server error or without an answer
+pub async fn send(url: &str, headers: &[(String, String)], payload: &Payload, pause: Duration) -> Result<(), String> {
+    let host = host(url);
+    let body = serde_json::to_string(payload).map_err(|err| err.to_string())?;
+    let client = reqwest::Client::builder().timeout(TIMEOUT).build().map_err(|err| err.to_string())?;
+    let mut pause = pause;
+    for attempt in 1..=ATTEMPTS {
+        let mut request = client.post(url).header(CONTENT_TYPE, "application/json").body(body.clone());
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let problem = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("notified the webhook at {}, it answered {}", host, response.status());
+                return Ok(());
+            }
+            Ok(response) if is_transient(response.status()) => {
+                format!("answered {}", response.status())
+            }
+            Ok(response) => return Err(format!("the webhook at {} answered {}", host, response.status())),
+            // the error names the URL
+            Err(err) if err.is_timeout() => "didn't answer in time".to_string(),
+            Err(_) => "could not be reached".to_string(),
+        };
+        if attempt == ATTEMPTS {
+            return Err(format!("the webhook at {} {}, gave up after {} tries", host, problem, ATTEMPTS));
+        }
+        warn!("the webhook at {} {}, trying again in {}s", host, problem, pause.as_secs());
+        tokio::time::sleep(pause).await;
+        pause *= 2;
+    }
+    unreachable!("the last attempt returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Status;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[tokio::test]
+    async fn payload_and_retries() {
+        let files = ["app.exe".to_string(), "lib.dll".to_string()];
+        let mut report = Report::new(&files);
+        report.files[0].status = Status::Signed;
+        report.files[1].status = Status::Failed;
+        report.files[1].error = Some("Number of errors: 1\nSignTool Error: No certificates were found.".to_string());
+        report.finish(Duration::from_secs(3));
+        let target = Target {
+            endpoint: "https://eus.codesigning.azure.net".to_string(),
+            account: "acc".to_string(),
+            certificate: "prof".to_string(),
+        };
+        let payload = Payload::new(&target, &report, Some("1 of 2 files could not be signed"), None);
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert_eq!((json["signed"].as_u64(), json["failed"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(json["failed_files"][0]["reason"], "SignTool Error: No certificates were found.");
+        assert!(json.get("ci").is_none());
+
+        let header = ("Authorization".to_string(), "Bearer t0k3n".to_string());
+        assert_eq!(parse_header("Authorization:  Bearer t0k3n").unwrap(), header);
+        assert!(parse_header("no colon").is_err());
+        assert!(parse_url("ftp://hooks.example.com").is_err());
+
+        // a 503 first, then the post goes through
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks/s3cr3t", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in ["503 Service Unavailable", "204 No Content"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !String::from_utf8_lossy(&request).contains("\"failed_files\"") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend(&buffer[..read]);
+                }
+                requests.push(String::from_utf8_lossy(&request).to_ascii_lowercase());
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        let headers = [("X-Token".to_string(), "abc".to_string())];
+        send(&url, &headers, &payload, Duration::ZERO).await.unwrap();
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("post /hooks/s3cr3t ") && requests[1].contains("x-token: abc"));
+        let failure = send(&url, &headers, &payload, Duration::ZERO).await.unwrap_err();
+        assert!(failure.starts_with("the webhook at 127.0.0.1 could not be reached"), "{}", failure);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    azure,
+    config::Resolved,
+    credman,
+    dotenv::EnvFile,
+    error::Error,
+    logging,
+    principal::{self, ServicePrincipal},
+    session, settings,
+};
+use clap::ArgMatches;
+use serde::Serialize;
+use tracing::{debug, info};
+
+/// The arguments a client secret can come from, in the order `sign` reads them
+const SECRET_ARGS: [&str; 3] = ["azure_client_secret", "azure_client_secret_credman", "azure_client_secret_keyvault"];
+
+/// The identity a sign with the same arguments would use
+#[derive(Serialize, Debug)]
+pub struct Identity {
+    /// As az reports it, like `servicePrincipal`
+    pub principal_type: String,
+    pub client_id: String,
+    pub tenant_id: String,
+    /// From Microsoft Entra ID, `None` when the principal may not read it
+    pub display_name: Option<String>,
+    /// The argument the client secret came from, like `azure-client-secret-credman`
+    pub credential: String,
+    /// Where that argument was set, like `env AZURE_CLIENT_SECRET from .env`
+    pub credential_source: String,
+    /// Why the credential was picked and how the secret was read
+    pub reason: String,
+    /// Whether the azure cli session of an earlier run was reused instead of logging in
+    pub session_reused: bool,
+}
+
+/// The client secret argument that is set, there may only be one
+fn selected(sign: &ArgMatches) -> Result<(&'static str, String), Error> {
+    let set: Vec<(&'static str, String)> = SECRET_ARGS
+        .iter()
+        .filter_map(|id| Some((*id, sign.try_get_one::<String>(id).ok().flatten()?.clone())))
+        .collect();
+    let flag = |id: &str| format!("--{}", id.replace('_', "-"));
+    match set.as_slice() {
+        [] => Err(Error::Usage(format!(
+            "no client secret is configured, pass {}",
+            SECRET_ARGS.map(flag).join(", ")
+        ))),
+        [one] => Ok(one.clone()),
+        several => Err(Error::Usage(format!(
+            "only one client secret may be configured, {} are all set",
+            several.iter().map(|(id, _)| flag(id)).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+/// A login with the credentials a sign would use
+#[derive(Debug)]
+pub struct Login {
+    pub cli_path: String,
+    /// The sign argument the client secret came from, like `azure_client_secret_credman`
+    pub credential: &'static str,
+    /// Why the credential was picked and how the secret was read
+    pub reason: String,
+    pub session_reused: bool,
+}
+
+/// Resolve the credentials like `sign` does and log in with them or reuse the session
+pub fn log_in(sign: &ArgMatches) -> Result<Login, Error> {
+    let get = |id: &str| sign.try_get_one::<String>(id).ok().flatten().cloned();
+    let cli_path = get("azure_cli_path").unwrap_or_else(|| azure::DEFAULT_CLI_PATH.to_string());
+    if let Some(principal) = principal::from_matches(sign)? {
+        let reason = format!("the service principal is read from {}", principal.source);
+        let ServicePrincipal { tenant_id, client_id, client_secret, credential, .. } = principal;
+        return log_in_as(cli_path, &tenant_id, &client_id, &client_secret, credential, reason);
+    }
+    let (Some(tenant_id), Some(client_id)) = (get("azure_tenant_id"), get("azure_client_id")) else {
+        Err(Error::Usage(
+            "no usable credential, --azure-tenant-id and --azure-client-id (or AZURE_TENANT_ID and AZURE_CLIENT_ID) \
+             are both needed, or --sp-file"
+                .to_string(),
+        ))?
+    };
+    let managed_identity = matches!(sign.try_get_one::<bool>("bootstrap_managed_identity"), Ok(Some(true)));
+
+    let (id, value) = selected(sign)?;
+    let (secret, reason) = match id {
+        "azure_client_secret_credman" => {
+            (credman::read(&value)?, format!("read from the Credential Manager entry '{}'", value))
+        }
+        "azure_client_secret_keyvault" => {
+            let identity = match managed_identity {
+                true => "the managed identity of this machine",
+                false => "the identity azure cli was logged in as",
+            };
+            let secret = azure::key_vault_secret(&cli_path, &value, managed_identity)?;
+            (secret, format!("read from the Key Vault secret {} with {}", value, identity))
+        }
+        _ => (value, "the client secret is given directly".to_string()),
+    };
+    log_in_as(cli_path, &tenant_id, &client_id, &secret, id, reason)
+}
+
+/// Log in with the credentials resolved, or reuse the session of an earlier run with them
+fn log_in_as(
+    cli_path: String,
+    tenant_id: &str,
+    client_id: &str,
+    secret: &str,
+    credential: &'static str,
+    reason: String,
+) -> Result<Login, Error> {
+    logging::register_secret(secret);
+
+    let session_reused = session::reusable(&cli_path, tenant_id, client_id, secret);
+    match session_reused {
+        true => info!("reusing the azure cli session of {}", client_id),
+        false => {
+            info!("logging in to azure cli as {}", client_id);
+            azure::login(&cli_path, tenant_id, client_id, secret, false).map_err(Error::Auth)?;
+            if let Err(err) = session::save(tenant_id, client_id, secret) {
+                debug!("{}", err);
+            }
+        }
+    }
+    Ok(Login {
+        cli_path,
+        credential,
+        reason,
+        session_reused,
+    })
+}
+
+/// Log in like `sign` does and ask az who it is logged in as. Nothing is signed and the secret is never part of
+/// the result
+pub fn run(sign: &ArgMatches, resolved: &Resolved, env_file: &EnvFile) -> Result<Identity, Error> {
+    let login = log_in(sign)?;
+    let account = azure::account(&login.cli_path).map_err(Error::Auth)?;
+    let display_name = azure::display_name(&login.cli_path, &account.user_name)
+        .map_err(|err| debug!("the display name can't be read: {}", err))
+        .ok();
+
+    Ok(Identity {
+        principal_type: account.user_type,
+        client_id: account.user_name,
+        tenant_id: account.tenant_id,
+        display_name,
+        credential: login.credential.replace('_', "-"),
+        credential_source: settings::source_of(login.credential, sign, resolved, env_file),
+        reason: login.reason,
+        session_reused: login.session_reused,
+    })
+}
+
+impl Identity {
+    /// Aligned table of the identity
+    pub fn table(&self) -> String {
+        let display_name = self.display_name.as_deref().unwrap_or("- (the principal may not read it)");
+        let login = match self.session_reused {
+            true => "reused the session of an earlier run",
+            false => "logged in",
+        };
+        let rows = [
+            ("principal type", self.principal_type.as_str()),
+            ("client id", &self.client_id),
+            ("tenant", &self.tenant_id),
+            ("display name", display_name),
+            ("credential", &format!("--{} ({})", self.credential, self.credential_source)),
+            ("selected", &self.reason),
+            ("azure cli", login),
+        ];
+        let mut table = String::from("Identity\n");
+        for (name, value) in rows {
+            table.push_str(&format!("  {:<15} {}\n", name, value));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    #[test]
+    fn secret_selection() {
+        let matches = |args: &[&str]| {
+            let argv = ["tsc", "whoami"].iter().chain(args);
+            Cli::command().ignore_errors(true).try_get_matches_from(argv).unwrap()
+        };
+        let direct = matches(&["--azure-client-secret", "hunter2"]);
+        let sign = crate::cli::sign_matches(&direct).unwrap();
+        assert_eq!(selected(sign).unwrap(), ("azure_client_secret", "hunter2".to_string()));
+
+        let both = matches(&["--azure-client-secret", "hunter2", "--azure-client-secret-credman", "signing"]);
+        let err = selected(crate::cli::sign_matches(&both).unwrap()).unwrap_err().to_string();
+        assert!(err.contains("--azure-client-secret, --azure-client-secret-credman") && !err.contains("hunter2"));
+        assert!(selected(crate::cli::sign_matches(&matches(&[])).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Verifying signatures in process with WinVerifyTrust, under the default Authenticode policy signtool verify /pa
+//! applies, and reading the signer and the timestamp from the state it leaves. Nothing depends on the language
+//! of the system or the version of signtool, and no process is started per file
+
+use crate::{inspect::DIGESTS, verify::Verification};
+#[cfg(windows)]
+use windows_sys::Win32::Security::{Cryptography::CERT_CONTEXT, WinTrust::CRYPT_PROVIDER_SGNR};
+
+/// The root Trusted Signing certificates chain to, which older and offline Windows images may not have yet
+pub const ROOT_NAME: &str = "Microsoft Identity Verification Root Certificate Authority 2020";
+
+/// SHA1 thumbprint of [ROOT_NAME]
+pub const ROOT_THUMBPRINT: &str = "F40042E2E5F7E8EF8189FED15519AECE42C3BFA2";
+
+/// CERT_E_UNTRUSTEDROOT
+#[cfg_attr(not(windows), allow(dead_code))]
+const UNTRUSTED_ROOT: u32 = 0x800B0109;
+
+/// HRESULTs of WinVerifyTrust that mean something to whoever signed the file
+#[cfg_attr(not(windows), allow(dead_code))]
+const FAILURES: [(u32, &str); 12] = [
+    (0x800B0100, "No signature found."),
+    (0x800B0003, "the file type has no signature format Windows knows"),
+    (0x80096010, "the file changed after it was signed, its hash doesn't match the signature"),
+    (0x80096019, "the signature is malformed"),
+    (UNTRUSTED_ROOT, "the certificate chain ends in a root certificate that isn't trusted"),
+    (0x800B010A, "the certificate chain could not be built up to a trusted root"),
+    (0x800B0101, "the signing certificate expired and the signature isn't timestamped"),
+    (0x800B010C, "the signing certificate was revoked"),
+    (0x800B0110, "the signing certificate isn't for code signing"),
+    (0x800B0111, "a certificate of the chain is explicitly distrusted"),
+    (0x800B0004, "the signature isn't trusted under the Authenticode policy"),
+    (0x80092026, "the security settings of the system don't allow the signature"),
+];
+
+/// What a failure of WinVerifyTrust means, and whether it is the file having no signature at all
+#[cfg_attr(not(windows), allow(dead_code))]
+fn failure(status: i32) -> (bool, String) {
+    let status = status as u32;
+    let message = FAILURES.iter().find(|(code, _)| *code == status).map(|(_, message)| message.to_string());
+    (status == FAILURES[0].0, message.unwrap_or_else(|| format!("WinVerifyTrust failed, error 0x{:08X}", status)))
+}
+
+/// A FILETIME from its two halves, 100 ns intervals since 1601, as RFC 3339 in local time
+#[cfg_attr(not(windows), allow(dead_code))]
+fn filetime(low: u32, high: u32) -> Option<String> {
+    let ticks = ((high as u64) << 32 | low as u64) as i64;
+    let seconds = ticks.div_euclid(10_000_000) - 11_644_473_600;
+    let nanoseconds = ticks.rem_euclid(10_000_000) as u32 * 100;
+    let time = chrono::DateTime::from_timestamp(seconds, nanoseconds)?;
+    Some(time.with_timezone(&chrono::Local).to_rfc3339())
+}
+
+/// The lowercase name of the digest algorithm `oid`, like signtool prints it
+#[cfg_attr(not(windows), allow(dead_code))]
+fn digest(oid: &str) -> String {
+    DIGESTS.iter().find(|(known, _)| *known == oid).map_or(oid.to_string(), |(_, name)| name.to_ascii_lowercase())
+}
+
+/// Verify the primary signature of a file, without revocation checks or anything sent anywhere
+#[cfg(windows)]
+pub fn verify(path: &str) -> Verification {
+    use std::{ffi::c_void, mem::size_of, os::windows::ffi::OsStrExt, ptr::null_mut};
+    use windows_sys::Win32::Security::WinTrust::{
+        WinVerifyTrust, WINTRUST_ACTION_GENERIC_VERIFY_V2, WINTRUST_DATA, WINTRUST_FILE_INFO,
+        WTD_CACHE_ONLY_URL_RETRIEVAL, WTD_CHOICE_FILE, WTD_REVOKE_NONE, WTD_STATEACTION_CLOSE,
+        WTD_STATEACTION_VERIFY, WTD_UI_NONE,
+    };
+
+    let mut verification = Verification { path: path.to_string(), ..Default::default() };
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.into());
+    let wide: Vec<u16> = absolute.as_os_str().encode_wide().chain([0]).collect();
+    let mut file = WINTRUST_FILE_INFO {
+        cbStruct: size_of::<WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: wide.as_ptr(),
+        hFile: null_mut(),
+        pgKnownSubject: null_mut(),
+    };
+    // SAFETY: WINTRUST_DATA is plain data, zeroed leaves every pointer null
+    let mut data: WINTRUST_DATA = unsafe { std::mem::zeroed() };
+    data.cbStruct = size_of::<WINTRUST_DATA>() as u32;
+    data.dwUIChoice = WTD_UI_NONE;
+    data.fdwRevocationChecks = WTD_REVOKE_NONE;
+    data.dwUnionChoice = WTD_CHOICE_FILE;
+    data.Anonymous.pFile = &mut file;
+    data.dwStateAction = WTD_STATEACTION_VERIFY;
+    data.dwProvFlags = WTD_CACHE_ONLY_URL_RETRIEVAL;
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    // SAFETY: `data` and the file info and path it points to outlive the call, no window is given for UI
+    let status = unsafe { WinVerifyTrust(null_mut(), &mut action, &mut data as *mut _ as *mut c_void) };
+    // SAFETY: the state data of the verification stays valid until it is closed below
+    unsafe { read_signer(data.hWVTStateData, &mut verification) };
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    // SAFETY: closes the state data of the verification above, which isn't used after this
+    unsafe { WinVerifyTrust(null_mut(), &mut action, &mut data as *mut _ as *mut c_void) };
+
+    if verification.timestamp.is_none() {
+        // RFC 3161 timestamps of older systems aren't among the countersigners
+        if let Some(signature) = crate::inspect::inspect(path).signatures.into_iter().find(|s| s.nested_in.is_none()) {
+            let timestamp = signature.timestamp.filter(|timestamp| timestamp.time.is_some());
+            verification.timestamp = timestamp.as_ref().and_then(|timestamp| timestamp.time.clone());
+            verification.timestamp_authority =
+                timestamp.and_then(|timestamp| timestamp.authority).map(|authority| authority.subject);
+        }
+    }
+    match status {
+        0 => {
+            verification.valid = verification.timestamp.is_some();
+            if !verification.valid {
+                let error = "the signature is not timestamped, it stops being valid when the certificate expires";
+                verification.error = Some(error.to_string());
+            }
+        }
+        status => {
+            let (unsigned, error) = failure(status);
+            verification.unsigned = unsigned;
+            verification.error = Some(error);
+            if status as u32 == UNTRUSTED_ROOT {
+                verification.status = crate::verify::Status::UntrustedRoot;
+            }
+        }
+    }
+    verification
+}
+
+/// Copy the signing certificate, the digest and the timestamp of the first signer of a verification
+///
+/// # Safety
+///
+/// `state` is the state data WinVerifyTrust returned for a verification not closed yet, or null
+#[cfg(windows)]
+unsafe fn read_signer(state: windows_sys::Win32::Foundation::HANDLE, verification: &mut Verification) {
+    use windows_sys::Win32::Security::WinTrust::{WTHelperGetProvSignerFromChain, WTHelperProvDataFromStateData};
+
+    if state.is_null() {
+        return;
+    }
+    let provider = WTHelperProvDataFromStateData(state);
+    let signer = match provider.is_null() {
+        true => return,
+        false => WTHelperGetProvSignerFromChain(provider, 0, 0, 0),
+    };
+    let Some(signer) = signer.as_ref() else {
+        return;
+    };
+    if let Some(certificate) = leaf(signer) {
+        verification.subject = name(certificate, false);
+        verification.issuer = name(certificate, true);
+        verification.thumbprint = thumbprint(certificate);
+    }
+    if let Some(signer_info) = signer.psSigner.as_ref() {
+        let oid = signer_info.HashAlgorithm.pszObjId;
+        if !oid.is_null() {
+            verification.digest = Some(digest(&std::ffi::CStr::from_ptr(oid as _).to_string_lossy()));
+        }
+    }
+    if signer.csCounterSigners > 0 {
+        if let Some(countersigner) = signer.pasCounterSigners.as_ref() {
+            let time = countersigner.sftVerifyAsOf;
+            verification.timestamp = filetime(time.dwLowDateTime, time.dwHighDateTime);
+            verification.timestamp_authority = leaf(countersigner).and_then(|certificate| name(certificate, false));
+        }
+    }
+}
+
+/// The certificate a signer signed with, the first of its chain
+#[cfg(windows)]
+unsafe fn leaf(signer: &CRYPT_PROVIDER_SGNR) -> Option<*const CERT_CONTEXT> {
+    match signer.csCertChain {
+        0 => None,
+        _ => signer.pasCertChain.as_ref().map(|certificate| certificate.pCert).filter(|pointer| !pointer.is_null()),
+    }
+}
+
+/// The display name of the subject or the issuer of a certificate, like signtool's "Issued to" and "Issued by"
+#[cfg(windows)]
+unsafe fn name(certificate: *const CERT_CONTEXT, issuer: bool) -> Option<String> {
+    use windows_sys::Win32::Security::Cryptography::{
+        CertGetNameStringW, CERT_NAME_ISSUER_FLAG, CERT_NAME_SIMPLE_DISPLAY_TYPE,
+    };
+
+    let flags = match issuer {
+        true => CERT_NAME_ISSUER_FLAG,
+        false => 0,
+    };
+    let mut buffer = [0u16; 512];
+    let written = CertGetNameStringW(
+        certificate,
+        CERT_NAME_SIMPLE_DISPLAY_TYPE,
+        flags,
+        std::ptr::null(),
+        buffer.as_mut_ptr(),
+        buffer.len() as u32,
+    ) as usize;
+    // the count includes the terminating null, 1 is an empty name
+    match written {
+        0 | 1 => None,
+        written => Some(String::from_utf16_lossy(&buffer[..written - 1])),
+    }
+}
+
+/// The SHA1 thumbprint of a certificate in uppercase hex
+#[cfg(windows)]
+unsafe fn thumbprint(certificate: *const CERT_CONTEXT) -> Option<String> {
+    use windows_sys::Win32::Security::Cryptography::{CertGetCertificateContextProperty, CERT_SHA1_HASH_PROP_ID};
+
+    let mut hash = [0u8; 20];
+    let mut size = hash.len() as u32;
+    match CertGetCertificateContextProperty(certificate, CERT_SHA1_HASH_PROP_ID, hash.as_mut_ptr() as _, &mut size) {
+        0 => None,
+        _ => Some(hash[..size as usize].iter().map(|byte| format!("{:02X}", byte)).collect()),
+    }
+}
+
+/// Whether [ROOT_NAME] is in the root store of the machine, including the roots Windows updates by itself
+#[cfg(windows)]
+pub fn root_present() -> Option<bool> {
+    use std::ptr::null;
+    use windows_sys::Win32::Security::Cryptography::{
+        CertCloseStore, CertFindCertificateInStore, CertFreeCertificateContext, CertOpenStore, CERT_FIND_SHA1_HASH,
+        CERT_STORE_PROV_SYSTEM_W, CERT_STORE_READONLY_FLAG, CERT_SYSTEM_STORE_LOCAL_MACHINE, CRYPT_INTEGER_BLOB,
+        PKCS_7_ASN_ENCODING, X509_ASN_ENCODING,
+    };
+
+    let mut hash: Vec<u8> = (0..ROOT_THUMBPRINT.len())
+        .step_by(2)
+        .filter_map(|at| u8::from_str_radix(&ROOT_THUMBPRINT[at..at + 2], 16).ok())
+        .collect();
+    let name: Vec<u16> = "ROOT".encode_utf16().chain([0]).collect();
+    let flags = CERT_SYSTEM_STORE_LOCAL_MACHINE | CERT_STORE_READONLY_FLAG;
+    // SAFETY: `name` is a null terminated wide string naming the system store to open read only
+    let store = unsafe { CertOpenStore(CERT_STORE_PROV_SYSTEM_W, 0, 0, flags, name.as_ptr() as _) };
+    if store.is_null() {
+        return None;
+    }
+    let blob = CRYPT_INTEGER_BLOB { cbData: hash.len() as u32, pbData: hash.as_mut_ptr() };
+    let encoding = X509_ASN_ENCODING | PKCS_7_ASN_ENCODING;
+    // SAFETY: `store` is open, `blob` points to the hash and outlives the call
+    let found = unsafe {
+        CertFindCertificateInStore(store, encoding, 0, CERT_FIND_SHA1_HASH, &blob as *const _ as _, null())
+    };
+    // SAFETY: the certificate came from the store above, and neither is used after this
+    unsafe {
+        if !found.is_null() {
+            CertFreeCertificateContext(found);
+        }
+        CertCloseStore(store, 0);
+    }
+    Some(!found.is_null())
+}
+
+/// The root store can only be looked at on Windows
+#[cfg(not(windows))]
+pub fn root_present() -> Option<bool> {
+    None
+}
+
+#[cfg(not(windows))]
+pub fn verify(path: &str) -> Verification {
+    let error = Some("WinVerifyTrust only exists on Windows, pass --verify-with-signtool".to_string());
+    Verification { path: path.to_string(), error, ..Default::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_and_times() {
+        assert_eq!(failure(0x800B0100u32 as i32), (true, "No signature found.".to_string()));
+        assert!(!failure(0x80096010u32 as i32).0);
+        assert_eq!(failure(0x80070005u32 as i32).1, "WinVerifyTrust failed, error 0x80070005");
+        // 2025-10-14T10:00:00Z
+        let time = filetime(0x4D8A_D000, 0x01DC_3CF1).unwrap();
+        let time = chrono::DateTime::parse_from_rfc3339(&time).unwrap();
+        assert_eq!(time.timestamp(), 1_760_436_000);
+        assert_eq!((digest("2.16.840.1.101.3.4.2.1"), digest("1.2.3")), ("sha256".to_string(), "1.2.3".to_string()));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn system_binaries() {
+        // the kernel carries its signature, most system DLLs are signed in a catalog instead
+        let kernel = verify(r"C:\Windows\System32\ntoskrnl.exe");
+        assert!(!kernel.unsigned, "{:?}", kernel);
+        assert_eq!(kernel.subject.as_deref(), Some("Microsoft Windows"));
+        assert!(kernel.issuer.unwrap().starts_with("Microsoft Windows"));
+        assert_eq!(kernel.thumbprint.map(|thumbprint| thumbprint.len()), Some(40));
+        assert_eq!(kernel.digest.as_deref(), Some("sha256"));
+
+        let temp = tempfile::tempdir().unwrap();
+        let unsigned = temp.path().join("unsigned.exe");
+        std::fs::write(&unsigned, crate::smoke::stub_exe()).unwrap();
+        let unsigned = verify(unsigned.to_str().unwrap());
+        assert!(unsigned.unsigned && !unsigned.valid && unsigned.subject.is_none(), "{:?}", unsigned);
+    }
+}
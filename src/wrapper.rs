@@ -0,0 +1,98 @@
+use crate::error::Error;
+use std::{ffi::OsString, fs, path::Path};
+
+/// First line of every wrapper, after the shell's own preamble
+const GENERATED: &str = "Generated by trusted-signing-cli print-signtool-args, regenerate it instead of editing";
+
+/// Quote an argument for a Windows command line when it needs it
+fn quote_cmd(arg: &str) -> String {
+    match arg.is_empty() || arg.contains([' ', '\t', '&', '(', ')', '^', '|', '<', '>']) {
+        true => format!("\"{}\"", arg),
+        false => arg.to_string(),
+    }
+}
+
+/// Quote an argument as a PowerShell single quoted string
+fn quote_ps(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "''"))
+}
+
+/// The arguments as one command line, as printed by print-signtool-args
+pub fn command_line(args: &[OsString]) -> String {
+    args.iter().map(|arg| quote_cmd(&arg.to_string_lossy())).collect::<Vec<_>>().join(" ")
+}
+
+/// A .cmd running signtool with `args` and the arguments it is given, `%` is doubled so cmd doesn't expand it.
+/// cmd reads scripts in the OEM code page, so one with paths like `C:\Users\Jürgen` switches to UTF-8 first
+fn cmd_script(signtool: &str, args: &[OsString]) -> String {
+    let command = format!("{} {}", quote_cmd(signtool), command_line(args)).replace('%', "%%");
+    let code_page = match command.is_ascii() {
+        true => "",
+        false => "chcp 65001 >nul\r\n",
+    };
+    format!("@echo off\r\n{}rem {}\r\n{} %*\r\nexit /b %ERRORLEVEL%\r\n", code_page, GENERATED, command)
+}
+
+/// A .ps1 running signtool with `args` and the arguments it is given, marked as UTF-8 when it isn't ASCII
+fn ps1_script(signtool: &str, args: &[OsString]) -> String {
+    let args: Vec<String> = args.iter().map(|arg| quote_ps(&arg.to_string_lossy())).collect();
+    let script =
+        format!("# {}\r\n& {} {} @args\r\nexit $LASTEXITCODE\r\n", GENERATED, quote_ps(signtool), args.join(" "));
+    // Windows PowerShell reads a script without a byte order mark in the ANSI code page
+    match script.is_ascii() {
+        true => script,
+        false => format!("\u{feff}{}", script),
+    }
+}
+
+/// Write a wrapper script for the extension of `path`, returning whether it changed.
+/// The content only depends on the arguments, so regenerating it leaves an unchanged file alone.
+pub fn write(path: &Path, signtool: &str, args: &[OsString]) -> Result<bool, Error> {
+    let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+    let script = match extension.as_deref() {
+        Some("cmd" | "bat") => cmd_script(signtool, args),
+        Some("ps1") => ps1_script(signtool, args),
+        _ => Err(Error::Usage(format!(
+            "wrapper '{}' must end in .cmd, .bat or .ps1",
+            path.display()
+        )))?,
+    };
+    if fs::read_to_string(path).is_ok_and(|existing| existing == script) {
+        return Ok(false);
+    }
+    fs::write(path, script).map_err(|err| format!("wrapper '{}' could not be written: {:?}", path.display(), err))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts() {
+        let args: Vec<OsString> =
+            ["sign", "/fd", "SHA256", "/dlib", r"C:\Users\Jane Doe\lib.dll", "/d", "100% it's ours"]
+                .map(OsString::from)
+                .to_vec();
+        assert_eq!(
+            command_line(&args),
+            r#"sign /fd SHA256 /dlib "C:\Users\Jane Doe\lib.dll" /d "100% it's ours""#
+        );
+        assert!(cmd_script("signtool.exe", &args).contains(r#"/d "100%% it's ours" %*"#));
+        assert!(cmd_script(r"C:\Program Files (x86)\signtool.exe", &args)
+            .contains("\r\n\"C:\\Program Files (x86)\\signtool.exe\" sign /fd"));
+        assert!(ps1_script("signtool.exe", &args).contains("'/d' '100% it''s ours' @args\r\n"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sign.cmd");
+        assert!(write(&path, "signtool.exe", &args).unwrap());
+        assert!(!write(&path, "signtool.exe", &args).unwrap());
+        assert!(write(&dir.path().join("sign.sh"), "signtool.exe", &args).is_err());
+
+        let args = [r"C:\Users\Jürgen Müller\.trusted-signing-cli\lib.dll"].map(OsString::from);
+        assert!(cmd_script("signtool.exe", &args).starts_with("@echo off\r\nchcp 65001 >nul\r\nrem "));
+        assert!(!cmd_script("signtool.exe", &args[..0]).contains("chcp"));
+        assert!(ps1_script("signtool.exe", &args).starts_with("\u{feff}# "));
+        assert!(ps1_script("signtool.exe", &args[..0]).starts_with("# "));
+    }
+}
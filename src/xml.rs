@@ -0,0 +1,44 @@
+/// Replace the entities XML attributes can have
+pub fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// The start tags of the elements called `name`, with or without a namespace prefix, up to their `>`
+pub fn start_tags<'a>(content: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    content.match_indices('<').filter_map(move |(start, _)| {
+        let rest = &content[start + 1..];
+        let tag = &rest[..rest.find('>')?];
+        let element = tag.split(|c: char| c.is_ascii_whitespace() || c == '/').next()?;
+        let local = element.rsplit(':').next()?;
+        (local == name).then_some(tag)
+    })
+}
+
+/// The value of the attribute `name` in the start tag `tag`
+pub fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!(" {}=", name))? + name.len() + 2;
+    let quote = tag[start..].chars().next().filter(|quote| *quote == '"' || *quote == '\'')?;
+    let value = &tag[start + 1..];
+    Some(unescape(&value[..value.find(quote)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes() {
+        let content = r#"<Bundle><Identity Name="App" Publisher='CN=Fabrikam &amp; Co, O=Fabrikam' />
+            <b:Package FileName="App_x64.msix"/><PackageX FileName="no"/></Bundle>"#;
+        let identity = start_tags(content, "Identity").next().unwrap();
+        assert_eq!(attribute(identity, "Publisher").as_deref(), Some("CN=Fabrikam & Co, O=Fabrikam"));
+        assert_eq!(attribute(identity, "Version"), None);
+        let packages: Vec<_> = start_tags(content, "Package").filter_map(|tag| attribute(tag, "FileName")).collect();
+        assert_eq!(packages, ["App_x64.msix"]);
+    }
+}